@@ -1,44 +1,137 @@
 pub mod path_descriptor;
+mod path_template;
+mod remote_path;
+mod store_error;
+mod store_ftp;
+mod store_limited;
 mod store_local;
 mod store_sftp;
+mod store_timeout;
+mod store_verified;
 mod store_virtual;
 pub mod traits;
 
-use path_descriptor::{IdentitySource, PathDescriptor};
+use path_descriptor::{IdentitySource, JumpHost, PathDescriptor, Secret};
 use std::{
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::Arc,
 };
+pub use store_error::StoreError;
+use store_ftp::FtpStore;
+pub use store_limited::ConcurrencyLimitedStore;
 use store_local::LocalStore;
-use store_sftp::AsyncSftpImpl;
+use store_sftp::{AsyncSftpImpl, SftpSessionOptions};
+pub use store_timeout::{StoreTimeoutError, TimeoutStore};
+pub use store_verified::{ListingVerifiedStore, UploadVerificationError};
 use store_virtual::InMemoryFileSystem;
+pub use store_virtual::{InMemoryCapacity, OnCapacityExceeded};
 use traits::StoreDestination;
+use utils::time_getter::TimeGetter;
 
 pub fn make_store(
     path_descriptor: &Arc<PathDescriptor>,
 ) -> anyhow::Result<Arc<dyn StoreDestination<Error = anyhow::Error>>> {
     match path_descriptor.as_ref() {
-        PathDescriptor::Local(p) => Ok(make_local_store(path_descriptor.clone(), p)),
+        PathDescriptor::Local {
+            path,
+            min_free_bytes,
+            max_concurrency,
+        } => Ok(limit_concurrency(
+            make_local_store(path_descriptor.clone(), path, *min_free_bytes),
+            *max_concurrency,
+        )),
         PathDescriptor::Sftp {
             username,
             remote_address,
             remote_path,
             identity,
-        } => make_sftp_store(
-            path_descriptor.clone(),
-            remote_address,
+            keepalive_interval,
+            timeout,
+            operation_timeout,
+            max_concurrency,
+            jump_host,
+            file_mode,
+            dir_mode,
+            bind_address,
+            verify_listing_after_upload,
+        } => {
+            let store = make_sftp_store(
+                path_descriptor.clone(),
+                remote_address,
+                username,
+                identity.clone(),
+                remote_path,
+                jump_host.clone(),
+                SftpSessionOptions {
+                    keepalive_interval: *keepalive_interval,
+                    timeout: *timeout,
+                    operation_timeout: *operation_timeout,
+                    file_mode: *file_mode,
+                    dir_mode: *dir_mode,
+                    bind_address: *bind_address,
+                },
+            );
+            Ok(limit_concurrency(
+                verify_listing(store, *verify_listing_after_upload),
+                *max_concurrency,
+            ))
+        }
+        PathDescriptor::Ftp {
+            host,
             username,
-            identity.clone(),
+            password,
             remote_path,
-        ),
+            tls,
+            max_concurrency,
+        } => Ok(limit_concurrency(
+            make_ftp_store(
+                path_descriptor.clone(),
+                host,
+                username,
+                password,
+                *tls,
+                remote_path,
+            ),
+            *max_concurrency,
+        )),
+    }
+}
+
+/// The limit is applied outside any timeout, so that waiting for an upload slot doesn't count towards it
+fn limit_concurrency(
+    store: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    max_concurrency: Option<NonZeroUsize>,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
+    match max_concurrency {
+        Some(max_concurrency) => Arc::new(ConcurrencyLimitedStore::new(store, max_concurrency)),
+        None => store,
+    }
+}
+
+/// Uploads are verified after any timeout, so that a stalled listing fails like a stalled upload
+fn verify_listing(
+    store: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    verify_listing_after_upload: bool,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
+    if verify_listing_after_upload {
+        Arc::new(ListingVerifiedStore::new(store))
+    } else {
+        store
     }
 }
 
 fn make_local_store(
     path_descriptor: Arc<PathDescriptor>,
     destination_dir: impl AsRef<Path>,
+    min_free_bytes: Option<u64>,
 ) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
-    let store = LocalStore::new(path_descriptor, destination_dir);
+    let store = LocalStore::new(
+        path_descriptor,
+        destination_dir,
+        min_free_bytes,
+        &TimeGetter::default(),
+    );
     Arc::new(store)
 }
 
@@ -48,23 +141,58 @@ fn make_sftp_store(
     username: &str,
     priv_key_path: IdentitySource,
     destination_path: impl Into<PathBuf>,
-) -> anyhow::Result<Arc<dyn StoreDestination<Error = anyhow::Error>>> {
+    jump_host: Option<JumpHost>,
+    session_options: SftpSessionOptions,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
     let sftp = AsyncSftpImpl::new_with_public_key(
         path_descriptor,
         host,
         username,
         priv_key_path,
         destination_path,
-    )?;
+        jump_host,
+        session_options,
+    );
 
-    Ok(Arc::new(sftp))
+    match session_options.operation_timeout {
+        Some(timeout) => Arc::new(TimeoutStore::new(Arc::new(sftp), timeout)),
+        None => Arc::new(sftp),
+    }
+}
+
+fn make_ftp_store(
+    path_descriptor: Arc<PathDescriptor>,
+    host: &str,
+    username: &str,
+    password: &Secret,
+    tls: bool,
+    destination_path: impl Into<PathBuf>,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
+    let store = FtpStore::new(
+        path_descriptor,
+        host,
+        username,
+        password,
+        tls,
+        destination_path,
+    );
+    Arc::new(store)
 }
 
 #[must_use]
 pub fn make_inmemory_filesystem() -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
-    Arc::new(InMemoryFileSystem::new(Arc::new(PathDescriptor::Local(
-        String::new().into(),
-    ))))
+    make_inmemory_filesystem_with_capacity(None)
+}
+
+/// An in-memory store that holds at most `capacity` bytes of files, if given
+#[must_use]
+pub fn make_inmemory_filesystem_with_capacity(
+    capacity: Option<InMemoryCapacity>,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
+    Arc::new(InMemoryFileSystem::new(
+        Arc::new(PathDescriptor::local(String::new())),
+        capacity,
+    ))
 }
 
 #[cfg(test)]
@@ -1,22 +1,73 @@
-use crate::store_sftp::SftpError;
+use crate::{
+    path_template::check_date_placeholders, remote_path::simplify_virtual_path,
+    store_ftp::split_host_and_port, store_sftp::SftpError,
+};
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Display,
-    path::PathBuf,
+    net::IpAddr,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
 const LOCAL_PREFIX: &str = "local";
 const SFTP_PREFIX: &str = "sftp";
+const FTP_PREFIX: &str = "ftp";
 
 const SFTP_KEY_USER: &str = "username";
 const SFTP_KEY_HOST: &str = "host";
 const SFTP_KEY_PATH: &str = "remote-path";
 const SFTP_KEY_IDENTITY: &str = "identity";
+const SFTP_KEY_KEEPALIVE_INTERVAL: &str = "keepalive-interval";
+const SFTP_KEY_TIMEOUT: &str = "timeout";
+const SFTP_KEY_OPERATION_TIMEOUT: &str = "operation-timeout";
+const SFTP_KEY_MAX_CONCURRENCY: &str = "max-concurrency";
+const SFTP_KEY_JUMP_HOST: &str = "jump-host";
+const SFTP_KEY_JUMP_USER: &str = "jump-username";
+const SFTP_KEY_JUMP_IDENTITY: &str = "jump-identity";
+const SFTP_KEY_FILE_MODE: &str = "file-mode";
+const SFTP_KEY_DIR_MODE: &str = "dir-mode";
+const SFTP_KEY_BIND_ADDRESS: &str = "bind-address";
+const SFTP_KEY_VERIFY_LISTING: &str = "verify-listing-after-upload";
+
+const FTP_KEY_USER: &str = "username";
+const FTP_KEY_PASSWORD: &str = "password";
+const FTP_KEY_PASSWORD_FILE: &str = "password-file";
+const FTP_KEY_HOST: &str = "host";
+const FTP_KEY_PATH: &str = "remote-path";
+const FTP_KEY_TLS: &str = "tls";
+const FTP_KEY_MAX_CONCURRENCY: &str = "max-concurrency";
+
+// The password is a secret, so it's not shown when a descriptor is displayed (e.g., in logs)
+const HIDDEN_PASSWORD: &str = "<hidden>";
+
+/// A password of a destination, which is hidden when the descriptor is debug-printed, as it's displayed
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    #[must_use]
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(HIDDEN_PASSWORD)
+    }
+}
 
 const LOCAL_KEY_PATH: &str = "path";
+const LOCAL_KEY_MIN_FREE_BYTES: &str = "min-free-bytes";
+const LOCAL_KEY_MAX_CONCURRENCY: &str = "max-concurrency";
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum IdentitySource {
     InMemory(String),
     OnDisk(std::path::PathBuf),
@@ -64,31 +115,177 @@ impl IdentitySource {
     }
 }
 
+/// An ssh server (a bastion) that the sftp destination is reached through, like ssh's `ProxyJump`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JumpHost {
+    pub host: String,
+    pub username: String,
+    pub identity: IdentitySource,
+}
+
 /// Defines a destination to which an upload will be made
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PathDescriptor {
-    Local(PathBuf),
+    Local {
+        path: PathBuf,
+        // If set, files aren't written if that would leave less than this many bytes free on the disk
+        min_free_bytes: Option<u64>,
+        max_concurrency: Option<NonZeroUsize>,
+    },
     Sftp {
         username: String,
         remote_address: String,
         remote_path: String,
         identity: IdentitySource,
+        // If set, a keepalive message is sent when the session has been idle for this long
+        keepalive_interval: Option<std::time::Duration>,
+        // If set, a blocking call on the session fails if it doesn't finish within this time
+        timeout: Option<std::time::Duration>,
+        // If set, a whole operation (e.g., an upload) fails if it doesn't finish within this time
+        operation_timeout: Option<std::time::Duration>,
+        max_concurrency: Option<NonZeroUsize>,
+        // If set, the destination is reached through a tunnel over an ssh session to this host
+        jump_host: Option<JumpHost>,
+        // The permissions of the files and directories that are created, 0o600 and 0o700 if not set
+        file_mode: Option<u16>,
+        dir_mode: Option<u16>,
+        // If set, connections to the destination (or its jump host) are made from this local address,
+        // e.g., to go through a specific interface
+        bind_address: Option<IpAddr>,
+        // If true, every upload is checked to be listed in the destination, and fails if it isn't
+        verify_listing_after_upload: bool,
+    },
+    Ftp {
+        host: String,
+        username: String,
+        password: Secret,
+        remote_path: String,
+        // Use explicit TLS (FTPS)
+        tls: bool,
+        max_concurrency: Option<NonZeroUsize>,
     },
 }
 
+impl PathDescriptor {
+    /// A local destination without a minimum of free space
+    pub fn local(path: impl Into<PathBuf>) -> Self {
+        Self::Local {
+            path: path.into(),
+            min_free_bytes: None,
+            max_concurrency: None,
+        }
+    }
+
+    /// If set, at most this many uploads to the destination run at the same time
+    #[must_use]
+    pub fn max_concurrency(&self) -> Option<NonZeroUsize> {
+        match self {
+            PathDescriptor::Local {
+                max_concurrency, ..
+            }
+            | PathDescriptor::Sftp {
+                max_concurrency, ..
+            }
+            | PathDescriptor::Ftp {
+                max_concurrency, ..
+            } => *max_concurrency,
+        }
+    }
+
+    /// The same destination, with connections made from `address` if it's one that supports a local address
+    /// and doesn't have its own
+    #[must_use]
+    pub fn with_default_bind_address(&self, address: IpAddr) -> Self {
+        let mut result = self.clone();
+        if let PathDescriptor::Sftp { bind_address, .. } = &mut result {
+            bind_address.get_or_insert(address);
+        }
+        result
+    }
+}
+
 impl Display for PathDescriptor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
-            PathDescriptor::Local(p) => format!("{LOCAL_PREFIX}:{LOCAL_KEY_PATH}={}", p.display()),
+            PathDescriptor::Local {
+                path,
+                min_free_bytes,
+                max_concurrency,
+            } => format!(
+                "{LOCAL_PREFIX}:{LOCAL_KEY_PATH}={}{}{}",
+                path.display(),
+                min_free_bytes
+                    .map(|v| format!(";{LOCAL_KEY_MIN_FREE_BYTES}={v}"))
+                    .unwrap_or_default(),
+                display_max_concurrency(LOCAL_KEY_MAX_CONCURRENCY, *max_concurrency)
+            ),
             PathDescriptor::Sftp {
                 username,
                 remote_address,
                 remote_path,
                 identity,
+                keepalive_interval,
+                timeout,
+                operation_timeout,
+                max_concurrency,
+                jump_host,
+                file_mode,
+                dir_mode,
+                bind_address,
+                verify_listing_after_upload,
+            } => {
+                // Optional keys are only shown when set
+                let session_options: String = [
+                    (SFTP_KEY_KEEPALIVE_INTERVAL, keepalive_interval),
+                    (SFTP_KEY_TIMEOUT, timeout),
+                    (SFTP_KEY_OPERATION_TIMEOUT, operation_timeout),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|v| format!(";{key}={}", v.as_secs())))
+                .collect();
+                let jump_host = jump_host
+                    .as_ref()
+                    .map(|jump| {
+                        format!(
+                            ";{SFTP_KEY_JUMP_HOST}={};{SFTP_KEY_JUMP_USER}={};{SFTP_KEY_JUMP_IDENTITY}={}",
+                            jump.host,
+                            jump.username,
+                            jump.identity.display()
+                        )
+                    })
+                    .unwrap_or_default();
+                let modes: String = [
+                    (SFTP_KEY_FILE_MODE, file_mode),
+                    (SFTP_KEY_DIR_MODE, dir_mode),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|v| format!(";{key}={v:04o}")))
+                .collect();
+                let bind_address = bind_address
+                    .map(|address| format!(";{SFTP_KEY_BIND_ADDRESS}={address}"))
+                    .unwrap_or_default();
+                let verify_listing = if *verify_listing_after_upload {
+                    format!(";{SFTP_KEY_VERIFY_LISTING}=true")
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{SFTP_PREFIX}:{SFTP_KEY_USER}={username};{SFTP_KEY_HOST}={remote_address};{SFTP_KEY_PATH}={remote_path};{SFTP_KEY_IDENTITY}={}{session_options}{}{jump_host}{modes}{bind_address}{verify_listing}",
+                    identity.display(),
+                    display_max_concurrency(SFTP_KEY_MAX_CONCURRENCY, *max_concurrency)
+                )
+            }
+            PathDescriptor::Ftp {
+                host,
+                username,
+                password: _,
+                remote_path,
+                tls,
+                max_concurrency,
             } => {
                 format!(
-                    "{SFTP_PREFIX}:{SFTP_KEY_USER}={username};{SFTP_KEY_HOST}={remote_address};{SFTP_KEY_PATH}={remote_path};{SFTP_KEY_IDENTITY}={}",
-                    identity.display()
+                    "{FTP_PREFIX}:{FTP_KEY_USER}={username};{FTP_KEY_PASSWORD}={HIDDEN_PASSWORD};{FTP_KEY_HOST}={host};{FTP_KEY_PATH}={remote_path};{FTP_KEY_TLS}={tls}{}",
+                    display_max_concurrency(FTP_KEY_MAX_CONCURRENCY, *max_concurrency)
                 )
             }
         };
@@ -105,49 +302,85 @@ impl FromStr for PathDescriptor {
         ))?;
 
         match dest_type.to_lowercase().as_str() {
-            // Format: `local:path=/home/user/something.txt``
+            // Format: `local:path=/home/user/something.txt;min-free-bytes=10000000000;max-concurrency=2`
             LOCAL_PREFIX => {
-                let key_vals = parse_key_vals_string(dest_data, dest_type, &[LOCAL_KEY_PATH], &[])?;
+                let key_vals = parse_key_vals_string(
+                    dest_data,
+                    dest_type,
+                    &[LOCAL_KEY_PATH],
+                    &[LOCAL_KEY_MIN_FREE_BYTES, LOCAL_KEY_MAX_CONCURRENCY],
+                )?;
                 let path = key_vals
                     .get(LOCAL_KEY_PATH)
                     .expect("Must exist since verified in parser");
-                Ok(PathDescriptor::Local(path.into()))
+                check_date_placeholders(path)?;
+                let min_free_bytes = key_vals
+                    .get(LOCAL_KEY_MIN_FREE_BYTES)
+                    .map(|v| {
+                        v.parse::<u64>().map_err(|_| {
+                            anyhow::anyhow!(
+                                "Failed to parse `{LOCAL_KEY_MIN_FREE_BYTES}` as a number of bytes: `{v}`"
+                            )
+                        })
+                    })
+                    .transpose()?;
+                let max_concurrency = parse_max_concurrency(&key_vals, LOCAL_KEY_MAX_CONCURRENCY)?;
+                Ok(PathDescriptor::Local {
+                    path: path.into(),
+                    min_free_bytes,
+                    max_concurrency,
+                })
             }
 
             // Format: sftp:username=<username>;host=example.com;port=22;remote-path=/home/user2/something_else;identity=/home/user/key.pem
-            SFTP_PREFIX => {
+            SFTP_PREFIX => parse_sftp(dest_data, dest_type),
+
+            // Format: ftp:username=<username>;password=<password>;host=example.com:21;remote-path=/home/user2/something_else;tls=true
+            // The password can be read from a file instead, with `password-file=/run/secrets/ftp_password`
+            FTP_PREFIX => {
                 const ERR: &str = "Must exist from parser";
 
                 let key_vals = parse_key_vals_string(
                     dest_data,
                     dest_type,
+                    &[FTP_KEY_USER, FTP_KEY_HOST, FTP_KEY_PATH],
                     &[
-                        SFTP_KEY_USER,
-                        SFTP_KEY_HOST,
-                        SFTP_KEY_PATH,
-                        SFTP_KEY_IDENTITY,
+                        FTP_KEY_PASSWORD,
+                        FTP_KEY_PASSWORD_FILE,
+                        FTP_KEY_TLS,
+                        FTP_KEY_MAX_CONCURRENCY,
                     ],
-                    &[],
                 )?;
 
-                let username = key_vals.get(SFTP_KEY_USER).expect(ERR);
-                let host = key_vals.get(SFTP_KEY_HOST).expect(ERR);
-                let remote_path = key_vals.get(SFTP_KEY_PATH).expect(ERR);
-                let identity = key_vals.get(SFTP_KEY_IDENTITY).expect(ERR);
+                let username = key_vals.get(FTP_KEY_USER).expect(ERR);
+                let password = parse_ftp_password(&key_vals)?;
+                let host = key_vals.get(FTP_KEY_HOST).expect(ERR);
+                let remote_path = parse_remote_path(key_vals.get(FTP_KEY_PATH).expect(ERR))?;
+                let tls = key_vals
+                    .get(FTP_KEY_TLS)
+                    .map(|v| {
+                        v.parse::<bool>().map_err(|_| {
+                            anyhow::anyhow!("Failed to parse `{FTP_KEY_TLS}` as true/false: `{v}`")
+                        })
+                    })
+                    .transpose()?
+                    .unwrap_or(false);
+                let max_concurrency = parse_max_concurrency(&key_vals, FTP_KEY_MAX_CONCURRENCY)?;
 
                 // Check valid port
-                if let Some((_host, port)) = host.split_once(':') {
+                if let (_host, Some(port)) = split_host_and_port(host) {
                     let _port = port
                         .parse::<u16>()
                         .map_err(|_| anyhow::anyhow!("Failed to parse port: `{port}`"))?;
                 }
 
-                // A query entry with identity must exist
-                Ok(PathDescriptor::Sftp {
-                    username: username.to_string(),
-                    remote_address: host.to_string(),
-                    remote_path: remote_path.to_string(),
-                    identity: IdentitySource::OnDisk(identity.into()),
+                Ok(PathDescriptor::Ftp {
+                    host: host.clone(),
+                    username: username.clone(),
+                    password,
+                    remote_path,
+                    tls,
+                    max_concurrency,
                 })
             }
 
@@ -158,6 +391,217 @@ impl FromStr for PathDescriptor {
     }
 }
 
+/// Parses an optional value of a whole number of seconds. Zero is rejected.
+fn parse_seconds(
+    key_vals: &BTreeMap<String, String>,
+    key: &str,
+) -> anyhow::Result<Option<std::time::Duration>> {
+    key_vals
+        .get(key)
+        .map(|v| match v.parse::<u32>() {
+            Ok(secs) if secs > 0 => Ok(std::time::Duration::from_secs(secs.into())),
+            _ => Err(anyhow::anyhow!(
+                "Failed to parse `{key}` as a positive number of seconds: `{v}`"
+            )),
+        })
+        .transpose()
+}
+
+/// The password is given either inline, or as a file to read it from, where a trailing newline
+/// isn't part of it, as secret files commonly end with one
+fn parse_ftp_password(key_vals: &BTreeMap<String, String>) -> anyhow::Result<Secret> {
+    match (
+        key_vals.get(FTP_KEY_PASSWORD),
+        key_vals.get(FTP_KEY_PASSWORD_FILE),
+    ) {
+        (Some(password), None) => Ok(Secret::new(password.clone())),
+        (None, Some(path)) => {
+            let password = std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read `{FTP_KEY_PASSWORD_FILE}` at `{path}`: {e}")
+            })?;
+            Ok(Secret::new(password.trim_end_matches(['\r', '\n'])))
+        }
+        (Some(_), Some(_)) => Err(anyhow::anyhow!(
+            "Only one of `{FTP_KEY_PASSWORD}` and `{FTP_KEY_PASSWORD_FILE}` can be set"
+        )),
+        (None, None) => Err(anyhow::anyhow!(
+            "Either `{FTP_KEY_PASSWORD}` or `{FTP_KEY_PASSWORD_FILE}` must be set"
+        )),
+    }
+}
+
+/// Parses an optional limit of concurrent uploads. Zero is rejected.
+fn parse_max_concurrency(
+    key_vals: &BTreeMap<String, String>,
+    key: &str,
+) -> anyhow::Result<Option<NonZeroUsize>> {
+    key_vals
+        .get(key)
+        .map(|v| {
+            v.parse::<NonZeroUsize>()
+                .map_err(|_| anyhow::anyhow!("Failed to parse `{key}` as a positive number: `{v}`"))
+        })
+        .transpose()
+}
+
+fn parse_sftp(dest_data: &str, dest_type: &str) -> anyhow::Result<PathDescriptor> {
+    const ERR: &str = "Must exist from parser";
+
+    let key_vals = parse_key_vals_string(
+        dest_data,
+        dest_type,
+        &[
+            SFTP_KEY_USER,
+            SFTP_KEY_HOST,
+            SFTP_KEY_PATH,
+            SFTP_KEY_IDENTITY,
+        ],
+        &[
+            SFTP_KEY_KEEPALIVE_INTERVAL,
+            SFTP_KEY_TIMEOUT,
+            SFTP_KEY_OPERATION_TIMEOUT,
+            SFTP_KEY_MAX_CONCURRENCY,
+            SFTP_KEY_JUMP_HOST,
+            SFTP_KEY_JUMP_USER,
+            SFTP_KEY_JUMP_IDENTITY,
+            SFTP_KEY_FILE_MODE,
+            SFTP_KEY_DIR_MODE,
+            SFTP_KEY_BIND_ADDRESS,
+            SFTP_KEY_VERIFY_LISTING,
+        ],
+    )?;
+
+    let username = key_vals.get(SFTP_KEY_USER).expect(ERR);
+    let host = key_vals.get(SFTP_KEY_HOST).expect(ERR);
+    let remote_path = parse_remote_path(key_vals.get(SFTP_KEY_PATH).expect(ERR))?;
+    let identity = key_vals.get(SFTP_KEY_IDENTITY).expect(ERR);
+    let keepalive_interval = parse_seconds(&key_vals, SFTP_KEY_KEEPALIVE_INTERVAL)?;
+    let timeout = parse_seconds(&key_vals, SFTP_KEY_TIMEOUT)?;
+    let operation_timeout = parse_seconds(&key_vals, SFTP_KEY_OPERATION_TIMEOUT)?;
+    let max_concurrency = parse_max_concurrency(&key_vals, SFTP_KEY_MAX_CONCURRENCY)?;
+    let jump_host = parse_jump_host(&key_vals, username, identity)?;
+    let file_mode = parse_mode(&key_vals, SFTP_KEY_FILE_MODE)?;
+    let dir_mode = parse_mode(&key_vals, SFTP_KEY_DIR_MODE)?;
+    let bind_address = key_vals
+        .get(SFTP_KEY_BIND_ADDRESS)
+        .map(|v| {
+            v.parse::<IpAddr>().map_err(|_| {
+                anyhow::anyhow!("Failed to parse `{SFTP_KEY_BIND_ADDRESS}` as an IP address: `{v}`")
+            })
+        })
+        .transpose()?;
+    let verify_listing_after_upload = key_vals
+        .get(SFTP_KEY_VERIFY_LISTING)
+        .map(|v| {
+            v.parse::<bool>().map_err(|_| {
+                anyhow::anyhow!("Failed to parse `{SFTP_KEY_VERIFY_LISTING}` as true/false: `{v}`")
+            })
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    // Check valid port
+    if let Some((_host, port)) = host.split_once(':') {
+        let _port = port
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Failed to parse port: `{port}`"))?;
+    }
+
+    // A query entry with identity must exist
+    Ok(PathDescriptor::Sftp {
+        username: username.clone(),
+        remote_address: host.clone(),
+        remote_path,
+        identity: IdentitySource::OnDisk(identity.into()),
+        keepalive_interval,
+        timeout,
+        operation_timeout,
+        max_concurrency,
+        jump_host,
+        file_mode,
+        dir_mode,
+        bind_address,
+        verify_listing_after_upload,
+    })
+}
+
+/// Parses optional permissions given in octal, e.g., `0640`, or `640`
+fn parse_mode(key_vals: &BTreeMap<String, String>, key: &str) -> anyhow::Result<Option<u16>> {
+    key_vals
+        .get(key)
+        .map(|v| match u16::from_str_radix(v, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(mode),
+            _ => Err(anyhow::anyhow!(
+                "Failed to parse `{key}` as octal permissions, like 0640: `{v}`"
+            )),
+        })
+        .transpose()
+}
+
+/// The jump host's user and identity default to the ones of the destination
+fn parse_jump_host(
+    key_vals: &BTreeMap<String, String>,
+    username: &str,
+    identity: &str,
+) -> anyhow::Result<Option<JumpHost>> {
+    let Some(host) = key_vals.get(SFTP_KEY_JUMP_HOST) else {
+        if let Some(key) = [SFTP_KEY_JUMP_USER, SFTP_KEY_JUMP_IDENTITY]
+            .into_iter()
+            .find(|key| key_vals.contains_key(*key))
+        {
+            return Err(anyhow::anyhow!(
+                "`{key}` can only be used with `{SFTP_KEY_JUMP_HOST}`"
+            ));
+        }
+        return Ok(None);
+    };
+
+    if let Some((_host, port)) = host.split_once(':') {
+        let _port = port
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Failed to parse jump host port: `{port}`"))?;
+    }
+
+    Ok(Some(JumpHost {
+        host: host.clone(),
+        username: key_vals
+            .get(SFTP_KEY_JUMP_USER)
+            .map_or(username, String::as_str)
+            .to_string(),
+        identity: IdentitySource::OnDisk(
+            key_vals
+                .get(SFTP_KEY_JUMP_IDENTITY)
+                .map_or(identity, String::as_str)
+                .into(),
+        ),
+    }))
+}
+
+fn display_max_concurrency(key: &str, max_concurrency: Option<NonZeroUsize>) -> String {
+    max_concurrency.map_or_else(String::new, |v| format!(";{key}={v}"))
+}
+
+/// Normalizes a remote path, e.g., `./a//b/` to `a/b`, so that it's resolved the same way by all
+/// destinations. A path that normalizes to nothing, like `a/..`, is the initial directory, `.`.
+fn parse_remote_path(remote_path: &str) -> anyhow::Result<String> {
+    if remote_path.trim().is_empty() {
+        return Err(anyhow::anyhow!("The remote path must not be empty"));
+    }
+    if remote_path.contains('\0') {
+        return Err(anyhow::anyhow!(
+            "The remote path must not contain a NUL character: `{}`",
+            remote_path.escape_default()
+        ));
+    }
+
+    let simplified = simplify_virtual_path(Path::new(remote_path));
+    if simplified.as_os_str().is_empty() {
+        return Ok(".".to_string());
+    }
+
+    Ok(simplified.to_string_lossy().into_owned())
+}
+
 fn parse_key_vals_string(
     input: &str,
     describing_what: &str,
@@ -220,7 +664,7 @@ mod tests {
     fn path_descriptor_parser() {
         {
             let d = PathDescriptor::from_str("local:path=/home/user/something.txt").unwrap();
-            assert_eq!(d, PathDescriptor::Local("/home/user/something.txt".into()));
+            assert_eq!(d, PathDescriptor::local("/home/user/something.txt"));
         }
 
         {
@@ -235,6 +679,15 @@ mod tests {
                     remote_address: "example.com".to_string(),
                     remote_path: "/home/user2/something_else.txt".to_string(),
                     identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                    keepalive_interval: None,
+                    timeout: None,
+                    operation_timeout: None,
+                    max_concurrency: None,
+                    jump_host: None,
+                    file_mode: None,
+                    dir_mode: None,
+                    bind_address: None,
+                    verify_listing_after_upload: false,
                 }
             );
         }
@@ -252,6 +705,15 @@ mod tests {
                     remote_address: "example.com:8888".to_string(),
                     remote_path: "/home/user2/something_else.txt".to_string(),
                     identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                    keepalive_interval: None,
+                    timeout: None,
+                    operation_timeout: None,
+                    max_concurrency: None,
+                    jump_host: None,
+                    file_mode: None,
+                    dir_mode: None,
+                    bind_address: None,
+                    verify_listing_after_upload: false,
                 }
             );
         }
@@ -276,12 +738,114 @@ mod tests {
         assert!(PathDescriptor::from_str("/home/user").is_err());
     }
 
+    #[test]
+    fn ftp_path_descriptor_parser() {
+        {
+            let d = PathDescriptor::from_str(
+                "ftp:username=user;password=pass;host=example.com:2121;remote-path=/home/user2/dir",
+            )
+            .unwrap();
+            assert_eq!(
+                d,
+                PathDescriptor::Ftp {
+                    host: "example.com:2121".to_string(),
+                    username: "user".to_string(),
+                    password: Secret::new("pass"),
+                    remote_path: "/home/user2/dir".to_string(),
+                    tls: false,
+                    max_concurrency: None,
+                }
+            );
+        }
+
+        {
+            let d = PathDescriptor::from_str(
+                "ftp:username=user;password=pass;host=example.com;remote-path=dir;tls=true",
+            )
+            .unwrap();
+            assert_eq!(
+                d,
+                PathDescriptor::Ftp {
+                    host: "example.com".to_string(),
+                    username: "user".to_string(),
+                    password: Secret::new("pass"),
+                    remote_path: "dir".to_string(),
+                    tls: true,
+                    max_concurrency: None,
+                }
+            );
+        }
+
+        {
+            let d = PathDescriptor::from_str(
+                "ftp:username=user;password=pass;host=[::1]:2121;remote-path=dir",
+            )
+            .unwrap();
+            assert!(matches!(d, PathDescriptor::Ftp { host, .. } if host == "[::1]:2121"));
+        }
+
+        assert!(
+            PathDescriptor::from_str(
+                "ftp:username=user;password=pass;host=[::1]:x;remote-path=dir"
+            )
+            .is_err()
+        );
+        assert!(
+            PathDescriptor::from_str(
+                "ftp:username=user;password=pass;host=example.com;remote-path=dir;tls=yes"
+            )
+            .is_err()
+        );
+        assert!(
+            PathDescriptor::from_str("ftp:username=user;host=example.com;remote-path=dir").is_err()
+        );
+    }
+
+    #[test]
+    fn ftp_password_is_read_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let password_path = dir.path().join("ftp_password");
+        std::fs::write(&password_path, "from-file\n").unwrap();
+
+        let d = PathDescriptor::from_str(&format!(
+            "ftp:username=user;password-file={};host=example.com;remote-path=dir",
+            password_path.display()
+        ))
+        .unwrap();
+        assert_eq!(
+            d,
+            PathDescriptor::Ftp {
+                host: "example.com".to_string(),
+                username: "user".to_string(),
+                password: Secret::new("from-file"),
+                remote_path: "dir".to_string(),
+                tls: false,
+                max_concurrency: None,
+            }
+        );
+
+        // Not both
+        assert!(
+            PathDescriptor::from_str(&format!(
+                "ftp:username=user;password=pass;password-file={};host=example.com;remote-path=dir",
+                password_path.display()
+            ))
+            .is_err()
+        );
+        assert!(
+            PathDescriptor::from_str(
+                "ftp:username=user;password-file=/nonexistent/ftp_password;host=example.com;remote-path=dir"
+            )
+            .is_err()
+        );
+    }
+
     #[test]
     fn path_descriptor_parse_back_and_forth() {
         {
             let s = "local:path=/home/user/something.txt";
             let d = PathDescriptor::from_str(s).unwrap();
-            assert_eq!(d, PathDescriptor::Local("/home/user/something.txt".into()));
+            assert_eq!(d, PathDescriptor::local("/home/user/something.txt"));
             assert_eq!(d.to_string(), s);
         }
 
@@ -295,6 +859,15 @@ mod tests {
                     remote_address: "example.com".to_string(),
                     remote_path: "/home/user2/something_else.txt".to_string(),
                     identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                    keepalive_interval: None,
+                    timeout: None,
+                    operation_timeout: None,
+                    max_concurrency: None,
+                    jump_host: None,
+                    file_mode: None,
+                    dir_mode: None,
+                    bind_address: None,
+                    verify_listing_after_upload: false,
                 }
             );
             {
@@ -332,6 +905,15 @@ mod tests {
                     remote_address: "example.com:8822".to_string(),
                     remote_path: "/home/user2/something_else.txt".to_string(),
                     identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                    keepalive_interval: None,
+                    timeout: None,
+                    operation_timeout: None,
+                    max_concurrency: None,
+                    jump_host: None,
+                    file_mode: None,
+                    dir_mode: None,
+                    bind_address: None,
+                    verify_listing_after_upload: false,
                 }
             );
             {
@@ -359,6 +941,354 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sftp_path_descriptor_session_options() {
+        let s = "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem;keepalive-interval=30;timeout=60;operation-timeout=600";
+        let d = PathDescriptor::from_str(s).unwrap();
+        assert_eq!(
+            d,
+            PathDescriptor::Sftp {
+                username: "user".to_string(),
+                remote_address: "example.com".to_string(),
+                remote_path: "dir".to_string(),
+                identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                keepalive_interval: Some(std::time::Duration::from_secs(30)),
+                timeout: Some(std::time::Duration::from_secs(60)),
+                operation_timeout: Some(std::time::Duration::from_secs(600)),
+                max_concurrency: None,
+                jump_host: None,
+                file_mode: None,
+                dir_mode: None,
+                bind_address: None,
+                verify_listing_after_upload: false,
+            }
+        );
+        assert_eq!(d.to_string(), s);
+
+        for invalid in [
+            "keepalive-interval=0",
+            "keepalive-interval=abc",
+            "timeout=-5",
+            "operation-timeout=0",
+        ] {
+            assert!(
+                PathDescriptor::from_str(&format!(
+                    "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem;{invalid}"
+                ))
+                .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn sftp_path_descriptor_jump_host() {
+        let s = "sftp:username=user;host=10.0.0.5:22;remote-path=dir;identity=/home/user/key.pem;jump-host=bastion.example.com:2222;jump-username=jumper;jump-identity=/home/user/bastion.pem";
+        let d = PathDescriptor::from_str(s).unwrap();
+        assert_eq!(
+            d,
+            PathDescriptor::Sftp {
+                username: "user".to_string(),
+                remote_address: "10.0.0.5:22".to_string(),
+                remote_path: "dir".to_string(),
+                identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                keepalive_interval: None,
+                timeout: None,
+                operation_timeout: None,
+                max_concurrency: None,
+                jump_host: Some(JumpHost {
+                    host: "bastion.example.com:2222".to_string(),
+                    username: "jumper".to_string(),
+                    identity: IdentitySource::OnDisk("/home/user/bastion.pem".into()),
+                }),
+                file_mode: None,
+                dir_mode: None,
+                bind_address: None,
+                verify_listing_after_upload: false,
+            }
+        );
+        assert_eq!(d.to_string(), s);
+
+        // The user and identity of the destination are used for the jump host by default
+        let d = PathDescriptor::from_str(
+            "sftp:username=user;host=10.0.0.5;remote-path=dir;identity=/home/user/key.pem;jump-host=bastion.example.com",
+        )
+        .unwrap();
+        match d {
+            PathDescriptor::Sftp { jump_host, .. } => assert_eq!(
+                jump_host,
+                Some(JumpHost {
+                    host: "bastion.example.com".to_string(),
+                    username: "user".to_string(),
+                    identity: IdentitySource::OnDisk("/home/user/key.pem".into()),
+                })
+            ),
+            _ => unreachable!(),
+        }
+
+        for invalid in [
+            "jump-host=bastion.example.com:abc",
+            "jump-username=jumper",
+            "jump-identity=/home/user/bastion.pem",
+        ] {
+            assert!(
+                PathDescriptor::from_str(&format!(
+                    "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem;{invalid}"
+                ))
+                .is_err()
+            );
+        }
+    }
+
+    #[rstest::rstest]
+    #[case("dir", "dir")]
+    #[case("./a//b/", "a/b")]
+    #[case("/home/user//dir/", "/home/user/dir")]
+    #[case("/home/./user/../dir", "/home/dir")]
+    #[case("a/..", ".")]
+    #[case(".", ".")]
+    #[case("../dir", "../dir")]
+    fn remote_path_is_normalized(#[case] remote_path: &str, #[case] expected: &str) {
+        let sftp = PathDescriptor::from_str(&format!(
+            "sftp:username=user;host=example.com;remote-path={remote_path};identity=/home/user/key.pem"
+        ))
+        .unwrap();
+        let ftp = PathDescriptor::from_str(&format!(
+            "ftp:username=user;password=pass;host=example.com;remote-path={remote_path}"
+        ))
+        .unwrap();
+
+        for d in [sftp, ftp] {
+            match d {
+                PathDescriptor::Sftp { remote_path, .. }
+                | PathDescriptor::Ftp { remote_path, .. } => {
+                    assert_eq!(remote_path, expected);
+                }
+                PathDescriptor::Local { .. } => unreachable!(),
+            }
+        }
+    }
+
+    #[rstest::rstest]
+    #[case("", "must not be empty")]
+    #[case("  ", "must not be empty")]
+    #[case("a\0b", "NUL")]
+    fn invalid_remote_path_is_rejected(#[case] remote_path: &str, #[case] expected_error: &str) {
+        let sftp = PathDescriptor::from_str(&format!(
+            "sftp:username=user;host=example.com;remote-path={remote_path};identity=/home/user/key.pem"
+        ));
+        let ftp = PathDescriptor::from_str(&format!(
+            "ftp:username=user;password=pass;host=example.com;remote-path={remote_path}"
+        ));
+
+        for result in [sftp, ftp] {
+            assert_str_contains(&result.unwrap_err().to_string(), expected_error);
+        }
+    }
+
+    #[rstest::rstest]
+    #[case("local:path=/mnt/frigate")]
+    #[case("sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem")]
+    #[case("ftp:username=user;password=<hidden>;host=example.com;remote-path=dir;tls=false")]
+    fn max_concurrency(#[case] descriptor: &str) {
+        let d = PathDescriptor::from_str(descriptor).unwrap();
+        assert_eq!(d.max_concurrency(), None);
+
+        let s = format!("{descriptor};max-concurrency=3");
+        let d = PathDescriptor::from_str(&s).unwrap();
+        assert_eq!(d.max_concurrency(), NonZeroUsize::new(3));
+        assert_eq!(d.to_string(), s);
+
+        for invalid in ["0", "-1", "abc"] {
+            assert!(
+                PathDescriptor::from_str(&format!("{descriptor};max-concurrency={invalid}"))
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn local_path_descriptor_min_free_bytes() {
+        let base = "local:path=/mnt/frigate";
+        assert_eq!(
+            PathDescriptor::from_str(base).unwrap(),
+            PathDescriptor::local("/mnt/frigate")
+        );
+
+        let s = format!("{base};min-free-bytes=10000000000");
+        let d = PathDescriptor::from_str(&s).unwrap();
+        assert_eq!(
+            d,
+            PathDescriptor::Local {
+                path: "/mnt/frigate".into(),
+                min_free_bytes: Some(10_000_000_000),
+                max_concurrency: None,
+            }
+        );
+        assert_eq!(d.to_string(), s);
+
+        for invalid in ["10GB", "-1", ""] {
+            assert!(PathDescriptor::from_str(&format!("{base};min-free-bytes={invalid}")).is_err());
+        }
+    }
+
+    #[test]
+    fn sftp_path_descriptor_modes() {
+        let base =
+            "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem";
+        let modes = |d: &PathDescriptor| match d {
+            PathDescriptor::Sftp {
+                file_mode,
+                dir_mode,
+                ..
+            } => (*file_mode, *dir_mode),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            modes(&PathDescriptor::from_str(base).unwrap()),
+            (None, None)
+        );
+
+        let s = format!("{base};file-mode=0640;dir-mode=0750");
+        let d = PathDescriptor::from_str(&s).unwrap();
+        assert_eq!(modes(&d), (Some(0o640), Some(0o750)));
+        assert_eq!(d.to_string(), s);
+
+        // Leading zeros are optional, and the mode is shown with 4 digits
+        let d = PathDescriptor::from_str(&format!("{base};dir-mode=755")).unwrap();
+        assert_eq!(modes(&d), (None, Some(0o755)));
+        assert_eq!(d.to_string(), format!("{base};dir-mode=0755"));
+
+        for invalid in [
+            "file-mode=0800",
+            "file-mode=abc",
+            "file-mode=-644",
+            "dir-mode=10000",
+            "dir-mode=",
+        ] {
+            assert!(PathDescriptor::from_str(&format!("{base};{invalid}")).is_err());
+        }
+    }
+
+    #[test]
+    fn sftp_path_descriptor_bind_address() {
+        let base =
+            "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem";
+        let bind_address = |d: &PathDescriptor| match d {
+            PathDescriptor::Sftp { bind_address, .. } => *bind_address,
+            _ => unreachable!(),
+        };
+
+        let d = PathDescriptor::from_str(base).unwrap();
+        assert_eq!(bind_address(&d), None);
+        assert_eq!(d.to_string(), base);
+
+        for address in ["10.8.0.2", "fd00::2"] {
+            let s = format!("{base};bind-address={address}");
+            let d = PathDescriptor::from_str(&s).unwrap();
+            assert_eq!(bind_address(&d), Some(address.parse().unwrap()));
+            assert_eq!(d.to_string(), s);
+        }
+
+        for invalid in [
+            "bind-address=",
+            "bind-address=eth0",
+            "bind-address=10.8.0.2:22",
+        ] {
+            assert!(PathDescriptor::from_str(&format!("{base};{invalid}")).is_err());
+        }
+    }
+
+    #[test]
+    fn sftp_path_descriptor_verify_listing_after_upload() {
+        let base =
+            "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem";
+        let verify_listing = |d: &PathDescriptor| match d {
+            PathDescriptor::Sftp {
+                verify_listing_after_upload,
+                ..
+            } => *verify_listing_after_upload,
+            _ => unreachable!(),
+        };
+
+        let d = PathDescriptor::from_str(base).unwrap();
+        assert!(!verify_listing(&d));
+        assert_eq!(d.to_string(), base);
+
+        let d =
+            PathDescriptor::from_str(&format!("{base};verify-listing-after-upload=false")).unwrap();
+        assert!(!verify_listing(&d));
+        assert_eq!(d.to_string(), base);
+
+        let s = format!("{base};verify-listing-after-upload=true");
+        let d = PathDescriptor::from_str(&s).unwrap();
+        assert!(verify_listing(&d));
+        assert_eq!(d.to_string(), s);
+
+        for invalid in [
+            "verify-listing-after-upload=",
+            "verify-listing-after-upload=yes",
+        ] {
+            assert!(PathDescriptor::from_str(&format!("{base};{invalid}")).is_err());
+        }
+    }
+
+    #[test]
+    fn default_bind_address_is_only_applied_without_one() {
+        let base =
+            "sftp:username=user;host=example.com;remote-path=dir;identity=/home/user/key.pem";
+        let default_address: IpAddr = "10.8.0.2".parse().unwrap();
+        let own_address: IpAddr = "192.168.1.2".parse().unwrap();
+
+        let d = PathDescriptor::from_str(base)
+            .unwrap()
+            .with_default_bind_address(default_address);
+        assert!(
+            matches!(d, PathDescriptor::Sftp { bind_address: Some(a), .. } if a == default_address)
+        );
+
+        let d = PathDescriptor::from_str(&format!("{base};bind-address={own_address}"))
+            .unwrap()
+            .with_default_bind_address(default_address);
+        assert!(
+            matches!(d, PathDescriptor::Sftp { bind_address: Some(a), .. } if a == own_address)
+        );
+
+        let d = PathDescriptor::local("/home/data");
+        assert_eq!(d.with_default_bind_address(default_address), d);
+    }
+
+    #[test]
+    fn ftp_path_descriptor_display_hides_password() {
+        let d = PathDescriptor::Ftp {
+            host: "example.com:21".to_string(),
+            username: "user".to_string(),
+            password: Secret::new("secret-pass"),
+            remote_path: "/home/user2/dir".to_string(),
+            tls: true,
+            max_concurrency: None,
+        };
+        assert!(!format!("{d:?}").contains("secret-pass"));
+
+        let serialized = d.to_string();
+        assert!(!serialized.contains("secret-pass"));
+        assert!(serialized.contains(&format!("{FTP_KEY_PASSWORD}={HIDDEN_PASSWORD}")));
+
+        // Apart from the password, it parses back to the same descriptor
+        let parsed = PathDescriptor::from_str(&serialized).unwrap();
+        assert_eq!(
+            parsed,
+            PathDescriptor::Ftp {
+                host: "example.com:21".to_string(),
+                username: "user".to_string(),
+                password: Secret::new(HIDDEN_PASSWORD),
+                remote_path: "/home/user2/dir".to_string(),
+                tls: true,
+                max_concurrency: None,
+            }
+        );
+    }
+
     #[test]
     fn key_value_parse_valid_input() {
         let input = "name=john;age=30";
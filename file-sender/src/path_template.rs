@@ -0,0 +1,88 @@
+use chrono::{DateTime, Datelike, TimeZone};
+use std::path::{Path, PathBuf};
+
+/// The placeholders that can be used in the path of a local destination, which are replaced
+/// with the date of the upload, e.g. `/mnt/{weekday}/frigate` for a disk per day of the week
+const DATE_PLACEHOLDERS: &[&str] = &["{year}", "{month}", "{day}", "{weekday}"];
+
+/// Fails if the path has a placeholder that isn't known, or a brace that isn't part of one
+pub fn check_date_placeholders(path: &str) -> anyhow::Result<()> {
+    let mut rest = path;
+    while let Some(start) = rest.find(['{', '}']) {
+        let placeholder = DATE_PLACEHOLDERS
+            .iter()
+            .find(|p| rest[start..].starts_with(**p))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown placeholder in path `{path}`. The known placeholders are: {}",
+                    DATE_PLACEHOLDERS.join(", ")
+                )
+            })?;
+        rest = &rest[start + placeholder.len()..];
+    }
+    Ok(())
+}
+
+/// Replaces the date placeholders in the path with the date of the given time:
+/// `{year}` (e.g. 2025), `{month}` (01-12), `{day}` (01-31) and `{weekday}` (mon-sun)
+pub fn resolve_date_placeholders<Tz: TimeZone>(path: &Path, datetime: &DateTime<Tz>) -> PathBuf {
+    let Some(path_str) = path.to_str() else {
+        return path.to_owned();
+    };
+    if !path_str.contains('{') {
+        return path.to_owned();
+    }
+
+    path_str
+        .replace("{year}", &format!("{:04}", datetime.year()))
+        .replace("{month}", &format!("{:02}", datetime.month()))
+        .replace("{day}", &format!("{:02}", datetime.day()))
+        .replace("{weekday}", &datetime.weekday().to_string().to_lowercase())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rstest::rstest;
+
+    #[rstest]
+    // 2025-06-02 was a Monday
+    #[case("2025-06-02T10:00:00Z", "/mnt/{weekday}/frigate", "/mnt/mon/frigate")]
+    #[case("2025-06-08T23:59:59Z", "/mnt/{weekday}/frigate", "/mnt/sun/frigate")]
+    #[case(
+        "2025-06-04T00:00:00Z",
+        "/archive/{year}/{month}/{day}",
+        "/archive/2025/06/04"
+    )]
+    #[case(
+        "2025-12-31T12:00:00Z",
+        "/archive/{year}-{weekday}",
+        "/archive/2025-wed"
+    )]
+    #[case("2025-12-31T12:00:00Z", "/archive/{year}/{year}", "/archive/2025/2025")]
+    #[case("2025-12-31T12:00:00Z", "/archive/plain", "/archive/plain")]
+    fn date_placeholders_are_resolved(
+        #[case] datetime: &str,
+        #[case] path: &str,
+        #[case] expected: &str,
+    ) {
+        let datetime = datetime.parse::<DateTime<Utc>>().unwrap();
+        check_date_placeholders(path).unwrap();
+        assert_eq!(
+            resolve_date_placeholders(Path::new(path), &datetime),
+            Path::new(expected)
+        );
+    }
+
+    #[rstest]
+    #[case("/mnt/{weekdays}/frigate")]
+    #[case("/mnt/{hour}")]
+    #[case("/mnt/{year")]
+    #[case("/mnt/year}")]
+    #[case("/mnt/{}")]
+    fn unknown_placeholders_are_rejected(#[case] path: &str) {
+        assert!(check_date_placeholders(path).is_err());
+    }
+}
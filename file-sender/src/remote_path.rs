@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+/// All the parents of the given path, from the outermost to the innermost,
+/// which are the directories that `mkdir_p` has to create before the path itself.
+pub fn get_all_parents_for_mkdir_p<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    let mut path = path.as_ref().to_owned();
+    while let Some(p) = path.parent() {
+        if p.to_string_lossy() != "" {
+            result.push(p.to_owned());
+        }
+        path = p.to_owned();
+    }
+
+    result.into_iter().rev().collect()
+}
+
+/// Simplifies cases of `abc/./xyz` to `abc/xyz`... and similar.
+pub fn simplify_virtual_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    let mut stack = Vec::new();
+    let is_absolute = path.is_absolute();
+
+    for comp in path.components() {
+        match comp {
+            std::path::Component::Prefix(_) => result.push(comp),
+            std::path::Component::RootDir => {
+                result.push(comp);
+                stack.clear(); // root resets the stack
+            }
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if let Some(last) = stack.pop() {
+                    if matches!(last, std::path::Component::Normal(_)) {
+                        // dropped
+                    } else {
+                        stack.push(last);
+                        if !is_absolute {
+                            stack.push(comp);
+                        }
+                    }
+                } else if !is_absolute {
+                    stack.push(comp);
+                }
+            }
+            std::path::Component::Normal(_) => stack.push(comp),
+        }
+    }
+
+    for comp in stack {
+        result.push(comp);
+    }
+
+    result
+}
+
+/// The path a file is written to before it's renamed to the given path, to replace it in one step.
+/// It's in the same directory, so that the rename doesn't move it across file systems.
+pub fn temporary_path_for_replace(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simplify_virtual_path() {
+        use std::path::{Path, PathBuf};
+
+        let s = |p| simplify_virtual_path(Path::new(p));
+
+        // Basic . and ..
+        assert_eq!(s("a/./.."), PathBuf::from(""));
+        assert_eq!(s("a/./b/../c"), PathBuf::from("a/c"));
+        assert_eq!(s("a/./b"), PathBuf::from("a/b"));
+        assert_eq!(s("./a/b"), PathBuf::from("a/b"));
+        assert_eq!(s("a/b/."), PathBuf::from("a/b"));
+        assert_eq!(s("."), PathBuf::from(""));
+        assert_eq!(s("./."), PathBuf::from(""));
+        assert_eq!(s("a/././b"), PathBuf::from("a/b"));
+        assert_eq!(s("a//b"), PathBuf::from("a/b"));
+        assert_eq!(s("a///b"), PathBuf::from("a/b"));
+        assert_eq!(s("a/./b/./c"), PathBuf::from("a/b/c"));
+        assert_eq!(s("a/./b/."), PathBuf::from("a/b"));
+        assert_eq!(s("a/./b/./"), PathBuf::from("a/b"));
+        assert_eq!(s(""), PathBuf::from(""));
+
+        // Absolute paths
+        assert_eq!(s("/a/./b"), PathBuf::from("/a/b"));
+        assert_eq!(s("/./a/b"), PathBuf::from("/a/b"));
+        assert_eq!(s("/a/b/."), PathBuf::from("/a/b"));
+        assert_eq!(s("/./"), PathBuf::from("/"));
+        assert_eq!(s("/"), PathBuf::from("/"));
+
+        // Parent resolution
+        assert_eq!(s("a/.."), PathBuf::from(""));
+        assert_eq!(s("a/b/.."), PathBuf::from("a"));
+        assert_eq!(s("a/b/../.."), PathBuf::from(""));
+        assert_eq!(s("a/b/../../.."), PathBuf::from(".."));
+        assert_eq!(s("a/./b/../c"), PathBuf::from("a/c"));
+        assert_eq!(s("./a/../b/."), PathBuf::from("b"));
+        assert_eq!(s("/a/b/../c"), PathBuf::from("/a/c"));
+        assert_eq!(s("/a/../../b"), PathBuf::from("/b"));
+
+        // Relative paths with leading ..
+        assert_eq!(s("../../a/b"), PathBuf::from("../../a/b"));
+        assert_eq!(s("../../../a"), PathBuf::from("../../../a"));
+        assert_eq!(s("../a"), PathBuf::from("../a"));
+        assert_eq!(s("../.."), PathBuf::from("../.."));
+        assert_eq!(s(".."), PathBuf::from(".."));
+        assert_eq!(s("./../a"), PathBuf::from("../a"));
+
+        // Absolute paths trying to go above root
+        assert_eq!(s("/.."), PathBuf::from("/"));
+        assert_eq!(s("/../.."), PathBuf::from("/"));
+
+        // Redundant parent dirs
+        assert_eq!(s("a/b/../../c"), PathBuf::from("c"));
+    }
+}
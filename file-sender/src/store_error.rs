@@ -0,0 +1,96 @@
+/// Errors of stores that callers handle differently from other failures.
+/// They're returned inside `anyhow::Error`, so [`StoreError::is_out_of_space`] and
+/// [`StoreError::is_directory_not_found`] look for them.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// Retrying doesn't help until space is freed in the destination
+    #[error("Destination `{destination}` is out of space: {reason}")]
+    OutOfSpace { destination: String, reason: String },
+    /// The write would leave less free space than the destination is configured to keep.
+    /// Like [`StoreError::OutOfSpace`], retrying doesn't help until space is freed.
+    #[error(
+        "Destination `{destination}` has {free_bytes} bytes free, which is not enough to write {write_bytes} bytes and keep {min_free_bytes} bytes free"
+    )]
+    BelowMinFreeSpace {
+        destination: String,
+        free_bytes: u64,
+        write_bytes: u64,
+        min_free_bytes: u64,
+    },
+    /// A file couldn't be written because its directory doesn't exist, e.g., it was deleted after it was made.
+    /// Making the directory again lets the write be retried.
+    #[error(
+        "Directory of the file to write doesn't exist in destination `{destination}`: {reason}"
+    )]
+    DirectoryNotFound { destination: String, reason: String },
+}
+
+impl StoreError {
+    /// Whether the error, or any error in its chain, is [`StoreError::OutOfSpace`],
+    /// or [`StoreError::BelowMinFreeSpace`]
+    #[must_use]
+    pub fn is_out_of_space(error: &anyhow::Error) -> bool {
+        error.chain().any(|e| {
+            matches!(
+                e.downcast_ref(),
+                Some(StoreError::OutOfSpace { .. } | StoreError::BelowMinFreeSpace { .. })
+            )
+        })
+    }
+
+    /// Whether the error, or any error in its chain, is [`StoreError::DirectoryNotFound`]
+    #[must_use]
+    pub fn is_directory_not_found(error: &anyhow::Error) -> bool {
+        error
+            .chain()
+            .any(|e| matches!(e.downcast_ref(), Some(StoreError::DirectoryNotFound { .. })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn out_of_space_is_found_under_context() {
+        let error = anyhow::Error::new(StoreError::OutOfSpace {
+            destination: "/data".to_string(),
+            reason: "No space left on device".to_string(),
+        });
+        assert!(StoreError::is_out_of_space(&error));
+
+        let error = Err::<(), _>(error).context("Writing file").unwrap_err();
+        assert!(StoreError::is_out_of_space(&error));
+
+        let error = Err::<(), _>(anyhow::Error::new(StoreError::BelowMinFreeSpace {
+            destination: "/data".to_string(),
+            free_bytes: 100,
+            write_bytes: 10,
+            min_free_bytes: 1000,
+        }))
+        .context("Writing file")
+        .unwrap_err();
+        assert!(StoreError::is_out_of_space(&error));
+
+        assert!(!StoreError::is_out_of_space(&anyhow::anyhow!(
+            "Connection reset"
+        )));
+    }
+
+    #[test]
+    fn directory_not_found_is_found_under_context() {
+        let error = Err::<(), _>(anyhow::Error::new(StoreError::DirectoryNotFound {
+            destination: "/data".to_string(),
+            reason: "No such file or directory".to_string(),
+        }))
+        .context("Writing file")
+        .unwrap_err();
+        assert!(StoreError::is_directory_not_found(&error));
+        assert!(!StoreError::is_out_of_space(&error));
+
+        assert!(!StoreError::is_directory_not_found(&anyhow::anyhow!(
+            "Connection reset"
+        )));
+    }
+}
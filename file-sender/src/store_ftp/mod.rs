@@ -0,0 +1,670 @@
+use crate::{
+    path_descriptor::{PathDescriptor, Secret},
+    remote_path::{get_all_parents_for_mkdir_p, simplify_virtual_path},
+    traits::StoreDestination,
+};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use suppaftp::{
+    Mode, Status,
+    tokio::{AsyncRustlsConnector, AsyncRustlsFtpStream},
+    tokio_rustls::{
+        TlsConnector,
+        rustls::{self, ClientConfig, RootCertStore},
+    },
+    types::FileType,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const DEFAULT_FTP_PORT: u16 = 21;
+
+/// How long connecting to the server, for both the control and data connections, may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a command, or a read or write of a transfer, may wait without progress, e.g., for a server that stopped responding
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+const TRANSFER_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A store on an FTP server, optionally over explicit TLS (FTPS).
+/// The connection is made on first use, and made again after any error that could have broken it.
+pub struct FtpStore {
+    path_descriptor: Arc<PathDescriptor>,
+    host: String,
+    username: String,
+    password: Secret,
+    tls: bool,
+    base_remote_path: PathBuf,
+    client: tokio::sync::Mutex<Option<FtpConnection>>,
+}
+
+impl FtpStore {
+    pub fn new(
+        path_descriptor: Arc<PathDescriptor>,
+        host: &str,
+        username: &str,
+        password: &Secret,
+        tls: bool,
+        base_remote_path: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            path_descriptor,
+            host: host.to_string(),
+            username: username.to_string(),
+            password: password.clone(),
+            tls,
+            base_remote_path: simplify_virtual_path(&base_remote_path.into()),
+            client: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.base_remote_path.join(path)
+    }
+
+    async fn connected_client(
+        &self,
+    ) -> Result<tokio::sync::MutexGuard<'_, Option<FtpConnection>>, FtpError> {
+        let mut client = self.client.lock().await;
+        if client.is_none() {
+            tracing::trace!("Connecting to FTP server: {}", self.path_descriptor);
+            *client = Some(
+                FtpConnection::connect(
+                    &self.host,
+                    &self.username,
+                    self.password.expose(),
+                    self.tls,
+                )
+                .await?,
+            );
+        }
+        Ok(client)
+    }
+
+    async fn ls_low_level(&self, path: &Path) -> Result<Vec<PathBuf>, FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.name_list(&path_to_ftp(path)).await;
+        let names = drop_broken_connection(&mut guard, result)?;
+
+        // Servers differ in whether they return names or paths
+        let names = names
+            .into_iter()
+            .filter_map(|n| Path::new(&n).file_name().map(PathBuf::from))
+            .collect();
+        Ok(names)
+    }
+
+    async fn dir_exists_low_level(&self, path: &Path) -> Result<bool, FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.dir_exists(&path_to_ftp(path)).await;
+        drop_broken_connection(&mut guard, result)
+    }
+
+    async fn file_exists_low_level(&self, path: &Path) -> Result<bool, FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.size(&path_to_ftp(path)).await;
+        drop_broken_connection(&mut guard, result).map(|size| size.is_some())
+    }
+
+    async fn file_size_low_level(&self, path: &Path) -> Result<u64, FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.size(&path_to_ftp(path)).await;
+        drop_broken_connection(&mut guard, result)?
+            .ok_or_else(|| FtpError::FileNotFound(path.to_owned()))
+    }
+
+    async fn put_from_memory_low_level(&self, from: &[u8], to: &Path) -> Result<(), FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.store(&path_to_ftp(to), from).await;
+        drop_broken_connection(&mut guard, result)
+    }
+
+    async fn get_to_memory_low_level(&self, from: &Path) -> Result<Vec<u8>, FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.retrieve(&path_to_ftp(from)).await;
+        drop_broken_connection(&mut guard, result)
+    }
+
+    async fn del_low_level(&self, path: &Path) -> Result<(), FtpError> {
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.delete(&path_to_ftp(path)).await;
+        drop_broken_connection(&mut guard, result)
+    }
+
+    /// Functionality of mkdir, but without resolving
+    async fn mkdir_low_level(&self, path: &Path) -> Result<(), FtpError> {
+        if self.dir_exists_low_level(path).await? {
+            return Ok(());
+        }
+        let mut guard = self.connected_client().await?;
+        let client = guard.as_mut().expect("Connected above");
+        let result = client.mkdir(&path_to_ftp(path)).await;
+        drop_broken_connection(&mut guard, result)
+    }
+
+    /// Functionality of `mkdir_p`, but without resolving
+    async fn mkdir_p_low_level(&self, path: &Path) -> Result<(), FtpError> {
+        if self.dir_exists_low_level(path).await? {
+            return Ok(());
+        }
+
+        for p in get_all_parents_for_mkdir_p(path) {
+            self.mkdir_low_level(&p).await?;
+        }
+
+        self.mkdir_low_level(path).await
+    }
+}
+
+/// Forgets the connection if the error could have left it in an unknown state, so that the next call reconnects
+fn drop_broken_connection<T>(
+    client: &mut Option<FtpConnection>,
+    result: Result<T, FtpError>,
+) -> Result<T, FtpError> {
+    if let Err(e) = &result {
+        if e.breaks_connection() {
+            tracing::trace!("Dropping FTP connection after error: {e}");
+            *client = None;
+        }
+    }
+    result
+}
+
+fn path_to_ftp(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[async_trait::async_trait]
+impl StoreDestination for FtpStore {
+    type Error = anyhow::Error;
+
+    async fn init(&self) -> Result<(), Self::Error> {
+        tracing::trace!("Initializing file sender: {}", self.path_descriptor);
+
+        if !self.dir_exists_low_level(&self.base_remote_path).await? {
+            tracing::trace!(
+                "Path in descriptor does not exist. Creating it: {}",
+                self.base_remote_path.display()
+            );
+            self.mkdir_p_low_level(&self.base_remote_path).await?;
+        }
+
+        if !self.dir_exists_low_level(&self.base_remote_path).await? {
+            return Err(FtpError::DestPathNotFound(self.base_remote_path.clone()).into());
+        }
+
+        Ok(())
+    }
+
+    async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+        Ok(self.ls_low_level(&self.resolve(path)).await?)
+    }
+
+    async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
+        Ok(self.del_low_level(&self.resolve(path)).await?)
+    }
+
+    async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
+        let path_resolved = self.resolve(path);
+        if self.dir_exists_low_level(&path_resolved).await? {
+            return Ok(());
+        }
+
+        for p in get_all_parents_for_mkdir_p(path) {
+            self.mkdir_low_level(&self.resolve(p)).await?;
+        }
+
+        Ok(self.mkdir_low_level(&path_resolved).await?)
+    }
+
+    async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
+        if !from.exists() {
+            return Err(FtpError::SourceFileNotFound(from.to_owned()).into());
+        }
+        let data = tokio::fs::read(from)
+            .await
+            .map_err(|e| FtpError::SourceFileReadFailed(from.to_owned(), e))?;
+        Ok(self
+            .put_from_memory_low_level(&data, &self.resolve(to))
+            .await?)
+    }
+
+    async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        Ok(self
+            .put_from_memory_low_level(from, &self.resolve(to))
+            .await?)
+    }
+
+    async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.get_to_memory_low_level(&self.resolve(from)).await?)
+    }
+
+    async fn dir_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        Ok(self.dir_exists_low_level(&self.resolve(path)).await?)
+    }
+
+    async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        Ok(self.file_exists_low_level(&self.resolve(path)).await?)
+    }
+
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        Ok(self.file_size_low_level(&self.resolve(path)).await?)
+    }
+
+    fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+        &self.path_descriptor
+    }
+}
+
+/// A logged in connection to the server, with passive mode data connections that go to the host
+/// we're connected to, regardless of the address the server advertises, which is often wrong behind NAT.
+struct FtpConnection {
+    stream: AsyncRustlsFtpStream,
+    /// The working directory after login. Relative paths are relative to it.
+    home_dir: String,
+}
+
+impl FtpConnection {
+    async fn connect(
+        host: &str,
+        username: &str,
+        password: &str,
+        tls: bool,
+    ) -> Result<Self, FtpError> {
+        let (domain, port) = split_host_and_port(host);
+        let port = match port {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_| FtpError::InvalidPort(port.to_string()))?,
+            None => DEFAULT_FTP_PORT,
+        };
+
+        let tcp = connect_with_timeout((domain, port))
+            .await
+            .map_err(FtpError::Connect)?;
+        let peer_address = tcp.peer_addr().map_err(FtpError::Connect)?;
+
+        let mut stream = with_timeout(AsyncRustlsFtpStream::connect_with_stream(tcp))
+            .await?
+            .passive_stream_builder(|address| {
+                Box::pin(async move {
+                    connect_with_timeout(address)
+                        .await
+                        .map_err(suppaftp::FtpError::ConnectionError)
+                })
+            });
+        // EPSV always uses the host we're connected to. PASV does so with the NAT workaround.
+        if peer_address.is_ipv6() {
+            stream.set_mode(Mode::ExtendedPassive);
+        } else {
+            stream.set_mode(Mode::Passive);
+            stream.set_passive_nat_workaround(true);
+        }
+
+        if tls {
+            stream = with_timeout(stream.into_secure(tls_connector()?, domain)).await?;
+        }
+
+        with_timeout(stream.login(username, password)).await?;
+        with_timeout(stream.transfer_type(FileType::Binary)).await?;
+        let home_dir = with_timeout(stream.pwd()).await?;
+
+        Ok(Self { stream, home_dir })
+    }
+
+    async fn store(&mut self, path: &str, data: &[u8]) -> Result<(), FtpError> {
+        let mut data_stream = with_timeout(self.stream.put_with_stream(path)).await?;
+        for chunk in data.chunks(TRANSFER_CHUNK_SIZE) {
+            io_with_timeout(data_stream.write_all(chunk)).await?;
+        }
+        // Closing the data connection is what marks the end of the file
+        with_timeout(self.stream.finalize_put_stream(data_stream)).await
+    }
+
+    async fn retrieve(&mut self, path: &str) -> Result<Vec<u8>, FtpError> {
+        let mut data_stream = with_timeout(self.stream.retr_as_stream(path)).await?;
+        let mut result = Vec::new();
+        let mut buffer = vec![0; TRANSFER_CHUNK_SIZE];
+        loop {
+            let size = io_with_timeout(data_stream.read(&mut buffer)).await?;
+            if size == 0 {
+                break;
+            }
+            result.extend_from_slice(&buffer[..size]);
+        }
+        with_timeout(self.stream.finalize_retr_stream(data_stream)).await?;
+        Ok(result)
+    }
+
+    async fn name_list(&mut self, path: &str) -> Result<Vec<String>, FtpError> {
+        with_timeout(self.stream.nlst(Some(path))).await
+    }
+
+    async fn delete(&mut self, path: &str) -> Result<(), FtpError> {
+        with_timeout(self.stream.rm(path)).await
+    }
+
+    async fn mkdir(&mut self, path: &str) -> Result<(), FtpError> {
+        with_timeout(self.stream.mkdir(path)).await
+    }
+
+    /// Returns the size of the file, or None if it doesn't exist. Directories are not files, so they return None too.
+    async fn size(&mut self, path: &str) -> Result<Option<u64>, FtpError> {
+        match with_timeout(self.stream.size(path)).await {
+            Ok(size) => Ok(Some(size as u64)),
+            Err(e) if e.is_file_unavailable() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// FTP has no portable way to check for a directory, so we try to enter it, then return to where we were
+    async fn dir_exists(&mut self, path: &str) -> Result<bool, FtpError> {
+        match with_timeout(self.stream.cwd(path)).await {
+            Ok(()) => {
+                with_timeout(self.stream.cwd(&self.home_dir)).await?;
+                Ok(true)
+            }
+            Err(e) if e.is_file_unavailable() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Data connections resume the TLS session of the control connection, since they share the config,
+/// which servers like vsftpd require by default
+fn tls_connector() -> Result<AsyncRustlsConnector, FtpError> {
+    let native_certs = rustls_native_certs::load_native_certs();
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(native_certs.certs);
+    if roots.is_empty() {
+        let errors = native_certs
+            .errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(FtpError::NoRootCertificates(errors));
+    }
+
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(FtpError::TlsConfig)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)).into())
+}
+
+async fn connect_with_timeout(
+    address: impl tokio::net::ToSocketAddrs,
+) -> std::io::Result<TcpStream> {
+    tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("Connecting timed out after {CONNECT_TIMEOUT:?}"),
+            )
+        })?
+}
+
+/// Runs an FTP command, failing if the server doesn't finish it within `IO_TIMEOUT`
+async fn with_timeout<T>(
+    command: impl Future<Output = suppaftp::FtpResult<T>>,
+) -> Result<T, FtpError> {
+    tokio::time::timeout(IO_TIMEOUT, command)
+        .await
+        .map_err(|_| FtpError::TimedOut(IO_TIMEOUT))?
+        .map_err(FtpError::from)
+}
+
+/// Runs a read or write of a transfer, failing if it makes no progress within `IO_TIMEOUT`
+async fn io_with_timeout<T>(
+    operation: impl Future<Output = std::io::Result<T>>,
+) -> Result<T, FtpError> {
+    tokio::time::timeout(IO_TIMEOUT, operation)
+        .await
+        .map_err(|_| FtpError::TimedOut(IO_TIMEOUT))?
+        .map_err(FtpError::DataTransfer)
+}
+
+/// Splits the host from its port, if it has one. IPv6 addresses with a port are in brackets, e.g., `[::1]:21`,
+/// and the host is returned without them. An IPv6 address without brackets has no port.
+pub(crate) fn split_host_and_port(host: &str) -> (&str, Option<&str>) {
+    if let Some((ip, rest)) = host.strip_prefix('[').and_then(|host| host.split_once(']')) {
+        return (
+            ip,
+            rest.strip_prefix(':')
+                .or(Some(rest).filter(|r| !r.is_empty())),
+        );
+    }
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') => (name, Some(port)),
+        _ => (host, None),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FtpError {
+    #[error("Invalid port: {0}")]
+    InvalidPort(String),
+    #[error("Connecting to server failed: {0}")]
+    Connect(std::io::Error),
+    #[error("No root certificates found for TLS. Errors: {0}")]
+    NoRootCertificates(String),
+    #[error("TLS configuration error: {0}")]
+    TlsConfig(rustls::Error),
+    #[error("FTP error: {0}")]
+    Protocol(suppaftp::FtpError),
+    #[error("Data transfer failed: {0}")]
+    DataTransfer(std::io::Error),
+    #[error("The server didn't respond within {0:?}")]
+    TimedOut(Duration),
+    #[error(
+        "The server refused the data connection because it didn't reuse the TLS session of the control connection. Disable this requirement on the server (e.g., `require_ssl_reuse=NO` in vsftpd). Reply: {0}"
+    )]
+    TlsSessionReuseRequired(String),
+    #[error("Could not find source file for put: {0}")]
+    SourceFileNotFound(PathBuf),
+    #[error("Could not read source file for put: {0}. Error: {1}")]
+    SourceFileReadFailed(PathBuf, std::io::Error),
+    #[error("Destination path not found: {0}")]
+    DestPathNotFound(PathBuf),
+    #[error("File not found: {0}")]
+    FileNotFound(PathBuf),
+}
+
+impl From<suppaftp::FtpError> for FtpError {
+    fn from(e: suppaftp::FtpError) -> Self {
+        match e {
+            // What vsftpd replies with `require_ssl_reuse` when the data connection has a new TLS session
+            suppaftp::FtpError::UnexpectedResponse(response)
+                if response.body.starts_with(b"522") =>
+            {
+                FtpError::TlsSessionReuseRequired(response.as_string().unwrap_or_default())
+            }
+            e => FtpError::Protocol(e),
+        }
+    }
+}
+
+impl FtpError {
+    /// Whether the connection can't be trusted anymore after this error
+    fn breaks_connection(&self) -> bool {
+        match self {
+            FtpError::Protocol(e) => !matches!(e, suppaftp::FtpError::UnexpectedResponse(_)),
+            FtpError::Connect(_)
+            | FtpError::NoRootCertificates(_)
+            | FtpError::TlsConfig(_)
+            | FtpError::DataTransfer(_)
+            | FtpError::TimedOut(_) => true,
+            FtpError::InvalidPort(_)
+            | FtpError::TlsSessionReuseRequired(_)
+            | FtpError::SourceFileNotFound(_)
+            | FtpError::SourceFileReadFailed(_, _)
+            | FtpError::DestPathNotFound(_)
+            | FtpError::FileNotFound(_) => false,
+        }
+    }
+
+    /// Whether the server replied that the file or directory isn't there, or isn't accessible
+    fn is_file_unavailable(&self) -> bool {
+        matches!(
+            self,
+            FtpError::Protocol(suppaftp::FtpError::UnexpectedResponse(response))
+                if response.status == Status::FileUnavailable
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// The server side of a control connection, which plays a script against the client under test
+    struct FakeServer {
+        control: BufReader<TcpStream>,
+    }
+
+    impl FakeServer {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.unwrap();
+            Self {
+                control: BufReader::new(stream),
+            }
+        }
+
+        async fn reply(&mut self, line: &str) {
+            self.control
+                .get_mut()
+                .write_all(format!("{line}\r\n").as_bytes())
+                .await
+                .unwrap();
+        }
+
+        async fn expect(&mut self, command: &str) {
+            let mut line = String::new();
+            self.control.read_line(&mut line).await.unwrap();
+            assert_eq!(line.trim_end(), command);
+        }
+
+        async fn login(&mut self) {
+            self.reply("220 Ready").await;
+            self.expect("USER user").await;
+            self.reply("331 Password required").await;
+            self.expect("PASS pass").await;
+            self.reply("230 Logged in").await;
+            self.expect("TYPE I").await;
+            self.reply("200 Binary").await;
+            self.expect("PWD").await;
+            self.reply(r#"257 "/home/user" is the current directory"#)
+                .await;
+        }
+
+        /// Replies to PASV with an address that isn't reachable, like a server behind NAT would advertise
+        async fn passive_behind_nat(&mut self, data_port: u16) {
+            self.expect("PASV").await;
+            self.reply(&format!(
+                "227 Entering Passive Mode (10,255,255,1,{},{})",
+                data_port / 256,
+                data_port % 256
+            ))
+            .await;
+        }
+    }
+
+    async fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        (listener, address)
+    }
+
+    #[tokio::test]
+    async fn passive_mode_ignores_the_advertised_address() {
+        let (listener, address) = listen().await;
+        let (data_listener, _) = listen().await;
+        let data_port = data_listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut server = FakeServer::accept(&listener).await;
+            server.login().await;
+            server.passive_behind_nat(data_port).await;
+            server.expect("RETR file").await;
+            let (mut data, _) = data_listener.accept().await.unwrap();
+            server.reply("150 Opening data connection").await;
+            data.write_all(b"content").await.unwrap();
+            drop(data);
+            server.reply("226 Transfer complete").await;
+        });
+
+        let mut connection = FtpConnection::connect(&address, "user", "pass", false)
+            .await
+            .unwrap();
+        assert_eq!(connection.home_dir, "/home/user");
+        assert_eq!(connection.retrieve("file").await.unwrap(), b"content");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refused_tls_session_reuse_is_reported_and_keeps_the_connection() {
+        let (listener, address) = listen().await;
+        let (data_listener, _) = listen().await;
+        let data_port = data_listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut server = FakeServer::accept(&listener).await;
+            server.login().await;
+            server.passive_behind_nat(data_port).await;
+            server.expect("STOR file").await;
+            let (mut data, _) = data_listener.accept().await.unwrap();
+            server.reply("150 Ok to send data").await;
+            let mut received = Vec::new();
+            data.read_to_end(&mut received).await.unwrap();
+            drop(data);
+            server
+                .reply("522 SSL connection failed: session reuse required")
+                .await;
+            server.expect("SIZE file").await;
+            server.reply("550 No such file").await;
+        });
+
+        let mut connection = FtpConnection::connect(&address, "user", "pass", false)
+            .await
+            .unwrap();
+        let error = connection.store("file", b"content").await.unwrap_err();
+        assert!(matches!(error, FtpError::TlsSessionReuseRequired(_)));
+        assert!(!error.breaks_connection());
+        assert_eq!(connection.size("file").await.unwrap(), None);
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn hosts_and_ports() {
+        assert_eq!(split_host_and_port("example.com"), ("example.com", None));
+        assert_eq!(
+            split_host_and_port("example.com:2121"),
+            ("example.com", Some("2121"))
+        );
+        assert_eq!(split_host_and_port("[::1]:21"), ("::1", Some("21")));
+        assert_eq!(split_host_and_port("[::1]"), ("::1", None));
+        assert_eq!(split_host_and_port("2001:db8::1"), ("2001:db8::1", None));
+        assert_eq!(split_host_and_port("[::1]x"), ("::1", Some("x")));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    path_descriptor::PathDescriptor,
+    traits::{ByteStream, DeletionResults, StoreDestination},
+};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+use tokio::sync::Semaphore;
+
+/// The upload permits of every destination that has a concurrency limit, shared by all the stores made for it
+static UPLOAD_PERMITS: LazyLock<UploadPermits> = LazyLock::new(UploadPermits::default);
+
+#[derive(Default)]
+struct UploadPermits {
+    permits: Mutex<HashMap<PathDescriptor, Arc<Semaphore>>>,
+}
+
+impl UploadPermits {
+    fn get(
+        &self,
+        path_descriptor: &PathDescriptor,
+        max_concurrency: NonZeroUsize,
+    ) -> Arc<Semaphore> {
+        self.permits
+            .lock()
+            .expect("Poisoned mutex")
+            .entry(path_descriptor.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(max_concurrency.get())))
+            .clone()
+    }
+}
+
+/// Wraps a store so that at most `max_concurrency` uploads run at the same time to its destination,
+/// across all the stores made for that destination. Other operations aren't limited.
+pub struct ConcurrencyLimitedStore {
+    inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimitedStore {
+    pub fn new(
+        inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+        max_concurrency: NonZeroUsize,
+    ) -> Self {
+        let permits = UPLOAD_PERMITS.get(inner.path_descriptor(), max_concurrency);
+        Self { inner, permits }
+    }
+
+    async fn acquire(&self) -> tokio::sync::SemaphorePermit<'_> {
+        self.permits
+            .acquire()
+            .await
+            .expect("The semaphore is never closed")
+    }
+}
+
+#[async_trait::async_trait]
+impl StoreDestination for ConcurrencyLimitedStore {
+    type Error = anyhow::Error;
+
+    async fn init(&self) -> Result<(), Self::Error> {
+        self.inner.init().await
+    }
+
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().await
+    }
+
+    async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+        self.inner.ls(path).await
+    }
+
+    async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
+        self.inner.del_file(path).await
+    }
+
+    async fn del_files(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<DeletionResults<Self::Error>, Self::Error> {
+        self.inner.del_files(paths).await
+    }
+
+    async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
+        self.inner.mkdir_p(path).await
+    }
+
+    async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
+        let _permit = self.acquire().await;
+        self.inner.put(from, to).await
+    }
+
+    async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        let _permit = self.acquire().await;
+        self.inner.put_from_memory(from, to).await
+    }
+
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        let _permit = self.acquire().await;
+        self.inner.replace_from_memory(from, to).await
+    }
+
+    async fn put_from_stream(&self, from: ByteStream, to: &Path) -> Result<(), Self::Error> {
+        let _permit = self.acquire().await;
+        self.inner.put_from_stream(from, to).await
+    }
+
+    async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
+        self.inner.get_to_memory(from).await
+    }
+
+    async fn dir_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.inner.dir_exists(path).await
+    }
+
+    async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.inner.file_exists(path).await
+    }
+
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        self.inner.file_size(path).await
+    }
+
+    fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+        self.inner.path_descriptor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A store whose uploads take a while, and that records the most uploads that ever overlapped
+    struct SlowStore {
+        path_descriptor: Arc<PathDescriptor>,
+        running_puts: AtomicUsize,
+        max_overlapping_puts: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl StoreDestination for SlowStore {
+        type Error = anyhow::Error;
+
+        async fn init(&self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn ls(&self, _path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn del_file(&self, _path: &Path) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn mkdir_p(&self, _path: &Path) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn put(&self, _from: &Path, _to: &Path) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn put_from_memory(&self, _from: &[u8], _to: &Path) -> Result<(), Self::Error> {
+            let running = self.running_puts.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_overlapping_puts
+                .fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            self.running_puts.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn get_to_memory(&self, _from: &Path) -> Result<Vec<u8>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn dir_exists(&self, _path: &Path) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+
+        async fn file_exists(&self, _path: &Path) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+
+        fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+            &self.path_descriptor
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(1)]
+    #[case(3)]
+    #[tokio::test(start_paused = true)]
+    async fn uploads_never_exceed_max_concurrency(#[case] max_concurrency: usize) {
+        // Unique per case, since the permits of a destination are shared process-wide
+        let inner = Arc::new(SlowStore {
+            path_descriptor: Arc::new(PathDescriptor::local(format!("/limited/{max_concurrency}"))),
+            running_puts: AtomicUsize::new(0),
+            max_overlapping_puts: AtomicUsize::new(0),
+        });
+
+        let tasks = (0..8)
+            .map(|i| {
+                // Every upload gets its own store, like the stores made for each upload
+                let store = ConcurrencyLimitedStore::new(
+                    inner.clone(),
+                    NonZeroUsize::new(max_concurrency).unwrap(),
+                );
+                tokio::spawn(async move {
+                    store
+                        .put_from_memory(b"Hello world!", Path::new(&format!("file{i}")))
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(
+            inner.max_overlapping_puts.load(Ordering::SeqCst),
+            max_concurrency
+        );
+    }
+}
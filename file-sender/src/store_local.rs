@@ -1,31 +1,101 @@
 use anyhow::Context;
 use async_trait::async_trait;
+use futures::StreamExt;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::fs;
+use tokio::{fs, io::AsyncWriteExt};
 
 use crate::path_descriptor::PathDescriptor;
-use crate::traits::StoreDestination;
+use crate::path_template::resolve_date_placeholders;
+use crate::remote_path::temporary_path_for_replace;
+use crate::store_error::StoreError;
+use crate::traits::{ByteStream, StoreDestination};
+use utils::time_getter::TimeGetter;
+
 pub struct LocalStore {
     path_descriptor: Arc<PathDescriptor>,
     dest_dir: PathBuf,
+    min_free_bytes: Option<u64>,
 }
 
 impl LocalStore {
-    pub fn new<P: AsRef<Path>>(path_descriptor: Arc<PathDescriptor>, dest_dir: P) -> Self {
-        let dest_dir = dest_dir.as_ref();
+    /// The date placeholders in `dest_dir` are resolved with the current time. As a store is made
+    /// for every upload, that's the time of the upload.
+    pub fn new<P: AsRef<Path>>(
+        path_descriptor: Arc<PathDescriptor>,
+        dest_dir: P,
+        min_free_bytes: Option<u64>,
+        time_getter: &TimeGetter,
+    ) -> Self {
+        let dest_dir = resolve_date_placeholders(
+            dest_dir.as_ref(),
+            &time_getter.get_time().as_local_datetime(),
+        );
         tracing::debug!("Creating local storage object in {}", dest_dir.display());
 
         Self {
             path_descriptor,
-            dest_dir: dest_dir.to_path_buf(),
+            dest_dir,
+            min_free_bytes,
         }
     }
 
     fn resolve<P: AsRef<Path>>(&self, path: &P) -> PathBuf {
         self.dest_dir.join(path)
     }
+
+    /// A full disk is reported as [`StoreError::OutOfSpace`], so that callers don't retry it like other errors,
+    /// and a missing directory as [`StoreError::DirectoryNotFound`], so that they can make it again
+    fn write_error(&self, e: std::io::Error) -> anyhow::Error {
+        match e.kind() {
+            std::io::ErrorKind::StorageFull => StoreError::OutOfSpace {
+                destination: self.dest_dir.display().to_string(),
+                reason: e.to_string(),
+            }
+            .into(),
+            std::io::ErrorKind::NotFound => StoreError::DirectoryNotFound {
+                destination: self.dest_dir.display().to_string(),
+                reason: e.to_string(),
+            }
+            .into(),
+            _ => e.into(),
+        }
+    }
+
+    /// Fails with [`StoreError::BelowMinFreeSpace`] if writing `write_bytes` would leave less than the minimum
+    /// free space on the disk of the destination. Streams are checked with zero bytes, since their size isn't known.
+    fn check_free_space(&self, write_bytes: u64) -> anyhow::Result<()> {
+        let Some(min_free_bytes) = self.min_free_bytes else {
+            return Ok(());
+        };
+
+        let free_bytes = free_space(&self.dest_dir).context(format!(
+            "Reading free space of local directory: {}",
+            self.dest_dir.display()
+        ))?;
+        if free_bytes.saturating_sub(write_bytes) < min_free_bytes {
+            return Err(StoreError::BelowMinFreeSpace {
+                destination: self.dest_dir.display().to_string(),
+                free_bytes,
+                write_bytes,
+                min_free_bytes,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// The space available to unprivileged users on the file system of the path
+fn free_space(path: &Path) -> anyhow::Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    // The types of the fields differ between platforms
+    #[allow(clippy::useless_conversion)]
+    let free_bytes =
+        u64::from(stat.blocks_available()).saturating_mul(u64::from(stat.fragment_size()));
+    Ok(free_bytes)
 }
 
 #[async_trait]
@@ -41,6 +111,22 @@ impl StoreDestination for LocalStore {
         Ok(())
     }
 
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        let metadata = fs::metadata(&self.dest_dir).await.context(format!(
+            "Reading metadata of local directory: {}",
+            self.dest_dir.display()
+        ))?;
+
+        if !metadata.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Local destination is not a directory: {}",
+                self.dest_dir.display()
+            ));
+        }
+
+        Ok(())
+    }
+
     async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
         let full_path = self.resolve(&path);
         tracing::debug!("Calling 'ls' on path: `{}`", full_path.display());
@@ -65,7 +151,7 @@ impl StoreDestination for LocalStore {
     async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
         fs::create_dir_all(self.resolve(&path))
             .await
-            .map_err(Into::into)
+            .map_err(|e| self.write_error(e))
     }
 
     async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
@@ -75,10 +161,11 @@ impl StoreDestination for LocalStore {
             from.display(),
             to_path.display()
         );
+        self.check_free_space(fs::metadata(from).await?.len())?;
         fs::copy(from, to_path)
             .await
             .map(|_| ())
-            .map_err(Into::into)
+            .map_err(|e| self.write_error(e))
     }
 
     async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
@@ -88,7 +175,62 @@ impl StoreDestination for LocalStore {
             from.len(),
             to_path.display()
         );
-        Ok(fs::write(to_path, from).await?)
+        self.check_free_space(from.len() as u64)?;
+        fs::write(to_path, from)
+            .await
+            .map_err(|e| self.write_error(e))
+    }
+
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        let to_path = self.resolve(&to);
+        let temp_path = temporary_path_for_replace(&to_path);
+        tracing::debug!(
+            "Calling 'replace_from_memory' for memory data with size {} bytes to path: `{}`",
+            from.len(),
+            to_path.display()
+        );
+        self.check_free_space(from.len() as u64)?;
+
+        let result = async {
+            fs::write(&temp_path, from)
+                .await
+                .map_err(|e| self.write_error(e))?;
+            fs::rename(&temp_path, &to_path).await.map_err(Into::into)
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path).await;
+        }
+
+        result
+    }
+
+    async fn put_from_stream(&self, mut from: ByteStream, to: &Path) -> Result<(), Self::Error> {
+        let to_path = self.resolve(&to);
+        tracing::debug!("Calling 'put_from_stream' to path: `{}`", to_path.display());
+        self.check_free_space(0)?;
+
+        let result = async {
+            let mut file = fs::File::create(&to_path)
+                .await
+                .map_err(|e| self.write_error(e))?;
+            while let Some(chunk) = from.next().await {
+                file.write_all(&chunk?)
+                    .await
+                    .map_err(|e| self.write_error(e))?;
+            }
+            file.flush().await.map_err(|e| self.write_error(e))?;
+            Ok(())
+        }
+        .await;
+
+        // A partial file would look like a complete one
+        if result.is_err() {
+            let _ = fs::remove_file(&to_path).await;
+        }
+
+        result
     }
 
     async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
@@ -109,7 +251,195 @@ impl StoreDestination for LocalStore {
         Ok(self.resolve(&path).is_file())
     }
 
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        let full_path = self.resolve(&path);
+        tracing::debug!("Calling 'file_size' on path: `{}`", full_path.display());
+        Ok(std::fs::metadata(full_path)?.len())
+    }
+
     fn path_descriptor(&self) -> &Arc<PathDescriptor> {
         &self.path_descriptor
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+    use utils::{time::Time, time_getter::TimeGetterFn};
+
+    struct FixedTimeGetterFn(Time);
+
+    impl TimeGetterFn for FixedTimeGetterFn {
+        fn get_time(&self) -> Time {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn dest_dir_placeholders_are_resolved_at_upload_time() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dest_dir = temp_dir.path().join("{weekday}").join("{year}");
+        let path_descriptor = Arc::new(PathDescriptor::local(dest_dir.clone()));
+
+        // 2025-06-04, noon UTC
+        let time = Time::from_secs_since_epoch(1_749_038_400);
+        let time_getter = TimeGetter::new(Arc::new(FixedTimeGetterFn(time)));
+        let store = LocalStore::new(path_descriptor, &dest_dir, None, &time_getter);
+
+        store.init().await.unwrap();
+        store
+            .put_from_memory(b"Hello world!", Path::new("clip.mp4"))
+            .await
+            .unwrap();
+
+        let weekday = time
+            .as_local_datetime()
+            .weekday()
+            .to_string()
+            .to_lowercase();
+        let uploaded = temp_dir.path().join(weekday).join("2025").join("clip.mp4");
+        assert_eq!(std::fs::read(uploaded).unwrap(), b"Hello world!");
+    }
+
+    #[tokio::test]
+    async fn replace_overwrites_existing_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_descriptor = Arc::new(PathDescriptor::local(temp_dir.path().to_owned()));
+        let store = LocalStore::new(
+            path_descriptor,
+            temp_dir.path(),
+            None,
+            &TimeGetter::default(),
+        );
+
+        for data in [b"first".as_slice(), b"second"] {
+            store
+                .replace_from_memory(data, Path::new("latest.jpg"))
+                .await
+                .unwrap();
+            assert_eq!(
+                std::fs::read(temp_dir.path().join("latest.jpg")).unwrap(),
+                data
+            );
+        }
+
+        // No temporary file is left behind
+        assert_eq!(
+            store.ls(Path::new(".")).await.unwrap(),
+            vec![PathBuf::from("latest.jpg")]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_to_missing_directory_are_reported_as_such() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_descriptor = Arc::new(PathDescriptor::local(temp_dir.path()));
+        let store = LocalStore::new(
+            path_descriptor,
+            temp_dir.path(),
+            None,
+            &TimeGetter::default(),
+        );
+
+        let path = Path::new("2025-01-31/clip.mp4");
+        let err = store
+            .put_from_memory(b"Hello world!", path)
+            .await
+            .unwrap_err();
+        assert!(StoreError::is_directory_not_found(&err));
+        let err = store
+            .replace_from_memory(b"Hello world!", path)
+            .await
+            .unwrap_err();
+        assert!(StoreError::is_directory_not_found(&err));
+
+        store.mkdir_p(Path::new("2025-01-31")).await.unwrap();
+        store.put_from_memory(b"Hello world!", path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn del_files_deletes_each_file_and_reports_each_result() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_descriptor = Arc::new(PathDescriptor::local(temp_dir.path()));
+        let store = LocalStore::new(
+            path_descriptor,
+            temp_dir.path(),
+            None,
+            &TimeGetter::default(),
+        );
+
+        for name in ["a.mp4", "b.mp4", "kept.mp4"] {
+            store
+                .put_from_memory(b"Hello world!", Path::new(name))
+                .await
+                .unwrap();
+        }
+
+        let paths = ["a.mp4", "missing.mp4", "b.mp4"].map(PathBuf::from);
+        let results = store.del_files(&paths).await.unwrap();
+
+        // A file that can't be deleted doesn't stop the ones after it
+        assert_eq!(
+            results.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>(),
+            paths
+        );
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_ok());
+        assert_eq!(
+            store.ls(Path::new(".")).await.unwrap(),
+            vec![PathBuf::from("kept.mp4")]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_that_leave_too_little_free_space_are_refused() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_descriptor = Arc::new(PathDescriptor::local(temp_dir.path()));
+        let free_bytes = free_space(temp_dir.path()).unwrap();
+
+        // No disk has this much free, so nothing is written
+        let store = LocalStore::new(
+            path_descriptor.clone(),
+            temp_dir.path(),
+            Some(u64::MAX),
+            &TimeGetter::default(),
+        );
+        let err = store
+            .put_from_memory(b"Hello world!", Path::new("clip.mp4"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(StoreError::BelowMinFreeSpace { .. })
+        ));
+        assert!(StoreError::is_out_of_space(&err));
+        let stream: ByteStream = Box::pin(futures::stream::iter([Ok(bytes::Bytes::from(
+            "Hello world!",
+        ))]));
+        assert!(
+            store
+                .put_from_stream(stream, Path::new("clip.mp4"))
+                .await
+                .is_err()
+        );
+        assert!(store.ls(Path::new(".")).await.unwrap().is_empty());
+
+        // Writes that leave enough free space go through
+        let store = LocalStore::new(
+            path_descriptor,
+            temp_dir.path(),
+            Some(free_bytes / 2),
+            &TimeGetter::default(),
+        );
+        store
+            .put_from_memory(b"Hello world!", Path::new("clip.mp4"))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("clip.mp4")).unwrap(),
+            b"Hello world!"
+        );
+    }
+}
@@ -1,25 +1,38 @@
 use crate::{
-    path_descriptor::{IdentitySource, PathDescriptor},
+    path_descriptor::{IdentitySource, JumpHost, PathDescriptor},
     traits::StoreDestination,
 };
 use async_trait::async_trait;
-use ssh2::{self, ErrorCode, OpenFlags, Session};
+use ssh2::{self, ErrorCode, Session};
 use std::{
     io::{BufRead, BufReader, Read},
-    net::TcpStream,
+    os::fd::OwnedFd,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 use tracing::trace_span;
 
-use super::SftpError;
+use super::{
+    SftpError, SftpSessionOptions,
+    socket::connect,
+    transport::{SftpTransport, Ssh2Transport},
+    tunnel::connect_through_jump_host,
+};
+use crate::remote_path::{
+    get_all_parents_for_mkdir_p, simplify_virtual_path, temporary_path_for_replace,
+};
+
+/// Only the owner can access what's uploaded, unless configured otherwise
+const DEFAULT_FILE_MODE: u16 = 0o600;
+const DEFAULT_DIR_MODE: u16 = 0o700;
 
 pub struct BlockingSftpImpl {
     path_descriptor: Arc<PathDescriptor>,
-    #[allow(dead_code)]
-    session: ssh2::Session,
-    sftp: ssh2::Sftp,
+    transport: Box<dyn SftpTransport>,
     base_remote_path: PathBuf,
+    file_mode: i32,
+    dir_mode: i32,
 }
 
 impl BlockingSftpImpl {
@@ -30,31 +43,75 @@ impl BlockingSftpImpl {
         username: &str,
         priv_key: IdentitySource,
         base_remote_path: impl Into<PathBuf>,
+        jump_host: Option<JumpHost>,
+        session_options: SftpSessionOptions,
     ) -> Result<Self, SftpError> {
+        let span = tracing::debug_span!("sftp_connect", host);
+        let _enter = span.enter();
+
         let mut session = Session::new().map_err(SftpError::SessionInitError)?;
 
-        let tcp = TcpStream::connect(host).unwrap();
+        if let Some(timeout) = session_options.timeout {
+            session.set_timeout(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX));
+        }
+
+        let tcp: OwnedFd = match jump_host {
+            Some(jump_host) => connect_through_jump_host(jump_host, host, session_options)?.into(),
+            None => connect(host, session_options.bind_address)
+                .map_err(|e| SftpError::ConnectFailed(host.to_string(), e))?
+                .into(),
+        };
         session.set_tcp_stream(tcp);
-        session.handshake().map_err(SftpError::HandshakeFailed)?;
+        let handshake_ms = timed(
+            &tracing::debug_span!("sftp_handshake", duration_ms = tracing::field::Empty),
+            || session.handshake().map_err(SftpError::HandshakeFailed),
+        )?;
+
+        if let Some(interval) = session_options.keepalive_interval {
+            // The server doesn't need to reply. A dead connection shows as a failure to send.
+            session.set_keepalive(false, u32::try_from(interval.as_secs()).unwrap_or(u32::MAX));
+        }
 
         let priv_key = priv_key.into_key()?;
 
-        session
-            .userauth_pubkey_memory(username, None, &priv_key, None)
-            .map_err(SftpError::PubKeyAuthError)?;
+        let auth_ms = timed(
+            &tracing::debug_span!("sftp_auth", username, duration_ms = tracing::field::Empty),
+            || {
+                session
+                    .userauth_pubkey_memory(username, None, &priv_key, None)
+                    .map_err(SftpError::PubKeyAuthError)
+            },
+        )?;
 
         let sftp = session.sftp().map_err(SftpError::SftpChannelOpenFailed)?;
+        tracing::debug!(
+            "Connected to sftp host `{host}`. Handshake took {handshake_ms} ms, and authentication {auth_ms} ms"
+        );
 
-        let base_remote_path = simplify_virtual_path(&base_remote_path.into());
-
-        let result = BlockingSftpImpl {
+        Ok(Self::with_transport(
             path_descriptor,
-            session,
-            sftp,
+            Box::new(Ssh2Transport::new(session, sftp)),
             base_remote_path,
-        };
+            &session_options,
+        ))
+    }
 
-        Ok(result)
+    fn with_transport(
+        path_descriptor: Arc<PathDescriptor>,
+        transport: Box<dyn SftpTransport>,
+        base_remote_path: impl Into<PathBuf>,
+        session_options: &SftpSessionOptions,
+    ) -> Self {
+        BlockingSftpImpl {
+            path_descriptor,
+            transport,
+            base_remote_path: simplify_virtual_path(&base_remote_path.into()),
+            file_mode: session_options
+                .file_mode
+                .unwrap_or(DEFAULT_FILE_MODE)
+                .into(),
+            dir_mode: session_options.dir_mode.unwrap_or(DEFAULT_DIR_MODE).into(),
+        }
     }
 
     fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
@@ -63,9 +120,7 @@ impl BlockingSftpImpl {
 
     fn ls_inner<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>, SftpError> {
         let path = self.resolve(path.as_ref());
-        let contents = self.sftp.readdir(path).map_err(SftpError::LsFailed)?;
-        let names = contents.into_iter().map(|v| v.0).collect();
-        Ok(names)
+        self.transport.readdir(&path).map_err(SftpError::LsFailed)
     }
 
     pub fn init(&self) -> Result<(), SftpError> {
@@ -98,7 +153,7 @@ impl BlockingSftpImpl {
                 })?;
         }
 
-        self.sftp
+        self.transport
             .opendir(&self.base_remote_path)
             .map_err(|_e| SftpError::DestPathNotFound(self.base_remote_path.clone()))
             .inspect_err(|e| {
@@ -107,7 +162,7 @@ impl BlockingSftpImpl {
                     self.base_remote_path.display()
                 );
             })
-            .inspect(|_| {
+            .inspect(|()| {
                 tracing::trace!(
                     "Opening dir in init success. Dir: `{}`.",
                     self.base_remote_path.display()
@@ -117,6 +172,30 @@ impl BlockingSftpImpl {
         Ok(())
     }
 
+    /// Sends a keepalive message if the session has been idle for the keepalive interval.
+    /// Returns how long until it should be called again.
+    pub fn keepalive_send(&self) -> Result<std::time::Duration, SftpError> {
+        let secs = self
+            .transport
+            .keepalive_send()
+            .map_err(SftpError::KeepaliveFailed)?;
+        Ok(std::time::Duration::from_secs(secs.into()))
+    }
+
+    /// A single stat of the base path, which is cheaper than listing it
+    pub fn health_check(&self) -> Result<(), SftpError> {
+        let stat = self
+            .transport
+            .stat(&self.base_remote_path)
+            .map_err(SftpError::StatFailed)?;
+
+        if stat.is_dir() {
+            Ok(())
+        } else {
+            Err(SftpError::DestPathNotFound(self.base_remote_path.clone()))
+        }
+    }
+
     pub fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, SftpError> {
         let result = self.ls_inner(path)?;
         let result = result
@@ -133,16 +212,28 @@ impl BlockingSftpImpl {
 
     pub fn del<P: AsRef<Path>>(&self, path: P) -> Result<(), SftpError> {
         let path = self.resolve(path.as_ref());
-        self.sftp.unlink(&path).map_err(SftpError::DelFileFailed)
+        self.transport
+            .unlink(&path)
+            .map_err(SftpError::DelFileFailed)
     }
 
+    /// The size and duration of the transfer are recorded in its span, to tell a slow link from a slow server
     fn copy_buffers(
         src: impl std::io::Read,
         mut dst: impl std::io::Write,
     ) -> Result<(), SftpError> {
+        let span = tracing::debug_span!(
+            "sftp_transfer",
+            bytes = tracing::field::Empty,
+            duration_ms = tracing::field::Empty
+        );
+        let _enter = span.enter();
+        let start = Instant::now();
+
         let mut buffer_queue = Vec::<u8>::new();
         let max_buffer_size = 1 << 24;
         let mut src_file_reader = BufReader::new(src);
+        let mut bytes = 0;
         loop {
             let size = Self::fill_buffer(&mut buffer_queue, &mut src_file_reader, max_buffer_size)?;
             if size == 0 {
@@ -152,8 +243,14 @@ impl BlockingSftpImpl {
             dst.write_all(&buffer_queue)
                 .map_err(SftpError::FileCopyForPutFailed)?;
             buffer_queue.clear();
+            bytes += size;
+            span.record("bytes", bytes);
         }
 
+        let duration_ms = elapsed_ms(start);
+        span.record("duration_ms", duration_ms);
+        tracing::debug!("Transferred {bytes} bytes in {duration_ms} ms");
+
         Ok(())
     }
 
@@ -166,13 +263,8 @@ impl BlockingSftpImpl {
         let src_file = std::fs::File::open(from)
             .map_err(|e| SftpError::SourceFileOpenFailed(from.to_owned(), e))?;
         let dest_file = self
-            .sftp
-            .open_mode(
-                to,
-                OpenFlags::WRITE | OpenFlags::CREATE,
-                0o600,
-                ssh2::OpenType::File,
-            )
+            .transport
+            .create(&to, self.file_mode)
             .map_err(SftpError::OpenDestinationFileToWriteFailed)?;
 
         // We don't use std::io::buffer because this is more efficient with buffering
@@ -189,13 +281,8 @@ impl BlockingSftpImpl {
         let to = self.resolve(to.as_ref());
 
         let dest_file = self
-            .sftp
-            .open_mode(
-                to,
-                OpenFlags::WRITE | OpenFlags::CREATE,
-                0o600,
-                ssh2::OpenType::File,
-            )
+            .transport
+            .create(&to, self.file_mode)
             .map_err(SftpError::OpenDestinationFileToWriteFailed)?;
 
         let from_buffer = from.as_ref();
@@ -206,12 +293,44 @@ impl BlockingSftpImpl {
         Ok(())
     }
 
+    /// Writes a temporary file next to `to`, and renames it over `to`. Servers that don't replace
+    /// an existing file on rename, like OpenSSH's, get `to` deleted first, so it's briefly missing.
+    pub fn replace_from_memory<P: AsRef<[u8]>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<(), SftpError> {
+        let temp_path = temporary_path_for_replace(to.as_ref());
+        self.put_from_memory(from, &temp_path)?;
+
+        let temp_path = self.resolve(&temp_path);
+        let to = self.resolve(to.as_ref());
+        let result = self.transport.rename(&temp_path, &to).or_else(|e| {
+            if self.transport.stat(&to).is_err() {
+                return Err(e);
+            }
+            tracing::debug!(
+                "Renaming over existing file `{}` failed. Deleting it first. Error: {e}",
+                to.display()
+            );
+            self.transport
+                .unlink(&to)
+                .and_then(|()| self.transport.rename(&temp_path, &to))
+        });
+
+        if result.is_err() {
+            let _ = self.transport.unlink(&temp_path);
+        }
+
+        result.map_err(SftpError::RenameFailed)
+    }
+
     pub fn get_to_memory<Q: AsRef<Path>>(&self, from: Q) -> Result<Vec<u8>, SftpError> {
         let from = self.resolve(from.as_ref());
 
         let mut dest_file = self
-            .sftp
-            .open(from)
+            .transport
+            .open(&from)
             .map_err(SftpError::OpenDestinationFileToReadFailed)?;
 
         let mut result = Vec::new();
@@ -251,7 +370,7 @@ impl BlockingSftpImpl {
 
     // Same as dir_exists, but without resolving
     fn dir_exists_low_level<P: AsRef<Path>>(&self, path: P) -> Result<bool, SftpError> {
-        match self.sftp.readdir(path) {
+        match self.transport.readdir(path.as_ref()) {
             Ok(_) => Ok(true),
             Err(e) => {
                 if e.code() == ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_NO_SUCH_FILE) {
@@ -265,7 +384,7 @@ impl BlockingSftpImpl {
 
     pub fn file_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool, SftpError> {
         let path = self.resolve(path.as_ref());
-        match self.sftp.open(path) {
+        match self.transport.open(&path) {
             Ok(_) => Ok(true),
             Err(e) => {
                 if e.code() == ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_NO_SUCH_FILE) {
@@ -277,25 +396,31 @@ impl BlockingSftpImpl {
         }
     }
 
+    pub fn file_size<P: AsRef<Path>>(&self, path: P) -> Result<u64, SftpError> {
+        let path = self.resolve(path.as_ref());
+        let stat = self.transport.stat(&path).map_err(SftpError::StatFailed)?;
+        stat.size.ok_or(SftpError::FileSizeUnknown(path))
+    }
+
     /// Functionality of mkdir, but without resolving
     fn mkdir_low_level<P: AsRef<Path>>(&self, path: P) -> Result<(), SftpError> {
         if self.dir_exists_low_level(path.as_ref())? {
             return Ok(());
         }
-        self.sftp
-            .mkdir(path.as_ref(), 0o700)
+        self.transport
+            .mkdir(path.as_ref(), self.dir_mode)
             .map_err(SftpError::MkdirFailed)
     }
 
     /// Functionality of `mkdir_p`, but without resolving
     fn mkdir_p_low_level(&self, path: &Path) -> Result<(), SftpError> {
-        if self.dir_exists(path)? {
+        if self.dir_exists_low_level(path)? {
             return Ok(());
         }
 
         let parents = get_all_parents_for_mkdir_p(path);
         for p in parents {
-            if !self.dir_exists(&p)? {
+            if !self.dir_exists_low_level(&p)? {
                 self.mkdir_low_level(&p)?;
             }
         }
@@ -320,58 +445,6 @@ impl BlockingSftpImpl {
     }
 }
 
-fn get_all_parents_for_mkdir_p<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
-    let mut result = Vec::new();
-    let mut path = path.as_ref().to_owned();
-    while let Some(p) = path.parent() {
-        if p.to_string_lossy() != "" {
-            result.push(p.to_owned());
-        }
-        path = p.to_owned();
-    }
-
-    result.into_iter().rev().collect()
-}
-
-/// Simplifies cases of `abc/./xyz` to `abc/xyz`... and similar.
-fn simplify_virtual_path(path: &Path) -> PathBuf {
-    let mut result = PathBuf::new();
-    let mut stack = Vec::new();
-    let is_absolute = path.is_absolute();
-
-    for comp in path.components() {
-        match comp {
-            std::path::Component::Prefix(_) => result.push(comp),
-            std::path::Component::RootDir => {
-                result.push(comp);
-                stack.clear(); // root resets the stack
-            }
-            std::path::Component::CurDir => {}
-            std::path::Component::ParentDir => {
-                if let Some(last) = stack.pop() {
-                    if matches!(last, std::path::Component::Normal(_)) {
-                        // dropped
-                    } else {
-                        stack.push(last);
-                        if !is_absolute {
-                            stack.push(comp);
-                        }
-                    }
-                } else if !is_absolute {
-                    stack.push(comp);
-                }
-            }
-            std::path::Component::Normal(_) => stack.push(comp),
-        }
-    }
-
-    for comp in stack {
-        result.push(comp);
-    }
-
-    result
-}
-
 #[async_trait]
 impl StoreDestination for BlockingSftpImpl {
     type Error = anyhow::Error;
@@ -380,6 +453,10 @@ impl StoreDestination for BlockingSftpImpl {
         self.init().map_err(Into::into)
     }
 
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.health_check().map_err(Into::into)
+    }
+
     async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
         self.ls(path).map_err(Into::into)
     }
@@ -396,6 +473,10 @@ impl StoreDestination for BlockingSftpImpl {
         self.put_from_memory(from, to).map_err(Into::into)
     }
 
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.replace_from_memory(from, to).map_err(Into::into)
+    }
+
     async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
         self.get_to_memory(from).map_err(Into::into)
     }
@@ -412,67 +493,336 @@ impl StoreDestination for BlockingSftpImpl {
         self.file_exists(path).map_err(Into::into)
     }
 
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        self.file_size(path).map_err(Into::into)
+    }
+
     fn path_descriptor(&self) -> &Arc<PathDescriptor> {
         &self.path_descriptor
     }
 }
 
+/// Runs `call` in the span, and records how long it took in the span's `duration_ms`, which is also returned
+fn timed<T, E>(span: &tracing::Span, call: impl FnOnce() -> Result<T, E>) -> Result<u64, E> {
+    let _enter = span.enter();
+    let start = Instant::now();
+    call()?;
+    let duration_ms = elapsed_ms(start);
+    span.record("duration_ms", duration_ms);
+    Ok(duration_ms)
+}
+
+fn elapsed_ms(start: Instant) -> u64 {
+    u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ssh2::FileStat;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        io::Write,
+        sync::Mutex,
+    };
+
+    #[derive(Default)]
+    struct FakeState {
+        dirs: BTreeSet<PathBuf>,
+        files: BTreeMap<PathBuf, Vec<u8>>,
+        mkdir_calls: Vec<PathBuf>,
+        modes: BTreeMap<PathBuf, u32>,
+    }
+
+    /// An in-memory sftp server. Paths are simplified the way a server would, and relative paths are in the home dir.
+    #[derive(Clone, Default)]
+    struct FakeTransport {
+        state: Arc<Mutex<FakeState>>,
+    }
+
+    impl FakeTransport {
+        fn with_dirs(dirs: &[&str]) -> Self {
+            let transport = Self::default();
+            transport
+                .state
+                .lock()
+                .unwrap()
+                .dirs
+                .extend(dirs.iter().map(|d| simplify_virtual_path(Path::new(d))));
+            transport
+        }
+
+        fn mkdir_calls(&self) -> Vec<PathBuf> {
+            self.state.lock().unwrap().mkdir_calls.clone()
+        }
+
+        fn is_dir(state: &FakeState, path: &Path) -> bool {
+            // The home and root dirs always exist
+            path.as_os_str().is_empty() || path == Path::new("/") || state.dirs.contains(path)
+        }
+    }
+
+    fn no_such_file() -> ssh2::Error {
+        ssh2::Error::new(
+            ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_NO_SUCH_FILE),
+            "No such file",
+        )
+    }
+
+    struct FakeFile {
+        state: Arc<Mutex<FakeState>>,
+        path: PathBuf,
+    }
+
+    impl Write for FakeFile {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut state = self.state.lock().unwrap();
+            state
+                .files
+                .entry(self.path.clone())
+                .or_default()
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SftpTransport for FakeTransport {
+        fn readdir(&self, path: &Path) -> Result<Vec<PathBuf>, ssh2::Error> {
+            let state = self.state.lock().unwrap();
+            let dir = simplify_virtual_path(path);
+            if !Self::is_dir(&state, &dir) {
+                return Err(no_such_file());
+            }
+            let entries = state
+                .dirs
+                .iter()
+                .chain(state.files.keys())
+                .filter(|p| p.parent() == Some(dir.as_path()))
+                // Like libssh2, the entries are joined to the path as given
+                .map(|p| path.join(p.file_name().unwrap()))
+                .collect();
+            Ok(entries)
+        }
+
+        fn opendir(&self, path: &Path) -> Result<(), ssh2::Error> {
+            self.readdir(path).map(|_| ())
+        }
+
+        fn stat(&self, path: &Path) -> Result<FileStat, ssh2::Error> {
+            let state = self.state.lock().unwrap();
+            let path = simplify_virtual_path(path);
+            let mode = |default| state.modes.get(&path).copied().unwrap_or(default);
+            let (size, perm) = if Self::is_dir(&state, &path) {
+                (None, 0o040_000 | mode(0o700))
+            } else {
+                let data = state.files.get(&path).ok_or_else(no_such_file)?;
+                (Some(data.len() as u64), 0o100_000 | mode(0o600))
+            };
+            Ok(FileStat {
+                size,
+                uid: None,
+                gid: None,
+                perm: Some(perm),
+                atime: None,
+                mtime: None,
+            })
+        }
+
+        fn mkdir(&self, path: &Path, mode: i32) -> Result<(), ssh2::Error> {
+            let mut state = self.state.lock().unwrap();
+            let path = simplify_virtual_path(path);
+            let parent = path.parent().unwrap_or(Path::new(""));
+            if !Self::is_dir(&state, parent) {
+                return Err(no_such_file());
+            }
+            state.mkdir_calls.push(path.clone());
+            state
+                .modes
+                .insert(path.clone(), u32::try_from(mode).unwrap());
+            state.dirs.insert(path);
+            Ok(())
+        }
+
+        fn unlink(&self, path: &Path) -> Result<(), ssh2::Error> {
+            let mut state = self.state.lock().unwrap();
+            state
+                .files
+                .remove(&simplify_virtual_path(path))
+                .map(|_| ())
+                .ok_or_else(no_such_file)
+        }
+
+        // Like OpenSSH's server, an existing file isn't replaced
+        fn rename(&self, from: &Path, to: &Path) -> Result<(), ssh2::Error> {
+            let mut state = self.state.lock().unwrap();
+            let (from, to) = (simplify_virtual_path(from), simplify_virtual_path(to));
+            if state.files.contains_key(&to) {
+                return Err(ssh2::Error::new(
+                    ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_FAILURE),
+                    "Failure",
+                ));
+            }
+            let data = state.files.remove(&from).ok_or_else(no_such_file)?;
+            state.files.insert(to.clone(), data);
+            if let Some(mode) = state.modes.remove(&from) {
+                state.modes.insert(to, mode);
+            }
+            Ok(())
+        }
+
+        fn create(&self, path: &Path, mode: i32) -> Result<Box<dyn Write>, ssh2::Error> {
+            let path = simplify_virtual_path(path);
+            let mut state = self.state.lock().unwrap();
+            if !Self::is_dir(&state, path.parent().unwrap_or(Path::new(""))) {
+                return Err(no_such_file());
+            }
+            state.files.insert(path.clone(), Vec::new());
+            state
+                .modes
+                .entry(path.clone())
+                .or_insert(u32::try_from(mode).unwrap());
+            Ok(Box::new(FakeFile {
+                state: self.state.clone(),
+                path,
+            }))
+        }
+
+        fn open(&self, path: &Path) -> Result<Box<dyn Read>, ssh2::Error> {
+            let state = self.state.lock().unwrap();
+            let data = state
+                .files
+                .get(&simplify_virtual_path(path))
+                .ok_or_else(no_such_file)?;
+            Ok(Box::new(std::io::Cursor::new(data.clone())))
+        }
+
+        fn keepalive_send(&self) -> Result<u32, ssh2::Error> {
+            Ok(0)
+        }
+    }
+
+    fn make_sftp(transport: &FakeTransport, base_remote_path: &str) -> BlockingSftpImpl {
+        make_sftp_with_options(transport, base_remote_path, &SftpSessionOptions::default())
+    }
+
+    fn make_sftp_with_options(
+        transport: &FakeTransport,
+        base_remote_path: &str,
+        session_options: &SftpSessionOptions,
+    ) -> BlockingSftpImpl {
+        BlockingSftpImpl::with_transport(
+            Arc::new(PathDescriptor::local("/fake")),
+            Box::new(transport.clone()),
+            base_remote_path,
+            session_options,
+        )
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[rstest::rstest]
+    #[case("/config/base", "/config/base")]
+    #[case("./base/", "base")]
+    #[case("/config/./other/../base/", "/config/base")]
+    fn mkdir_p_creates_missing_parents(#[case] base_remote_path: &str, #[case] base: &str) {
+        let transport = FakeTransport::with_dirs(&["/config", base, &format!("{base}/a")]);
+        let sftp = make_sftp(&transport, base_remote_path);
+
+        sftp.mkdir_p(Path::new("a/b/c")).unwrap();
+        assert_eq!(
+            transport.mkdir_calls(),
+            paths(&[&format!("{base}/a/b"), &format!("{base}/a/b/c")])
+        );
+        assert!(sftp.dir_exists("a/b/c").unwrap());
+
+        // Nothing is left to create
+        sftp.mkdir_p(Path::new("a/b/c")).unwrap();
+        sftp.mkdir_p(Path::new("a/b")).unwrap();
+        assert_eq!(transport.mkdir_calls().len(), 2);
+    }
+
+    #[rstest::rstest]
+    #[case("x/y/z", &["x", "x/y", "x/y/z"])]
+    #[case("/srv/./x//y/", &["/srv", "/srv/x", "/srv/x/y"])]
+    fn init_creates_missing_base_path(#[case] base_remote_path: &str, #[case] expected: &[&str]) {
+        let transport = FakeTransport::default();
+        let sftp = make_sftp(&transport, base_remote_path);
+
+        sftp.init().unwrap();
+        assert_eq!(transport.mkdir_calls(), paths(expected));
+        sftp.health_check().unwrap();
+    }
+
+    #[rstest::rstest]
+    #[case("/config/test-dir")]
+    #[case("./test-dir/")]
+    #[case("/config/./test-dir/abc/..")]
+    fn ls_strips_base_path(#[case] base_remote_path: &str) {
+        let transport = FakeTransport::with_dirs(&["/config"]);
+        let sftp = make_sftp(&transport, base_remote_path);
+        sftp.init().unwrap();
+
+        sftp.mkdir_p(Path::new("sub")).unwrap();
+        sftp.put_from_memory(b"Hello", "file1").unwrap();
+        sftp.put_from_memory(b"world!", "sub/file2").unwrap();
+
+        let mut listed = sftp.ls(Path::new(".")).unwrap();
+        listed.sort();
+        assert_eq!(listed, paths(&["file1", "sub"]));
+        assert_eq!(sftp.ls(Path::new("sub")).unwrap(), paths(&["sub/file2"]));
+
+        assert_eq!(sftp.get_to_memory("sub/file2").unwrap(), b"world!");
+        assert_eq!(sftp.file_size("file1").unwrap(), 5);
+    }
+
+    #[rstest::rstest]
+    #[case(None, None, 0o600, 0o700)]
+    #[case(Some(0o640), Some(0o750), 0o640, 0o750)]
+    #[case(Some(0o644), None, 0o644, 0o700)]
+    fn configured_modes_are_applied(
+        #[case] file_mode: Option<u16>,
+        #[case] dir_mode: Option<u16>,
+        #[case] expected_file_mode: u32,
+        #[case] expected_dir_mode: u32,
+    ) {
+        let transport = FakeTransport::with_dirs(&["/config"]);
+        let session_options = SftpSessionOptions {
+            file_mode,
+            dir_mode,
+            ..Default::default()
+        };
+        let sftp = make_sftp_with_options(&transport, "/config/base", &session_options);
+        sftp.init().unwrap();
+
+        sftp.mkdir_p(Path::new("a/b")).unwrap();
+        sftp.put_from_memory(b"Hello", "a/b/file1").unwrap();
+
+        let perm = |path: &str| transport.stat(Path::new(path)).unwrap().perm.unwrap() & 0o7777;
+        for dir in ["/config/base", "/config/base/a", "/config/base/a/b"] {
+            assert_eq!(perm(dir), expected_dir_mode);
+        }
+        assert_eq!(perm("/config/base/a/b/file1"), expected_file_mode);
+    }
 
     #[test]
-    fn test_simplify_virtual_path() {
-        use std::path::{Path, PathBuf};
-
-        let s = |p| simplify_virtual_path(Path::new(p));
-
-        // Basic . and ..
-        assert_eq!(s("a/./.."), PathBuf::from(""));
-        assert_eq!(s("a/./b/../c"), PathBuf::from("a/c"));
-        assert_eq!(s("a/./b"), PathBuf::from("a/b"));
-        assert_eq!(s("./a/b"), PathBuf::from("a/b"));
-        assert_eq!(s("a/b/."), PathBuf::from("a/b"));
-        assert_eq!(s("."), PathBuf::from(""));
-        assert_eq!(s("./."), PathBuf::from(""));
-        assert_eq!(s("a/././b"), PathBuf::from("a/b"));
-        assert_eq!(s("a//b"), PathBuf::from("a/b"));
-        assert_eq!(s("a///b"), PathBuf::from("a/b"));
-        assert_eq!(s("a/./b/./c"), PathBuf::from("a/b/c"));
-        assert_eq!(s("a/./b/."), PathBuf::from("a/b"));
-        assert_eq!(s("a/./b/./"), PathBuf::from("a/b"));
-        assert_eq!(s(""), PathBuf::from(""));
-
-        // Absolute paths
-        assert_eq!(s("/a/./b"), PathBuf::from("/a/b"));
-        assert_eq!(s("/./a/b"), PathBuf::from("/a/b"));
-        assert_eq!(s("/a/b/."), PathBuf::from("/a/b"));
-        assert_eq!(s("/./"), PathBuf::from("/"));
-        assert_eq!(s("/"), PathBuf::from("/"));
-
-        // Parent resolution
-        assert_eq!(s("a/.."), PathBuf::from(""));
-        assert_eq!(s("a/b/.."), PathBuf::from("a"));
-        assert_eq!(s("a/b/../.."), PathBuf::from(""));
-        assert_eq!(s("a/b/../../.."), PathBuf::from(".."));
-        assert_eq!(s("a/./b/../c"), PathBuf::from("a/c"));
-        assert_eq!(s("./a/../b/."), PathBuf::from("b"));
-        assert_eq!(s("/a/b/../c"), PathBuf::from("/a/c"));
-        assert_eq!(s("/a/../../b"), PathBuf::from("/b"));
-
-        // Relative paths with leading ..
-        assert_eq!(s("../../a/b"), PathBuf::from("../../a/b"));
-        assert_eq!(s("../../../a"), PathBuf::from("../../../a"));
-        assert_eq!(s("../a"), PathBuf::from("../a"));
-        assert_eq!(s("../.."), PathBuf::from("../.."));
-        assert_eq!(s(".."), PathBuf::from(".."));
-        assert_eq!(s("./../a"), PathBuf::from("../a"));
-
-        // Absolute paths trying to go above root
-        assert_eq!(s("/.."), PathBuf::from("/"));
-        assert_eq!(s("/../.."), PathBuf::from("/"));
-
-        // Redundant parent dirs
-        assert_eq!(s("a/b/../../c"), PathBuf::from("c"));
+    fn replace_overwrites_existing_file() {
+        let transport = FakeTransport::with_dirs(&["/config"]);
+        let sftp = make_sftp(&transport, "/config/base");
+        sftp.init().unwrap();
+
+        sftp.replace_from_memory(b"first", "latest.jpg").unwrap();
+        assert_eq!(sftp.get_to_memory("latest.jpg").unwrap(), b"first");
+
+        sftp.replace_from_memory(b"second", "latest.jpg").unwrap();
+        assert_eq!(sftp.get_to_memory("latest.jpg").unwrap(), b"second");
+
+        // No temporary file is left behind
+        assert_eq!(sftp.ls(Path::new(".")).unwrap(), paths(&["latest.jpg"]));
     }
 }
@@ -1,18 +1,89 @@
 mod blocking;
+mod pool;
+mod socket;
+mod transport;
+mod tunnel;
 
 use crate::{
-    path_descriptor::{IdentitySource, PathDescriptor},
-    traits::StoreDestination,
+    path_descriptor::{IdentitySource, JumpHost, PathDescriptor},
+    store_error::StoreError,
+    traits::{DeletionResults, StoreDestination},
 };
 use blocking::BlockingSftpImpl;
+use pool::{PooledSession, SessionPool};
+use ssh2::ErrorCode;
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, LazyLock},
 };
 
+/// At most this many sessions are kept open to each sftp destination. Concurrent uploads beyond that share them.
+const MAX_SESSIONS_PER_DESTINATION: usize = 4;
+/// Sessions that no store has used for this long are closed
+const IDLE_SESSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+type SftpSession = tokio::sync::Mutex<BlockingSftpImpl>;
+
+static SESSION_POOL: LazyLock<SessionPool<SftpSession>> =
+    LazyLock::new(|| SessionPool::new(MAX_SESSIONS_PER_DESTINATION, IDLE_SESSION_TIMEOUT));
+
+/// Settings of the ssh session that aren't needed to reach the destination
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SftpSessionOptions {
+    pub keepalive_interval: Option<std::time::Duration>,
+    pub timeout: Option<std::time::Duration>,
+    /// Applied by wrapping the store, see [`crate::TimeoutStore`]
+    pub operation_timeout: Option<std::time::Duration>,
+    /// The permissions of created files and directories, or the defaults if not set
+    pub file_mode: Option<u16>,
+    pub dir_mode: Option<u16>,
+    /// The local address connections are made from, or any if not set
+    pub bind_address: Option<std::net::IpAddr>,
+}
+
+/// A store on an sftp server. Stores of the same destination share established sessions from a pool,
+/// so that only the first of them pays for the handshake. A store takes its session on first use.
 pub struct AsyncSftpImpl {
-    sftp: Arc<tokio::sync::Mutex<blocking::BlockingSftpImpl>>,
+    sftp: tokio::sync::OnceCell<Arc<PooledSession<SftpSession>>>,
+    connector: Arc<SftpConnector>,
+    path_descriptor: Arc<PathDescriptor>,
+}
+
+/// What's needed to connect a new session to the destination
+struct SftpConnector {
     path_descriptor: Arc<PathDescriptor>,
+    host: String,
+    username: String,
+    priv_key: IdentitySource,
+    base_remote_path: PathBuf,
+    jump_host: Option<JumpHost>,
+    session_options: SftpSessionOptions,
+}
+
+impl SftpConnector {
+    /// Blocks while waiting for a session that's being connected, or while connecting one
+    fn get_or_connect(&self) -> Result<Arc<PooledSession<SftpSession>>, SftpError> {
+        SESSION_POOL.get_or_connect(&self.path_descriptor, || {
+            tracing::trace!("Connecting a new sftp session to: {}", self.path_descriptor);
+            let sftp = BlockingSftpImpl::new_with_public_key(
+                self.path_descriptor.clone(),
+                &self.host,
+                &self.username,
+                self.priv_key.clone(),
+                self.base_remote_path.clone(),
+                self.jump_host.clone(),
+                self.session_options,
+            )?;
+            let sftp = Arc::new(PooledSession::new(tokio::sync::Mutex::new(sftp)));
+
+            if let Some(interval) = self.session_options.keepalive_interval {
+                spawn_keepalive(&sftp, interval);
+            }
+            spawn_idle_eviction(&sftp);
+
+            Ok(sftp)
+        })
+    }
 }
 
 impl AsyncSftpImpl {
@@ -22,21 +93,154 @@ impl AsyncSftpImpl {
         username: &str,
         priv_key: IdentitySource,
         base_remote_path: impl Into<PathBuf>,
-    ) -> Result<Self, SftpError> {
-        let sftp = BlockingSftpImpl::new_with_public_key(
-            path_descriptor.clone(),
-            host,
-            username,
+        jump_host: Option<JumpHost>,
+        session_options: SftpSessionOptions,
+    ) -> Self {
+        let connector = SftpConnector {
+            path_descriptor: path_descriptor.clone(),
+            host: host.to_string(),
+            username: username.to_string(),
             priv_key,
-            base_remote_path,
-        )?;
+            base_remote_path: base_remote_path.into(),
+            jump_host,
+            session_options,
+        };
 
-        let result = Self {
-            sftp: Arc::new(tokio::sync::Mutex::new(sftp)),
+        Self {
+            sftp: tokio::sync::OnceCell::new(),
+            connector: Arc::new(connector),
             path_descriptor,
-        };
+        }
+    }
 
-        Ok(result)
+    /// The session is taken from the pool in a blocking thread, since taking it can block
+    async fn session(&self) -> anyhow::Result<&Arc<PooledSession<SftpSession>>> {
+        self.sftp
+            .get_or_try_init(|| async {
+                let connector = self.connector.clone();
+                spawn_blocking_in_current_span(move || connector.get_or_connect())
+                    .await?
+                    .map_err(|e| e.into_store_error(&self.path_descriptor))
+            })
+            .await
+    }
+}
+
+/// Runs `call` on a blocking thread, in the caller's span and with its subscriber,
+/// so that the call's spans are nested under the caller's
+fn spawn_blocking_in_current_span<T, Func>(call: Func) -> tokio::task::JoinHandle<T>
+where
+    T: Send + 'static,
+    Func: FnOnce() -> T + Send + 'static,
+{
+    let span = tracing::Span::current();
+    let dispatch = tracing::dispatcher::get_default(Clone::clone);
+
+    tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        let _dispatch = tracing::dispatcher::set_default(&dispatch);
+        call()
+    })
+}
+
+/// While the session exists, the pool is periodically asked to close the sessions that have been idle for too long,
+/// since nothing else would if no more stores are made
+fn spawn_idle_eviction(session: &Arc<PooledSession<SftpSession>>) {
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let session = Arc::downgrade(session);
+    runtime.spawn(async move {
+        loop {
+            tokio::time::sleep(IDLE_SESSION_TIMEOUT).await;
+            if session.strong_count() == 0 {
+                return;
+            }
+            SESSION_POOL.evict_idle();
+        }
+    });
+}
+
+/// libssh2 only sends keepalive messages when asked to, so while the session exists,
+/// it's asked periodically. The task ends when the session is dropped, or once it's found dead.
+fn spawn_keepalive(session: &Arc<PooledSession<SftpSession>>, interval: std::time::Duration) {
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        tracing::warn!("No async runtime to send sftp keepalive messages from");
+        return;
+    };
+
+    let session = Arc::downgrade(session);
+    runtime.spawn(async move {
+        let mut wait = interval;
+        loop {
+            tokio::time::sleep(wait).await;
+
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+
+            // Like every other call on the session, it blocks, so it's kept off the async runtime
+            let sent = tokio::task::spawn_blocking({
+                let session = session.clone();
+                move || session.session().blocking_lock().keepalive_send()
+            })
+            .await;
+
+            match sent {
+                Ok(Ok(next)) => wait = next.max(std::time::Duration::from_secs(1)),
+                Ok(Err(e)) => {
+                    tracing::warn!("Sftp keepalive failed. Keepalive stopped. Error: {e}");
+                    session.mark_broken();
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!("Sftp keepalive panicked. Keepalive stopped. Error: {e}");
+                    return;
+                }
+            }
+        }
+    });
+}
+
+impl AsyncSftpImpl {
+    /// Runs the call on a blocking thread, so that the async runtime isn't blocked by it,
+    /// and so that it can be abandoned (e.g., on a timeout) while it's stuck
+    async fn run_blocking<T, Func>(&self, call: Func) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        Func: FnOnce(&BlockingSftpImpl) -> Result<T, SftpError> + Send + 'static,
+    {
+        let session = self.session().await?.clone();
+
+        // If the call is abandoned, it may still be stuck holding the session
+        let abandon_guard = AbandonGuard(&session);
+
+        let result = spawn_blocking_in_current_span({
+            let session = session.clone();
+            move || {
+                let result = call(&session.session().blocking_lock());
+                session.touch();
+                if result.as_ref().is_err_and(SftpError::breaks_session) {
+                    // Stores made after this one get a new session
+                    session.mark_broken();
+                }
+                result
+            }
+        })
+        .await;
+
+        std::mem::forget(abandon_guard);
+        result?.map_err(|e| e.into_store_error(&self.path_descriptor))
+    }
+}
+
+/// Marks the session as broken when dropped, which only happens if the call it guards is abandoned
+struct AbandonGuard<'a>(&'a PooledSession<SftpSession>);
+
+impl Drop for AbandonGuard<'_> {
+    fn drop(&mut self) {
+        self.0.mark_broken();
     }
 }
 
@@ -46,88 +250,95 @@ impl StoreDestination for AsyncSftpImpl {
     type Error = anyhow::Error;
 
     async fn init(&self) -> Result<(), Self::Error> {
-        let session = self.sftp.clone();
-        tokio::task::spawn_blocking(async move || session.lock().await.init())
-            .await?
-            .await?;
-        Ok(())
+        self.run_blocking(BlockingSftpImpl::init).await
+    }
+
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.run_blocking(BlockingSftpImpl::health_check).await
     }
 
     async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
-        let session = self.sftp.clone();
         let path = path.to_owned();
-        let result = tokio::task::spawn_blocking(async move || session.lock().await.ls(&path))
-            .await?
-            .await?;
-        Ok(result)
+        self.run_blocking(move |s| s.ls(&path)).await
     }
 
     async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
-        let session = self.sftp.clone();
         let path = path.to_owned();
-        tokio::task::spawn_blocking(async move || session.lock().await.del(&path))
-            .await?
+        self.run_blocking(move |s| s.del(&path)).await
+    }
+
+    /// All the files are deleted in one blocking call on the session. An error that breaks the session
+    /// stops the batch, since the rest would fail too.
+    async fn del_files(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<DeletionResults<Self::Error>, Self::Error> {
+        let paths = paths.to_vec();
+        let results = self
+            .run_blocking(move |s| {
+                let mut results = Vec::with_capacity(paths.len());
+                for path in paths {
+                    match s.del(&path) {
+                        Err(e) if e.breaks_session() => return Err(e),
+                        result => results.push((path, result)),
+                    }
+                }
+                Ok(results)
+            })
             .await?;
-        Ok(())
+
+        Ok(results
+            .into_iter()
+            .map(|(path, result)| {
+                let result = result.map_err(|e| e.into_store_error(&self.path_descriptor));
+                (path, result)
+            })
+            .collect())
     }
 
     async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
-        let session = self.sftp.clone();
         let from = from.to_owned();
         let to = to.to_owned();
-        tokio::task::spawn_blocking(async move || session.lock().await.put(&from, &to))
-            .await?
-            .await?;
-        Ok(())
+        self.run_blocking(move |s| s.put(&from, &to)).await
     }
 
     async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
-        let session = self.sftp.clone();
         let from = from.to_owned();
         let to = to.to_owned();
-        tokio::task::spawn_blocking(async move || session.lock().await.put_from_memory(&from, &to))
-            .await?
-            .await?;
-        Ok(())
+        self.run_blocking(move |s| s.put_from_memory(&from, &to))
+            .await
+    }
+
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        let from = from.to_owned();
+        let to = to.to_owned();
+        self.run_blocking(move |s| s.replace_from_memory(&from, &to))
+            .await
     }
 
     async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
-        let session = self.sftp.clone();
         let from = from.to_owned();
-        let result =
-            tokio::task::spawn_blocking(async move || session.lock().await.get_to_memory(&from))
-                .await?
-                .await?;
-        Ok(result)
+        self.run_blocking(move |s| s.get_to_memory(&from)).await
     }
 
     async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
-        let session = self.sftp.clone();
         let path = path.to_owned();
-        tokio::task::spawn_blocking(async move || session.lock().await.mkdir_p(&path))
-            .await?
-            .await?;
-        Ok(())
+        self.run_blocking(move |s| s.mkdir_p(&path)).await
     }
 
     async fn dir_exists(&self, path: &Path) -> Result<bool, Self::Error> {
-        let session = self.sftp.clone();
         let path = path.to_owned();
-        let result =
-            tokio::task::spawn_blocking(async move || session.lock().await.dir_exists(&path))
-                .await?
-                .await?;
-        Ok(result)
+        self.run_blocking(move |s| s.dir_exists(&path)).await
     }
 
     async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error> {
-        let session = self.sftp.clone();
         let path = path.to_owned();
-        let result =
-            tokio::task::spawn_blocking(async move || session.lock().await.file_exists(&path))
-                .await?
-                .await?;
-        Ok(result)
+        self.run_blocking(move |s| s.file_exists(&path)).await
+    }
+
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        let path = path.to_owned();
+        self.run_blocking(move |s| s.file_size(&path)).await
     }
 
     fn path_descriptor(&self) -> &Arc<PathDescriptor> {
@@ -139,6 +350,8 @@ impl StoreDestination for AsyncSftpImpl {
 pub enum SftpError {
     #[error("Initialization failed: {0}")]
     SessionInitError(ssh2::Error),
+    #[error("Connecting to `{0}` failed: {1}")]
+    ConnectFailed(String, std::io::Error),
     #[error("Handshake failed: {0}")]
     HandshakeFailed(ssh2::Error),
     #[error("Public key isn't readable in path. Error: {0}")]
@@ -153,6 +366,8 @@ pub enum SftpError {
     LsFailed(ssh2::Error),
     #[error("Del file failed: {0}")]
     DelFileFailed(ssh2::Error),
+    #[error("Rename failed: {0}")]
+    RenameFailed(ssh2::Error),
     #[error("Mkdir failed: {0}")]
     MkdirFailed(ssh2::Error),
     #[error("Open file to write failed: {0}")]
@@ -173,4 +388,67 @@ pub enum SftpError {
     ReadBufferError(std::io::Error),
     #[error("Read remote file error: {0}")]
     ReadRemoteFileError(std::io::Error),
+    #[error("Stat of path failed: {0}")]
+    StatFailed(ssh2::Error),
+    #[error("Sending keepalive failed: {0}")]
+    KeepaliveFailed(ssh2::Error),
+    #[error("The server didn't report the size of file: {0}")]
+    FileSizeUnknown(PathBuf),
+    #[error("Jump host `{0}` failed: {1}")]
+    JumpHostFailed(String, Box<SftpError>),
+    #[error("Opening a tunnel through the jump host to `{0}` failed: {1}")]
+    TunnelOpenFailed(String, ssh2::Error),
+    #[error("Setting up the local end of the jump host tunnel failed: {0}")]
+    TunnelSocketFailed(std::io::Error),
+}
+
+impl SftpError {
+    /// Whether the session may be unusable after this error, as opposed to errors of a single sftp operation
+    fn breaks_session(&self) -> bool {
+        match self {
+            SftpError::SessionInitError(e)
+            | SftpError::HandshakeFailed(e)
+            | SftpError::PubKeyAuthError(e)
+            | SftpError::SftpChannelOpenFailed(e)
+            | SftpError::LsFailed(e)
+            | SftpError::DelFileFailed(e)
+            | SftpError::RenameFailed(e)
+            | SftpError::MkdirFailed(e)
+            | SftpError::OpenDestinationFileToWriteFailed(e)
+            | SftpError::OpenDestinationFileToReadFailed(e)
+            | SftpError::DirExistsCheckError(e)
+            | SftpError::StatFailed(e)
+            | SftpError::KeepaliveFailed(e) => matches!(e.code(), ErrorCode::Session(_)),
+            SftpError::FileCopyForPutFailed(_)
+            | SftpError::ReadRemoteFileError(_)
+            | SftpError::ConnectFailed(_, _)
+            | SftpError::JumpHostFailed(_, _)
+            | SftpError::TunnelOpenFailed(_, _)
+            | SftpError::TunnelSocketFailed(_) => true,
+            SftpError::PrivKeyNotFoundInPath(_)
+            | SftpError::PrivKeyReadError(_)
+            | SftpError::SourceFileNotFound(_)
+            | SftpError::DestPathNotFound(_)
+            | SftpError::FileSizeUnknown(_)
+            | SftpError::SourceFileOpenFailed(_, _)
+            | SftpError::ReadBufferError(_) => false,
+        }
+    }
+
+    /// A file that can't be created because its directory doesn't exist is reported as
+    /// [`StoreError::DirectoryNotFound`], so that callers can make the directory again
+    fn into_store_error(self, destination: &PathDescriptor) -> anyhow::Error {
+        match &self {
+            SftpError::OpenDestinationFileToWriteFailed(e)
+                if e.code() == ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_NO_SUCH_FILE) =>
+            {
+                StoreError::DirectoryNotFound {
+                    destination: destination.to_string(),
+                    reason: self.to_string(),
+                }
+                .into()
+            }
+            _ => self.into(),
+        }
+    }
 }
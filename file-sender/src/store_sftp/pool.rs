@@ -0,0 +1,370 @@
+use crate::path_descriptor::PathDescriptor;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// A session kept in a [`SessionPool`], which can be marked as broken by its users so that it's not handed out again
+pub struct PooledSession<T> {
+    session: T,
+    broken: AtomicBool,
+    /// When its last use ended, which is how long it's been idle for once nothing uses it
+    last_used: Mutex<Instant>,
+}
+
+impl<T> PooledSession<T> {
+    pub fn new(session: T) -> Self {
+        Self {
+            session,
+            broken: AtomicBool::new(false),
+            last_used: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn session(&self) -> &T {
+        &self.session
+    }
+
+    pub fn mark_broken(&self) {
+        self.broken.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks the end of a use of the session, from which its idle time is counted
+    pub fn touch(&self) {
+        *self
+            .last_used
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Instant::now();
+    }
+
+    fn is_broken(&self) -> bool {
+        self.broken.load(Ordering::Relaxed)
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .elapsed()
+    }
+}
+
+/// The sessions to a destination, and the number of them being connected
+struct DestinationSessions<T> {
+    sessions: Vec<Arc<PooledSession<T>>>,
+    connecting: usize,
+}
+
+impl<T> Default for DestinationSessions<T> {
+    fn default() -> Self {
+        Self {
+            sessions: Vec::new(),
+            connecting: 0,
+        }
+    }
+}
+
+impl<T> DestinationSessions<T> {
+    /// Drops the broken sessions, and the ones nothing has used for `idle_timeout`
+    fn evict(&mut self, idle_timeout: Duration) {
+        self.sessions.retain(|s| {
+            // The pool's own reference is the only one to an idle session
+            !s.is_broken() && (Arc::strong_count(s) > 1 || s.idle_for() < idle_timeout)
+        });
+    }
+
+    fn least_shared(&self) -> Option<Arc<PooledSession<T>>> {
+        self.sessions
+            .iter()
+            .min_by_key(|s| Arc::strong_count(s))
+            .cloned()
+    }
+}
+
+/// Established sessions to every destination, so that stores made for the same destination reuse them
+/// instead of connecting again. At most `max_sessions_per_destination` are kept per destination; once they're
+/// all in use, the least shared one is handed out again. Broken sessions, and sessions that have been idle for
+/// `idle_timeout`, are dropped, and replaced when needed.
+///
+/// A slot is reserved before connecting, so concurrent callers never exceed the maximum, and callers that
+/// come while a session is being connected wait for it instead of connecting their own.
+pub struct SessionPool<T> {
+    max_sessions_per_destination: usize,
+    idle_timeout: Duration,
+    sessions: Mutex<HashMap<PathDescriptor, DestinationSessions<T>>>,
+    connected: Condvar,
+}
+
+/// Releases a reserved slot once its connection attempt is over, even if it panicked, and wakes the waiters
+struct Reservation<'a, T> {
+    pool: &'a SessionPool<T>,
+    path_descriptor: &'a PathDescriptor,
+    session: Option<Arc<PooledSession<T>>>,
+}
+
+impl<T> Drop for Reservation<'_, T> {
+    fn drop(&mut self) {
+        // A panic while the lock was held elsewhere must not leave the waiters stuck
+        let mut sessions = self
+            .pool
+            .sessions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let destination = sessions.entry(self.path_descriptor.clone()).or_default();
+        destination.connecting -= 1;
+        destination.sessions.extend(self.session.take());
+        drop(sessions);
+        self.pool.connected.notify_all();
+    }
+}
+
+impl<T> SessionPool<T> {
+    pub fn new(max_sessions_per_destination: usize, idle_timeout: Duration) -> Self {
+        Self {
+            max_sessions_per_destination: max_sessions_per_destination.max(1),
+            idle_timeout,
+            sessions: Mutex::new(HashMap::new()),
+            connected: Condvar::new(),
+        }
+    }
+
+    /// Drops the sessions of every destination that have been idle for too long, so that their connections
+    /// don't stay open while nothing needs them
+    pub fn evict_idle(&self) {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for destination in sessions.values_mut() {
+            destination.evict(self.idle_timeout);
+        }
+        sessions.retain(|_, d| !d.sessions.is_empty() || d.connecting > 0);
+    }
+
+    /// Returns an idle session to the destination, or a new one made with `connect`.
+    /// It blocks while another caller connects a session to the destination, as well as while connecting,
+    /// so it must not be called from an async runtime's worker.
+    pub fn get_or_connect<E>(
+        &self,
+        path_descriptor: &PathDescriptor,
+        connect: impl FnOnce() -> Result<Arc<PooledSession<T>>, E>,
+    ) -> Result<Arc<PooledSession<T>>, E> {
+        let mut reservation = {
+            let mut sessions = self.sessions.lock().expect("Poisoned mutex");
+            let mut waited = false;
+            loop {
+                let destination = sessions.entry(path_descriptor.clone()).or_default();
+                destination.evict(self.idle_timeout);
+
+                // The pool's own reference is the only one to an idle session
+                if let Some(session) = destination
+                    .sessions
+                    .iter()
+                    .find(|s| Arc::strong_count(s) == 1)
+                {
+                    return Ok(session.clone());
+                }
+
+                // A session that was connected while waiting is shared rather than connecting another
+                if waited || destination.sessions.len() >= self.max_sessions_per_destination {
+                    if let Some(session) = destination.least_shared() {
+                        return Ok(session);
+                    }
+                }
+
+                if destination.connecting > 0 {
+                    sessions = self.connected.wait(sessions).expect("Poisoned mutex");
+                    waited = true;
+                    continue;
+                }
+
+                destination.connecting += 1;
+                break Reservation {
+                    pool: self,
+                    path_descriptor,
+                    session: None,
+                };
+            }
+        };
+
+        // Connecting can take long, so other destinations aren't held up meanwhile
+        let session = connect()?;
+        reservation.session = Some(session.clone());
+
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+    fn destination(name: &str) -> PathDescriptor {
+        PathDescriptor::local(name)
+    }
+
+    /// Counts the connections made, numbering the sessions by the order they were made in
+    fn counting_connect(
+        connections: &AtomicUsize,
+    ) -> impl FnOnce() -> Result<Arc<PooledSession<usize>>, ()> + '_ {
+        || {
+            Ok(Arc::new(PooledSession::new(
+                connections.fetch_add(1, Ordering::Relaxed),
+            )))
+        }
+    }
+
+    #[test]
+    fn sequential_uses_share_a_single_connection() {
+        let pool = SessionPool::new(4, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        for _ in 0..10 {
+            let session = pool
+                .get_or_connect(&destination("a"), counting_connect(&connections))
+                .unwrap();
+            assert_eq!(*session.session(), 0);
+        }
+
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn concurrent_uses_are_bounded() {
+        let pool = SessionPool::new(2, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        let sessions = (0..5)
+            .map(|_| {
+                pool.get_or_connect(&destination("a"), counting_connect(&connections))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(connections.load(Ordering::Relaxed), 2);
+        let mut ids = sessions.iter().map(|s| *s.session()).collect::<Vec<_>>();
+        ids.sort_unstable();
+        // The sessions are shared evenly once the pool is full
+        assert_eq!(ids, [0, 0, 0, 1, 1]);
+
+        // Each destination has its own sessions
+        pool.get_or_connect(&destination("b"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(connections.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn concurrent_callers_share_a_single_handshake() {
+        let pool = SessionPool::new(4, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        let ids = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let session = pool
+                            .get_or_connect(&destination("a"), || {
+                                // A slow handshake, during which the other callers arrive
+                                std::thread::sleep(std::time::Duration::from_millis(200));
+                                counting_connect(&connections)()
+                            })
+                            .unwrap();
+                        // Kept in use until every caller got one
+                        std::thread::sleep(std::time::Duration::from_millis(300));
+                        *session.session()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+        assert!(ids.iter().all(|id| *id == 0));
+    }
+
+    #[test]
+    fn panicking_connection_releases_its_slot() {
+        let pool = SessionPool::<usize>::new(1, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.get_or_connect::<()>(&destination("a"), || panic!("Connection panicked"))
+        }));
+        assert!(result.is_err());
+
+        pool.get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn broken_sessions_are_replaced() {
+        let pool = SessionPool::new(1, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        let session = pool
+            .get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        session.mark_broken();
+        drop(session);
+
+        let session = pool
+            .get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(*session.session(), 1);
+        assert_eq!(connections.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn failed_connection_is_not_pooled() {
+        let pool = SessionPool::<usize>::new(1, IDLE_TIMEOUT);
+        let connections = AtomicUsize::new(0);
+
+        assert!(pool.get_or_connect(&destination("a"), || Err(())).is_err());
+
+        pool.get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn idle_sessions_are_evicted() {
+        let pool = SessionPool::new(1, Duration::from_millis(100));
+        let connections = AtomicUsize::new(0);
+
+        let session = pool
+            .get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        // Sessions in use are never idle
+        pool.evict_idle();
+        session.touch();
+        drop(session);
+
+        let session = pool
+            .get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(*session.session(), 0);
+        drop(session);
+
+        std::thread::sleep(Duration::from_millis(200));
+        pool.evict_idle();
+        assert!(pool.sessions.lock().unwrap().is_empty());
+
+        let session = pool
+            .get_or_connect(&destination("a"), counting_connect(&connections))
+            .unwrap();
+        assert_eq!(*session.session(), 1);
+        assert_eq!(connections.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,81 @@
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+};
+
+/// Connects to `address` (`host:port`), from `bind_address` if given. Like `TcpStream::connect`,
+/// every address the host resolves to is tried in order, skipping those of another IP version than `bind_address`.
+pub fn connect(address: &str, bind_address: Option<IpAddr>) -> std::io::Result<TcpStream> {
+    let Some(bind_address) = bind_address else {
+        return TcpStream::connect(address);
+    };
+
+    let mut last_error = None;
+    for target in address.to_socket_addrs()? {
+        if target.is_ipv4() != bind_address.is_ipv4() {
+            continue;
+        }
+        match connect_from(target, bind_address) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("`{address}` has no address of the same IP version as `{bind_address}`"),
+        )
+    }))
+}
+
+fn connect_from(target: SocketAddr, bind_address: IpAddr) -> std::io::Result<TcpStream> {
+    let socket = Socket::new(
+        Domain::for_address(target),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.bind(&SockAddr::from(SocketAddr::new(bind_address, 0)))?;
+    socket.connect(&SockAddr::from(target))?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+
+    #[test]
+    fn connection_is_made_from_bind_address() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let stream = connect(&address, Some(IpAddr::V4(Ipv4Addr::LOCALHOST))).unwrap();
+        let (accepted, peer) = listener.accept().unwrap();
+        assert_eq!(peer.ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(stream.local_addr().unwrap(), peer);
+        drop(accepted);
+
+        // Without one, it's up to the OS
+        connect(&address, None).unwrap();
+    }
+
+    #[test]
+    fn bind_address_of_another_ip_version_fails() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let error = connect(&address, Some(IpAddr::V6(Ipv6Addr::LOCALHOST))).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unavailable_bind_address_fails() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // A documentation address, which no interface has
+        assert!(connect(&address, Some("192.0.2.1".parse().unwrap())).is_err());
+    }
+}
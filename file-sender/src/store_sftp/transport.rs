@@ -0,0 +1,94 @@
+use ssh2::{FileStat, OpenFlags, RenameFlags, Session, Sftp};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The sftp operations the store is built on, on paths that are already resolved.
+/// Everything else (path resolution, `mkdir_p` and copying data) is done over them,
+/// so that it can be tested without an ssh server.
+pub trait SftpTransport: Send + Sync {
+    /// The entries in the directory, each joined to `path`
+    fn readdir(&self, path: &Path) -> Result<Vec<PathBuf>, ssh2::Error>;
+
+    fn opendir(&self, path: &Path) -> Result<(), ssh2::Error>;
+
+    fn stat(&self, path: &Path) -> Result<FileStat, ssh2::Error>;
+
+    fn mkdir(&self, path: &Path, mode: i32) -> Result<(), ssh2::Error>;
+
+    fn unlink(&self, path: &Path) -> Result<(), ssh2::Error>;
+
+    /// Renames the file, replacing `to` if the server supports it
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), ssh2::Error>;
+
+    /// Opens the file for writing, creating it with the given permissions if it doesn't exist
+    fn create(&self, path: &Path, mode: i32) -> Result<Box<dyn Write>, ssh2::Error>;
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read>, ssh2::Error>;
+
+    /// Sends a keepalive message if the session has been idle for the keepalive interval.
+    /// Returns the number of seconds until it should be called again.
+    fn keepalive_send(&self) -> Result<u32, ssh2::Error>;
+}
+
+/// The transport of an established ssh session
+pub struct Ssh2Transport {
+    session: Session,
+    sftp: Sftp,
+}
+
+impl Ssh2Transport {
+    pub fn new(session: Session, sftp: Sftp) -> Self {
+        Self { session, sftp }
+    }
+}
+
+impl SftpTransport for Ssh2Transport {
+    fn readdir(&self, path: &Path) -> Result<Vec<PathBuf>, ssh2::Error> {
+        let contents = self.sftp.readdir(path)?;
+        Ok(contents.into_iter().map(|v| v.0).collect())
+    }
+
+    fn opendir(&self, path: &Path) -> Result<(), ssh2::Error> {
+        self.sftp.opendir(path).map(|_| ())
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat, ssh2::Error> {
+        self.sftp.stat(path)
+    }
+
+    fn mkdir(&self, path: &Path, mode: i32) -> Result<(), ssh2::Error> {
+        self.sftp.mkdir(path, mode)
+    }
+
+    fn unlink(&self, path: &Path) -> Result<(), ssh2::Error> {
+        self.sftp.unlink(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), ssh2::Error> {
+        self.sftp.rename(
+            from,
+            to,
+            Some(RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE),
+        )
+    }
+
+    fn create(&self, path: &Path, mode: i32) -> Result<Box<dyn Write>, ssh2::Error> {
+        let file = self.sftp.open_mode(
+            path,
+            OpenFlags::WRITE | OpenFlags::CREATE,
+            mode,
+            ssh2::OpenType::File,
+        )?;
+        Ok(Box::new(file))
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read>, ssh2::Error> {
+        Ok(Box::new(self.sftp.open(path)?))
+    }
+
+    fn keepalive_send(&self) -> Result<u32, ssh2::Error> {
+        self.session.keepalive_send()
+    }
+}
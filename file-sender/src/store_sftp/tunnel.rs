@@ -0,0 +1,263 @@
+use crate::path_descriptor::JumpHost;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use ssh2::{BlockDirections, Channel, ErrorCode, Session};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    os::{fd::AsFd, unix::net::UnixStream},
+    time::Duration,
+};
+
+use super::{SftpError, SftpSessionOptions, socket::connect};
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+/// The session to the jump host is idle whenever the tunnel is, so it's kept alive even if keepalives
+/// aren't configured, or a firewall on the way can drop it while the target session is pooled
+const DEFAULT_JUMP_HOST_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait before retrying a keepalive that couldn't be sent yet
+const KEEPALIVE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connects to `target` (`host[:port]`) through the jump host, like ssh's `ProxyJump`.
+///
+/// A session to the jump host opens a direct-tcpip channel to the target. libssh2 can only run
+/// a session over a socket, so the channel is exposed through a socket pair, and a thread forwards
+/// the bytes between them. Unlike a local listener, a socket pair can't be connected to by another process.
+/// The returned socket is the end for the target session. The thread, with the session to the jump host,
+/// lives until either end is closed.
+pub fn connect_through_jump_host(
+    jump_host: JumpHost,
+    target: &str,
+    session_options: SftpSessionOptions,
+) -> Result<UnixStream, SftpError> {
+    let jump_host_name = jump_host.host.clone();
+    let (session, channel, session_socket) = open_channel(jump_host, target, session_options)
+        .map_err(|e| SftpError::JumpHostFailed(jump_host_name, Box::new(e)))?;
+
+    let (target_end, channel_end) = UnixStream::pair().map_err(SftpError::TunnelSocketFailed)?;
+    channel_end
+        .set_nonblocking(true)
+        .map_err(SftpError::TunnelSocketFailed)?;
+
+    let keepalive_interval = session_options
+        .keepalive_interval
+        .unwrap_or(DEFAULT_JUMP_HOST_KEEPALIVE_INTERVAL);
+    // The server doesn't need to reply. A dead connection shows as a failure to send.
+    session.set_keepalive(
+        false,
+        u32::try_from(keepalive_interval.as_secs())
+            .unwrap_or(u32::MAX)
+            .max(1),
+    );
+
+    let target = target.to_string();
+    std::thread::Builder::new()
+        .name("sftp-jump-tunnel".to_string())
+        .spawn(move || {
+            // From here on, the channel is polled along with the socket
+            session.set_blocking(false);
+            match forward(&session, channel, &session_socket, channel_end) {
+                Ok(()) => tracing::trace!("Jump host tunnel to `{target}` closed"),
+                Err(e) => tracing::warn!("Jump host tunnel to `{target}` failed: {e}"),
+            }
+            // The session to the jump host is only dropped once the tunnel is closed
+            drop(session);
+        })
+        .map_err(SftpError::TunnelSocketFailed)?;
+
+    Ok(target_end)
+}
+
+/// Returns the session to the jump host with the channel to the target, and a handle of the session's socket
+/// to wait on
+fn open_channel(
+    jump_host: JumpHost,
+    target: &str,
+    session_options: SftpSessionOptions,
+) -> Result<(Session, Channel, TcpStream), SftpError> {
+    let mut session = Session::new().map_err(SftpError::SessionInitError)?;
+
+    if let Some(timeout) = session_options.timeout {
+        session.set_timeout(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX));
+    }
+
+    let (jump_address, jump_port) = split_host_port(&jump_host.host);
+    let tcp = connect(
+        &format!("{jump_address}:{jump_port}"),
+        session_options.bind_address,
+    )
+    .map_err(|e| SftpError::ConnectFailed(jump_host.host.clone(), e))?;
+    let session_socket = tcp
+        .try_clone()
+        .map_err(|e| SftpError::ConnectFailed(jump_host.host.clone(), e))?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(SftpError::HandshakeFailed)?;
+
+    let priv_key = jump_host.identity.into_key()?;
+
+    session
+        .userauth_pubkey_memory(&jump_host.username, None, &priv_key, None)
+        .map_err(SftpError::PubKeyAuthError)?;
+
+    let (host, port) = split_host_port(target);
+
+    let channel = session
+        .channel_direct_tcpip(host, port, None)
+        .map_err(|e| SftpError::TunnelOpenFailed(target.to_string(), e))?;
+
+    Ok((session, channel, session_socket))
+}
+
+/// Forwards the bytes in both directions until either end is closed.
+/// Both ends are non-blocking, so whatever can't be written yet is kept until it can be.
+/// When neither end can make progress, the thread sleeps until one of the sockets is ready,
+/// or the next keepalive of the session is due.
+fn forward(
+    session: &Session,
+    mut channel: Channel,
+    session_socket: &TcpStream,
+    mut socket: UnixStream,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; 32 * 1024];
+    let mut to_channel = Vec::new();
+    let mut to_socket = Vec::new();
+
+    loop {
+        let mut progressed = false;
+
+        if to_channel.is_empty() {
+            match read_available(&mut socket, &mut buffer)? {
+                Some(0) => return Ok(()),
+                Some(n) => {
+                    to_channel.extend_from_slice(&buffer[..n]);
+                    progressed = true;
+                }
+                None => (),
+            }
+        }
+        progressed |= write_available(&mut channel, &mut to_channel)?;
+
+        if to_socket.is_empty() {
+            match read_available(&mut channel, &mut buffer)? {
+                Some(0) if channel.eof() => return Ok(()),
+                Some(n) => {
+                    to_socket.extend_from_slice(&buffer[..n]);
+                    progressed |= n > 0;
+                }
+                None => (),
+            }
+        }
+        progressed |= write_available(&mut socket, &mut to_socket)?;
+
+        let until_keepalive = match session.keepalive_send() {
+            Ok(seconds) => Duration::from_secs(seconds.into()),
+            Err(e) if e.code() == ErrorCode::Session(libssh2_sys::LIBSSH2_ERROR_EAGAIN) => {
+                KEEPALIVE_RETRY_INTERVAL
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if !progressed {
+            wait_until_ready(
+                session,
+                session_socket,
+                &socket,
+                to_channel.is_empty(),
+                !to_socket.is_empty(),
+                until_keepalive,
+            )?;
+        }
+    }
+}
+
+/// Waits for the session's socket to be ready in the directions libssh2 is blocked on, and for the local socket
+/// to be ready for what's to be forwarded to or from it, or for the timeout to pass
+fn wait_until_ready(
+    session: &Session,
+    session_socket: &TcpStream,
+    socket: &UnixStream,
+    read_socket: bool,
+    write_socket: bool,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    // Incoming data of the channel is always waited for
+    let mut session_events = PollFlags::POLLIN;
+    if matches!(
+        session.block_directions(),
+        BlockDirections::Outbound | BlockDirections::Both
+    ) {
+        session_events |= PollFlags::POLLOUT;
+    }
+
+    let mut socket_events = PollFlags::empty();
+    if read_socket {
+        socket_events |= PollFlags::POLLIN;
+    }
+    if write_socket {
+        socket_events |= PollFlags::POLLOUT;
+    }
+
+    let mut fds = [
+        PollFd::new(session_socket.as_fd(), session_events),
+        PollFd::new(socket.as_fd(), socket_events),
+    ];
+    match poll(
+        &mut fds,
+        PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX),
+    ) {
+        Ok(_) | Err(nix::errno::Errno::EINTR) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns `None` if nothing can be read yet
+fn read_available(from: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<Option<usize>> {
+    match from.read(buffer) {
+        Ok(n) => Ok(Some(n)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes as much of the pending data as possible, and returns whether any was written
+fn write_available(to: &mut impl Write, pending: &mut Vec<u8>) -> std::io::Result<bool> {
+    if pending.is_empty() {
+        return Ok(false);
+    }
+
+    match to.write(pending) {
+        Ok(n) => {
+            pending.drain(..n);
+            Ok(n > 0)
+        }
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Splits `host[:port]` into the host and the port, which is the ssh port by default
+fn split_host_port(address: &str) -> (&str, u16) {
+    match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().unwrap_or(DEFAULT_SSH_PORT)),
+        None => (address, DEFAULT_SSH_PORT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_defaults_to_ssh_port() {
+        assert_eq!(
+            split_host_port("bastion.example.com"),
+            ("bastion.example.com", DEFAULT_SSH_PORT)
+        );
+        assert_eq!(
+            split_host_port("bastion.example.com:2222"),
+            ("bastion.example.com", 2222)
+        );
+        assert_eq!(split_host_port("[::1]:2222"), ("[::1]", 2222));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    path_descriptor::PathDescriptor,
+    traits::{ByteStream, DeletionResults, StoreDestination},
+};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum StoreTimeoutError {
+    #[error("Store operation `{operation}` did not finish within {} seconds", timeout.as_secs_f64())]
+    OperationTimedOut {
+        operation: &'static str,
+        timeout: std::time::Duration,
+    },
+}
+
+/// Wraps a store so that every operation fails if it doesn't finish within a timeout, instead of hanging
+/// when the network stalls. A stalled session is abandoned, so a new store has to be made to reconnect.
+/// Sftp stores retire an abandoned session from their pool, so the new store doesn't get it again.
+pub struct TimeoutStore {
+    inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    timeout: std::time::Duration,
+}
+
+impl TimeoutStore {
+    pub fn new(
+        inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self { inner, timeout }
+    }
+
+    async fn run<T>(
+        &self,
+        operation: &'static str,
+        op: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        if let Ok(result) = tokio::time::timeout(self.timeout, op).await {
+            result
+        } else {
+            tracing::warn!(
+                "Store operation `{operation}` on `{}` timed out",
+                self.inner.path_descriptor()
+            );
+            Err(StoreTimeoutError::OperationTimedOut {
+                operation,
+                timeout: self.timeout,
+            }
+            .into())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StoreDestination for TimeoutStore {
+    type Error = anyhow::Error;
+
+    async fn init(&self) -> Result<(), Self::Error> {
+        self.run("init", self.inner.init()).await
+    }
+
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.run("health_check", self.inner.health_check()).await
+    }
+
+    async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+        self.run("ls", self.inner.ls(path)).await
+    }
+
+    async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
+        self.run("del_file", self.inner.del_file(path)).await
+    }
+
+    async fn del_files(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<DeletionResults<Self::Error>, Self::Error> {
+        self.run("del_files", self.inner.del_files(paths)).await
+    }
+
+    async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
+        self.run("mkdir_p", self.inner.mkdir_p(path)).await
+    }
+
+    async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
+        self.run("put", self.inner.put(from, to)).await
+    }
+
+    async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.run("put_from_memory", self.inner.put_from_memory(from, to))
+            .await
+    }
+
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.run(
+            "replace_from_memory",
+            self.inner.replace_from_memory(from, to),
+        )
+        .await
+    }
+
+    async fn put_from_stream(&self, from: ByteStream, to: &Path) -> Result<(), Self::Error> {
+        self.run("put_from_stream", self.inner.put_from_stream(from, to))
+            .await
+    }
+
+    async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
+        self.run("get_to_memory", self.inner.get_to_memory(from))
+            .await
+    }
+
+    async fn dir_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.run("dir_exists", self.inner.dir_exists(path)).await
+    }
+
+    async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.run("file_exists", self.inner.file_exists(path)).await
+    }
+
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        self.run("file_size", self.inner.file_size(path)).await
+    }
+
+    fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+        self.inner.path_descriptor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store whose uploads stall for longer than any timeout used in the tests
+    struct StalledStore {
+        path_descriptor: Arc<PathDescriptor>,
+    }
+
+    #[async_trait::async_trait]
+    impl StoreDestination for StalledStore {
+        type Error = anyhow::Error;
+
+        async fn init(&self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn ls(&self, _path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn del_file(&self, _path: &Path) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn mkdir_p(&self, _path: &Path) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn put(&self, _from: &Path, _to: &Path) -> Result<(), Self::Error> {
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            Ok(())
+        }
+
+        async fn put_from_memory(&self, _from: &[u8], _to: &Path) -> Result<(), Self::Error> {
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            Ok(())
+        }
+
+        async fn get_to_memory(&self, _from: &Path) -> Result<Vec<u8>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn dir_exists(&self, _path: &Path) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+
+        async fn file_exists(&self, _path: &Path) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+
+        fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+            &self.path_descriptor
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_operation_times_out() {
+        let timeout = std::time::Duration::from_secs(10);
+        let store = TimeoutStore::new(
+            Arc::new(StalledStore {
+                path_descriptor: Arc::new(PathDescriptor::local("/home/data/")),
+            }),
+            timeout,
+        );
+
+        // Operations that finish in time are unaffected
+        store.mkdir_p(Path::new("dir")).await.unwrap();
+        assert!(!store.file_exists(Path::new("dir/file")).await.unwrap());
+
+        let err = store
+            .put_from_memory(b"Hello world!", Path::new("dir/file"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreTimeoutError>(),
+            Some(&StoreTimeoutError::OperationTimedOut {
+                operation: "put_from_memory",
+                timeout,
+            })
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    path_descriptor::PathDescriptor,
+    traits::{ByteStream, DeletionResults, StoreDestination},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum UploadVerificationError {
+    #[error("File `{}` was uploaded, but isn't listed in the destination", path.display())]
+    NotListed { path: PathBuf },
+}
+
+/// Wraps a store so that every upload is checked to be listed in the destination after it finishes,
+/// and fails if it isn't. Some servers report a write as successful, yet the file never shows up,
+/// e.g., when the disk behind them fails silently. Failing lets the upload be retried.
+pub struct ListingVerifiedStore {
+    inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+}
+
+impl ListingVerifiedStore {
+    pub fn new(inner: Arc<dyn StoreDestination<Error = anyhow::Error>>) -> Self {
+        Self { inner }
+    }
+
+    async fn verify_listed(&self, path: &Path) -> anyhow::Result<()> {
+        if self.inner.file_exists(path).await? {
+            Ok(())
+        } else {
+            tracing::warn!(
+                "Uploaded file `{}` isn't listed in `{}`",
+                path.display(),
+                self.inner.path_descriptor()
+            );
+            Err(UploadVerificationError::NotListed {
+                path: path.to_path_buf(),
+            }
+            .into())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StoreDestination for ListingVerifiedStore {
+    type Error = anyhow::Error;
+
+    async fn init(&self) -> Result<(), Self::Error> {
+        self.inner.init().await
+    }
+
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.inner.health_check().await
+    }
+
+    async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error> {
+        self.inner.ls(path).await
+    }
+
+    async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
+        self.inner.del_file(path).await
+    }
+
+    async fn del_files(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<DeletionResults<Self::Error>, Self::Error> {
+        self.inner.del_files(paths).await
+    }
+
+    async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
+        self.inner.mkdir_p(path).await
+    }
+
+    async fn put(&self, from: &Path, to: &Path) -> Result<(), Self::Error> {
+        self.inner.put(from, to).await?;
+        self.verify_listed(to).await
+    }
+
+    async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.inner.put_from_memory(from, to).await?;
+        self.verify_listed(to).await
+    }
+
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.inner.replace_from_memory(from, to).await?;
+        self.verify_listed(to).await
+    }
+
+    async fn put_from_stream(&self, from: ByteStream, to: &Path) -> Result<(), Self::Error> {
+        self.inner.put_from_stream(from, to).await?;
+        self.verify_listed(to).await
+    }
+
+    async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
+        self.inner.get_to_memory(from).await
+    }
+
+    async fn dir_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.inner.dir_exists(path).await
+    }
+
+    async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error> {
+        self.inner.file_exists(path).await
+    }
+
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        self.inner.file_size(path).await
+    }
+
+    fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+        self.inner.path_descriptor()
+    }
+}
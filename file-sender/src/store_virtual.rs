@@ -1,23 +1,114 @@
-use crate::{path_descriptor::PathDescriptor, traits::StoreDestination};
+use crate::{path_descriptor::PathDescriptor, store_error::StoreError, traits::StoreDestination};
 use anyhow::Context;
 use async_trait::async_trait;
 use std::{
+    collections::VecDeque,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
+/// What to do when a write doesn't fit in the capacity of an in-memory store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnCapacityExceeded {
+    /// Delete the oldest written files until the new file fits
+    EvictOldest,
+    /// Fail the write
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InMemoryCapacity {
+    pub max_bytes: u64,
+    pub on_exceeded: OnCapacityExceeded,
+}
+
 pub struct InMemoryFileSystem {
     root: vfs::VfsPath,
     path_descriptor: Arc<PathDescriptor>,
+    capacity: Option<InMemoryCapacity>,
+    /// The files in the store with their sizes, oldest written first
+    files: Mutex<VecDeque<(String, u64)>>,
 }
 
 impl InMemoryFileSystem {
-    pub fn new(path_descriptor: Arc<PathDescriptor>) -> Self {
+    pub fn new(path_descriptor: Arc<PathDescriptor>, capacity: Option<InMemoryCapacity>) -> Self {
         let fs = vfs::MemoryFS::default();
         Self {
             root: vfs::VfsPath::new(fs),
             path_descriptor,
+            capacity,
+            files: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Checks that a file of the given size can be written at the given path, if a capacity is set, and returns
+    /// the oldest files to evict once it's written. Nothing is changed, so a write that fails loses nothing.
+    fn plan_room(
+        &self,
+        files: &VecDeque<(String, u64)>,
+        path: &vfs::VfsPath,
+        size: u64,
+    ) -> anyhow::Result<Vec<String>> {
+        let Some(capacity) = self.capacity else {
+            return Ok(Vec::new());
+        };
+
+        if size > capacity.max_bytes {
+            return Err(anyhow::anyhow!(
+                "File `{}` of size {size} bytes is larger than the capacity of the in-memory store: {} bytes",
+                path.as_str(),
+                capacity.max_bytes
+            ));
+        }
+
+        // The file being overwritten doesn't count towards the used capacity
+        let others = files.iter().filter(|(p, _)| p != path.as_str());
+        let mut used = others.clone().map(|(_, s)| s).sum::<u64>();
+
+        match capacity.on_exceeded {
+            OnCapacityExceeded::Reject => {
+                if used + size > capacity.max_bytes {
+                    return Err(StoreError::OutOfSpace {
+                        destination: "in-memory store".to_string(),
+                        reason: format!(
+                            "In-memory store is full. Writing file `{}` of size {size} bytes would exceed its capacity: {used} of {} bytes used",
+                            path.as_str(),
+                            capacity.max_bytes
+                        ),
+                    }
+                    .into());
+                }
+                Ok(Vec::new())
+            }
+            OnCapacityExceeded::EvictOldest => {
+                let mut evicted = Vec::new();
+                for (oldest, oldest_size) in others {
+                    if used + size <= capacity.max_bytes {
+                        break;
+                    }
+                    used -= oldest_size;
+                    evicted.push(oldest.clone());
+                }
+                Ok(evicted)
+            }
+        }
+    }
+
+    /// Evicts the given files, after the file that needed the room is written
+    fn evict(
+        &self,
+        files: &mut VecDeque<(String, u64)>,
+        evicted: Vec<String>,
+    ) -> anyhow::Result<()> {
+        for oldest in evicted {
+            tracing::debug!("Evicting file from in-memory store to make room: `{oldest}`");
+            self.root
+                .join(&oldest)
+                .and_then(|p| p.remove_file())
+                .context("Evicting oldest file")?;
+            files.retain(|(p, _)| *p != oldest);
         }
+        Ok(())
     }
 }
 
@@ -48,7 +139,10 @@ impl StoreDestination for InMemoryFileSystem {
     async fn del_file(&self, path: &Path) -> Result<(), Self::Error> {
         let path = path_as_str(path);
         let path = self.root.join(path).context("path join failed")?;
-        path.remove_file().context("del file")
+        let mut files = self.files.lock().expect("Poisoned mutex");
+        path.remove_file().context("del file")?;
+        files.retain(|(p, _)| p != path.as_str());
+        Ok(())
     }
 
     async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error> {
@@ -67,11 +161,20 @@ impl StoreDestination for InMemoryFileSystem {
     async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
         let to = path_as_str(to);
         let to = self.root.join(to).context("path join failed")?;
+        let size = from.len() as u64;
+
+        // The lock is held until the file is written, so that the accounting stays consistent
+        let mut files = self.files.lock().expect("Poisoned mutex");
+        let evicted = self.plan_room(&files, &to, size)?;
 
         to.create_file()
             .context("create_file")?
             .write_all(from)
-            .context("write_all")
+            .context("write_all")?;
+        files.retain(|(p, _)| p != to.as_str());
+        files.push_back((to.as_str().to_string(), size));
+
+        self.evict(&mut files, evicted)
     }
 
     async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error> {
@@ -101,6 +204,13 @@ impl StoreDestination for InMemoryFileSystem {
         path.is_file().context("is_file")
     }
 
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        let path = path_as_str(path);
+        let path = self.root.join(path).context("path join failed")?;
+
+        Ok(path.metadata().context("metadata")?.len)
+    }
+
     fn path_descriptor(&self) -> &Arc<PathDescriptor> {
         &self.path_descriptor
     }
@@ -108,7 +218,143 @@ impl StoreDestination for InMemoryFileSystem {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    fn make_store(max_bytes: u64, on_exceeded: OnCapacityExceeded) -> InMemoryFileSystem {
+        InMemoryFileSystem::new(
+            Arc::new(PathDescriptor::local(String::new())),
+            Some(InMemoryCapacity {
+                max_bytes,
+                on_exceeded,
+            }),
+        )
+    }
 
     #[test]
     fn basic() {}
+
+    #[tokio::test]
+    async fn evict_oldest_when_full() {
+        let store = make_store(10, OnCapacityExceeded::EvictOldest);
+
+        store
+            .put_from_memory(&[1; 4], Path::new("a"))
+            .await
+            .unwrap();
+        store
+            .put_from_memory(&[2; 4], Path::new("b"))
+            .await
+            .unwrap();
+        // Overwriting makes "a" the newest, and doesn't count it twice
+        store
+            .put_from_memory(&[1; 4], Path::new("a"))
+            .await
+            .unwrap();
+        assert!(store.file_exists(Path::new("a")).await.unwrap());
+        assert!(store.file_exists(Path::new("b")).await.unwrap());
+
+        // "b" is now the oldest, and removing it is enough to fit "c"
+        store
+            .put_from_memory(&[3; 5], Path::new("c"))
+            .await
+            .unwrap();
+        assert!(store.file_exists(Path::new("a")).await.unwrap());
+        assert!(!store.file_exists(Path::new("b")).await.unwrap());
+        assert!(store.file_exists(Path::new("c")).await.unwrap());
+
+        // Needs evicting both files
+        store
+            .put_from_memory(&[4; 9], Path::new("d"))
+            .await
+            .unwrap();
+        assert!(!store.file_exists(Path::new("a")).await.unwrap());
+        assert!(!store.file_exists(Path::new("c")).await.unwrap());
+        assert_eq!(
+            store.get_to_memory(Path::new("d")).await.unwrap(),
+            vec![4; 9]
+        );
+
+        // Larger than the whole capacity, whether it's a new file or overwrites one
+        store
+            .put_from_memory(&[5; 11], Path::new("e"))
+            .await
+            .unwrap_err();
+        store
+            .put_from_memory(&[5; 11], Path::new("d"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            store.get_to_memory(Path::new("d")).await.unwrap(),
+            vec![4; 9]
+        );
+
+        // "d" still counts after the failed overwrite, so it's evicted to fit "e"
+        store
+            .put_from_memory(&[5; 2], Path::new("e"))
+            .await
+            .unwrap();
+        assert!(!store.file_exists(Path::new("d")).await.unwrap());
+        assert!(store.file_exists(Path::new("e")).await.unwrap());
+
+        // Nothing is evicted for a write that fails
+        store
+            .put_from_memory(&[6; 10], Path::new("missing/f"))
+            .await
+            .unwrap_err();
+        assert!(store.file_exists(Path::new("e")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reject_writes_when_full() {
+        let store = make_store(10, OnCapacityExceeded::Reject);
+
+        store
+            .put_from_memory(&[1; 4], Path::new("a"))
+            .await
+            .unwrap();
+        store
+            .put_from_memory(&[2; 6], Path::new("b"))
+            .await
+            .unwrap();
+
+        let err = store
+            .put_from_memory(&[3; 1], Path::new("c"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("full"), "Unexpected error: {err}");
+        assert!(StoreError::is_out_of_space(&err));
+        assert!(!store.file_exists(Path::new("c")).await.unwrap());
+        assert!(store.file_exists(Path::new("a")).await.unwrap());
+        assert!(store.file_exists(Path::new("b")).await.unwrap());
+
+        // Overwriting a file with one of the same size fits, but a larger one doesn't, and the old one stays counted
+        store
+            .put_from_memory(&[3; 6], Path::new("b"))
+            .await
+            .unwrap();
+        let err = store
+            .put_from_memory(&[3; 7], Path::new("b"))
+            .await
+            .unwrap_err();
+        assert!(StoreError::is_out_of_space(&err));
+        assert_eq!(
+            store.get_to_memory(Path::new("b")).await.unwrap(),
+            vec![3; 6]
+        );
+        store
+            .put_from_memory(&[3; 1], Path::new("c"))
+            .await
+            .unwrap_err();
+
+        // Deleting frees space
+        store.del_file(Path::new("a")).await.unwrap();
+        store
+            .put_from_memory(&[3; 4], Path::new("c"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_to_memory(Path::new("c")).await.unwrap(),
+            vec![3; 4]
+        );
+    }
 }
@@ -1,23 +1,29 @@
 use crate::{
-    make_inmemory_filesystem, make_store, path_descriptor::PathDescriptor, traits::StoreDestination,
+    make_inmemory_filesystem, make_store,
+    path_descriptor::{PathDescriptor, Secret},
+    traits::StoreDestination,
 };
 use logging::init_logging;
 use rstest::rstest;
 use russh::keys::ssh_encoding::EncodePem;
 use std::{
+    collections::BTreeMap,
     fmt::{Debug, Display},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 use test_utils::random::{
     Rng, Seed, gen_random_bytes, gen_random_string, make_seedable_rng, random_seed,
 };
+use tracing_subscriber::{Layer, Registry, layer::SubscriberExt, registry::LookupSpan};
 use utils::podman::Podman;
 
-async fn test_store<E: Display + Debug, S: StoreDestination<Error = E> + ?Sized>(
+async fn test_store<E: Display + Debug + Send, S: StoreDestination<Error = E> + ?Sized>(
     fs: &S,
     rng: &mut impl Rng,
 ) {
+    fs.health_check().await.unwrap();
+
     assert!(fs.ls(Path::new(".")).await.unwrap().is_empty());
 
     // Test that random files and directory names don't exist
@@ -38,6 +44,7 @@ async fn test_store<E: Display + Debug, S: StoreDestination<Error = E> + ?Sized>
         assert_eq!(bytes_read, bytes);
 
         assert!(fs.file_exists(&file_name).await.unwrap());
+        assert_eq!(fs.file_size(&file_name).await.unwrap(), bytes.len() as u64);
         assert_eq!(fs.ls(Path::new(".")).await.unwrap(), [file_name.clone()]);
         fs.del_file(&file_name).await.unwrap();
         assert!(!fs.file_exists(&file_name).await.unwrap());
@@ -69,6 +76,39 @@ async fn test_store<E: Display + Debug, S: StoreDestination<Error = E> + ?Sized>
         assert_eq!(fs.ls(Path::new(".")).await.unwrap(), Vec::<PathBuf>::new());
     }
 
+    // Test deleting files in a batch, where a missing file fails alone
+    {
+        let file_names = (0..3)
+            .map(|_| PathBuf::from(gen_random_string(rng, 10..20)))
+            .collect::<Vec<_>>();
+        for file_name in &file_names {
+            fs.put_from_memory(b"Hello world!", file_name)
+                .await
+                .unwrap();
+        }
+
+        let missing: PathBuf = gen_random_string(rng, 21..30).into();
+        let to_delete = [
+            file_names[0].clone(),
+            missing.clone(),
+            file_names[1].clone(),
+        ];
+        let results = fs.del_files(&to_delete).await.unwrap();
+        assert_eq!(
+            results.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            to_delete.iter().collect::<Vec<_>>()
+        );
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_ok());
+        assert_eq!(
+            fs.ls(Path::new(".")).await.unwrap(),
+            [file_names[2].clone()]
+        );
+
+        fs.del_file(&file_names[2]).await.unwrap();
+    }
+
     // Test creating a deep dir and that it exists
     {
         let deep_dir = (0..10)
@@ -107,13 +147,31 @@ async fn local_filesystem(random_seed: Seed) {
 
     let temp_dir = tempfile::TempDir::new().unwrap();
 
-    let fs = make_store(&Arc::new(PathDescriptor::Local(temp_dir.path().to_owned()))).unwrap();
+    let fs = make_store(&Arc::new(PathDescriptor::local(temp_dir.path().to_owned()))).unwrap();
     fs.init().await.unwrap();
     test_store(fs.as_ref(), &mut rng).await;
 
     println!("End of test for local filesystem reached.");
 }
 
+#[tokio::test]
+async fn local_filesystem_health_check_failures() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+
+    // The directory wasn't created, as there was no init
+    let missing_dir = temp_dir.path().join("missing");
+    let fs = make_store(&Arc::new(PathDescriptor::local(missing_dir))).unwrap();
+    assert!(fs.health_check().await.is_err());
+    fs.init().await.unwrap();
+    fs.health_check().await.unwrap();
+
+    // A file where the directory should be
+    let file_path = temp_dir.path().join("some-file");
+    std::fs::write(&file_path, b"Hello world!").unwrap();
+    let fs = make_store(&Arc::new(PathDescriptor::local(file_path))).unwrap();
+    assert!(fs.health_check().await.is_err());
+}
+
 #[tokio::test]
 #[rstest]
 #[trace]
@@ -141,6 +199,418 @@ async fn sftp_filesystem(
 
     let username = "some_user";
 
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (_podman, ssh_port, priv_key_openssh_format_str) = start_sftp_server(username).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        remote_address: format!("127.0.0.1:{ssh_port}"),
+        remote_path: base_remote_path,
+        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        keepalive_interval: None,
+        timeout: None,
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: None,
+        file_mode: None,
+        dir_mode: None,
+        bind_address: None,
+        verify_listing_after_upload: false,
+    }))
+    .unwrap();
+
+    fs.init().await.unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+
+    test_store(fs.as_ref(), &mut rng).await;
+
+    println!("End of test for sftp filesystem reached.");
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn sftp_filesystem_with_keepalive(random_seed: Seed) {
+    init_logging();
+
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping sftp containerized tests");
+        return;
+    }
+
+    let username = "some_user";
+
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (_podman, ssh_port, priv_key_openssh_format_str) = start_sftp_server(username).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        remote_address: format!("127.0.0.1:{ssh_port}"),
+        remote_path: "test-dir".to_string(),
+        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        keepalive_interval: Some(std::time::Duration::from_secs(1)),
+        timeout: Some(std::time::Duration::from_secs(30)),
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: None,
+        file_mode: None,
+        dir_mode: None,
+        bind_address: None,
+        verify_listing_after_upload: false,
+    }))
+    .unwrap();
+
+    fs.init().await.unwrap();
+
+    // Idle for a few keepalive intervals, so keepalive messages are sent, then the session must still work
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+
+    test_store(fs.as_ref(), &mut rng).await;
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn sftp_filesystem_through_jump_host(random_seed: Seed) {
+    init_logging();
+
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping sftp containerized tests");
+        return;
+    }
+
+    let username = "some_user";
+    let jump_username = "jump_user";
+
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (_target_podman, target_ssh_port, priv_key_openssh_format_str) =
+        start_sftp_server(username).await;
+
+    // The jump host only allows tcp forwarding with this mod
+    // See: https://github.com/linuxserver/docker-mods/tree/openssh-server-ssh-tunnel
+    let (_jump_podman, jump_ssh_port, jump_priv_key_openssh_format_str) = start_ssh_server(
+        jump_username,
+        &[("DOCKER_MODS", "linuxserver/mods:openssh-server-ssh-tunnel")],
+    )
+    .await;
+
+    // Installing the mod takes a while
+    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        // The address as seen from the jump host container, which reaches the target through the podman host
+        remote_address: format!("host.containers.internal:{target_ssh_port}"),
+        remote_path: "test-dir".to_string(),
+        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        keepalive_interval: None,
+        timeout: Some(std::time::Duration::from_secs(30)),
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: Some(crate::path_descriptor::JumpHost {
+            host: format!("127.0.0.1:{jump_ssh_port}"),
+            username: jump_username.to_string(),
+            identity: crate::path_descriptor::IdentitySource::InMemory(
+                jump_priv_key_openssh_format_str,
+            ),
+        }),
+        file_mode: None,
+        dir_mode: None,
+        bind_address: None,
+        verify_listing_after_upload: false,
+    }))
+    .unwrap();
+
+    fs.init().await.unwrap();
+
+    test_store(fs.as_ref(), &mut rng).await;
+}
+
+#[tokio::test]
+async fn sftp_filesystem_applies_configured_modes() {
+    init_logging();
+
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping sftp containerized tests");
+        return;
+    }
+
+    let username = "some_user";
+
+    let (_podman, ssh_port, priv_key_openssh_format_str) = start_sftp_server(username).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        remote_address: format!("127.0.0.1:{ssh_port}"),
+        remote_path: "test-dir".to_string(),
+        identity: crate::path_descriptor::IdentitySource::InMemory(
+            priv_key_openssh_format_str.clone(),
+        ),
+        keepalive_interval: None,
+        timeout: Some(std::time::Duration::from_secs(30)),
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: None,
+        file_mode: Some(0o640),
+        dir_mode: Some(0o750),
+        bind_address: None,
+        verify_listing_after_upload: false,
+    }))
+    .unwrap();
+
+    fs.init().await.unwrap();
+    fs.mkdir_p(Path::new("a/b")).await.unwrap();
+    fs.put_from_memory(b"Hello world!", Path::new("a/b/file.txt"))
+        .await
+        .unwrap();
+
+    // The permissions are checked over a separate session, as the server sees them
+    let session = {
+        let mut session = ssh2::Session::new().unwrap();
+        session.set_tcp_stream(std::net::TcpStream::connect(("127.0.0.1", ssh_port)).unwrap());
+        session.handshake().unwrap();
+        session
+            .userauth_pubkey_memory(username, None, &priv_key_openssh_format_str, None)
+            .unwrap();
+        session
+    };
+    let sftp = session.sftp().unwrap();
+    let perm = |path: &str| sftp.stat(Path::new(path)).unwrap().perm.unwrap() & 0o7777;
+
+    assert_eq!(perm("test-dir/a"), 0o750);
+    assert_eq!(perm("test-dir/a/b"), 0o750);
+    assert_eq!(perm("test-dir/a/b/file.txt"), 0o640);
+}
+
+#[derive(Debug, Clone)]
+struct RecordedSpan {
+    name: String,
+    parent: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+/// Records the name, parent and fields of every span created while it's the active subscriber,
+/// including the fields recorded after the span was created
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<BTreeMap<u64, RecordedSpan>>>,
+}
+
+impl SpanRecorder {
+    fn span(&self, name: &str) -> RecordedSpan {
+        self.spans
+            .lock()
+            .unwrap()
+            .values()
+            .find(|span| span.name == name)
+            .cloned()
+            .unwrap_or_else(|| panic!("No span named `{name}` was created"))
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.spans
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|span| span.name == name)
+            .count()
+    }
+}
+
+impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+    // Interest in a callsite is cached globally, so being interested in events would make
+    // tests running concurrently on other threads evaluate the arguments of their log lines
+    fn register_callsite(
+        &self,
+        metadata: &'static tracing::Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        if metadata.is_span() {
+            tracing::subscriber::Interest::always()
+        } else {
+            tracing::subscriber::Interest::never()
+        }
+    }
+
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        let parent = ctx
+            .span(id)
+            .and_then(|span| span.parent())
+            .map(|parent| parent.name().to_string());
+        self.spans.lock().unwrap().insert(
+            id.into_u64(),
+            RecordedSpan {
+                name: attrs.metadata().name().to_string(),
+                parent,
+                fields: fields.0,
+            },
+        );
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut fields = SpanFields::default();
+        values.record(&mut fields);
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            span.fields.extend(fields.0);
+        }
+    }
+}
+
+#[derive(Default)]
+struct SpanFields(BTreeMap<String, String>);
+
+impl tracing::field::Visit for SpanFields {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+#[tokio::test]
+async fn sftp_connection_and_transfer_are_traced() {
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping sftp containerized tests");
+        return;
+    }
+
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(recorder.clone()));
+
+    let username = "some_user";
+
+    let (_podman, ssh_port, priv_key_openssh_format_str) = start_sftp_server(username).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        remote_address: format!("127.0.0.1:{ssh_port}"),
+        remote_path: "test-dir".to_string(),
+        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        keepalive_interval: None,
+        timeout: Some(std::time::Duration::from_secs(30)),
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: None,
+        file_mode: None,
+        dir_mode: None,
+        bind_address: None,
+        verify_listing_after_upload: false,
+    }))
+    .unwrap();
+
+    fs.init().await.unwrap();
+    let data = vec![7u8; 100_000];
+    fs.put_from_memory(&data, Path::new("file.bin"))
+        .await
+        .unwrap();
+
+    let connect = recorder.span("sftp_connect");
+    assert_eq!(connect.fields["host"], format!("127.0.0.1:{ssh_port}"));
+
+    for name in ["sftp_handshake", "sftp_auth"] {
+        let span = recorder.span(name);
+        assert_eq!(span.parent.as_deref(), Some("sftp_connect"));
+        assert!(span.fields["duration_ms"].parse::<u64>().is_ok());
+    }
+    assert_eq!(recorder.span("sftp_auth").fields["username"], username);
+
+    // Recorded in the blocking thread the upload runs in
+    let transfer = recorder.span("sftp_transfer");
+    assert_eq!(transfer.fields["bytes"], data.len().to_string());
+    assert!(transfer.fields["duration_ms"].parse::<u64>().is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_sftp_uploads_share_one_handshake() {
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping sftp containerized tests");
+        return;
+    }
+
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(recorder.clone()));
+
+    let username = "some_user";
+
+    let (_podman, ssh_port, priv_key_openssh_format_str) = start_sftp_server(username).await;
+
+    let path_descriptor = Arc::new(PathDescriptor::Sftp {
+        username: username.to_string(),
+        remote_address: format!("127.0.0.1:{ssh_port}"),
+        remote_path: "test-dir".to_string(),
+        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        keepalive_interval: None,
+        timeout: Some(std::time::Duration::from_secs(30)),
+        operation_timeout: None,
+        max_concurrency: None,
+        jump_host: None,
+        file_mode: None,
+        dir_mode: None,
+        bind_address: None,
+        verify_listing_after_upload: false,
+    });
+
+    let stores = (0..4)
+        .map(|_| make_store(&path_descriptor).unwrap())
+        .collect::<Vec<_>>();
+
+    // The stores take their sessions on first use, which happens at the same time, like the uploads of concurrent reviews
+    futures::future::join_all(stores.iter().map(|fs| fs.dir_exists(Path::new(""))))
+        .await
+        .into_iter()
+        .for_each(|r| {
+            r.unwrap();
+        });
+
+    stores[0].init().await.unwrap();
+    futures::future::join_all(stores.iter().enumerate().map(|(i, fs)| async move {
+        fs.put_from_memory(&[7u8; 10_000], Path::new(&format!("file-{i}.bin")))
+            .await
+            .unwrap();
+    }))
+    .await;
+
+    for i in 0..stores.len() {
+        assert!(
+            stores[0]
+                .file_exists(Path::new(&format!("file-{i}.bin")))
+                .await
+                .unwrap()
+        );
+    }
+    assert_eq!(recorder.count("sftp_handshake"), 1);
+}
+
+/// Returns the running container, the host port of its ssh server and the private key of the user
+async fn start_sftp_server(username: &str) -> (Podman, u16, String) {
+    start_ssh_server(username, &[]).await
+}
+
+/// Like [`start_sftp_server`], with extra environment variables for the container
+async fn start_ssh_server(username: &str, extra_env: &[(&str, &str)]) -> (Podman, u16, String) {
     let priv_key = gen_ssh_private_key().unwrap();
     let public_key = priv_key.public_key().clone();
 
@@ -148,8 +618,6 @@ async fn sftp_filesystem(
         .encode_pem_string(russh::keys::ssh_key::LineEnding::LF)
         .unwrap();
 
-    let mut rng = make_seedable_rng(random_seed);
-
     // Container: https://docs.linuxserver.io/images/docker-openssh-server
     // Note: To access with password for debugging, add the following args (-e for env var)
     // `-e PASSWORD_ACCESS=true -e USER_PASSWORD=YourPassword`
@@ -163,6 +631,9 @@ async fn sftp_filesystem(
         .with_env("PGID", "1000")
         .with_env("TZ", "Etc/UTC")
         .with_env("PUBLIC_KEY", &public_key.to_openssh().unwrap());
+    for (key, value) in extra_env {
+        podman = podman.with_env(key, value);
+    }
 
     podman.run();
 
@@ -170,27 +641,85 @@ async fn sftp_filesystem(
 
     tokio::time::sleep(std::time::Duration::from_secs(2)).await;
 
-    let fs = make_store(&Arc::new(PathDescriptor::Sftp {
+    (podman, ssh_port, priv_key_openssh_format_str)
+}
+
+fn gen_ssh_private_key() -> anyhow::Result<russh::keys::PrivateKey> {
+    let key = russh::keys::PrivateKey::random(
+        &mut rand_core::OsRng,
+        russh::keys::ssh_key::Algorithm::Ed25519,
+    )?;
+    Ok(key)
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn ftp_filesystem(
+    random_seed: Seed,
+    #[values(
+        "test-dir/",
+        "test-dir",
+        "test-dir/abc",
+        "test-dir/abc/",
+        "./test-dir/",
+        "./test-dir",
+        "./test-dir/abc",
+        "./test-dir/abc/"
+    )]
+    base_remote_path: String,
+) {
+    init_logging();
+
+    // Podman is needed to make this work, so we guard it behind an env var
+    if std::env::var("SNAPSYNC_CONTAINERIZED_TESTS").is_err() {
+        eprintln!("Warning: Skipping ftp containerized tests");
+        return;
+    }
+
+    println!("Starting test for ftp filesystem...");
+
+    let username = "some_user";
+    let password = "some_password";
+
+    let mut rng = make_seedable_rng(random_seed);
+
+    // Passive mode ports are given to the client as they are in the container,
+    // so they have to be mapped to the same ports on the host
+    let passive_ports_count = 5;
+    let min_passive_port = rng.random_range(30000..60000);
+    let max_passive_port = min_passive_port + passive_ports_count - 1;
+
+    // Container: https://github.com/delfer/docker-alpine-ftp-server
+    let mut podman = Podman::new("FtpTest", "docker.io/delfer/alpine-ftp-server:latest")
+        .with_port_mapping(None, 21)
+        .with_env("USERS", &format!("{username}|{password}"))
+        .with_env("ADDRESS", "127.0.0.1")
+        .with_env("MIN_PORT", &min_passive_port.to_string())
+        .with_env("MAX_PORT", &max_passive_port.to_string());
+    for port in min_passive_port..=max_passive_port {
+        podman = podman.with_port_mapping(Some(port), port);
+    }
+
+    podman.run();
+
+    let ftp_port = podman.get_port_mapping(21).unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let fs = make_store(&Arc::new(PathDescriptor::Ftp {
+        host: format!("127.0.0.1:{ftp_port}"),
         username: username.to_string(),
-        remote_address: format!("127.0.0.1:{ssh_port}"),
+        password: Secret::new(password),
         remote_path: base_remote_path,
-        identity: crate::path_descriptor::IdentitySource::InMemory(priv_key_openssh_format_str),
+        tls: false,
+        max_concurrency: None,
     }))
     .unwrap();
 
     fs.init().await.unwrap();
 
-    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-
     test_store(fs.as_ref(), &mut rng).await;
 
-    println!("End of test for sftp filesystem reached.");
-}
-
-fn gen_ssh_private_key() -> anyhow::Result<russh::keys::PrivateKey> {
-    let key = russh::keys::PrivateKey::random(
-        &mut rand_core::OsRng,
-        russh::keys::ssh_key::Algorithm::Ed25519,
-    )?;
-    Ok(key)
+    println!("End of test for ftp filesystem reached.");
 }
@@ -4,9 +4,16 @@ use std::{
 };
 
 use async_trait::async_trait;
+use futures::{StreamExt, stream::BoxStream};
 
 use crate::path_descriptor::PathDescriptor;
 
+/// Data that arrives in chunks, e.g., while it's being downloaded
+pub type ByteStream = BoxStream<'static, anyhow::Result<bytes::Bytes>>;
+
+/// The result of deleting each of a batch of files, by its path
+pub type DeletionResults<E> = Vec<(PathBuf, Result<(), E>)>;
+
 /// A representation of store location, remote possibly, where we data can be sent.
 /// All the functions (docs) in this trait assume that we're dealing with a remote system.
 /// However, this also applies to local systems.
@@ -17,12 +24,35 @@ pub trait StoreDestination: Send + Sync {
     /// Any initialization needed for the destination.
     async fn init(&self) -> Result<(), Self::Error>;
 
+    /// Checks that the destination is reachable and usable. By default, this lists the base directory,
+    /// but destinations can replace it with something cheaper or more accurate.
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        self.ls(Path::new(".")).await.map(|_| ())
+    }
+
     /// List the available files at the given remote path
     async fn ls(&self, path: &Path) -> Result<Vec<PathBuf>, Self::Error>;
 
     /// Delete the file at the given remote path
     async fn del_file(&self, path: &Path) -> Result<(), Self::Error>;
 
+    /// Delete the files at the given remote paths, and return the result of deleting each of them.
+    /// The outer error is for failures that stop the whole batch. By default, every file is deleted
+    /// with `del_file`, so destinations with a bulk delete should replace it with that.
+    async fn del_files(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<DeletionResults<Self::Error>, Self::Error>
+    where
+        Self::Error: Send,
+    {
+        let mut results = Vec::with_capacity(paths.len());
+        for path in paths {
+            results.push((path.clone(), self.del_file(path).await));
+        }
+        Ok(results)
+    }
+
     /// Create a directory at the given remote path, recursively
     async fn mkdir_p(&self, path: &Path) -> Result<(), Self::Error>;
 
@@ -32,6 +62,27 @@ pub trait StoreDestination: Send + Sync {
     /// Copy the given raw data in `from` to the given remote path in `to`.
     async fn put_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error>;
 
+    /// Like `put_from_memory`, but replaces the file at `to` such that it's never seen partially written,
+    /// for files that are overwritten in place. By default, this is `put_from_memory`, so destinations
+    /// that can rename files should replace it with writing a temporary file and renaming it over `to`.
+    async fn replace_from_memory(&self, from: &[u8], to: &Path) -> Result<(), Self::Error> {
+        self.put_from_memory(from, to).await
+    }
+
+    /// Copy the data of the given stream to the given remote path in `to`, while it arrives.
+    /// The upload fails if the stream returns an error. By default, the whole stream is collected
+    /// in memory, then uploaded with `put_from_memory`.
+    async fn put_from_stream(&self, mut from: ByteStream, to: &Path) -> Result<(), Self::Error>
+    where
+        Self::Error: From<anyhow::Error>,
+    {
+        let mut data = Vec::new();
+        while let Some(chunk) = from.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        self.put_from_memory(&data, to).await
+    }
+
     /// Reads a given remote file `from` the given path and returns it in the result
     async fn get_to_memory(&self, from: &Path) -> Result<Vec<u8>, Self::Error>;
 
@@ -41,6 +92,12 @@ pub trait StoreDestination: Send + Sync {
     /// Returns true if the given path is a file, and exists
     async fn file_exists(&self, path: &Path) -> Result<bool, Self::Error>;
 
+    /// Returns the size in bytes of the file at the given remote path. By default, the whole file is read
+    /// to find its size, so destinations should replace it with something that asks the remote system.
+    async fn file_size(&self, path: &Path) -> Result<u64, Self::Error> {
+        self.get_to_memory(path).await.map(|data| data.len() as u64)
+    }
+
     /// Returns a local copy of the PathDescriptor object. This is done primarily to simplify some processes.
     fn path_descriptor(&self) -> &Arc<PathDescriptor>;
 }
@@ -1,9 +1,319 @@
+use crate::{FrigateApiError, dns::FrigateHostResolver, mp4_boxes::is_complete_mp4};
+
 #[must_use]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrigateApiConfig {
     pub frigate_api_base_url: String,
+    // Inserted between the base url and `/api`, for Frigate behind a path-prefixed reverse proxy, e.g.: /frigate
+    pub api_path_prefix: Option<String>,
     // e.g.: socks5://192.168.1.1:9000
     pub frigate_api_proxy: Option<String>,
-    // Uptime of Frigate to wait for, after which uploads can happen
-    pub delay_after_startup: std::time::Duration,
+    // Basic auth credentials of the proxy, instead of embedding them in its url
+    pub frigate_api_proxy_auth: Option<ProxyAuth>,
+    // Credentials for Frigate's built-in authentication, to log in with and use the session it returns
+    pub frigate_login: Option<FrigateLogin>,
+    // Uptime of Frigate to wait for, after which uploads can happen. None doesn't wait, nor checks Frigate's uptime.
+    pub delay_after_startup: Option<std::time::Duration>,
+    // Resume interrupted clip downloads with HTTP Range requests. Requires Frigate (and any proxy in between) to support ranges.
+    pub resume_downloads: bool,
+    // Number of decimals the clip timestamps are rounded to in the URL. None keeps full precision.
+    pub clip_timestamp_precision: Option<u32>,
+    // The container clips are expected in, which decides how they're validated, and their file extension
+    pub clip_container: ClipContainer,
+    // For a review that hasn't ended, its clip is requested up to this long before now, to stay within what Frigate has recorded
+    pub clip_end_safety_margin: std::time::Duration,
+    // Downloading a clip fails once it's larger than this many bytes. None doesn't limit them.
+    pub max_clip_bytes: Option<u64>,
+    // If set, connections to the API are made from this local address, e.g., to go through a specific interface
+    pub bind_address: Option<std::net::IpAddr>,
+    // Ask for responses compressed with gzip or brotli, and decompress them. Some proxies mishandle compressed responses.
+    pub compress_responses: bool,
+    // Scan the boxes of mp4 clips to check that they're complete, beyond their header. It costs a pass over the clip.
+    pub deep_clip_validation: bool,
+    // If set, the host of the base url is resolved with it on every new connection, instead of by the system
+    pub host_resolver: Option<FrigateHostResolver>,
+}
+
+impl Default for FrigateApiConfig {
+    /// Frigate at its default address, without a proxy or login, validating mp4 clips by their header
+    fn default() -> Self {
+        Self {
+            frigate_api_base_url: "http://127.0.0.1:5000".to_string(),
+            api_path_prefix: None,
+            frigate_api_proxy: None,
+            frigate_api_proxy_auth: None,
+            frigate_login: None,
+            delay_after_startup: None,
+            resume_downloads: false,
+            clip_timestamp_precision: None,
+            clip_container: ClipContainer::Mp4,
+            clip_end_safety_margin: std::time::Duration::ZERO,
+            max_clip_bytes: None,
+            bind_address: None,
+            compress_responses: true,
+            deep_clip_validation: false,
+            host_resolver: None,
+        }
+    }
+}
+
+/// The credentials of a proxy. The password is hidden in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for ProxyAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyAuth")
+            .field("username", &self.username)
+            .field("password", &"<hidden>")
+            .finish()
+    }
+}
+
+/// The credentials of a Frigate user. The password is hidden in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FrigateLogin {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for FrigateLogin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrigateLogin")
+            .field("username", &self.username)
+            .field("password", &"<hidden>")
+            .finish()
+    }
+}
+
+impl FrigateApiConfig {
+    /// Fails unless the base url is an `http` or `https` url with a host, which is easily missed when pasting it
+    pub fn check_base_url(&self) -> Result<(), FrigateApiError> {
+        let base_url = &self.frigate_api_base_url;
+        let url = reqwest::Url::parse(base_url).map_err(|e| {
+            FrigateApiError::InvalidConfig(format!("Invalid Frigate API url `{base_url}`: {e}"))
+        })?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(FrigateApiError::InvalidConfig(format!(
+                "Frigate API url `{base_url}` must start with `http://` or `https://`"
+            )));
+        }
+        if url.host_str().is_none_or(str::is_empty) {
+            return Err(FrigateApiError::InvalidConfig(format!(
+                "Frigate API url `{base_url}` has no host"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The url all API endpoints are under, without a trailing slash, e.g.: `http://127.0.0.1:5000/frigate/api`
+    #[must_use]
+    pub fn api_root(&self) -> String {
+        format!("{}/api", self.web_root())
+    }
+
+    /// The url Frigate's web server is at, which also serves its media files outside the API,
+    /// without a trailing slash, e.g.: `http://127.0.0.1:5000/frigate`
+    #[must_use]
+    pub fn web_root(&self) -> String {
+        let base_url = self.frigate_api_base_url.trim_end_matches('/');
+        let prefix = self
+            .api_path_prefix
+            .as_deref()
+            .map(|p| p.trim_matches('/'))
+            .unwrap_or_default();
+
+        if prefix.is_empty() {
+            base_url.to_string()
+        } else {
+            format!("{base_url}/{prefix}")
+        }
+    }
+
+    /// Whether the clip is valid in the configured container, and complete if deep validation is enabled
+    #[must_use]
+    pub fn is_valid_clip(&self, data: &[u8]) -> bool {
+        self.clip_container.is_valid(data)
+            && (!self.deep_clip_validation || self.clip_container.is_complete(data))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipContainer {
+    #[default]
+    Mp4,
+    Mkv,
+    /// Accept any clip without validation, e.g., for proxied or transcoded streams.
+    /// The file extension is taken from the detected container, and defaults to mp4.
+    Auto,
+}
+
+impl ClipContainer {
+    /// Basic check that the clip provided is in this container
+    #[must_use]
+    pub fn is_valid(self, data: &[u8]) -> bool {
+        match self {
+            ClipContainer::Mp4 => is_mp4(data),
+            ClipContainer::Mkv => is_mkv(data),
+            ClipContainer::Auto => true,
+        }
+    }
+
+    /// Deeper check that the whole clip is there, e.g., that it wasn't cut short. Only mp4 clips are checked.
+    #[must_use]
+    pub fn is_complete(self, data: &[u8]) -> bool {
+        match self {
+            ClipContainer::Mp4 => is_complete_mp4(data),
+            ClipContainer::Mkv | ClipContainer::Auto => true,
+        }
+    }
+
+    /// The extension of the file the given clip is stored in
+    #[must_use]
+    pub fn extension_for(self, data: &[u8]) -> &'static str {
+        match self {
+            ClipContainer::Mp4 => "mp4",
+            ClipContainer::Mkv => "mkv",
+            ClipContainer::Auto => {
+                if is_mkv(data) {
+                    "mkv"
+                } else {
+                    "mp4"
+                }
+            }
+        }
+    }
+
+    /// All the extensions a clip may be stored with, before its data is known
+    #[must_use]
+    pub fn possible_extensions(self) -> &'static [&'static str] {
+        match self {
+            ClipContainer::Mp4 => &["mp4"],
+            ClipContainer::Mkv => &["mkv"],
+            ClipContainer::Auto => &["mp4", "mkv"],
+        }
+    }
+}
+
+/// The number of bytes at the start of a clip that are enough to tell its container
+pub(crate) const CLIP_HEADER_SIZE: usize = 12;
+
+fn is_mp4(data: &[u8]) -> bool {
+    data.len() > 11 && &data[4..8] == b"ftyp"
+}
+
+/// Matroska files start with the EBML header magic bytes
+fn is_mkv(data: &[u8]) -> bool {
+    data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const MP4_HEADER: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00";
+    const MKV_HEADER: &[u8] = b"\x1A\x45\xDF\xA3\x9F\x42\x86\x81\x01\x42\xF7\x81";
+
+    #[rstest]
+    #[case("http://127.0.0.1:5000", None, "http://127.0.0.1:5000/api")]
+    #[case("http://127.0.0.1:5000/", None, "http://127.0.0.1:5000/api")]
+    #[case("http://127.0.0.1:5000", Some(""), "http://127.0.0.1:5000/api")]
+    #[case(
+        "http://127.0.0.1:5000",
+        Some("frigate"),
+        "http://127.0.0.1:5000/frigate/api"
+    )]
+    #[case(
+        "http://127.0.0.1:5000/",
+        Some("/frigate/"),
+        "http://127.0.0.1:5000/frigate/api"
+    )]
+    #[case("https://host", Some("/a/b"), "https://host/a/b/api")]
+    fn api_root_with_path_prefix(
+        #[case] base_url: &str,
+        #[case] prefix: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        let config = FrigateApiConfig {
+            frigate_api_base_url: base_url.to_string(),
+            api_path_prefix: prefix.map(ToOwned::to_owned),
+            ..Default::default()
+        };
+        assert_eq!(config.api_root(), expected);
+    }
+
+    #[rstest]
+    #[case("http://127.0.0.1:5000", true)]
+    #[case("https://frigate.example.com/", true)]
+    #[case("http://[::1]:5000/frigate", true)]
+    #[case("127.0.0.1:5000", false)]
+    #[case("frigate.local", false)]
+    #[case("ftp://127.0.0.1", false)]
+    #[case("http://", false)]
+    #[case("http://:5000", false)]
+    fn base_url_is_checked(#[case] base_url: &str, #[case] valid: bool) {
+        let config = FrigateApiConfig {
+            frigate_api_base_url: base_url.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.check_base_url().is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case(ClipContainer::Mp4, MP4_HEADER, true)]
+    #[case(ClipContainer::Mp4, MKV_HEADER, false)]
+    #[case(ClipContainer::Mp4, b"", false)]
+    #[case(ClipContainer::Mp4, b"\x00\x00\x00\x20ftyp", false)]
+    #[case(ClipContainer::Mkv, MKV_HEADER, true)]
+    #[case(ClipContainer::Mkv, MP4_HEADER, false)]
+    #[case(ClipContainer::Mkv, b"\x1A\x45\xDF", false)]
+    #[case(ClipContainer::Auto, MP4_HEADER, true)]
+    #[case(ClipContainer::Auto, MKV_HEADER, true)]
+    #[case(ClipContainer::Auto, b"anything", true)]
+    fn clip_validation(
+        #[case] container: ClipContainer,
+        #[case] data: &[u8],
+        #[case] expected: bool,
+    ) {
+        assert_eq!(container.is_valid(data), expected);
+    }
+
+    #[rstest]
+    #[case(ClipContainer::Mp4, false, true)]
+    #[case(ClipContainer::Mp4, true, false)]
+    #[case(ClipContainer::Auto, true, true)]
+    fn deep_clip_validation_rejects_header_only_clip(
+        #[case] clip_container: ClipContainer,
+        #[case] deep_clip_validation: bool,
+        #[case] expected: bool,
+    ) {
+        let config = FrigateApiConfig {
+            clip_container,
+            deep_clip_validation,
+            ..Default::default()
+        };
+        // A header with nothing after it, e.g., of a recording that's still being written
+        assert_eq!(config.is_valid_clip(MP4_HEADER), expected);
+    }
+
+    #[rstest]
+    #[case(ClipContainer::Mp4, MKV_HEADER, "mp4")]
+    #[case(ClipContainer::Mkv, MP4_HEADER, "mkv")]
+    #[case(ClipContainer::Auto, MP4_HEADER, "mp4")]
+    #[case(ClipContainer::Auto, MKV_HEADER, "mkv")]
+    #[case(ClipContainer::Auto, b"anything", "mp4")]
+    fn clip_extension(
+        #[case] container: ClipContainer,
+        #[case] data: &[u8],
+        #[case] expected: &str,
+    ) {
+        assert_eq!(container.extension_for(data), expected);
+        assert!(container.possible_extensions().contains(&expected));
+    }
 }
@@ -0,0 +1,62 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{net::SocketAddr, sync::Arc};
+use utils::mdns::HostResolver;
+
+/// Resolves the host of Frigate's address with a `HostResolver`, e.g., with mDNS, whenever a connection is made to it,
+/// so that a change of its address is followed. The url keeps the hostname, e.g., for https certificates to be verified.
+/// Other hosts, e.g., of a proxy, are resolved by the system.
+#[derive(Clone)]
+pub struct FrigateHostResolver {
+    hostname: String,
+    resolver: Arc<dyn HostResolver>,
+}
+
+impl FrigateHostResolver {
+    pub fn new(hostname: impl Into<String>, resolver: Arc<dyn HostResolver>) -> Self {
+        Self {
+            hostname: hostname.into(),
+            resolver,
+        }
+    }
+}
+
+impl std::fmt::Debug for FrigateHostResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrigateHostResolver")
+            .field("hostname", &self.hostname)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Resolvers are compared by identity, as they can't be compared otherwise
+impl PartialEq for FrigateHostResolver {
+    fn eq(&self, other: &Self) -> bool {
+        self.hostname == other.hostname && Arc::ptr_eq(&self.resolver, &other.resolver)
+    }
+}
+
+impl Eq for FrigateHostResolver {}
+
+impl Resolve for FrigateHostResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let hostname = name.as_str().to_string();
+
+        if !hostname.eq_ignore_ascii_case(&self.hostname) {
+            return Box::pin(async move {
+                let addresses = tokio::net::lookup_host((hostname.as_str(), 0))
+                    .await?
+                    .collect::<Vec<_>>();
+                Ok(Box::new(addresses.into_iter()) as Addrs)
+            });
+        }
+
+        // Resolving may block, e.g., until an mDNS query times out. The port is replaced with the url's.
+        let resolver = self.resolver.clone();
+        Box::pin(async move {
+            let address =
+                tokio::task::spawn_blocking(move || resolver.resolve(&hostname)).await??;
+            tracing::debug!("Resolved Frigate's host to {address}");
+            Ok(Box::new(std::iter::once(SocketAddr::new(address, 0))) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,574 @@
+use crate::error::FrigateApiError;
+use anyhow::Context;
+use reqwest::{StatusCode, header::HeaderMap};
+use std::{
+    collections::HashMap,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+use tokio::io::AsyncWriteExt;
+
+const PARTIAL_DOWNLOADS_DIR_NAME: &str = "snap-sync-partial-downloads";
+const PARTIAL_DOWNLOAD_EXTENSION: &str = "part";
+/// Holds the URL of the download whose data is in the partial file next to it
+const PARTIAL_DOWNLOAD_SOURCE_EXTENSION: &str = "source";
+
+/// Partial downloads that weren't touched for this long were left behind, e.g., by a crash
+pub const STALE_PARTIAL_DOWNLOAD_AGE: std::time::Duration =
+    std::time::Duration::from_secs(24 * 60 * 60);
+
+/// The partial files that are being downloaded into, so that concurrent downloads of the same clip take turns
+static PARTIALS_IN_USE: LazyLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Mutex::default);
+
+fn partial_downloads_dir() -> PathBuf {
+    std::env::temp_dir().join(PARTIAL_DOWNLOADS_DIR_NAME)
+}
+
+/// Creates the directory, only accessible by the user, unless it exists. The temporary directory is shared, so another
+/// user could create the directory first, or a symlink in its place, to read the downloads or have them written elsewhere.
+/// Hence, an existing directory is only used if it's the user's own, and no one else could have written to it.
+async fn create_private_dir(dir: &Path) -> anyhow::Result<()> {
+    let mut builder = tokio::fs::DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(dir).await.context(format!(
+        "Creating directory for partial downloads: {}",
+        dir.display()
+    ))?;
+    check_private_dir(dir).await
+}
+
+/// Fails unless `dir` is a directory, not a symlink, that's owned by the user and not writable by others.
+/// If others can still read it, e.g., as it was made by an earlier version, it's made private.
+async fn check_private_dir(dir: &Path) -> anyhow::Result<()> {
+    let metadata = tokio::fs::symlink_metadata(dir).await.context(format!(
+        "Reading the metadata of the partial downloads directory: {}",
+        dir.display()
+    ))?;
+
+    if !metadata.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Partial downloads directory `{}` is not a directory",
+            dir.display()
+        ));
+    }
+    if metadata.uid() != nix::unistd::geteuid().as_raw() {
+        return Err(anyhow::anyhow!(
+            "Partial downloads directory `{}` is owned by another user",
+            dir.display()
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(anyhow::anyhow!(
+            "Partial downloads directory `{}` is writable by other users, with permissions {:o}",
+            dir.display(),
+            metadata.mode() & 0o777
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        use std::os::unix::fs::PermissionsExt;
+
+        tokio::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .await
+            .context(format!(
+                "Making the partial downloads directory private: {}",
+                dir.display()
+            ))?;
+    }
+    Ok(())
+}
+
+/// The path where the partial data of a clip is kept between download attempts. It's the same for every clip
+/// of the camera that starts at the same time, e.g., every clip of an ongoing review, so that a review leaves
+/// at most one partial file behind. The data is only resumed by a download of the same clip.
+#[must_use]
+pub fn partial_download_path(camera_label: &str, start_ts: f64) -> PathBuf {
+    // The label is only a part of the file name
+    let camera_label = camera_label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    partial_downloads_dir().join(format!(
+        "{camera_label}-{start_ts:.6}.mp4.{PARTIAL_DOWNLOAD_EXTENSION}"
+    ))
+}
+
+fn partial_download_source_path(partial_path: &Path) -> PathBuf {
+    partial_path.with_extension(format!(
+        "{PARTIAL_DOWNLOAD_EXTENSION}.{PARTIAL_DOWNLOAD_SOURCE_EXTENSION}"
+    ))
+}
+
+/// Deletes the partial download of the clips of the camera that start at `start_ts`, e.g., once the upload
+/// of their review is concluded or given up, as they won't be resumed anymore
+pub async fn discard_partial_download(camera_label: &str, start_ts: f64) {
+    let partial_path = partial_download_path(camera_label, start_ts);
+    let lock = partial_lock(&partial_path);
+    let _guard = lock.lock().await;
+    remove_partial_file(&partial_path).await;
+}
+
+/// Deletes the partial downloads that were left behind, e.g., by a crash, and weren't touched for `max_age`
+pub async fn remove_stale_partial_downloads(max_age: std::time::Duration) {
+    remove_stale_files(&partial_downloads_dir(), max_age).await;
+}
+
+async fn remove_stale_files(dir: &Path, max_age: std::time::Duration) {
+    if !tokio::fs::try_exists(dir).await.unwrap_or(false) {
+        return;
+    }
+    // Files in a directory of another user aren't ours to delete
+    if let Err(e) = check_private_dir(dir).await {
+        tracing::warn!("Not deleting stale partial downloads. Error: {e:#}");
+        return;
+    }
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            tracing::warn!(
+                "Listing the partial downloads in `{}` failed. Error: {e}",
+                dir.display()
+            );
+            return;
+        }
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let is_stale = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= max_age);
+        if is_stale {
+            tracing::info!(
+                "Deleting stale partial download `{}`",
+                entry.path().display()
+            );
+            if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                tracing::warn!(
+                    "Deleting stale partial download `{}` failed. Error: {e}",
+                    entry.path().display()
+                );
+            }
+        }
+    }
+}
+
+/// The lock of the partial file. It's dropped from the map by the last one to release it.
+fn partial_lock(partial_path: &Path) -> PartialLock {
+    let lock = PARTIALS_IN_USE
+        .lock()
+        .expect("Poisoned mutex")
+        .entry(partial_path.to_path_buf())
+        .or_default()
+        .clone();
+    PartialLock {
+        path: partial_path.to_path_buf(),
+        lock,
+    }
+}
+
+struct PartialLock {
+    path: PathBuf,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl std::ops::Deref for PartialLock {
+    type Target = tokio::sync::Mutex<()>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.lock
+    }
+}
+
+impl Drop for PartialLock {
+    fn drop(&mut self) {
+        let mut partials = PARTIALS_IN_USE.lock().expect("Poisoned mutex");
+        // The map's own reference and this one
+        if Arc::strong_count(&self.lock) == 2 {
+            partials.remove(&self.path);
+        }
+    }
+}
+
+/// Fails if a clip of `size` bytes, which may only be what has arrived of it so far, is over the limit
+pub fn check_clip_size(size: u64, max_clip_bytes: Option<u64>) -> Result<(), FrigateApiError> {
+    match max_clip_bytes {
+        Some(max_clip_bytes) if size > max_clip_bytes => Err(FrigateApiError::TooLarge(format!(
+            "The clip is at least {size} bytes, while at most {max_clip_bytes} bytes are allowed"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Downloads the file at `url` into `partial_path`, continuing from the data left there by previous attempts
+/// using an HTTP Range request. Every received chunk is appended to the file immediately, so an interrupted
+/// download keeps whatever has arrived. Once the download is complete, the partial file is removed and its data is returned.
+/// Data of another URL in the partial file is discarded, and concurrent downloads into the same partial file take turns.
+/// An unsuccessful HTTP status, or a file larger than `max_bytes`, is returned as a `FrigateApiError` within the error.
+pub async fn download_resumable(
+    client: &reqwest::Client,
+    url: &str,
+    headers: HeaderMap,
+    partial_path: &Path,
+    max_bytes: Option<u64>,
+) -> anyhow::Result<Vec<u8>> {
+    let lock = partial_lock(partial_path);
+    let _guard = lock.lock().await;
+
+    prepare_partial_file(partial_path, url).await?;
+
+    let already_downloaded = tokio::fs::metadata(partial_path)
+        .await
+        .map_or(0, |m| m.len());
+
+    let mut request = client.request(reqwest::Method::GET, url).headers(headers);
+    if already_downloaded > 0 {
+        tracing::debug!(
+            "Resuming download of `{url}` from byte {already_downloaded}, using partial file: {}",
+            partial_path.display()
+        );
+        request = request.header(
+            reqwest::header::RANGE,
+            format!("bytes={already_downloaded}-"),
+        );
+    }
+
+    let mut response = request
+        .send()
+        .await
+        .context("Sending clip request failed")?;
+
+    let mut file = match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let expected_range_start = format!("bytes {already_downloaded}-");
+            let content_range = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            if !content_range.starts_with(&expected_range_start) {
+                // We can't trust the data we have anymore, so the next attempt starts over
+                remove_partial_file(partial_path).await;
+                return Err(anyhow::anyhow!(
+                    "Unexpected Content-Range in resumed download. Expected it to start with `{expected_range_start}`, found: `{content_range}`"
+                ));
+            }
+
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(partial_path)
+                .await
+                .context("Opening partial download file for appending")?
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE if already_downloaded > 0 => {
+            // The partial file already contains everything there is to download
+            return take_completed_download(partial_path).await;
+        }
+        status if status.is_success() => {
+            if already_downloaded > 0 {
+                tracing::warn!(
+                    "Server ignored the Range request for `{url}`. Restarting the download from scratch."
+                );
+            }
+            tokio::fs::File::create(partial_path)
+                .await
+                .context("Creating partial download file")?
+        }
+        status => {
+            let body = response.text().await.unwrap_or_default();
+            return Err(FrigateApiError::from_status(status, body).into());
+        }
+    };
+
+    let mut size = tokio::fs::metadata(partial_path)
+        .await
+        .map_or(0, |m| m.len());
+    let expected_size = size + response.content_length().unwrap_or(0);
+    if let Err(e) = check_clip_size(expected_size, max_bytes) {
+        // The download is given up, so its data is of no use
+        remove_partial_file(partial_path).await;
+        return Err(e.into());
+    }
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                size += chunk.len() as u64;
+                if let Err(e) = check_clip_size(size, max_bytes) {
+                    drop(file);
+                    remove_partial_file(partial_path).await;
+                    return Err(e.into());
+                }
+                file.write_all(&chunk)
+                    .await
+                    .context("Writing to partial download file")?;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                // Make sure everything received so far is on disk for the next attempt
+                file.flush()
+                    .await
+                    .context("Flushing partial download file")?;
+                return Err(e).context("Clip download interrupted");
+            }
+        }
+    }
+
+    file.flush()
+        .await
+        .context("Flushing partial download file")?;
+    drop(file);
+
+    take_completed_download(partial_path).await
+}
+
+/// Creates the directory of the partial file. The data of a clip with other parameters, e.g., an earlier clip
+/// of an ongoing review, can't be resumed, so the partial file is only kept if it's of `url`.
+async fn prepare_partial_file(partial_path: &Path, url: &str) -> anyhow::Result<()> {
+    if let Some(parent) = partial_path.parent() {
+        create_private_dir(parent).await?;
+    }
+
+    let source_path = partial_download_source_path(partial_path);
+    let source = tokio::fs::read_to_string(&source_path)
+        .await
+        .unwrap_or_default();
+    if source == url {
+        return Ok(());
+    }
+
+    if tokio::fs::try_exists(partial_path).await.unwrap_or(false) {
+        tracing::debug!(
+            "Discarding partial download `{}` of another clip",
+            partial_path.display()
+        );
+        remove_partial_file(partial_path).await;
+    }
+    tokio::fs::write(&source_path, url)
+        .await
+        .context("Writing the source of the partial download")
+}
+
+async fn take_completed_download(partial_path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = tokio::fs::read(partial_path)
+        .await
+        .context("Reading completed download file")?;
+    remove_partial_file(partial_path).await;
+    Ok(data)
+}
+
+/// Removes the partial file and its source, if they exist
+async fn remove_partial_file(partial_path: &Path) {
+    for path in [
+        partial_path.to_path_buf(),
+        partial_download_source_path(partial_path),
+    ] {
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => tracing::error!(
+                "Failed to remove partial download file `{}`. Error: {e}",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    async fn read_request_head(socket: &mut TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !data.ends_with(b"\r\n\r\n") {
+            let n = socket.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "Connection closed before the request was complete");
+            data.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(data).unwrap().to_lowercase()
+    }
+
+    #[test]
+    fn partial_download_path_is_in_the_partial_downloads_dir() {
+        let path = partial_download_path("../front door/..", 1.5);
+        assert_eq!(path.parent().unwrap(), partial_downloads_dir());
+        assert_eq!(
+            path.file_name().unwrap(),
+            "___front_door___-1.500000.mp4.part"
+        );
+        // Every clip of a review that's ongoing has the same partial file
+        assert_eq!(path, partial_download_path("../front door/..", 1.5));
+    }
+
+    #[tokio::test]
+    async fn partial_download_of_another_clip_is_not_resumed() {
+        let clip = [b"\0\0\0\x18ftypmp42".as_slice(), &[7u8; 4096]].concat();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server_clip = clip.clone();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request_head(&mut socket).await;
+            assert!(!request.contains("range:"));
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                server_clip.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&server_clip).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+
+        // Left by a download of the same review that ended earlier
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let partial_path = temp_dir.path().join("clip.mp4.part");
+        std::fs::write(&partial_path, b"Data of another clip").unwrap();
+        std::fs::write(
+            partial_download_source_path(&partial_path),
+            "http://localhost/another_clip.mp4",
+        )
+        .unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{address}/clip.mp4");
+        let result = download_resumable(&client, &url, HeaderMap::new(), &partial_path, None)
+            .await
+            .unwrap();
+        assert_eq!(result, clip);
+        assert!(std::fs::read_dir(temp_dir.path()).unwrap().next().is_none());
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn partial_downloads_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("partial");
+
+        create_private_dir(&dir).await.unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // An existing directory of the user is used as is
+        create_private_dir(&dir).await.unwrap();
+
+        // Readable by others, which is made private
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&dir).await.unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // Writable by others
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o775)).unwrap();
+        create_private_dir(&dir).await.unwrap_err();
+
+        // A symlink in its place, even to a private directory
+        let link = temp_dir.path().join("link");
+        let target = temp_dir.path().join("target");
+        create_private_dir(&target).await.unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        create_private_dir(&link).await.unwrap_err();
+
+        // Stale downloads aren't deleted from a directory that isn't private
+        let partial_path = dir.join("clip.mp4.part");
+        std::fs::write(&partial_path, b"Partial data").unwrap();
+        remove_stale_files(&dir, std::time::Duration::ZERO).await;
+        assert!(partial_path.exists());
+    }
+
+    #[tokio::test]
+    async fn stale_partial_downloads_are_removed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let partial_path = temp_dir.path().join("clip.mp4.part");
+        std::fs::write(&partial_path, b"Partial data").unwrap();
+
+        remove_stale_files(temp_dir.path(), STALE_PARTIAL_DOWNLOAD_AGE).await;
+        assert!(partial_path.exists());
+
+        remove_stale_files(temp_dir.path(), std::time::Duration::ZERO).await;
+        assert!(!partial_path.exists());
+
+        // A missing directory has nothing to remove
+        remove_stale_files(&temp_dir.path().join("missing"), std::time::Duration::ZERO).await;
+    }
+
+    #[tokio::test]
+    async fn resume_interrupted_download() {
+        let clip = [b"\0\0\0\x18ftypmp42".as_slice(), &[7u8; 4096]].concat();
+        let half = clip.len() / 2;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server_clip = clip.clone();
+        let server = tokio::spawn(async move {
+            // First attempt: the full length is promised, but only half is sent before the connection drops
+            {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request_head(&mut socket).await;
+                assert!(!request.contains("range:"));
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                    server_clip.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&server_clip[..half]).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+
+            // Second attempt: the client asks for the remainder, and gets it
+            {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request_head(&mut socket).await;
+                assert!(request.contains(&format!("range: bytes={half}-")));
+                let head = format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {half}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                    server_clip.len() - 1,
+                    server_clip.len(),
+                    server_clip.len() - half,
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&server_clip[half..]).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+        });
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let partial_path = temp_dir.path().join("clip.mp4.part");
+        let client = reqwest::Client::new();
+        let url = format!("http://{address}/clip.mp4");
+
+        download_resumable(&client, &url, HeaderMap::new(), &partial_path, None)
+            .await
+            .unwrap_err();
+        assert_eq!(std::fs::read(&partial_path).unwrap(), clip[..half]);
+
+        let result = download_resumable(&client, &url, HeaderMap::new(), &partial_path, None)
+            .await
+            .unwrap();
+        assert_eq!(result, clip);
+        assert!(!partial_path.exists());
+
+        server.await.unwrap();
+    }
+}
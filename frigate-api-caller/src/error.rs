@@ -0,0 +1,78 @@
+use reqwest::StatusCode;
+
+/// Errors of calls to the Frigate API, categorized so that callers can tell which are worth retrying
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum FrigateApiError {
+    #[error("Frigate doesn't have the requested data ready yet: {0}")]
+    NotReady(String),
+    #[error("Frigate rejected the request as unauthorized with HTTP status {status}")]
+    Unauthorized { status: u16 },
+    #[error("Not found in Frigate: {0}")]
+    NotFound(String),
+    #[error("Calling the Frigate API failed: {0}")]
+    Network(String),
+    #[error("The response of the Frigate API could not be decoded: {0}")]
+    Decode(String),
+    #[error("The Frigate API responded with HTTP status {status}: {body}")]
+    Server { status: u16, body: String },
+    #[error("The Frigate API client could not be created: {0}")]
+    InvalidConfig(String),
+    #[error("The response of the Frigate API is larger than allowed: {0}")]
+    TooLarge(String),
+}
+
+impl FrigateApiError {
+    /// The error of a response with an unsuccessful status
+    #[must_use]
+    pub fn from_status(status: StatusCode, body: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized {
+                status: status.as_u16(),
+            },
+            StatusCode::NOT_FOUND => Self::NotFound(body),
+            _ => Self::Server {
+                status: status.as_u16(),
+                body,
+            },
+        }
+    }
+
+    /// Whether the same call may succeed later. Credentials, the config and the shape of Frigate's
+    /// responses don't change while running, so errors caused by them are permanent.
+    #[must_use]
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Self::NotReady(_) | Self::NotFound(_) | Self::Network(_) | Self::Server { .. } => true,
+            Self::Unauthorized { .. }
+            | Self::Decode(_)
+            | Self::InvalidConfig(_)
+            | Self::TooLarge(_) => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FrigateApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Self::Decode(e.to_string())
+        } else if let Some(status) = e.status() {
+            Self::from_status(status, e.to_string())
+        } else if e.is_builder() {
+            Self::InvalidConfig(e.to_string())
+        } else {
+            Self::Network(with_sources(&e))
+        }
+    }
+}
+
+/// The message of the error followed by those of its sources, which is where the reason of network errors is
+fn with_sources(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(s) = source {
+        message.push_str(": ");
+        message.push_str(&s.to_string());
+        source = s.source();
+    }
+    message
+}
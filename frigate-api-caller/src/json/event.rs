@@ -0,0 +1,17 @@
+#![allow(dead_code)]
+
+/// A tracked object, as returned by the events API.
+/// <https://docs.frigate.video/integrations/api/events-events-get>
+#[must_use]
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Event {
+    pub id: String,
+    pub camera: String,
+    pub label: String,
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    #[serde(default)]
+    pub has_clip: bool,
+    #[serde(default)]
+    pub has_snapshot: bool,
+}
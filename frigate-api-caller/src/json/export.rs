@@ -0,0 +1,58 @@
+/// The response of starting an export of a recording.
+/// <https://docs.frigate.video/integrations/api/export-recording-export-camera-name-start-start-time-end-end-time-post>
+#[must_use]
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ExportStarted {
+    pub success: bool,
+    #[serde(default)]
+    pub message: String,
+    pub export_id: Option<String>,
+}
+
+/// An export of a recording, which Frigate writes to a file in the background.
+/// <https://docs.frigate.video/integrations/api/get-export-exports-export-id-get>
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct Export {
+    pub id: String,
+    pub camera: String,
+    /// Where Frigate writes the file of the export, e.g., `/media/frigate/exports/front_abc123.mp4`
+    pub video_path: String,
+    pub in_progress: bool,
+}
+
+impl Export {
+    /// The name of the file of the export, which is what it's served by under `/exports/`
+    #[must_use]
+    pub fn file_name(&self) -> &str {
+        self.video_path
+            .rsplit('/')
+            .next()
+            .unwrap_or(&self.video_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_deserialization() {
+        let export: Export = serde_json::from_str(
+            r#"{
+                "id": "front_abc123",
+                "camera": "front",
+                "name": "Front Jun 4, 10:00",
+                "date": 1749031200.0,
+                "video_path": "/media/frigate/exports/front_abc123.mp4",
+                "thumb_path": "/media/frigate/clips/export/front_abc123.webp",
+                "in_progress": true
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(export.id, "front_abc123");
+        assert!(export.in_progress);
+        assert_eq!(export.file_name(), "front_abc123.mp4");
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const SECONDS_IN_DAY: f64 = 24. * 60. * 60.;
+
+/// The parts of Frigate's own configuration, as returned by `/api/config`, that are relevant here.
+/// Everything else in it is ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrigateConfig {
+    #[serde(default)]
+    pub cameras: HashMap<String, CameraConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraConfig {
+    #[serde(default)]
+    pub record: Option<RecordConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecordConfig {
+    /// Retention of continuous recordings
+    #[serde(default)]
+    pub retain: Option<RetainConfig>,
+    /// Retention of recordings of alerts and detections, which are what reviews point to
+    #[serde(default)]
+    pub alerts: Option<ReviewRetainConfig>,
+    #[serde(default)]
+    pub detections: Option<ReviewRetainConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReviewRetainConfig {
+    #[serde(default)]
+    pub retain: Option<RetainConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetainConfig {
+    pub days: f64,
+    #[serde(default)]
+    pub mode: Option<RetainMode>,
+}
+
+/// Which recording segments Frigate keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetainMode {
+    /// Only segments with active tracked objects
+    ActiveObjects,
+    /// Only segments with motion
+    Motion,
+    /// All segments
+    All,
+}
+
+impl FrigateConfig {
+    /// The longest time recordings of the given camera are kept for, or None if unknown
+    #[must_use]
+    pub fn recording_retention(&self, camera_label: &str) -> Option<std::time::Duration> {
+        self.cameras
+            .get(camera_label)?
+            .retains()
+            .map(|r| r.days)
+            .filter(|days| days.is_finite() && *days >= 0.)
+            .reduce(f64::max)
+            .map(|days| std::time::Duration::from_secs_f64(days * SECONDS_IN_DAY))
+    }
+
+    /// The most inclusive mode of the recordings that are kept for each camera, out of continuous
+    /// recordings, alerts and detections. A camera is missing if it keeps no recordings.
+    /// A retain without a mode is assumed to keep everything.
+    #[must_use]
+    pub fn recordings_retain_modes(&self) -> HashMap<String, RetainMode> {
+        self.cameras
+            .iter()
+            .filter_map(|(camera_label, camera)| {
+                camera
+                    .retains()
+                    .filter(|r| r.days > 0.)
+                    .map(|r| r.mode.unwrap_or(RetainMode::All))
+                    .max()
+                    .map(|mode| (camera_label.clone(), mode))
+            })
+            .collect()
+    }
+}
+
+impl CameraConfig {
+    fn retains(&self) -> impl Iterator<Item = &RetainConfig> {
+        let record = self.record.as_ref();
+
+        let review_retains = record
+            .into_iter()
+            .flat_map(|r| [&r.alerts, &r.detections])
+            .flatten()
+            .filter_map(|r| r.retain.as_ref());
+
+        record
+            .and_then(|r| r.retain.as_ref())
+            .into_iter()
+            .chain(review_retains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_is_longest_of_all_kinds() {
+        let config: FrigateConfig = serde_json::from_value(serde_json::json!({
+            "mqtt": { "host": "localhost" },
+            "cameras": {
+                "front": {
+                    "name": "front",
+                    "record": {
+                        "enabled": true,
+                        "retain": { "days": 1, "mode": "all" },
+                        "alerts": { "pre_capture": 5, "retain": { "days": 10, "mode": "motion" } },
+                        "detections": { "retain": { "days": 3.5, "mode": "motion" } }
+                    }
+                },
+                "back": {
+                    "record": { "retain": { "days": 2 } }
+                },
+                "side": {}
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            config.recording_retention("front"),
+            Some(std::time::Duration::from_secs(10 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            config.recording_retention("back"),
+            Some(std::time::Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert_eq!(config.recording_retention("side"), None);
+        assert_eq!(config.recording_retention("unknown"), None);
+    }
+
+    #[test]
+    fn retain_mode_is_most_inclusive_of_kept_recordings() {
+        let config: FrigateConfig = serde_json::from_value(serde_json::json!({
+            "cameras": {
+                "front": {
+                    "record": {
+                        "retain": { "days": 0, "mode": "all" },
+                        "alerts": { "retain": { "days": 10, "mode": "motion" } },
+                        "detections": { "retain": { "days": 3, "mode": "active_objects" } }
+                    }
+                },
+                "back": {
+                    "record": {
+                        "retain": { "days": 1, "mode": "all" },
+                        "alerts": { "retain": { "days": 10, "mode": "motion" } }
+                    }
+                },
+                "garage": {
+                    "record": {
+                        "detections": { "retain": { "days": 3, "mode": "active_objects" } }
+                    }
+                },
+                "no_mode": {
+                    "record": { "retain": { "days": 2 } }
+                },
+                "side": {}
+            }
+        }))
+        .unwrap();
+
+        let modes = config.recordings_retain_modes();
+        assert_eq!(modes.get("front"), Some(&RetainMode::Motion));
+        assert_eq!(modes.get("back"), Some(&RetainMode::All));
+        assert_eq!(modes.get("garage"), Some(&RetainMode::ActiveObjects));
+        assert_eq!(modes.get("no_mode"), Some(&RetainMode::All));
+        assert_eq!(modes.get("side"), None);
+        assert_eq!(modes.len(), 4);
+    }
+}
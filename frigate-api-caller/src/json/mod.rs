@@ -1,2 +1,5 @@
+pub mod event;
+pub mod export;
+pub mod frigate_config;
 pub mod review;
 pub mod stats;
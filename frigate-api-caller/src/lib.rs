@@ -1,60 +1,108 @@
 pub mod config;
+pub mod dns;
+mod download;
+mod error;
 pub mod helpers;
 pub mod json;
+mod mp4_boxes;
+mod session;
 pub mod traits;
 
-use crate::json::stats::{Stats, StatsProps};
-use anyhow::Context;
+use crate::json::{
+    frigate_config::{FrigateConfig, RetainMode},
+    stats::{Stats, StatsProps},
+};
 use async_trait::async_trait;
-use config::FrigateApiConfig;
-use json::review::Review;
+use config::{CLIP_HEADER_SIZE, ClipContainer, FrigateApiConfig};
+use download::check_clip_size;
+pub use download::{
+    STALE_PARTIAL_DOWNLOAD_AGE, discard_partial_download, partial_download_path,
+    remove_stale_partial_downloads,
+};
+pub use error::FrigateApiError;
+use futures::StreamExt;
+use json::{
+    event::Event,
+    export::{Export, ExportStarted},
+    review::Review,
+};
+use mp4_boxes::Mp4BoxScanner;
+use reqwest::header::{COOKIE, HeaderMap, HeaderValue};
+use serde::Deserialize;
 use serde_json::Value;
+use session::FrigateSession;
 use std::sync::Arc;
 use tracing::trace_span;
-use traits::FrigateApi;
+use traits::{ClipStream, FrigateApi};
 
-pub fn make_frigate_client(config: FrigateApiConfig) -> anyhow::Result<Arc<dyn FrigateApi>> {
+pub fn make_frigate_client(
+    config: FrigateApiConfig,
+) -> Result<Arc<dyn FrigateApi>, FrigateApiError> {
     let span = trace_span!("make_frigate_client");
     let _enter = span.enter();
 
     tracing::trace!("Begin make_frigate_client function");
-    let builder = reqwest::ClientBuilder::new();
+    config.check_base_url()?;
+
+    let mut builder = reqwest::ClientBuilder::new()
+        .local_address(config.bind_address)
+        .gzip(config.compress_responses)
+        .brotli(config.compress_responses);
+    if let Some(host_resolver) = &config.host_resolver {
+        builder = builder.dns_resolver(Arc::new(host_resolver.clone()));
+    }
 
     tracing::trace!("Builder created");
 
     let client = match &config.frigate_api_proxy {
-        Some(proxy) => builder
-            .proxy(reqwest::Proxy::all(proxy).context("Invalid proxy URL")?)
-            .build()
-            .context("Building Frigate API with proxy")?,
-        None => builder
-            .build()
-            .context("Building Frigate API without proxy")?,
+        Some(proxy) => {
+            let mut proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| FrigateApiError::InvalidConfig(format!("Invalid proxy URL: {e}")))?;
+            if let Some(auth) = &config.frigate_api_proxy_auth {
+                proxy = proxy.basic_auth(&auth.username, &auth.password);
+            }
+            builder.proxy(proxy).build().map_err(|e| {
+                FrigateApiError::InvalidConfig(format!("Building Frigate API with proxy: {e}"))
+            })?
+        }
+        None => builder.build().map_err(|e| {
+            FrigateApiError::InvalidConfig(format!("Building Frigate API without proxy: {e}"))
+        })?,
     };
 
     tracing::trace!("Building client done");
 
-    let result = FrigateApiClient { client, config };
+    let session = config.frigate_login.clone().map(FrigateSession::new);
+    let result = FrigateApiClient {
+        client,
+        config,
+        session,
+    };
 
     tracing::trace!("Returning API object");
 
     Ok(Arc::new(result))
 }
 
+/// The number of events requested at once from the events API
+const EVENTS_PAGE_SIZE: usize = 100;
+
 struct FrigateApiClient {
     client: reqwest::Client,
     config: FrigateApiConfig,
+    // Only with Frigate's built-in authentication
+    session: Option<FrigateSession>,
 }
 
 #[async_trait]
 impl FrigateApi for FrigateApiClient {
-    async fn test_call(&self) -> anyhow::Result<()> {
+    async fn test_call(&self) -> Result<(), FrigateApiError> {
         let span = tracing::trace_span!("Frigate API test_call");
         let _enter = span.enter();
         tracing::trace!("Start");
 
-        let base_url = &self.config.frigate_api_base_url;
-        let url = format!("{base_url}/api/review/summary");
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/review/summary");
 
         tracing::trace!("Creating request");
 
@@ -64,13 +112,10 @@ impl FrigateApi for FrigateApiClient {
             .headers(json_headers_map());
 
         tracing::trace!("Submitting request to URL: {url}");
-        let response = request
-            .send()
-            .await
-            .context("Sending test request failed")?;
+        let response = self.send(request).await?;
 
         tracing::trace!("Parsing response request");
-        let response_json = response.json::<Value>().await.context("Parsing response")?;
+        let response_json = response.json::<Value>().await?;
 
         tracing::trace!("Printing results");
         // Review summaries always contain the key "last24Hours"
@@ -79,9 +124,9 @@ impl FrigateApi for FrigateApiClient {
                 tracing::debug!("API test call succeeded with output: {response_json}",);
             }
             None => {
-                return Err(anyhow::anyhow!(
+                return Err(FrigateApiError::Decode(format!(
                     "Test request succeeded, but the response does not seem valid. Perhaps the URL is invalid: {response_json}"
-                ));
+                )));
             }
         }
 
@@ -90,29 +135,89 @@ impl FrigateApi for FrigateApiClient {
         Ok(())
     }
 
-    async fn review(&self, id: &str) -> anyhow::Result<Review> {
-        let base_url = &self.config.frigate_api_base_url;
-        let url = format!("{base_url}/api/review/{id}");
+    async fn review(&self, id: &str) -> Result<Review, FrigateApiError> {
+        let raw = self.review_raw(id).await?;
+        let result = Review::deserialize(&raw).map_err(|e| {
+            FrigateApiError::Decode(format!("Review with id {id} is invalid: {e}: {raw}"))
+        })?;
+
+        tracing::debug!("Call `review` with id {id} with response: {:?}", result);
+
+        Ok(result)
+    }
+
+    async fn review_raw(&self, id: &str) -> Result<serde_json::Value, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/review/{id}");
         let request = self
             .client
             .request(reqwest::Method::GET, url)
             .headers(json_headers_map());
-        let response = request.send().await?;
-        let result = response.json::<Review>().await?;
+        let response = self.send(request).await?;
+        let result = response.json::<Value>().await?;
 
-        tracing::debug!("Call `review` with id {id} with response: {:?}", result);
+        tracing::trace!("Call `review_raw` with id {id} with response: {result}");
+
+        Ok(result)
+    }
+
+    async fn events(
+        &self,
+        after: f64,
+        before: f64,
+        cameras: &[String],
+    ) -> Result<Vec<Event>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let mut result: Vec<Event> = Vec::new();
+        let mut seen_ids = std::collections::HashSet::new();
+        let mut page_before = before;
+
+        // Events are returned newest first, with a limit on their count, so we page backwards in time
+        loop {
+            let url = events_url(&api_root, after, page_before, cameras, EVENTS_PAGE_SIZE);
+            let request = self
+                .client
+                .request(reqwest::Method::GET, url)
+                .headers(json_headers_map());
+            let response = self.send(request).await?;
+            let page = response.json::<Vec<Event>>().await?;
+
+            let page_len = page.len();
+            let earliest_start = page
+                .iter()
+                .map(|e| e.start_time)
+                .fold(page_before, f64::min);
+
+            // Events that started exactly at a page boundary are returned in both pages
+            for event in page {
+                if seen_ids.insert(event.id.clone()) {
+                    result.push(event);
+                }
+            }
+
+            // A full page whose events all started at the same time cannot be paged further
+            if page_len < EVENTS_PAGE_SIZE || earliest_start >= page_before {
+                break;
+            }
+            page_before = earliest_start;
+        }
+
+        tracing::debug!(
+            "Call `events` within [{after},{before}] for cameras {cameras:?} returned {} events",
+            result.len()
+        );
 
         Ok(result)
     }
 
-    async fn stats(&self) -> anyhow::Result<Box<dyn StatsProps>> {
-        let base_url = &self.config.frigate_api_base_url;
-        let url = format!("{base_url}/api/stats");
+    async fn stats(&self) -> Result<Box<dyn StatsProps>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/stats");
         let request = self
             .client
             .request(reqwest::Method::GET, url)
             .headers(json_headers_map());
-        let response = request.send().await?;
+        let response = self.send(request).await?;
         let result = response.json::<Stats>().await?;
 
         tracing::debug!("Call `stats` with response: {:?}", result);
@@ -120,25 +225,86 @@ impl FrigateApi for FrigateApiClient {
         Ok(Box::new(result))
     }
 
-    async fn recording_clip(
+    async fn recording_retention(
         &self,
         camera_label: &str,
-        start_ts: f64,
-        end_ts: f64,
-    ) -> anyhow::Result<Option<Vec<u8>>> {
-        let base_url = &self.config.frigate_api_base_url;
-        let url = format!("{base_url}/api/{camera_label}/start/{start_ts}/end/{end_ts}/clip.mp4");
+    ) -> Result<Option<std::time::Duration>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/config");
         let request = self
             .client
             .request(reqwest::Method::GET, url)
             .headers(json_headers_map());
-        let response = request.send().await?;
-        let result = response.bytes().await?;
+        let response = self.send(request).await?;
+        let result = response.json::<FrigateConfig>().await?;
+
+        let retention = result.recording_retention(camera_label);
+
+        tracing::debug!(
+            "Call `config` for the recording retention of camera `{camera_label}` with result: {retention:?}"
+        );
+
+        Ok(retention)
+    }
+
+    async fn recordings_retain_modes(
+        &self,
+    ) -> Result<std::collections::HashMap<String, RetainMode>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/config");
+        let request = self
+            .client
+            .request(reqwest::Method::GET, url)
+            .headers(json_headers_map());
+        let response = self.send(request).await?;
+        let result = response.json::<FrigateConfig>().await?;
+
+        let modes = result.recordings_retain_modes();
+
+        tracing::debug!("Call `config` for the recordings retain modes with result: {modes:?}");
+
+        Ok(modes)
+    }
 
-        if !is_valid_mp4(&result) {
-            return Err(anyhow::anyhow!(
-                "The file returned in `recording_clip` API call is not a valid MP4 file. Parameters: [start,end] times [{start_ts},{end_ts}]"
-            ));
+    async fn recording_clip(
+        &self,
+        camera_label: &str,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        let url = recording_clip_url(
+            &self.config.api_root(),
+            camera_label,
+            start_ts,
+            end_ts,
+            self.config.clip_timestamp_precision,
+        );
+        let result: Vec<u8> = if self.config.resume_downloads {
+            let partial_path = download::partial_download_path(camera_label, start_ts);
+            self.with_session(|cookie| async {
+                download::download_resumable(
+                    &self.client,
+                    &url,
+                    with_cookie(json_headers_map(), cookie),
+                    &partial_path,
+                    self.config.max_clip_bytes,
+                )
+                .await
+                .map_err(|e| match e.downcast::<FrigateApiError>() {
+                    Ok(e) => e,
+                    Err(e) => FrigateApiError::Network(format!("{e:#}")),
+                })
+            })
+            .await?
+        } else {
+            self.download_clip(url).await?
+        };
+
+        if !self.config.is_valid_clip(&result) {
+            return Err(FrigateApiError::NotReady(format!(
+                "The file returned in `recording_clip` API call is not a valid {:?} file. Parameters: [start,end] times [{start_ts},{end_ts}]",
+                self.config.clip_container
+            )));
         }
 
         if result.is_empty() {
@@ -154,10 +320,369 @@ impl FrigateApi for FrigateApiClient {
             result.len()
         );
 
-        Ok(Some(result.into()))
+        Ok(Some(result))
+    }
+
+    async fn recording_clip_stream(
+        &self,
+        camera_label: &str,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<Option<ClipStream>, FrigateApiError> {
+        // A resumed download is kept in a file until it's complete
+        if self.config.resume_downloads {
+            let clip = self.recording_clip(camera_label, start_ts, end_ts).await?;
+            return Ok(
+                clip.map(|clip| futures::stream::once(async move { Ok(clip.into()) }).boxed())
+            );
+        }
+
+        let url = recording_clip_url(
+            &self.config.api_root(),
+            camera_label,
+            start_ts,
+            end_ts,
+            self.config.clip_timestamp_precision,
+        );
+        let request = self
+            .client
+            .request(reqwest::Method::GET, url)
+            .headers(json_headers_map());
+        let mut response = self.send(request).await?;
+        let max_clip_bytes = self.config.max_clip_bytes;
+        check_clip_size(response.content_length().unwrap_or(0), max_clip_bytes)?;
+
+        // Enough of the clip is read to validate its container before any of it is returned
+        let mut head = Vec::new();
+        while head.len() < CLIP_HEADER_SIZE {
+            match response.chunk().await? {
+                Some(chunk) => head.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        check_clip_size(head.len() as u64, max_clip_bytes)?;
+
+        if !self.config.clip_container.is_valid(&head) {
+            return Err(FrigateApiError::NotReady(format!(
+                "The file returned in `recording_clip_stream` API call is not a valid {:?} file. Parameters: [start,end] times [{start_ts},{end_ts}]",
+                self.config.clip_container
+            )));
+        }
+
+        if head.is_empty() {
+            return Ok(None);
+        }
+
+        tracing::debug!(
+            "Call `recording_clip_stream` with [start,end] times [{start_ts:.6},{end_ts:.6}] started streaming"
+        );
+
+        // Whether the clip is complete is only known once all of it is streamed
+        let scanner = (self.config.deep_clip_validation
+            && self.config.clip_container == ClipContainer::Mp4)
+            .then(|| {
+                let mut scanner = Mp4BoxScanner::default();
+                scanner.feed(&head);
+                scanner
+            });
+
+        let head_size = head.len() as u64;
+        let rest = futures::stream::unfold(
+            Some((response, head_size, scanner)),
+            move |response| async move {
+                let (mut response, size, mut scanner) = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        let size = size + chunk.len() as u64;
+                        if let Some(scanner) = &mut scanner {
+                            scanner.feed(&chunk);
+                        }
+                        match check_clip_size(size, max_clip_bytes) {
+                            Ok(()) => Some((Ok(chunk), Some((response, size, scanner)))),
+                            // The rest of the clip isn't read
+                            Err(e) => Some((Err(e.into()), None)),
+                        }
+                    }
+                    Ok(None) => match scanner {
+                        Some(scanner) if !scanner.is_complete() => {
+                            let error = FrigateApiError::NotReady(format!(
+                                "The file returned in `recording_clip_stream` API call is an incomplete mp4 file. Parameters: [start,end] times [{start_ts},{end_ts}]"
+                            ));
+                            Some((Err(error.into()), None))
+                        }
+                        _ => None,
+                    },
+                    // Nothing can be read after an error
+                    Err(e) => Some((Err(e.into()), None)),
+                }
+            },
+        );
+
+        Ok(Some(
+            futures::stream::once(async move { Ok(head.into()) })
+                .chain(rest)
+                .boxed(),
+        ))
+    }
+
+    async fn start_export(
+        &self,
+        camera_label: &str,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<String, FrigateApiError> {
+        let precision = self.config.clip_timestamp_precision;
+        let url = format!(
+            "{}/export/{camera_label}/start/{}/end/{}",
+            self.config.api_root(),
+            format_timestamp(start_ts, precision),
+            format_timestamp(end_ts, precision),
+        );
+        let request = self
+            .client
+            .request(reqwest::Method::POST, url)
+            .headers(json_headers_map())
+            .json(&serde_json::json!({ "playback": "realtime" }));
+        let response = self.send(request).await?;
+        let result = response.json::<ExportStarted>().await?;
+
+        tracing::debug!(
+            "Call `start_export` with [start,end] times [{start_ts:.6},{end_ts:.6}] with response: {result:?}"
+        );
+
+        if !result.success {
+            return Err(FrigateApiError::NotReady(format!(
+                "Frigate didn't start the export: {}",
+                result.message
+            )));
+        }
+
+        result.export_id.ok_or_else(|| {
+            FrigateApiError::Decode(
+                "Frigate started the export without returning its id. Following exports requires Frigate 0.15 or later".to_string(),
+            )
+        })
+    }
+
+    async fn export(&self, export_id: &str) -> Result<Export, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/exports/{export_id}");
+        let request = self
+            .client
+            .request(reqwest::Method::GET, url)
+            .headers(json_headers_map());
+        let response = self.send(request).await?;
+        let result = response.json::<Export>().await?;
+
+        tracing::debug!("Call `export` with id {export_id} with response: {result:?}");
+
+        Ok(result)
+    }
+
+    async fn export_clip(&self, export: &Export) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        let url = format!("{}/exports/{}", self.config.web_root(), export.file_name());
+        let result = self.download_clip(url).await?;
+
+        if !self.config.is_valid_clip(&result) {
+            return Err(FrigateApiError::NotReady(format!(
+                "The file of export with id `{}` is not a valid {:?} file",
+                export.id, self.config.clip_container
+            )));
+        }
+
+        tracing::debug!(
+            "Call `export_clip` with id {} with response of size: {} bytes",
+            export.id,
+            result.len()
+        );
+
+        Ok((!result.is_empty()).then_some(result))
+    }
+
+    async fn delete_export(&self, export_id: &str) -> Result<(), FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/export/{export_id}");
+        let request = self
+            .client
+            .request(reqwest::Method::DELETE, url)
+            .headers(json_headers_map());
+        self.send(request).await?;
+
+        tracing::debug!("Call `delete_export` with id {export_id} succeeded");
+
+        Ok(())
+    }
+
+    async fn event_snapshot(&self, event_id: &str) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/events/{event_id}/snapshot.jpg?bbox=1");
+        let request = self.client.request(reqwest::Method::GET, url);
+        let response = match self.send(request).await {
+            // Events without a snapshot, or that have been deleted, are not found
+            Err(FrigateApiError::NotFound(_)) => {
+                tracing::debug!("Call `event_snapshot` with id {event_id} found no snapshot");
+                return Ok(None);
+            }
+            response => response?,
+        };
+
+        let result: Vec<u8> = response.bytes().await?.into();
+
+        tracing::debug!(
+            "Call `event_snapshot` with id {event_id} with response of size: {} bytes",
+            result.len()
+        );
+
+        Ok((!result.is_empty()).then_some(result))
+    }
+
+    async fn latest_snapshot(
+        &self,
+        camera_label: &str,
+    ) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        let api_root = self.config.api_root();
+        let url = format!("{api_root}/{camera_label}/latest.jpg");
+        let request = self.client.request(reqwest::Method::GET, url);
+        let response = match self.send(request).await {
+            // Cameras that haven't produced a frame yet have none
+            Err(FrigateApiError::NotFound(_)) => {
+                tracing::debug!(
+                    "Call `latest_snapshot` for camera `{camera_label}` found no snapshot"
+                );
+                return Ok(None);
+            }
+            response => response?,
+        };
+
+        let result: Vec<u8> = response.bytes().await?.into();
+
+        tracing::debug!(
+            "Call `latest_snapshot` for camera `{camera_label}` with response of size: {} bytes",
+            result.len()
+        );
+
+        Ok((!result.is_empty()).then_some(result))
+    }
+}
+
+impl FrigateApiClient {
+    /// Sends the request and checks the status of its response, within the session if there's one
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, FrigateApiError> {
+        self.with_session(|cookie| {
+            let request = request
+                .try_clone()
+                .expect("Requests to Frigate have no streamed body");
+            async move {
+                let request = request.headers(with_cookie(HeaderMap::new(), cookie));
+                check_status(request.send().await?).await
+            }
+        })
+        .await
+    }
+
+    /// Makes the call with the session cookie, if Frigate's authentication is used. A call that's
+    /// rejected with 401 is made once more after logging in again, since the session may have expired.
+    async fn with_session<T, F, Fut>(&self, call: F) -> Result<T, FrigateApiError>
+    where
+        F: Fn(Option<HeaderValue>) -> Fut,
+        Fut: Future<Output = Result<T, FrigateApiError>>,
+    {
+        let Some(session) = &self.session else {
+            return call(None).await;
+        };
+
+        let api_root = self.config.api_root();
+        let cookie = session.cookie(&self.client, &api_root).await?;
+        match call(Some(cookie.clone())).await {
+            Err(FrigateApiError::Unauthorized { status: 401 }) => {
+                tracing::debug!(
+                    "Frigate rejected the session, which may have expired. Logging in again."
+                );
+                session.expire(&cookie).await;
+                let cookie = session.cookie(&self.client, &api_root).await?;
+                call(Some(cookie)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Downloads a clip in one go, up to `max_clip_bytes`
+    async fn download_clip(&self, url: String) -> Result<Vec<u8>, FrigateApiError> {
+        let request = self
+            .client
+            .request(reqwest::Method::GET, url)
+            .headers(json_headers_map());
+        let mut response = self.send(request).await?;
+        check_clip_size(
+            response.content_length().unwrap_or(0),
+            self.config.max_clip_bytes,
+        )?;
+
+        // Checked while downloading, as the length of the response may not be known in advance
+        let mut clip = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            clip.extend_from_slice(&chunk);
+            check_clip_size(clip.len() as u64, self.config.max_clip_bytes)?;
+        }
+        Ok(clip)
+    }
+}
+
+/// Returns the response if its status is successful, or the error it stands for, with the body it came with
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, FrigateApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(FrigateApiError::from_status(status, body))
+}
+
+fn recording_clip_url(
+    api_root: &str,
+    camera_label: &str,
+    start_ts: f64,
+    end_ts: f64,
+    precision: Option<u32>,
+) -> String {
+    format!(
+        "{api_root}/{camera_label}/start/{}/end/{}/clip.mp4",
+        format_timestamp(start_ts, precision),
+        format_timestamp(end_ts, precision)
+    )
+}
+
+/// Rounded to `precision` decimals, if given
+fn format_timestamp(ts: f64, precision: Option<u32>) -> String {
+    match precision {
+        Some(p) => {
+            let p = p as usize;
+            format!("{ts:.p$}")
+        }
+        None => ts.to_string(),
     }
 }
 
+fn events_url(api_root: &str, after: f64, before: f64, cameras: &[String], limit: usize) -> String {
+    let cameras = if cameras.is_empty() {
+        "all".to_string()
+    } else {
+        cameras.join(",")
+    };
+    format!("{api_root}/events?after={after}&before={before}&cameras={cameras}&limit={limit}")
+}
+
+/// Adds the session cookie to the headers, if there's one
+fn with_cookie(mut headers: HeaderMap, cookie: Option<HeaderValue>) -> HeaderMap {
+    if let Some(cookie) = cookie {
+        headers.insert(COOKIE, cookie);
+    }
+    headers
+}
+
 fn json_headers_map() -> reqwest::header::HeaderMap {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -167,14 +692,10 @@ fn json_headers_map() -> reqwest::header::HeaderMap {
     headers
 }
 
-/// Basic check that the file provided is an MP4 file
-fn is_valid_mp4(data: &[u8]) -> bool {
-    data.len() > 11 && &data[4..8] == b"ftyp"
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use config::{FrigateLogin, ProxyAuth};
     use rstest::{fixture, rstest};
 
     #[fixture]
@@ -182,6 +703,620 @@ mod tests {
         "http://127.0.0.1:5000".to_string()
     }
 
+    #[rstest]
+    #[case(None, "1744534711.333822", "1744534731.13457")]
+    #[case(Some(0), "1744534711", "1744534731")]
+    #[case(Some(2), "1744534711.33", "1744534731.13")]
+    #[case(Some(6), "1744534711.333822", "1744534731.134570")]
+    fn recording_clip_url_precision(
+        base_url: String,
+        #[case] precision: Option<u32>,
+        #[case] expected_start: &str,
+        #[case] expected_end: &str,
+    ) {
+        let url = recording_clip_url(
+            &format!("{base_url}/api"),
+            "my_camera",
+            1_744_534_711.333_822,
+            1_744_534_731.134_57,
+            precision,
+        );
+        assert_eq!(
+            url,
+            format!("{base_url}/api/my_camera/start/{expected_start}/end/{expected_end}/clip.mp4")
+        );
+    }
+
+    #[rstest]
+    fn recording_clip_url_with_path_prefix(base_url: String) {
+        let config = FrigateApiConfig {
+            frigate_api_base_url: base_url.clone(),
+            api_path_prefix: Some("/frigate/".to_string()),
+            ..Default::default()
+        };
+
+        let url = recording_clip_url(&config.api_root(), "my_camera", 1.5, 2.5, None);
+        assert_eq!(
+            url,
+            format!("{base_url}/frigate/api/my_camera/start/1.5/end/2.5/clip.mp4")
+        );
+    }
+
+    #[rstest]
+    #[case(&[], "all")]
+    #[case(&["front"], "front")]
+    #[case(&["front", "back"], "front,back")]
+    fn events_url_cameras(base_url: String, #[case] cameras: &[&str], #[case] expected: &str) {
+        let cameras: Vec<String> = cameras.iter().map(ToString::to_string).collect();
+        let url = events_url(&format!("{base_url}/api"), 100.5, 200., &cameras, 50);
+        assert_eq!(
+            url,
+            format!("{base_url}/api/events?after=100.5&before=200&cameras={expected}&limit=50")
+        );
+    }
+
+    fn local_config(base_url: String) -> FrigateApiConfig {
+        FrigateApiConfig {
+            frigate_api_base_url: base_url,
+            ..Default::default()
+        }
+    }
+
+    /// Answers a single request with the given status line and body, and returns the base url to reach it
+    async fn serve_once(status_line: &'static str, body: &'static [u8]) -> String {
+        serve_once_with_length(status_line, body, true).await
+    }
+
+    /// Without the length, the body ends when the connection is closed
+    async fn serve_once_with_length(
+        status_line: &'static str,
+        body: &'static [u8],
+        with_length: bool,
+    ) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // The body of the request, if any, isn't needed
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let length = if with_length {
+                format!("Content-Length: {}\r\n", body.len())
+            } else {
+                String::new()
+            };
+            let head = format!("HTTP/1.1 {status_line}\r\n{length}Connection: close\r\n\r\n");
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(body).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case("401 Unauthorized", b"", FrigateApiError::Unauthorized { status: 401 })]
+    #[case("403 Forbidden", b"", FrigateApiError::Unauthorized { status: 403 })]
+    #[case("404 Not Found", b"Review not found", FrigateApiError::NotFound("Review not found".to_string()))]
+    #[case("500 Internal Server Error", b"Oops", FrigateApiError::Server { status: 500, body: "Oops".to_string() })]
+    async fn review_response_maps_to_error(
+        #[case] status_line: &'static str,
+        #[case] body: &'static [u8],
+        #[case] expected: FrigateApiError,
+    ) {
+        let base_url = serve_once(status_line, body).await;
+        let frigate_client = make_frigate_client(local_config(base_url)).unwrap();
+
+        assert_eq!(
+            frigate_client.review("some-id").await.unwrap_err(),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn raw_review_is_returned_unchanged() {
+        // With fields that `Review` doesn't have, and numbers that are easily changed by conversions
+        const REVIEW: &[u8] = br#"{"id":"1744534711.333822-vsz5s4","camera":"front","start_time":1744534711.333822,"end_time":null,"has_been_reviewed":false,"severity":"alert","thumb_path":"/media/frigate/clips/review/thumb-front.webp","data":{"detections":["1744534711.333822-abc"],"objects":["person"],"sub_labels":[],"zones":["yard"],"audio":[],"significant_motion_areas":[[0.1,0.25]],"future_field":{"nested":[1,2.5,"three"]}},"extra":18446744073709551615}"#;
+
+        let base_url = serve_once("200 OK", REVIEW).await;
+        let frigate_client = make_frigate_client(local_config(base_url)).unwrap();
+        let raw = frigate_client
+            .review_raw("1744534711.333822-vsz5s4")
+            .await
+            .unwrap();
+
+        assert_eq!(raw, serde_json::from_slice::<Value>(REVIEW).unwrap());
+        assert_eq!(
+            raw["data"]["future_field"]["nested"],
+            serde_json::json!([1, 2.5, "three"])
+        );
+        assert_eq!(raw["extra"], u64::MAX);
+
+        let review = Review::deserialize(&raw).unwrap();
+        assert_eq!(review.id, "1744534711.333822-vsz5s4");
+        assert_eq!(
+            review.start_time.to_bits(),
+            1_744_534_711.333_822_f64.to_bits()
+        );
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn gzip_response_is_decompressed(#[values(false, true)] compress_responses: bool) {
+        use std::io::Write;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const REVIEW: &[u8] =
+            br#"{"id":"1744534711.333822-vsz5s4","camera":"front","data":{"objects":["person"]}}"#;
+
+        // Answers with the review compressed, unless the request doesn't accept gzip, and reports the request's head
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (head_sender, head_receiver) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let head = String::from_utf8(request).unwrap().to_lowercase();
+            let accepts_gzip = head
+                .lines()
+                .find_map(|l| l.strip_prefix("accept-encoding: "))
+                .is_some_and(|encodings| encodings.contains("gzip"));
+            head_sender.send(head).unwrap();
+
+            let (encoding, body) = if accepts_gzip {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(REVIEW).unwrap();
+                ("Content-Encoding: gzip\r\n", encoder.finish().unwrap())
+            } else {
+                ("", REVIEW.to_vec())
+            };
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{encoding}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+
+        let config = FrigateApiConfig {
+            compress_responses,
+            ..local_config(format!("http://{address}"))
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+        let raw = frigate_client
+            .review_raw("1744534711.333822-vsz5s4")
+            .await
+            .unwrap();
+        assert_eq!(raw, serde_json::from_slice::<Value>(REVIEW).unwrap());
+
+        let head = head_receiver.await.unwrap();
+        let accept_encoding = head
+            .lines()
+            .find_map(|l| l.strip_prefix("accept-encoding: "));
+        if compress_responses {
+            let accept_encoding = accept_encoding.unwrap();
+            assert!(accept_encoding.contains("gzip"), "{accept_encoding}");
+            assert!(accept_encoding.contains("br"), "{accept_encoding}");
+        } else {
+            assert_eq!(accept_encoding, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_decode_error() {
+        let base_url = serve_once("200 OK", b"<html></html>").await;
+        let frigate_client = make_frigate_client(local_config(base_url)).unwrap();
+
+        let error = frigate_client.stats().await.err().unwrap();
+        assert!(matches!(error, FrigateApiError::Decode(_)), "{error:?}");
+        assert!(!error.is_recoverable());
+    }
+
+    #[tokio::test]
+    async fn invalid_clip_is_not_ready() {
+        let base_url = serve_once("200 OK", b"Not a video file").await;
+        let frigate_client = make_frigate_client(local_config(base_url)).unwrap();
+
+        let error = frigate_client
+            .recording_clip("my_camera", 1.5, 2.5)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FrigateApiError::NotReady(_)), "{error:?}");
+        assert!(error.is_recoverable());
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn oversized_clip_is_too_large(
+        #[values(false, true)] resume_downloads: bool,
+        #[values(false, true)] with_length: bool,
+    ) {
+        const CLIP: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00 and much more of the clip";
+
+        let base_url = serve_once_with_length("200 OK", CLIP, with_length).await;
+        let config = FrigateApiConfig {
+            resume_downloads,
+            max_clip_bytes: Some(16),
+            ..local_config(base_url)
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        // Unique, so that no partial download of another test is resumed
+        let camera_label = format!("oversized_{resume_downloads}_{with_length}");
+        let error = frigate_client
+            .recording_clip(&camera_label, 1.5, 2.5)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FrigateApiError::TooLarge(_)), "{error:?}");
+        assert!(!error.is_recoverable());
+        assert!(!download::partial_download_path(&camera_label, 1.5).exists());
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn oversized_clip_stream_is_too_large(#[values(false, true)] with_length: bool) {
+        const CLIP: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00 and much more of the clip";
+
+        let base_url = serve_once_with_length("200 OK", CLIP, with_length).await;
+        let config = FrigateApiConfig {
+            max_clip_bytes: Some(16),
+            ..local_config(base_url)
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        // Without the length, the limit is only found to be exceeded while streaming
+        let error = match frigate_client
+            .recording_clip_stream("my_camera", 1.5, 2.5)
+            .await
+        {
+            Ok(stream) => {
+                let chunks = stream.unwrap().collect::<Vec<_>>().await;
+                chunks
+                    .into_iter()
+                    .find_map(Result::err)
+                    .unwrap()
+                    .downcast::<FrigateApiError>()
+                    .unwrap()
+            }
+            Err(e) => e,
+        };
+        assert!(matches!(error, FrigateApiError::TooLarge(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn truncated_clip_stream_fails_deep_validation(
+        #[values(false, true)] deep_clip_validation: bool,
+    ) {
+        // The `mdat` box is cut short of its declared size
+        const CLIP: &[u8] = b"\x00\x00\x00\x10ftypisom\x00\x00\x02\x00\x00\x00\x00\x08moov\x00\x00\x00\x20mdat\x07\x07\x07\x07";
+
+        let base_url = serve_once("200 OK", CLIP).await;
+        let config = FrigateApiConfig {
+            deep_clip_validation,
+            ..local_config(base_url)
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        let chunks = frigate_client
+            .recording_clip_stream("my_camera", 1.5, 2.5)
+            .await
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let error = chunks.into_iter().find_map(Result::err);
+
+        // The header is valid, so it's only found to be incomplete once all of it is streamed
+        if deep_clip_validation {
+            let error = error.unwrap().downcast::<FrigateApiError>().unwrap();
+            assert!(matches!(error, FrigateApiError::NotReady(_)), "{error:?}");
+        } else {
+            assert!(error.is_none());
+        }
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case(
+        br#"{"success": true, "message": "Starting export of recording.", "export_id": "front_abc123"}"#,
+        Ok("front_abc123".to_string())
+    )]
+    #[case(
+        br#"{"success": true, "message": "Starting export of recording."}"#,
+        Err(false)
+    )]
+    #[case(br#"{"success": false, "message": "Export failed"}"#, Err(true))]
+    async fn start_export_response(
+        #[case] body: &'static [u8],
+        // The export id, or whether the error is recoverable
+        #[case] expected: Result<String, bool>,
+    ) {
+        let base_url = serve_once("200 OK", body).await;
+        let frigate_client = make_frigate_client(local_config(base_url)).unwrap();
+
+        let result = frigate_client.start_export("front", 1.5, 2.5).await;
+        assert_eq!(result.map_err(|e| e.is_recoverable()), expected);
+    }
+
+    #[tokio::test]
+    async fn host_is_resolved_with_host_resolver() {
+        struct CountingResolver(std::sync::atomic::AtomicUsize);
+
+        impl utils::mdns::HostResolver for CountingResolver {
+            fn resolve(&self, hostname: &str) -> anyhow::Result<std::net::IpAddr> {
+                assert_eq!(hostname, "frigate.local");
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(std::net::Ipv4Addr::LOCALHOST.into())
+            }
+        }
+
+        // The url keeps the hostname, which the system can't resolve
+        let base_url = serve_once("200 OK", b"{}").await;
+        let port = base_url.rsplit_once(':').unwrap().1;
+        let resolver = Arc::new(CountingResolver(std::sync::atomic::AtomicUsize::new(0)));
+        let config = FrigateApiConfig {
+            host_resolver: Some(dns::FrigateHostResolver::new(
+                "frigate.local",
+                resolver.clone(),
+            )),
+            ..local_config(format!("http://frigate.local:{port}"))
+        };
+
+        let frigate_client = make_frigate_client(config).unwrap();
+        frigate_client.review_raw("some-id").await.unwrap();
+        assert_eq!(resolver.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_api_is_network_error() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let frigate_client =
+            make_frigate_client(local_config(format!("http://{address}"))).unwrap();
+
+        let error = frigate_client.test_call().await.unwrap_err();
+        assert!(matches!(error, FrigateApiError::Network(_)), "{error:?}");
+        assert!(error.is_recoverable());
+    }
+
+    #[test]
+    fn invalid_proxy_is_invalid_config() {
+        let config = FrigateApiConfig {
+            frigate_api_proxy: Some("not a proxy url".to_string()),
+            ..local_config("http://127.0.0.1:5000".to_string())
+        };
+
+        let error = make_frigate_client(config).err().unwrap();
+        assert!(
+            matches!(error, FrigateApiError::InvalidConfig(_)),
+            "{error:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_sent_from_bind_address() {
+        use tokio::io::AsyncWriteExt;
+
+        // Answers the first request, and reports the address it came from
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (peer_sender, peer_receiver) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, peer) = listener.accept().await.unwrap();
+            peer_sender.send(peer).unwrap();
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+        });
+
+        let bind_address = std::net::IpAddr::from([127, 0, 0, 1]);
+        let config = FrigateApiConfig {
+            bind_address: Some(bind_address),
+            ..local_config(format!("http://{address}"))
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+        let _ = frigate_client.test_call().await;
+
+        assert_eq!(peer_receiver.await.unwrap().ip(), bind_address);
+    }
+
+    #[tokio::test]
+    async fn unavailable_bind_address_is_network_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // A documentation address, which no interface has
+        let config = FrigateApiConfig {
+            bind_address: Some(std::net::IpAddr::from([192, 0, 2, 1])),
+            ..local_config(format!("http://{address}"))
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        let error = frigate_client.test_call().await.unwrap_err();
+        assert!(matches!(error, FrigateApiError::Network(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn proxy_credentials_are_sent_to_proxy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A proxy that records the head of the request it receives, and answers it
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = listener.local_addr().unwrap();
+        let (head_sender, head_receiver) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            head_sender
+                .send(String::from_utf8(request).unwrap())
+                .unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let config = FrigateApiConfig {
+            frigate_api_proxy: Some(format!("http://{proxy_address}")),
+            frigate_api_proxy_auth: Some(ProxyAuth {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+            ..local_config("http://frigate.invalid:5000".to_string())
+        };
+        assert!(!format!("{config:?}").contains("pass\""));
+
+        let frigate_client = make_frigate_client(config).unwrap();
+        // The proxy's empty answer isn't a valid response, but the request has been sent through it by then
+        let _ = frigate_client.test_call().await;
+
+        let head = head_receiver.await.unwrap().to_lowercase();
+        // base64 of `user:pass`
+        assert!(
+            head.contains("proxy-authorization: basic dxnlcjpwyxnz"),
+            "{head}"
+        );
+    }
+
+    /// Answers the requests in order with the given responses, each being the status line and
+    /// the headers after it, with an empty json body. Returns the base url to reach it, and
+    /// the received requests, each being its head and body.
+    fn serve_in_order(
+        responses: Vec<&'static str>,
+    ) -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        let (request_sender, request_receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                let head_end = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break i + 4;
+                    }
+                };
+                let head = String::from_utf8(request[..head_end].to_vec())
+                    .unwrap()
+                    .to_lowercase();
+                let body_length = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length: "))
+                    .map_or(0, |l| l.trim().parse::<usize>().unwrap());
+                while request.len() < head_end + body_length {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                request_sender
+                    .send(String::from_utf8(request).unwrap())
+                    .unwrap();
+
+                let body = r#"{"last24Hours": {}}"#;
+                let head = format!(
+                    "HTTP/1.1 {response}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+        });
+        (format!("http://{address}"), request_receiver)
+    }
+
+    #[tokio::test]
+    async fn login_session_is_used_and_renewed_once_expired() {
+        let (base_url, mut requests) = serve_in_order(vec![
+            "200 OK\r\nSet-Cookie: frigate_token=first; Path=/; HttpOnly",
+            "200 OK",
+            // The session expired
+            "401 Unauthorized",
+            "200 OK\r\nSet-Cookie: frigate_token=second; Path=/; HttpOnly",
+            "200 OK",
+        ]);
+        let config = FrigateApiConfig {
+            frigate_login: Some(FrigateLogin {
+                username: "user".to_string(),
+                password: "frigate-secret".to_string(),
+            }),
+            ..local_config(base_url)
+        };
+        assert!(!format!("{config:?}").contains("frigate-secret"));
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        frigate_client.test_call().await.unwrap();
+        frigate_client.test_call().await.unwrap();
+
+        let mut next_request = || requests.try_recv().unwrap();
+        let login = next_request();
+        assert!(login.starts_with("POST /api/login "), "{login}");
+        assert!(
+            login.ends_with(r#"{"password":"frigate-secret","user":"user"}"#),
+            "{login}"
+        );
+        for expected_cookie in ["first", "first"] {
+            let request = next_request().to_lowercase();
+            assert!(request.starts_with("get /api/review/summary "), "{request}");
+            assert!(
+                request.contains(&format!("cookie: frigate_token={expected_cookie}\r\n")),
+                "{request}"
+            );
+        }
+        assert!(next_request().starts_with("POST /api/login "));
+        let request = next_request().to_lowercase();
+        assert!(
+            request.contains("cookie: frigate_token=second\r\n"),
+            "{request}"
+        );
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejected_login_is_unauthorized() {
+        let (base_url, _requests) = serve_in_order(vec!["401 Unauthorized"]);
+        let config = FrigateApiConfig {
+            frigate_login: Some(FrigateLogin {
+                username: "user".to_string(),
+                password: "wrong".to_string(),
+            }),
+            ..local_config(base_url)
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+
+        let error = frigate_client.test_call().await.unwrap_err();
+        assert_eq!(error, FrigateApiError::Unauthorized { status: 401 });
+        assert!(!error.is_recoverable());
+    }
+
     #[tokio::test]
     #[rstest]
     #[trace]
@@ -189,8 +1324,7 @@ mod tests {
     async fn test_call(base_url: String) {
         let config = FrigateApiConfig {
             frigate_api_base_url: base_url,
-            frigate_api_proxy: None,
-            delay_after_startup: std::time::Duration::ZERO,
+            ..Default::default()
         };
         let frigate_client = make_frigate_client(config).unwrap();
         frigate_client.test_call().await.unwrap();
@@ -205,8 +1339,7 @@ mod tests {
 
         let config = FrigateApiConfig {
             frigate_api_base_url: base_url,
-            frigate_api_proxy: None,
-            delay_after_startup: std::time::Duration::ZERO,
+            ..Default::default()
         };
         let frigate_client = make_frigate_client(config).unwrap();
         println!(
@@ -222,8 +1355,7 @@ mod tests {
     async fn stats(base_url: String) {
         let config = FrigateApiConfig {
             frigate_api_base_url: base_url,
-            frigate_api_proxy: None,
-            delay_after_startup: std::time::Duration::ZERO,
+            ..Default::default()
         };
         let frigate_client = make_frigate_client(config).unwrap();
         let stats = frigate_client.stats().await.unwrap();
@@ -241,8 +1373,7 @@ mod tests {
 
         let config = FrigateApiConfig {
             frigate_api_base_url: base_url,
-            frigate_api_proxy: None,
-            delay_after_startup: std::time::Duration::ZERO,
+            ..Default::default()
         };
         let frigate_client = make_frigate_client(config).unwrap();
         let mov = frigate_client
@@ -253,4 +1384,46 @@ mod tests {
 
         std::fs::write("test.mp4", mov).unwrap();
     }
+
+    #[tokio::test]
+    #[rstest]
+    #[trace]
+    #[ignore = "If you want to run this, set the fixture url, set the parameters then run it"]
+    async fn event_snapshot(base_url: String) {
+        let event_id = "1744534706.323662-abcdefg";
+
+        let config = FrigateApiConfig {
+            frigate_api_base_url: base_url,
+            ..Default::default()
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+        let jpg = frigate_client
+            .event_snapshot(event_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        std::fs::write("test.jpg", jpg).unwrap();
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[trace]
+    #[ignore = "If you want to run this, set the fixture url, set the parameters then run it"]
+    async fn latest_snapshot(base_url: String) {
+        let camera_label = "CameraLabel";
+
+        let config = FrigateApiConfig {
+            frigate_api_base_url: base_url,
+            ..Default::default()
+        };
+        let frigate_client = make_frigate_client(config).unwrap();
+        let jpg = frigate_client
+            .latest_snapshot(camera_label)
+            .await
+            .unwrap()
+            .unwrap();
+
+        std::fs::write("latest.jpg", jpg).unwrap();
+    }
 }
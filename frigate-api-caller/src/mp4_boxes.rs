@@ -0,0 +1,196 @@
+/// The size and type of a box, which is followed by a 64-bit size if its 32-bit size is 1
+const BOX_HEADER_SIZE: usize = 8;
+const LARGE_BOX_HEADER_SIZE: usize = 16;
+
+/// Scans the top-level boxes of an mp4 file as its data arrives, to tell whether it's complete. A file that was cut
+/// short, e.g., by Frigate serving a recording that's still being written, can have a valid header, but lacks
+/// the `moov` box with its metadata or the `mdat` box with its media, or has its last box run past the data.
+/// Only the box headers are read, and the rest is skipped.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Default)]
+pub(crate) struct Mp4BoxScanner {
+    /// The number of bytes fed so far
+    fed: u64,
+    /// Where the next top-level box starts
+    next_box: u64,
+    /// The header of the next box as far as it's fed, as it can be split between chunks
+    header: Vec<u8>,
+    has_moov: bool,
+    has_mdat: bool,
+    /// A box with size 0 extends to the end of the file, so there are no boxes after it
+    last_box_reached: bool,
+    malformed: bool,
+}
+
+impl Mp4BoxScanner {
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() && !self.malformed && !self.last_box_reached {
+            let skipped = if self.fed < self.next_box {
+                // Within the body of a box
+                usize::try_from(self.next_box - self.fed).map_or(data.len(), |s| s.min(data.len()))
+            } else {
+                let header_size = self.header_size();
+                let taken = (header_size - self.header.len()).min(data.len());
+                self.header.extend_from_slice(&data[..taken]);
+                taken
+            };
+            self.fed += skipped as u64;
+            data = &data[skipped..];
+
+            if !self.header.is_empty() && self.header.len() == self.header_size() {
+                self.read_header();
+            }
+        }
+
+        // Whatever is left is after the last box, or isn't worth reading
+        self.fed += data.len() as u64;
+    }
+
+    /// Whether the data fed is a complete mp4 file
+    pub fn is_complete(&self) -> bool {
+        !self.malformed
+            && self.has_moov
+            && self.has_mdat
+            && self.header.is_empty()
+            && (self.last_box_reached || self.fed == self.next_box)
+    }
+
+    /// The size of the header of the next box, which is only known to be large once its 32-bit size is read
+    fn header_size(&self) -> usize {
+        if self.header.len() >= BOX_HEADER_SIZE && self.header[..4] == [0, 0, 0, 1] {
+            LARGE_BOX_HEADER_SIZE
+        } else {
+            BOX_HEADER_SIZE
+        }
+    }
+
+    fn read_header(&mut self) {
+        let header_size = self.header.len() as u64;
+        let box_start = self.fed - header_size;
+
+        match &self.header[4..8] {
+            b"moov" => self.has_moov = true,
+            b"mdat" => self.has_mdat = true,
+            _ => (),
+        }
+
+        let size = match u32::from_be_bytes(self.header[..4].try_into().expect("Must be 4 bytes")) {
+            0 => {
+                self.last_box_reached = true;
+                None
+            }
+            1 => Some(u64::from_be_bytes(
+                self.header[8..16].try_into().expect("Must be 8 bytes"),
+            )),
+            size => Some(u64::from(size)),
+        };
+        self.header.clear();
+
+        if let Some(size) = size {
+            match box_start.checked_add(size) {
+                Some(next_box) if size >= header_size => self.next_box = next_box,
+                _ => self.malformed = true,
+            }
+        }
+    }
+}
+
+/// Whether the clip is a complete mp4 file, see [`Mp4BoxScanner`]
+pub(crate) fn is_complete_mp4(data: &[u8]) -> bool {
+    let mut scanner = Mp4BoxScanner::default();
+    scanner.feed(data);
+    scanner.is_complete()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn mp4_box(box_type: [u8; 4], body: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(BOX_HEADER_SIZE + body.len()).unwrap();
+        [&size.to_be_bytes(), box_type.as_slice(), body].concat()
+    }
+
+    /// The boxes a minimal mp4 file has at its top level
+    fn minimal_mp4() -> Vec<u8> {
+        [
+            mp4_box(*b"ftyp", b"isom\x00\x00\x02\x00isomiso2mp41"),
+            mp4_box(*b"moov", &mp4_box(*b"mvhd", &[0; 100])),
+            mp4_box(*b"mdat", &[7; 1000]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn minimal_mp4_is_complete() {
+        let clip = minimal_mp4();
+        assert!(is_complete_mp4(&clip));
+
+        // However it's split into chunks, e.g., when it's streamed
+        for chunk_size in [1, 3, 8, 13, 100] {
+            let mut scanner = Mp4BoxScanner::default();
+            for chunk in clip.chunks(chunk_size) {
+                scanner.feed(chunk);
+            }
+            assert!(scanner.is_complete(), "Chunk size: {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn boxes_with_large_size_or_to_the_end_are_complete() {
+        let large_mdat = [
+            1_u32.to_be_bytes().as_slice(),
+            b"mdat",
+            &(16_u64 + 10).to_be_bytes(),
+            &[7; 10],
+        ]
+        .concat();
+        let clip = [
+            mp4_box(*b"ftyp", b"isom"),
+            mp4_box(*b"moov", &[0; 10]),
+            large_mdat,
+        ]
+        .concat();
+        assert!(is_complete_mp4(&clip));
+
+        let mdat_to_the_end = [0_u32.to_be_bytes().as_slice(), b"mdat", &[7; 10]].concat();
+        let clip = [
+            mp4_box(*b"ftyp", b"isom"),
+            mp4_box(*b"moov", &[0; 10]),
+            mdat_to_the_end,
+        ]
+        .concat();
+        assert!(is_complete_mp4(&clip));
+    }
+
+    #[rstest]
+    // Cut within the media, within the header of `mdat`, within the header of `moov`, and with nothing at all
+    #[case(minimal_mp4().len() - 1)]
+    #[case(minimal_mp4().len() - 1000 - 4)]
+    #[case(32)]
+    #[case(0)]
+    fn truncated_mp4_is_incomplete(#[case] length: usize) {
+        assert!(!is_complete_mp4(&minimal_mp4()[..length]));
+    }
+
+    #[test]
+    fn mp4_without_moov_or_mdat_is_incomplete() {
+        let without_moov = [mp4_box(*b"ftyp", b"isom"), mp4_box(*b"mdat", &[7; 10])].concat();
+        assert!(!is_complete_mp4(&without_moov));
+
+        let without_mdat = [mp4_box(*b"ftyp", b"isom"), mp4_box(*b"moov", &[0; 10])].concat();
+        assert!(!is_complete_mp4(&without_mdat));
+    }
+
+    #[test]
+    fn box_smaller_than_its_header_is_malformed() {
+        let clip = [
+            mp4_box(*b"ftyp", b"isom"),
+            [4_u32.to_be_bytes().as_slice(), b"moov"].concat(),
+            mp4_box(*b"mdat", &[7; 10]),
+        ]
+        .concat();
+        assert!(!is_complete_mp4(&clip));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{config::FrigateLogin, error::FrigateApiError};
+use reqwest::header::{HeaderValue, SET_COOKIE};
+use tokio::sync::Mutex;
+
+/// A session with Frigate's built-in authentication, which is the cookie that logging in returns.
+/// Logging in happens on first use, and again whenever the session is expired.
+pub struct FrigateSession {
+    login: FrigateLogin,
+    cookie: Mutex<Option<HeaderValue>>,
+}
+
+impl FrigateSession {
+    pub fn new(login: FrigateLogin) -> Self {
+        Self {
+            login,
+            cookie: Mutex::new(None),
+        }
+    }
+
+    /// The cookie of the current session, after logging in if there's none.
+    /// Concurrent callers wait for the same login instead of each logging in.
+    pub async fn cookie(
+        &self,
+        client: &reqwest::Client,
+        api_root: &str,
+    ) -> Result<HeaderValue, FrigateApiError> {
+        let mut cookie = self.cookie.lock().await;
+        if let Some(cookie) = cookie.as_ref() {
+            return Ok(cookie.clone());
+        }
+
+        let new_cookie = login(client, api_root, &self.login).await?;
+        *cookie = Some(new_cookie.clone());
+        Ok(new_cookie)
+    }
+
+    /// Forgets the session, so that the next call logs in again. If another caller already
+    /// replaced `rejected` with a new session, that one is kept.
+    pub async fn expire(&self, rejected: &HeaderValue) {
+        let mut cookie = self.cookie.lock().await;
+        if cookie.as_ref() == Some(rejected) {
+            *cookie = None;
+        }
+    }
+}
+
+/// Logs in, and returns the session cookie set by the response, as it's sent back in the `Cookie` header
+async fn login(
+    client: &reqwest::Client,
+    api_root: &str,
+    login: &FrigateLogin,
+) -> Result<HeaderValue, FrigateApiError> {
+    tracing::debug!("Logging in to Frigate as user `{}`", login.username);
+
+    let response = client
+        .post(format!("{api_root}/login"))
+        .json(&serde_json::json!({
+            "user": login.username,
+            "password": login.password,
+        }))
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(FrigateApiError::from_status(status, body));
+    }
+
+    // The cookie's attributes, like its expiry, are only meant for the client that stores it
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .find(|v| {
+            v.split_once('=')
+                .is_some_and(|(_, value)| !value.is_empty())
+        })
+        .and_then(|v| HeaderValue::from_str(v.trim()).ok())
+        .ok_or_else(|| {
+            FrigateApiError::Decode(
+                "Logging in to Frigate succeeded without returning a session cookie".to_string(),
+            )
+        })
+}
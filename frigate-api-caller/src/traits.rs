@@ -1,20 +1,57 @@
-use crate::json::{review::Review, stats::StatsProps};
+use crate::error::FrigateApiError;
+use crate::json::{
+    event::Event, export::Export, frigate_config::RetainMode, review::Review, stats::StatsProps,
+};
 use async_trait::async_trait;
+use futures::{StreamExt, stream::BoxStream};
+use std::collections::HashMap;
+
+/// A clip that's returned in chunks while it's being downloaded
+pub type ClipStream = BoxStream<'static, anyhow::Result<bytes::Bytes>>;
 
 #[async_trait]
 pub trait FrigateApi: Send + Sync {
     /// Attempt a call to the API that only tests whether the API is healthy
     #[must_use]
-    async fn test_call(&self) -> anyhow::Result<()>;
+    async fn test_call(&self) -> Result<(), FrigateApiError>;
 
     /// Returns review information as json
     /// https://docs.frigate.video/integrations/api/get-review-review-review-id-get
     /// https://demo.frigate.video/api/review/:review_id
     #[must_use]
-    async fn review(&self, id: &str) -> anyhow::Result<Review>;
+    async fn review(&self, id: &str) -> Result<Review, FrigateApiError>;
 
+    /// Returns the review exactly as Frigate returned it, including the fields that `Review` doesn't have
     #[must_use]
-    async fn stats(&self) -> anyhow::Result<Box<dyn StatsProps>>;
+    async fn review_raw(&self, id: &str) -> Result<serde_json::Value, FrigateApiError>;
+
+    /// Returns all the events that started within [after,before], for the given cameras, or all cameras if empty
+    /// <https://docs.frigate.video/integrations/api/events-events-get>
+    #[must_use]
+    async fn events(
+        &self,
+        after: f64,
+        before: f64,
+        cameras: &[String],
+    ) -> Result<Vec<Event>, FrigateApiError>;
+
+    #[must_use]
+    async fn stats(&self) -> Result<Box<dyn StatsProps>, FrigateApiError>;
+
+    /// Returns how long recordings of the given camera are kept for, based on Frigate's config.
+    /// Ok(None) is returned if the retention of that camera could not be determined.
+    /// <https://docs.frigate.video/integrations/api/config-config-get>
+    #[must_use]
+    async fn recording_retention(
+        &self,
+        camera_label: &str,
+    ) -> Result<Option<std::time::Duration>, FrigateApiError>;
+
+    /// Returns the mode recordings are retained with for every camera that keeps recordings, based on Frigate's config.
+    /// <https://docs.frigate.video/integrations/api/config-config-get>
+    #[must_use]
+    async fn recordings_retain_modes(&self)
+    -> Result<HashMap<String, RetainMode>, FrigateApiError>;
 
     /// Returns MP4 clip as raw data
     /// Ok(None) is returned if the request is successful, but the video file is empty (zero bytes).
@@ -26,5 +63,57 @@ pub trait FrigateApi: Send + Sync {
         camera_label: &str,
         start_ts: f64,
         end_ts: f64,
-    ) -> anyhow::Result<Option<Vec<u8>>>;
+    ) -> Result<Option<Vec<u8>>, FrigateApiError>;
+
+    /// Like `recording_clip`, but the clip is returned while it's being downloaded. The first chunk holds
+    /// at least the header of the clip, which is validated. By default, the whole clip is downloaded first.
+    #[must_use]
+    async fn recording_clip_stream(
+        &self,
+        camera_label: &str,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<Option<ClipStream>, FrigateApiError> {
+        let clip = self.recording_clip(camera_label, start_ts, end_ts).await?;
+        Ok(clip.map(|clip| futures::stream::once(async move { Ok(clip.into()) }).boxed()))
+    }
+
+    /// Starts exporting the recording of the given camera within [start,end] to a file, which Frigate does
+    /// in the background. Returns the id of the export, to follow it with `export`.
+    /// <https://docs.frigate.video/integrations/api/export-recording-export-camera-name-start-start-time-end-end-time-post>
+    #[must_use]
+    async fn start_export(
+        &self,
+        camera_label: &str,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<String, FrigateApiError>;
+
+    /// Returns the export with the given id, which is done once it's no longer in progress
+    /// <https://docs.frigate.video/integrations/api/get-export-exports-export-id-get>
+    #[must_use]
+    async fn export(&self, export_id: &str) -> Result<Export, FrigateApiError>;
+
+    /// Returns the file of a finished export as raw data, validated like `recording_clip`.
+    /// Ok(None) is returned if the file is empty (zero bytes).
+    #[must_use]
+    async fn export_clip(&self, export: &Export) -> Result<Option<Vec<u8>>, FrigateApiError>;
+
+    /// Deletes the export with the given id, with its file
+    /// <https://docs.frigate.video/integrations/api/export-delete-export-event-id-delete>
+    #[must_use]
+    async fn delete_export(&self, export_id: &str) -> Result<(), FrigateApiError>;
+
+    /// Returns the JPEG snapshot of an event (a detection), with its bounding box drawn
+    /// Ok(None) is returned if the event has no snapshot.
+    /// <https://docs.frigate.video/integrations/api/event-snapshot-events-event-id-snapshot-jpg-get>
+    #[must_use]
+    async fn event_snapshot(&self, event_id: &str) -> Result<Option<Vec<u8>>, FrigateApiError>;
+
+    /// Returns the latest frame of the given camera as JPEG
+    /// Ok(None) is returned if the camera has no frame yet.
+    /// <https://docs.frigate.video/integrations/api/latest-frame-camera-name-latest-extension-get>
+    #[must_use]
+    async fn latest_snapshot(&self, camera_label: &str)
+    -> Result<Option<Vec<u8>>, FrigateApiError>;
 }
@@ -1,4 +1,5 @@
 mod log_style;
+mod otlp;
 mod tracing_utils;
 mod utils;
 
@@ -16,12 +17,25 @@ use log_style::{LogStyleParseError, get_log_style_from_env};
 
 pub use log;
 pub use log_style::{LogStyle, TextColoring};
+pub use otlp::{OTLP_ENDPOINT_ENV_VAR_NAME, shutdown_tracing};
 pub use tracing_utils::{spawn_in_current_span, spawn_in_span};
 pub use utils::{GetFromEnvError, ValueOrEnvVar, get_from_env};
 
 /// Send log output to the terminal.
 pub fn init_logging() {
-    init_logging_generic(default_writer_settings(), no_writer_settings());
+    init_logging_with_level(None);
+}
+
+/// Send log output to the terminal, at the given level unless the filter env var sets one.
+/// Without a level, [`DEFAULT_LOG_LEVEL`] is used.
+pub fn init_logging_with_level(log_level: Option<LevelFilter>) {
+    init_logging_generic(
+        WriterSettings {
+            default_level: log_level.unwrap_or(DEFAULT_LOG_LEVEL),
+            ..default_writer_settings()
+        },
+        no_writer_settings(),
+    );
 }
 
 /// Send log output to the specified [Write] instance, log lines are separated by '\n'
@@ -32,7 +46,8 @@ pub fn init_logging_to(file: impl Write + Send + 'static, is_terminal: bool) {
         WriterSettings {
             make_writer: write_to_make_writer(file),
             is_terminal,
-            filter: ValueOrEnvVar::EnvVar("RUST_LOG".into()),
+            filter: filter_env_var(),
+            default_level: DEFAULT_LOG_LEVEL,
             log_style: ValueOrEnvVar::EnvVar(LOG_STYLE_ENV_VAR_NAME.into()),
         },
         no_writer_settings(),
@@ -47,7 +62,8 @@ pub fn default_writer_settings() -> WriterSettings<fn() -> std::io::Stderr> {
         // to a file etc).
         is_terminal: std::io::stderr().is_terminal(),
         // Use the default env var for filtering.
-        filter: ValueOrEnvVar::EnvVar("RUST_LOG".into()),
+        filter: filter_env_var(),
+        default_level: DEFAULT_LOG_LEVEL,
         // Use the default env var for style.
         log_style: ValueOrEnvVar::EnvVar(LOG_STYLE_ENV_VAR_NAME.into()),
     }
@@ -61,6 +77,17 @@ pub fn write_to_make_writer(
 }
 
 static LOG_STYLE_ENV_VAR_NAME: &str = "VIDEO_SYNC_LOG_STYLE";
+
+/// Filter directives in the format of `RUST_LOG`, e.g. `warn,sync_system=debug,rumqttc=error`.
+/// If it isn't set, `RUST_LOG` is used.
+pub static LOG_FILTER_ENV_VAR_NAME: &str = "SNAPSYNC_LOG";
+
+/// The level of everything that the filter directives don't set a level for
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::INFO;
+/// Targets that log secrets at the trace level, which are logged at most at the debug level.
+/// suppaftp traces every command it sends, including the password of the FTP login.
+const SECRET_TRACING_TARGETS: &[&str] = &["suppaftp"];
+
 static DEFAULT_LOG_STYLE: LogStyle = LogStyle::Text(TextColoring::Auto);
 
 static INITIALIZE_LOGGER_ONCE_FLAG: std::sync::Once = std::sync::Once::new();
@@ -69,11 +96,15 @@ pub struct WriterSettings<MW> {
     pub make_writer: MW,
     pub is_terminal: bool,
     pub filter: ValueOrEnvVar<String>,
+    /// Applies unless the filter has a directive with a level alone
+    pub default_level: LevelFilter,
     pub log_style: ValueOrEnvVar<LogStyle>,
 }
 
 /// Generic version of init_logging that allows to have an auxiliary writer with its own settings
 /// for filtering and log style.
+///
+/// Spans are additionally exported over OTLP if the `VIDEO_SYNC_OTLP_ENDPOINT` env var is set.
 pub fn init_logging_generic<MW1, MW2>(
     main_writer_settings: WriterSettings<MW1>,
     aux_writer_settings: Option<WriterSettings<MW2>>,
@@ -85,10 +116,15 @@ pub fn init_logging_generic<MW1, MW2>(
         let mut errors = Vec::new();
         let main_layer = make_layer(main_writer_settings, &mut errors);
         let aux_layer = aux_writer_settings.map(|settings| make_layer(settings, &mut errors));
+        let otlp_layer = otlp::make_otlp_layer(
+            &ValueOrEnvVar::EnvVar(OTLP_ENDPOINT_ENV_VAR_NAME.into()),
+            &mut errors,
+        );
 
         Registry::default()
             .with(main_layer)
             .with(aux_layer)
+            .with(otlp_layer)
             // This basically calls tracing::subscriber::set_global_default on self and then
             // initializes a 'log' compatibility layer, so that 'log' macros continue to work
             // (this requires the "tracing-log" feature to be enabled, but it is enabled by default).
@@ -113,7 +149,11 @@ where
     MW: for<'a> MakeWriter<'a> + Send + Sync + 'static,
     S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
 {
-    let filter = make_env_filter(writer_settings.filter, errors);
+    let filter = make_env_filter(
+        writer_settings.filter,
+        writer_settings.default_level,
+        errors,
+    );
     let log_style = get_log_style(&writer_settings.log_style, errors);
 
     make_layer_impl(
@@ -176,11 +216,20 @@ fn get_log_style_impl(
     }
 }
 
+fn filter_env_var() -> ValueOrEnvVar<String> {
+    if std::env::var_os(LOG_FILTER_ENV_VAR_NAME).is_some() {
+        ValueOrEnvVar::EnvVar(LOG_FILTER_ENV_VAR_NAME.into())
+    } else {
+        ValueOrEnvVar::EnvVar("RUST_LOG".into())
+    }
+}
+
 fn make_env_filter(
     filter_str: ValueOrEnvVar<String>,
+    default_level: LevelFilter,
     errors: &mut Vec<InternalLogInitError>,
 ) -> EnvFilter {
-    let result_opt = match make_env_filter_impl(filter_str) {
+    let result_opt = match make_env_filter_impl(filter_str, default_level) {
         Ok(filter) => Some(filter),
         Err(err) => {
             errors.push(err);
@@ -188,19 +237,41 @@ fn make_env_filter(
         }
     };
 
-    result_opt.unwrap_or_else(|| {
+    let filter = result_opt.unwrap_or_else(|| {
         EnvFilter::builder()
-            .with_default_directive(default_filter_directive())
+            .with_default_directive(default_level.into())
             .parse_lossy("")
-    })
+    });
+
+    without_secret_tracing(filter)
+}
+
+fn without_secret_tracing(filter: EnvFilter) -> EnvFilter {
+    if filter.max_level_hint() != Some(LevelFilter::TRACE) {
+        return filter;
+    }
+
+    SECRET_TRACING_TARGETS
+        .iter()
+        .fold(filter, |filter, target| {
+            filter.add_directive(
+                format!("{target}={}", LevelFilter::DEBUG)
+                    .parse()
+                    .expect("Valid directive"),
+            )
+        })
 }
 
-fn make_env_filter_impl(filter: ValueOrEnvVar<String>) -> Result<EnvFilter, InternalLogInitError> {
+fn make_env_filter_impl(
+    filter: ValueOrEnvVar<String>,
+    default_level: LevelFilter,
+) -> Result<EnvFilter, InternalLogInitError> {
     let filter_directives = match filter {
         ValueOrEnvVar::Value(val) => Some(val),
         ValueOrEnvVar::EnvVar(var_name) => get_from_env(var_name.as_ref())?,
     };
-    let filter_directives = filter_directives.unwrap_or_default();
+    let filter_directives =
+        with_default_level(filter_directives.unwrap_or_default(), default_level);
 
     // Note: here we try to catch errors to later print them to the log with the "error" severity, so that
     // typos in the filter string can be noticed. But not all errors will be caught. E.g. if you set the filter
@@ -209,7 +280,7 @@ fn make_env_filter_impl(filter: ValueOrEnvVar<String>) -> Result<EnvFilter, Inte
     let filter = EnvFilter::builder()
         // Default filter to use if the passed directives are empty (i.e. if the whole string is empty or it contains
         // a list of empty directives, e.g. something like ",,,").
-        .with_default_directive(default_filter_directive())
+        .with_default_directive(default_level.into())
         .parse(&filter_directives)
         .map_err(|err| InternalLogInitError::FilterDirectivesParseError {
             directives: filter_directives,
@@ -219,9 +290,20 @@ fn make_env_filter_impl(filter: ValueOrEnvVar<String>) -> Result<EnvFilter, Inte
     Ok(filter)
 }
 
-// Note: EnvFilter::from_env also uses ERROR as the default.
-fn default_filter_directive() -> tracing_subscriber::filter::Directive {
-    LevelFilter::ERROR.into()
+/// The directives only refine the default level for some modules, unless one of them is a level alone
+fn with_default_level(filter_directives: String, default_level: LevelFilter) -> String {
+    let has_level = filter_directives
+        .split(',')
+        .map(str::trim)
+        // An empty string is parsed as a level
+        .filter(|d| !d.is_empty())
+        .any(|d| d.parse::<LevelFilter>().is_ok());
+
+    if has_level {
+        filter_directives
+    } else {
+        format!("{default_level},{filter_directives}")
+    }
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -241,6 +323,12 @@ enum InternalLogInitError {
         directives: String,
         error: tracing_subscriber::filter::ParseError,
     },
+
+    #[error("Error creating the OTLP exporter for endpoint '{endpoint}': {error}")]
+    OtlpExporterBuildError {
+        endpoint: String,
+        error: opentelemetry_otlp::ExporterBuildError,
+    },
 }
 
 fn can_use_coloring() -> bool {
@@ -277,4 +365,83 @@ mod tests {
         init_logging();
         init_logging();
     }
+
+    /// Logs a message at every level from every module, through a layer with the given filter,
+    /// and returns the messages that passed it
+    fn log_through_filter(filter: &str, default_level: LevelFilter) -> Vec<String> {
+        let output = std::sync::Arc::new(Mutex::new(Vec::new()));
+
+        #[derive(Clone)]
+        struct CapturingWriter(std::sync::Arc<Mutex<Vec<u8>>>);
+
+        impl Write for CapturingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut errors = Vec::new();
+        let layer = make_layer(
+            WriterSettings {
+                make_writer: write_to_make_writer(CapturingWriter(output.clone())),
+                is_terminal: false,
+                filter: ValueOrEnvVar::Value(filter.to_string()),
+                default_level,
+                log_style: ValueOrEnvVar::Value(LogStyle::Json),
+            },
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "{errors:?}");
+
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            tracing::debug!(target: "sync_system", "sync_system debug");
+            tracing::info!(target: "sync_system", "sync_system info");
+            tracing::info!(target: "rumqttc", "rumqttc info");
+            tracing::warn!(target: "rumqttc", "rumqttc warn");
+            tracing::error!(target: "rumqttc", "rumqttc error");
+            tracing::trace!(target: "suppaftp::async_ftp", "suppaftp trace");
+            tracing::debug!(target: "suppaftp::async_ftp", "suppaftp debug");
+        });
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let mut messages = [
+            "sync_system debug",
+            "sync_system info",
+            "rumqttc info",
+            "rumqttc warn",
+            "rumqttc error",
+            "suppaftp trace",
+            "suppaftp debug",
+        ]
+        .into_iter()
+        .filter(|m| output.contains(m))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+        messages.sort();
+        messages
+    }
+
+    #[rstest::rstest]
+    #[case("", LevelFilter::INFO, &["rumqttc error", "rumqttc info", "rumqttc warn", "sync_system info"])]
+    #[case("", LevelFilter::WARN, &["rumqttc error", "rumqttc warn"])]
+    #[case("rumqttc=error", LevelFilter::INFO, &["rumqttc error", "sync_system info"])]
+    #[case("rumqttc=error,sync_system=debug", LevelFilter::WARN, &["rumqttc error", "sync_system debug", "sync_system info"])]
+    // A level alone in the directives takes precedence over the default level
+    #[case("error,sync_system=info", LevelFilter::DEBUG, &["rumqttc error", "sync_system info"])]
+    #[case("off", LevelFilter::INFO, &[])]
+    // Targets that trace secrets aren't traced
+    #[case("trace", LevelFilter::INFO, &["rumqttc error", "rumqttc info", "rumqttc warn", "suppaftp debug", "sync_system debug", "sync_system info"])]
+    #[case("suppaftp=trace", LevelFilter::INFO, &["rumqttc error", "rumqttc info", "rumqttc warn", "suppaftp debug", "sync_system info"])]
+    fn filter_is_applied(
+        #[case] filter: &str,
+        #[case] default_level: LevelFilter,
+        #[case] expected: &[&str],
+    ) {
+        assert_eq!(log_through_filter(filter, default_level), expected);
+    }
 }
@@ -0,0 +1,85 @@
+use std::sync::OnceLock;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing::Subscriber;
+use tracing_subscriber::{EnvFilter, Layer, registry::LookupSpan};
+
+use crate::{InternalLogInitError, ValueOrEnvVar, get_from_env};
+
+pub static OTLP_ENDPOINT_ENV_VAR_NAME: &str = "VIDEO_SYNC_OTLP_ENDPOINT";
+
+const SERVICE_NAME: &str = "frigate-snap-sync";
+
+// The exporter's own http client is instrumented with tracing, so its spans must not be fed back
+// into the exporter.
+const OTLP_FILTER_DIRECTIVES: &str = "info,hyper=off,hyper_util=off,h2=off,reqwest=off,tower=off,opentelemetry=off,opentelemetry_sdk=off,opentelemetry_otlp=off";
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// Create the OpenTelemetry layer that exports spans over OTLP/HTTP, if an endpoint is configured.
+/// The endpoint is used as is, e.g. `http://localhost:4318/v1/traces`.
+pub(crate) fn make_otlp_layer<S>(
+    endpoint: &ValueOrEnvVar<String>,
+    errors: &mut Vec<InternalLogInitError>,
+) -> Option<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: Subscriber + Send + Sync + for<'a> LookupSpan<'a>,
+{
+    match make_otlp_layer_impl(endpoint) {
+        Ok(layer) => layer,
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    }
+}
+
+fn make_otlp_layer_impl<S>(
+    endpoint: &ValueOrEnvVar<String>,
+) -> Result<Option<Box<dyn Layer<S> + Send + Sync>>, InternalLogInitError>
+where
+    S: Subscriber + Send + Sync + for<'a> LookupSpan<'a>,
+{
+    let endpoint = match endpoint {
+        ValueOrEnvVar::Value(val) => Some(val.clone()),
+        ValueOrEnvVar::EnvVar(var_name) => get_from_env(var_name.as_ref())?,
+    };
+    let Some(endpoint) = endpoint.filter(|e| !e.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint.trim())
+        .build()
+        .map_err(|err| InternalLogInitError::OtlpExporterBuildError {
+            endpoint: endpoint.clone(),
+            error: err,
+        })?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    let _ = TRACER_PROVIDER.set(provider);
+
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(EnvFilter::new(OTLP_FILTER_DIRECTIVES))
+        .boxed();
+
+    Ok(Some(layer))
+}
+
+/// Flush the spans that haven't been exported yet and stop the exporter. Does nothing
+/// if OTLP export isn't enabled.
+pub fn shutdown_tracing() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(err) = provider.shutdown() {
+            log::error!("Failed to shut down the OTLP trace exporter: {err}");
+        }
+    }
+}
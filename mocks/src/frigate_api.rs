@@ -1,6 +1,13 @@
 use async_trait::async_trait;
-use frigate_api_caller::json::review::Review;
-use frigate_api_caller::{json::stats::StatsProps, traits::FrigateApi};
+use frigate_api_caller::json::{
+    event::Event, export::Export, frigate_config::RetainMode, review::Review,
+};
+use frigate_api_caller::{
+    FrigateApiError,
+    json::stats::StatsProps,
+    traits::{ClipStream, FrigateApi},
+};
+use std::collections::HashMap;
 
 #[must_use]
 pub fn make_frigate_client_mock() -> MockFrigateApi {
@@ -12,14 +19,45 @@ mockall::mock! {
 
     #[async_trait]
     impl FrigateApi for FrigateApi {
-        async fn test_call(&self) -> anyhow::Result<()>;
-        async fn review(&self, id: &str) -> anyhow::Result<Review>;
-        async fn stats(&self) -> anyhow::Result<Box<dyn StatsProps>>;
+        async fn test_call(&self) -> Result<(), FrigateApiError>;
+        async fn review(&self, id: &str) -> Result<Review, FrigateApiError>;
+        async fn review_raw(&self, id: &str) -> Result<serde_json::Value, FrigateApiError>;
+        async fn events(
+            &self,
+            after: f64,
+            before: f64,
+            cameras: &[String],
+        ) -> Result<Vec<Event>, FrigateApiError>;
+        async fn stats(&self) -> Result<Box<dyn StatsProps>, FrigateApiError>;
+        async fn recording_retention(
+            &self,
+            camera_label: &str,
+        ) -> Result<Option<std::time::Duration>, FrigateApiError>;
+        async fn recordings_retain_modes(&self)
+            -> Result<HashMap<String, RetainMode>, FrigateApiError>;
         async fn recording_clip(
             &self,
             camera_label: &str,
             start_ts: f64,
             end_ts: f64,
-        ) -> anyhow::Result<Option<Vec<u8>>>;
+        ) -> Result<Option<Vec<u8>>, FrigateApiError>;
+        async fn recording_clip_stream(
+            &self,
+            camera_label: &str,
+            start_ts: f64,
+            end_ts: f64,
+        ) -> Result<Option<ClipStream>, FrigateApiError>;
+        async fn start_export(
+            &self,
+            camera_label: &str,
+            start_ts: f64,
+            end_ts: f64,
+        ) -> Result<String, FrigateApiError>;
+        async fn export(&self, export_id: &str) -> Result<Export, FrigateApiError>;
+        async fn export_clip(&self, export: &Export) -> Result<Option<Vec<u8>>, FrigateApiError>;
+        async fn delete_export(&self, export_id: &str) -> Result<(), FrigateApiError>;
+        async fn event_snapshot(&self, event_id: &str) -> Result<Option<Vec<u8>>, FrigateApiError>;
+        async fn latest_snapshot(&self, camera_label: &str)
+            -> Result<Option<Vec<u8>>, FrigateApiError>;
     }
 }
@@ -0,0 +1,60 @@
+/// A command to control the program, received over mqtt on the command topic, if one is set.
+/// Its payload is JSON with the name of the command, and its arguments, if any, e.g.,
+/// `{"command": "sync_review", "id": "1745534741.333822-vsz5s4", "force": false}`.
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum MqttCommand {
+    /// Upload the recording of the review with the given id now
+    SyncReview {
+        id: String,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Publish the number of uploads per camera
+    GetUploadStats,
+    /// Publish the destinations that are failing their checks
+    GetDegradedDestinations,
+}
+
+impl MqttCommand {
+    pub fn from_payload(payload: &[u8]) -> serde_json::Result<Self> {
+        serde_json::from_slice(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        r#"{"command": "sync_review", "id": "abc", "force": true}"#,
+        MqttCommand::SyncReview { id: "abc".to_string(), force: true }
+    )]
+    #[case(
+        r#"{"command": "sync_review", "id": "abc"}"#,
+        MqttCommand::SyncReview { id: "abc".to_string(), force: false }
+    )]
+    #[case(r#"{"command": "get_upload_stats"}"#, MqttCommand::GetUploadStats)]
+    #[case(
+        r#"{"command": "get_degraded_destinations"}"#,
+        MqttCommand::GetDegradedDestinations
+    )]
+    fn commands_are_parsed(#[case] payload: &str, #[case] expected: MqttCommand) {
+        assert_eq!(
+            MqttCommand::from_payload(payload.as_bytes()).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(r#"{"command": "sync_review"}"#)]
+    #[case(r#"{"command": "unknown"}"#)]
+    #[case(r#"{"id": "abc"}"#)]
+    #[case("sync_review")]
+    fn invalid_commands_are_rejected(#[case] payload: &str) {
+        assert!(MqttCommand::from_payload(payload.as_bytes()).is_err());
+    }
+}
@@ -1,35 +1,71 @@
+use command::MqttCommand;
 use config::MqttHandlerConfig;
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
-use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use publication::{Publication, forward_publications};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, Transport};
+use std::sync::Arc;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot, watch,
+};
 use types::CapturedPayloads;
+use utils::{
+    mdns::{HostResolver, MDNS_PREFIX, resolve_host},
+    systemd::{ServiceNotifier, ServiceState},
+};
 
+pub mod broker_url;
+pub mod command;
 pub mod config;
+pub mod publication;
+pub mod replay;
 pub mod types;
 
 pub struct MqttHandler {
     task_handle: Option<tokio::task::JoinHandle<()>>,
     stop_sender: Option<oneshot::Sender<()>>,
+    connection_status: watch::Receiver<bool>,
 }
 
 impl MqttHandler {
-    pub fn new(
+    /// Messages received from `publication_receiver`, if given, are published to the broker.
+    /// Commands received on the command topic of the config, if set, are sent to `command_sender`.
+    /// The service is reported ready to `service_notifier`, if given, once connected to the broker.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
         config: MqttHandlerConfig,
         data_sender: UnboundedSender<CapturedPayloads>,
+        publication_receiver: Option<UnboundedReceiver<Publication>>,
+        command_sender: Option<UnboundedSender<MqttCommand>>,
+        host_resolver: Arc<dyn HostResolver>,
+        service_notifier: Option<Arc<dyn ServiceNotifier>>,
     ) -> anyhow::Result<Self> {
-        let mqtt_options = (&config).try_into()?;
+        let mqtt_options = resolve_mqtt_options(config.clone(), host_resolver.clone()).await?;
         let (stop_sender, stop_receiver) = oneshot::channel();
+        let (connection_status_sender, connection_status) = watch::channel(false);
         let task_handle = tokio::task::spawn(launch_eventloop(
             data_sender,
+            publication_receiver,
+            command_sender,
             mqtt_options,
-            config,
+            Arc::new(config),
+            host_resolver,
+            service_notifier,
+            connection_status_sender,
             stop_receiver,
         ));
         Ok(Self {
             task_handle: Some(task_handle),
             stop_sender: Some(stop_sender),
+            connection_status,
         })
     }
 
+    /// Whether the client is connected to the broker, updated as it connects and disconnects
+    #[must_use]
+    pub fn connection_status(&self) -> watch::Receiver<bool> {
+        self.connection_status.clone()
+    }
+
     /// returns a future that awaits exiting the inner task of mqtt
     pub async fn wait(&mut self) {
         self.task_handle
@@ -48,12 +84,20 @@ impl MqttHandler {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn launch_eventloop(
     data_sender: tokio::sync::mpsc::UnboundedSender<CapturedPayloads>,
+    publication_receiver: Option<UnboundedReceiver<Publication>>,
+    command_sender: Option<UnboundedSender<MqttCommand>>,
     mqtt_options: MqttOptions,
-    config: MqttHandlerConfig,
+    config: Arc<MqttHandlerConfig>,
+    host_resolver: Arc<dyn HostResolver>,
+    service_notifier: Option<Arc<dyn ServiceNotifier>>,
+    connection_status: watch::Sender<bool>,
     mut stop_receiver: oneshot::Receiver<()>,
 ) {
+    let mut reresolution_backoff = ReresolutionBackoff::default();
+
     tracing::info!(
         "Connecting to mqtt server: {}:{}",
         mqtt_options.broker_address().0,
@@ -68,6 +112,18 @@ async fn launch_eventloop(
 
     client.subscribe(topic, QoS::ExactlyOnce).await.unwrap();
 
+    if let Some(command_topic) = &config.mqtt_command_topic {
+        tracing::info!("Subscribing to command topic: {command_topic}");
+        client
+            .subscribe(command_topic, QoS::AtLeastOnce)
+            .await
+            .unwrap();
+    }
+
+    // Publishing is done in a separate task, so that a full request queue doesn't block polling the event loop
+    let publications_task = publication_receiver
+        .map(|receiver| tokio::task::spawn(forward_publications(client.clone(), receiver)));
+
     loop {
         match stop_receiver.try_recv() {
             Ok(()) => break,
@@ -80,20 +136,40 @@ async fn launch_eventloop(
         if let Ok(notification) = eventloop.poll().await {
             if let Event::Incoming(notification) = notification {
                 match notification {
+                    Packet::Publish(publish)
+                        if config.mqtt_command_topic.as_ref() == Some(&publish.topic) =>
+                    {
+                        forward_command(&publish.payload, command_sender.as_ref());
+                    }
+                    Packet::Publish(publish)
+                        if !CapturedPayloads::is_handled_topic(&config, &publish.topic) =>
+                    {
+                        tracing::trace!("Ignoring data with topic: {}", publish.topic);
+                    }
                     Packet::Publish(publish) => {
-                        if let Some(data) = CapturedPayloads::from_publish(
-                            &config,
-                            &publish.topic,
-                            &publish.payload,
-                        ) {
+                        // Awaited in place, so that messages are still delivered in order
+                        if let Some(data) = CapturedPayloads::from_publish_blocking(
+                            config.clone(),
+                            publish.topic.clone(),
+                            publish.payload.clone(),
+                        )
+                        .await
+                        {
                             tracing::debug!("Found relevant data from topic: {}", publish.topic);
                             data_sender.send(data).expect("Sending data message failed");
                         } else {
                             tracing::trace!("Ignoring data with topic: {}", publish.topic);
                         }
                     }
+                    Packet::ConnAck(_) => {
+                        tracing::info!("Connected to mqtt server");
+                        connection_status.send_replace(true);
+                        reresolution_backoff = ReresolutionBackoff::default();
+                        if let Some(notifier) = &service_notifier {
+                            notifier.notify(ServiceState::Ready);
+                        }
+                    }
                     Packet::Connect(_)
-                    | Packet::ConnAck(_)
                     | Packet::PubAck(_)
                     | Packet::PubRec(_)
                     | Packet::PubRel(_)
@@ -108,9 +184,99 @@ async fn launch_eventloop(
                 }
             }
         } else {
+            connection_status.send_replace(false);
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+            // The broker may have a new address by now, e.g., after DHCP reassigned it
+            if config.mqtt_host.starts_with(MDNS_PREFIX)
+                && reresolution_backoff.is_due(tokio::time::Instant::now())
+            {
+                match resolve_mqtt_options(config.as_ref().clone(), host_resolver.clone()).await {
+                    Ok(mqtt_options) => eventloop.mqtt_options = mqtt_options,
+                    Err(e) => tracing::warn!("Resolving the mqtt host failed: {e}"),
+                }
+            }
         }
     }
+
+    if let Some(task) = publications_task {
+        task.abort();
+    }
+}
+
+/// A command that can't be parsed is only logged, since it may come from anyone who can publish to the broker
+fn forward_command(payload: &[u8], command_sender: Option<&UnboundedSender<MqttCommand>>) {
+    let command = match MqttCommand::from_payload(payload) {
+        Ok(command) => command,
+        Err(e) => {
+            tracing::warn!(
+                "Ignoring invalid mqtt command `{}`: {e}",
+                String::from_utf8_lossy(payload)
+            );
+            return;
+        }
+    };
+
+    tracing::info!("Received mqtt command: {command:?}");
+    if let Some(sender) = command_sender {
+        if sender.send(command).is_err() {
+            tracing::error!("Forwarding mqtt command failed, as its receiver is gone");
+        }
+    }
+}
+
+/// How long to wait before resolving the mdns host of the broker again while connecting to it fails.
+/// It doubles every time, up to the max, until connected, so that the network isn't flooded with queries.
+const MDNS_RERESOLUTION_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+const MDNS_RERESOLUTION_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(60);
+
+struct ReresolutionBackoff {
+    backoff: std::time::Duration,
+    next_at: Option<tokio::time::Instant>,
+}
+
+impl Default for ReresolutionBackoff {
+    fn default() -> Self {
+        Self {
+            backoff: MDNS_RERESOLUTION_INITIAL_BACKOFF,
+            next_at: None,
+        }
+    }
+}
+
+impl ReresolutionBackoff {
+    /// Whether to resolve the host again now, which pushes back the next time it's due
+    fn is_due(&mut self, now: tokio::time::Instant) -> bool {
+        if self.next_at.is_some_and(|next_at| now < next_at) {
+            return false;
+        }
+        self.next_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(MDNS_RERESOLUTION_MAX_BACKOFF);
+        true
+    }
+}
+
+/// Makes the options to connect to the broker with on a blocking thread, since resolving its host
+/// waits for the devices on the network to answer
+async fn resolve_mqtt_options(
+    config: MqttHandlerConfig,
+    host_resolver: Arc<dyn HostResolver>,
+) -> anyhow::Result<MqttOptions> {
+    tokio::task::spawn_blocking(move || make_mqtt_options(&config, host_resolver.as_ref())).await?
+}
+
+/// The options to connect to the broker with, after decomposing its host if it's given as a url,
+/// and resolving it if it's given as `mdns:<hostname>`
+fn make_mqtt_options(
+    config: &MqttHandlerConfig,
+    host_resolver: &dyn HostResolver,
+) -> anyhow::Result<MqttOptions> {
+    let config = config.with_broker_url_decomposed()?;
+    let config = MqttHandlerConfig {
+        mqtt_host: resolve_host(&config.mqtt_host, host_resolver)?,
+        ..config
+    };
+    (&config).try_into()
 }
 
 fn set_credentials(
@@ -145,9 +311,137 @@ impl TryFrom<&MqttHandlerConfig> for MqttOptions {
         mqtt_options.set_keep_alive(std::time::Duration::from_secs(
             config.mqtt_keep_alive_seconds,
         ));
+        mqtt_options.set_clean_session(config.mqtt_clean_session);
+        if let Some(inflight) = config.mqtt_inflight {
+            mqtt_options.set_inflight(inflight.get());
+        }
+
+        if config.mqtt_tls {
+            mqtt_options.set_transport(Transport::tls_with_default_config());
+        }
 
         set_credentials(config, &mut mqtt_options)?;
 
         Ok(mqtt_options)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mdns_host_is_resolved() {
+        struct FixedResolver;
+
+        impl HostResolver for FixedResolver {
+            fn resolve(&self, hostname: &str) -> anyhow::Result<std::net::IpAddr> {
+                assert_eq!(hostname, "mosquitto.local");
+                Ok(std::net::Ipv4Addr::new(192, 168, 1, 30).into())
+            }
+        }
+
+        let config = MqttHandlerConfig {
+            mqtt_client_id: "client".to_string(),
+            mqtt_host: "mdns:mosquitto.local".to_string(),
+            mqtt_port: 1883,
+            mqtt_keep_alive_seconds: 5,
+            ..MqttHandlerConfig::default()
+        };
+        let mqtt_options = make_mqtt_options(&config, &FixedResolver).unwrap();
+        assert_eq!(
+            mqtt_options.broker_address(),
+            ("192.168.1.30".to_string(), 1883)
+        );
+
+        // Explicit hosts are used as they are
+        let config = MqttHandlerConfig {
+            mqtt_host: "127.0.0.1".to_string(),
+            ..config
+        };
+        let mqtt_options = make_mqtt_options(&config, &FixedResolver).unwrap();
+        assert_eq!(
+            mqtt_options.broker_address(),
+            ("127.0.0.1".to_string(), 1883)
+        );
+    }
+
+    #[test]
+    fn host_reresolution_is_backed_off_until_connected() {
+        let start = tokio::time::Instant::now();
+        let after = |secs| start + std::time::Duration::from_secs(secs);
+
+        let mut backoff = ReresolutionBackoff::default();
+        assert!(backoff.is_due(start));
+        assert!(!backoff.is_due(start));
+        assert!(backoff.is_due(after(1)));
+        assert!(!backoff.is_due(after(2)));
+        assert!(backoff.is_due(after(3)));
+        assert!(!backoff.is_due(after(6)));
+        assert!(backoff.is_due(after(7)));
+
+        // Up to the max
+        let mut now = after(7);
+        for _ in 0..10 {
+            now += MDNS_RERESOLUTION_MAX_BACKOFF;
+            assert!(backoff.is_due(now));
+        }
+        assert!(!backoff.is_due(now + MDNS_RERESOLUTION_MAX_BACKOFF / 2));
+
+        // Once connected, it starts over
+        let mut backoff = ReresolutionBackoff::default();
+        assert!(backoff.is_due(now));
+        assert!(backoff.is_due(now + MDNS_RERESOLUTION_INITIAL_BACKOFF));
+    }
+
+    #[test]
+    fn commands_are_forwarded() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        forward_command(br#"{"command": "get_upload_stats"}"#, Some(&sender));
+        assert_eq!(receiver.try_recv().unwrap(), MqttCommand::GetUploadStats);
+
+        // Invalid commands are dropped
+        forward_command(b"not a command", Some(&sender));
+        assert!(receiver.try_recv().is_err());
+
+        // Without a receiver, commands are only logged
+        forward_command(br#"{"command": "get_upload_stats"}"#, None);
+    }
+
+    #[test]
+    fn password_is_hidden_in_debug_output() {
+        let config = MqttHandlerConfig {
+            mqtt_username: Some("user".to_string()),
+            mqtt_password: Some("very-secret".to_string()),
+            ..MqttHandlerConfig::default()
+        };
+
+        let debug = format!("{config:?}");
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("very-secret"));
+    }
+
+    #[test]
+    fn session_options_are_set() {
+        let config = MqttHandlerConfig {
+            mqtt_client_id: "client".to_string(),
+            mqtt_host: "127.0.0.1".to_string(),
+            mqtt_port: 1883,
+            mqtt_keep_alive_seconds: 5,
+            ..MqttHandlerConfig::default()
+        };
+        let default_options = MqttOptions::try_from(&config).unwrap();
+        assert!(default_options.clean_session());
+
+        let config = MqttHandlerConfig {
+            mqtt_clean_session: false,
+            mqtt_inflight: Some(std::num::NonZeroU16::new(10).unwrap()),
+            ..config
+        };
+        let options = MqttOptions::try_from(&config).unwrap();
+        assert!(!options.clean_session());
+        assert_eq!(options.inflight(), 10);
+        assert_eq!(options.keep_alive(), std::time::Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,36 @@
+use rumqttc::{AsyncClient, QoS};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// A message to publish to the broker, sent to the handler through its publication channel
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Publication {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+/// Publishes everything received from the channel, until all its senders are dropped.
+/// The client only queues the messages, so they're sent once the event loop is (re)connected.
+pub(crate) async fn forward_publications(
+    client: AsyncClient,
+    mut publication_receiver: UnboundedReceiver<Publication>,
+) {
+    while let Some(publication) = publication_receiver.recv().await {
+        tracing::debug!("Publishing to topic: {}", publication.topic);
+
+        if let Err(e) = client
+            .publish(
+                publication.topic,
+                QoS::AtLeastOnce,
+                publication.retain,
+                publication.payload,
+            )
+            .await
+        {
+            // The event loop is gone, so nothing can be published anymore
+            tracing::error!("Publishing to mqtt failed: {e}");
+            break;
+        }
+    }
+}
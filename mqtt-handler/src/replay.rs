@@ -0,0 +1,125 @@
+use crate::{config::MqttHandlerConfig, types::CapturedPayloads};
+use anyhow::Context;
+use std::path::Path;
+
+/// A message recorded from mqtt, as one line of a replay file, e.g.,
+/// `{"topic": "frigate/front/recordings/state", "payload": "ON"}`
+#[derive(serde::Deserialize)]
+struct RecordedMessage {
+    topic: String,
+    /// Text payloads are given as strings. Any other json, like a review, is replayed as that json,
+    /// so it doesn't have to be escaped. Binary payloads, like snapshots, can't be replayed.
+    payload: serde_json::Value,
+}
+
+impl RecordedMessage {
+    fn payload_bytes(&self) -> bytes::Bytes {
+        match &self.payload {
+            serde_json::Value::String(text) => bytes::Bytes::from(text.clone()),
+            other => bytes::Bytes::from(other.to_string()),
+        }
+    }
+}
+
+/// Reads the messages recorded in the given file, one json per line, in the order they're replayed.
+/// Empty lines are skipped, and so are messages that wouldn't be parsed from mqtt either, like those of other topics.
+pub fn read_replay_file(
+    config: &MqttHandlerConfig,
+    path: &Path,
+) -> anyhow::Result<Vec<CapturedPayloads>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading replay file `{}`", path.display()))?;
+    parse_replay(config, &contents)
+        .with_context(|| format!("Parsing replay file `{}`", path.display()))
+}
+
+fn parse_replay(
+    config: &MqttHandlerConfig,
+    contents: &str,
+) -> anyhow::Result<Vec<CapturedPayloads>> {
+    let mut payloads = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message: RecordedMessage = serde_json::from_str(line)
+            .with_context(|| format!("Line {} is not a recorded message", index + 1))?;
+
+        if let Some(payload) =
+            CapturedPayloads::from_publish(config, &message.topic, &message.payload_bytes())
+        {
+            payloads.push(payload);
+        } else {
+            tracing::warn!(
+                "Skipping recorded message on line {} with topic `{}`, as it's not handled or can't be parsed",
+                index + 1,
+                message.topic
+            );
+        }
+    }
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::reviews::payload::TypeField;
+
+    const REVIEW: &str = r#"{"type": "end", "before": {"id": "1745534741.333822-vsz5s4", "camera": "front", "start_time": 1745534741.333822, "end_time": null, "severity": "alert", "thumb_path": "/media/frigate/clips/review/thumb.webp", "data": {"detections": [], "objects": ["person"], "sub_labels": [], "zones": [], "audio": []}}, "after": {"id": "1745534741.333822-vsz5s4", "camera": "front", "start_time": 1745534741.333822, "end_time": 1745534761.5, "severity": "alert", "thumb_path": "/media/frigate/clips/review/thumb.webp", "data": {"detections": [], "objects": ["person"], "sub_labels": [], "zones": [], "audio": []}}}"#;
+
+    fn config() -> MqttHandlerConfig {
+        MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: "frigate".to_string(),
+            ..MqttHandlerConfig::default()
+        }
+    }
+
+    #[test]
+    fn recorded_messages_are_parsed_in_order() {
+        let contents = [
+            r#"{"topic": "frigate/front/recordings/state", "payload": "ON"}"#.to_string(),
+            String::new(),
+            // Escaped json and plain json are the same
+            format!(
+                r#"{{"topic": "frigate/reviews", "payload": {}}}"#,
+                serde_json::to_string(REVIEW).unwrap()
+            ),
+            format!(r#"{{"topic": "frigate/reviews", "payload": {REVIEW}}}"#),
+            // Not handled, so skipped
+            r#"{"topic": "frigate/stats", "payload": {"cpu": 5}}"#.to_string(),
+        ]
+        .join("\n");
+
+        let payloads = parse_replay(&config(), &contents).unwrap();
+        assert_eq!(payloads.len(), 3);
+
+        let mut payloads = payloads.into_iter();
+        let CapturedPayloads::CameraRecordingsState(state) = payloads.next().unwrap() else {
+            panic!("Expected a recordings state");
+        };
+        assert_eq!(state.camera_label, "front");
+        assert!(state.state);
+
+        for payload in payloads {
+            let CapturedPayloads::Reviews(review) = payload else {
+                panic!("Expected a review");
+            };
+            assert_eq!(review.id(), "1745534741.333822-vsz5s4");
+            assert_eq!(review.type_field(), TypeField::End);
+            assert!(review.end_time().is_some());
+        }
+    }
+
+    #[test]
+    fn invalid_line_is_reported_with_its_number() {
+        let contents = [
+            r#"{"topic": "frigate/front/recordings/state", "payload": "ON"}"#,
+            "not json",
+        ]
+        .join("\n");
+
+        let error = parse_replay(&config(), &contents).unwrap_err();
+        assert!(error.to_string().contains("Line 2"));
+    }
+}
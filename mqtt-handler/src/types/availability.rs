@@ -0,0 +1,88 @@
+use tap::TapOptional;
+
+/// Whether Frigate itself is online, as published on `<prefix>/available`.
+/// Frigate publishes `offline` as its last will, and `online` when it (re)starts.
+#[must_use]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FrigateAvailability {
+    pub online: bool,
+}
+
+impl FrigateAvailability {
+    #[must_use]
+    pub fn from_topic_parts(topic_parts: &[&str], payload: &bytes::Bytes) -> Option<Self> {
+        // <prefix>/available
+        if topic_parts.len() == 2 && topic_parts[1] == "available" {
+            let online = online_offline_from_bytes(payload).tap_none(|| {
+                tracing::error!("Failed to parse availability payload: {:?}", payload);
+            })?;
+            Some(Self { online })
+        } else {
+            None
+        }
+    }
+}
+
+fn online_offline_from_bytes(value: &[u8]) -> Option<bool> {
+    match std::str::from_utf8(value).ok()?.trim() {
+        "online" => Some(true),
+        "offline" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use rstest::rstest;
+    use test_utils::random::{
+        Seed, make_random_alphanumeric_string, make_seedable_rng, random_seed,
+    };
+
+    use super::*;
+
+    #[rstest]
+    #[trace]
+    #[case(b"online".to_vec(), Some(true))]
+    #[trace]
+    #[case(b"offline".to_vec(), Some(false))]
+    #[trace]
+    #[case(b"ON".to_vec(), None)]
+    #[trace]
+    fn frigate_availability(
+        random_seed: Seed,
+        #[case] payload: Vec<u8>,
+        #[case] expected_online: Option<bool>,
+    ) {
+        use crate::{config::MqttHandlerConfig, types::CapturedPayloads};
+
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+
+        let config = MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        };
+
+        let parse_result = CapturedPayloads::from_publish(
+            &config,
+            &format!("{mqtt_topic_prefix}/available"),
+            &Bytes::from_owner(payload),
+        );
+
+        if let Some(expected_online) = expected_online {
+            match parse_result.unwrap() {
+                CapturedPayloads::FrigateAvailability(availability) => assert_eq!(
+                    availability,
+                    FrigateAvailability {
+                        online: expected_online
+                    }
+                ),
+                other => panic!("Unexpected parse result: {other:?}"),
+            }
+        } else {
+            assert!(parse_result.is_none());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod availability;
 pub mod recordings_state;
 pub mod reviews;
 pub mod snapshot;
@@ -8,6 +9,7 @@ mod utils;
 use std::sync::Arc;
 
 use crate::config::MqttHandlerConfig;
+use availability::FrigateAvailability;
 use recordings_state::RecordingsState;
 use reviews::{ReviewProps, Reviews};
 use snapshot::Snapshot;
@@ -20,6 +22,7 @@ pub enum CapturedPayloads {
     CameraSnapshotsState(SnapshotsState),
     Snapshot(Arc<Snapshot>),
     Reviews(Arc<dyn ReviewProps>),
+    FrigateAvailability(FrigateAvailability),
 }
 
 impl CapturedPayloads {
@@ -28,13 +31,13 @@ impl CapturedPayloads {
         topic: &str,
         payload: &bytes::Bytes,
     ) -> Option<Self> {
-        let topic_parts = topic.split('/').collect::<Vec<_>>();
-        if !topic_parts.is_empty() && topic_parts[0] == config.mqtt_frigate_topic_prefix {
-            // Do nothing
-        } else {
+        if !Self::is_handled_topic(config, topic) {
+            tracing::trace!("Ignoring message with unhandled topic: {topic}");
             return None;
         }
 
+        let topic_parts = topic.split('/').collect::<Vec<_>>();
+
         if let Some(o) = SnapshotsState::from_topic_parts(&topic_parts, payload) {
             tracing::debug!("Parsed success: SnapshotsState");
             return Some(Self::CameraSnapshotsState(o));
@@ -55,11 +58,54 @@ impl CapturedPayloads {
             return Some(Self::Reviews(Arc::new(o)));
         }
 
+        if let Some(o) = FrigateAvailability::from_topic_parts(&topic_parts, payload) {
+            tracing::debug!("Parsed success: FrigateAvailability");
+            return Some(Self::FrigateAvailability(o));
+        }
+
         tracing::debug!("Ignoring message with topic: {topic}");
 
         None
     }
 
+    /// Whether the topic is one that any of the payloads is parsed from, looking only at the topic.
+    /// Frigate publishes large payloads, like camera frames, on topics that are never used here,
+    /// so they're rejected before the payload is touched.
+    #[must_use]
+    pub fn is_handled_topic(config: &MqttHandlerConfig, topic: &str) -> bool {
+        let mut topic_parts = topic.split('/');
+        if topic_parts.next() != Some(config.mqtt_frigate_topic_prefix.as_str()) {
+            return false;
+        }
+
+        match (topic_parts.next(), topic_parts.next(), topic_parts.next()) {
+            // <prefix>/reviews and <prefix>/available
+            (Some("reviews" | "available"), None, _)
+            // <prefix>/<camera_name>/{recordings,snapshots}/state
+            | (Some(_), Some("recordings" | "snapshots"), Some("state"))
+            // <prefix>/<camera_name>/<object_name>/snapshot
+            | (Some(_), Some(_), Some("snapshot"))
+            // <prefix>/<camera_name>/snapshot
+            | (Some(_), Some("snapshot"), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Same as `from_publish`, but runs on the blocking thread pool.
+    /// Parsing a snapshot decodes its image, which is CPU-heavy and would otherwise stall the async runtime.
+    pub async fn from_publish_blocking(
+        config: Arc<MqttHandlerConfig>,
+        topic: String,
+        payload: bytes::Bytes,
+    ) -> Option<Self> {
+        tokio::task::spawn_blocking(move || Self::from_publish(&config, &topic, &payload))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Parsing mqtt message failed to complete with error: {e}");
+                None
+            })
+    }
+
     #[must_use]
     pub fn into_recordings_state(self) -> Option<RecordingsState> {
         match self {
@@ -84,3 +130,89 @@ impl CapturedPayloads {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use rstest::rstest;
+    use test_utils::random::{
+        Seed, make_random_alphanumeric_string, make_seedable_rng, random_seed,
+    };
+
+    #[rstest]
+    #[trace]
+    #[case("{prefix}/reviews", true)]
+    #[case("{prefix}/available", true)]
+    #[case("{prefix}/{camera}/recordings/state", true)]
+    #[case("{prefix}/{camera}/snapshots/state", true)]
+    #[case("{prefix}/{camera}/person/snapshot", true)]
+    #[case("{prefix}/{camera}/snapshot", true)]
+    #[case("{prefix}/stats", false)]
+    #[case("{prefix}/events", false)]
+    #[case("{prefix}/reviews/extra", false)]
+    #[case("{prefix}/{camera}/person", false)]
+    #[case("{prefix}/{camera}/motion", false)]
+    #[case("{prefix}/{camera}/detect/state", false)]
+    #[case("{prefix}/{camera}/recordings/set", false)]
+    #[case("other/reviews", false)]
+    #[case("other/{camera}/person/snapshot", false)]
+    #[case("", false)]
+    fn handled_topics(random_seed: Seed, #[case] topic: &str, #[case] expected: bool) {
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+        let config = MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        };
+
+        let camera_name = make_random_alphanumeric_string(&mut rng, 20);
+        let topic = topic
+            .replace("{prefix}", &mqtt_topic_prefix)
+            .replace("{camera}", &camera_name);
+
+        assert_eq!(
+            CapturedPayloads::is_handled_topic(&config, &topic),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[trace]
+    #[case("{prefix}/{camera}/birdseye")]
+    #[case("{prefix}/{camera}/person")]
+    #[case("{prefix}/stats")]
+    #[case("other/reviews")]
+    fn huge_payloads_on_unhandled_topics_are_not_processed(random_seed: Seed, #[case] topic: &str) {
+        // Copying or parsing this payload even once per message would take far longer than the limit below
+        const PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+        const MESSAGE_COUNT: usize = 1000;
+        const TIME_LIMIT: std::time::Duration = std::time::Duration::from_secs(1);
+
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+        let config = MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        };
+
+        let camera_name = make_random_alphanumeric_string(&mut rng, 20);
+        let topic = topic
+            .replace("{prefix}", &mqtt_topic_prefix)
+            .replace("{camera}", &camera_name);
+        let payload = Bytes::from_owner(vec![b'A'; PAYLOAD_SIZE]);
+
+        let start = std::time::Instant::now();
+        for _ in 0..MESSAGE_COUNT {
+            assert!(CapturedPayloads::from_publish(&config, &topic, &payload).is_none());
+        }
+        let elapsed = start.elapsed();
+
+        assert!(
+            elapsed < TIME_LIMIT,
+            "Rejecting {MESSAGE_COUNT} messages took {elapsed:?}"
+        );
+    }
+}
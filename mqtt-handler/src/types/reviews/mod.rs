@@ -24,7 +24,7 @@ impl Reviews {
                 }
             };
 
-            let payload = match serde_json::from_str::<ReviewsPayload>(&payload_str) {
+            let payload = match ReviewsPayload::from_json_lenient(&payload_str) {
                 Ok(p) => p,
                 Err(e) => {
                     tracing::error!("Parsing payload to json failed: `{e}`.");
@@ -54,6 +54,19 @@ pub trait ReviewProps: Send + Sync + Debug {
 
     #[must_use]
     fn type_field(&self) -> payload::TypeField;
+
+    /// The ids of the events (detections) that make up the review
+    #[must_use]
+    fn detections(&self) -> &[String];
+
+    /// The labels of the objects tracked in the review, e.g., "person". Empty for audio-only reviews.
+    #[must_use]
+    fn objects(&self) -> &[String];
+
+    /// Whether objects were tracked or audio was detected in the review,
+    /// or None if that isn't known, e.g., when the data of its payload was missing
+    #[must_use]
+    fn has_activity(&self) -> Option<bool>;
 }
 
 impl ReviewProps for Reviews {
@@ -76,4 +89,29 @@ impl ReviewProps for Reviews {
     fn type_field(&self) -> payload::TypeField {
         self.payload.type_field
     }
+
+    fn detections(&self) -> &[String] {
+        // Detections are added while the review is ongoing, so the latest state has them all
+        self.payload
+            .after
+            .data
+            .as_ref()
+            .map_or(&[], payload::ReviewData::detections)
+    }
+
+    fn objects(&self) -> &[String] {
+        self.payload
+            .after
+            .data
+            .as_ref()
+            .map_or(&[], payload::ReviewData::objects)
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        self.payload
+            .after
+            .data
+            .as_ref()
+            .and_then(payload::ReviewData::has_activity)
+    }
 }
@@ -1,5 +1,8 @@
 #![allow(dead_code)] // Not everything is needed, but we want to parse the whole json as future-proofing
 
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct ReviewsPayload {
     #[serde(rename = "type")]
@@ -8,6 +11,53 @@ pub struct ReviewsPayload {
     pub after: BeforeAfterField,
 }
 
+impl ReviewsPayload {
+    /// Parses the payload, while tolerating changes of a newer Frigate version. Only the type, and the id,
+    /// camera and start time of both states are essential. Other fields that are missing take their defaults,
+    /// and ones that don't parse are ignored with a warning.
+    pub fn from_json_lenient(json: &str) -> serde_json::Result<Self> {
+        let mut payload = serde_json::from_str::<Value>(json)?;
+        for state in ["before", "after"] {
+            if let Some(fields) = payload.get_mut(state).and_then(Value::as_object_mut) {
+                remove_malformed_fields(state, fields);
+            }
+        }
+        serde_json::from_value(payload)
+    }
+}
+
+fn remove_malformed_fields(state: &str, fields: &mut Map<String, Value>) {
+    remove_if_malformed::<Option<f64>>(state, fields, "end_time");
+    remove_if_malformed::<String>(state, fields, "severity");
+    remove_if_malformed::<String>(state, fields, "thumb_path");
+
+    if let Some(data) = fields.get_mut("data").and_then(Value::as_object_mut) {
+        let parent = format!("{state}.data");
+        for field in ["detections", "objects", "zones"] {
+            remove_if_malformed::<Vec<String>>(&parent, data, field);
+        }
+        for field in ["sub_labels", "audio"] {
+            remove_if_malformed::<Vec<Value>>(&parent, data, field);
+        }
+    } else {
+        remove_if_malformed::<ReviewData>(state, fields, "data");
+    }
+}
+
+fn remove_if_malformed<T: DeserializeOwned>(
+    parent: &str,
+    fields: &mut Map<String, Value>,
+    field: &str,
+) {
+    let Some(value) = fields.get(field) else {
+        return;
+    };
+    if let Err(e) = T::deserialize(value) {
+        tracing::warn!("Ignoring malformed field `{parent}.{field}` of a review payload: {e}");
+        fields.remove(field);
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, serde::Deserialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum TypeField {
@@ -22,23 +72,54 @@ pub struct BeforeAfterField {
     pub camera: String,
     pub start_time: f64,
     pub end_time: Option<f64>,
+    #[serde(default)]
     pub severity: String,
+    #[serde(default)]
     pub thumb_path: String,
-    pub data: ReviewData,
+    /// None if the payload has none, or it's malformed, in which case what was detected in the review is unknown
+    #[serde(default)]
+    pub data: Option<ReviewData>,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct ReviewData {
     detections: Vec<String>, // Assuming these are detection IDs
-    objects: Vec<String>,    // Array of object labels (e.g., "person")
+    // None if missing or malformed, so that it isn't mistaken for nothing detected
+    objects: Option<Vec<String>>, // Array of object labels (e.g., "person")
     sub_labels: Vec<serde_json::Value>,
     zones: Vec<String>, // Array of zone names (e.g., "full_frame")
-    audio: Vec<serde_json::Value>,
+    audio: Option<Vec<serde_json::Value>>,
+}
+
+impl ReviewData {
+    #[must_use]
+    pub fn detections(&self) -> &[String] {
+        &self.detections
+    }
+
+    #[must_use]
+    pub fn objects(&self) -> &[String] {
+        self.objects.as_deref().unwrap_or_default()
+    }
+
+    /// Whether objects were tracked or audio was detected in the review,
+    /// or None if neither was and either is missing from the payload
+    #[must_use]
+    pub fn has_activity(&self) -> Option<bool> {
+        match (&self.objects, &self.audio) {
+            (Some(objects), _) if !objects.is_empty() => Some(true),
+            (_, Some(audio)) if !audio.is_empty() => Some(true),
+            (Some(_), Some(_)) => Some(false),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
 
     #[test]
     fn test() {
@@ -65,17 +146,17 @@ mod tests {
                 "/media/frigate/clips/review/thumb-CameraLabel-1745534741.333822-vsz5s4.webp"
             );
             assert_eq!(
-                new_data.before.data.detections,
+                new_data.before.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
             assert_eq!(
-                new_data.after.data.detections,
+                new_data.after.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
-            assert_eq!(new_data.before.data.objects, ["person"]);
-            assert_eq!(new_data.after.data.objects, ["person"]);
-            assert_eq!(new_data.before.data.zones, ["full_frame"]);
-            assert_eq!(new_data.after.data.zones, ["full_frame"]);
+            assert_eq!(new_data.before.data.as_ref().unwrap().objects(), ["person"]);
+            assert_eq!(new_data.after.data.as_ref().unwrap().objects(), ["person"]);
+            assert_eq!(new_data.before.data.as_ref().unwrap().zones, ["full_frame"]);
+            assert_eq!(new_data.after.data.as_ref().unwrap().zones, ["full_frame"]);
         }
 
         {
@@ -99,21 +180,33 @@ mod tests {
                 "/media/frigate/clips/review/thumb-CameraLabel-1745534741.333822-vsz5s4.webp"
             );
             assert_eq!(
-                update_data.before.data.detections,
+                update_data.before.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
             assert_eq!(
-                update_data.before.data.detections,
+                update_data.before.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
             assert_eq!(
-                update_data.after.data.detections,
+                update_data.after.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
-            assert_eq!(update_data.before.data.objects, ["person"]);
-            assert_eq!(update_data.after.data.objects, ["person"]);
-            assert_eq!(update_data.before.data.zones, ["full_frame"]);
-            assert_eq!(update_data.after.data.zones, ["full_frame"]);
+            assert_eq!(
+                update_data.before.data.as_ref().unwrap().objects(),
+                ["person"]
+            );
+            assert_eq!(
+                update_data.after.data.as_ref().unwrap().objects(),
+                ["person"]
+            );
+            assert_eq!(
+                update_data.before.data.as_ref().unwrap().zones,
+                ["full_frame"]
+            );
+            assert_eq!(
+                update_data.after.data.as_ref().unwrap().zones,
+                ["full_frame"]
+            );
         }
 
         {
@@ -137,21 +230,127 @@ mod tests {
                 "/media/frigate/clips/review/thumb-CameraLabel-1745534741.333822-vsz5s4.webp"
             );
             assert_eq!(
-                end_data.before.data.detections,
+                end_data.before.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
             assert_eq!(
-                end_data.before.data.detections,
+                end_data.before.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
             assert_eq!(
-                end_data.after.data.detections,
+                end_data.after.data.as_ref().unwrap().detections,
                 ["1744534706.323662-abcdefg"]
             );
-            assert_eq!(end_data.before.data.objects, ["person"]);
-            assert_eq!(end_data.after.data.objects, ["person"]);
-            assert_eq!(end_data.before.data.zones, ["full_frame"]);
-            assert_eq!(end_data.after.data.zones, ["full_frame"]);
+            assert_eq!(end_data.before.data.as_ref().unwrap().objects(), ["person"]);
+            assert_eq!(end_data.after.data.as_ref().unwrap().objects(), ["person"]);
+            assert_eq!(end_data.before.data.as_ref().unwrap().zones, ["full_frame"]);
+            assert_eq!(end_data.after.data.as_ref().unwrap().zones, ["full_frame"]);
+        }
+    }
+
+    const END_SAMPLE_DATA: &str = r#"{"type": "end", "before": {"id": "1745534741.333822-vsz5s4", "camera": "CameraLabel", "start_time": 1745534741.333822, "end_time": null, "severity": "alert", "thumb_path": "/media/frigate/clips/review/thumb-CameraLabel-1745534741.333822-vsz5s4.webp", "data": {"detections": ["1744534706.323662-abcdefg"], "objects": ["person"], "sub_labels": [], "zones": ["full_frame"], "audio": []}}, "after": {"id": "1745534741.333822-vsz5s4", "camera": "CameraLabel", "start_time": 1745534741.333822, "end_time": 1756534721.13457, "severity": "alert", "thumb_path": "/media/frigate/clips/review/thumb-CameraLabel-1745534741.333822-vsz5s4.webp", "data": {"detections": ["1744534706.323662-abcdefg"], "objects": ["person"], "sub_labels": [], "zones": ["full_frame"], "audio": []}}}"#;
+
+    /// Applies `edit` to the data of both states of the sample payload
+    fn edit_sample(edit: impl Fn(&mut Map<String, Value>)) -> String {
+        let mut payload = serde_json::from_str::<Value>(END_SAMPLE_DATA).unwrap();
+        for state in ["before", "after"] {
+            edit(payload[state].as_object_mut().unwrap());
+        }
+        payload.to_string()
+    }
+
+    fn assert_essentials(payload: &ReviewsPayload) {
+        assert_eq!(payload.type_field, TypeField::End);
+        for state in [&payload.before, &payload.after] {
+            assert_eq!(state.id, "1745534741.333822-vsz5s4");
+            assert_eq!(state.camera, "CameraLabel");
+            assert_eq!(
+                state.start_time.to_bits(),
+                1_745_534_741.333_822_f64.to_bits()
+            );
         }
     }
+
+    #[rstest]
+    #[case::no_data(|f: &mut Map<String, Value>| { f.remove("data"); })]
+    #[case::no_audio(|f: &mut Map<String, Value>| { f["data"].as_object_mut().unwrap().remove("audio"); })]
+    #[case::no_sub_labels(|f: &mut Map<String, Value>| { f["data"].as_object_mut().unwrap().remove("sub_labels"); })]
+    #[case::no_severity_or_thumb(|f: &mut Map<String, Value>| { f.remove("severity"); f.remove("thumb_path"); })]
+    #[case::extra_field(|f: &mut Map<String, Value>| { f.insert("new_field".into(), Value::from(1)); })]
+    fn missing_non_essential_fields_are_tolerated(#[case] edit: fn(&mut Map<String, Value>)) {
+        let payload = ReviewsPayload::from_json_lenient(&edit_sample(edit)).unwrap();
+        assert_essentials(&payload);
+        assert_eq!(
+            payload.after.end_time.map(f64::to_bits),
+            Some(1_756_534_721.134_57_f64.to_bits())
+        );
+    }
+
+    #[test]
+    fn malformed_non_essential_fields_are_ignored() {
+        let json = edit_sample(|f| {
+            f.insert("severity".into(), Value::from(3));
+            let data = f["data"].as_object_mut().unwrap();
+            data.insert("sub_labels".into(), Value::from("not a list"));
+            data.insert("zones".into(), serde_json::json!([1, 2]));
+        });
+        // The strict parsing fails on it
+        assert!(serde_json::from_str::<ReviewsPayload>(&json).is_err());
+
+        let payload = ReviewsPayload::from_json_lenient(&json).unwrap();
+        assert_essentials(&payload);
+        assert_eq!(payload.after.severity, "");
+        assert!(payload.after.data.as_ref().unwrap().zones.is_empty());
+        // The valid fields next to the malformed ones are kept
+        assert_eq!(
+            payload.after.data.as_ref().unwrap().detections(),
+            ["1744534706.323662-abcdefg"]
+        );
+        assert_eq!(payload.after.data.as_ref().unwrap().objects(), ["person"]);
+
+        let json = edit_sample(|f| {
+            f.insert("data".into(), Value::from("not an object"));
+        });
+        let payload = ReviewsPayload::from_json_lenient(&json).unwrap();
+        assert_essentials(&payload);
+        assert!(payload.after.data.is_none());
+    }
+
+    #[rstest]
+    #[case::objects(r#"{"objects": ["person"], "audio": []}"#, Some(true))]
+    #[case::audio_only(r#"{"objects": [], "audio": ["speech"]}"#, Some(true))]
+    #[case::nothing(r#"{"objects": [], "audio": []}"#, Some(false))]
+    #[case::no_audio(r#"{"objects": []}"#, None)]
+    #[case::malformed_objects(r#"{"objects": "not a list", "audio": []}"#, None)]
+    #[case::malformed_objects_with_audio(r#"{"objects": 1, "audio": ["speech"]}"#, Some(true))]
+    fn activity_is_unknown_unless_the_payload_says(
+        #[case] data: &str,
+        #[case] expected: Option<bool>,
+    ) {
+        let data = serde_json::from_str::<Value>(data).unwrap();
+        let json = edit_sample(|f| {
+            f.insert("data".into(), data.clone());
+        });
+        let payload = ReviewsPayload::from_json_lenient(&json).unwrap();
+        assert_eq!(
+            payload.after.data.as_ref().unwrap().has_activity(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("id")]
+    #[case("camera")]
+    #[case("start_time")]
+    fn missing_essential_fields_fail(#[case] field: &str) {
+        let json = edit_sample(|f| {
+            f.remove(field);
+        });
+        assert!(ReviewsPayload::from_json_lenient(&json).is_err());
+
+        let json = edit_sample(|f| {
+            f.insert(field.into(), serde_json::json!({"unexpected": true}));
+        });
+        assert!(ReviewsPayload::from_json_lenient(&json).is_err());
+    }
 }
@@ -1,50 +1,227 @@
 use std::path::PathBuf;
+use utils::file_name::{FileNameCharset, sanitize_file_name_part};
 
 #[must_use]
 #[derive(Debug, Clone)]
 pub struct Snapshot {
     pub image_bytes: Vec<u8>, // a raw copy of the image, to save it to disk
     pub camera_label: String,
-    pub object_name: String,
+    /// None for the snapshots of the camera itself, whose topics have no object
+    pub object_name: Option<String>,
 }
 
 impl Snapshot {
     #[must_use]
     pub fn from_topic_parts(topic_parts: &[&str], payload: &bytes::Bytes) -> Option<Self> {
-        // <prefix>/<camera_name>/<object_name>/snapshot
-        if topic_parts.len() > 3 && topic_parts[3] == "snapshot" {
-            let camera_label = topic_parts[1].to_string();
-            let object_name = topic_parts[2].to_string();
-            let _snapshot_image =
-                match image::load_from_memory_with_format(payload, image::ImageFormat::Jpeg) {
-                    Ok(img) => img,
-                    Err(e) => {
-                        tracing::error!(
-                            "Failed to parse `snapshot` topic (${}) image with error: `{e}`",
-                            topic_parts.join("/")
-                        );
-                        return None;
-                    }
-                };
-            Some(Self {
-                image_bytes: payload.to_vec(),
-                camera_label,
-                object_name,
-            })
-        } else {
-            None
-        }
+        let (camera_label, object_name) = match topic_parts {
+            // <prefix>/<camera_name>/<object_name>/snapshot
+            [_, camera_label, object_name, "snapshot", ..] => (camera_label, Some(object_name)),
+            // <prefix>/<camera_name>/snapshot
+            [_, camera_label, "snapshot"] => (camera_label, None),
+            _ => return None,
+        };
+
+        let _snapshot_image =
+            match image::load_from_memory_with_format(payload, image::ImageFormat::Jpeg) {
+                Ok(img) => img,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to parse `snapshot` topic (${}) image with error: `{e}`",
+                        topic_parts.join("/")
+                    );
+                    return None;
+                }
+            };
+        Some(Self {
+            image_bytes: payload.to_vec(),
+            camera_label: (*camera_label).to_string(),
+            object_name: object_name.map(|o| (*o).to_string()),
+        })
     }
 
+    /// Named after `camera_name`, which is the camera's label unless paths give it another name.
+    /// The names come from MQTT topics, so they're sanitized to not break out of the upload directory.
     #[must_use]
-    pub fn make_file_name(&self) -> PathBuf {
+    pub fn make_file_name(&self, camera_name: &str, charset: FileNameCharset) -> PathBuf {
         let datetime = chrono::Local::now()
             .format("%Y-%m-%d_%H-%M-%S%z")
             .to_string();
-        format!(
-            "Snapshot-{}-{datetime}-{}.jpg",
-            self.camera_label, self.object_name
-        )
+        let camera = sanitize_file_name_part(camera_name, charset);
+        match &self.object_name {
+            Some(object_name) => format!(
+                "Snapshot-{camera}-{datetime}-{}.jpg",
+                sanitize_file_name_part(object_name, charset)
+            ),
+            None => format!("Snapshot-{camera}-{datetime}.jpg"),
+        }
         .into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::MqttHandlerConfig, types::CapturedPayloads};
+    use bytes::Bytes;
+    use rstest::rstest;
+    use std::sync::Arc;
+    use test_utils::random::{
+        Seed, make_random_alphanumeric_string, make_seedable_rng, random_seed,
+    };
+
+    fn make_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(width, height);
+        let mut result = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut result, image::ImageFormat::Jpeg)
+            .unwrap();
+        result.into_inner()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[rstest]
+    #[trace]
+    async fn parse_many_snapshots_concurrently(random_seed: Seed) {
+        const SNAPSHOT_COUNT: usize = 16;
+
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+        let config = Arc::new(MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        });
+
+        let payload = Bytes::from_owner(make_jpeg(640, 480));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut expected = Vec::new();
+        for _ in 0..SNAPSHOT_COUNT {
+            let camera_name = make_random_alphanumeric_string(&mut rng, 20);
+            let object_name = make_random_alphanumeric_string(&mut rng, 20);
+            let topic = format!("{mqtt_topic_prefix}/{camera_name}/{object_name}/snapshot");
+            tasks.spawn(CapturedPayloads::from_publish_blocking(
+                config.clone(),
+                topic,
+                payload.clone(),
+            ));
+            expected.push((camera_name, object_name));
+        }
+
+        let mut parsed = tasks
+            .join_all()
+            .await
+            .into_iter()
+            .map(|p| {
+                let snapshot = p.unwrap().into_snapshot().unwrap();
+                assert_eq!(snapshot.image_bytes, payload.to_vec());
+                (
+                    snapshot.camera_label.clone(),
+                    snapshot.object_name.clone().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        parsed.sort();
+        expected.sort();
+        assert_eq!(parsed, expected);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[trace]
+    async fn invalid_snapshot_image(random_seed: Seed) {
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+        let config = Arc::new(MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        });
+
+        let parse_result = CapturedPayloads::from_publish_blocking(
+            config,
+            format!("{mqtt_topic_prefix}/camera/person/snapshot"),
+            Bytes::from_static(b"not an image"),
+        )
+        .await;
+
+        assert!(parse_result.is_none());
+    }
+
+    #[rstest]
+    #[case("back/yard", "person", "Snapshot-back_yard-")]
+    #[case("..", "..", "Snapshot-__-")]
+    #[case("back yard", "red car", "Snapshot-back_yard-")]
+    #[case("../../etc", "person/../x", "Snapshot-______etc-")]
+    fn file_name_of_hostile_labels_stays_in_upload_dir(
+        #[case] camera_label: &str,
+        #[case] object_name: &str,
+        #[case] expected_prefix: &str,
+    ) {
+        let snapshot = super::Snapshot {
+            image_bytes: Vec::new(),
+            camera_label: camera_label.to_string(),
+            object_name: Some(object_name.to_string()),
+        };
+
+        let file_name = snapshot.make_file_name(
+            &snapshot.camera_label,
+            utils::file_name::FileNameCharset::Ascii,
+        );
+        let file_name = file_name.to_str().unwrap();
+
+        assert!(file_name.starts_with(expected_prefix));
+        assert!(!file_name.contains('/'));
+        assert!(!file_name.contains(' '));
+        assert!(!file_name.contains(".."));
+    }
+
+    #[rstest]
+    #[trace]
+    #[case("{prefix}/{camera}/person/snapshot", Some("person"))]
+    #[case("{prefix}/{camera}/snapshot", None)]
+    fn snapshot_topics_with_and_without_object(
+        random_seed: Seed,
+        #[case] topic: &str,
+        #[case] expected_object_name: Option<&str>,
+    ) {
+        let mut rng = make_seedable_rng(random_seed);
+
+        let mqtt_topic_prefix = make_random_alphanumeric_string(&mut rng, 20);
+        let config = MqttHandlerConfig {
+            mqtt_frigate_topic_prefix: mqtt_topic_prefix.clone(),
+            ..MqttHandlerConfig::default()
+        };
+
+        let camera_name = make_random_alphanumeric_string(&mut rng, 20);
+        let topic = topic
+            .replace("{prefix}", &mqtt_topic_prefix)
+            .replace("{camera}", &camera_name);
+
+        let snapshot =
+            CapturedPayloads::from_publish(&config, &topic, &Bytes::from_owner(make_jpeg(64, 48)))
+                .unwrap()
+                .into_snapshot()
+                .unwrap();
+        assert_eq!(snapshot.camera_label, camera_name);
+        assert_eq!(snapshot.object_name.as_deref(), expected_object_name);
+
+        let file_name = snapshot.make_file_name(
+            &snapshot.camera_label,
+            utils::file_name::FileNameCharset::Ascii,
+        );
+        let file_name = file_name.to_str().unwrap();
+        assert!(file_name.starts_with(&format!("Snapshot-{camera_name}-")));
+        assert!(
+            std::path::Path::new(file_name)
+                .extension()
+                .is_some_and(|ext| ext == "jpg")
+        );
+        // Without an object, there's no empty segment for it
+        assert!(!file_name.contains("--"));
+        assert!(!file_name.contains("-.jpg"));
+        if let Some(object_name) = expected_object_name {
+            assert!(file_name.ends_with(&format!("-{object_name}.jpg")));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use tap::TapOptional;
 
-use super::utils::on_off_from_bytes;
+use super::utils::state_from_bytes;
 
 #[must_use]
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -14,7 +14,7 @@ impl SnapshotsState {
     pub fn from_topic_parts(topic_parts: &[&str], payload: &bytes::Bytes) -> Option<Self> {
         if topic_parts.len() > 3 && topic_parts[2] == "snapshots" && topic_parts[3] == "state" {
             let camera_label = topic_parts[1].to_string();
-            let state = on_off_from_bytes(payload.to_vec()).tap_none(|| {
+            let state = state_from_bytes(payload).tap_none(|| {
                 tracing::error!("Failed to parse snapshots payload: {:?}", payload);
             })?;
             Some(Self {
@@ -45,6 +45,16 @@ mod tests {
     #[trace]
     #[case(b"abcdefg".to_vec(), None)]
     #[trace]
+    #[case(br#"{"state": true}"#.to_vec(), Some(true))]
+    #[trace]
+    #[case(br#"{"enabled": false, "camera": "front"}"#.to_vec(), Some(false))]
+    #[trace]
+    #[case(br#"{"state": "ON"}"#.to_vec(), None)]
+    #[trace]
+    #[case(br#"{"active": true}"#.to_vec(), None)]
+    #[trace]
+    #[case(b"true".to_vec(), None)]
+    #[trace]
     fn snapshots_state(
         random_seed: Seed,
         #[case] payload: Vec<u8>,
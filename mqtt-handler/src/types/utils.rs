@@ -9,3 +9,14 @@ pub fn on_off_from_bytes(value: Vec<u8>) -> Option<bool> {
         None
     }
 }
+
+/// The state of a camera's feature, which Frigate publishes as `ON`/`OFF`.
+/// Some integrations publish a JSON object instead, with a boolean `state` or `enabled` field.
+pub fn state_from_bytes(value: &[u8]) -> Option<bool> {
+    on_off_from_bytes(value.to_vec()).or_else(|| {
+        let object = serde_json::from_slice::<serde_json::Value>(value).ok()?;
+        ["state", "enabled"]
+            .into_iter()
+            .find_map(|key| object.get(key)?.as_bool())
+    })
+}
@@ -0,0 +1,21 @@
+use clap::Parser;
+
+use super::config_file_options::ConfigFileOptions;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct BackfillOptions {
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+
+    /// Upload the recordings of events that started after this unix timestamp, in seconds
+    #[clap(long)]
+    pub after: f64,
+
+    /// Upload the recordings of events that started before this unix timestamp, in seconds
+    #[clap(long)]
+    pub before: f64,
+
+    /// The cameras whose events are uploaded, separated by commas. All cameras if not provided
+    #[clap(long, value_delimiter = ',')]
+    pub cameras: Vec<String>,
+}
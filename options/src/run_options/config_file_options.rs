@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+/// The options shared by the commands that read the config file
+#[derive(Args, Clone, Debug, Default)]
+pub struct ConfigFileOptions {
+    /// The path to the config file
+    /// If not provided, it's taken from the `SNAPSYNC_CONFIG` env var, or the default value is used, config.yaml
+    #[clap(
+        long,
+        short('c'),
+        visible_alias = "config",
+        env = super::CONFIG_FILE_PATH_ENV,
+        default_value_os = super::DEFAULT_CONFIG_FILE_PATH
+    )]
+    pub config_file_path: PathBuf,
+
+    /// The log level: off, error, warn, info, debug or trace. The default is info.
+    /// For finer control, per module, set the `SNAPSYNC_LOG` env var with directives like `RUST_LOG`'s,
+    /// e.g. `SNAPSYNC_LOG=sync_system=debug,rumqttc=warn`, which refine this level.
+    #[clap(long)]
+    pub log_level: Option<tracing::level_filters::LevelFilter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::run_options::{
+        CONFIG_FILE_PATH_ENV, DEFAULT_CONFIG_FILE_PATH, RunCommand, RunOptions,
+    };
+
+    /// Every command that reads the config file, with the arguments it requires
+    const COMMANDS: &[&[&str]] = &[
+        &["start"],
+        &["backfill", "--after", "0", "--before", "1"],
+        &["resync"],
+        &["retry-dead-letters"],
+        &["self-test"],
+        &["prune-alternatives"],
+    ];
+
+    fn parse_config_file_path(command: &[&str], args: &[&str]) -> PathBuf {
+        let options = RunOptions::try_parse_from(
+            std::iter::once("snap-sync")
+                .chain(command.iter().copied())
+                .chain(args.iter().copied()),
+        )
+        .unwrap();
+
+        let config_file = match options.command {
+            RunCommand::Start(options) => options.config_file,
+            RunCommand::Backfill(options) => options.config_file,
+            RunCommand::Resync(options) => options.config_file,
+            RunCommand::RetryDeadLetters(options) => options.config_file,
+            RunCommand::SelfTest(options) => options.config_file,
+            RunCommand::PruneAlternatives(options) => options.config_file,
+            RunCommand::TestDestination(_) => panic!("test-destination reads no config file"),
+        };
+        config_file.config_file_path
+    }
+
+    // All checks are in one test, since they change the same env var, and tests run in parallel
+    #[test]
+    fn config_file_path_flag_overrides_env_var_which_overrides_default() {
+        for command in COMMANDS {
+            let parse = |args: &[&str]| parse_config_file_path(command, args);
+
+            unsafe {
+                std::env::remove_var(CONFIG_FILE_PATH_ENV);
+            }
+            assert_eq!(parse(&[]), PathBuf::from(DEFAULT_CONFIG_FILE_PATH));
+            assert_eq!(
+                parse(&["--config", "flag.yaml"]),
+                PathBuf::from("flag.yaml")
+            );
+
+            unsafe {
+                std::env::set_var(CONFIG_FILE_PATH_ENV, "env.yaml");
+            }
+            assert_eq!(parse(&[]), PathBuf::from("env.yaml"));
+            for flag in ["--config", "--config-file-path", "-c"] {
+                assert_eq!(parse(&[flag, "flag.yaml"]), PathBuf::from("flag.yaml"));
+            }
+        }
+
+        unsafe {
+            std::env::remove_var(CONFIG_FILE_PATH_ENV);
+        }
+    }
+}
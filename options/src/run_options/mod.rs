@@ -1,8 +1,17 @@
+pub mod backfill_options;
+pub mod config_file_options;
+pub mod prune_alternatives_options;
+pub mod resync_options;
+pub mod retry_dead_letters_options;
+pub mod self_test_options;
 pub mod start_options;
+pub mod test_destination_options;
 
 use clap::{Parser, Subcommand};
 
 const DEFAULT_CONFIG_FILE_PATH: &str = "config.yaml";
+/// The path to the config file if it's not given as an argument
+const CONFIG_FILE_PATH_ENV: &str = "SNAPSYNC_CONFIG";
 
 #[derive(Parser)]
 pub struct RunOptions {
@@ -14,4 +23,21 @@ pub struct RunOptions {
 pub enum RunCommand {
     /// The default command to start the application.
     Start(start_options::StartOptions),
+    /// Upload the recordings of all the events within a past time range, then exit.
+    Backfill(backfill_options::BackfillOptions),
+    /// Upload the recordings of the events of the last hours that aren't in all the upload destinations, then exit,
+    /// e.g., after fixing a destination.
+    Resync(resync_options::ResyncOptions),
+    /// Upload the recordings of the reviews in the dead-letter file of the config, then exit.
+    /// The reviews that fail again are kept in the file.
+    RetryDeadLetters(retry_dead_letters_options::RetryDeadLettersOptions),
+    /// Download the recording of a recent event from Frigate and upload it to memory, reporting each step, then exit.
+    /// Nothing is written to the upload destinations.
+    SelfTest(self_test_options::SelfTestOptions),
+    /// Find the recordings of which both alternative versions (`-0` and `-1`) are in the upload destinations,
+    /// delete the stale version of each, then exit.
+    PruneAlternatives(prune_alternatives_options::PruneAlternativesOptions),
+    /// Write a file to a single destination, read it back and delete it, reporting each step, then exit.
+    /// No config file is needed.
+    TestDestination(test_destination_options::TestDestinationOptions),
 }
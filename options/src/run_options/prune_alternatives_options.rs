@@ -0,0 +1,13 @@
+use clap::Parser;
+
+use super::config_file_options::ConfigFileOptions;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct PruneAlternativesOptions {
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+
+    /// Only report the stale files, without deleting them
+    #[clap(long)]
+    pub dry_run: bool,
+}
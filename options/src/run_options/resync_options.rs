@@ -0,0 +1,19 @@
+use clap::Parser;
+
+use super::config_file_options::ConfigFileOptions;
+
+const DEFAULT_RESYNC_HOURS: u64 = 24;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct ResyncOptions {
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+
+    /// Upload the recordings of events that started within this many hours before now
+    #[clap(long, default_value_t = DEFAULT_RESYNC_HOURS)]
+    pub hours: u64,
+
+    /// The cameras whose events are uploaded, separated by commas. All cameras if not provided
+    #[clap(long, value_delimiter = ',')]
+    pub cameras: Vec<String>,
+}
@@ -0,0 +1,9 @@
+use clap::Parser;
+
+use super::config_file_options::ConfigFileOptions;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct RetryDeadLettersOptions {
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+}
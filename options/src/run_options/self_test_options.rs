@@ -0,0 +1,15 @@
+use clap::Parser;
+
+use super::config_file_options::ConfigFileOptions;
+
+const DEFAULT_LOOKBACK_HOURS: u64 = 24;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct SelfTestOptions {
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+
+    /// How far back to look for an event to download the recording of, in hours
+    #[clap(long, default_value_t = DEFAULT_LOOKBACK_HOURS)]
+    pub lookback_hours: u64,
+}
@@ -2,10 +2,20 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
+use super::config_file_options::ConfigFileOptions;
+
 #[derive(Parser, Clone, Debug, Default)]
 pub struct StartOptions {
-    /// The path to the config file
-    /// If not provided, the default value is used, config.yaml
-    #[clap(long, short('c'), default_value_os = super::DEFAULT_CONFIG_FILE_PATH)]
-    pub config_file_path: PathBuf,
+    #[clap(flatten)]
+    pub config_file: ConfigFileOptions,
+
+    /// Instead of connecting to MQTT, replay the messages recorded in this file, then exit once they're processed,
+    /// e.g., for demos. Every line is a message as json, e.g. `{"topic": "frigate/reviews", "payload": {...}}`.
+    /// Frigate isn't called either, and the clip of every recording is the one given with `--replay-clip`.
+    #[clap(long, requires = "replay_clip")]
+    pub replay: Option<PathBuf>,
+
+    /// The clip that is uploaded for every recording while replaying with `--replay`
+    #[clap(long, requires = "replay")]
+    pub replay_clip: Option<PathBuf>,
 }
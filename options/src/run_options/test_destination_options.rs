@@ -0,0 +1,12 @@
+use clap::Parser;
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct TestDestinationOptions {
+    /// The destination to test, written like the upload destinations in the config file, e.g.,
+    /// `sftp:username=user;host=example.com;remote-path=/dir;identity=/home/user/key.pem`
+    pub descriptor: String,
+
+    /// The log level: off, error, warn, info, debug or trace. The default is info.
+    #[clap(long)]
+    pub log_level: Option<tracing::level_filters::LevelFilter>,
+}
@@ -1,6 +1,9 @@
 use clap::Parser;
 use options::run_options::{self, RunOptions};
-use sync_system::runner::run;
+use sync_system::runner::{
+    run, run_backfill, run_prune_alternatives, run_resync, run_retry_dead_letters, run_self_test,
+    run_test_destination,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -8,5 +11,19 @@ async fn main() -> anyhow::Result<()> {
 
     match args.command {
         run_options::RunCommand::Start(start_options) => run(start_options).await,
+        run_options::RunCommand::Backfill(backfill_options) => run_backfill(backfill_options).await,
+        run_options::RunCommand::Resync(resync_options) => run_resync(resync_options).await,
+        run_options::RunCommand::RetryDeadLetters(retry_dead_letters_options) => {
+            run_retry_dead_letters(retry_dead_letters_options).await
+        }
+        run_options::RunCommand::SelfTest(self_test_options) => {
+            run_self_test(self_test_options).await
+        }
+        run_options::RunCommand::PruneAlternatives(prune_alternatives_options) => {
+            run_prune_alternatives(prune_alternatives_options).await
+        }
+        run_options::RunCommand::TestDestination(test_destination_options) => {
+            run_test_destination(test_destination_options).await
+        }
     }
 }
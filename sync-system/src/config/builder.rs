@@ -0,0 +1,663 @@
+use super::{
+    ConfigError, MqttHost, PathDescriptors, RecordingRetryConfig, RetryConfig, Secret,
+    VideoSyncConfig, check_subdir,
+};
+use crate::system::config::{
+    CameraDirOrder, CameraNameTransform, DateBasis, GateOnStatsFailure, MontageGrid, PathTemplate,
+    RecordingRetry, SnapshotMode, UploadWindow,
+};
+use file_sender::path_descriptor::PathDescriptor;
+use frigate_api_caller::config::{ClipContainer, FrigateLogin, ProxyAuth};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    num::{NonZeroU16, NonZeroUsize},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use utils::file_name::FileNameCharset;
+
+/// Builds a `VideoSyncConfig` in code, for embedding the sync system instead of loading a config file.
+/// Whatever isn't set takes the same default as when it's left out of the config file.
+/// Durations are kept in whole seconds, like in the config file.
+#[must_use]
+#[derive(Debug, Clone)]
+pub struct VideoSyncConfigBuilder {
+    config: VideoSyncConfig,
+    upload_destinations: Vec<Arc<PathDescriptor>>,
+    force_enable_cameras: Vec<String>,
+}
+
+impl VideoSyncConfigBuilder {
+    pub fn new(mqtt_host: impl Into<String>, frigate_api_address: impl Into<String>) -> Self {
+        let config = VideoSyncConfig {
+            mqtt_frigate_topic_prefix: None,
+            mqtt_host: MqttHost(mqtt_host.into()),
+            mqtt_port: None,
+            mqtt_keep_alive_seconds: None,
+            mqtt_username: None,
+            mqtt_password: None,
+            mqtt_password_file: None,
+            mqtt_client_id: None,
+            mqtt_clean_session: None,
+            mqtt_inflight: None,
+            mqtt_publish_uploads: None,
+            mqtt_upload_topic_prefix: None,
+            mqtt_upload_retain: None,
+            mqtt_commands: None,
+
+            frigate_api_address: frigate_api_address.into(),
+            frigate_api_proxy: None,
+            bind_address: None,
+            frigate_api_proxy_username: None,
+            frigate_api_proxy_password: None,
+            frigate_api_proxy_password_file: None,
+            frigate_api_username: None,
+            frigate_api_password: None,
+            frigate_api_password_file: None,
+            frigate_api_path_prefix: None,
+            resume_downloads: None,
+            clip_timestamp_precision: None,
+            clip_container: None,
+            clip_end_safety_margin: None,
+            max_clip_bytes: None,
+            deep_clip_validation: None,
+            frigate_api_compression: None,
+
+            upload_destinations: Vec::new().into(),
+            final_only_upload_destinations: Vec::new(),
+
+            require_all_destinations: None,
+            destination_retry_period: None,
+
+            instance_lock_file: None,
+            destination_lock: None,
+            instance_id: None,
+            state_dir: None,
+            config_path: None,
+            recording_retry: RecordingRetryConfig::default(),
+            snapshot_retry: RetryConfig::default(),
+            file_op_retry: RetryConfig::default(),
+
+            delay_after_startup: None,
+            gate_on_stats_failure: None,
+
+            skip_if_already_uploaded: None,
+
+            shutdown_timeout: None,
+            heartbeat_interval: None,
+
+            min_review_duration: None,
+            review_update_debounce: None,
+            review_idle_timeout: None,
+            post_end_grace: None,
+            max_task_lifetime: None,
+
+            audit_log_path: None,
+
+            dead_letter_path: None,
+
+            keep_alternative_versions: None,
+
+            enable_recordings_sync: None,
+            enable_snapshots_sync: None,
+
+            upload_window: None,
+            upload_window_timezone: None,
+
+            upload_event_snapshots: None,
+
+            unicode_file_names: None,
+
+            snapshot_mode: None,
+
+            maintain_latest_snapshot: None,
+            max_snapshot_tasks: None,
+            fetch_latest_snapshot_on_reconnect: None,
+
+            upload_montage: None,
+            montage_grid: None,
+
+            force_enable_cameras: Vec::new(),
+
+            stream_uploads: None,
+
+            export_recording: None,
+            export_timeout: None,
+
+            group_recordings_by_camera: None,
+
+            camera_dir_order: None,
+
+            date_basis: None,
+
+            recordings_subdir: None,
+            snapshots_subdir: None,
+            recording_path_template: None,
+            snapshot_path_template: None,
+
+            camera_display_names: BTreeMap::new(),
+            camera_display_name_transform: None,
+        };
+
+        Self {
+            config,
+            upload_destinations: Vec::new(),
+            force_enable_cameras: Vec::new(),
+        }
+    }
+
+    /// Fails if a required value is empty, there are no upload destinations, a camera name pattern
+    /// or subdirectory is invalid, or a password file can't be read
+    pub fn build(self) -> Result<VideoSyncConfig, ConfigError> {
+        let Self {
+            mut config,
+            upload_destinations,
+            force_enable_cameras,
+        } = self;
+
+        if config.mqtt_host.0.trim().is_empty() {
+            return Err(ConfigError::EmptyValue("mqtt_host"));
+        }
+        if config.frigate_api_address.trim().is_empty() {
+            return Err(ConfigError::EmptyValue("frigate_api_address"));
+        }
+        if upload_destinations.is_empty() {
+            return Err(ConfigError::NoUploadDestinations);
+        }
+
+        config.upload_destinations = PathDescriptors::from(upload_destinations);
+        config.force_enable_cameras = force_enable_cameras
+            .into_iter()
+            .map(|p| glob::Pattern::new(&p).map_err(|e| ConfigError::InvalidCameraPattern(p, e)))
+            .collect::<Result<_, _>>()?;
+
+        for subdir in [&config.recordings_subdir, &config.snapshots_subdir]
+            .into_iter()
+            .flatten()
+        {
+            check_subdir(subdir)?;
+        }
+
+        config.load_secret_files()?;
+        config.apply_bind_address();
+
+        Ok(config)
+    }
+
+    pub fn mqtt_frigate_topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.mqtt_frigate_topic_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn mqtt_port(mut self, port: u16) -> Self {
+        self.config.mqtt_port = Some(port);
+        self
+    }
+
+    pub fn mqtt_keep_alive_seconds(mut self, seconds: u64) -> Self {
+        self.config.mqtt_keep_alive_seconds = Some(seconds);
+        self
+    }
+
+    pub fn mqtt_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.config.mqtt_username = Some(username.into());
+        self.config.mqtt_password = Some(Secret(password.into()));
+        self
+    }
+
+    /// Read on `build()`, and takes precedence over the password given with the credentials
+    pub fn mqtt_password_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.mqtt_password_file = Some(path.into());
+        self
+    }
+
+    pub fn mqtt_client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.config.mqtt_client_id = Some(client_id.into());
+        self
+    }
+
+    pub fn mqtt_clean_session(mut self, clean_session: bool) -> Self {
+        self.config.mqtt_clean_session = Some(clean_session);
+        self
+    }
+
+    pub fn mqtt_inflight(mut self, inflight: NonZeroU16) -> Self {
+        self.config.mqtt_inflight = Some(inflight);
+        self
+    }
+
+    pub fn mqtt_publish_uploads(mut self, publish: bool) -> Self {
+        self.config.mqtt_publish_uploads = Some(publish);
+        self
+    }
+
+    pub fn mqtt_upload_topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.mqtt_upload_topic_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn mqtt_upload_retain(mut self, retain: bool) -> Self {
+        self.config.mqtt_upload_retain = Some(retain);
+        self
+    }
+
+    pub fn mqtt_commands(mut self, enabled: bool) -> Self {
+        self.config.mqtt_commands = Some(enabled);
+        self
+    }
+
+    pub fn bind_address(mut self, address: IpAddr) -> Self {
+        self.config.bind_address = Some(address);
+        self
+    }
+
+    pub fn frigate_api_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.frigate_api_proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn frigate_api_proxy_auth(mut self, auth: ProxyAuth) -> Self {
+        self.config.frigate_api_proxy_username = Some(auth.username);
+        self.config.frigate_api_proxy_password = Some(Secret(auth.password));
+        self
+    }
+
+    /// Read on `build()`, and takes precedence over the password given with the proxy credentials
+    pub fn frigate_api_proxy_password_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.frigate_api_proxy_password_file = Some(path.into());
+        self
+    }
+
+    pub fn frigate_api_login(mut self, login: FrigateLogin) -> Self {
+        self.config.frigate_api_username = Some(login.username);
+        self.config.frigate_api_password = Some(Secret(login.password));
+        self
+    }
+
+    /// Read on `build()`, and takes precedence over the password given with the login
+    pub fn frigate_api_password_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.frigate_api_password_file = Some(path.into());
+        self
+    }
+
+    pub fn frigate_api_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.frigate_api_path_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn resume_downloads(mut self, resume: bool) -> Self {
+        self.config.resume_downloads = Some(resume);
+        self
+    }
+
+    pub fn frigate_api_compression(mut self, compression: bool) -> Self {
+        self.config.frigate_api_compression = Some(compression);
+        self
+    }
+
+    pub fn clip_timestamp_precision(mut self, precision: u32) -> Self {
+        self.config.clip_timestamp_precision = Some(precision);
+        self
+    }
+
+    pub fn clip_container(mut self, container: ClipContainer) -> Self {
+        self.config.clip_container = Some(container);
+        self
+    }
+
+    pub fn clip_end_safety_margin(mut self, margin: Duration) -> Self {
+        self.config.clip_end_safety_margin = Some(margin.as_secs());
+        self
+    }
+
+    pub fn max_clip_bytes(mut self, max_bytes: u64) -> Self {
+        self.config.max_clip_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn deep_clip_validation(mut self, deep: bool) -> Self {
+        self.config.deep_clip_validation = Some(deep);
+        self
+    }
+
+    pub fn upload_destination(mut self, destination: PathDescriptor) -> Self {
+        self.upload_destinations.push(Arc::new(destination));
+        self
+    }
+
+    pub fn final_only_upload_destination(mut self, destination: PathDescriptor) -> Self {
+        self.config
+            .final_only_upload_destinations
+            .push(Arc::new(destination));
+        self
+    }
+
+    pub fn require_all_destinations(mut self, require: bool) -> Self {
+        self.config.require_all_destinations = Some(require);
+        self
+    }
+
+    pub fn destination_retry_period(mut self, period: Duration) -> Self {
+        self.config.destination_retry_period = Some(period.as_secs());
+        self
+    }
+
+    pub fn instance_lock_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.instance_lock_file = Some(path.into());
+        self
+    }
+
+    pub fn destination_lock(mut self, lock: bool) -> Self {
+        self.config.destination_lock = Some(lock);
+        self
+    }
+
+    pub fn instance_id(mut self, id: impl Into<String>) -> Self {
+        self.config.instance_id = Some(id.into());
+        self
+    }
+
+    pub fn state_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.state_dir = Some(path.into());
+        self
+    }
+
+    pub fn recording_retry(mut self, retry: RecordingRetry) -> Self {
+        let retry = RetryConfig::from(retry);
+        self.config.recording_retry.max_attempts = retry.max_attempts;
+        self.config.recording_retry.period = retry.period;
+        self.config.recording_retry.max_period = retry.max_period;
+        self
+    }
+
+    /// Overrides `recording_retry` for the camera with the given name
+    pub fn camera_recording_retry(
+        mut self,
+        camera: impl Into<String>,
+        retry: RecordingRetry,
+    ) -> Self {
+        self.config
+            .recording_retry
+            .cameras
+            .insert(camera.into(), retry.into());
+        self
+    }
+
+    pub fn snapshot_retry(mut self, retry: RecordingRetry) -> Self {
+        self.config.snapshot_retry = retry.into();
+        self
+    }
+
+    pub fn file_op_retry(mut self, retry: RecordingRetry) -> Self {
+        self.config.file_op_retry = retry.into();
+        self
+    }
+
+    pub fn delay_after_startup(mut self, delay: Duration) -> Self {
+        self.config.delay_after_startup = Some(delay.as_secs());
+        self
+    }
+
+    pub fn gate_on_stats_failure(mut self, gate: GateOnStatsFailure) -> Self {
+        self.config.gate_on_stats_failure = Some(gate);
+        self
+    }
+
+    pub fn skip_if_already_uploaded(mut self, skip: bool) -> Self {
+        self.config.skip_if_already_uploaded = Some(skip);
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = Some(timeout.as_secs());
+        self
+    }
+
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.config.heartbeat_interval = Some(interval.as_secs());
+        self
+    }
+
+    pub fn min_review_duration(mut self, duration: Duration) -> Self {
+        self.config.min_review_duration = Some(duration.as_secs());
+        self
+    }
+
+    pub fn review_update_debounce(mut self, interval: Duration) -> Self {
+        self.config.review_update_debounce = Some(interval.as_secs());
+        self
+    }
+
+    pub fn review_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.review_idle_timeout = Some(timeout.as_secs());
+        self
+    }
+
+    pub fn post_end_grace(mut self, grace: Duration) -> Self {
+        self.config.post_end_grace = Some(grace.as_secs());
+        self
+    }
+
+    pub fn max_task_lifetime(mut self, lifetime: Duration) -> Self {
+        self.config.max_task_lifetime = Some(lifetime.as_secs());
+        self
+    }
+
+    pub fn audit_log_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.audit_log_path = Some(path.into());
+        self
+    }
+
+    pub fn dead_letter_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.dead_letter_path = Some(path.into());
+        self
+    }
+
+    pub fn keep_alternative_versions(mut self, keep: bool) -> Self {
+        self.config.keep_alternative_versions = Some(keep);
+        self
+    }
+
+    pub fn enable_recordings_sync(mut self, enable: bool) -> Self {
+        self.config.enable_recordings_sync = Some(enable);
+        self
+    }
+
+    pub fn enable_snapshots_sync(mut self, enable: bool) -> Self {
+        self.config.enable_snapshots_sync = Some(enable);
+        self
+    }
+
+    pub fn upload_window(mut self, window: UploadWindow) -> Self {
+        self.config.upload_window = Some(window);
+        self
+    }
+
+    pub fn upload_window_timezone(mut self, timezone: chrono_tz::Tz) -> Self {
+        self.config.upload_window_timezone = Some(timezone);
+        self
+    }
+
+    pub fn upload_event_snapshots(mut self, upload: bool) -> Self {
+        self.config.upload_event_snapshots = Some(upload);
+        self
+    }
+
+    pub fn file_name_charset(mut self, charset: FileNameCharset) -> Self {
+        self.config.unicode_file_names = Some(charset == FileNameCharset::Unicode);
+        self
+    }
+
+    pub fn snapshot_mode(mut self, mode: SnapshotMode) -> Self {
+        self.config.snapshot_mode = Some(mode);
+        self
+    }
+
+    pub fn maintain_latest_snapshot(mut self, maintain: bool) -> Self {
+        self.config.maintain_latest_snapshot = Some(maintain);
+        self
+    }
+
+    pub fn max_snapshot_tasks(mut self, max: NonZeroUsize) -> Self {
+        self.config.max_snapshot_tasks = Some(max);
+        self
+    }
+
+    pub fn fetch_latest_snapshot_on_reconnect(mut self, fetch: bool) -> Self {
+        self.config.fetch_latest_snapshot_on_reconnect = Some(fetch);
+        self
+    }
+
+    pub fn upload_montage(mut self, upload: bool) -> Self {
+        self.config.upload_montage = Some(upload);
+        self
+    }
+
+    pub fn montage_grid(mut self, grid: MontageGrid) -> Self {
+        self.config.montage_grid = Some(grid);
+        self
+    }
+
+    /// A glob pattern of camera names, checked on `build()`
+    pub fn force_enable_camera(mut self, pattern: impl Into<String>) -> Self {
+        self.force_enable_cameras.push(pattern.into());
+        self
+    }
+
+    pub fn stream_uploads(mut self, stream: bool) -> Self {
+        self.config.stream_uploads = Some(stream);
+        self
+    }
+
+    pub fn export_recording(mut self, export: bool) -> Self {
+        self.config.export_recording = Some(export);
+        self
+    }
+
+    pub fn export_timeout(mut self, timeout: Duration) -> Self {
+        self.config.export_timeout = Some(timeout.as_secs());
+        self
+    }
+
+    /// Groups the uploaded files by camera in the given order, or not at all with None
+    pub fn camera_dirs(mut self, order: Option<CameraDirOrder>) -> Self {
+        self.config.group_recordings_by_camera = Some(order.is_some());
+        self.config.camera_dir_order = order;
+        self
+    }
+
+    pub fn date_basis(mut self, date_basis: DateBasis) -> Self {
+        self.config.date_basis = Some(date_basis);
+        self
+    }
+
+    /// Checked on `build()` to be relative, and not to leave the destination
+    pub fn recordings_subdir(mut self, subdir: impl Into<PathBuf>) -> Self {
+        self.config.recordings_subdir = Some(subdir.into());
+        self
+    }
+
+    /// Checked on `build()` to be relative, and not to leave the destination
+    pub fn snapshots_subdir(mut self, subdir: impl Into<PathBuf>) -> Self {
+        self.config.snapshots_subdir = Some(subdir.into());
+        self
+    }
+
+    pub fn recording_path_template(mut self, template: PathTemplate) -> Self {
+        self.config.recording_path_template = Some(template);
+        self
+    }
+
+    pub fn snapshot_path_template(mut self, template: PathTemplate) -> Self {
+        self.config.snapshot_path_template = Some(template);
+        self
+    }
+
+    pub fn camera_display_name(
+        mut self,
+        camera_label: impl Into<String>,
+        display_name: impl Into<String>,
+    ) -> Self {
+        self.config
+            .camera_display_names
+            .insert(camera_label.into(), display_name.into());
+        self
+    }
+
+    pub fn camera_display_name_transform(mut self, transform: CameraNameTransform) -> Self {
+        self.config.camera_display_name_transform = Some(transform);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn local_dest() -> PathDescriptor {
+        PathDescriptor::local("/tmp")
+    }
+
+    #[test]
+    fn unset_values_take_the_config_file_defaults() {
+        let built = VideoSyncConfig::builder("localhost", "http://localhost:5000")
+            .upload_destination(local_dest())
+            .build()
+            .unwrap();
+        let parsed: VideoSyncConfig = serde_yml::from_str(
+            "mqtt_host: localhost\nfrigate_api_address: http://localhost:5000\nupload_destinations:\n  - local:path=/tmp",
+        )
+        .unwrap();
+
+        assert_eq!(built, parsed);
+    }
+
+    #[rstest]
+    #[case(
+        VideoSyncConfig::builder(" ", "http://localhost:5000").upload_destination(local_dest()),
+        "mqtt_host"
+    )]
+    #[case(
+        VideoSyncConfig::builder("localhost", "").upload_destination(local_dest()),
+        "frigate_api_address"
+    )]
+    #[case(
+        VideoSyncConfig::builder("localhost", "http://localhost:5000"),
+        "Upload destinations"
+    )]
+    #[case(
+        VideoSyncConfig::builder("localhost", "http://localhost:5000")
+            .upload_destination(local_dest())
+            .force_enable_camera("front[".to_string()),
+        "front["
+    )]
+    #[case(
+        VideoSyncConfig::builder("localhost", "http://localhost:5000")
+            .upload_destination(local_dest())
+            .mqtt_password_file("/nonexistent/mqtt_password"),
+        "/nonexistent/mqtt_password"
+    )]
+    #[case(
+        VideoSyncConfig::builder("localhost", "http://localhost:5000")
+            .upload_destination(local_dest())
+            .snapshots_subdir("../snapshots"),
+        "../snapshots"
+    )]
+    fn invalid_configs_fail_to_build(
+        #[case] builder: VideoSyncConfigBuilder,
+        #[case] expected_in_error: &str,
+    ) {
+        let error = builder.build().unwrap_err();
+        assert!(
+            error.to_string().contains(expected_in_error),
+            "Unexpected error: {error}"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-mod config;
+pub mod config;
+mod montage;
 pub mod runner;
+mod snapshot_buffer;
 mod state;
+mod stats;
 pub mod system;
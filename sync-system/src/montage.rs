@@ -0,0 +1,146 @@
+use crate::system::config::MontageGrid;
+use image::{DynamicImage, RgbImage, codecs::jpeg::JpegEncoder, imageops::FilterType};
+
+/// Snapshots wider than this are scaled down to it in the montage, keeping their aspect ratio
+const MAX_CELL_WIDTH: u32 = 640;
+const MONTAGE_JPEG_QUALITY: u8 = 85;
+
+/// Composes the images, in order, into a JPEG grid filled row by row. Every cell has the size of the first
+/// image (scaled down to `MAX_CELL_WIDTH`), and the others are resized to it. Images that can't be decoded
+/// are left out, and images beyond the grid's cells are ignored.
+pub fn compose_montage(images: &[&[u8]], grid: MontageGrid) -> anyhow::Result<Vec<u8>> {
+    let images = images
+        .iter()
+        .filter_map(|bytes| {
+            image::load_from_memory(bytes)
+                .inspect_err(|e| tracing::warn!("Leaving a snapshot out of montage: {e}"))
+                .ok()
+        })
+        .take(grid.cells())
+        .collect::<Vec<_>>();
+
+    let first = images
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No snapshot to compose a montage of"))?;
+    let (cell_width, cell_height) = cell_size(first);
+
+    let count = u32::try_from(images.len()).expect("At most the grid's cells");
+    let columns = count.min(grid.columns());
+    let rows = count.div_ceil(grid.columns());
+    let mut montage = RgbImage::new(columns * cell_width, rows * cell_height);
+
+    for (index, image) in (0..).zip(&images) {
+        let cell = image
+            .resize_exact(cell_width, cell_height, FilterType::Triangle)
+            .into_rgb8();
+        let x = (index % grid.columns()) * cell_width;
+        let y = (index / grid.columns()) * cell_height;
+        image::imageops::replace(&mut montage, &cell, x.into(), y.into());
+    }
+
+    let mut result = Vec::new();
+    JpegEncoder::new_with_quality(&mut result, MONTAGE_JPEG_QUALITY).encode_image(&montage)?;
+    Ok(result)
+}
+
+fn cell_size(image: &DynamicImage) -> (u32, u32) {
+    let (width, height) = (image.width(), image.height());
+    if width <= MAX_CELL_WIDTH {
+        return (width, height);
+    }
+
+    let height = u64::from(height) * u64::from(MAX_CELL_WIDTH) / u64::from(width);
+    (
+        MAX_CELL_WIDTH,
+        u32::try_from(height).expect("Scaled down").max(1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb};
+    use rstest::rstest;
+
+    fn make_jpeg(width: u32, height: u32, color: [u8; 3]) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, Rgb(color));
+        let mut result = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut result, image::ImageFormat::Jpeg)
+            .unwrap();
+        result.into_inner()
+    }
+
+    fn assert_color_near(actual: Rgb<u8>, expected: [u8; 3]) {
+        // JPEG is lossy, so colors come back only approximately
+        for (a, e) in actual.0.iter().zip(expected) {
+            assert!(a.abs_diff(e) < 24, "{actual:?} isn't close to {expected:?}");
+        }
+    }
+
+    #[test]
+    fn montage_is_composed_row_by_row() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let images = colors
+            .iter()
+            .map(|c| make_jpeg(32, 24, *c))
+            .collect::<Vec<_>>();
+        let images = images.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let montage = compose_montage(&images, MontageGrid::new(3, 2).unwrap()).unwrap();
+
+        let montage = image::load_from_memory(&montage).unwrap().into_rgb8();
+        assert_eq!(montage.dimensions(), (3 * 32, 2 * 24));
+        // The centers of the cells, with the last one of the second row left empty
+        assert_color_near(*montage.get_pixel(16, 12), colors[0]);
+        assert_color_near(*montage.get_pixel(48, 12), colors[1]);
+        assert_color_near(*montage.get_pixel(80, 12), colors[2]);
+        assert_color_near(*montage.get_pixel(16, 36), colors[3]);
+        assert_color_near(*montage.get_pixel(48, 36), [0, 0, 0]);
+    }
+
+    #[rstest]
+    #[case(2, 3, 1, (2 * 32, 24))]
+    #[case(5, 3, 2, (3 * 32, 2 * 24))]
+    #[case(4, 1, 1, (32, 24))]
+    fn montage_has_only_the_rows_it_fills(
+        #[case] image_count: usize,
+        #[case] columns: u32,
+        #[case] rows: u32,
+        #[case] expected_size: (u32, u32),
+    ) {
+        let images = vec![make_jpeg(32, 24, [128, 128, 128]); image_count];
+        let images = images.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let montage = compose_montage(&images, MontageGrid::new(columns, rows).unwrap()).unwrap();
+
+        let montage = image::load_from_memory(&montage).unwrap();
+        assert_eq!(montage.dimensions(), expected_size);
+    }
+
+    #[test]
+    fn images_are_resized_to_the_first() {
+        let images = [
+            make_jpeg(MAX_CELL_WIDTH * 2, 480, [0, 0, 0]),
+            make_jpeg(100, 100, [0, 0, 0]),
+        ];
+        let images = images.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let montage = compose_montage(&images, MontageGrid::new(2, 1).unwrap()).unwrap();
+
+        let montage = image::load_from_memory(&montage).unwrap();
+        assert_eq!(montage.dimensions(), (2 * MAX_CELL_WIDTH, 240));
+    }
+
+    #[test]
+    fn undecodable_images_are_left_out() {
+        let valid = make_jpeg(32, 24, [0, 0, 0]);
+        let montage =
+            compose_montage(&[b"not an image", &valid], MontageGrid::new(2, 1).unwrap()).unwrap();
+        let montage = image::load_from_memory(&montage).unwrap();
+        assert_eq!(montage.dimensions(), (32, 24));
+
+        assert!(compose_montage(&[b"not an image"], MontageGrid::default()).is_err());
+        assert!(compose_montage(&[], MontageGrid::default()).is_err());
+    }
+}
@@ -1,17 +1,126 @@
-use crate::{config::VideoSyncConfig, system::SyncSystem};
-use file_sender::{make_store, path_descriptor::PathDescriptor};
-use frigate_api_caller::{config::FrigateApiConfig, make_frigate_client};
-use logging::init_logging;
-use mqtt_handler::config::MqttHandlerConfig;
-use options::run_options::start_options::StartOptions;
-use std::sync::Arc;
+use crate::{
+    config::VideoSyncConfig,
+    system::{
+        BackfillRange, FixtureFrigateApi, SyncSystem, config::SyncSystemConfig, prune_alternatives,
+        run_dead_letters_retry, run_destination_test, run_mqtt_commands, run_replay,
+        run_self_test as self_test, traits::FrigateApiMaker, with_destination_locks,
+    },
+};
+use anyhow::Context;
+use file_sender::{make_inmemory_filesystem, make_store, path_descriptor::PathDescriptor};
+use frigate_api_caller::{
+    config::FrigateApiConfig, dns::FrigateHostResolver, make_frigate_client, traits::FrigateApi,
+};
+use logging::{init_logging_with_level, shutdown_tracing};
+use mqtt_handler::{config::MqttHandlerConfig, replay::read_replay_file};
+use options::run_options::{
+    backfill_options::BackfillOptions, prune_alternatives_options::PruneAlternativesOptions,
+    resync_options::ResyncOptions, retry_dead_letters_options::RetryDeadLettersOptions,
+    self_test_options::SelfTestOptions, start_options::StartOptions,
+    test_destination_options::TestDestinationOptions,
+};
+use std::{future::Future, path::Path, sync::Arc};
+use utils::{
+    instance_lock::InstanceLock,
+    mdns::{HostResolver, MdnsResolver, strip_mdns_url},
+    systemd::{ServiceNotifier, SystemdNotifier},
+    time::get_time,
+};
 
 impl From<&VideoSyncConfig> for FrigateApiConfig {
     fn from(config: &VideoSyncConfig) -> Self {
         Self {
             frigate_api_base_url: config.frigate_api_address().to_string(),
+            api_path_prefix: config.frigate_api_path_prefix().map(ToOwned::to_owned),
             frigate_api_proxy: config.frigate_api_proxy().map(str::to_string),
-            delay_after_startup: std::time::Duration::ZERO,
+            frigate_api_proxy_auth: config.frigate_api_proxy_auth(),
+            frigate_login: config.frigate_api_login(),
+            delay_after_startup: config.delay_after_startup(),
+            resume_downloads: config.resume_downloads(),
+            clip_timestamp_precision: config.clip_timestamp_precision(),
+            clip_container: config.clip_container(),
+            clip_end_safety_margin: config.clip_end_safety_margin(),
+            max_clip_bytes: config.max_clip_bytes(),
+            bind_address: config.bind_address(),
+            compress_responses: config.frigate_api_compression(),
+            deep_clip_validation: config.deep_clip_validation(),
+            host_resolver: None,
+        }
+    }
+}
+
+/// The Frigate API config. If Frigate's address is given as `mdns:<url>`, the url is used without the prefix,
+/// and its host is resolved with mDNS on every new connection, like the broker's, so that a change of its address is followed.
+fn frigate_api_config(
+    config: &VideoSyncConfig,
+    host_resolver: Arc<dyn HostResolver>,
+) -> anyhow::Result<FrigateApiConfig> {
+    let mut frigate_api_config = FrigateApiConfig::from(config);
+    if let Some((url, hostname)) = strip_mdns_url(config.frigate_api_address())? {
+        frigate_api_config.host_resolver = Some(FrigateHostResolver::new(hostname, host_resolver));
+        frigate_api_config.frigate_api_base_url = url.to_string();
+    }
+    frigate_api_config.check_base_url()?;
+    Ok(frigate_api_config)
+}
+
+/// Makes the client of the Frigate API once, so that every call to Frigate shares its connection pool
+/// and login session, rather than making a client, and logging in, for every review
+fn shared_frigate_api_maker(
+    frigate_api_config: &FrigateApiConfig,
+) -> anyhow::Result<impl FrigateApiMaker> {
+    let frigate_api = make_frigate_client(frigate_api_config.clone())?;
+    Ok(move |_: &FrigateApiConfig| Ok(frigate_api.clone()))
+}
+
+impl From<&VideoSyncConfig> for SyncSystemConfig {
+    fn from(config: &VideoSyncConfig) -> Self {
+        Self {
+            skip_if_already_uploaded: config.skip_if_already_uploaded(),
+            shutdown_timeout: config.shutdown_timeout(),
+            heartbeat_interval: config.heartbeat_interval(),
+            audit_log_path: config.audit_log_path().map(ToOwned::to_owned),
+            dead_letter_path: config.dead_letter_path().map(ToOwned::to_owned),
+            keep_alternative_versions: config.keep_alternative_versions(),
+            enable_recordings_sync: config.enable_recordings_sync(),
+            enable_snapshots_sync: config.enable_snapshots_sync(),
+            upload_window: config.upload_window(),
+            upload_event_snapshots: config.upload_event_snapshots(),
+            min_review_duration: config.min_review_duration(),
+            review_update_debounce: config.review_update_debounce(),
+            review_idle_timeout: config.review_idle_timeout(),
+            post_end_grace: config.post_end_grace(),
+            max_task_lifetime: config.max_task_lifetime(),
+            final_only_destinations: config.final_only_upload_destinations().to_vec(),
+            file_name_charset: config.file_name_charset(),
+            snapshot_mode: config.snapshot_mode(),
+            maintain_latest_snapshot: config.maintain_latest_snapshot(),
+            max_snapshot_tasks: config.max_snapshot_tasks(),
+            fetch_latest_snapshot_on_reconnect: config.fetch_latest_snapshot_on_reconnect(),
+            upload_montage: config.upload_montage(),
+            montage_grid: config.montage_grid(),
+            force_enable_cameras: config.force_enable_cameras().to_vec(),
+            stream_uploads: config.stream_uploads(),
+            export_recording: config.export_recording(),
+            export_timeout: config.export_timeout(),
+            gate_on_stats_failure: config.gate_on_stats_failure(),
+            camera_dirs: config.camera_dirs(),
+            date_basis: config.date_basis(),
+            recordings_subdir: config.recordings_subdir().to_owned(),
+            snapshots_subdir: config.snapshots_subdir().to_owned(),
+            recording_path_template: config.recording_path_template().cloned(),
+            snapshot_path_template: config.snapshot_path_template().cloned(),
+            camera_display_names: config.camera_display_names(),
+            require_all_destinations: config.require_all_destinations(),
+            destination_retry_period: config.destination_retry_period(),
+            destination_lock: config.destination_lock(),
+            instance_id: config.instance_id(),
+            recording_retry: config.recording_retry(),
+            camera_recording_retries: config.camera_recording_retries(),
+            snapshot_retry: config.snapshot_retry(),
+            file_op_retry: config.file_op_retry(),
+            mqtt_upload_topic_prefix: config.mqtt_upload_topic_prefix().to_string(),
+            mqtt_upload_retain: config.mqtt_upload_retain(),
         }
     }
 }
@@ -26,6 +135,11 @@ impl From<&VideoSyncConfig> for MqttHandlerConfig {
             mqtt_username: config.mqtt_username().map(ToOwned::to_owned),
             mqtt_password: config.mqtt_password().map(ToOwned::to_owned),
             mqtt_client_id: config.mqtt_client_id().to_string(),
+            mqtt_clean_session: config.mqtt_clean_session(),
+            mqtt_inflight: config.mqtt_inflight(),
+            // Only enabled by giving the host as an `mqtts://` url
+            mqtt_tls: false,
+            mqtt_command_topic: config.mqtt_command_topic(),
         }
     }
 }
@@ -33,13 +147,29 @@ impl From<&VideoSyncConfig> for MqttHandlerConfig {
 pub async fn run(options: StartOptions) -> anyhow::Result<()> {
     const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-    init_logging();
+    init_logging_with_level(options.config_file.log_level);
 
     tracing::info!("Starting Snap Sync. Version: {}", PROGRAM_VERSION);
 
-    let config = VideoSyncConfig::from_file_or_default(options.config_file_path)?;
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    // Held until the program exits, so that another instance with the same lock file refuses to start
+    let _instance_lock = config
+        .instance_lock_file()
+        .map(InstanceLock::acquire)
+        .transpose()?;
 
-    let frigate_api_maker = move |cfg: &FrigateApiConfig| make_frigate_client(cfg.clone());
+    if let (Some(replay_file), Some(clip_file)) = (&options.replay, &options.replay_clip) {
+        let result = replay_recorded_messages(&config, replay_file, clip_file).await;
+        shutdown_tracing();
+        return result;
+    }
+
+    // Shared, so that Frigate and the broker use the same mDNS daemon and resolved addresses
+    let host_resolver: Arc<dyn HostResolver> = Arc::new(MdnsResolver::default());
+
+    let frigate_api_config = frigate_api_config(&config, host_resolver.clone())?;
+    let frigate_api_maker = shared_frigate_api_maker(&frigate_api_config)?;
     let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
 
     let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
@@ -54,28 +184,347 @@ pub async fn run(options: StartOptions) -> anyhow::Result<()> {
     })
     .expect("Error setting Ctrl+C handler");
 
+    // Running under systemd with `Type=notify`, it's told once connected to mqtt, and its watchdog is notified if enabled
+    let service_notifier = SystemdNotifier::from_env().map(|notifier| {
+        tracing::info!("Notifying systemd of the service state");
+        Arc::new(notifier) as Arc<dyn ServiceNotifier>
+    });
+
     {
         let mqtt_config = MqttHandlerConfig::from(&config);
 
         let (mqtt_data_sender, mqtt_data_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (publication_sender, publication_receiver) =
+            if config.mqtt_publish_uploads() || config.mqtt_commands() {
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                (Some(sender), Some(receiver))
+            } else {
+                (None, None)
+            };
+
+        // Commands from mqtt are run through the command channel of the sync system, which publishes their results
+        let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (mqtt_command_sender, mqtt_commands_task) = match &publication_sender {
+            Some(publication_sender) if config.mqtt_commands() => {
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                let task = tokio::spawn(run_mqtt_commands(
+                    receiver,
+                    command_sender.clone(),
+                    publication_sender.clone(),
+                    config.mqtt_upload_topic_prefix().to_string(),
+                ));
+                (Some(sender), Some(task))
+            }
+            _ => (None, None),
+        };
 
-        let mut mqtt_handler = mqtt_handler::MqttHandler::new(mqtt_config, mqtt_data_sender)?;
+        let mut mqtt_handler = mqtt_handler::MqttHandler::new(
+            mqtt_config,
+            mqtt_data_sender,
+            publication_receiver,
+            mqtt_command_sender,
+            host_resolver,
+            service_notifier.clone(),
+        )
+        .await?;
 
-        let sync_sys = SyncSystem::new(
+        let mut sync_sys = SyncSystem::new(
             config.upload_destinations().clone(),
-            Arc::new(FrigateApiConfig::from(&config)),
+            Arc::new(frigate_api_config),
+            Arc::new(SyncSystemConfig::from(&config)),
             frigate_api_maker,
             file_sender_maker,
             mqtt_data_receiver,
+            publication_sender.filter(|_| config.mqtt_publish_uploads()),
             None,
+            Some(command_receiver),
             Some(stop_receiver),
-        );
+        )
+        .with_mqtt_connection_status(mqtt_handler.connection_status());
+        if let Some(notifier) = service_notifier {
+            sync_sys =
+                sync_sys.with_service_notifier(notifier, SystemdNotifier::watchdog_interval());
+        }
 
         sync_sys.start().await?;
 
+        if let Some(task) = mqtt_commands_task {
+            task.abort();
+        }
         mqtt_handler.stop();
         mqtt_handler.wait().await;
     }
 
+    shutdown_tracing();
+
+    Ok(())
+}
+
+/// Replays the recorded mqtt messages in `replay_file` to the upload destinations of the config, with the clip
+/// in `clip_file` as the recording of every review, without connecting to MQTT or Frigate.
+async fn replay_recorded_messages(
+    config: &VideoSyncConfig,
+    replay_file: &Path,
+    clip_file: &Path,
+) -> anyhow::Result<()> {
+    let payloads = read_replay_file(&MqttHandlerConfig::from(config), replay_file)?;
+    let clip = std::fs::read(clip_file)
+        .with_context(|| format!("Reading replay clip `{}`", clip_file.display()))?;
+
+    let frigate_api: Arc<dyn FrigateApi> = Arc::new(FixtureFrigateApi::new(clip));
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api.clone());
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    run_replay(
+        payloads,
+        config.upload_destinations().clone(),
+        Arc::new(FrigateApiConfig::from(config)),
+        Arc::new(SyncSystemConfig::from(config)),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await
+}
+
+/// Runs a command that writes to the upload destinations with the same locks as `start`, so that it refuses to run
+/// while an instance, or another command, uses them, rather than overwriting the alternative versions of its recordings.
+async fn run_locked<T>(
+    config: &VideoSyncConfig,
+    command: &str,
+    body: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    // Held until the command is done
+    let _instance_lock = config
+        .instance_lock_file()
+        .map(InstanceLock::acquire)
+        .transpose()?;
+
+    if !config.destination_lock() {
+        return body.await;
+    }
+
+    // Not the instance's own id, since a lock object with it is taken back, which would take it from a running instance
+    let owner = format!("{} ({command})", config.instance_id());
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+    with_destination_locks(
+        &file_sender_maker,
+        &config.upload_destinations().path_descriptors,
+        &owner,
+        body,
+    )
+    .await
+}
+
+/// Uploads the recordings of the events within the given range, without connecting to MQTT, then exits.
+pub async fn run_backfill(options: BackfillOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.config_file.log_level);
+
+    tracing::info!(
+        "Starting backfill of events within [{},{}]",
+        options.after,
+        options.before
+    );
+
+    if options.after >= options.before {
+        return Err(anyhow::anyhow!(
+            "The start of the backfill range ({}) must be before its end ({})",
+            options.after,
+            options.before
+        ));
+    }
+
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    let range = BackfillRange {
+        after: options.after,
+        before: options.before,
+        cameras: options.cameras,
+    };
+
+    let result = run_locked(
+        &config,
+        "backfill",
+        backfill(&config, &range, SyncSystemConfig::from(&config)),
+    )
+    .await;
+
+    shutdown_tracing();
+
+    result
+}
+
+/// Uploads the recordings of the events of the last hours that aren't already in all the upload destinations,
+/// e.g., after fixing a destination, then exits.
+pub async fn run_resync(options: ResyncOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.config_file.log_level);
+
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    let range = BackfillRange::last(
+        std::time::Duration::from_secs(options.hours.saturating_mul(60 * 60)),
+        get_time(),
+        options.cameras,
+    );
+
+    tracing::info!(
+        "Starting resync of the events of the last {} hours, within [{},{}]",
+        options.hours,
+        range.after,
+        range.before
+    );
+
+    // Whatever is already in all the destinations is skipped, so only what's missing is uploaded
+    let sync_config = SyncSystemConfig {
+        skip_if_already_uploaded: true,
+        ..SyncSystemConfig::from(&config)
+    };
+    let result = run_locked(&config, "resync", backfill(&config, &range, sync_config)).await;
+
+    shutdown_tracing();
+
+    result
+}
+
+async fn backfill(
+    config: &VideoSyncConfig,
+    range: &BackfillRange,
+    sync_config: SyncSystemConfig,
+) -> anyhow::Result<()> {
+    let frigate_api_config = frigate_api_config(config, Arc::new(MdnsResolver::default()))?;
+    let frigate_api_maker = shared_frigate_api_maker(&frigate_api_config)?;
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    crate::system::run_backfill(
+        range,
+        config.upload_destinations().clone(),
+        Arc::new(frigate_api_config),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Uploads the recordings of the reviews in the dead-letter file, e.g., after an outage of a destination, then exits.
+/// The reviews that fail again are recorded in the file again.
+pub async fn run_retry_dead_letters(options: RetryDeadLettersOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.config_file.log_level);
+
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    let result = run_locked(&config, "retry-dead-letters", retry_dead_letters(&config)).await;
+
+    shutdown_tracing();
+
+    result
+}
+
+async fn retry_dead_letters(config: &VideoSyncConfig) -> anyhow::Result<()> {
+    let frigate_api_config = frigate_api_config(config, Arc::new(MdnsResolver::default()))?;
+    let frigate_api_maker = shared_frigate_api_maker(&frigate_api_config)?;
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    // A review may have been uploaded since it was recorded, e.g., with a resync, so what's complete is skipped
+    let sync_config = SyncSystemConfig {
+        skip_if_already_uploaded: true,
+        ..SyncSystemConfig::from(config)
+    };
+
+    run_dead_letters_retry(
+        config.upload_destinations().clone(),
+        Arc::new(frigate_api_config),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Downloads the recording of a recent event from Frigate and uploads it to memory, to check the config and
+/// connectivity of a deployment without writing to the upload destinations, then exits.
+pub async fn run_self_test(options: SelfTestOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.config_file.log_level);
+
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    let frigate_api_maker =
+        move |cfg: &FrigateApiConfig| make_frigate_client(cfg.clone()).map_err(Into::into);
+
+    let report = self_test(
+        &frigate_api_config(&config, Arc::new(MdnsResolver::default()))?,
+        std::time::Duration::from_secs(options.lookback_hours * 60 * 60),
+        frigate_api_maker,
+        make_inmemory_filesystem(),
+    )
+    .await;
+
+    // Every step has already been logged as it ran
+    shutdown_tracing();
+
+    if report.is_success() {
+        tracing::info!("Self-test succeeded");
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Self-test failed"))
+    }
+}
+
+/// Writes a file to the given destination, reads it back and deletes it, to check a destination
+/// before adding it to the config, then exits.
+pub async fn run_test_destination(options: TestDestinationOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.log_level);
+
+    let report = run_destination_test(&options.descriptor, make_store).await;
+
+    // Every step has already been logged as it ran
+    shutdown_tracing();
+
+    if report.is_success() {
+        tracing::info!("Destination test succeeded");
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Destination test failed"))
+    }
+}
+
+/// Deletes the stale version of every recording of which both alternative versions are in the upload
+/// destinations, then exits. These are left behind when the program stops between uploading a version
+/// and deleting the other.
+pub async fn run_prune_alternatives(options: PruneAlternativesOptions) -> anyhow::Result<()> {
+    init_logging_with_level(options.config_file.log_level);
+
+    let config = VideoSyncConfig::from_file_or_default(options.config_file.config_file_path)?;
+
+    // A dry run doesn't write to the destinations, so it can run alongside an instance
+    let result = if options.dry_run {
+        prune_all_destinations(&config, true).await
+    } else {
+        run_locked(
+            &config,
+            "prune-alternatives",
+            prune_all_destinations(&config, false),
+        )
+        .await
+    };
+
+    shutdown_tracing();
+
+    result
+}
+
+async fn prune_all_destinations(config: &VideoSyncConfig, dry_run: bool) -> anyhow::Result<()> {
+    for path_descriptor in config.upload_destinations().path_descriptors.iter() {
+        let store = make_store(path_descriptor)?;
+        store.init().await?;
+
+        let report = prune_alternatives(store.as_ref(), dry_run).await?;
+        tracing::info!(
+            "Found {} recordings with both alternative versions in `{path_descriptor}`",
+            report.pairs.len()
+        );
+    }
+
     Ok(())
 }
@@ -0,0 +1,241 @@
+use mqtt_handler::types::snapshot::Snapshot;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+/// Snapshots older than this, relative to the newest one of their camera, are dropped
+const MAX_SNAPSHOT_AGE_SECONDS: f64 = 60.;
+/// At most this many snapshots are kept per camera, the oldest are dropped first
+const MAX_SNAPSHOTS_PER_CAMERA: usize = 32;
+/// A snapshot is only correlated with a review if it was received this close to the review's start
+const MAX_DISTANCE_FROM_REVIEW_START_SECONDS: f64 = 30.;
+/// At most this many ids of reviews that got a snapshot are remembered, in case their end messages are missed
+const MAX_REVIEWS_WITH_SNAPSHOT: usize = 256;
+
+/// The recent snapshots of each camera, with the unix timestamps they were received at,
+/// so that a review can be matched with the snapshot taken closest to its start, or with those taken during it.
+#[derive(Debug, Default)]
+pub struct SnapshotBuffer {
+    cameras: HashMap<String, VecDeque<(f64, Arc<Snapshot>)>>,
+    /// The ids of the ongoing reviews a snapshot was already taken for, oldest first
+    reviews_with_snapshot: VecDeque<String>,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, snapshot: Arc<Snapshot>, received_at: f64) {
+        let buffer = self
+            .cameras
+            .entry(snapshot.camera_label.clone())
+            .or_default();
+        buffer.push_back((received_at, snapshot));
+
+        while buffer.len() > MAX_SNAPSHOTS_PER_CAMERA {
+            buffer.pop_front();
+        }
+        while buffer
+            .front()
+            .is_some_and(|(t, _)| received_at - t > MAX_SNAPSHOT_AGE_SECONDS)
+        {
+            buffer.pop_front();
+        }
+    }
+
+    /// Remove and return the snapshot of the camera received closest to the given review start time,
+    /// or None if no snapshot was received close enough to it
+    pub fn take_closest(&mut self, camera_name: &str, start_time: f64) -> Option<Arc<Snapshot>> {
+        let buffer = self.cameras.get_mut(camera_name)?;
+
+        let (index, _) = buffer
+            .iter()
+            .map(|(t, _)| (t - start_time).abs())
+            .enumerate()
+            .filter(|(_, distance)| *distance <= MAX_DISTANCE_FROM_REVIEW_START_SECONDS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        buffer.remove(index).map(|(_, snapshot)| snapshot)
+    }
+
+    /// Like `take_closest`, but only once per review, so that a review with many messages gets one snapshot.
+    /// Returns None for a review that already got one, until it's forgotten with `forget_review`.
+    pub fn take_closest_for_review(
+        &mut self,
+        review_id: &str,
+        camera_name: &str,
+        start_time: f64,
+    ) -> Option<Arc<Snapshot>> {
+        if self.reviews_with_snapshot.iter().any(|id| id == review_id) {
+            return None;
+        }
+
+        let snapshot = self.take_closest(camera_name, start_time)?;
+
+        self.reviews_with_snapshot.push_back(review_id.to_string());
+        while self.reviews_with_snapshot.len() > MAX_REVIEWS_WITH_SNAPSHOT {
+            self.reviews_with_snapshot.pop_front();
+        }
+
+        Some(snapshot)
+    }
+
+    /// Forget that the review got a snapshot, once it ended
+    pub fn forget_review(&mut self, review_id: &str) {
+        self.reviews_with_snapshot.retain(|id| id != review_id);
+    }
+
+    /// Up to `count` of the snapshots of the camera received within the given times, spread evenly over them
+    /// if there are more, in the order they were received. They're kept in the buffer.
+    pub fn spanning(
+        &self,
+        camera_name: &str,
+        start_time: f64,
+        end_time: f64,
+        count: usize,
+    ) -> Vec<Arc<Snapshot>> {
+        let Some(buffer) = self.cameras.get(camera_name) else {
+            return Vec::new();
+        };
+
+        let within = buffer
+            .iter()
+            .filter(|(t, _)| (start_time..=end_time).contains(t))
+            .map(|(_, snapshot)| snapshot.clone())
+            .collect::<Vec<_>>();
+        if within.len() <= count {
+            return within;
+        }
+        if count <= 1 {
+            return within.into_iter().take(count).collect();
+        }
+
+        // The first and the last are always picked, and the rest are equally apart between them
+        (0..count)
+            .map(|i| within[i * (within.len() - 1) / (count - 1)].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(camera: &str, object: &str) -> Arc<Snapshot> {
+        Arc::new(Snapshot {
+            image_bytes: Vec::new(),
+            camera_label: camera.to_string(),
+            object_name: Some(object.to_string()),
+        })
+    }
+
+    #[test]
+    fn closest_snapshot_of_the_camera_is_taken() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(snapshot("front", "early"), 100.);
+        buffer.push(snapshot("front", "close"), 109.);
+        buffer.push(snapshot("back", "other_camera"), 110.);
+        buffer.push(snapshot("front", "late"), 125.);
+
+        let taken = buffer.take_closest("front", 110.).unwrap();
+        assert_eq!(taken.object_name.as_deref(), Some("close"));
+
+        // A taken snapshot isn't uploaded again for another review
+        let taken = buffer.take_closest("front", 110.).unwrap();
+        assert_eq!(taken.object_name.as_deref(), Some("early"));
+
+        assert!(buffer.take_closest("side", 110.).is_none());
+    }
+
+    #[test]
+    fn review_gets_one_snapshot() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(snapshot("front", "first"), 100.);
+        buffer.push(snapshot("front", "second"), 101.);
+
+        let taken = buffer
+            .take_closest_for_review("review", "front", 100.)
+            .unwrap();
+        assert_eq!(taken.object_name.as_deref(), Some("first"));
+        assert!(
+            buffer
+                .take_closest_for_review("review", "front", 100.)
+                .is_none()
+        );
+
+        // Other reviews still get theirs
+        let taken = buffer
+            .take_closest_for_review("other", "front", 100.)
+            .unwrap();
+        assert_eq!(taken.object_name.as_deref(), Some("second"));
+
+        buffer.push(snapshot("front", "third"), 102.);
+        buffer.forget_review("review");
+        let taken = buffer
+            .take_closest_for_review("review", "front", 100.)
+            .unwrap();
+        assert_eq!(taken.object_name.as_deref(), Some("third"));
+    }
+
+    #[test]
+    fn snapshots_far_from_review_start_are_not_correlated() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(snapshot("front", "old"), 100.);
+
+        assert!(
+            buffer
+                .take_closest("front", 100. + MAX_DISTANCE_FROM_REVIEW_START_SECONDS + 1.)
+                .is_none()
+        );
+        assert!(buffer.take_closest("front", 100.).is_some());
+    }
+
+    #[test]
+    fn snapshots_spanning_review_are_spread_over_it() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(snapshot("front", "before"), 99.);
+        for i in 0..10 {
+            buffer.push(snapshot("front", &i.to_string()), 100. + f64::from(i));
+        }
+        buffer.push(snapshot("back", "other_camera"), 105.);
+        buffer.push(snapshot("front", "after"), 111.);
+
+        let names = |snapshots: Vec<Arc<Snapshot>>| {
+            snapshots
+                .iter()
+                .map(|s| s.object_name.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(buffer.spanning("front", 100., 109., 4)),
+            ["0", "3", "6", "9"]
+        );
+        assert_eq!(
+            names(buffer.spanning("front", 104., 106., 4)),
+            ["4", "5", "6"]
+        );
+        assert_eq!(names(buffer.spanning("front", 100., 109., 1)), ["0"]);
+        assert!(buffer.spanning("side", 100., 109., 4).is_empty());
+
+        // Snapshots are kept for other reviews
+        assert_eq!(buffer.cameras["front"].len(), 12);
+    }
+
+    #[test]
+    fn buffer_drops_old_snapshots() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(snapshot("front", "expired"), 100.);
+        buffer.push(
+            snapshot("front", "recent"),
+            100. + MAX_SNAPSHOT_AGE_SECONDS + 1.,
+        );
+        assert_eq!(buffer.cameras["front"].len(), 1);
+        assert_eq!(
+            buffer.cameras["front"][0].1.object_name.as_deref(),
+            Some("recent")
+        );
+
+        for i in 0..MAX_SNAPSHOTS_PER_CAMERA + 5 {
+            buffer.push(snapshot("back", &i.to_string()), 200.);
+        }
+        assert_eq!(buffer.cameras["back"].len(), MAX_SNAPSHOTS_PER_CAMERA);
+    }
+}
@@ -1,39 +1,127 @@
-use std::collections::HashMap;
+use frigate_api_caller::json::frigate_config::RetainMode;
+use std::collections::{HashMap, HashSet};
 
 const DEFAULT_CAMERA_RECORDINGS_STATE: bool = false;
 const DEFAULT_CAMERA_SNAPSHOTS_STATE: bool = false;
 
+/// How long after Frigate is back online the cameras it hasn't republished are pruned. Frigate may announce
+/// that it's online before it republishes the states of its cameras.
+pub const CAMERA_REPUBLISH_GRACE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Which of the states of a camera in Frigate changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraStateKind {
+    Recordings,
+    Snapshots,
+}
+
+/// A change of a camera's state in Frigate, as published by Frigate.
+/// Cameras whose sync is forced still report the state they have in Frigate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraStateChange {
+    pub camera_name: String,
+    pub kind: CameraStateKind,
+    /// None if the state of the camera wasn't known before, like at startup
+    pub old_state: Option<bool>,
+    pub new_state: bool,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct CamerasState {
     cameras_recordings_state: HashMap<String, bool>,
     cameras_snapshots_state: HashMap<String, bool>,
+    /// From Frigate's config, which recording segments it keeps for each camera
+    cameras_retain_modes: HashMap<String, RetainMode>,
+    /// Cameras whose state has been updated since Frigate went offline, until the ones that weren't are pruned.
+    /// None otherwise.
+    updated_since_frigate_offline: Option<HashSet<String>>,
+    /// Cameras matching any of these are synced regardless of their state in Frigate
+    force_enabled_cameras: Vec<glob::Pattern>,
 }
 
 impl CamerasState {
+    #[must_use]
+    pub fn new(force_enabled_cameras: Vec<glob::Pattern>) -> Self {
+        Self {
+            force_enabled_cameras,
+            ..Self::default()
+        }
+    }
+
     pub fn camera_recordings_state(&self, camera_name: impl AsRef<str>) -> bool {
-        self.cameras_recordings_state
-            .get(camera_name.as_ref())
-            .copied()
-            .unwrap_or(DEFAULT_CAMERA_RECORDINGS_STATE)
+        self.is_force_enabled(camera_name.as_ref())
+            || self
+                .cameras_recordings_state
+                .get(camera_name.as_ref())
+                .copied()
+                .unwrap_or(DEFAULT_CAMERA_RECORDINGS_STATE)
     }
 
     pub fn camera_snapshots_state(&self, camera_name: impl AsRef<str>) -> bool {
-        self.cameras_snapshots_state
-            .get(camera_name.as_ref())
-            .copied()
-            .unwrap_or(DEFAULT_CAMERA_SNAPSHOTS_STATE)
+        self.is_force_enabled(camera_name.as_ref())
+            || self
+                .cameras_snapshots_state
+                .get(camera_name.as_ref())
+                .copied()
+                .unwrap_or(DEFAULT_CAMERA_SNAPSHOTS_STATE)
+    }
+
+    fn is_force_enabled(&self, camera_name: &str) -> bool {
+        self.force_enabled_cameras
+            .iter()
+            .any(|pattern| pattern.matches(camera_name))
     }
 
-    pub fn update_recordings_state(&mut self, camera_name: impl Into<String>, value: bool) {
+    /// Returns the change, if the state is different from the one known before
+    pub fn update_recordings_state(
+        &mut self,
+        camera_name: impl Into<String>,
+        value: bool,
+    ) -> Option<CameraStateChange> {
         let camera_name = camera_name.into();
         tracing::debug!("Updating recordings state of camera `{camera_name}` to `{value}`");
-        self.cameras_recordings_state.insert(camera_name, value);
+        self.mark_updated(&camera_name);
+        let old_state = self
+            .cameras_recordings_state
+            .insert(camera_name.clone(), value);
+        state_change(camera_name, CameraStateKind::Recordings, old_state, value)
     }
 
-    pub fn update_snapshots_state(&mut self, camera_name: impl Into<String>, value: bool) {
+    /// Returns the change, if the state is different from the one known before
+    pub fn update_snapshots_state(
+        &mut self,
+        camera_name: impl Into<String>,
+        value: bool,
+    ) -> Option<CameraStateChange> {
         let camera_name = camera_name.into();
         tracing::debug!("Updating snapshots state of camera `{camera_name}` to `{value}`");
-        self.cameras_snapshots_state.insert(camera_name, value);
+        self.mark_updated(&camera_name);
+        let old_state = self
+            .cameras_snapshots_state
+            .insert(camera_name.clone(), value);
+        state_change(camera_name, CameraStateKind::Snapshots, old_state, value)
+    }
+
+    pub fn update_retain_modes(&mut self, retain_modes: HashMap<String, RetainMode>) {
+        tracing::debug!("Updating recordings retain modes of cameras to `{retain_modes:?}`");
+        self.cameras_retain_modes = retain_modes;
+    }
+
+    /// Whether Frigate may have kept the recording of a review of the given camera, given whether objects
+    /// were tracked or audio was detected in it. Objects and audio are only detected where there's motion,
+    /// so a review without either may have neither motion nor active objects. If the retain mode
+    /// or what was detected is unknown, the recording is assumed kept.
+    pub fn camera_review_recording_retained(
+        &self,
+        camera_name: impl AsRef<str>,
+        review_has_activity: Option<bool>,
+    ) -> bool {
+        match self.cameras_retain_modes.get(camera_name.as_ref()) {
+            None | Some(RetainMode::All) => true,
+            Some(RetainMode::Motion | RetainMode::ActiveObjects) => {
+                review_has_activity != Some(false)
+            }
+        }
     }
 
     pub fn recordings_state(&self) -> &HashMap<String, bool> {
@@ -43,4 +131,187 @@ impl CamerasState {
     pub fn snapshots_state(&self) -> &HashMap<String, bool> {
         &self.cameras_snapshots_state
     }
+
+    /// Once Frigate is back online, the cameras it doesn't republish are pruned with `prune_unpublished_cameras`
+    pub fn update_frigate_availability(&mut self, online: bool) {
+        if !online && self.updated_since_frigate_offline.is_none() {
+            self.updated_since_frigate_offline = Some(HashSet::new());
+        }
+    }
+
+    /// Whether Frigate went offline, and the cameras it doesn't republish are yet to be pruned
+    pub fn awaits_republished_cameras(&self) -> bool {
+        self.updated_since_frigate_offline.is_some()
+    }
+
+    /// Frigate republishes the state of all its cameras when it restarts, so once it's been back online
+    /// for a while, cameras that haven't been republished since it went offline don't exist anymore.
+    pub fn prune_unpublished_cameras(&mut self) {
+        let Some(updated) = self.updated_since_frigate_offline.take() else {
+            return;
+        };
+
+        for camera_name in self
+            .cameras_recordings_state
+            .keys()
+            .chain(self.cameras_snapshots_state.keys())
+            .filter(|c| !updated.contains(*c))
+            .collect::<HashSet<_>>()
+        {
+            tracing::info!(
+                "Removing the state of camera `{camera_name}`, as Frigate has restarted without it"
+            );
+        }
+
+        self.cameras_recordings_state
+            .retain(|camera_name, _| updated.contains(camera_name));
+        self.cameras_snapshots_state
+            .retain(|camera_name, _| updated.contains(camera_name));
+    }
+
+    fn mark_updated(&mut self, camera_name: &str) {
+        if let Some(updated) = self.updated_since_frigate_offline.as_mut() {
+            updated.insert(camera_name.to_string());
+        }
+    }
+}
+
+fn state_change(
+    camera_name: String,
+    kind: CameraStateKind,
+    old_state: Option<bool>,
+    new_state: bool,
+) -> Option<CameraStateChange> {
+    (old_state != Some(new_state)).then_some(CameraStateChange {
+        camera_name,
+        kind,
+        old_state,
+        new_state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&["front_*"], "front_door", true)]
+    #[case(&["front_*"], "front_", true)]
+    #[case(&["front_*"], "front", false)]
+    #[case(&["front_*"], "back_front_door", false)]
+    #[case(&["cam?"], "cam1", true)]
+    #[case(&["cam?"], "cam12", false)]
+    #[case(&["cam[0-3]"], "cam2", true)]
+    #[case(&["cam[0-3]"], "cam7", false)]
+    #[case(&["garage", "*_yard"], "garage", true)]
+    #[case(&["garage", "*_yard"], "back_yard", true)]
+    #[case(&["garage", "*_yard"], "garage_door", false)]
+    #[case(&[], "front_door", false)]
+    fn force_enabled_cameras_override_frigate_state(
+        #[case] patterns: &[&str],
+        #[case] camera_name: &str,
+        #[case] expected: bool,
+    ) {
+        let patterns = patterns
+            .iter()
+            .map(|p| glob::Pattern::new(p).unwrap())
+            .collect();
+        let mut state = CamerasState::new(patterns);
+
+        assert_eq!(state.camera_recordings_state(camera_name), expected);
+        assert_eq!(state.camera_snapshots_state(camera_name), expected);
+
+        // Disabling the camera in Frigate doesn't affect a forced camera
+        state.update_recordings_state(camera_name, false);
+        state.update_snapshots_state(camera_name, false);
+        assert_eq!(state.camera_recordings_state(camera_name), expected);
+        assert_eq!(state.camera_snapshots_state(camera_name), expected);
+
+        state.update_recordings_state(camera_name, true);
+        assert!(state.camera_recordings_state(camera_name));
+    }
+
+    #[test]
+    fn frigate_restart_prunes_removed_cameras() {
+        let mut state = CamerasState::default();
+        state.update_recordings_state("kept", true);
+        state.update_snapshots_state("kept", true);
+        state.update_recordings_state("removed", true);
+        state.update_snapshots_state("removed", true);
+
+        // Being online from the start changes nothing
+        state.update_frigate_availability(true);
+        assert!(!state.awaits_republished_cameras());
+        state.prune_unpublished_cameras();
+        assert!(state.camera_recordings_state("removed"));
+
+        // Frigate restarts and only republishes one camera
+        state.update_frigate_availability(false);
+        state.update_recordings_state("kept", true);
+        state.update_snapshots_state("kept", false);
+        state.update_frigate_availability(true);
+        assert!(state.awaits_republished_cameras());
+        state.prune_unpublished_cameras();
+
+        assert!(state.camera_recordings_state("kept"));
+        assert!(!state.camera_snapshots_state("kept"));
+        assert_eq!(state.recordings_state().len(), 1);
+        assert_eq!(state.snapshots_state().len(), 1);
+        assert!(!state.camera_recordings_state("removed"));
+        assert!(!state.camera_snapshots_state("removed"));
+
+        // Once pruned, updates are no longer tracked, and nothing else is pruned
+        state.update_recordings_state("new", true);
+        state.prune_unpublished_cameras();
+        assert!(state.camera_recordings_state("new"));
+        assert!(state.camera_recordings_state("kept"));
+    }
+
+    #[test]
+    fn cameras_republished_after_frigate_is_online_are_kept() {
+        let mut state = CamerasState::default();
+        state.update_recordings_state("front", true);
+        state.update_snapshots_state("back", true);
+
+        // Frigate announces it's online before republishing its cameras
+        state.update_frigate_availability(false);
+        state.update_frigate_availability(true);
+        assert!(state.camera_recordings_state("front"));
+        assert!(state.camera_snapshots_state("back"));
+
+        state.update_recordings_state("front", true);
+        state.update_snapshots_state("back", true);
+        state.prune_unpublished_cameras();
+
+        assert!(state.camera_recordings_state("front"));
+        assert!(state.camera_snapshots_state("back"));
+        assert!(!state.awaits_republished_cameras());
+    }
+
+    #[test]
+    fn motion_retain_mode_skips_reviews_without_objects() {
+        let mut state = CamerasState::default();
+
+        // Nothing is skipped before the retain modes are known
+        assert!(state.camera_review_recording_retained("motion", Some(false)));
+
+        state.update_retain_modes(HashMap::from([
+            ("all".to_string(), RetainMode::All),
+            ("motion".to_string(), RetainMode::Motion),
+            ("objects".to_string(), RetainMode::ActiveObjects),
+        ]));
+
+        assert!(state.camera_review_recording_retained("all", Some(false)));
+        assert!(state.camera_review_recording_retained("all", Some(true)));
+        assert!(!state.camera_review_recording_retained("motion", Some(false)));
+        assert!(state.camera_review_recording_retained("motion", Some(true)));
+        assert!(!state.camera_review_recording_retained("objects", Some(false)));
+        assert!(state.camera_review_recording_retained("objects", Some(true)));
+        assert!(state.camera_review_recording_retained("unknown", Some(false)));
+
+        // Not knowing what was detected doesn't skip it
+        assert!(state.camera_review_recording_retained("motion", None));
+        assert!(state.camera_review_recording_retained("objects", None));
+    }
 }
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use utils::time::Time;
+
+/// What has been uploaded for a single camera since the system started.
+/// Times are serialized as unix timestamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct CameraUploadStats {
+    /// Reviews whose recording has been fully uploaded
+    pub recordings_uploaded: u64,
+    #[serde(serialize_with = "serialize_time")]
+    pub last_recording_upload: Option<Time>,
+    pub snapshots_uploaded: u64,
+    #[serde(serialize_with = "serialize_time")]
+    pub last_snapshot_upload: Option<Time>,
+    /// Snapshots that were dropped without an upload attempt, because too many were waiting for their turn
+    pub snapshots_dropped: u64,
+}
+
+#[allow(clippy::ref_option)] // The signature is required by serde
+fn serialize_time<S: serde::Serializer>(time: &Option<Time>, s: S) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&time.map(|t| t.as_unix_timestamp_f64()), s)
+}
+
+/// Serialized as the stats of each camera, by its name
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(transparent)]
+pub struct UploadStats {
+    cameras: HashMap<String, CameraUploadStats>,
+}
+
+impl UploadStats {
+    pub fn camera(&self, camera_name: impl AsRef<str>) -> CameraUploadStats {
+        self.cameras
+            .get(camera_name.as_ref())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn cameras(&self) -> &HashMap<String, CameraUploadStats> {
+        &self.cameras
+    }
+
+    pub fn record_recording_upload(&mut self, camera_name: impl Into<String>, time: Time) {
+        let stats = self.cameras.entry(camera_name.into()).or_default();
+        stats.recordings_uploaded += 1;
+        stats.last_recording_upload = Some(time);
+    }
+
+    pub fn record_snapshot_upload(&mut self, camera_name: impl Into<String>, time: Time) {
+        let stats = self.cameras.entry(camera_name.into()).or_default();
+        stats.snapshots_uploaded += 1;
+        stats.last_snapshot_upload = Some(time);
+    }
+
+    pub fn record_snapshot_drop(&mut self, camera_name: impl Into<String>) {
+        self.cameras
+            .entry(camera_name.into())
+            .or_default()
+            .snapshots_dropped += 1;
+    }
+}
+
+/// Upload stats that are updated by the upload handlers as uploads finish, and read by the sync system
+#[derive(Debug, Clone, Default)]
+pub struct SharedUploadStats {
+    stats: Arc<Mutex<UploadStats>>,
+}
+
+impl SharedUploadStats {
+    pub fn record_recording_upload(&self, camera_name: impl Into<String>) {
+        self.lock()
+            .record_recording_upload(camera_name, utils::time::get_time());
+    }
+
+    pub fn record_snapshot_upload(&self, camera_name: impl Into<String>) {
+        self.lock()
+            .record_snapshot_upload(camera_name, utils::time::get_time());
+    }
+
+    pub fn record_snapshot_drop(&self, camera_name: impl Into<String>) {
+        self.lock().record_snapshot_drop(camera_name);
+    }
+
+    /// A copy of the stats as they are now
+    pub fn get(&self) -> UploadStats {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UploadStats> {
+        // The stats are always left in a valid state, so a panic elsewhere doesn't invalidate them
+        self.stats
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_read_back() {
+        let mut stats = UploadStats::default();
+        assert_eq!(stats.camera("front"), CameraUploadStats::default());
+
+        stats.record_recording_upload("front", Time::from_secs_since_epoch(100));
+        stats.record_recording_upload("front", Time::from_secs_since_epoch(200));
+        stats.record_snapshot_upload("front", Time::from_secs_since_epoch(150));
+        stats.record_snapshot_upload("back", Time::from_secs_since_epoch(300));
+        stats.record_snapshot_drop("back");
+
+        assert_eq!(
+            stats.camera("front"),
+            CameraUploadStats {
+                recordings_uploaded: 2,
+                last_recording_upload: Some(Time::from_secs_since_epoch(200)),
+                snapshots_uploaded: 1,
+                last_snapshot_upload: Some(Time::from_secs_since_epoch(150)),
+                snapshots_dropped: 0,
+            }
+        );
+        assert_eq!(
+            stats.camera("back"),
+            CameraUploadStats {
+                recordings_uploaded: 0,
+                last_recording_upload: None,
+                snapshots_uploaded: 1,
+                last_snapshot_upload: Some(Time::from_secs_since_epoch(300)),
+                snapshots_dropped: 1,
+            }
+        );
+        assert_eq!(stats.cameras().len(), 2);
+    }
+
+    #[test]
+    fn shared_stats_are_shared() {
+        let shared = SharedUploadStats::default();
+        let shared_clone = shared.clone();
+
+        shared_clone.record_snapshot_upload("front");
+        shared_clone.record_recording_upload("front");
+
+        let stats = shared.get();
+        assert_eq!(stats.camera("front").snapshots_uploaded, 1);
+        assert_eq!(stats.camera("front").recordings_uploaded, 1);
+        assert!(stats.camera("front").last_snapshot_upload.is_some());
+    }
+}
@@ -0,0 +1,164 @@
+use super::{
+    config::SyncSystemConfig,
+    recording_upload_handler::{RecordingsTaskHandler, RecordingsUploadTaskHandlerCommand},
+    review_from_event::ReviewFromEvent,
+    traits::{FileSenderMaker, FrigateApiMaker},
+};
+use crate::{
+    config::PathDescriptors,
+    stats::{SharedUploadStats, UploadStats},
+};
+use frigate_api_caller::config::FrigateApiConfig;
+use mqtt_handler::types::reviews::ReviewProps;
+use std::sync::Arc;
+use utils::time::{Time, get_time};
+
+/// The events whose recordings are uploaded by a backfill
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackfillRange {
+    /// Unix timestamp, in seconds
+    pub after: f64,
+    /// Unix timestamp, in seconds
+    pub before: f64,
+    /// Empty means all cameras
+    pub cameras: Vec<String>,
+}
+
+impl BackfillRange {
+    /// The events that started within `window` before `now`, e.g., to upload again what a destination missed
+    #[must_use]
+    pub fn last(window: std::time::Duration, now: Time, cameras: Vec<String>) -> Self {
+        Self {
+            after: now.saturating_duration_sub(window).as_unix_timestamp_f64(),
+            before: now.as_unix_timestamp_f64(),
+            cameras,
+        }
+    }
+}
+
+/// Uploads the recordings of all the events that started within the given range, and returns once
+/// all of them have been processed. Nothing is received from MQTT, so the recordings state of cameras
+/// isn't checked.
+pub async fn run_backfill<F, S>(
+    range: &BackfillRange,
+    upload_dests: PathDescriptors,
+    frigate_api_config: Arc<FrigateApiConfig>,
+    sync_config: Arc<SyncSystemConfig>,
+    frigate_api_maker: F,
+    file_sender_maker: S,
+) -> anyhow::Result<UploadStats>
+where
+    F: FrigateApiMaker,
+    S: FileSenderMaker,
+{
+    let api = frigate_api_maker(&frigate_api_config)?;
+    let events = api
+        .events(range.after, range.before, &range.cameras)
+        .await?;
+
+    tracing::info!(
+        "Backfill: found {} events within [{},{}]",
+        events.len(),
+        range.after,
+        range.before
+    );
+
+    let reviews = events
+        .into_iter()
+        .map(|event| Arc::new(ReviewFromEvent::from(event)) as Arc<dyn ReviewProps>)
+        .filter(|review| should_backfill(review.as_ref(), &sync_config))
+        .collect::<Vec<_>>();
+
+    let stats = upload_reviews(
+        reviews,
+        upload_dests,
+        frigate_api_config,
+        sync_config,
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await;
+
+    tracing::info!(
+        "Backfill done. Uploaded recordings: {}",
+        stats
+            .cameras()
+            .values()
+            .map(|s| s.recordings_uploaded)
+            .sum::<u64>()
+    );
+
+    Ok(stats)
+}
+
+/// Uploads the recordings of the given reviews, which must have ended, and returns once all of them
+/// have been processed
+pub(super) async fn upload_reviews<F, S>(
+    reviews: impl IntoIterator<Item = Arc<dyn ReviewProps>>,
+    upload_dests: PathDescriptors,
+    frigate_api_config: Arc<FrigateApiConfig>,
+    sync_config: Arc<SyncSystemConfig>,
+    frigate_api_maker: F,
+    file_sender_maker: S,
+) -> UploadStats
+where
+    F: FrigateApiMaker,
+    S: FileSenderMaker,
+{
+    let upload_stats = SharedUploadStats::default();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let handler = RecordingsTaskHandler::new(
+        command_receiver,
+        frigate_api_config,
+        Arc::new(frigate_api_maker),
+        Arc::new(file_sender_maker),
+        upload_dests,
+        sync_config,
+        upload_stats.clone(),
+        None,
+        None,
+        None,
+        None,
+    );
+
+    for review in reviews {
+        command_sender
+            .send(RecordingsUploadTaskHandlerCommand::Task(review, None))
+            .expect("The handler hasn't started, so its receiver must exist");
+    }
+
+    // The handler processes all the tasks that were queued before the stop, then returns
+    command_sender
+        .send(RecordingsUploadTaskHandlerCommand::Stop)
+        .expect("The handler hasn't started, so its receiver must exist");
+    handler.run().await;
+
+    upload_stats.get()
+}
+
+fn should_backfill(review: &dyn ReviewProps, sync_config: &SyncSystemConfig) -> bool {
+    // A backfill runs once, so an ongoing event would be left with a partial recording
+    if review.end_time().is_none() {
+        tracing::info!(
+            "Backfill: skipping event with id `{}` - It hasn't ended yet.",
+            review.id()
+        );
+        return false;
+    }
+
+    let now = get_time().as_unix_timestamp_f64();
+    if sync_config.is_review_long_enough(review.start_time(), review.end_time(), now) == Some(false)
+    {
+        tracing::debug!(
+            "Backfill: skipping event with id `{}` - It's shorter than the minimum review duration.",
+            review.id()
+        );
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests;
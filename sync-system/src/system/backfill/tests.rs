@@ -0,0 +1,175 @@
+use super::{BackfillRange, run_backfill};
+use crate::{config::PathDescriptors, system::config::SyncSystemConfig};
+use file_sender::{make_inmemory_filesystem, path_descriptor::PathDescriptor};
+use frigate_api_caller::{config::FrigateApiConfig, json::event::Event, traits::FrigateApi};
+use mocks::frigate_api::make_frigate_client_mock;
+use std::{path::Path, sync::Arc};
+use utils::time::Time;
+
+fn make_event(id: &str, camera: &str, start_time: f64, end_time: Option<f64>) -> Event {
+    Event {
+        id: id.to_string(),
+        camera: camera.to_string(),
+        label: "person".to_string(),
+        start_time,
+        end_time,
+        has_clip: true,
+        has_snapshot: true,
+    }
+}
+
+fn frigate_config() -> FrigateApiConfig {
+    FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    }
+}
+
+fn path_descriptors() -> PathDescriptors {
+    PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    }
+}
+
+#[tokio::test]
+async fn backfill_uploads_ended_events_in_range() {
+    let range = BackfillRange {
+        after: 1000.,
+        before: 2000.,
+        cameras: vec!["front".to_string(), "back".to_string()],
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_events()
+        .withf(|after, before, cameras| {
+            (after.to_bits(), before.to_bits()) == (1000_f64.to_bits(), 2000_f64.to_bits())
+                && cameras == ["front", "back"]
+        })
+        .returning(|_, _, _| {
+            Ok(vec![
+                make_event("event-1", "front", 1100., Some(1150.)),
+                make_event("event-2", "back", 1200., Some(1230.)),
+                // Still ongoing, so its recording is incomplete
+                make_event("event-3", "front", 1900., None),
+            ])
+        })
+        .once();
+    // Only the events that have ended are downloaded
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(|_, _, end| *end <= 1230.)
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone());
+
+    let stats = run_backfill(
+        &range,
+        path_descriptors(),
+        Arc::new(frigate_config()),
+        Arc::new(SyncSystemConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(stats.camera("front").recordings_uploaded, 1);
+    assert_eq!(stats.camera("back").recordings_uploaded, 1);
+
+    let mut uploaded_files = Vec::new();
+    for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+        let dir = Path::new(".").join(dir);
+        uploaded_files.extend(file_sender.ls(&dir).await.unwrap());
+    }
+    assert_eq!(uploaded_files.len(), 2);
+    for camera in ["front", "back"] {
+        assert!(
+            uploaded_files
+                .iter()
+                .any(|f| f.to_string_lossy().contains(camera)),
+            "No uploaded file for camera `{camera}` in {uploaded_files:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn resync_of_last_hours_skips_uploaded_recordings() {
+    // The last hour before a fixed time
+    let range = BackfillRange::last(
+        std::time::Duration::from_secs(60 * 60),
+        Time::from_secs_since_epoch(10_000),
+        Vec::new(),
+    );
+    assert_eq!(
+        range,
+        BackfillRange {
+            after: 6400.,
+            before: 10_000.,
+            cameras: Vec::new(),
+        }
+    );
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        skip_if_already_uploaded: true,
+        ..SyncSystemConfig::default()
+    });
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone());
+
+    // The first run uploads the missing recordings, and the second finds them all uploaded,
+    // so their clips aren't downloaded again
+    for expected_downloads in [2, 0] {
+        let mut frigate_api_mock = make_frigate_client_mock();
+        frigate_api_mock
+            .expect_events()
+            .withf(|after, before, cameras| {
+                (after.to_bits(), before.to_bits()) == (6400_f64.to_bits(), 10_000_f64.to_bits())
+                    && cameras.is_empty()
+            })
+            .returning(|_, _, _| {
+                Ok(vec![
+                    make_event("event-1", "front", 7000., Some(7030.)),
+                    make_event("event-2", "back", 9000., Some(9060.)),
+                ])
+            })
+            .once();
+        frigate_api_mock
+            .expect_recording_clip()
+            .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+            .times(expected_downloads);
+        let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+        let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+        run_backfill(
+            &range,
+            path_descriptors(),
+            Arc::new(frigate_config()),
+            sync_config.clone(),
+            frigate_api_maker,
+            file_sender_maker.clone(),
+        )
+        .await
+        .unwrap();
+    }
+
+    let mut uploaded_files = Vec::new();
+    for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+        let dir = Path::new(".").join(dir);
+        uploaded_files.extend(file_sender.ls(&dir).await.unwrap());
+    }
+    // Every clip has the marker of its completed upload next to it
+    let (clips, markers): (Vec<_>, Vec<_>) = uploaded_files
+        .iter()
+        .partition(|f| f.extension().is_some_and(|e| e == "mp4"));
+    assert_eq!(clips.len(), 2, "{uploaded_files:?}");
+    assert_eq!(markers.len(), 2, "{uploaded_files:?}");
+}
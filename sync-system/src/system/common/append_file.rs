@@ -0,0 +1,18 @@
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// Appends the line to the file at the given path, creating it if needed, and syncs it to disk
+pub async fn append_line_synced(path: &Path, line: &[u8]) -> std::io::Result<()> {
+    let mut data = Vec::with_capacity(line.len() + 1);
+    data.extend_from_slice(line);
+    data.push(b'\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    // A single write in append mode, so that lines from concurrent tasks don't interleave
+    file.write_all(&data).await?;
+    file.sync_all().await
+}
@@ -0,0 +1,69 @@
+use super::{append_file::append_line_synced, file_upload::UploadableFile};
+use file_sender::path_descriptor::PathDescriptor;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadOutcome {
+    Success,
+    Failure,
+}
+
+/// A single line of the upload audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub camera: String,
+    pub id: Option<String>,
+    pub path: String,
+    pub destination: String,
+    pub size: usize,
+    pub sha256: String,
+    pub outcome: UploadOutcome,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(
+        file: &dyn UploadableFile,
+        destination: &PathDescriptor,
+        outcome: UploadOutcome,
+        error: Option<String>,
+    ) -> Self {
+        let bytes = file.file_bytes();
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            camera: file.camera_name().to_string(),
+            id: file.source_id(),
+            path: file.full_upload_path().display().to_string(),
+            destination: destination.to_string(),
+            size: bytes.len(),
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+            outcome,
+            error,
+        }
+    }
+}
+
+/// Appends the record as a JSON line to the audit log at the given path, and syncs it to disk.
+/// Failing to write is logged, but doesn't fail the upload.
+pub async fn append_audit_record(audit_log_path: &Path, record: &AuditRecord) {
+    if let Err(e) = append_audit_record_inner(audit_log_path, record).await {
+        tracing::error!(
+            "Failed to write to the audit log at `{}`. Record: {record:?}. Error: {e}",
+            audit_log_path.display()
+        );
+    }
+}
+
+async fn append_audit_record_inner(
+    audit_log_path: &Path,
+    record: &AuditRecord,
+) -> anyhow::Result<()> {
+    let line = serde_json::to_vec(record)?;
+    append_line_synced(audit_log_path, &line).await?;
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use super::append_file::append_line_synced;
+use std::path::{Path, PathBuf};
+
+/// Separates the id of a dead letter from the reason it was given up on
+const REASON_SEPARATOR: char = '\t';
+
+/// Records the id of a review whose upload was given up on, with the reason, one review per line,
+/// so that it can be retried later (e.g., with the `retry-dead-letters` subcommand).
+/// Failing to write is only logged, since there's nothing else to do about it.
+pub async fn record_dead_letter(dead_letter_path: &Path, id: &str, reason: &str) {
+    // The reason is kept on the line of its id
+    let reason = reason.replace(['\n', '\r', REASON_SEPARATOR], " ");
+    let line = format!("{id}{REASON_SEPARATOR}{reason}");
+    if let Err(e) = append_line_synced(dead_letter_path, line.as_bytes()).await {
+        tracing::error!(
+            "Failed to record review with id `{id}` in the dead-letter file at `{}`. Error: {e}",
+            dead_letter_path.display()
+        );
+    }
+}
+
+/// Where the dead letters that are being retried are kept, until they're all retried
+fn retrying_path(dead_letter_path: &Path) -> PathBuf {
+    let mut path = dead_letter_path.as_os_str().to_owned();
+    path.push(".retrying");
+    path.into()
+}
+
+/// Moves the dead letters out of the file, so that the reviews that fail again are recorded anew, and returns
+/// their ids without duplicates. They're kept aside until `finish_dead_letters_retry`, so that a retry that's
+/// interrupted is resumed by the next one.
+pub async fn take_dead_letters(dead_letter_path: &Path) -> std::io::Result<Vec<String>> {
+    let retrying_path = retrying_path(dead_letter_path);
+
+    match tokio::fs::read(dead_letter_path).await {
+        Ok(data) => {
+            for id in String::from_utf8_lossy(&data).lines() {
+                append_line_synced(&retrying_path, id.as_bytes()).await?;
+            }
+            tokio::fs::remove_file(dead_letter_path).await?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+
+    let data = match tokio::fs::read_to_string(&retrying_path).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut ids = Vec::<String>::new();
+    // Lines without a reason are of older versions
+    for id in data
+        .lines()
+        .filter_map(|line| line.split(REASON_SEPARATOR).next())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+    {
+        if !ids.iter().any(|i| i == id) {
+            ids.push(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Drops the dead letters taken by `take_dead_letters`, once they've all been retried
+pub async fn finish_dead_letters_retry(dead_letter_path: &Path) {
+    let retrying_path = retrying_path(dead_letter_path);
+    if let Err(e) = tokio::fs::remove_file(&retrying_path).await {
+        tracing::error!(
+            "Failed to remove the retried dead letters at `{}`. They'll be retried again. Error: {e}",
+            retrying_path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dead_letters_are_taken_until_their_retry_finishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead-letter.txt");
+
+        assert!(take_dead_letters(&path).await.unwrap().is_empty());
+
+        // Lines of older versions have no reason
+        std::fs::write(&path, "id-1\nid-2\tSome reason\nid-1\tAnother reason\n").unwrap();
+        assert_eq!(take_dead_letters(&path).await.unwrap(), ["id-1", "id-2"]);
+        assert!(!path.exists());
+
+        // An interrupted retry is taken again, with what failed since
+        record_dead_letter(&path, "id-3", "Multi-line\nreason").await;
+        assert_eq!(
+            take_dead_letters(&path).await.unwrap(),
+            ["id-1", "id-2", "id-3"]
+        );
+        assert!(
+            std::fs::read_to_string(retrying_path(&path))
+                .unwrap()
+                .ends_with("id-3\tMulti-line reason\n")
+        );
+
+        finish_dead_letters_retry(&path).await;
+        assert!(take_dead_letters(&path).await.unwrap().is_empty());
+    }
+}
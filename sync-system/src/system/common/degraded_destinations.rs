@@ -0,0 +1,163 @@
+use super::destination_lock::acquire_destination_locks;
+use crate::system::traits::FileSenderMaker;
+use file_sender::path_descriptor::PathDescriptor;
+use std::sync::{Arc, Mutex};
+
+/// Creates a file sender for the given destination, then initializes it and checks its health
+pub async fn check_destination<S: FileSenderMaker>(
+    file_sender_maker: &S,
+    descriptor: &Arc<PathDescriptor>,
+) -> anyhow::Result<()> {
+    let sender = file_sender_maker(descriptor)?;
+    sender.init().await?;
+    sender.health_check().await
+}
+
+/// Destinations that failed their check at startup, and haven't passed it since.
+/// Uploads are still attempted to them.
+#[derive(Debug, Clone, Default)]
+pub struct DegradedDestinations {
+    destinations: Arc<Mutex<Vec<Arc<PathDescriptor>>>>,
+}
+
+impl DegradedDestinations {
+    pub fn insert(&self, descriptor: Arc<PathDescriptor>) {
+        let mut destinations = self.lock();
+        if !destinations.contains(&descriptor) {
+            destinations.push(descriptor);
+        }
+    }
+
+    pub fn remove(&self, descriptor: &PathDescriptor) {
+        self.lock().retain(|d| d.as_ref() != descriptor);
+    }
+
+    /// A copy of the degraded destinations as they are now
+    pub fn get(&self) -> Vec<Arc<PathDescriptor>> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Arc<PathDescriptor>>> {
+        // The list is always left in a valid state, so a panic elsewhere doesn't invalidate it
+        self.destinations
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Checks the degraded destinations every `retry_period`, and removes those that pass,
+/// until none are left. If `lock_owner` is set, a destination that passes is locked for it first,
+/// since it wasn't locked at startup, and stays degraded if another instance holds it.
+pub async fn retry_degraded_destinations<S: FileSenderMaker>(
+    file_sender_maker: Arc<S>,
+    degraded: DegradedDestinations,
+    retry_period: std::time::Duration,
+    lock_owner: Option<String>,
+) {
+    loop {
+        let remaining = degraded.get();
+        if remaining.is_empty() {
+            break;
+        }
+
+        tokio::time::sleep(retry_period).await;
+
+        for descriptor in remaining {
+            if let Err(e) = check_destination(file_sender_maker.as_ref(), &descriptor).await {
+                tracing::warn!("Destination `{descriptor}` is still unreachable: {e}");
+                continue;
+            }
+
+            if let Some(owner) = &lock_owner {
+                if let Err(e) = acquire_destination_locks(
+                    file_sender_maker.as_ref(),
+                    std::slice::from_ref(&descriptor),
+                    owner,
+                )
+                .await
+                {
+                    tracing::error!(
+                        "Destination `{descriptor}` is reachable again, but locking it failed, so it stays degraded: {e}"
+                    );
+                    continue;
+                }
+            }
+
+            tracing::info!("Destination `{descriptor}` is reachable again");
+            degraded.remove(&descriptor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::common::destination_lock::DESTINATION_LOCK_FILE_NAME;
+    use file_sender::make_inmemory_filesystem;
+    use std::path::Path;
+
+    #[tokio::test(start_paused = true)]
+    async fn recovered_destination_is_locked() {
+        let store = make_inmemory_filesystem();
+        let file_sender_maker = {
+            let store = store.clone();
+            Arc::new(move |_: &Arc<PathDescriptor>| Ok(store.clone()))
+        };
+        let degraded = DegradedDestinations::default();
+        degraded.insert(Arc::new(PathDescriptor::local("/data")));
+
+        retry_degraded_destinations(
+            file_sender_maker,
+            degraded.clone(),
+            std::time::Duration::from_secs(1),
+            Some("instance-a".to_string()),
+        )
+        .await;
+
+        assert!(degraded.get().is_empty());
+        assert_eq!(
+            store
+                .get_to_memory(Path::new(DESTINATION_LOCK_FILE_NAME))
+                .await
+                .unwrap(),
+            b"instance-a"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recovered_destination_locked_by_another_instance_stays_degraded() {
+        let store = make_inmemory_filesystem();
+        store
+            .put_from_memory(b"instance-b", Path::new(DESTINATION_LOCK_FILE_NAME))
+            .await
+            .unwrap();
+        let file_sender_maker = {
+            let store = store.clone();
+            Arc::new(move |_: &Arc<PathDescriptor>| Ok(store.clone()))
+        };
+        let descriptor = Arc::new(PathDescriptor::local("/data"));
+        let degraded = DegradedDestinations::default();
+        degraded.insert(descriptor.clone());
+
+        tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            retry_degraded_destinations(
+                file_sender_maker,
+                degraded.clone(),
+                std::time::Duration::from_secs(1),
+                Some("instance-a".to_string()),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(degraded.get(), vec![descriptor]);
+        assert_eq!(
+            store
+                .get_to_memory(Path::new(DESTINATION_LOCK_FILE_NAME))
+                .await
+                .unwrap(),
+            b"instance-b"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::system::traits::FileSenderMaker;
+use anyhow::Context;
+use file_sender::{path_descriptor::PathDescriptor, traits::StoreDestination};
+use std::{future::Future, path::Path, sync::Arc};
+
+/// The object at the root of every destination that says which running instance uses it
+pub const DESTINATION_LOCK_FILE_NAME: &str = ".snap-sync.lock";
+
+/// Writes a lock object with `owner` to every destination, and fails if any of them has one of another owner.
+/// Not every kind of destination can create a file atomically, so the lock object is read back after writing it,
+/// to notice another instance that started at the same moment and wrote over it. One that writes right after
+/// that read still gets through, so this narrows the race without closing it.
+/// Unlike a local lock file, it's left behind if the program dies; a lock object of `owner` itself is then taken
+/// back, as the owner stays the same across restarts.
+pub async fn acquire_destination_locks<S: FileSenderMaker>(
+    file_sender_maker: &S,
+    descriptors: &[Arc<PathDescriptor>],
+    owner: &str,
+) -> anyhow::Result<()> {
+    let path = Path::new(DESTINATION_LOCK_FILE_NAME);
+    for descriptor in descriptors {
+        let store = file_sender_maker(descriptor)?;
+
+        if let Some(holder) = lock_holder(store.as_ref()).await? {
+            if holder != owner {
+                return Err(anyhow::anyhow!(
+                    "Destination `{descriptor}` is used by another instance ({holder}). If it isn't, e.g., it was left by an instance that crashed, delete `{DESTINATION_LOCK_FILE_NAME}` from the destination."
+                ));
+            }
+            tracing::warn!(
+                "Destination `{descriptor}` has a lock object left by a previous run of this instance. Taking it back."
+            );
+        }
+
+        store
+            .replace_from_memory(owner.as_bytes(), path)
+            .await
+            .with_context(|| format!("Writing lock object to destination `{descriptor}`"))?;
+
+        // Another instance that checked the destination at the same time may have written its own over ours
+        match lock_holder(store.as_ref()).await? {
+            Some(holder) if holder == owner => {}
+            holder => {
+                return Err(anyhow::anyhow!(
+                    "Destination `{descriptor}` was locked by another instance ({}) while locking it for this one",
+                    holder.as_deref().unwrap_or("unknown")
+                ));
+            }
+        }
+        tracing::info!("Locked destination `{descriptor}` for this instance");
+    }
+
+    Ok(())
+}
+
+/// Deletes the lock objects of `owner` from the destinations. Lock objects of other owners are left as they are.
+pub async fn release_destination_locks<S: FileSenderMaker>(
+    file_sender_maker: &S,
+    descriptors: &[Arc<PathDescriptor>],
+    owner: &str,
+) {
+    for descriptor in descriptors {
+        let result = async {
+            let store = file_sender_maker(descriptor)?;
+            if lock_holder(store.as_ref()).await?.as_deref() == Some(owner) {
+                store
+                    .del_file(Path::new(DESTINATION_LOCK_FILE_NAME))
+                    .await?;
+                tracing::info!("Unlocked destination `{descriptor}`");
+            }
+            anyhow::Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!(
+                "Deleting the lock object from destination `{descriptor}` failed. It has to be deleted by hand before starting again. Error: {e}"
+            );
+        }
+    }
+}
+
+/// Runs `body` with the destinations locked for `owner`, and unlocks them once it's done, whether it succeeded or not.
+/// It isn't run if any of them is locked by another owner.
+pub async fn with_destination_locks<S: FileSenderMaker, T>(
+    file_sender_maker: &S,
+    descriptors: &[Arc<PathDescriptor>],
+    owner: &str,
+    body: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let result = match acquire_destination_locks(file_sender_maker, descriptors, owner).await {
+        Ok(()) => body.await,
+        Err(e) => Err(e),
+    };
+    // Also after a failure to lock, for the destinations that were locked before it
+    release_destination_locks(file_sender_maker, descriptors, owner).await;
+    result
+}
+
+/// The owner in the lock object of the destination, if it has one
+async fn lock_holder(
+    store: &dyn StoreDestination<Error = anyhow::Error>,
+) -> anyhow::Result<Option<String>> {
+    let path = Path::new(DESTINATION_LOCK_FILE_NAME);
+    if !store.file_exists(path).await? {
+        return Ok(None);
+    }
+
+    let holder = store.get_to_memory(path).await?;
+    Ok(Some(String::from_utf8_lossy(&holder).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_sender::make_inmemory_filesystem;
+
+    #[tokio::test]
+    async fn destination_locked_by_another_owner_is_refused() {
+        let store = make_inmemory_filesystem();
+        let file_sender_maker = {
+            let store = store.clone();
+            move |_: &Arc<PathDescriptor>| Ok(store.clone())
+        };
+        let descriptors = [Arc::new(PathDescriptor::local("/data"))];
+
+        acquire_destination_locks(&file_sender_maker, &descriptors, "first")
+            .await
+            .unwrap();
+        let err = acquire_destination_locks(&file_sender_maker, &descriptors, "second")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("used by another instance (first)"));
+
+        // Only the owner's lock is released
+        release_destination_locks(&file_sender_maker, &descriptors, "second").await;
+        assert!(
+            store
+                .file_exists(Path::new(DESTINATION_LOCK_FILE_NAME))
+                .await
+                .unwrap()
+        );
+        release_destination_locks(&file_sender_maker, &descriptors, "first").await;
+        assert!(
+            !store
+                .file_exists(Path::new(DESTINATION_LOCK_FILE_NAME))
+                .await
+                .unwrap()
+        );
+
+        acquire_destination_locks(&file_sender_maker, &descriptors, "second")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn lock_left_by_a_crash_is_taken_back_by_the_same_owner() {
+        let store = make_inmemory_filesystem();
+        let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(store.clone());
+        let descriptors = [Arc::new(PathDescriptor::local("/data"))];
+
+        // Never released, like when the program crashes
+        acquire_destination_locks(&file_sender_maker, &descriptors, "host-a")
+            .await
+            .unwrap();
+
+        acquire_destination_locks(&file_sender_maker, &descriptors, "host-a")
+            .await
+            .unwrap();
+        assert!(
+            acquire_destination_locks(&file_sender_maker, &descriptors, "host-b")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn body_is_not_run_while_another_owner_holds_the_lock() {
+        let store = make_inmemory_filesystem();
+        let file_sender_maker = {
+            let store = store.clone();
+            move |_: &Arc<PathDescriptor>| Ok(store.clone())
+        };
+        let descriptors = [Arc::new(PathDescriptor::local("/data"))];
+
+        acquire_destination_locks(&file_sender_maker, &descriptors, "instance")
+            .await
+            .unwrap();
+        let mut ran = false;
+        let err = with_destination_locks(&file_sender_maker, &descriptors, "command", async {
+            ran = true;
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("used by another instance (instance)")
+        );
+        assert!(!ran);
+
+        // The lock of the other owner is kept
+        let holder = store
+            .get_to_memory(Path::new(DESTINATION_LOCK_FILE_NAME))
+            .await
+            .unwrap();
+        assert_eq!(holder, b"instance");
+
+        release_destination_locks(&file_sender_maker, &descriptors, "instance").await;
+        let result = with_destination_locks(&file_sender_maker, &descriptors, "command", async {
+            Ok(lock_holder(store.as_ref()).await.unwrap())
+        })
+        .await
+        .unwrap();
+        assert_eq!(result.as_deref(), Some("command"));
+        assert!(
+            !store
+                .file_exists(Path::new(DESTINATION_LOCK_FILE_NAME))
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -1,68 +1,163 @@
 use crate::system::traits::FileSenderMaker;
-use file_sender::{path_descriptor::PathDescriptor, traits::StoreDestination};
+use file_sender::{StoreError, path_descriptor::PathDescriptor, traits::StoreDestination};
 use std::{
+    collections::BTreeMap,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use super::file_senders::{make_file_senders, split_file_senders_and_descriptors};
+use super::{
+    audit_log::{AuditRecord, UploadOutcome, append_audit_record},
+    file_senders::{make_file_senders, split_file_senders_and_descriptors},
+    retry::Backoff,
+};
+
+/// The file names of recording clips start with this
+pub const RECORDING_FILE_NAME_PREFIX: &str = "RecordingClip-";
+/// The suffixes of the two alternative versions of a recording, before the extension,
+/// when `keep_alternative_versions` is set
+pub const ALTERNATIVE_FILE_NAME_SUFFIXES: [&str; 2] = ["-0", "-1"];
 
 pub trait UploadableFile: Send + Sync {
     fn file_bytes(&self) -> &[u8];
-    fn file_name(&self) -> PathBuf;
     fn file_description(&self) -> String;
-    fn upload_dir(&self) -> PathBuf;
-    fn full_upload_path(&self) -> PathBuf {
-        self.upload_dir().join(self.file_name())
+    fn camera_name(&self) -> &str;
+    /// The id of what this file belongs to (e.g., a review), if any
+    fn source_id(&self) -> Option<String>;
+    /// Where the file is uploaded to, as given by the naming strategy
+    fn full_upload_path(&self) -> PathBuf;
+    fn upload_dir(&self) -> PathBuf {
+        self.full_upload_path()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
     }
 }
 
+/// The destinations that a file op failed for, after all their attempts
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "File op '{op_name}' failed for `{file_description}` in {} destination(s): '{}'",
+    .failed_destinations.len(),
+    .failed_destinations.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+)]
+pub struct RemoteFileOpError {
+    op_name: String,
+    file_description: String,
+    pub failed_destinations: Vec<Arc<PathDescriptor>>,
+}
+
+/// A destination that the op hasn't succeeded for yet
+struct PendingDestination {
+    descriptor: Arc<PathDescriptor>,
+    attempt_number: u32,
+    attempt_at: tokio::time::Instant,
+}
+
+/// Runs the op on every destination. Each destination is retried on its own, after a wait given by `backoff` that
+/// grows with its failures, so a failing destination neither delays the others nor gets the op repeated on them.
+/// The error has the destinations that the op failed for, which are the only ones worth retrying it for.
+#[tracing::instrument(
+    skip_all,
+    fields(op = %op.op_name(), review_id = op.source_id(), camera = op.camera_name())
+)]
 pub async fn remote_file_op<S: FileSenderMaker>(
     op: RemoteFileOp<'_>,
     path_descriptors: Vec<Arc<PathDescriptor>>,
     file_sender_maker: Arc<S>,
     max_attempt_count: u32,
-    sleep_after_error: std::time::Duration,
-) -> anyhow::Result<()> {
-    // Take a copy of all the descriptors as the initial ones to use for the op
-    let mut remaining_descriptors = path_descriptors;
-
+    backoff: Backoff,
+    audit_log_path: Option<&Path>,
+) -> Result<(), RemoteFileOpError> {
     let op_name = op.op_name();
 
-    for attempt_number in 0..max_attempt_count {
-        if remaining_descriptors.is_empty() {
-            // no +1 here because it finished in last iter
-            tracing::info!(
-                "Done file op '{op_name}' at attempt '{attempt_number}' for: {}",
-                op.file_description()
-            );
-            break;
-        }
+    let start = tokio::time::Instant::now();
+    let mut pending_destinations = path_descriptors
+        .into_iter()
+        .map(|descriptor| PendingDestination {
+            descriptor,
+            attempt_number: 0,
+            attempt_at: start,
+        })
+        .collect::<Vec<_>>();
 
-        let file_senders = make_file_senders(&file_sender_maker, &remaining_descriptors).await;
-        let (file_senders, path_descriptors) = split_file_senders_and_descriptors(file_senders);
+    // The last error of every destination, to record the reason of failure in the audit log
+    let mut last_errors = BTreeMap::new();
 
-        // The descriptors that we failed to open, are the ones we'll attempt open again in the next iteration
-        remaining_descriptors = path_descriptors;
+    // Destinations whose attempts ran out, or that are out of space, which isn't retried
+    // as retrying fails the same way until space is freed
+    let mut failed_descriptors = Vec::new();
 
-        for s in &file_senders {
-            let op_result = match op {
-                RemoteFileOp::Upload(uploadable_file) => {
-                    upload_file_inner(uploadable_file, s, attempt_number).await
-                }
-                RemoteFileOp::DeleteFileIfExists(path) => {
-                    delete_file_inner(path, s, attempt_number).await
+    while let Some(attempt_at) = pending_destinations.iter().map(|d| d.attempt_at).min() {
+        // Only waiting when there's nothing to attempt now, so that an op that doesn't fail never yields to a timer
+        if attempt_at > tokio::time::Instant::now() {
+            tokio::time::sleep_until(attempt_at).await;
+        }
+
+        let now = tokio::time::Instant::now();
+        let (due_destinations, waiting_destinations) = pending_destinations
+            .into_iter()
+            .partition::<Vec<_>, _>(|d| d.attempt_at <= now);
+        pending_destinations = waiting_destinations;
+
+        for mut destination in due_destinations {
+            let attempt_number = destination.attempt_number;
+            let e = match run_op_on_destination(
+                op,
+                &destination.descriptor,
+                &file_sender_maker,
+                attempt_number,
+                audit_log_path,
+            )
+            .await
+            {
+                Ok(()) => {
+                    tracing::info!(
+                        "Done file op '{op_name}' at attempt '{}' in `{}` for: {}",
+                        attempt_number + 1,
+                        destination.descriptor,
+                        op.file_description()
+                    );
+                    continue;
                 }
+                Err(e) => e,
             };
-            if op_result.is_err() {
-                // Since it failed, we try again later
-                remaining_descriptors.push(s.path_descriptor().clone());
-                tokio::time::sleep(sleep_after_error).await;
+
+            let path_descriptor = destination.descriptor.clone();
+            last_errors.insert(path_descriptor.to_string(), e.to_string());
+            destination.attempt_number += 1;
+
+            if StoreError::is_out_of_space(&e) {
+                tracing::error!(
+                    "Destination `{path_descriptor}` is out of space. Not retrying file op '{op_name}' for: {}",
+                    op.file_description()
+                );
+                failed_descriptors.push(path_descriptor);
+            } else if destination.attempt_number >= max_attempt_count {
+                failed_descriptors.push(path_descriptor);
+            } else {
+                // Since it failed, we try again later, without holding back the other destinations
+                let delay = backoff.delay(
+                    destination.attempt_number - 1,
+                    &mut randomness::make_pseudo_rng(),
+                );
+                destination.attempt_at = tokio::time::Instant::now() + delay;
+                pending_destinations.push(destination);
             }
         }
     }
 
-    if remaining_descriptors.is_empty() {
+    if let (RemoteFileOp::Upload(file), Some(audit_log_path)) = (op, audit_log_path) {
+        for d in &failed_descriptors {
+            let error = last_errors
+                .remove(&d.to_string())
+                .unwrap_or_else(|| "Could not connect to destination".to_string());
+            let record = AuditRecord::new(file, d, UploadOutcome::Failure, Some(error));
+            append_audit_record(audit_log_path, &record).await;
+        }
+    }
+
+    if failed_descriptors.is_empty() {
         tracing::debug!(
             "Success: Reaching the end of file op '{op_name}' code for camera {}",
             op.file_description()
@@ -70,25 +165,54 @@ pub async fn remote_file_op<S: FileSenderMaker>(
 
         Ok(())
     } else {
-        let error = format!(
-            "Error: Reaching the end of file op '{op_name}' code for file `{}` with {} destination(s) having received the file. These are: '{}'",
-            op.file_description(),
-            remaining_descriptors.len(),
-            remaining_descriptors
-                .iter()
-                .map(ToString::to_string)
-                .collect::<Vec<_>>()
-                .join(", ")
-        );
+        Err(RemoteFileOpError {
+            op_name,
+            file_description: op.file_description(),
+            failed_destinations: failed_descriptors,
+        })
+    }
+}
+
+/// A single attempt of the op on a destination, which is recorded in the audit log if it's a successful upload
+async fn run_op_on_destination<S: FileSenderMaker>(
+    op: RemoteFileOp<'_>,
+    descriptor: &Arc<PathDescriptor>,
+    file_sender_maker: &Arc<S>,
+    attempt_number: u32,
+    audit_log_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let file_senders = make_file_senders(file_sender_maker, std::slice::from_ref(descriptor)).await;
+    let (file_senders, _) = split_file_senders_and_descriptors(file_senders);
+    let Some(s) = file_senders.first() else {
+        return Err(anyhow::anyhow!("Could not connect to destination"));
+    };
 
-        Err(anyhow::anyhow!("{error}"))
+    match op {
+        RemoteFileOp::Upload(uploadable_file) => {
+            upload_file_inner(uploadable_file, s, attempt_number, false).await?;
+        }
+        RemoteFileOp::Replace(uploadable_file) => {
+            upload_file_inner(uploadable_file, s, attempt_number, true).await?;
+        }
+        RemoteFileOp::DeleteFileIfExists(path) => {
+            delete_file_inner(path, s, attempt_number).await?;
+        }
+    }
+
+    if let (RemoteFileOp::Upload(file), Some(audit_log_path)) = (op, audit_log_path) {
+        let record = AuditRecord::new(file, s.path_descriptor(), UploadOutcome::Success, None);
+        append_audit_record(audit_log_path, &record).await;
     }
+
+    Ok(())
 }
 
+/// If `replace` is set, the file is written such that a file already at its path is replaced in one step
 async fn upload_file_inner(
     file: &dyn UploadableFile,
     file_sender: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
     attempt_number: u32,
+    replace: bool,
 ) -> anyhow::Result<()> {
     let dir = file.upload_dir();
     let upload_path = file.full_upload_path();
@@ -98,14 +222,46 @@ async fn upload_file_inner(
     // Unfortunately, we have to call this ugly function twice because Result::and() doesn't work with async
     handle_upload_error(&upload_path, file_sender, attempt_number, result)?;
 
-    let result = file_sender
-        .as_ref()
-        .put_from_memory(file.file_bytes(), &upload_path)
-        .await;
+    let result = match write_file(file, &upload_path, file_sender, replace).await {
+        // E.g., the directory was deleted between making it and writing to it, so it's made again.
+        // This is retried only once, so a failure after that counts like any other.
+        Err(e) if StoreError::is_directory_not_found(&e) => {
+            tracing::warn!(
+                "Directory `{}` is missing in {} after it was made. Making it again to retry uploading file {}. Error: {e}",
+                dir.display(),
+                file_sender.path_descriptor(),
+                upload_path.display(),
+            );
+            match file_sender.as_ref().mkdir_p(&dir).await {
+                Ok(()) => write_file(file, &upload_path, file_sender, replace).await,
+                Err(e) => Err(e),
+            }
+        }
+        result => result,
+    };
 
     handle_upload_error(&upload_path, file_sender, attempt_number, result)
 }
 
+async fn write_file(
+    file: &dyn UploadableFile,
+    upload_path: &Path,
+    file_sender: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    replace: bool,
+) -> anyhow::Result<()> {
+    if replace {
+        file_sender
+            .as_ref()
+            .replace_from_memory(file.file_bytes(), upload_path)
+            .await
+    } else {
+        file_sender
+            .as_ref()
+            .put_from_memory(file.file_bytes(), upload_path)
+            .await
+    }
+}
+
 fn handle_upload_error(
     upload_path: &Path,
     file_sender: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
@@ -174,8 +330,11 @@ async fn delete_file_inner(
     result
 }
 
+#[derive(Clone, Copy)]
 pub enum RemoteFileOp<'a> {
     Upload(&'a dyn UploadableFile),
+    /// Overwrites the file at the same path on every upload, e.g., the latest snapshot of a camera
+    Replace(&'a dyn UploadableFile),
     DeleteFileIfExists(&'a Path),
 }
 
@@ -183,14 +342,35 @@ impl RemoteFileOp<'_> {
     pub fn op_name(&self) -> String {
         match self {
             RemoteFileOp::Upload(_uploadable_file) => "file upload".to_string(),
+            RemoteFileOp::Replace(_uploadable_file) => "file replace".to_string(),
             RemoteFileOp::DeleteFileIfExists(_path) => "Delete file".to_string(),
         }
     }
 
     pub fn file_description(&self) -> String {
         match self {
-            RemoteFileOp::Upload(uploadable_file) => uploadable_file.file_description(),
+            RemoteFileOp::Upload(uploadable_file) | RemoteFileOp::Replace(uploadable_file) => {
+                uploadable_file.file_description()
+            }
             RemoteFileOp::DeleteFileIfExists(path) => format!("Deleting file {}", path.display()),
         }
     }
+
+    pub fn camera_name(&self) -> Option<&str> {
+        match self {
+            RemoteFileOp::Upload(uploadable_file) | RemoteFileOp::Replace(uploadable_file) => {
+                Some(uploadable_file.camera_name())
+            }
+            RemoteFileOp::DeleteFileIfExists(_path) => None,
+        }
+    }
+
+    pub fn source_id(&self) -> Option<String> {
+        match self {
+            RemoteFileOp::Upload(uploadable_file) | RemoteFileOp::Replace(uploadable_file) => {
+                uploadable_file.source_id()
+            }
+            RemoteFileOp::DeleteFileIfExists(_path) => None,
+        }
+    }
 }
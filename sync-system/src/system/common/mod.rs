@@ -1,2 +1,11 @@
+pub mod append_file;
+pub mod audit_log;
+pub mod dead_letter;
+pub mod degraded_destinations;
+pub mod destination_lock;
 pub mod file_senders;
 pub mod file_upload;
+pub mod naming;
+pub mod retry;
+pub mod stream_upload;
+pub mod upload_notifier;
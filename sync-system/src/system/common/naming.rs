@@ -0,0 +1,741 @@
+use super::file_upload::{ALTERNATIVE_FILE_NAME_SUFFIXES, RECORDING_FILE_NAME_PREFIX};
+use crate::system::config::{
+    CameraDirOrder, CameraDisplayNames, DateBasis, PathTemplate, SyncSystemConfig,
+};
+use chrono::Datelike;
+use mqtt_handler::types::{
+    reviews::{ReviewProps, payload::TypeField},
+    snapshot::Snapshot,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use utils::{
+    file_name::{FileNameCharset, sanitize_file_name_part},
+    time::{Time, get_time},
+};
+
+/// The directory under the snapshots subdirectory with the latest snapshot of each camera
+const LATEST_SNAPSHOTS_DIR: &str = "latest";
+
+/// Where uploaded files go, relative to the root of every destination
+pub trait NamingStrategy: std::fmt::Debug + Send + Sync {
+    /// The path of the clip of the review. `alternative` is which of the two alternating versions of the clip
+    /// it is, or None if only one version is kept. Files named after the clip, like its montage, only differ
+    /// from it in their extension.
+    fn recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> PathBuf;
+
+    /// The path the clip of the ended review had while it was ongoing, if it's elsewhere now, e.g., when it's
+    /// dated by its end, on another day than its start. Its ongoing versions are deleted once it's uploaded.
+    fn ongoing_recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> Option<PathBuf>;
+
+    /// The path of the snapshot of one of the events of the review, next to its clip
+    fn event_snapshot_path(&self, review: &dyn ReviewProps, event_id: &str) -> PathBuf;
+
+    /// The path of a snapshot from MQTT that is uploaded now
+    fn snapshot_path(&self, snapshot: &Snapshot) -> PathBuf;
+
+    /// The path that always has the latest snapshot of the camera
+    fn latest_snapshot_path(&self, camera_name: &str) -> PathBuf;
+}
+
+/// The naming strategy of the config, which is the default one unless a path template is set
+pub fn naming_strategy(config: &SyncSystemConfig) -> Arc<dyn NamingStrategy> {
+    let default = DefaultNaming::new(config);
+    if config.recording_path_template.is_none() && config.snapshot_path_template.is_none() {
+        return Arc::new(default);
+    }
+
+    Arc::new(TemplateNaming {
+        default,
+        recording_template: config.recording_path_template.clone(),
+        snapshot_template: config.snapshot_path_template.clone(),
+    })
+}
+
+/// Files are grouped in a directory per date (and camera, if set), named after their camera and time
+#[derive(Debug, Clone)]
+pub struct DefaultNaming {
+    file_name_charset: FileNameCharset,
+    camera_dirs: Option<CameraDirOrder>,
+    date_basis: DateBasis,
+    recordings_subdir: PathBuf,
+    snapshots_subdir: PathBuf,
+    camera_names: CameraDisplayNames,
+}
+
+impl DefaultNaming {
+    pub fn new(config: &SyncSystemConfig) -> Self {
+        Self {
+            file_name_charset: config.file_name_charset,
+            camera_dirs: config.camera_dirs,
+            date_basis: config.date_basis,
+            recordings_subdir: config.recordings_subdir.clone(),
+            snapshots_subdir: config.snapshots_subdir.clone(),
+            camera_names: config.camera_display_names.clone(),
+        }
+    }
+
+    /// The display name of the camera, made safe to be part of a file name
+    fn camera_name_part(&self, camera_label: &str) -> String {
+        sanitize_file_name_part(
+            &self.camera_names.display_name(camera_label),
+            self.file_name_charset,
+        )
+    }
+
+    /// The time the review's files are dated by, which is its start unless they're dated by the end of reviews
+    /// that have ended
+    fn review_date(&self, review: &dyn ReviewProps) -> Time {
+        let end_time = match self.date_basis {
+            DateBasis::End if review.type_field() == TypeField::End => review.end_time(),
+            DateBasis::Start | DateBasis::End => None,
+        };
+        Time::from_f64_secs_since_epoch(end_time.unwrap_or(review.start_time()))
+    }
+
+    /// The time the review's files were dated by while it was ongoing, if it's different now that it has ended
+    fn ongoing_review_date(&self, review: &dyn ReviewProps) -> Option<Time> {
+        let start = Time::from_f64_secs_since_epoch(review.start_time());
+        (self.review_date(review).as_local_time_in_dir_foramt()
+            != start.as_local_time_in_dir_foramt())
+        .then_some(start)
+    }
+
+    fn review_dir(&self, review: &dyn ReviewProps, date: Time) -> PathBuf {
+        let date = date.as_local_time_in_dir_foramt();
+        self.upload_dir(&self.recordings_subdir, date, review.camera_name())
+    }
+
+    fn dated_recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        date: Time,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> PathBuf {
+        let datetime = Time::from_f64_secs_since_epoch(review.start_time())
+            .as_local_time_in_file_name_format();
+        let file_name = format!(
+            "{RECORDING_FILE_NAME_PREFIX}{}-{datetime}{}.{extension}",
+            self.camera_name_part(review.camera_name()),
+            alternative_suffix(alternative)
+        );
+        self.review_dir(review, date).join(file_name)
+    }
+
+    /// The directory files of a camera from the given date are uploaded to, given as a formatted date,
+    /// under the subdirectory of their type (which is empty by default)
+    fn upload_dir(&self, subdir: &Path, date_dir: String, camera_name: &str) -> PathBuf {
+        let Some(order) = self.camera_dirs else {
+            return subdir.join(date_dir);
+        };
+
+        let camera_dir = self.camera_name_part(camera_name);
+        match order {
+            CameraDirOrder::CameraFirst => subdir.join(camera_dir).join(date_dir),
+            CameraDirOrder::DateFirst => subdir.join(date_dir).join(camera_dir),
+        }
+    }
+
+    /// Named after the review like its clip, so they're listed together
+    fn event_snapshot_file_name(&self, review: &dyn ReviewProps, event_id: &str) -> String {
+        let datetime = Time::from_f64_secs_since_epoch(review.start_time())
+            .as_local_time_in_file_name_format();
+        format!(
+            "EventSnapshot-{}-{datetime}-{}.jpg",
+            self.camera_name_part(review.camera_name()),
+            sanitize_file_name_part(event_id, self.file_name_charset)
+        )
+    }
+}
+
+impl NamingStrategy for DefaultNaming {
+    /// The name is derived from the review's start time, so every upload of the same review gets the same name
+    fn recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> PathBuf {
+        self.dated_recording_path(review, self.review_date(review), alternative, extension)
+    }
+
+    fn ongoing_recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> Option<PathBuf> {
+        let date = self.ongoing_review_date(review)?;
+        Some(self.dated_recording_path(review, date, alternative, extension))
+    }
+
+    fn event_snapshot_path(&self, review: &dyn ReviewProps, event_id: &str) -> PathBuf {
+        self.review_dir(review, self.review_date(review))
+            .join(self.event_snapshot_file_name(review, event_id))
+    }
+
+    fn snapshot_path(&self, snapshot: &Snapshot) -> PathBuf {
+        self.upload_dir(
+            &self.snapshots_subdir,
+            Time::local_time_in_dir_foramt(),
+            &snapshot.camera_label,
+        )
+        .join(snapshot.make_file_name(
+            &self.camera_names.display_name(&snapshot.camera_label),
+            self.file_name_charset,
+        ))
+    }
+
+    fn latest_snapshot_path(&self, camera_name: &str) -> PathBuf {
+        let camera_name = self.camera_name_part(camera_name);
+        self.snapshots_subdir
+            .join(LATEST_SNAPSHOTS_DIR)
+            .join(format!("{camera_name}.jpg"))
+    }
+}
+
+/// The paths of recordings and snapshots are made from templates, with the default naming for whichever has none.
+/// Event snapshots go to the directory of their review's clip, and the latest snapshots stay where they are
+/// by default.
+#[derive(Debug, Clone)]
+pub struct TemplateNaming {
+    default: DefaultNaming,
+    recording_template: Option<PathTemplate>,
+    snapshot_template: Option<PathTemplate>,
+}
+
+impl TemplateNaming {
+    /// Like the default naming, only the dates follow the date basis, so the name stays that of the start
+    fn dated_recording_path(
+        &self,
+        template: &PathTemplate,
+        review: &dyn ReviewProps,
+        date: Time,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> PathBuf {
+        let path = self.render(
+            template,
+            date,
+            Time::from_f64_secs_since_epoch(review.start_time()),
+            review.camera_name(),
+            &[("{id}", review.id())],
+            &[
+                ("{alternative}", alternative_suffix(alternative)),
+                ("{ext}", extension),
+            ],
+        );
+        self.default.recordings_subdir.join(path)
+    }
+
+    /// Replaces the placeholders that all templates have, and the given ones. The dates are those of `date`,
+    /// and `{datetime}` is that of `time`. The `names`, like the camera's display name, are sanitized first,
+    /// and the `values` are made here to be safe in a path.
+    fn render(
+        &self,
+        template: &PathTemplate,
+        date: Time,
+        time: Time,
+        camera_name: &str,
+        names: &[(&str, &str)],
+        values: &[(&str, &str)],
+    ) -> PathBuf {
+        let charset = self.default.file_name_charset;
+        let local_date = date.as_local_datetime();
+        let mut path = template
+            .as_str()
+            .replace("{camera}", &self.default.camera_name_part(camera_name))
+            .replace("{date}", &date.as_local_time_in_dir_foramt())
+            .replace("{datetime}", &time.as_local_time_in_file_name_format())
+            .replace("{year}", &format!("{:04}", local_date.year()))
+            .replace("{month}", &format!("{:02}", local_date.month()))
+            .replace("{day}", &format!("{:02}", local_date.day()));
+        for (placeholder, name) in names {
+            path = path.replace(placeholder, &sanitize_file_name_part(name, charset));
+        }
+        for (placeholder, value) in values {
+            path = path.replace(placeholder, value);
+        }
+        path.into()
+    }
+}
+
+impl NamingStrategy for TemplateNaming {
+    fn recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> PathBuf {
+        let Some(template) = &self.recording_template else {
+            return self.default.recording_path(review, alternative, extension);
+        };
+
+        self.dated_recording_path(
+            template,
+            review,
+            self.default.review_date(review),
+            alternative,
+            extension,
+        )
+    }
+
+    fn ongoing_recording_path(
+        &self,
+        review: &dyn ReviewProps,
+        alternative: Option<bool>,
+        extension: &str,
+    ) -> Option<PathBuf> {
+        let Some(template) = &self.recording_template else {
+            return self
+                .default
+                .ongoing_recording_path(review, alternative, extension);
+        };
+
+        let date = self.default.ongoing_review_date(review)?;
+        let path = self.dated_recording_path(template, review, date, alternative, extension);
+        // The template may not have a date in it
+        (path != self.recording_path(review, alternative, extension)).then_some(path)
+    }
+
+    fn event_snapshot_path(&self, review: &dyn ReviewProps, event_id: &str) -> PathBuf {
+        self.recording_path(review, None, "jpg")
+            .with_file_name(self.default.event_snapshot_file_name(review, event_id))
+    }
+
+    fn snapshot_path(&self, snapshot: &Snapshot) -> PathBuf {
+        let Some(template) = &self.snapshot_template else {
+            return self.default.snapshot_path(snapshot);
+        };
+
+        let now = get_time();
+        let path = self.render(
+            template,
+            now,
+            now,
+            &snapshot.camera_label,
+            // Empty for snapshots without an object
+            &[(
+                "{object}",
+                snapshot.object_name.as_deref().unwrap_or_default(),
+            )],
+            &[],
+        );
+        self.default.snapshots_subdir.join(path)
+    }
+
+    fn latest_snapshot_path(&self, camera_name: &str) -> PathBuf {
+        self.default.latest_snapshot_path(camera_name)
+    }
+}
+
+/// Two versions of a clip are uploaded in an alternating fashion, such that there's
+/// at least one complete file in the store, and the other (alternative) one is only
+/// deleted once the first is uploaded. Without alternative versions, there's no suffix.
+fn alternative_suffix(alternative: Option<bool>) -> &'static str {
+    alternative.map_or("", |a| ALTERNATIVE_FILE_NAME_SUFFIXES[usize::from(a)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::config::CameraNameTransform;
+    use mqtt_handler::types::reviews::payload;
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone)]
+    struct TestReviewData {
+        camera_name: String,
+        start_time: f64,
+        end_time: Option<f64>,
+        id: String,
+    }
+
+    impl ReviewProps for TestReviewData {
+        fn camera_name(&self) -> &str {
+            &self.camera_name
+        }
+
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn start_time(&self) -> f64 {
+            self.start_time
+        }
+
+        fn end_time(&self) -> Option<f64> {
+            self.end_time
+        }
+
+        fn type_field(&self) -> payload::TypeField {
+            if self.end_time.is_some() {
+                payload::TypeField::End
+            } else {
+                payload::TypeField::New
+            }
+        }
+
+        fn detections(&self) -> &[String] {
+            &[]
+        }
+
+        fn objects(&self) -> &[String] {
+            &[]
+        }
+
+        fn has_activity(&self) -> Option<bool> {
+            None
+        }
+    }
+
+    fn review(camera_name: &str, start_time: f64) -> TestReviewData {
+        TestReviewData {
+            camera_name: camera_name.to_string(),
+            start_time,
+            end_time: None,
+            id: "1718000000.5-abc".to_string(),
+        }
+    }
+
+    fn ended_review(camera_name: &str, start_time: f64, end_time: f64) -> TestReviewData {
+        TestReviewData {
+            end_time: Some(end_time),
+            ..review(camera_name, start_time)
+        }
+    }
+
+    /// A review from a minute before to a minute after a local midnight, so it starts and ends on different dates
+    fn review_over_midnight() -> (TestReviewData, TestReviewData) {
+        let midnight = chrono::NaiveDate::from_ymd_opt(2024, 6, 10)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(chrono::Local)
+            .earliest()
+            .unwrap()
+            .timestamp();
+        #[allow(clippy::cast_precision_loss)]
+        let midnight = midnight as f64;
+        (
+            review("front", midnight - 60.),
+            ended_review("front", midnight - 60., midnight + 60.),
+        )
+    }
+
+    fn snapshot(camera_label: &str, object_name: &str) -> Snapshot {
+        Snapshot {
+            image_bytes: Vec::new(),
+            camera_label: camera_label.to_string(),
+            object_name: Some(object_name.to_string()),
+        }
+    }
+
+    fn times_of(start_time: f64) -> (String, String) {
+        let time = Time::from_f64_secs_since_epoch(start_time);
+        (
+            time.as_local_time_in_dir_foramt(),
+            time.as_local_time_in_file_name_format(),
+        )
+    }
+
+    #[rstest]
+    #[case(None, None, "", "{date}/RecordingClip-front_door-{datetime}.mp4")]
+    #[case(
+        Some(false),
+        None,
+        "",
+        "{date}/RecordingClip-front_door-{datetime}-0.mp4"
+    )]
+    #[case(
+        Some(true),
+        Some(CameraDirOrder::CameraFirst),
+        "",
+        "front_door/{date}/RecordingClip-front_door-{datetime}-1.mp4"
+    )]
+    #[case(
+        Some(true),
+        Some(CameraDirOrder::DateFirst),
+        "recordings",
+        "recordings/{date}/front_door/RecordingClip-front_door-{datetime}-1.mp4"
+    )]
+    fn default_recording_path_is_unchanged(
+        #[case] alternative: Option<bool>,
+        #[case] camera_dirs: Option<CameraDirOrder>,
+        #[case] recordings_subdir: &str,
+        #[case] expected: &str,
+    ) {
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            camera_dirs,
+            recordings_subdir: recordings_subdir.into(),
+            ..SyncSystemConfig::default()
+        });
+        let (date, datetime) = times_of(1_718_000_000.5);
+
+        assert_eq!(
+            naming.recording_path(&review("front door", 1_718_000_000.5), alternative, "mp4"),
+            Path::new(
+                &expected
+                    .replace("{date}", &date)
+                    .replace("{datetime}", &datetime)
+            )
+        );
+    }
+
+    #[test]
+    fn default_event_snapshot_path_is_next_to_clip() {
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            camera_dirs: Some(CameraDirOrder::CameraFirst),
+            ..SyncSystemConfig::default()
+        });
+        let (date, datetime) = times_of(1_718_000_000.5);
+
+        assert_eq!(
+            naming.event_snapshot_path(&review("front", 1_718_000_000.5), "event/1"),
+            Path::new(&format!(
+                "front/{date}/EventSnapshot-front-{datetime}-event_1.jpg"
+            ))
+        );
+    }
+
+    #[test]
+    fn default_snapshot_paths_are_unchanged() {
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            snapshots_subdir: "snapshots".into(),
+            ..SyncSystemConfig::default()
+        });
+
+        let path = naming.snapshot_path(&snapshot("back yard", "person"));
+        let date = Time::local_time_in_dir_foramt();
+        assert_eq!(path.parent().unwrap(), Path::new("snapshots").join(date));
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("Snapshot-back_yard-"));
+        assert!(file_name.ends_with("-person.jpg"));
+
+        assert_eq!(
+            naming.latest_snapshot_path("back yard"),
+            Path::new("snapshots/latest/back_yard.jpg")
+        );
+    }
+
+    #[test]
+    fn recording_path_is_made_from_template() {
+        let naming = naming_strategy(&SyncSystemConfig {
+            recordings_subdir: "recordings".into(),
+            recording_path_template: Some(
+                PathTemplate::recording("{camera}/{year}/{month}/{day}/{id}{alternative}.{ext}")
+                    .unwrap(),
+            ),
+            ..SyncSystemConfig::default()
+        });
+        let review = review("front/door", 1_718_000_000.5);
+        let datetime = Time::from_f64_secs_since_epoch(1_718_000_000.5).as_local_datetime();
+        let dir = format!(
+            "recordings/front_door/{:04}/{:02}/{:02}",
+            datetime.year(),
+            datetime.month(),
+            datetime.day()
+        );
+
+        assert_eq!(
+            naming.recording_path(&review, Some(true), "mp4"),
+            Path::new(&format!("{dir}/1718000000_5-abc-1.mp4"))
+        );
+        assert_eq!(
+            naming.recording_path(&review, None, "montage.jpg"),
+            Path::new(&format!("{dir}/1718000000_5-abc.montage.jpg"))
+        );
+        assert!(
+            naming
+                .event_snapshot_path(&review, "event")
+                .starts_with(&dir)
+        );
+    }
+
+    #[test]
+    fn snapshot_path_is_made_from_template() {
+        let naming = naming_strategy(&SyncSystemConfig {
+            snapshot_path_template: Some(
+                PathTemplate::snapshot("{camera}/{date}/{object}-{datetime}.jpg").unwrap(),
+            ),
+            ..SyncSystemConfig::default()
+        });
+
+        let path = naming.snapshot_path(&snapshot("front", "../car"));
+
+        let mut components = path.iter();
+        assert_eq!(components.next().unwrap(), "front");
+        components.next().unwrap();
+        let file_name = components.next().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("___car-"));
+        assert!(components.next().is_none());
+
+        // Recordings keep the default naming without a template of their own
+        let review = review("front", 1_718_000_000.5);
+        assert_eq!(
+            naming.recording_path(&review, None, "mp4"),
+            DefaultNaming::new(&SyncSystemConfig::default()).recording_path(&review, None, "mp4")
+        );
+    }
+
+    #[test]
+    fn camera_display_names_are_used_in_paths() {
+        let camera_display_names = CameraDisplayNames::new(
+            BTreeMap::from([("cam_garage".to_string(), "car port".to_string())]),
+            Some(CameraNameTransform::new("^cam_(.+)_hq$", "$1").unwrap()),
+        );
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            camera_dirs: Some(CameraDirOrder::CameraFirst),
+            camera_display_names: camera_display_names.clone(),
+            ..SyncSystemConfig::default()
+        });
+        let (date, datetime) = times_of(1_718_000_000.5);
+
+        // Renamed by the transform
+        assert_eq!(
+            naming.recording_path(&review("cam_front_door_hq", 1_718_000_000.5), None, "mp4"),
+            Path::new(&format!(
+                "front_door/{date}/RecordingClip-front_door-{datetime}.mp4"
+            ))
+        );
+        assert_eq!(
+            naming.event_snapshot_path(&review("cam_front_door_hq", 1_718_000_000.5), "event"),
+            Path::new(&format!(
+                "front_door/{date}/EventSnapshot-front_door-{datetime}-event.jpg"
+            ))
+        );
+        // Renamed by the override, and sanitized like labels
+        let path = naming.snapshot_path(&snapshot("cam_garage", "car"));
+        assert!(path.starts_with("car_port"));
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.starts_with("Snapshot-car_port-"));
+        assert_eq!(
+            naming.latest_snapshot_path("cam_garage"),
+            Path::new("latest/car_port.jpg")
+        );
+        // Not renamed
+        assert_eq!(
+            naming.latest_snapshot_path("garage"),
+            Path::new("latest/garage.jpg")
+        );
+
+        let naming = naming_strategy(&SyncSystemConfig {
+            recording_path_template: Some(
+                PathTemplate::recording("{camera}/{id}{alternative}.{ext}").unwrap(),
+            ),
+            camera_display_names,
+            ..SyncSystemConfig::default()
+        });
+        assert_eq!(
+            naming.recording_path(&review("cam_front_door_hq", 1_718_000_000.5), None, "mp4"),
+            Path::new("front_door/1718000000_5-abc.mp4")
+        );
+    }
+
+    #[rstest]
+    #[case(DateBasis::Start, false)]
+    #[case(DateBasis::End, true)]
+    fn recording_dir_is_dated_by_date_basis(
+        #[case] date_basis: DateBasis,
+        #[case] dated_by_end: bool,
+    ) {
+        let (ongoing, ended) = review_over_midnight();
+        let (start_date, datetime) = times_of(ongoing.start_time);
+        let (end_date, _) = times_of(ended.end_time.unwrap());
+        assert_ne!(start_date, end_date);
+        let ended_date = if dated_by_end { &end_date } else { &start_date };
+
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            date_basis,
+            ..SyncSystemConfig::default()
+        });
+        // The end of an ongoing review isn't known, so it's dated by its start either way
+        assert_eq!(
+            naming.recording_path(&ongoing, None, "mp4"),
+            Path::new(&format!("{start_date}/RecordingClip-front-{datetime}.mp4"))
+        );
+        // Only the directory follows the date basis, and the name is still that of the start
+        assert_eq!(
+            naming.recording_path(&ended, None, "mp4"),
+            Path::new(&format!("{ended_date}/RecordingClip-front-{datetime}.mp4"))
+        );
+        assert_eq!(
+            naming.event_snapshot_path(&ended, "event"),
+            Path::new(&format!(
+                "{ended_date}/EventSnapshot-front-{datetime}-event.jpg"
+            ))
+        );
+
+        let naming = naming_strategy(&SyncSystemConfig {
+            date_basis,
+            recording_path_template: Some(
+                PathTemplate::recording("{date}/{camera}-{datetime}{alternative}.{ext}").unwrap(),
+            ),
+            ..SyncSystemConfig::default()
+        });
+        assert_eq!(
+            naming.recording_path(&ongoing, None, "mp4"),
+            Path::new(&format!("{start_date}/front-{datetime}.mp4"))
+        );
+        assert_eq!(
+            naming.recording_path(&ended, None, "mp4"),
+            Path::new(&format!("{ended_date}/front-{datetime}.mp4"))
+        );
+    }
+
+    #[rstest]
+    #[case(DateBasis::Start, false)]
+    #[case(DateBasis::End, true)]
+    fn ongoing_recording_path_is_where_interim_clips_were(
+        #[case] date_basis: DateBasis,
+        #[case] dated_by_end: bool,
+    ) {
+        let (ongoing, ended) = review_over_midnight();
+
+        let naming = DefaultNaming::new(&SyncSystemConfig {
+            date_basis,
+            ..SyncSystemConfig::default()
+        });
+        assert_eq!(naming.ongoing_recording_path(&ongoing, None, "mp4"), None);
+        assert_eq!(
+            naming.ongoing_recording_path(&ended, Some(true), "mp4"),
+            dated_by_end.then(|| naming.recording_path(&ongoing, Some(true), "mp4"))
+        );
+
+        let naming = naming_strategy(&SyncSystemConfig {
+            date_basis,
+            recording_path_template: Some(
+                PathTemplate::recording("{date}/{camera}-{datetime}{alternative}.{ext}").unwrap(),
+            ),
+            ..SyncSystemConfig::default()
+        });
+        assert_eq!(
+            naming.ongoing_recording_path(&ended, None, "mp4"),
+            dated_by_end.then(|| naming.recording_path(&ongoing, None, "mp4"))
+        );
+
+        // Without a date in the path, the clip stays where it was
+        let naming = naming_strategy(&SyncSystemConfig {
+            date_basis,
+            recording_path_template: Some(
+                PathTemplate::recording("{camera}/{datetime}{alternative}.{ext}").unwrap(),
+            ),
+            ..SyncSystemConfig::default()
+        });
+        assert_eq!(naming.ongoing_recording_path(&ended, None, "mp4"), None);
+    }
+}
@@ -0,0 +1,40 @@
+use randomness::Rng;
+
+/// The waits between the retries of an op: exponential in the retry number from `period`, capped at `max_period`,
+/// with jitter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    period: std::time::Duration,
+    max_period: std::time::Duration,
+}
+
+impl Backoff {
+    /// The max can't be lower than the period
+    #[must_use]
+    pub fn new(period: std::time::Duration, max_period: std::time::Duration) -> Self {
+        Self {
+            period,
+            max_period: max_period.max(period),
+        }
+    }
+
+    /// The wait before the retry after `retry_attempt` retries
+    pub fn delay(&self, retry_attempt: u32, rng: &mut impl Rng) -> std::time::Duration {
+        retry_delay(self.period, self.max_period, retry_attempt, rng)
+    }
+}
+
+/// The wait before the next retry: exponential in the attempt number, capped at `max`.
+/// A random jitter of up to half of it is subtracted, so that tasks that failed together don't retry in lockstep.
+pub fn retry_delay(
+    base: std::time::Duration,
+    max: std::time::Duration,
+    retry_attempt: u32,
+    rng: &mut impl Rng,
+) -> std::time::Duration {
+    let delay = base
+        .checked_mul(2u32.saturating_pow(retry_attempt))
+        .map_or(max, |d| d.min(max));
+    let half = delay / 2;
+    half + half.mul_f64(rng.random::<f64>())
+}
@@ -0,0 +1,102 @@
+use super::file_senders::{make_file_senders, split_file_senders_and_descriptors};
+use crate::system::traits::FileSenderMaker;
+use file_sender::{path_descriptor::PathDescriptor, traits::ByteStream};
+use futures::{SinkExt, StreamExt, channel::mpsc, future::join_all};
+use std::{path::Path, sync::Arc};
+
+/// How many chunks the upload to a destination may fall behind the source, before the source waits for it
+const CHUNKS_BUFFERED_PER_DESTINATION: usize = 16;
+
+pub struct StreamUploadResult {
+    /// All the data read from the source
+    pub data: Vec<u8>,
+    /// The destinations that received all the data
+    pub uploaded: Vec<Arc<PathDescriptor>>,
+    /// The destinations that couldn't be reached, or whose upload failed
+    pub failed: Vec<Arc<PathDescriptor>>,
+}
+
+/// Uploads the data of `source` to `path` in every destination while it's being read, so that reading it
+/// and uploading it overlap. There are no retries, as the data is read once; the caller can retry the failed
+/// destinations with the returned data. Fails only if reading the source fails, which fails all the uploads.
+pub async fn stream_upload<S: FileSenderMaker>(
+    source: ByteStream,
+    path: &Path,
+    path_descriptors: &[Arc<PathDescriptor>],
+    file_sender_maker: &Arc<S>,
+) -> anyhow::Result<StreamUploadResult> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let file_senders = make_file_senders(file_sender_maker, path_descriptors).await;
+    let (file_senders, mut failed) = split_file_senders_and_descriptors(file_senders);
+
+    let mut chunk_senders = Vec::with_capacity(file_senders.len());
+    let mut uploads = Vec::with_capacity(file_senders.len());
+    for file_sender in file_senders {
+        let (chunk_sender, chunk_receiver) = mpsc::channel(CHUNKS_BUFFERED_PER_DESTINATION);
+        chunk_senders.push(chunk_sender);
+        uploads.push(async move {
+            let result = async {
+                file_sender.mkdir_p(dir).await?;
+                file_sender
+                    .put_from_stream(chunk_receiver.boxed(), path)
+                    .await
+            }
+            .await;
+            (file_sender.path_descriptor().clone(), result)
+        });
+    }
+
+    let pump = async move {
+        let mut source = source;
+        let mut data = Vec::new();
+        while let Some(chunk) = source.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    for chunk_sender in &mut chunk_senders {
+                        let _ = chunk_sender
+                            .send(Err(anyhow::anyhow!("Reading the uploaded data failed")))
+                            .await;
+                    }
+                    return Err(e);
+                }
+            };
+            data.extend_from_slice(&chunk);
+            for chunk_sender in &mut chunk_senders {
+                // A destination whose upload has failed doesn't receive anymore
+                let _ = chunk_sender.send(Ok(chunk.clone())).await;
+            }
+        }
+        // Dropping the senders ends the uploads
+        Ok(data)
+    };
+
+    let (data, results) = tokio::join!(pump, join_all(uploads));
+
+    let mut uploaded = Vec::new();
+    for (path_descriptor, result) in results {
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    "Successfully streamed file {} to {path_descriptor}",
+                    path.display()
+                );
+                uploaded.push(path_descriptor);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Error streaming file {} to {path_descriptor}. Error: {e}",
+                    path.display()
+                );
+                failed.push(path_descriptor);
+            }
+        }
+    }
+
+    Ok(StreamUploadResult {
+        data: data?,
+        uploaded,
+        failed,
+    })
+}
@@ -0,0 +1,81 @@
+use file_sender::path_descriptor::PathDescriptor;
+use mqtt_handler::publication::Publication;
+use serde::Serialize;
+use std::{path::Path, sync::Arc};
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadKind {
+    Recording,
+    Snapshot,
+}
+
+/// The payload of the message published for an uploaded file
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadConfirmation {
+    pub camera: String,
+    pub kind: UploadKind,
+    pub path: String,
+    pub destinations: Vec<String>,
+}
+
+/// Publishes a confirmation over mqtt for every file that's uploaded to all destinations,
+/// to the topic `<topic_prefix>/<camera>/uploaded`
+#[derive(Debug, Clone)]
+pub struct UploadNotifier {
+    publication_sender: UnboundedSender<Publication>,
+    topic_prefix: String,
+    retain: bool,
+}
+
+impl UploadNotifier {
+    pub fn new(
+        publication_sender: UnboundedSender<Publication>,
+        topic_prefix: String,
+        retain: bool,
+    ) -> Self {
+        Self {
+            publication_sender,
+            topic_prefix,
+            retain,
+        }
+    }
+
+    /// Failing to publish is logged, since the upload itself has already succeeded
+    pub fn notify(
+        &self,
+        camera: &str,
+        kind: UploadKind,
+        path: &Path,
+        destinations: &[Arc<PathDescriptor>],
+    ) {
+        let confirmation = UploadConfirmation {
+            camera: camera.to_string(),
+            kind,
+            path: path.display().to_string(),
+            destinations: destinations.iter().map(ToString::to_string).collect(),
+        };
+
+        let payload = match serde_json::to_vec(&confirmation) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("Serializing upload confirmation {confirmation:?} failed: {e}");
+                return;
+            }
+        };
+
+        let publication = Publication {
+            topic: format!("{}/{camera}/uploaded", self.topic_prefix),
+            payload,
+            retain: self.retain,
+        };
+
+        if self.publication_sender.send(publication).is_err() {
+            tracing::error!(
+                "Publishing the upload confirmation of `{}` failed, as the mqtt handler is gone",
+                path.display()
+            );
+        }
+    }
+}
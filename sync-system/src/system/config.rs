@@ -0,0 +1,692 @@
+use file_sender::path_descriptor::PathDescriptor;
+use std::{borrow::Cow, collections::BTreeMap, num::NonZeroUsize, sync::Arc};
+use utils::{file_name::FileNameCharset, time::Time};
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+pub const DEFAULT_DESTINATION_RETRY_PERIOD: std::time::Duration =
+    std::time::Duration::from_secs(30);
+pub const DEFAULT_MQTT_UPLOAD_TOPIC_PREFIX: &str = "snapsync";
+pub const DEFAULT_EXPORT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+pub const DEFAULT_MAX_SNAPSHOT_TASKS: NonZeroUsize = NonZeroUsize::new(64).expect("Non-zero");
+
+/// A montage can't have more snapshots than this, which keeps its image a reasonable size
+const MAX_MONTAGE_CELLS: u32 = 64;
+
+const SECONDS_IN_HOUR: u32 = 60 * 60;
+const SECONDS_IN_DAY: u32 = 24 * SECONDS_IN_HOUR;
+
+/// Settings that control how the sync system processes what it receives, as opposed to
+/// how it talks to Frigate (see `FrigateApiConfig`).
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct SyncSystemConfig {
+    // Before downloading the clip of a review that has ended, check whether it already exists in all destinations, and skip it if so
+    pub skip_if_already_uploaded: bool,
+    // On shutdown, how long to wait for running uploads to finish before abandoning them
+    pub shutdown_timeout: std::time::Duration,
+    // If set, a summary of the state of the system is logged this often, so that a quiet log isn't mistaken for a hang
+    pub heartbeat_interval: Option<std::time::Duration>,
+    // If set, a JSON line is appended to this file for every file uploaded, or failed to upload, to every destination
+    pub audit_log_path: Option<std::path::PathBuf>,
+    // If set, the ids of reviews whose upload was given up on after all retries are appended to this file
+    pub dead_letter_path: Option<std::path::PathBuf>,
+    // Upload every review to two alternating file names, and only delete the older one after the newer one is uploaded.
+    // Disabling this halves the writes, but a lost connection mid-upload can leave a truncated file.
+    pub keep_alternative_versions: bool,
+    // Whether recordings and snapshots are synced at all. A disabled kind ignores everything received for it.
+    pub enable_recordings_sync: bool,
+    pub enable_snapshots_sync: bool,
+    // If set, recordings are only uploaded within these hours. Outside them, the upload task defers the download and
+    // upload of the latest clip of a review until the window opens. Snapshots from MQTT and montages aren't held.
+    pub upload_window: Option<UploadWindow>,
+    // Once a review ends, also upload the snapshot of each of its events from the API, next to its clip
+    pub upload_event_snapshots: bool,
+    // Reviews shorter than this aren't uploaded. If non-zero, a review is only uploaded once it ends and its duration is known.
+    pub min_review_duration: std::time::Duration,
+    // Updates of a review within this long of processing it are held, and only the latest of them is downloaded
+    // once the interval passes, instead of each. The end of a review is never held. Zero processes every update.
+    pub review_update_debounce: std::time::Duration,
+    // If set, a review that receives no update for this long is concluded once its latest clip is uploaded,
+    // like when it ends. For reviews whose end never arrives, which are otherwise retried until the attempts run out.
+    pub review_idle_timeout: Option<std::time::Duration>,
+    // If set, the upload of a review that ended is only concluded once this long passes without another update of it.
+    // Frigate can reopen a review after its end, and such late updates are uploaded like any other.
+    pub post_end_grace: Option<std::time::Duration>,
+    // If set, the upload task of a review is concluded this long after it started, with the clip it has uploaded,
+    // even if the review is still being updated. This bounds how long a task can hold on to its resources.
+    pub max_task_lifetime: Option<std::time::Duration>,
+    // Destinations that only receive a review's clip once it has ended, and not its interim versions, e.g., for slow destinations
+    pub final_only_destinations: Vec<Arc<PathDescriptor>>,
+    // The characters of camera and object names that are kept in the names of uploaded files
+    pub file_name_charset: FileNameCharset,
+    // Whether every snapshot is uploaded as it's received, or only the one closest to the start of each review
+    pub snapshot_mode: SnapshotMode,
+    // Also keep the latest uploaded snapshot of each camera at `<snapshots_subdir>/latest/<camera>.jpg`, overwritten by every new one
+    pub maintain_latest_snapshot: bool,
+    // At most this many snapshots are uploaded at the same time, and as many more wait for their turn.
+    // Beyond that, the oldest waiting snapshot is dropped, so that a slow destination doesn't exhaust memory.
+    pub max_snapshot_tasks: NonZeroUsize,
+    // When the mqtt connection is restored, fetch the current frame of every camera with snapshots enabled from the API,
+    // and handle it like a snapshot from mqtt, so that the snapshots missed while disconnected leave no gap
+    pub fetch_latest_snapshot_on_reconnect: bool,
+    // Once a review ends, stitch the buffered snapshots received during it into one image of this grid,
+    // and upload it next to its clip
+    pub upload_montage: bool,
+    pub montage_grid: MontageGrid,
+    // Cameras whose names match any of these are synced even if their recordings or snapshots are disabled in Frigate
+    pub force_enable_cameras: Vec<glob::Pattern>,
+    // Upload a clip to the destinations while it's being downloaded, instead of after
+    pub stream_uploads: bool,
+    // Have Frigate export clips to a file in the background, and download the file once the export is done,
+    // instead of the clip endpoint. An export that isn't done within `export_timeout` is requested again.
+    // Only for ended reviews, with the other clips downloaded as usual.
+    pub export_recording: bool,
+    pub export_timeout: std::time::Duration,
+    // If set, uploaded files are grouped in a directory per camera, nested with their date directory in this order
+    pub camera_dirs: Option<CameraDirOrder>,
+    // Whether the date directory of a review's files is that of its start, or of its end once it has ended
+    pub date_basis: DateBasis,
+    // Prepended to the paths of recordings (with their event snapshots) and of snapshots respectively,
+    // e.g., to keep them apart on the same destination. Empty by default.
+    pub recordings_subdir: std::path::PathBuf,
+    pub snapshots_subdir: std::path::PathBuf,
+    // If set, the paths of recordings and snapshots respectively under their subdirectories, instead of the default layout
+    pub recording_path_template: Option<PathTemplate>,
+    pub snapshot_path_template: Option<PathTemplate>,
+    // The names cameras are given in the paths of uploaded files, which are their labels in Frigate by default
+    pub camera_display_names: CameraDisplayNames,
+    // If set, startup fails if any destination can't be created, initialized or health checked.
+    // Otherwise, such destinations are marked degraded, and are checked again every `destination_retry_period`.
+    pub require_all_destinations: bool,
+    pub destination_retry_period: std::time::Duration,
+    // Write a lock object to every destination at startup, and refuse to start if another instance has one there.
+    // It's deleted on shutdown.
+    pub destination_lock: bool,
+    // The owner in the lock objects, which stays the same across restarts so that a crashed run's locks are taken back
+    pub instance_id: String,
+    // How the upload tasks of reviews retry, and overrides of it by camera name
+    pub recording_retry: RecordingRetry,
+    pub camera_recording_retries: BTreeMap<String, RecordingRetry>,
+    // How the upload of every snapshot retries the destinations it failed for
+    pub snapshot_retry: RecordingRetry,
+    // How every upload of a recording's files retries the destinations it failed for, before the upload task itself retries
+    pub file_op_retry: RecordingRetry,
+    // When upload confirmations are published over mqtt, they go to `<prefix>/<camera>/uploaded`, retained by the broker if set
+    pub mqtt_upload_topic_prefix: String,
+    pub mqtt_upload_retain: bool,
+    // Whether uploads happen when Frigate's uptime can't be retrieved, to check whether `delay_after_startup` has passed
+    pub gate_on_stats_failure: GateOnStatsFailure,
+}
+
+impl Default for SyncSystemConfig {
+    fn default() -> Self {
+        Self {
+            skip_if_already_uploaded: false,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            heartbeat_interval: None,
+            audit_log_path: None,
+            dead_letter_path: None,
+            keep_alternative_versions: true,
+            enable_recordings_sync: true,
+            enable_snapshots_sync: true,
+            upload_window: None,
+            upload_event_snapshots: false,
+            min_review_duration: std::time::Duration::ZERO,
+            review_update_debounce: std::time::Duration::ZERO,
+            review_idle_timeout: None,
+            post_end_grace: None,
+            max_task_lifetime: None,
+            final_only_destinations: Vec::new(),
+            file_name_charset: FileNameCharset::default(),
+            snapshot_mode: SnapshotMode::default(),
+            maintain_latest_snapshot: false,
+            max_snapshot_tasks: DEFAULT_MAX_SNAPSHOT_TASKS,
+            fetch_latest_snapshot_on_reconnect: false,
+            upload_montage: false,
+            montage_grid: MontageGrid::default(),
+            force_enable_cameras: Vec::new(),
+            stream_uploads: false,
+            export_recording: false,
+            export_timeout: DEFAULT_EXPORT_TIMEOUT,
+            camera_dirs: None,
+            date_basis: DateBasis::default(),
+            recordings_subdir: std::path::PathBuf::new(),
+            snapshots_subdir: std::path::PathBuf::new(),
+            recording_path_template: None,
+            snapshot_path_template: None,
+            camera_display_names: CameraDisplayNames::default(),
+            require_all_destinations: false,
+            destination_retry_period: DEFAULT_DESTINATION_RETRY_PERIOD,
+            destination_lock: false,
+            instance_id: String::new(),
+            recording_retry: RecordingRetry::default(),
+            camera_recording_retries: BTreeMap::new(),
+            snapshot_retry: RecordingRetry::default(),
+            file_op_retry: RecordingRetry::default(),
+            mqtt_upload_topic_prefix: DEFAULT_MQTT_UPLOAD_TOPIC_PREFIX.to_string(),
+            mqtt_upload_retain: false,
+            gate_on_stats_failure: GateOnStatsFailure::default(),
+        }
+    }
+}
+
+impl SyncSystemConfig {
+    /// Whether a review with the given times is long enough to upload. An ongoing review lasts until `now`,
+    /// and is None while it's shorter, as it may still become long enough.
+    #[must_use]
+    pub fn is_review_long_enough(
+        &self,
+        start_time: f64,
+        end_time: Option<f64>,
+        now: f64,
+    ) -> Option<bool> {
+        if self.min_review_duration.is_zero() {
+            return Some(true);
+        }
+
+        let long_enough =
+            end_time.unwrap_or(now) - start_time >= self.min_review_duration.as_secs_f64();
+        (long_enough || end_time.is_some()).then_some(long_enough)
+    }
+
+    /// The retries of the upload tasks of the camera's reviews, where what the camera doesn't override is the default
+    #[must_use]
+    pub fn recording_retry_of(&self, camera_name: &str) -> RecordingRetry {
+        self.camera_recording_retries
+            .get(camera_name)
+            .map_or(self.recording_retry, |retry| retry.or(self.recording_retry))
+    }
+}
+
+/// How the upload task of a review retries. Unset values are the defaults of the task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordingRetry {
+    // The attempts after which the task gives up on the review
+    pub max_attempts: Option<u32>,
+    // The wait before the first retry, which doubles with every retry
+    pub period: Option<std::time::Duration>,
+    // The longest the wait between retries grows to
+    pub max_period: Option<std::time::Duration>,
+}
+
+impl RecordingRetry {
+    /// The values of `self`, with the unset ones taken from `fallback`
+    #[must_use]
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            max_attempts: self.max_attempts.or(fallback.max_attempts),
+            period: self.period.or(fallback.period),
+            max_period: self.max_period.or(fallback.max_period),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotMode {
+    /// Upload every snapshot received from MQTT
+    #[default]
+    Continuous,
+    /// Buffer the snapshots of each camera for a short while, and only upload the one
+    /// received closest to the start of a new review of that camera
+    OnReview,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateOnStatsFailure {
+    /// Upload as if the delay after Frigate's startup has passed, so that a stats outage doesn't halt syncing
+    #[default]
+    Allow,
+    /// Hold uploads until Frigate's uptime can be retrieved again
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraDirOrder {
+    /// `camera/YYYY-MM-DD/...`
+    #[default]
+    CameraFirst,
+    /// `YYYY-MM-DD/camera/...`
+    DateFirst,
+}
+
+/// Which time of a review dates the directory its files are uploaded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateBasis {
+    /// When the review started
+    #[default]
+    Start,
+    /// When the review ended, for reviews that have ended, and when it started otherwise
+    End,
+}
+
+/// A daily window of hours, from `start_hour` inclusive to `end_hour` exclusive, in the given timezone,
+/// or in local time if none is given. The window wraps around midnight if `end_hour` is smaller than `start_hour`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadWindow {
+    start_hour: u32,
+    end_hour: u32,
+    timezone: Option<chrono_tz::Tz>,
+}
+
+impl UploadWindow {
+    /// Returns None if any of the hours is not in the range 0-23, or if they're equal
+    #[must_use]
+    pub fn new(start_hour: u32, end_hour: u32) -> Option<Self> {
+        if start_hour >= 24 || end_hour >= 24 || start_hour == end_hour {
+            return None;
+        }
+
+        Some(Self {
+            start_hour,
+            end_hour,
+            timezone: None,
+        })
+    }
+
+    /// The hours are in the given timezone, instead of local time
+    #[must_use]
+    pub fn with_timezone(self, timezone: chrono_tz::Tz) -> Self {
+        Self {
+            timezone: Some(timezone),
+            ..self
+        }
+    }
+
+    /// How long to wait from the given time for the window to open, zero if it's already open
+    #[must_use]
+    pub fn time_until_open(self, now: Time) -> std::time::Duration {
+        let seconds_of_day = match self.timezone {
+            Some(timezone) => now.as_seconds_of_day_in(&timezone),
+            None => now.as_local_seconds_of_day(),
+        };
+        self.time_until_open_at(seconds_of_day)
+    }
+
+    fn time_until_open_at(self, seconds_of_day: u32) -> std::time::Duration {
+        let start = self.start_hour * SECONDS_IN_HOUR;
+        let end = self.end_hour * SECONDS_IN_HOUR;
+
+        let is_open = if start < end {
+            (start..end).contains(&seconds_of_day)
+        } else {
+            seconds_of_day >= start || seconds_of_day < end
+        };
+
+        if is_open {
+            std::time::Duration::ZERO
+        } else {
+            let wait = (start + SECONDS_IN_DAY - seconds_of_day) % SECONDS_IN_DAY;
+            std::time::Duration::from_secs(wait.into())
+        }
+    }
+}
+
+/// Where a file is uploaded, relative to the subdirectory of its type, with placeholders in braces that are
+/// replaced for every file, e.g., `{camera}/{year}-{month}/{camera}-{datetime}{alternative}.{ext}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate(String);
+
+impl PathTemplate {
+    pub const RECORDING_PLACEHOLDERS: &[&str] = &[
+        "{camera}",
+        "{id}",
+        "{date}",
+        "{datetime}",
+        "{year}",
+        "{month}",
+        "{day}",
+        "{alternative}",
+        "{ext}",
+    ];
+    pub const SNAPSHOT_PLACEHOLDERS: &[&str] = &[
+        "{camera}",
+        "{object}",
+        "{date}",
+        "{datetime}",
+        "{year}",
+        "{month}",
+        "{day}",
+    ];
+
+    /// The alternative versions of a clip, and the other files named after it (e.g., its montage),
+    /// must not end up at the same path, so both their placeholders are required
+    pub fn recording(template: &str) -> Result<Self, String> {
+        Self::new(
+            template,
+            Self::RECORDING_PLACEHOLDERS,
+            &["{alternative}", "{ext}"],
+        )
+    }
+
+    pub fn snapshot(template: &str) -> Result<Self, String> {
+        Self::new(template, Self::SNAPSHOT_PLACEHOLDERS, &[])
+    }
+
+    fn new(template: &str, known: &[&str], required: &[&str]) -> Result<Self, String> {
+        let path = std::path::Path::new(template);
+        let is_contained = path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+        if template.is_empty() || !is_contained {
+            return Err("It must be a relative path, without `..`".to_string());
+        }
+
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            let placeholder = known
+                .iter()
+                .find(|p| rest[start..].starts_with(**p))
+                .ok_or_else(|| {
+                    format!(
+                        "It has an unknown placeholder. The known placeholders are: {}",
+                        known.join(", ")
+                    )
+                })?;
+            rest = &rest[start + placeholder.len()..];
+        }
+
+        if let Some(missing) = required.iter().find(|p| !template.contains(**p)) {
+            return Err(format!("It must have the placeholder {missing}"));
+        }
+
+        Ok(Self(template.to_string()))
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The names cameras are given in paths, e.g., `front_door` for a camera labelled `cam_front_door_hq` in Frigate.
+/// Only paths use them, so cameras are still matched (e.g., in MQTT topics) by their labels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CameraDisplayNames {
+    overrides: BTreeMap<String, String>,
+    transform: Option<CameraNameTransform>,
+}
+
+impl CameraDisplayNames {
+    #[must_use]
+    pub fn new(
+        overrides: BTreeMap<String, String>,
+        transform: Option<CameraNameTransform>,
+    ) -> Self {
+        Self {
+            overrides,
+            transform,
+        }
+    }
+
+    /// The override of the camera if it has one, otherwise its label with the transform applied
+    #[must_use]
+    pub fn display_name<'a>(&'a self, camera_label: &'a str) -> Cow<'a, str> {
+        if let Some(name) = self.overrides.get(camera_label) {
+            return Cow::Borrowed(name);
+        }
+        match &self.transform {
+            Some(transform) => transform.apply(camera_label),
+            None => Cow::Borrowed(camera_label),
+        }
+    }
+}
+
+/// Replaces the first match of a regex in camera labels, e.g., `^cam_(.+)_hq$` with `$1` turns `cam_front_door_hq`
+/// into `front_door`. Labels that don't match are unchanged.
+#[derive(Debug, Clone)]
+pub struct CameraNameTransform {
+    pattern: regex::Regex,
+    replacement: String,
+}
+
+impl CameraNameTransform {
+    pub fn new(pattern: &str, replacement: impl Into<String>) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: regex::Regex::new(pattern)?,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// A label that would be replaced with nothing is kept, so the camera still has a name
+    fn apply<'a>(&self, camera_label: &'a str) -> Cow<'a, str> {
+        let name = self
+            .pattern
+            .replace(camera_label, self.replacement.as_str());
+        if name.is_empty() {
+            Cow::Borrowed(camera_label)
+        } else {
+            name
+        }
+    }
+}
+
+impl PartialEq for CameraNameTransform {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.replacement == other.replacement
+    }
+}
+
+impl Eq for CameraNameTransform {}
+
+/// The columns and rows of a montage, which has a cell for each of its snapshots.
+/// Rows that no snapshot fills are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MontageGrid {
+    columns: u32,
+    rows: u32,
+}
+
+impl MontageGrid {
+    /// Returns None if there are no columns or rows, or if there are more than `MAX_MONTAGE_CELLS` cells
+    #[must_use]
+    pub fn new(columns: u32, rows: u32) -> Option<Self> {
+        if columns == 0 || rows == 0 || columns.saturating_mul(rows) > MAX_MONTAGE_CELLS {
+            return None;
+        }
+
+        Some(Self { columns, rows })
+    }
+
+    #[must_use]
+    pub fn columns(self) -> u32 {
+        self.columns
+    }
+
+    #[must_use]
+    pub fn rows(self) -> u32 {
+        self.rows
+    }
+
+    /// The maximum number of snapshots in the montage
+    #[must_use]
+    pub fn cells(self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+impl Default for MontageGrid {
+    fn default() -> Self {
+        Self {
+            columns: 3,
+            rows: 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, 100., None, 100., Some(true))]
+    #[case(0, 100., Some(100.5), 200., Some(true))]
+    #[case(5, 100., Some(104.9), 200., Some(false))]
+    #[case(5, 100., Some(105.), 200., Some(true))]
+    #[case(5, 100., Some(160.), 200., Some(true))]
+    // Still ongoing and too short so far, so it's deferred until it's long enough
+    #[case(5, 100., None, 104.9, None)]
+    #[case(5, 100., None, 105., Some(true))]
+    fn review_duration_filter(
+        #[case] min_secs: u64,
+        #[case] start_time: f64,
+        #[case] end_time: Option<f64>,
+        #[case] now: f64,
+        #[case] expected: Option<bool>,
+    ) {
+        let config = SyncSystemConfig {
+            min_review_duration: std::time::Duration::from_secs(min_secs),
+            ..SyncSystemConfig::default()
+        };
+        assert_eq!(
+            config.is_review_long_enough(start_time, end_time, now),
+            expected
+        );
+    }
+
+    #[test]
+    fn camera_recording_retry_overrides_default() {
+        let secs = std::time::Duration::from_secs;
+        let config = SyncSystemConfig {
+            recording_retry: RecordingRetry {
+                max_attempts: Some(10),
+                period: None,
+                max_period: Some(secs(600)),
+            },
+            camera_recording_retries: BTreeMap::from([
+                (
+                    "slow".to_string(),
+                    RecordingRetry {
+                        max_attempts: Some(100),
+                        period: Some(secs(120)),
+                        max_period: Some(secs(3600)),
+                    },
+                ),
+                (
+                    "fast".to_string(),
+                    RecordingRetry {
+                        max_attempts: None,
+                        period: Some(secs(5)),
+                        max_period: None,
+                    },
+                ),
+            ]),
+            ..SyncSystemConfig::default()
+        };
+
+        let retry = |max_attempts, period, max_period| RecordingRetry {
+            max_attempts,
+            period,
+            max_period,
+        };
+        assert_eq!(
+            config.recording_retry_of("slow"),
+            retry(Some(100), Some(secs(120)), Some(secs(3600)))
+        );
+        assert_eq!(
+            config.recording_retry_of("fast"),
+            retry(Some(10), Some(secs(5)), Some(secs(600)))
+        );
+        assert_eq!(
+            config.recording_retry_of("other"),
+            retry(Some(10), None, Some(secs(600)))
+        );
+    }
+
+    #[rstest]
+    #[case(UploadWindow::new(1, 5).unwrap(), 0, 3600)]
+    #[case(UploadWindow::new(1, 5).unwrap(), 3600, 0)]
+    #[case(UploadWindow::new(1, 5).unwrap(), 5 * 3600 - 1, 0)]
+    #[case(UploadWindow::new(1, 5).unwrap(), 5 * 3600, 20 * 3600)]
+    #[case(UploadWindow::new(22, 6).unwrap(), 23 * 3600, 0)]
+    #[case(UploadWindow::new(22, 6).unwrap(), 3 * 3600, 0)]
+    #[case(UploadWindow::new(22, 6).unwrap(), 6 * 3600, 16 * 3600)]
+    #[case(UploadWindow::new(22, 6).unwrap(), 22 * 3600 - 30, 30)]
+    fn upload_window_wait(
+        #[case] window: UploadWindow,
+        #[case] seconds_of_day: u32,
+        #[case] expected_wait_secs: u64,
+    ) {
+        assert_eq!(
+            window.time_until_open_at(seconds_of_day),
+            std::time::Duration::from_secs(expected_wait_secs)
+        );
+    }
+
+    #[test]
+    fn invalid_upload_window() {
+        assert!(UploadWindow::new(24, 5).is_none());
+        assert!(UploadWindow::new(5, 24).is_none());
+        assert!(UploadWindow::new(5, 5).is_none());
+    }
+
+    #[rstest]
+    #[case("{camera}/{date}/{camera}-{datetime}{alternative}.{ext}", true)]
+    #[case("{year}/{month}/{day}/{id}{alternative}.{ext}", true)]
+    #[case("{camera}-{datetime}.{ext}", false)]
+    #[case("{camera}{alternative}", false)]
+    #[case("{camera}/{object}{alternative}.{ext}", false)]
+    #[case("{camera}/{hour}{alternative}.{ext}", false)]
+    #[case("{camera{alternative}.{ext}", false)]
+    #[case("../{camera}{alternative}.{ext}", false)]
+    #[case("/{camera}{alternative}.{ext}", false)]
+    #[case("", false)]
+    fn recording_path_template_validity(#[case] template: &str, #[case] valid: bool) {
+        assert_eq!(PathTemplate::recording(template).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("{camera}/{date}/{object}-{datetime}.jpg", true)]
+    #[case("latest.jpg", true)]
+    #[case("{camera}/{id}.jpg", false)]
+    #[case("{camera}/{ext}", false)]
+    #[case("a/../{camera}.jpg", false)]
+    fn snapshot_path_template_validity(#[case] template: &str, #[case] valid: bool) {
+        assert_eq!(PathTemplate::snapshot(template).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case(3, 2, true)]
+    #[case(1, 1, true)]
+    #[case(8, 8, true)]
+    #[case(0, 2, false)]
+    #[case(3, 0, false)]
+    #[case(8, 9, false)]
+    #[case(u32::MAX, 2, false)]
+    fn montage_grid_validity(#[case] columns: u32, #[case] rows: u32, #[case] valid: bool) {
+        assert_eq!(MontageGrid::new(columns, rows).is_some(), valid);
+    }
+
+    #[rstest]
+    // Overrides win over the transform
+    #[case("cam_front_door_hq", "porch")]
+    #[case("cam_back_yard_hq", "back_yard")]
+    // Labels that don't match the transform are unchanged
+    #[case("garage", "garage")]
+    #[case("cam_garage", "cam_garage")]
+    fn camera_display_name(#[case] camera_label: &str, #[case] expected: &str) {
+        let names = CameraDisplayNames::new(
+            BTreeMap::from([("cam_front_door_hq".to_string(), "porch".to_string())]),
+            Some(CameraNameTransform::new("^cam_(.+)_hq$", "$1").unwrap()),
+        );
+        assert_eq!(names.display_name(camera_label), expected);
+    }
+
+    #[test]
+    fn camera_name_transform_to_nothing_keeps_label() {
+        let names = CameraDisplayNames::new(
+            BTreeMap::new(),
+            Some(CameraNameTransform::new("^cam_.*$", "").unwrap()),
+        );
+        assert_eq!(names.display_name("cam_front"), "cam_front");
+        assert_eq!(
+            CameraDisplayNames::default().display_name("cam_front"),
+            "cam_front"
+        );
+    }
+}
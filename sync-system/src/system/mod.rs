@@ -1,47 +1,201 @@
+mod backfill;
 mod common;
+pub mod config;
+mod mqtt_commands;
+mod prune_alternatives;
 mod recording_upload_handler;
+mod replay;
+mod retry_dead_letters;
+mod review_from_api;
+mod review_from_event;
+mod self_test;
 mod snapshot_upload_task;
 pub mod traits;
+mod uptime_gate;
 
-use crate::{config::PathDescriptors, state::CamerasState};
-use file_sender::{path_descriptor::PathDescriptor, traits::StoreDestination};
-use frigate_api_caller::{config::FrigateApiConfig, traits::FrigateApi};
+use crate::{
+    config::PathDescriptors,
+    snapshot_buffer::SnapshotBuffer,
+    state::{CAMERA_REPUBLISH_GRACE, CamerasState},
+    stats::{SharedUploadStats, UploadStats},
+};
+use common::{
+    degraded_destinations::{DegradedDestinations, check_destination, retry_degraded_destinations},
+    destination_lock::{acquire_destination_locks, release_destination_locks},
+    upload_notifier::UploadNotifier,
+};
+use config::{SnapshotMode, SyncSystemConfig};
+use file_sender::path_descriptor::PathDescriptor;
+use frigate_api_caller::{
+    STALE_PARTIAL_DOWNLOAD_AGE, config::FrigateApiConfig, json::frigate_config::RetainMode,
+    remove_stale_partial_downloads, traits::FrigateApi,
+};
 use futures::FutureExt;
-use mqtt_handler::types::{CapturedPayloads, reviews::ReviewProps, snapshot::Snapshot};
+use mqtt_handler::{
+    publication::Publication,
+    types::{
+        CapturedPayloads,
+        reviews::{ReviewProps, payload::TypeField},
+        snapshot::Snapshot,
+    },
+};
 use recording_upload_handler::{RecordingsTaskHandler, RecordingsUploadTaskHandlerCommand};
+use review_from_api::ReviewFromApi;
 use snapshot_upload_task::{SnapshotsTaskHandler, SnapshotsUploadTaskHandlerCommand};
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 use tokio::{
     sync::{
+        broadcast,
         mpsc::{UnboundedReceiver, UnboundedSender},
-        oneshot,
+        oneshot, watch,
     },
     task::JoinHandle,
 };
 use traits::{FileSenderMaker, FrigateApiMaker};
-use utils::struct_name;
+
+pub use crate::state::{CameraStateChange, CameraStateKind};
+pub use backfill::{BackfillRange, run_backfill};
+pub use common::destination_lock::with_destination_locks;
+pub use mqtt_commands::run_mqtt_commands;
+pub use prune_alternatives::{AlternativePair, PruneReport, prune_alternatives};
+pub use replay::{FixtureFrigateApi, run_replay};
+pub use retry_dead_letters::run_dead_letters_retry;
+pub use self_test::{SelfTestReport, SelfTestStep, run_destination_test, run_self_test};
+use utils::{
+    struct_name,
+    systemd::{ServiceNotifier, ServiceState},
+    time::get_time,
+};
 
 const STRUCT_NAME: &str = struct_name!(SyncSystem);
 const SLEEP_TIME_ON_API_ERROR: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long retrieving the recordings retain modes of cameras from Frigate may take, so that a hanging Frigate
+/// doesn't hold up startup
+const RETAIN_MODES_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// Subscribers that fall behind by more changes than this miss the oldest ones
+const CAMERA_STATE_CHANGES_CAPACITY: usize = 64;
+/// The object name of the snapshots fetched from the API on reconnecting to mqtt, as they're not of any object
+const LATEST_SNAPSHOT_OBJECT_NAME: &str = "latest";
+
+/// The first heartbeat is one interval after startup, as there's nothing to report right away
+fn heartbeat_interval(period: std::time::Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval
+}
+
+/// The recordings retain modes of cameras, or None if Frigate doesn't give them within `RETAIN_MODES_TIMEOUT`
+async fn fetch_retain_modes(
+    api: anyhow::Result<Arc<dyn FrigateApi>>,
+) -> Option<HashMap<String, RetainMode>> {
+    let result = match api {
+        Ok(api) => tokio::time::timeout(RETAIN_MODES_TIMEOUT, api.recordings_retain_modes())
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out after {RETAIN_MODES_TIMEOUT:?}"))
+            .and_then(|result| result.map_err(Into::into)),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(retain_modes) => Some(retain_modes),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to retrieve the recordings retain modes of cameras from the Frigate API: {e}"
+            );
+            None
+        }
+    }
+}
+
+/// Completes at `deadline`, or never if there isn't one
+fn sleep_until_deadline(
+    deadline: Option<tokio::time::Instant>,
+) -> futures::future::BoxFuture<'static, ()> {
+    match deadline {
+        Some(instant) => tokio::time::sleep_until(instant).boxed(),
+        None => futures::future::pending().boxed(),
+    }
+}
 
 pub struct SyncSystem<F, S> {
     cameras_state: CamerasState,
+    /// When the cameras Frigate hasn't republished since it restarted are pruned, once it's back online
+    camera_prune_at: Option<tokio::time::Instant>,
+    upload_stats: SharedUploadStats,
     upload_dests: PathDescriptors,
 
     frigate_api_config: Arc<FrigateApiConfig>,
     frigate_api_maker: Arc<F>,
     file_sender_maker: Arc<S>,
+    sync_config: Arc<SyncSystemConfig>,
 
-    rec_updates_sender: UnboundedSender<RecordingsUploadTaskHandlerCommand>,
-    snapshots_updates_sender: UnboundedSender<SnapshotsUploadTaskHandlerCommand>,
+    /// None if recordings sync is disabled in the config, and its handler isn't running
+    rec_updates_sender: Option<UnboundedSender<RecordingsUploadTaskHandlerCommand>>,
+    /// None if snapshots sync is disabled in the config, and its handler isn't running
+    snapshots_updates_sender: Option<UnboundedSender<SnapshotsUploadTaskHandlerCommand>>,
+    /// Recent snapshots waiting for a review to be uploaded with, when snapshots are only uploaded on reviews,
+    /// or to be composed into the montage of a review
+    snapshot_buffer: SnapshotBuffer,
     mqtt_data_receiver: tokio::sync::mpsc::UnboundedReceiver<CapturedPayloads>,
+    /// Set once every sender of mqtt data is gone, and all the data they sent has been processed
+    mqtt_data_ended: bool,
+    /// Whether the system stops once the mqtt data ends, e.g., when replaying recorded messages
+    stop_on_mqtt_data_end: bool,
+    /// When the last data from mqtt was processed, for the heartbeat
+    last_event_time: Option<tokio::time::Instant>,
+    /// If set, whether the mqtt client is connected to the broker, for the heartbeat and to catch up on reconnecting
+    mqtt_connection_status: Option<watch::Receiver<bool>>,
+    /// The last seen connection status of the mqtt client. None until it's connected for the first time.
+    mqtt_connected: Option<bool>,
 
     /// This can be used in tests (and otherwise) to retrieve the current state of cameras
     camera_state_getter: Option<UnboundedReceiver<oneshot::Sender<CamerasState>>>,
+    /// Changes of the states of cameras in Frigate, for whoever subscribed to them
+    camera_state_changes: broadcast::Sender<CameraStateChange>,
+
+    /// Commands to control the system while it's running
+    command_receiver: Option<UnboundedReceiver<SyncSystemCommand>>,
 
     join_handles: Vec<(String, JoinHandle<()>)>,
 
+    /// Destinations that failed their check at startup, until they pass it again
+    degraded_destinations: DegradedDestinations,
+    /// Checks the degraded destinations again in the background, while there are any
+    degraded_destinations_task: Option<JoinHandle<()>>,
+    /// The owner written to the lock objects of the destinations, once they're locked with `destination_lock`
+    destination_lock_owner: Option<String>,
+    /// The recordings retain modes of cameras, retrieved from Frigate in the background when it comes online,
+    /// so that a slow Frigate doesn't hold up the event loop
+    retain_modes_sender: UnboundedSender<HashMap<String, RetainMode>>,
+    retain_modes_receiver: UnboundedReceiver<HashMap<String, RetainMode>>,
+    /// Retrieves the retain modes in the background, while it's running
+    retain_modes_task: Option<JoinHandle<()>>,
+    /// Ongoing reviews that are still shorter than `min_review_duration`, by id, with their latest update
+    /// and when they become long enough to upload
+    pending_short_reviews: BTreeMap<String, (Arc<dyn ReviewProps>, tokio::time::Instant)>,
+
     stop_receiver: Option<UnboundedReceiver<()>>,
+
+    /// If set, the service manager (e.g., systemd) is told when the system stops,
+    /// and its watchdog is notified from the event loop on every tick of `watchdog_interval`
+    service_notifier: Option<Arc<dyn ServiceNotifier>>,
+    watchdog_interval: Option<tokio::time::Interval>,
+}
+
+/// Commands that can be sent to a running `SyncSystem`
+#[derive(Debug)]
+pub enum SyncSystemCommand {
+    /// Retrieve the review with the given id from the Frigate API and upload its recording now,
+    /// without waiting for it to arrive from MQTT. Like reviews from MQTT, it's ignored if recordings
+    /// are disabled for its camera, unless `force` is set.
+    SyncReview { id: String, force: bool },
+    /// Get the number of recordings and snapshots uploaded per camera, and when the last ones were
+    GetUploadStats(oneshot::Sender<UploadStats>),
+    /// Get the destinations that failed their check at startup, and haven't passed it since
+    GetDegradedDestinations(oneshot::Sender<Vec<Arc<PathDescriptor>>>),
 }
 
 impl<F, S> SyncSystem<F, S>
@@ -49,64 +203,180 @@ where
     F: FrigateApiMaker,
     S: FileSenderMaker,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         upload_dests: PathDescriptors,
         frigate_api_config: Arc<FrigateApiConfig>,
+        sync_config: Arc<SyncSystemConfig>,
         frigate_api_maker: F,
         file_sender_maker: S,
         mqtt_data_receiver: tokio::sync::mpsc::UnboundedReceiver<CapturedPayloads>,
+        publication_sender: Option<UnboundedSender<Publication>>,
         camera_state_getter: Option<UnboundedReceiver<oneshot::Sender<CamerasState>>>,
+        command_receiver: Option<UnboundedReceiver<SyncSystemCommand>>,
         stop_receiver: Option<UnboundedReceiver<()>>,
     ) -> Self {
         let frigate_api_maker = Arc::new(frigate_api_maker);
         let file_sender_maker = Arc::new(file_sender_maker);
 
-        let (rec_updates_sender, rec_updates_receiver) = tokio::sync::mpsc::unbounded_channel();
-        let rec_handler_task = Self::run_reviews_task_handler(
-            rec_updates_receiver,
-            frigate_api_maker.clone(),
-            frigate_api_config.clone(),
-            file_sender_maker.clone(),
-            upload_dests.clone(),
-        );
+        let upload_stats = SharedUploadStats::default();
+        let upload_notifier = publication_sender.map(|sender| {
+            UploadNotifier::new(
+                sender,
+                sync_config.mqtt_upload_topic_prefix.clone(),
+                sync_config.mqtt_upload_retain,
+            )
+        });
 
-        let (snapshots_updates_sender, snapshots_updates_receiver) =
-            tokio::sync::mpsc::unbounded_channel();
-        let snapshots_task_join_handler = Self::run_snapshots_task_handler(
-            snapshots_updates_receiver,
-            file_sender_maker.clone(),
-            upload_dests.clone(),
-        );
+        let mut join_handles = Vec::new();
 
-        let join_handles = vec![
-            ("recordings handler".to_string(), rec_handler_task),
-            ("snapshots handler".to_string(), snapshots_task_join_handler),
-        ];
+        let rec_updates_sender = if sync_config.enable_recordings_sync {
+            let (rec_updates_sender, rec_updates_receiver) = tokio::sync::mpsc::unbounded_channel();
+            let rec_handler_task = Self::run_reviews_task_handler(
+                rec_updates_receiver,
+                frigate_api_maker.clone(),
+                frigate_api_config.clone(),
+                file_sender_maker.clone(),
+                upload_dests.clone(),
+                sync_config.clone(),
+                upload_stats.clone(),
+                upload_notifier.clone(),
+            );
+            join_handles.push(("recordings handler".to_string(), rec_handler_task));
+            Some(rec_updates_sender)
+        } else {
+            tracing::info!("Recordings sync is disabled in the config");
+            None
+        };
+
+        let snapshots_updates_sender = if sync_config.enable_snapshots_sync {
+            let (snapshots_updates_sender, snapshots_updates_receiver) =
+                tokio::sync::mpsc::unbounded_channel();
+            let snapshots_task_join_handler = Self::run_snapshots_task_handler(
+                snapshots_updates_receiver,
+                file_sender_maker.clone(),
+                upload_dests.clone(),
+                sync_config.clone(),
+                upload_stats.clone(),
+                upload_notifier,
+            );
+            join_handles.push(("snapshots handler".to_string(), snapshots_task_join_handler));
+            Some(snapshots_updates_sender)
+        } else {
+            tracing::info!("Snapshots sync is disabled in the config");
+            None
+        };
+
+        let (retain_modes_sender, retain_modes_receiver) = tokio::sync::mpsc::unbounded_channel();
 
         Self {
-            cameras_state: CamerasState::default(),
+            cameras_state: CamerasState::new(sync_config.force_enable_cameras.clone()),
+            camera_prune_at: None,
+            upload_stats,
             upload_dests,
 
             frigate_api_config,
             frigate_api_maker,
             file_sender_maker,
+            sync_config,
 
             rec_updates_sender,
             snapshots_updates_sender,
+            snapshot_buffer: SnapshotBuffer::default(),
             mqtt_data_receiver,
+            mqtt_data_ended: false,
+            stop_on_mqtt_data_end: false,
+            last_event_time: None,
+            mqtt_connection_status: None,
+            mqtt_connected: None,
 
             camera_state_getter,
+            camera_state_changes: broadcast::channel(CAMERA_STATE_CHANGES_CAPACITY).0,
+
+            command_receiver,
 
             join_handles,
 
+            degraded_destinations: DegradedDestinations::default(),
+            degraded_destinations_task: None,
+            destination_lock_owner: None,
+            retain_modes_sender,
+            retain_modes_receiver,
+            retain_modes_task: None,
+            pending_short_reviews: BTreeMap::new(),
+
             stop_receiver,
+
+            service_notifier: None,
+            watchdog_interval: None,
         }
     }
 
+    /// Reports the state of the system to the given notifier. With a watchdog interval, the watchdog is
+    /// notified that often from the event loop, so that it stops being notified if the event loop is stuck.
+    #[must_use]
+    pub fn with_service_notifier(
+        mut self,
+        notifier: Arc<dyn ServiceNotifier>,
+        watchdog_interval: Option<std::time::Duration>,
+    ) -> Self {
+        self.service_notifier = Some(notifier);
+        self.watchdog_interval = watchdog_interval.map(|period| {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+        self
+    }
+
+    /// Reports in the heartbeat whether the mqtt client is connected to the broker, and catches up on
+    /// what was missed while it wasn't (see `fetch_latest_snapshot_on_reconnect`) once it's connected again
+    #[must_use]
+    pub fn with_mqtt_connection_status(mut self, status: watch::Receiver<bool>) -> Self {
+        self.mqtt_connection_status = Some(status);
+        self
+    }
+
+    /// Stops the system, like a stop signal, once all the senders of mqtt data are dropped and the data
+    /// they sent is processed. Otherwise, the system keeps running without mqtt data until it's stopped.
+    #[must_use]
+    pub fn with_stop_on_mqtt_data_end(mut self) -> Self {
+        self.stop_on_mqtt_data_end = true;
+        self
+    }
+
+    /// Receives every change of the recordings or snapshots state of a camera in Frigate,
+    /// e.g., for automations. Repeating the same state isn't a change.
+    #[must_use]
+    pub fn subscribe_camera_state_changes(&self) -> broadcast::Receiver<CameraStateChange> {
+        self.camera_state_changes.subscribe()
+    }
+
     pub async fn start(mut self) -> anyhow::Result<()> {
+        if self.frigate_api_config.resume_downloads {
+            remove_stale_partial_downloads(STALE_PARTIAL_DOWNLOAD_AGE).await;
+        }
+
         self.test_frigate_api_connection().await;
 
-        self.test_file_senders().await;
+        // Awaited, so that the first reviews are checked against the retain modes
+        if let Some(retain_modes) = fetch_retain_modes(self.make_frigate_api()).await {
+            self.cameras_state.update_retain_modes(retain_modes);
+        }
+
+        if let Err(e) = self.check_destinations().await {
+            self.shutdown().await;
+            return Err(e);
+        }
+
+        if self.sync_config.destination_lock {
+            if let Err(e) = self.lock_destinations().await {
+                self.shutdown().await;
+                return Err(e);
+            }
+        }
+
+        let mut heartbeat_interval = self.sync_config.heartbeat_interval.map(heartbeat_interval);
 
         loop {
             let stop_receiver = match self.stop_receiver.as_mut() {
@@ -119,9 +389,46 @@ where
                 None => futures::future::pending().boxed(),
             };
 
+            let command_receiver = match self.command_receiver.as_mut() {
+                Some(receiver) => receiver.recv().boxed(),
+                None => futures::future::pending().boxed(),
+            };
+
+            let watchdog_tick = match self.watchdog_interval.as_mut() {
+                Some(interval) => interval.tick().boxed(),
+                None => futures::future::pending().boxed(),
+            };
+
+            let mqtt_connection_change = match self.mqtt_connection_status.as_mut() {
+                Some(status) => status.changed().boxed(),
+                None => futures::future::pending().boxed(),
+            };
+
+            let heartbeat_tick = match heartbeat_interval.as_mut() {
+                Some(interval) => interval.tick().boxed(),
+                None => futures::future::pending().boxed(),
+            };
+
+            let camera_prune = sleep_until_deadline(self.camera_prune_at);
+
+            let short_review_due = sleep_until_deadline(
+                self.pending_short_reviews
+                    .values()
+                    .map(|(_, due)| *due)
+                    .min(),
+            );
+
             tokio::select! {
-                Some(data) = self.mqtt_data_receiver.recv() => {
-                    self.on_mqtt_data_received(data).await;
+                data = self.mqtt_data_receiver.recv(), if !self.mqtt_data_ended => {
+                    if let Some(data) = data {
+                        self.on_mqtt_data_received(data).await;
+                    } else {
+                        self.mqtt_data_ended = true;
+                        if self.stop_on_mqtt_data_end {
+                            tracing::info!("All mqtt data was processed. Stopping {STRUCT_NAME}.");
+                            break;
+                        }
+                    }
                 },
 
                 Some(sender) = camera_state_receiver => {
@@ -133,6 +440,35 @@ where
                     }
                 },
 
+                Some(command) = command_receiver => {
+                    self.on_command_received(command).await;
+                },
+
+                Some(retain_modes) = self.retain_modes_receiver.recv() => {
+                    self.cameras_state.update_retain_modes(retain_modes);
+                },
+
+                _ = watchdog_tick => {
+                    self.notify_service(ServiceState::Watchdog);
+                },
+
+                Ok(()) = mqtt_connection_change => {
+                    self.on_mqtt_connection_changed().await;
+                },
+
+                _ = heartbeat_tick => {
+                    self.log_heartbeat().await;
+                },
+
+                () = camera_prune => {
+                    self.camera_prune_at = None;
+                    self.cameras_state.prune_unpublished_cameras();
+                },
+
+                () = short_review_due => {
+                    self.on_short_reviews_due().await;
+                },
+
                 Some(()) = stop_receiver => {
                     tracing::info!("Received stop signal to stop {STRUCT_NAME}.");
                     break;
@@ -142,27 +478,52 @@ where
 
         tracing::info!("Reached the end of {STRUCT_NAME} event loop. Unwinding all task managers.");
 
-        self.rec_updates_sender
-            .send(RecordingsUploadTaskHandlerCommand::Stop)
-            .expect("Sending stop signal for recordings handler failed");
+        self.notify_service(ServiceState::Stopping);
 
-        self.snapshots_updates_sender
-            .send(SnapshotsUploadTaskHandlerCommand::Stop)
-            .expect("Sending stop signal for snapshots handler failed");
+        self.shutdown().await;
 
-        for (task_name, join_handle) in &mut self.join_handles {
-            match join_handle.await {
-                Ok(()) => tracing::info!("Joining {task_name} task completed successfully"),
-                Err(e) => tracing::error!("CRITICAL: Failed to join {task_name} task: {e}"),
-            }
+        Ok(())
+    }
+
+    /// Stops the task handlers, and waits for them to finish their running tasks, up to the shutdown timeout
+    async fn shutdown(&mut self) {
+        if let Some(task) = self.degraded_destinations_task.take() {
+            task.abort();
         }
 
-        tracing::info!("Unwinding of {STRUCT_NAME} done.");
+        if let Some(task) = self.retain_modes_task.take() {
+            task.abort();
+        }
 
-        Ok(())
+        if let Some(sender) = &self.rec_updates_sender {
+            sender
+                .send(RecordingsUploadTaskHandlerCommand::Stop)
+                .expect("Sending stop signal for recordings handler failed");
+        }
+
+        if let Some(sender) = &self.snapshots_updates_sender {
+            sender
+                .send(SnapshotsUploadTaskHandlerCommand::Stop)
+                .expect("Sending stop signal for snapshots handler failed");
+        }
+
+        join_tasks_with_timeout(&mut self.join_handles, self.sync_config.shutdown_timeout).await;
+
+        if let Some(owner) = self.destination_lock_owner.take() {
+            release_destination_locks(
+                self.file_sender_maker.as_ref(),
+                &self.all_destinations(),
+                &owner,
+            )
+            .await;
+        }
+
+        tracing::info!("Unwinding of {STRUCT_NAME} done.");
     }
 
     async fn on_mqtt_data_received(&mut self, data: CapturedPayloads) {
+        self.last_event_time = Some(tokio::time::Instant::now());
+
         match data {
             CapturedPayloads::CameraRecordingsState(recordings_state) => {
                 tracing::info!(
@@ -171,8 +532,10 @@ where
                     recordings_state.state
                 );
 
-                self.cameras_state
+                let change = self
+                    .cameras_state
                     .update_recordings_state(recordings_state.camera_label, recordings_state.state);
+                self.notify_camera_state_change(change);
             }
             CapturedPayloads::CameraSnapshotsState(snapshots_state) => {
                 tracing::info!(
@@ -181,8 +544,36 @@ where
                     snapshots_state.state
                 );
 
-                self.cameras_state
+                let change = self
+                    .cameras_state
                     .update_snapshots_state(snapshots_state.camera_label, snapshots_state.state);
+                self.notify_camera_state_change(change);
+            }
+            CapturedPayloads::FrigateAvailability(availability) => {
+                tracing::info!(
+                    "{STRUCT_NAME}: Frigate is now {}",
+                    if availability.online {
+                        "online"
+                    } else {
+                        "offline"
+                    }
+                );
+
+                self.cameras_state
+                    .update_frigate_availability(availability.online);
+                self.camera_prune_at =
+                    if availability.online && self.cameras_state.awaits_republished_cameras() {
+                        // Frigate republishes its cameras after it says it's online, so they're given time
+                        self.camera_prune_at
+                            .or_else(|| Some(tokio::time::Instant::now() + CAMERA_REPUBLISH_GRACE))
+                    } else {
+                        None
+                    };
+
+                // Frigate's config may have changed while it was offline
+                if availability.online {
+                    self.update_retain_modes();
+                }
             }
             CapturedPayloads::Snapshot(snapshot) => {
                 tracing::info!(
@@ -205,22 +596,175 @@ where
         }
     }
 
+    async fn on_mqtt_connection_changed(&mut self) {
+        let Some(status) = self.mqtt_connection_status.as_mut() else {
+            return;
+        };
+        let connected = *status.borrow_and_update();
+
+        let reconnected = connected && self.mqtt_connected == Some(false);
+        if connected || self.mqtt_connected.is_some() {
+            self.mqtt_connected = Some(connected);
+        }
+
+        if reconnected && self.sync_config.fetch_latest_snapshot_on_reconnect {
+            self.fetch_latest_snapshots().await;
+        }
+    }
+
+    /// Handles the current frame of every camera with snapshots enabled like a snapshot from mqtt
+    async fn fetch_latest_snapshots(&mut self) {
+        if self.snapshots_updates_sender.is_none() {
+            return;
+        }
+
+        let api = match self.make_frigate_api() {
+            Ok(api) => api,
+            Err(e) => {
+                tracing::error!("Failed to create Frigate API to fetch the latest snapshots: {e}");
+                return;
+            }
+        };
+
+        let mut cameras = self
+            .cameras_state
+            .snapshots_state()
+            .keys()
+            .filter(|camera| self.cameras_state.camera_snapshots_state(camera))
+            .cloned()
+            .collect::<Vec<_>>();
+        cameras.sort();
+
+        tracing::info!(
+            "{STRUCT_NAME}: Reconnected to mqtt. Fetching the latest snapshots of cameras: {cameras:?}"
+        );
+
+        for camera in cameras {
+            match api.latest_snapshot(&camera).await {
+                Ok(Some(image_bytes)) => {
+                    let snapshot = Snapshot {
+                        image_bytes,
+                        camera_label: camera,
+                        object_name: Some(LATEST_SNAPSHOT_OBJECT_NAME.to_string()),
+                    };
+                    self.handle_snapshot_payload(Arc::new(snapshot)).await;
+                }
+                Ok(None) => {
+                    tracing::debug!("Camera `{camera}` has no latest snapshot to fetch");
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to fetch the latest snapshot of camera `{camera}`: {e}"
+                    );
+                }
+            }
+        }
+    }
+
+    async fn log_heartbeat(&self) {
+        let mqtt_status = match &self.mqtt_connection_status {
+            Some(status) if *status.borrow() => "connected",
+            Some(_) => "disconnected",
+            None => "unknown",
+        };
+        let recording_tasks = running_task_count(
+            self.rec_updates_sender.as_ref(),
+            RecordingsUploadTaskHandlerCommand::GetTaskCount,
+        )
+        .await;
+        let snapshot_tasks = running_task_count(
+            self.snapshots_updates_sender.as_ref(),
+            SnapshotsUploadTaskHandlerCommand::GetTaskCount,
+        )
+        .await;
+        let last_event = self.last_event_time.map_or_else(
+            || "none yet".to_string(),
+            |time| {
+                let elapsed = std::time::Duration::from_secs(time.elapsed().as_secs());
+                format!("{} ago", humantime::format_duration(elapsed))
+            },
+        );
+
+        tracing::info!(
+            "{STRUCT_NAME} heartbeat. MQTT: {mqtt_status}. Running recording tasks: {recording_tasks}. Running snapshot tasks: {snapshot_tasks}. Last event: {last_event}"
+        );
+    }
+
+    fn notify_service(&self, state: ServiceState) {
+        if let Some(notifier) = &self.service_notifier {
+            notifier.notify(state);
+        }
+    }
+
+    fn notify_camera_state_change(&self, change: Option<CameraStateChange>) {
+        let Some(change) = change else {
+            return;
+        };
+
+        tracing::debug!("{STRUCT_NAME}: Camera state changed: {change:?}");
+
+        // Sending only fails when there are no subscribers
+        let _ = self.camera_state_changes.send(change);
+    }
+
+    async fn on_command_received(&mut self, command: SyncSystemCommand) {
+        match command {
+            SyncSystemCommand::SyncReview { id, force } => {
+                tracing::info!("{STRUCT_NAME}: Received command to sync review with id `{id}`");
+
+                let review = match self.retrieve_review(&id).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to retrieve review with id `{id}` from the Frigate API: {e}"
+                        );
+                        return;
+                    }
+                };
+
+                if force {
+                    self.dispatch_review(review).await;
+                } else {
+                    self.handle_review_payload(review).await;
+                }
+            }
+            SyncSystemCommand::GetUploadStats(result_sender) => {
+                if result_sender.send(self.upload_stats.get()).is_err() {
+                    tracing::error!(
+                        "Failed to send upload stats in {STRUCT_NAME} due to channel dead."
+                    );
+                }
+            }
+            SyncSystemCommand::GetDegradedDestinations(result_sender) => {
+                if result_sender
+                    .send(self.degraded_destinations.get())
+                    .is_err()
+                {
+                    tracing::error!(
+                        "Failed to send degraded destinations in {STRUCT_NAME} due to channel dead."
+                    );
+                }
+            }
+        }
+    }
+
+    async fn retrieve_review(&self, id: &str) -> anyhow::Result<Arc<dyn ReviewProps>> {
+        let api = self.make_frigate_api()?;
+        let review = api.review(id).await?;
+        Ok(Arc::new(ReviewFromApi::from(review)))
+    }
+
     pub fn make_frigate_api(&self) -> anyhow::Result<Arc<dyn FrigateApi>> {
         (self.frigate_api_maker)(&self.frigate_api_config)
     }
 
-    #[allow(clippy::type_complexity)]
-    #[must_use]
-    pub fn make_file_senders(
-        &self,
-    ) -> Vec<(
-        Arc<PathDescriptor>,
-        anyhow::Result<Arc<dyn StoreDestination<Error = anyhow::Error>>>,
-    )> {
+    /// All the destinations, including those that only receive final clips
+    fn all_destinations(&self) -> Vec<Arc<PathDescriptor>> {
         self.upload_dests
             .path_descriptors
             .iter()
-            .map(|d| (d.clone(), (self.file_sender_maker)(d)))
+            .chain(&self.sync_config.final_only_destinations)
+            .cloned()
             .collect()
     }
 
@@ -242,43 +786,126 @@ where
         }
     }
 
-    pub async fn test_file_senders(&self) {
-        let senders = self.make_file_senders();
-        for (descriptor, sender_result) in senders {
-            match sender_result {
-                Ok(s) => {
-                    match s.init().await {
-                        Ok(()) => tracing::info!(
-                            "Initializing file sender with descriptor `{}` for basic testing is successful.",
-                            s.path_descriptor()
-                        ),
-                        Err(e) => tracing::error!(
-                            "Error while initializing file sender after successful creation for basic testing. Path descriptor: `{}`. Error: {e}",
-                            s.path_descriptor()
-                        ),
-                    }
-
-                    match s.ls(Path::new(".")).await {
-                        Ok(_) => {
-                            tracing::info!("Basic file sender test for `{descriptor}` succeeded!");
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "Basic file sender test failed for descriptor `{descriptor}`: {e}",
-                            );
-                        }
-                    }
+    /// Checks every destination. With `require_all_destinations`, fails on the first one that doesn't pass.
+    /// Otherwise, the ones that don't pass are marked degraded, and are checked again in the background.
+    pub async fn check_destinations(&mut self) -> anyhow::Result<()> {
+        for descriptor in self.all_destinations() {
+            match check_destination(self.file_sender_maker.as_ref(), &descriptor).await {
+                Ok(()) => {
+                    tracing::info!("Basic file sender test for `{descriptor}` succeeded!");
+                }
+                Err(e) if self.sync_config.require_all_destinations => {
+                    return Err(anyhow::anyhow!(
+                        "Basic file sender test failed for descriptor `{descriptor}`, and all destinations are required: {e}"
+                    ));
                 }
                 Err(e) => {
                     tracing::error!(
-                        "Failed to create file sender with descriptor `{descriptor}`: {e}",
+                        "Basic file sender test failed for descriptor `{descriptor}`. It's marked degraded, and will be checked again every {}. Error: {e}",
+                        humantime::format_duration(self.sync_config.destination_retry_period)
                     );
+                    self.degraded_destinations.insert(descriptor);
                 }
             }
         }
+
+        if !self.degraded_destinations.get().is_empty() {
+            self.degraded_destinations_task =
+                Some(tokio::task::spawn(retry_degraded_destinations(
+                    self.file_sender_maker.clone(),
+                    self.degraded_destinations.clone(),
+                    self.sync_config.destination_retry_period,
+                    self.sync_config
+                        .destination_lock
+                        .then(|| self.sync_config.instance_id.clone()),
+                )));
+        }
+
+        Ok(())
+    }
+
+    /// Writes the lock object of this instance to every destination that passed its check, so that another instance
+    /// with any of the same destinations refuses to start. Degraded destinations are locked once they pass their check
+    /// again. Lock objects with this instance's id were left by a previous run of it, and are taken back.
+    async fn lock_destinations(&mut self) -> anyhow::Result<()> {
+        let degraded = self.degraded_destinations.get();
+        let destinations = self
+            .all_destinations()
+            .into_iter()
+            .filter(|d| !degraded.contains(d))
+            .collect::<Vec<_>>();
+
+        let owner = self.sync_config.instance_id.clone();
+        // Set first, so that the destinations locked before a failure are unlocked on shutdown
+        self.destination_lock_owner = Some(owner.clone());
+        acquire_destination_locks(self.file_sender_maker.as_ref(), &destinations, &owner).await
+    }
+
+    /// In `SnapshotMode::OnReview`, upload the buffered snapshot of the review's camera received closest to its start.
+    /// Called for every message of a review that passes the filters, since the review may be deferred or its `new`
+    /// message missed. Only one snapshot is uploaded per review, until it ends.
+    fn send_snapshot_of_review(&mut self, review: &dyn ReviewProps) {
+        if self.sync_config.snapshot_mode != SnapshotMode::OnReview {
+            return;
+        }
+        let Some(snapshots_updates_sender) = self.snapshots_updates_sender.clone() else {
+            return;
+        };
+
+        let snapshot = self.snapshot_buffer.take_closest_for_review(
+            review.id(),
+            review.camera_name(),
+            review.start_time(),
+        );
+        if review.type_field() == TypeField::End {
+            self.snapshot_buffer.forget_review(review.id());
+        }
+
+        if let Some(snapshot) = snapshot {
+            Self::send_snapshot(&snapshots_updates_sender, snapshot);
+        } else {
+            tracing::debug!(
+                "No snapshot was received close to the start of review with id `{}` from camera {}",
+                review.id(),
+                review.camera_name()
+            );
+        }
+    }
+
+    fn send_snapshot(
+        snapshots_updates_sender: &UnboundedSender<SnapshotsUploadTaskHandlerCommand>,
+        snapshot: Arc<Snapshot>,
+    ) {
+        let camera_name = snapshot.camera_label.clone();
+
+        tracing::debug!("Sending snapshot for camera {camera_name}");
+
+        let send_res =
+            snapshots_updates_sender.send(SnapshotsUploadTaskHandlerCommand::Task(snapshot, None));
+
+        match send_res {
+            Ok(()) => {
+                tracing::trace!(
+                    "Sent new task snapshot upload task successfully for camera {camera_name}"
+                );
+            }
+            Err(e) => {
+                tracing::error!(
+                    "CRITICAL: Failed to send message to snapshots upload handler: {e}"
+                );
+            }
+        }
     }
 
     async fn handle_snapshot_payload(&mut self, snapshot: Arc<Snapshot>) {
+        let Some(snapshots_updates_sender) = self.snapshots_updates_sender.clone() else {
+            tracing::debug!(
+                "Ignoring snapshot from camera: {} - Snapshots sync is disabled in the config.",
+                snapshot.camera_label
+            );
+            return;
+        };
+
         if self
             .cameras_state
             .camera_snapshots_state(&snapshot.camera_label)
@@ -288,27 +915,29 @@ where
             if !self.has_upload_delay_passed().await {
                 tracing::info!(
                     "Received snapshot for camera {camera_name}, but skipping it because the provided delay of {} seconds has not passed yet",
-                    self.frigate_api_config.delay_after_startup.as_secs()
+                    self.frigate_api_config
+                        .delay_after_startup
+                        .unwrap_or_default()
+                        .as_secs()
                 );
                 return;
             }
 
-            tracing::debug!("Sending snapshot for camera {camera_name}");
-
-            let send_res = self
-                .snapshots_updates_sender
-                .send(SnapshotsUploadTaskHandlerCommand::Task(snapshot, None));
+            if self.sync_config.snapshot_mode == SnapshotMode::OnReview {
+                tracing::debug!(
+                    "Buffering snapshot for camera {camera_name} until a review of it starts"
+                );
+                self.snapshot_buffer
+                    .push(snapshot, get_time().as_unix_timestamp_f64());
+                return;
+            }
 
-            match send_res {
-                Ok(()) => {
-                    tracing::trace!(
-                        "Sent new task snapshot upload task successfully for camera {camera_name}"
-                    );
-                }
-                Err(e) => tracing::error!(
-                    "CRITICAL: Failed to send message to snapshots upload handler: {e}"
-                ),
+            if self.sync_config.upload_montage {
+                self.snapshot_buffer
+                    .push(snapshot.clone(), get_time().as_unix_timestamp_f64());
             }
+
+            Self::send_snapshot(&snapshots_updates_sender, snapshot);
         } else {
             tracing::debug!(
                 "Ignoring snapshot from camera: {} - Snapshots are disabled in Frigate.",
@@ -317,50 +946,210 @@ where
         }
     }
 
-    async fn handle_review_payload(&mut self, review: Arc<dyn ReviewProps>) {
-        if self
+    /// Whether recordings are enabled in Frigate for the camera of the review, logging it if they aren't
+    fn review_camera_recordings_enabled(&self, review: &dyn ReviewProps) -> bool {
+        let enabled = self
             .cameras_state
-            .camera_recordings_state(review.camera_name())
-        {
-            let camera_name = review.camera_name().to_string();
+            .camera_recordings_state(review.camera_name());
+        if !enabled {
+            tracing::debug!(
+                "Ignoring review from camera: `{}` - Recordings are disabled in Frigate.",
+                review.camera_name()
+            );
+        }
+        enabled
+    }
 
-            if !self.has_upload_delay_passed().await {
-                tracing::info!(
-                    "Received review for camera {camera_name}, but skipping it because the provided delay of {} seconds has not passed yet",
-                    self.frigate_api_config.delay_after_startup.as_secs()
+    async fn handle_review_payload(&mut self, review: Arc<dyn ReviewProps>) {
+        if !self.review_camera_recordings_enabled(review.as_ref()) {
+            return;
+        }
+
+        let now = get_time().as_unix_timestamp_f64();
+        match self
+            .sync_config
+            .is_review_long_enough(review.start_time(), review.end_time(), now)
+        {
+            Some(true) => {
+                self.pending_short_reviews.remove(review.id());
+            }
+            Some(false) => {
+                self.pending_short_reviews.remove(review.id());
+                tracing::debug!(
+                    "Ignoring review from camera: `{}` with id `{}` - It's shorter than the minimum review duration.",
+                    review.camera_name(),
+                    review.id()
                 );
                 return;
             }
+            None => {
+                tracing::debug!(
+                    "Deferring review from camera: `{}` with id `{}` until it's longer than the minimum review duration.",
+                    review.camera_name(),
+                    review.id()
+                );
+                let remaining = self.sync_config.min_review_duration.as_secs_f64()
+                    - (now - review.start_time());
+                let due = tokio::time::Instant::now()
+                    + std::time::Duration::try_from_secs_f64(remaining).unwrap_or_default();
+                self.pending_short_reviews
+                    .insert(review.id().to_string(), (review, due));
+                return;
+            }
+        }
+
+        self.on_long_enough_review(review).await;
+    }
+
+    /// Uploads the latest update of every deferred ongoing review that has become long enough
+    async fn on_short_reviews_due(&mut self) {
+        let now = tokio::time::Instant::now();
+        let due_ids = self
+            .pending_short_reviews
+            .iter()
+            .filter(|(_, (_, due))| *due <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+
+        for id in due_ids {
+            if let Some((review, _)) = self.pending_short_reviews.remove(&id) {
+                tracing::debug!(
+                    "Review from camera: `{}` with id `{id}` is now longer than the minimum review duration.",
+                    review.camera_name()
+                );
+                // Recordings may have been disabled since the review was deferred
+                if !self.review_camera_recordings_enabled(review.as_ref()) {
+                    continue;
+                }
+                self.on_long_enough_review(review).await;
+            }
+        }
+    }
+
+    /// The rest of the filters of a review, once it's known to be long enough
+    async fn on_long_enough_review(&mut self, review: Arc<dyn ReviewProps>) {
+        if !self
+            .cameras_state
+            .camera_review_recording_retained(review.camera_name(), review.has_activity())
+        {
+            tracing::debug!(
+                "Ignoring review from camera: `{}` with id `{}` - It has no tracked objects or audio, and Frigate only retains recordings with motion or objects.",
+                review.camera_name(),
+                review.id()
+            );
+            return;
+        }
+
+        self.send_snapshot_of_review(review.as_ref());
+
+        self.dispatch_review(review).await;
+    }
+
+    /// Retrieves from Frigate's config which recordings it keeps for each camera, in the background,
+    /// and sends them to the event loop. On failure, the modes known so far are kept. A retrieval that's still
+    /// running is abandoned, so that its result doesn't replace the newer one.
+    fn update_retain_modes(&mut self) {
+        if let Some(task) = self.retain_modes_task.take() {
+            task.abort();
+        }
+
+        let api = self.make_frigate_api();
+        let sender = self.retain_modes_sender.clone();
+        self.retain_modes_task = Some(tokio::task::spawn(async move {
+            if let Some(retain_modes) = fetch_retain_modes(api).await {
+                // The system may have stopped in the meantime
+                let _ = sender.send(retain_modes);
+            }
+        }));
+    }
+
+    /// Sends the review to the recordings handler, regardless of the recordings state of its camera
+    async fn dispatch_review(&mut self, review: Arc<dyn ReviewProps>) {
+        let camera_name = review.camera_name().to_string();
+
+        let Some(rec_updates_sender) = self.rec_updates_sender.clone() else {
+            tracing::debug!(
+                "Ignoring review from camera: `{camera_name}` - Recordings sync is disabled in the config."
+            );
+            return;
+        };
+
+        if !self.has_upload_delay_passed().await {
+            tracing::info!(
+                "Received review for camera {camera_name}, but skipping it because the provided delay of {} seconds has not passed yet",
+                self.frigate_api_config
+                    .delay_after_startup
+                    .unwrap_or_default()
+                    .as_secs()
+            );
+            return;
+        }
 
-            let id = review.id().to_string();
-            tracing::debug!("Sending review for camera {camera_name} with id {id}");
+        let id = review.id().to_string();
+        tracing::debug!("Sending review for camera {camera_name} with id {id}");
 
-            let send_res = self
-                .rec_updates_sender
-                .send(RecordingsUploadTaskHandlerCommand::Task(review, None));
+        let review_for_montage = review.clone();
+        let send_res =
+            rec_updates_sender.send(RecordingsUploadTaskHandlerCommand::Task(review, None));
 
-            match send_res {
-                Ok(()) => tracing::trace!(
-                    "Sent new recording upload task successfully for camera {camera_name} with id {id}"
-                ),
-                Err(e) => tracing::error!(
+        match send_res {
+            Ok(()) => tracing::trace!(
+                "Sent new recording upload task successfully for camera {camera_name} with id {id}"
+            ),
+            Err(e) => {
+                tracing::error!(
                     "CRITICAL: Failed to send message to recordings upload handler: {e}"
-                ),
+                );
             }
-        } else {
+        }
+
+        self.send_montage_of_ended_review(&rec_updates_sender, review_for_montage);
+    }
+
+    /// If montages are uploaded, send the buffered snapshots received during the review, once it has ended
+    fn send_montage_of_ended_review(
+        &self,
+        rec_updates_sender: &UnboundedSender<RecordingsUploadTaskHandlerCommand>,
+        review: Arc<dyn ReviewProps>,
+    ) {
+        if !self.sync_config.upload_montage || review.type_field() != TypeField::End {
+            return;
+        }
+        let Some(end_time) = review.end_time() else {
+            return;
+        };
+
+        let snapshots = self.snapshot_buffer.spanning(
+            review.camera_name(),
+            review.start_time(),
+            end_time,
+            self.sync_config.montage_grid.cells(),
+        );
+        if snapshots.is_empty() {
             tracing::debug!(
-                "Ignoring review from camera: `{}` - Recordings are disabled in Frigate.",
-                review.camera_name()
+                "No snapshot was received during review with id `{}` for its montage",
+                review.id()
             );
+            return;
+        }
+
+        if let Err(e) = rec_updates_sender.send(RecordingsUploadTaskHandlerCommand::Montage(
+            review, snapshots,
+        )) {
+            tracing::error!("CRITICAL: Failed to send message to recordings upload handler: {e}");
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn run_reviews_task_handler(
         rec_updates_receiver: UnboundedReceiver<RecordingsUploadTaskHandlerCommand>,
         frigate_api_maker: Arc<F>,
         frigate_api_config: Arc<FrigateApiConfig>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
+        upload_stats: SharedUploadStats,
+        upload_notifier: Option<UploadNotifier>,
     ) -> JoinHandle<()> {
         tokio::task::spawn(async move {
             RecordingsTaskHandler::new(
@@ -369,6 +1158,10 @@ where
                 frigate_api_maker,
                 file_sender_maker,
                 path_descriptors,
+                sync_config,
+                upload_stats,
+                upload_notifier,
+                None,
                 None,
                 None,
             )
@@ -381,48 +1174,81 @@ where
         command_receiver: UnboundedReceiver<SnapshotsUploadTaskHandlerCommand>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
+        upload_stats: SharedUploadStats,
+        upload_notifier: Option<UploadNotifier>,
     ) -> JoinHandle<()> {
+        let retry = sync_config.snapshot_retry;
         tokio::task::spawn(
-            SnapshotsTaskHandler::new(command_receiver, file_sender_maker, path_descriptors).run(),
+            SnapshotsTaskHandler::new(
+                command_receiver,
+                file_sender_maker,
+                path_descriptors,
+                sync_config,
+                upload_stats,
+                upload_notifier,
+                retry,
+            )
+            .run(),
         )
     }
 
     /// Checks whether the uptime value from Frigate is higher than `delay_after_startup` given in config.
+    /// Without a delay, Frigate isn't asked.
     async fn has_upload_delay_passed(&self) -> bool {
-        const DEFAULT_RESPONSE: bool = true;
-
-        let frigate_api = match self.make_frigate_api() {
-            Ok(f) => f,
-            Err(e) => {
-                tracing::error!(
-                    "Failed to create Frigate API caller to check whether frigate uptime delay has passed. Reverting to default behavior. Error: {e}"
-                );
-                return DEFAULT_RESPONSE;
-            }
+        let Some(delay) = self.frigate_api_config.delay_after_startup else {
+            return true;
         };
 
-        let uptime = match frigate_api.stats().await {
-            Ok(stats) => stats.uptime(),
-            Err(e) => {
-                tracing::error!(
-                    "Failed to check whether frigate uptime delay has passed. Reverting to default behavior. Error: {e}"
+        uptime_gate::has_upload_delay_passed(
+            self.make_frigate_api(),
+            delay,
+            self.sync_config.gate_on_stats_failure,
+        )
+        .await
+    }
+}
+
+/// Joins the given tasks, giving all of them together until `timeout` to finish.
+/// Tasks that are still running after that are abandoned, so that a stuck upload cannot block shutdown forever.
+async fn join_tasks_with_timeout(
+    join_handles: &mut [(String, JoinHandle<()>)],
+    timeout: std::time::Duration,
+) {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    for (task_name, join_handle) in join_handles {
+        match tokio::time::timeout_at(deadline, &mut *join_handle).await {
+            Ok(Ok(())) => tracing::info!("Joining {task_name} task completed successfully"),
+            Ok(Err(e)) => tracing::error!("CRITICAL: Failed to join {task_name} task: {e}"),
+            Err(_) => {
+                tracing::warn!(
+                    "Joining {task_name} task did not finish within the shutdown timeout of {}. Abandoning it.",
+                    humantime::format_duration(timeout)
                 );
-                return DEFAULT_RESPONSE;
+                join_handle.abort();
             }
-        };
-
-        if uptime >= self.frigate_api_config.delay_after_startup {
-            true
-        } else {
-            tracing::info!(
-                "Delay after uptime has not passed yet. Upload will not happen. Frigate uptime: {} seconds. vs required delay: {} seconds",
-                uptime.as_secs(),
-                self.frigate_api_config.delay_after_startup.as_secs()
-            );
-            false
         }
     }
 }
 
+/// The number of tasks running in the handler that `sender` controls, or why it can't be told
+async fn running_task_count<C>(
+    sender: Option<&UnboundedSender<C>>,
+    command: impl FnOnce(oneshot::Sender<usize>) -> C,
+) -> String {
+    let Some(sender) = sender else {
+        return "disabled".to_string();
+    };
+
+    let (result_sender, result_receiver) = oneshot::channel();
+    if sender.send(command(result_sender)).is_err() {
+        return "handler stopped".to_string();
+    }
+    result_receiver
+        .await
+        .map_or_else(|_| "handler stopped".to_string(), |count| count.to_string())
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,89 @@
+use super::SyncSystemCommand;
+use mqtt_handler::{command::MqttCommand, publication::Publication};
+use serde::Serialize;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+/// Runs the commands received over mqtt on the sync system, until either of them is gone.
+/// What a command returns is published to `<topic_prefix>/<result name>`, e.g., `snapsync/upload_stats`.
+pub async fn run_mqtt_commands(
+    mut mqtt_command_receiver: UnboundedReceiver<MqttCommand>,
+    command_sender: UnboundedSender<SyncSystemCommand>,
+    publication_sender: UnboundedSender<Publication>,
+    topic_prefix: String,
+) {
+    while let Some(command) = mqtt_command_receiver.recv().await {
+        let sent = match command {
+            MqttCommand::SyncReview { id, force } => command_sender
+                .send(SyncSystemCommand::SyncReview { id, force })
+                .is_ok(),
+            MqttCommand::GetUploadStats => {
+                let (result_sender, result_receiver) = oneshot::channel();
+                command_sender
+                    .send(SyncSystemCommand::GetUploadStats(result_sender))
+                    .is_ok()
+                    && publish_result(
+                        result_receiver,
+                        &publication_sender,
+                        format!("{topic_prefix}/upload_stats"),
+                    )
+                    .await
+            }
+            MqttCommand::GetDegradedDestinations => {
+                let (result_sender, result_receiver) = oneshot::channel();
+                command_sender
+                    .send(SyncSystemCommand::GetDegradedDestinations(result_sender))
+                    .is_ok()
+                    && publish_result(
+                        async {
+                            result_receiver.await.map(|destinations| {
+                                destinations
+                                    .iter()
+                                    .map(ToString::to_string)
+                                    .collect::<Vec<_>>()
+                            })
+                        },
+                        &publication_sender,
+                        format!("{topic_prefix}/degraded_destinations"),
+                    )
+                    .await
+            }
+        };
+
+        if !sent {
+            tracing::info!("The sync system is gone. Stopping running mqtt commands.");
+            return;
+        }
+    }
+}
+
+/// Returns false if the result never arrives, since the sync system is gone
+async fn publish_result<T: Serialize>(
+    result: impl Future<Output = Result<T, oneshot::error::RecvError>>,
+    publication_sender: &UnboundedSender<Publication>,
+    topic: String,
+) -> bool {
+    let Ok(result) = result.await else {
+        return false;
+    };
+
+    match serde_json::to_vec(&result) {
+        Ok(payload) => {
+            let publication = Publication {
+                topic,
+                payload,
+                retain: false,
+            };
+            if publication_sender.send(publication).is_err() {
+                tracing::error!(
+                    "Publishing the result of an mqtt command failed, as the mqtt handler is gone"
+                );
+            }
+        }
+        Err(e) => tracing::error!("Serializing the result of an mqtt command failed: {e}"),
+    }
+
+    true
+}
@@ -0,0 +1,164 @@
+use super::common::file_upload::{ALTERNATIVE_FILE_NAME_SUFFIXES, RECORDING_FILE_NAME_PREFIX};
+use anyhow::Context;
+use file_sender::traits::StoreDestination;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+/// Both alternative versions of a recording that were found in a destination, of which one is stale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlternativePair {
+    pub kept: PathBuf,
+    pub stale: PathBuf,
+}
+
+/// The alternative pairs found in a destination. Their stale files are deleted, unless it's a dry run.
+#[must_use]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    pub pairs: Vec<AlternativePair>,
+}
+
+/// Scans all the directories of the given store for recordings that have both their `-0` and `-1`
+/// versions, which happens when the process stops between uploading one version and deleting the other.
+/// Of each pair, the larger file is kept, since an interrupted upload is truncated, and a later upload
+/// of a review covers at least as much of it. If both have the same size, the `-0` version is kept.
+/// The stale files are deleted in one batch once the scan is done. With `dry_run`, nothing is deleted.
+pub async fn prune_alternatives(
+    store: &dyn StoreDestination<Error = anyhow::Error>,
+    dry_run: bool,
+) -> anyhow::Result<PruneReport> {
+    let mut report = PruneReport::default();
+    let mut dirs_to_scan = vec![PathBuf::new()];
+
+    while let Some(dir) = dirs_to_scan.pop() {
+        let ls_path = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir.as_path()
+        };
+        let names = store
+            .ls(ls_path)
+            .await
+            .with_context(|| format!("Listing directory `{}`", ls_path.display()))?
+            .into_iter()
+            .filter_map(|name| name.to_str().map(ToOwned::to_owned))
+            .collect::<BTreeSet<_>>();
+
+        for name in &names {
+            let path = dir.join(name);
+            if !name.starts_with(RECORDING_FILE_NAME_PREFIX) && store.dir_exists(&path).await? {
+                dirs_to_scan.push(path);
+                continue;
+            }
+
+            let Some(other_name) = other_alternative_name(name) else {
+                continue;
+            };
+            if !names.contains(&other_name) {
+                continue;
+            }
+
+            let pair = choose_stale(store, path, dir.join(other_name)).await?;
+            if dry_run {
+                tracing::info!(
+                    "Dry run: would delete `{}`, and keep `{}`",
+                    pair.stale.display(),
+                    pair.kept.display()
+                );
+            } else {
+                tracing::info!(
+                    "Deleting `{}`, and keeping `{}`",
+                    pair.stale.display(),
+                    pair.kept.display()
+                );
+            }
+            report.pairs.push(pair);
+        }
+    }
+
+    if !dry_run {
+        delete_stale(store, &report.pairs).await?;
+    }
+
+    Ok(report)
+}
+
+/// Fails if any of the files couldn't be deleted, after trying all of them
+async fn delete_stale(
+    store: &dyn StoreDestination<Error = anyhow::Error>,
+    pairs: &[AlternativePair],
+) -> anyhow::Result<()> {
+    if pairs.is_empty() {
+        return Ok(());
+    }
+
+    let stale = pairs
+        .iter()
+        .map(|pair| pair.stale.clone())
+        .collect::<Vec<_>>();
+    let results = store
+        .del_files(&stale)
+        .await
+        .context("Deleting the stale alternatives")?;
+
+    let mut failed = 0;
+    for (path, result) in results {
+        if let Err(e) = result {
+            tracing::error!("Deleting `{}` failed: {e}", path.display());
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "Deleting {failed} of {} stale alternatives failed",
+            stale.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Given the file name of the `-0` version of a recording, returns the name of its `-1` version.
+/// Returns None for any other file, so that each pair is found once.
+fn other_alternative_name(name: &str) -> Option<String> {
+    if !name.starts_with(RECORDING_FILE_NAME_PREFIX) {
+        return None;
+    }
+    let (stem, extension) = name.rsplit_once('.')?;
+    let base = stem.strip_suffix(ALTERNATIVE_FILE_NAME_SUFFIXES[0])?;
+    Some(format!(
+        "{base}{}.{extension}",
+        ALTERNATIVE_FILE_NAME_SUFFIXES[1]
+    ))
+}
+
+async fn choose_stale(
+    store: &dyn StoreDestination<Error = anyhow::Error>,
+    first: PathBuf,
+    second: PathBuf,
+) -> anyhow::Result<AlternativePair> {
+    let first_size = store
+        .file_size(&first)
+        .await
+        .with_context(|| format!("Getting the size of `{}`", first.display()))?;
+    let second_size = store
+        .file_size(&second)
+        .await
+        .with_context(|| format!("Getting the size of `{}`", second.display()))?;
+
+    Ok(if first_size >= second_size {
+        AlternativePair {
+            kept: first,
+            stale: second,
+        }
+    } else {
+        AlternativePair {
+            kept: second,
+            stale: first,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests;
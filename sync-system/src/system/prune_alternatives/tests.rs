@@ -0,0 +1,201 @@
+use super::*;
+use file_sender::make_inmemory_filesystem;
+use rstest::rstest;
+use std::sync::Arc;
+
+const OLD_AND_NEW: [(&str, usize); 2] = [
+    ("RecordingClip-front-2024-01-01_10-00-00-0.mp4", 100),
+    ("RecordingClip-front-2024-01-01_10-00-00-1.mp4", 200),
+];
+const NEW_AND_OLD: [(&str, usize); 2] = [
+    ("RecordingClip-back-2024-01-01_11-00-00-0.mp4", 300),
+    ("RecordingClip-back-2024-01-01_11-00-00-1.mp4", 50),
+];
+/// Files that aren't part of a pair, and are never deleted
+const SINGLES: [(&str, usize); 3] = [
+    ("RecordingClip-front-2024-01-01_12-00-00-1.mp4", 10),
+    ("RecordingClip-front-2024-01-01_13-00-00.mp4", 10),
+    ("Snapshot-front-person-2024-01-01_10-00-00-0.jpg", 10),
+];
+
+async fn seed_store(
+    store: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    dir: &Path,
+    files: &[(&str, usize)],
+) {
+    store.mkdir_p(dir).await.unwrap();
+    for (name, size) in files {
+        store
+            .put_from_memory(&vec![0; *size], &dir.join(name))
+            .await
+            .unwrap();
+    }
+}
+
+async fn file_names(
+    store: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    dir: &Path,
+) -> BTreeSet<String> {
+    store
+        .ls(dir)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.to_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+#[rstest]
+async fn stale_alternatives_are_pruned(#[values(false, true)] dry_run: bool) {
+    let store = make_inmemory_filesystem();
+
+    let date_dir = Path::new("2024-01-01");
+    let camera_date_dir = Path::new("front").join("2024-01-01");
+    seed_store(&store, date_dir, &OLD_AND_NEW).await;
+    seed_store(&store, date_dir, &SINGLES).await;
+    seed_store(&store, &camera_date_dir, &NEW_AND_OLD).await;
+
+    let report = prune_alternatives(store.as_ref(), dry_run).await.unwrap();
+
+    let mut pairs = report.pairs;
+    pairs.sort_by(|a, b| a.kept.cmp(&b.kept));
+    assert_eq!(
+        pairs,
+        vec![
+            AlternativePair {
+                kept: date_dir.join(OLD_AND_NEW[1].0),
+                stale: date_dir.join(OLD_AND_NEW[0].0),
+            },
+            AlternativePair {
+                kept: camera_date_dir.join(NEW_AND_OLD[0].0),
+                stale: camera_date_dir.join(NEW_AND_OLD[1].0),
+            },
+        ]
+    );
+
+    let mut expected_in_date_dir = SINGLES
+        .iter()
+        .map(|(name, _)| (*name).to_string())
+        .collect::<BTreeSet<_>>();
+    expected_in_date_dir.insert(OLD_AND_NEW[1].0.to_string());
+    let mut expected_in_camera_date_dir = BTreeSet::from([NEW_AND_OLD[0].0.to_string()]);
+    if dry_run {
+        expected_in_date_dir.insert(OLD_AND_NEW[0].0.to_string());
+        expected_in_camera_date_dir.insert(NEW_AND_OLD[1].0.to_string());
+    }
+
+    assert_eq!(file_names(&store, date_dir).await, expected_in_date_dir);
+    assert_eq!(
+        file_names(&store, &camera_date_dir).await,
+        expected_in_camera_date_dir
+    );
+}
+
+#[tokio::test]
+async fn alternatives_of_same_size_keep_the_first() {
+    let store = make_inmemory_filesystem();
+
+    let dir = Path::new("2024-01-01");
+    seed_store(
+        &store,
+        dir,
+        &[(OLD_AND_NEW[0].0, 100), (OLD_AND_NEW[1].0, 100)],
+    )
+    .await;
+
+    let report = prune_alternatives(store.as_ref(), false).await.unwrap();
+
+    assert_eq!(
+        report.pairs,
+        vec![AlternativePair {
+            kept: dir.join(OLD_AND_NEW[0].0),
+            stale: dir.join(OLD_AND_NEW[1].0),
+        }]
+    );
+    assert_eq!(
+        file_names(&store, dir).await,
+        BTreeSet::from([OLD_AND_NEW[0].0.to_string()])
+    );
+}
+
+#[tokio::test]
+async fn stale_alternatives_are_deleted_in_one_batch() {
+    let dir = Path::new("2024-01-01");
+    let files = OLD_AND_NEW
+        .iter()
+        .chain(&NEW_AND_OLD)
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut store = mocks::store_dest::make_store_mock();
+    store
+        .expect_ls()
+        .withf(|path| path == Path::new("."))
+        .returning(move |_| Ok(vec![dir.into()]));
+    store.expect_dir_exists().returning(|_| Ok(true));
+    store.expect_ls().withf(move |path| path == dir).returning({
+        let files = files.clone();
+        move |_| Ok(files.iter().map(|(name, _)| name.into()).collect())
+    });
+    store.expect_file_size().returning(move |path| {
+        let (_, size) = files
+            .iter()
+            .find(|(name, _)| path == dir.join(name))
+            .unwrap();
+        Ok(*size as u64)
+    });
+    store.expect_del_file().never();
+    store
+        .expect_del_files()
+        .withf(move |paths| {
+            // In the order they're listed
+            paths == [dir.join(NEW_AND_OLD[1].0), dir.join(OLD_AND_NEW[0].0)]
+        })
+        .once()
+        .returning(|paths| Ok(paths.iter().map(|p| (p.clone(), Ok(()))).collect()));
+
+    let report = prune_alternatives(&store, false).await.unwrap();
+    assert_eq!(report.pairs.len(), 2);
+}
+
+#[tokio::test]
+async fn failed_deletions_fail_the_prune() {
+    let mut store = mocks::store_dest::make_store_mock();
+    let dir = Path::new("2024-01-01");
+    store
+        .expect_ls()
+        .withf(|path| path == Path::new("."))
+        .returning(move |_| Ok(vec![dir.into()]));
+    store.expect_dir_exists().returning(|_| Ok(true));
+    store
+        .expect_ls()
+        .withf(move |path| path == dir)
+        .returning(|_| Ok(OLD_AND_NEW.iter().map(|(name, _)| name.into()).collect()));
+    store.expect_file_size().returning(|_| Ok(100));
+    store.expect_del_files().once().returning(|paths| {
+        Ok(paths
+            .iter()
+            .map(|p| (p.clone(), Err(anyhow::anyhow!("Permission denied"))))
+            .collect())
+    });
+
+    assert!(prune_alternatives(&store, false).await.is_err());
+}
+
+#[rstest]
+#[case(
+    "RecordingClip-front-2024-01-01_10-00-00-0.mp4",
+    Some("RecordingClip-front-2024-01-01_10-00-00-1.mp4")
+)]
+#[case(
+    "RecordingClip-front-2024-01-01_10-00-00-0.mkv",
+    Some("RecordingClip-front-2024-01-01_10-00-00-1.mkv")
+)]
+#[case("RecordingClip-front-2024-01-01_10-00-00-1.mp4", None)]
+#[case("RecordingClip-front-2024-01-01_10-00-00.mp4", None)]
+#[case("Snapshot-front-2024-01-01_10-00-00-0.jpg", None)]
+#[case("RecordingClip-front-0", None)]
+fn other_alternative(#[case] name: &str, #[case] expected: Option<&str>) {
+    assert_eq!(other_alternative_name(name).as_deref(), expected);
+}
@@ -1,17 +1,30 @@
 mod task;
 
-use super::traits::{FileSenderMaker, FrigateApiMaker};
-use crate::config::PathDescriptors;
+use super::{
+    common::upload_notifier::{UploadKind, UploadNotifier},
+    config::SyncSystemConfig,
+    traits::{FileSenderMaker, FrigateApiMaker},
+};
+use crate::{config::PathDescriptors, stats::SharedUploadStats};
 use frigate_api_caller::config::FrigateApiConfig;
 use futures::{StreamExt, stream::FuturesUnordered};
-use mqtt_handler::types::reviews::ReviewProps;
-use std::{collections::HashMap, fmt::Display, sync::Arc};
-use task::SingleRecordingUploadTask;
+use mqtt_handler::types::{
+    reviews::{ReviewProps, payload::TypeField},
+    snapshot::Snapshot,
+};
+use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
+use task::{
+    RecordingTaskResult, SingleRecordingUploadTask, UploadConclusion, upload_review_montage,
+};
 use tokio::{sync::oneshot, task::JoinHandle};
 use utils::{struct_name, time_getter::TimeGetter};
 
 const STRUCT_NAME: &str = struct_name!(SyncSystem);
 
+/// An update of a review that's identical to the last one received for it within this long is a duplicate,
+/// e.g., redelivered by the mqtt broker on reconnect, and is dropped
+const DUPLICATE_UPDATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+
 type TaskMap = HashMap<
     String,
     tokio::sync::mpsc::UnboundedSender<(Arc<dyn ReviewProps>, Option<oneshot::Sender<()>>)>,
@@ -23,28 +36,61 @@ pub struct RecordingsTaskHandler<F, S> {
     /// Commands that control this struct
     command_receiver: tokio::sync::mpsc::UnboundedReceiver<RecordingsUploadTaskHandlerCommand>,
     /// All the upload tasks futures running are here and are to be eventually joined
-    running_tasks: FuturesUnordered<JoinHandle<String>>,
+    running_tasks: FuturesUnordered<JoinHandle<RecordingTaskResult>>,
+    /// Uploads of the montages of reviews, which are independent of the uploads of their clips
+    montage_tasks: FuturesUnordered<JoinHandle<()>>,
     /// Tasks that are running have review ids that are stored here, with a sender
     /// that can send them update objects from Frigate, coming from mqtt
     tasks_communicators: TaskMap,
+    /// The last update received for every review, within `DUPLICATE_UPDATE_WINDOW`
+    last_updates: HashMap<String, LastUpdate>,
 
     frigate_api_config: Arc<FrigateApiConfig>,
     frigate_api_maker: Arc<F>,
     file_sender_maker: Arc<S>,
     path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
+    /// Every recording that is fully uploaded is counted here
+    upload_stats: SharedUploadStats,
+    /// If set, a confirmation is published for every recording that is fully uploaded
+    upload_notifier: Option<UploadNotifier>,
 
     max_retry_attempts_on_task: Option<u32>,
     retry_attempt_period: Option<std::time::Duration>,
+    max_retry_attempt_period: Option<std::time::Duration>,
 
     /// Stops the event loop
     stopped: bool,
 }
 
+/// What identifies an update of a review as a duplicate of the previous one
+struct LastUpdate {
+    type_field: TypeField,
+    // As bits, since float times are compared for being identical
+    end_time: Option<u64>,
+    received_at: tokio::time::Instant,
+}
+
+impl LastUpdate {
+    fn new(review: &dyn ReviewProps) -> Self {
+        Self {
+            type_field: review.type_field(),
+            end_time: review.end_time().map(f64::to_bits),
+            received_at: tokio::time::Instant::now(),
+        }
+    }
+
+    fn is_same_as(&self, other: &Self) -> bool {
+        self.type_field == other.type_field && self.end_time == other.end_time
+    }
+}
+
 pub enum RecordingsUploadTaskHandlerCommand {
     /// Send a new Review to process its recording
     Task(Arc<dyn ReviewProps>, Option<oneshot::Sender<()>>),
+    /// Upload a montage of the snapshots received during a review that has ended
+    Montage(Arc<dyn ReviewProps>, Vec<Arc<Snapshot>>),
     /// Get the number of outstanding upload tasks running
-    #[allow(dead_code)]
     GetTaskCount(oneshot::Sender<usize>),
     /// Stops the task handler by shutting down the event loop
     Stop,
@@ -55,26 +101,37 @@ where
     F: FrigateApiMaker,
     S: FileSenderMaker,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         command_receiver: tokio::sync::mpsc::UnboundedReceiver<RecordingsUploadTaskHandlerCommand>,
         frigate_api_config: Arc<FrigateApiConfig>,
         frigate_api_maker: Arc<F>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
+        upload_stats: SharedUploadStats,
+        upload_notifier: Option<UploadNotifier>,
         max_retry_attempts_on_task: Option<u32>,
         retry_attempt_period: Option<std::time::Duration>,
+        max_retry_attempt_period: Option<std::time::Duration>,
     ) -> Self {
         Self {
             running_tasks: FuturesUnordered::default(),
+            montage_tasks: FuturesUnordered::default(),
             command_receiver,
             tasks_communicators: HashMap::default(),
+            last_updates: HashMap::default(),
             frigate_api_config,
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            sync_config,
+            upload_stats,
+            upload_notifier,
 
             max_retry_attempts_on_task,
             retry_attempt_period,
+            max_retry_attempt_period,
 
             stopped: false,
         }
@@ -87,7 +144,7 @@ where
                     match update {
                         RecordingsUploadTaskHandlerCommand::Stop => {
                             self.stopped = true;
-                            if self.running_tasks.is_empty() {
+                            if self.running_tasks.is_empty() && self.montage_tasks.is_empty() {
                                 break;
                             }
                         }
@@ -99,6 +156,9 @@ where
                                 }
                             }
                         }
+                        RecordingsUploadTaskHandlerCommand::Montage(review, snapshots) => {
+                            self.launch_montage_upload_task(review, snapshots);
+                        }
                         RecordingsUploadTaskHandlerCommand::GetTaskCount(result_sender) => {
                             if result_sender.send(self.running_tasks.len()).is_err() {
                                 tracing::error!("CRITICAL: Oneshot get tasks size sender for a task in {STRUCT_NAME} failed to send. This indicates a race condition.");
@@ -110,7 +170,15 @@ where
                 Some(task_result) = self.running_tasks.next() => {
                     self.on_task_joined(task_result);
 
-                    if self.running_tasks.is_empty() && self.stopped {
+                    if self.running_tasks.is_empty() && self.montage_tasks.is_empty() && self.stopped {
+                        break;
+                    }
+                }
+
+                Some(task_result) = self.montage_tasks.next() => {
+                    Self::on_montage_task_joined(task_result);
+
+                    if self.running_tasks.is_empty() && self.montage_tasks.is_empty() && self.stopped {
                         break;
                     }
                 }
@@ -121,24 +189,72 @@ where
         while let Some(task_result) = self.running_tasks.next().await {
             self.on_task_joined(task_result);
         }
+        while let Some(task_result) = self.montage_tasks.next().await {
+            Self::on_montage_task_joined(task_result);
+        }
+    }
+
+    fn launch_montage_upload_task(
+        &self,
+        review: Arc<dyn ReviewProps>,
+        snapshots: Vec<Arc<Snapshot>>,
+    ) {
+        let handle = tokio::task::spawn(upload_review_montage(
+            review,
+            snapshots,
+            self.file_sender_maker.clone(),
+            self.path_descriptors.clone(),
+            self.sync_config.clone(),
+        ));
+        self.montage_tasks.push(handle);
+    }
+
+    fn on_montage_task_joined<E: Display>(task_result: Result<(), E>) {
+        if let Err(e) = task_result {
+            tracing::error!("CRITICAL. Montage task joined with error: {e}");
+
+            // We have to panic in tests on error, otherwise panics in tasks will be ignored
+            #[cfg(test)]
+            panic!("Panic occurred: {e}")
+        }
     }
 
     async fn register_review_update(&mut self, review: Arc<dyn ReviewProps>) {
-        let id = review.id().to_string();
+        if self.is_duplicate_update(review.as_ref()) {
+            tracing::debug!(
+                "Dropping duplicate {:?} update of review with id `{}`",
+                review.type_field(),
+                review.id()
+            );
+            return;
+        }
 
-        if !self.tasks_communicators.contains_key(review.id()) {
-            let updates_sender = self.launch_upload_task(review.clone()).await;
+        if let Some(sender) = self.tasks_communicators.get(review.id()) {
+            sender
+                .send((review, None))
+                .expect("Invariant broken. Task communicators map could not send.");
+        } else {
+            // The task starts with uploading the review it's launched with
+            let id = review.id().to_string();
+            let updates_sender = self.launch_upload_task(review).await;
             self.tasks_communicators.insert(id, updates_sender);
         }
+    }
 
-        let sender = self
-            .tasks_communicators
-            .get(review.id())
-            .expect("It was just inserted");
+    /// Records the update as the last one of its review, and returns whether it's identical
+    /// to the one before it. Only the updates within the window are kept.
+    fn is_duplicate_update(&mut self, review: &dyn ReviewProps) -> bool {
+        let update = LastUpdate::new(review);
+
+        self.last_updates
+            .retain(|_, last| update.received_at - last.received_at < DUPLICATE_UPDATE_WINDOW);
 
-        sender
-            .send((review, None))
-            .expect("Invariant broken. Task communicators map could not send.");
+        let is_duplicate = self
+            .last_updates
+            .get(review.id())
+            .is_some_and(|last| last.is_same_as(&update));
+        self.last_updates.insert(review.id().to_string(), update);
+        is_duplicate
     }
 
     async fn launch_upload_task(
@@ -148,6 +264,8 @@ where
     {
         let (reviews_sender, reviews_receiver) = tokio::sync::mpsc::unbounded_channel();
         let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+        // The config, and the camera's override in it, take precedence over the retries the handler was made with
+        let retry = self.sync_config.recording_retry_of(review.camera_name());
         let handle = tokio::task::spawn(
             SingleRecordingUploadTask::new(
                 review,
@@ -158,8 +276,10 @@ where
                 self.frigate_api_maker.clone(),
                 self.file_sender_maker.clone(),
                 self.path_descriptors.clone(),
-                self.max_retry_attempts_on_task,
-                self.retry_attempt_period,
+                self.sync_config.clone(),
+                retry.max_attempts.or(self.max_retry_attempts_on_task),
+                retry.period.or(self.retry_attempt_period),
+                retry.max_period.or(self.max_retry_attempt_period),
                 TimeGetter::default(),
             )
             .start(),
@@ -174,10 +294,16 @@ where
         reviews_sender
     }
 
-    fn on_task_joined<E: Display>(&mut self, task_result: Result<String, E>) {
+    fn on_task_joined<E: Display>(&mut self, task_result: Result<RecordingTaskResult, E>) {
         match task_result {
-            Ok(id) => {
+            Ok(result) => {
+                let id = result.id;
                 tracing::info!("Recording task for id `{id}` joined successfully");
+                if result.conclusion == UploadConclusion::Done {
+                    self.notify_upload(&result.camera_name, result.uploaded_path.as_deref());
+                    self.upload_stats
+                        .record_recording_upload(result.camera_name);
+                }
                 self.tasks_communicators
                     .remove(&id)
                     .expect("The value must have been inserted before");
@@ -193,6 +319,19 @@ where
             }
         }
     }
+
+    /// The path is None if the recording was already in all destinations, and wasn't uploaded again
+    fn notify_upload(&self, camera_name: &str, uploaded_path: Option<&Path>) {
+        let (Some(notifier), Some(path)) = (&self.upload_notifier, uploaded_path) else {
+            return;
+        };
+
+        // A recording that is done has ended, so it has been uploaded to the final-only destinations too
+        let mut destinations = self.path_descriptors.path_descriptors.as_ref().clone();
+        destinations.extend(self.sync_config.final_only_destinations.iter().cloned());
+
+        notifier.notify(camera_name, UploadKind::Recording, path, &destinations);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,55 @@
+use crate::system::common::{file_upload::UploadableFile, naming::NamingStrategy};
+use mqtt_handler::types::reviews::ReviewProps;
+use std::{path::PathBuf, sync::Arc};
+
+/// The snapshot of one of the events of a review, uploaded to the same directory as the review's clip
+#[derive(Debug, Clone)]
+pub struct EventSnapshot {
+    review: Arc<dyn ReviewProps>,
+    event_id: String,
+    snapshot: Vec<u8>,
+    naming: Arc<dyn NamingStrategy>,
+}
+
+impl EventSnapshot {
+    pub fn new(
+        review: Arc<dyn ReviewProps>,
+        event_id: String,
+        snapshot: Vec<u8>,
+        naming: Arc<dyn NamingStrategy>,
+    ) -> Self {
+        Self {
+            review,
+            event_id,
+            snapshot,
+            naming,
+        }
+    }
+}
+
+impl UploadableFile for EventSnapshot {
+    fn file_bytes(&self) -> &[u8] {
+        &self.snapshot
+    }
+
+    fn full_upload_path(&self) -> PathBuf {
+        self.naming
+            .event_snapshot_path(self.review.as_ref(), &self.event_id)
+    }
+
+    fn file_description(&self) -> String {
+        format!(
+            "Event snapshot with id {} of review with id {}",
+            self.event_id,
+            self.review.id()
+        )
+    }
+
+    fn camera_name(&self) -> &str {
+        self.review.camera_name()
+    }
+
+    fn source_id(&self) -> Option<String> {
+        Some(self.review.id().to_string())
+    }
+}
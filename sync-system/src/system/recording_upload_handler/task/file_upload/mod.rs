@@ -1,21 +1,62 @@
+mod event_snapshot;
+mod review_montage;
 mod review_with_clip;
+mod upload_marker;
+
+pub use review_montage::upload_review_montage;
 
 use crate::{
     config::PathDescriptors,
     system::{
-        common::file_upload::{RemoteFileOp, remote_file_op},
+        common::{
+            audit_log::{AuditRecord, UploadOutcome, append_audit_record},
+            file_upload::{RemoteFileOp, UploadableFile, remote_file_op},
+            naming::{NamingStrategy, naming_strategy},
+            retry::Backoff,
+            stream_upload::stream_upload,
+        },
+        config::{RecordingRetry, SyncSystemConfig},
         traits::{FileSenderMaker, FrigateApiMaker},
     },
 };
-use anyhow::Context;
-use frigate_api_caller::{config::FrigateApiConfig, traits::FrigateApi};
-use mqtt_handler::types::reviews::ReviewProps;
+use event_snapshot::EventSnapshot;
+use file_sender::path_descriptor::PathDescriptor;
+use frigate_api_caller::{FrigateApiError, config::FrigateApiConfig, traits::FrigateApi};
+use futures::StreamExt;
+use mqtt_handler::types::reviews::{ReviewProps, payload::TypeField};
 use review_with_clip::ReviewWithClip;
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use upload_marker::{UploadMarker, find_completed_upload};
 use utils::time_getter::TimeGetter;
 
 pub const MAX_UPLOAD_ATTEMPTS: u32 = 3;
 const MAX_DELETE_ATTEMPTS: u32 = 5;
+const DEFAULT_FILE_OP_RETRY_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+const DEFAULT_MAX_FILE_OP_RETRY_PERIOD: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long to wait before the first check of whether an export is done. Every check doubles it, up to the max.
+const EXPORT_POLL_INITIAL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+const EXPORT_POLL_MAX_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The waits between the retries of an upload of a file to a destination, from the config
+#[must_use]
+pub fn file_op_backoff(retry: &RecordingRetry) -> Backoff {
+    Backoff::new(
+        retry.period.unwrap_or(DEFAULT_FILE_OP_RETRY_PERIOD),
+        retry.max_period.unwrap_or(DEFAULT_MAX_FILE_OP_RETRY_PERIOD),
+    )
+}
+
+/// How many times a file is uploaded to a destination before the upload fails
+fn upload_attempts(sync_config: &SyncSystemConfig) -> u32 {
+    sync_config
+        .file_op_retry
+        .max_attempts
+        .unwrap_or(MAX_UPLOAD_ATTEMPTS)
+}
 
 #[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
 pub enum ReviewUploadError {
@@ -24,13 +65,36 @@ pub enum ReviewUploadError {
     #[error("Retrieving clip returned an error: {0}")]
     ClipRetrievalError(String),
     #[error(
-        "Retrieving video from API returned an empty video. This is an unrecoverable error. Id: "
+        "Retrieving video from API returned an empty video. This is an unrecoverable error. Id: {0}"
     )]
     EmptyVideoReturned(String),
     #[error("Review recording upload failed: {0}")]
     RecordingUpload(String),
     #[error("Deleting alternative upload file failed: {0}")]
     DeletingAltFile(String),
+    #[error(
+        "Review with id `{0}` started too recently for Frigate to have recorded anything of it yet"
+    )]
+    NothingRecordedYet(String),
+    #[error(
+        "Unrecoverable: the recording of review with id `{0}` is older than Frigate's recording retention"
+    )]
+    OutsideRetention(String),
+    #[error("Unrecoverable: retrieving clip from the Frigate API failed: {0}")]
+    UnrecoverableApiError(String),
+    #[error("Exporting the recording of review with id `{0}` didn't finish in time")]
+    ExportTimedOut(String),
+}
+
+impl ReviewUploadError {
+    /// Retrying a call that failed with an unrecoverable error is pointless, so the upload is given up
+    fn from_clip_retrieval(context: &str, error: &FrigateApiError) -> Self {
+        if error.is_recoverable() {
+            Self::ClipRetrievalError(format!("{context}: {error}"))
+        } else {
+            Self::UnrecoverableApiError(format!("{context}: {error}"))
+        }
+    }
 }
 
 #[must_use]
@@ -42,16 +106,26 @@ pub struct ReviewUpload<F, S> {
     /// video without deleting it while we upload the next video. So every
     /// upload of the same review, can add more on the previous one. This
     /// helps in case the connection is lost, the most amount of information
-    /// is left.
+    /// is left. Only used if `keep_alternative_versions` is set in the config.
     alternative_upload: bool,
+    /// Where the clip was last uploaded to by this upload, if it has been
+    uploaded_path: Option<PathBuf>,
+    /// The destinations that the clip being uploaded hasn't reached yet, once uploading it partly failed.
+    /// Retrying the upload only uploads to them, instead of uploading the clip again where it already is.
+    pending_destinations: Option<Vec<Arc<PathDescriptor>>>,
+    /// Where the clip and the files that go with it are uploaded to
+    naming: Arc<dyn NamingStrategy>,
+    /// The export of the clip that's awaited, which is deleted from Frigate once it's no longer needed
+    pending_export: Option<PendingExport>,
 
     frigate_api_config: Arc<FrigateApiConfig>,
     frigate_api_maker: Arc<F>,
     file_sender_maker: Arc<S>,
     time_getter: TimeGetter,
     path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
 
-    upload_file_op_retry_sleep: std::time::Duration,
+    file_op_backoff: Backoff,
 }
 
 impl<F, S> ReviewUpload<F, S>
@@ -67,13 +141,18 @@ where
         frigate_api_maker: Arc<F>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
         time_getter: TimeGetter,
-        upload_file_op_retry_sleep: std::time::Duration,
+        file_op_backoff: Backoff,
     ) -> Self {
         Self {
             review,
             state: ReviewUploadState::default(),
             alternative_upload,
+            uploaded_path: None,
+            pending_destinations: None,
+            naming: naming_strategy(&sync_config),
+            pending_export: None,
 
             frigate_api_config,
             frigate_api_maker,
@@ -81,67 +160,140 @@ where
 
             time_getter,
             path_descriptors,
+            sync_config,
 
-            upload_file_op_retry_sleep,
+            file_op_backoff,
         }
     }
 
+    /// Where the clip was uploaded to, once it has been
+    pub fn uploaded_path(&self) -> Option<&Path> {
+        self.uploaded_path.as_deref()
+    }
+
     pub async fn start(&mut self) -> Result<(), ReviewUploadError> // The result indicates whether all the steps have finished successfully for the file, since review files is uploaded sequentially
     {
-        let id = self.review.id().to_string();
+        self.run(false).await
+    }
+
+    /// Runs the steps up to uploading the clip, which is kept until `start` uploads it.
+    /// Streamed clips are downloaded while they're uploaded, so they're left to `start`.
+    pub async fn download(&mut self) -> Result<(), ReviewUploadError> {
+        self.run(true).await
+    }
+
+    /// Whether the next step uploads anything
+    fn uploads_next(&self) -> bool {
+        match self.state {
+            ReviewUploadState::Start
+            | ReviewUploadState::CheckingExistingUpload
+            | ReviewUploadState::RequestingExport
+            | ReviewUploadState::AwaitingExport => false,
+            ReviewUploadState::GettingVideoFromAPI => {
+                self.sync_config.stream_uploads
+                    && !(self.sync_config.export_recording
+                        && self.review.type_field() == TypeField::End)
+            }
+            ReviewUploadState::UploadToStore(_)
+            | ReviewUploadState::DeleteTheAlternative(_)
+            | ReviewUploadState::DeleteTheOngoingVersions(_)
+            | ReviewUploadState::UploadingEventSnapshots
+            | ReviewUploadState::UploadingMarker
+            | ReviewUploadState::Done => true,
+        }
+    }
 
+    #[tracing::instrument(
+        name = "review_upload",
+        skip_all,
+        fields(review_id = %self.review.id(), camera = %self.review.camera_name())
+    )]
+    async fn run(&mut self, until_upload: bool) -> Result<(), ReviewUploadError> {
         loop {
+            if until_upload && self.uploads_next() {
+                return Ok(());
+            }
+
             match &self.state {
-                ReviewUploadState::Start => self.state = ReviewUploadState::GettingVideoFromAPI,
+                ReviewUploadState::Start => {
+                    // Only a review that has ended can have a complete upload that we can skip
+                    self.state = if self.sync_config.skip_if_already_uploaded
+                        && self.review.type_field() == TypeField::End
+                    {
+                        ReviewUploadState::CheckingExistingUpload
+                    } else {
+                        ReviewUploadState::GettingVideoFromAPI
+                    }
+                }
+                ReviewUploadState::CheckingExistingUpload => {
+                    self.state = self.check_existing_upload().await;
+                }
+                // Exporting is worth it for the whole clip of the review, which only an ended one has
+                ReviewUploadState::GettingVideoFromAPI
+                    if self.sync_config.export_recording
+                        && self.review.type_field() == TypeField::End =>
+                {
+                    self.state = ReviewUploadState::RequestingExport;
+                }
+                ReviewUploadState::RequestingExport => {
+                    self.state = self.request_export().await?;
+                }
+                ReviewUploadState::AwaitingExport => {
+                    self.state = self.download_export().await?;
+                }
+                ReviewUploadState::GettingVideoFromAPI if self.sync_config.stream_uploads => {
+                    self.state = self.stream_clip_to_stores().await?;
+                }
                 ReviewUploadState::GettingVideoFromAPI => {
-                    let api = self
-                        .make_frigate_api()
-                        .map_err(|e| ReviewUploadError::APIConstructionFailed(e.to_string()))?;
-
-                    let start_ts = self.review.start_time();
-                    let end_ts = self
-                        .review
-                        .end_time()
-                        .unwrap_or(self.time_getter.get_time().as_unix_timestamp_f64());
-
-                    let clip = api
-                        .recording_clip(self.review.camera_name(), start_ts, end_ts)
-                        .await
-                        .context("Retrieving video clip failed")
-                        .map_err(|e| ReviewUploadError::ClipRetrievalError(e.to_string()))?;
-
-                    let Some(clip) = clip else {
-                        return Err(ReviewUploadError::EmptyVideoReturned(id));
-                    };
-
-                    let review_with_clip =
-                        ReviewWithClip::new(self.review.clone(), clip, self.alternative_upload);
+                    let clip = self.download_clip().await?;
 
-                    self.state = ReviewUploadState::UploadToStore(review_with_clip);
+                    self.state = ReviewUploadState::UploadToStore(self.review_with_clip(clip));
                 }
                 ReviewUploadState::UploadToStore(rec) => {
+                    let destinations = self
+                        .pending_destinations
+                        .take()
+                        .unwrap_or_else(|| self.destinations());
                     remote_file_op(
                         RemoteFileOp::Upload(rec),
-                        self.path_descriptors.path_descriptors.as_ref().clone(),
+                        destinations,
                         self.file_sender_maker.clone(),
-                        MAX_UPLOAD_ATTEMPTS,
-                        self.upload_file_op_retry_sleep,
+                        upload_attempts(&self.sync_config),
+                        self.file_op_backoff,
+                        self.sync_config.audit_log_path.as_deref(),
                     )
                     .await
-                    .map_err(|e| ReviewUploadError::DeletingAltFile(e.to_string()))?;
+                    .map_err(|e| {
+                        let error = ReviewUploadError::RecordingUpload(e.to_string());
+                        self.pending_destinations = Some(e.failed_destinations);
+                        error
+                    })?;
 
-                    self.state = ReviewUploadState::DeleteTheAlternative(rec.alternative_path());
+                    self.uploaded_path = Some(rec.full_upload_path());
+                    self.state = match rec.alternative_path() {
+                        Some(alt_path) => ReviewUploadState::DeleteTheAlternative(alt_path),
+                        None => self.state_after_clip_upload(),
+                    };
                 }
                 ReviewUploadState::DeleteTheAlternative(alt_path) => {
-                    remote_file_op(
-                        RemoteFileOp::DeleteFileIfExists(alt_path),
-                        self.path_descriptors.path_descriptors.as_ref().clone(),
-                        self.file_sender_maker.clone(),
-                        MAX_DELETE_ATTEMPTS,
-                        self.upload_file_op_retry_sleep,
-                    )
-                    .await
-                    .map_err(|e| ReviewUploadError::RecordingUpload(e.to_string()))?;
+                    self.delete_alternative(alt_path).await?;
+
+                    self.state = self.state_after_clip_upload();
+                }
+                ReviewUploadState::DeleteTheOngoingVersions(paths) => {
+                    for path in paths {
+                        self.delete_alternative(path).await?;
+                    }
+
+                    self.state = self.state_after_ongoing_versions();
+                }
+                ReviewUploadState::UploadingEventSnapshots => {
+                    self.upload_event_snapshots().await;
+
+                    self.state = self.state_after_event_snapshots();
+                }
+                ReviewUploadState::UploadingMarker => {
+                    self.upload_marker().await;
 
                     self.state = ReviewUploadState::Done;
                 }
@@ -150,18 +302,552 @@ where
         }
     }
 
+    /// Skips the upload if the review was completely uploaded already
+    async fn check_existing_upload(&mut self) -> ReviewUploadState {
+        // A clip at the review's path may be of it before it ended, so only its marker counts
+        match find_completed_upload(
+            self.review.as_ref(),
+            self.naming.as_ref(),
+            &self.destinations(),
+            &self.file_sender_maker,
+        )
+        .await
+        {
+            Some(path) => {
+                tracing::info!(
+                    "Recording of review with id `{}` already exists in all destinations at `{}`. Skipping its upload.",
+                    self.review.id(),
+                    path.display()
+                );
+                self.uploaded_path = Some(path);
+                ReviewUploadState::Done
+            }
+            None => ReviewUploadState::GettingVideoFromAPI,
+        }
+    }
+
+    /// Deletes the other version of the clip (or one that was uploaded elsewhere), now that this one is uploaded
+    async fn delete_alternative(&self, alt_path: &Path) -> Result<(), ReviewUploadError> {
+        remote_file_op(
+            RemoteFileOp::DeleteFileIfExists(alt_path),
+            self.destinations(),
+            self.file_sender_maker.clone(),
+            MAX_DELETE_ATTEMPTS,
+            self.file_op_backoff,
+            None,
+        )
+        .await
+        .map_err(|e| ReviewUploadError::DeletingAltFile(e.to_string()))
+    }
+
+    #[tracing::instrument(
+        name = "recording_clip",
+        skip_all,
+        fields(review_id = %self.review.id(), camera = %self.review.camera_name())
+    )]
+    async fn download_clip(&self) -> Result<Vec<u8>, ReviewUploadError> {
+        let api = self
+            .make_frigate_api()
+            .map_err(|e| ReviewUploadError::APIConstructionFailed(e.to_string()))?;
+
+        let (start_ts, end_ts) = self.clip_range()?;
+
+        tracing::debug!(
+            "Requesting the clip of review with id `{}` with [start,end] times [{start_ts},{end_ts}]",
+            self.review.id()
+        );
+
+        let clip = api
+            .recording_clip(self.review.camera_name(), start_ts, end_ts)
+            .await
+            .map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Retrieving video clip failed", &e)
+            })?;
+
+        match clip {
+            Some(clip) => Ok(clip),
+            None => {
+                self.clip_within_retention(api.as_ref(), start_ts, end_ts)
+                    .await
+            }
+        }
+    }
+
+    /// Asks Frigate to export the clip of the review to a file, which has until the export timeout to finish
+    async fn request_export(&mut self) -> Result<ReviewUploadState, ReviewUploadError> {
+        let api = self
+            .make_frigate_api()
+            .map_err(|e| ReviewUploadError::APIConstructionFailed(e.to_string()))?;
+
+        let (start_ts, end_ts) = self.clip_range()?;
+
+        tracing::debug!(
+            "Requesting the export of review with id `{}` with [start,end] times [{start_ts},{end_ts}]",
+            self.review.id()
+        );
+
+        let export_id = api
+            .start_export(self.review.camera_name(), start_ts, end_ts)
+            .await
+            .map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Starting the export failed", &e)
+            })?;
+
+        self.pending_export = Some(PendingExport {
+            api,
+            export_id,
+            deadline: tokio::time::Instant::now() + self.sync_config.export_timeout,
+            deleted: false,
+        });
+        Ok(ReviewUploadState::AwaitingExport)
+    }
+
+    /// Polls the export with an increasing interval until it's done, then downloads its file and deletes it
+    /// from Frigate. Fails with `ExportTimedOut` if it isn't done by its deadline. Once it's deleted, whether
+    /// it was downloaded or not, the retry goes back to requesting an export.
+    #[tracing::instrument(
+        name = "recording_export",
+        skip_all,
+        fields(review_id = %self.review.id(), camera = %self.review.camera_name())
+    )]
+    async fn download_export(&mut self) -> Result<ReviewUploadState, ReviewUploadError> {
+        let Some(pending_export) = self.pending_export.as_ref() else {
+            tracing::error!("CRITICAL: INVARIANT BROKEN: Awaiting an export that wasn't requested");
+            return Ok(ReviewUploadState::RequestingExport);
+        };
+        let api = pending_export.api.clone();
+        let export_id = pending_export.export_id.clone();
+        let deadline = pending_export.deadline;
+
+        let mut poll_interval = EXPORT_POLL_INITIAL_INTERVAL;
+        let export = loop {
+            let export = api.export(&export_id).await.map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Checking the export failed", &e)
+            })?;
+            if !export.in_progress {
+                break export;
+            }
+
+            if tokio::time::Instant::now() + poll_interval > deadline {
+                self.delete_pending_export().await;
+                return Err(ReviewUploadError::ExportTimedOut(
+                    self.review.id().to_string(),
+                ));
+            }
+
+            tracing::trace!(
+                "Export `{export_id}` is in progress. Checking again in {poll_interval:?}"
+            );
+            tokio::time::sleep(poll_interval).await;
+            poll_interval = (poll_interval * 2).min(EXPORT_POLL_MAX_INTERVAL);
+        };
+
+        let clip = api.export_clip(&export).await;
+        self.delete_pending_export().await;
+
+        let clip = clip
+            .map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Downloading the export failed", &e)
+            })?
+            .ok_or_else(|| ReviewUploadError::EmptyVideoReturned(self.review.id().to_string()))?;
+        Ok(ReviewUploadState::UploadToStore(
+            self.review_with_clip(clip),
+        ))
+    }
+
+    /// Uploads the clip to all the destinations while it's downloaded. The destinations that fail get
+    /// the downloaded clip afterwards, as a regular upload with retries.
+    #[tracing::instrument(
+        name = "recording_clip_stream",
+        skip_all,
+        fields(review_id = %self.review.id(), camera = %self.review.camera_name())
+    )]
+    async fn stream_clip_to_stores(&mut self) -> Result<ReviewUploadState, ReviewUploadError> {
+        let api = self
+            .make_frigate_api()
+            .map_err(|e| ReviewUploadError::APIConstructionFailed(e.to_string()))?;
+
+        let (start_ts, end_ts) = self.clip_range()?;
+
+        tracing::debug!(
+            "Streaming the clip of review with id `{}` with [start,end] times [{start_ts},{end_ts}]",
+            self.review.id()
+        );
+
+        let stream = api
+            .recording_clip_stream(self.review.camera_name(), start_ts, end_ts)
+            .await
+            .map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Retrieving video clip stream failed", &e)
+            })?;
+
+        let Some(mut stream) = stream else {
+            // What's left after retention isn't worth streaming
+            let clip = self
+                .clip_within_retention(api.as_ref(), start_ts, end_ts)
+                .await?;
+            return Ok(ReviewUploadState::UploadToStore(
+                self.review_with_clip(clip),
+            ));
+        };
+
+        // The extension depends on the clip's header, which is in its first chunk
+        let head = stream
+            .next()
+            .await
+            .ok_or_else(|| ReviewUploadError::EmptyVideoReturned(self.review.id().to_string()))?
+            .map_err(|e| ReviewUploadError::ClipRetrievalError(e.to_string()))?;
+        let extension = self.frigate_api_config.clip_container.extension_for(&head);
+        let path = self
+            .naming
+            .recording_path(self.review.as_ref(), self.upload_slot(), extension);
+
+        let source = futures::stream::once(async move { Ok(head) })
+            .chain(stream)
+            .boxed();
+        let result = stream_upload(source, &path, &self.destinations(), &self.file_sender_maker)
+            .await
+            .map_err(|e| match e.downcast_ref::<FrigateApiError>() {
+                // E.g., the clip turned out to be too large while it was streamed
+                Some(api_error) => {
+                    ReviewUploadError::from_clip_retrieval("Streaming video clip failed", api_error)
+                }
+                None => ReviewUploadError::ClipRetrievalError(
+                    e.context("Streaming video clip failed").to_string(),
+                ),
+            })?;
+
+        let review_with_clip = ReviewWithClip::new(
+            self.review.clone(),
+            result.data,
+            self.upload_slot(),
+            extension,
+            self.naming.clone(),
+        );
+
+        if !result.failed.is_empty() {
+            tracing::warn!(
+                "Streaming the clip of review with id `{}` failed for {} destination(s). Uploading the downloaded clip instead.",
+                self.review.id(),
+                result.failed.len()
+            );
+            self.pending_destinations = Some(result.failed);
+            return Ok(ReviewUploadState::UploadToStore(review_with_clip));
+        }
+
+        if let Some(audit_log_path) = self.sync_config.audit_log_path.as_deref() {
+            for d in &result.uploaded {
+                let record = AuditRecord::new(&review_with_clip, d, UploadOutcome::Success, None);
+                append_audit_record(audit_log_path, &record).await;
+            }
+        }
+
+        self.uploaded_path = Some(path);
+        Ok(match review_with_clip.alternative_path() {
+            Some(alt_path) => ReviewUploadState::DeleteTheAlternative(alt_path),
+            None => self.state_after_clip_upload(),
+        })
+    }
+
+    /// The [start,end] times of the clip to download for the review
+    fn clip_range(&self) -> Result<(f64, f64), ReviewUploadError> {
+        let start_ts = self.review.start_time();
+        let end_ts = if let Some(end_ts) = self.review.end_time() {
+            end_ts
+        } else {
+            // The review is ongoing, and Frigate may not have recorded up to now yet
+            let end_ts = self
+                .time_getter
+                .get_time()
+                .saturating_duration_sub(self.frigate_api_config.clip_end_safety_margin)
+                .as_unix_timestamp_f64();
+
+            if end_ts <= start_ts {
+                return Err(ReviewUploadError::NothingRecordedYet(
+                    self.review.id().to_string(),
+                ));
+            }
+
+            end_ts
+        };
+
+        Ok((start_ts, end_ts))
+    }
+
+    fn review_with_clip(&self, clip: Vec<u8>) -> ReviewWithClip {
+        let extension = self.frigate_api_config.clip_container.extension_for(&clip);
+        ReviewWithClip::new(
+            self.review.clone(),
+            clip,
+            self.upload_slot(),
+            extension,
+            self.naming.clone(),
+        )
+    }
+
+    /// An empty clip can mean that the recording of the review has been deleted by Frigate's retention.
+    /// If only its beginning has been deleted, what's left is requested instead.
+    async fn clip_within_retention(
+        &self,
+        api: &dyn FrigateApi,
+        start_ts: f64,
+        end_ts: f64,
+    ) -> Result<Vec<u8>, ReviewUploadError> {
+        let id = self.review.id().to_string();
+        let camera_name = self.review.camera_name();
+
+        let retention = match api.recording_retention(camera_name).await {
+            Ok(Some(retention)) => retention,
+            Ok(None) => return Err(ReviewUploadError::EmptyVideoReturned(id)),
+            Err(e) => {
+                tracing::warn!(
+                    "Getting the recording retention of camera `{camera_name}` failed: {e}"
+                );
+                return Err(ReviewUploadError::EmptyVideoReturned(id));
+            }
+        };
+
+        let earliest_ts = self
+            .time_getter
+            .get_time()
+            .saturating_duration_sub(retention)
+            .as_unix_timestamp_f64();
+
+        if end_ts <= earliest_ts {
+            return Err(ReviewUploadError::OutsideRetention(id));
+        }
+
+        if start_ts >= earliest_ts {
+            // Retention isn't the reason the clip is empty
+            return Err(ReviewUploadError::EmptyVideoReturned(id));
+        }
+
+        tracing::info!(
+            "The start of review with id `{id}` is older than the recording retention. Clamping its start from {start_ts} to {earliest_ts}"
+        );
+
+        api.recording_clip(camera_name, earliest_ts, end_ts)
+            .await
+            .map_err(|e| {
+                ReviewUploadError::from_clip_retrieval("Retrieving clamped video clip failed", &e)
+            })?
+            .ok_or(ReviewUploadError::EmptyVideoReturned(id))
+    }
+
+    fn state_after_clip_upload(&self) -> ReviewUploadState {
+        let ongoing_paths = self.ongoing_clip_paths();
+        if ongoing_paths.is_empty() {
+            self.state_after_ongoing_versions()
+        } else {
+            ReviewUploadState::DeleteTheOngoingVersions(ongoing_paths)
+        }
+    }
+
+    /// The clips uploaded while the review was ongoing that are elsewhere than its final clip,
+    /// e.g., in the directory of the day it started when it's dated by its end.
+    /// They'd otherwise be left behind as stale duplicates of it.
+    fn ongoing_clip_paths(&self) -> Vec<PathBuf> {
+        let Some(extension) = self
+            .uploaded_path
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|extension| extension.to_str())
+        else {
+            return Vec::new();
+        };
+        let alternatives = if self.sync_config.keep_alternative_versions {
+            vec![Some(false), Some(true)]
+        } else {
+            vec![None]
+        };
+        alternatives
+            .into_iter()
+            .filter_map(|alternative| {
+                self.naming
+                    .ongoing_recording_path(self.review.as_ref(), alternative, extension)
+            })
+            .collect()
+    }
+
+    fn state_after_ongoing_versions(&self) -> ReviewUploadState {
+        // Only once a review has ended are all its events known
+        if self.sync_config.upload_event_snapshots && self.review.type_field() == TypeField::End {
+            ReviewUploadState::UploadingEventSnapshots
+        } else {
+            self.state_after_event_snapshots()
+        }
+    }
+
+    fn state_after_event_snapshots(&self) -> ReviewUploadState {
+        // The marker is only worth uploading if it's checked
+        if self.sync_config.skip_if_already_uploaded && self.review.type_field() == TypeField::End {
+            ReviewUploadState::UploadingMarker
+        } else {
+            ReviewUploadState::Done
+        }
+    }
+
+    /// Uploads the marker of the completed upload, which only saves uploading the review again,
+    /// so failing is logged without failing the review
+    async fn upload_marker(&self) {
+        let Some(clip_path) = self.uploaded_path.clone() else {
+            return;
+        };
+        let Some(marker) = UploadMarker::new(self.review.clone(), clip_path, self.naming.clone())
+        else {
+            return;
+        };
+
+        if let Err(e) = remote_file_op(
+            RemoteFileOp::Replace(&marker),
+            self.destinations(),
+            self.file_sender_maker.clone(),
+            upload_attempts(&self.sync_config),
+            self.file_op_backoff,
+            None,
+        )
+        .await
+        {
+            tracing::warn!(
+                "Uploading the upload marker of review with id `{}` failed: {e}",
+                self.review.id()
+            );
+        }
+    }
+
+    /// Uploads the snapshot of every event of the review. The clip is what matters most,
+    /// so failing here is logged without failing the review.
+    async fn upload_event_snapshots(&self) {
+        let api = match self.make_frigate_api() {
+            Ok(api) => api,
+            Err(e) => {
+                tracing::warn!("Frigate API construction for event snapshots failed: {e}");
+                return;
+            }
+        };
+
+        for event_id in self.review.detections() {
+            let snapshot = match api.event_snapshot(event_id).await {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => {
+                    tracing::debug!("Event with id `{event_id}` has no snapshot to upload");
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("Retrieving snapshot of event with id `{event_id}` failed: {e}");
+                    continue;
+                }
+            };
+
+            let event_snapshot = EventSnapshot::new(
+                self.review.clone(),
+                event_id.clone(),
+                snapshot,
+                self.naming.clone(),
+            );
+
+            if let Err(e) = remote_file_op(
+                RemoteFileOp::Upload(&event_snapshot),
+                self.destinations(),
+                self.file_sender_maker.clone(),
+                upload_attempts(&self.sync_config),
+                self.file_op_backoff,
+                self.sync_config.audit_log_path.as_deref(),
+            )
+            .await
+            {
+                tracing::warn!("Uploading snapshot of event with id `{event_id}` failed: {e}");
+            }
+        }
+    }
+
+    /// Final-only destinations are included only once the review has ended
+    fn destinations(&self) -> Vec<Arc<PathDescriptor>> {
+        let mut destinations = self.path_descriptors.path_descriptors.as_ref().clone();
+        if self.review.type_field() == TypeField::End {
+            destinations.extend(self.sync_config.final_only_destinations.iter().cloned());
+        }
+        destinations
+    }
+
+    /// Which of the two alternative versions to upload to, or None if only a single version is kept
+    fn upload_slot(&self) -> Option<bool> {
+        self.sync_config
+            .keep_alternative_versions
+            .then_some(self.alternative_upload)
+    }
+
+    /// Deletes the awaited export from Frigate, so the next attempt requests a new one
+    async fn delete_pending_export(&mut self) {
+        if let Some(pending_export) = self.pending_export.take() {
+            pending_export.delete().await;
+        }
+        self.state = ReviewUploadState::RequestingExport;
+    }
+
     pub fn make_frigate_api(&self) -> anyhow::Result<Arc<dyn FrigateApi>> {
         (self.frigate_api_maker)(&self.frigate_api_config)
     }
 }
 
+/// Exports are deleted once they aren't needed, so that they don't pile up in Frigate.
+/// Failing to delete one doesn't fail the upload.
+async fn delete_export(api: &dyn FrigateApi, export_id: &str) {
+    if let Err(e) = api.delete_export(export_id).await {
+        tracing::warn!("Deleting export `{export_id}` from Frigate failed: {e}");
+    }
+}
+
+/// An export requested from Frigate. If it's dropped before being deleted, e.g., when the upload is replaced
+/// by that of a newer update of the review or given up on, it's deleted in the background.
+struct PendingExport {
+    api: Arc<dyn FrigateApi>,
+    export_id: String,
+    /// When it's requested again if it isn't done
+    deadline: tokio::time::Instant,
+    deleted: bool,
+}
+
+impl PendingExport {
+    async fn delete(mut self) {
+        delete_export(self.api.as_ref(), &self.export_id).await;
+        self.deleted = true;
+    }
+}
+
+impl Drop for PendingExport {
+    fn drop(&mut self) {
+        if self.deleted {
+            return;
+        }
+        let api = self.api.clone();
+        let export_id = std::mem::take(&mut self.export_id);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { delete_export(api.as_ref(), &export_id).await });
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "Export `{export_id}` is left in Frigate, since it was abandoned outside the runtime"
+                );
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub enum ReviewUploadState {
     #[default]
     Start,
+    CheckingExistingUpload,
     GettingVideoFromAPI,
+    RequestingExport,
+    AwaitingExport,
     UploadToStore(ReviewWithClip),
     DeleteTheAlternative(PathBuf),
+    DeleteTheOngoingVersions(Vec<PathBuf>),
+    UploadingEventSnapshots,
+    UploadingMarker,
     Done,
 }
 
@@ -0,0 +1,106 @@
+use super::{file_op_backoff, upload_attempts};
+use crate::{
+    config::PathDescriptors,
+    montage::compose_montage,
+    system::{
+        common::{
+            file_upload::{RemoteFileOp, UploadableFile, remote_file_op},
+            naming::{NamingStrategy, naming_strategy},
+        },
+        config::SyncSystemConfig,
+        traits::FileSenderMaker,
+    },
+};
+use mqtt_handler::types::{reviews::ReviewProps, snapshot::Snapshot};
+use std::{path::PathBuf, sync::Arc};
+
+/// Takes the place of the clip's extension, so that the montage is named after the clip
+const MONTAGE_EXTENSION: &str = "montage.jpg";
+
+/// The snapshots received during a review, composed into one image, uploaded to the same directory as its clip
+pub struct ReviewMontage {
+    review: Arc<dyn ReviewProps>,
+    image: Vec<u8>,
+    naming: Arc<dyn NamingStrategy>,
+}
+
+impl UploadableFile for ReviewMontage {
+    fn file_bytes(&self) -> &[u8] {
+        &self.image
+    }
+
+    fn full_upload_path(&self) -> PathBuf {
+        self.naming
+            .recording_path(self.review.as_ref(), None, MONTAGE_EXTENSION)
+    }
+
+    fn file_description(&self) -> String {
+        format!("Snapshot montage of review with id {}", self.review.id())
+    }
+
+    fn camera_name(&self) -> &str {
+        self.review.camera_name()
+    }
+
+    fn source_id(&self) -> Option<String> {
+        Some(self.review.id().to_string())
+    }
+}
+
+/// Composes the snapshots of the review, which has ended, into a montage and uploads it.
+/// The montage is secondary to the clip, so failing is only logged.
+pub async fn upload_review_montage<S: FileSenderMaker>(
+    review: Arc<dyn ReviewProps>,
+    snapshots: Vec<Arc<Snapshot>>,
+    file_sender_maker: Arc<S>,
+    path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
+) {
+    let id = review.id().to_string();
+
+    // Decoding and encoding images takes long enough to hold up other tasks on the runtime
+    let grid = sync_config.montage_grid;
+    let image = tokio::task::spawn_blocking(move || {
+        let images = snapshots
+            .iter()
+            .map(|s| s.image_bytes.as_slice())
+            .collect::<Vec<_>>();
+        compose_montage(&images, grid)
+    })
+    .await;
+    let image = match image {
+        Ok(Ok(image)) => image,
+        Ok(Err(e)) => {
+            tracing::warn!("Composing the montage of review with id `{id}` failed: {e}");
+            return;
+        }
+        Err(e) => {
+            tracing::warn!("Composing the montage of review with id `{id}` failed: {e}");
+            return;
+        }
+    };
+
+    let montage = ReviewMontage {
+        review,
+        image,
+        naming: naming_strategy(&sync_config),
+    };
+
+    // The review has ended, so it goes to the final-only destinations too
+    let mut destinations = path_descriptors.path_descriptors.as_ref().clone();
+    destinations.extend(sync_config.final_only_destinations.iter().cloned());
+
+    match remote_file_op(
+        RemoteFileOp::Upload(&montage),
+        destinations,
+        file_sender_maker,
+        upload_attempts(&sync_config),
+        file_op_backoff(&sync_config.file_op_retry),
+        sync_config.audit_log_path.as_deref(),
+    )
+    .await
+    {
+        Ok(()) => tracing::info!("Uploaded the montage of review with id `{id}`"),
+        Err(e) => tracing::warn!("Uploading the montage of review with id `{id}` failed: {e}"),
+    }
+}
@@ -1,37 +1,31 @@
-use crate::system::common::file_upload::UploadableFile;
+use crate::system::common::{file_upload::UploadableFile, naming::NamingStrategy};
 use mqtt_handler::types::reviews::ReviewProps;
 use std::{path::PathBuf, sync::Arc};
-use utils::time::Time;
 
 #[derive(Debug, Clone)]
 pub struct ReviewWithClip {
     review: Arc<dyn ReviewProps>,
     clip: Vec<u8>,
-    alternative_upload: bool,
+    /// None when alternative versions aren't kept, and the file is always uploaded with the same name
+    alternative_upload: Option<bool>,
+    extension: &'static str,
+    naming: Arc<dyn NamingStrategy>,
 }
 
 impl ReviewWithClip {
-    pub fn new(review: Arc<dyn ReviewProps>, clip: Vec<u8>, alternative_upload: bool) -> Self {
+    pub fn new(
+        review: Arc<dyn ReviewProps>,
+        clip: Vec<u8>,
+        alternative_upload: Option<bool>,
+        extension: &'static str,
+        naming: Arc<dyn NamingStrategy>,
+    ) -> Self {
         Self {
             review,
             clip,
             alternative_upload,
-        }
-    }
-
-    /// To facilitate upload two different files in an alternating fashion, such that,
-    /// we have at least one complete file in the store,
-    /// and only delete the other file (alternative) when the first is successful.
-    /// This function returns two possible suffixes for the file name.
-    fn alternative_name_suffix(&self, flip: bool) -> &str {
-        #[allow(clippy::if_not_else)]
-        if self.alternative_upload != flip
-        // We use '!= flip' as an XOR operation that flips the boolean on demand
-        // Remember: XORing with `true` always flips/toggles the operand.
-        {
-            "-1"
-        } else {
-            "-0"
+            extension,
+            naming,
         }
     }
 
@@ -40,26 +34,14 @@ impl ReviewWithClip {
     /// So two versions are uploaded, say with suffixes `-0` and `-1`.
     /// Once we upload `-0`, we delete the `-1`, and vice-versa.
     /// This helps in preventing deleting a copy before a better copy is uploaded.
-    pub fn alternative_path(&self) -> PathBuf {
-        self.upload_dir().join(self.file_name_impl(true))
-    }
-
-    /// Params:
-    /// alternative: The alternative path to the current setting.
-    /// We use this to delete this file when the first upload is complete.
-    /// So two versions are uploaded, say with suffixes `-0` and `-1`.
-    /// Once we upload `-0`, we delete the `-1`, and vice-versa.
-    /// This helps in preventing deleting a copy before a better copy is uploaded.
-    fn file_name_impl(&self, alternative: bool) -> PathBuf {
-        let datetime = chrono::Local::now()
-            .format("%Y-%m-%d_%H-%M-%S%z")
-            .to_string();
-        format!(
-            "RecordingClip-{}-{datetime}{}.mp4",
-            self.review.camera_name(),
-            self.alternative_name_suffix(alternative)
-        )
-        .into()
+    /// None if alternative versions aren't kept.
+    pub fn alternative_path(&self) -> Option<PathBuf> {
+        let alternative_upload = self.alternative_upload?;
+        Some(self.naming.recording_path(
+            self.review.as_ref(),
+            Some(!alternative_upload),
+            self.extension,
+        ))
     }
 }
 
@@ -68,19 +50,23 @@ impl UploadableFile for ReviewWithClip {
         &self.clip
     }
 
-    fn file_name(&self) -> std::path::PathBuf {
-        self.file_name_impl(false)
+    fn full_upload_path(&self) -> PathBuf {
+        self.naming.recording_path(
+            self.review.as_ref(),
+            self.alternative_upload,
+            self.extension,
+        )
     }
 
-    fn upload_dir(&self) -> std::path::PathBuf {
-        let start_time = self.review.start_time();
-        let time = Time::from_f64_secs_since_epoch(start_time);
+    fn file_description(&self) -> String {
+        format!("Recording clip with id {}", self.review.id())
+    }
 
-        let date = time.as_local_time_in_dir_foramt();
-        PathBuf::from(date)
+    fn camera_name(&self) -> &str {
+        self.review.camera_name()
     }
 
-    fn file_description(&self) -> String {
-        format!("Recording clip with id {}", self.review.id())
+    fn source_id(&self) -> Option<String> {
+        Some(self.review.id().to_string())
     }
 }
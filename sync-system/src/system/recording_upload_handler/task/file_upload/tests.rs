@@ -1,23 +1,50 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::config::PathDescriptors;
+use crate::{
+    config::PathDescriptors,
+    system::{
+        common::{
+            naming::{DefaultNaming, NamingStrategy},
+            retry::Backoff,
+        },
+        config::{CameraDirOrder, DateBasis, RecordingRetry, SyncSystemConfig},
+        traits::{FileSenderMaker, FrigateApiMaker},
+    },
+};
 
-use super::ReviewUpload;
+use super::{MAX_UPLOAD_ATTEMPTS, ReviewUpload, ReviewUploadError};
 use file_sender::{
     make_inmemory_filesystem, path_descriptor::PathDescriptor, traits::StoreDestination,
 };
-use frigate_api_caller::{config::FrigateApiConfig, traits::FrigateApi};
+use frigate_api_caller::{
+    FrigateApiError, config::FrigateApiConfig, json::export::Export, traits::FrigateApi,
+};
 use mocks::{frigate_api::make_frigate_client_mock, store_dest::make_store_mock};
 use mqtt_handler::types::reviews::{ReviewProps, payload};
-use utils::time_getter::TimeGetter;
+use tracing_subscriber::{Layer, Registry, layer::SubscriberExt};
+use utils::{
+    file_name::FileNameCharset,
+    time::Time,
+    time_getter::{TimeGetter, TimeGetterFn},
+};
+
+/// Every retry of a failed file op waits up to `period`
+fn fixed_backoff(period: std::time::Duration) -> Backoff {
+    Backoff::new(period, period)
+}
 
 #[derive(Debug, Clone)]
 struct TestReviewData {
     camera_name: String,
     start_time: f64,
-    end_time: f64,
+    end_time: Option<f64>,
     id: String,
     type_field: payload::TypeField,
+    detections: Vec<String>,
 }
 
 impl ReviewProps for TestReviewData {
@@ -34,12 +61,39 @@ impl ReviewProps for TestReviewData {
     }
 
     fn end_time(&self) -> Option<f64> {
-        Some(self.end_time)
+        self.end_time
     }
 
     fn type_field(&self) -> payload::TypeField {
         self.type_field
     }
+
+    fn detections(&self) -> &[String] {
+        &self.detections
+    }
+
+    fn objects(&self) -> &[String] {
+        &[]
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        None
+    }
+}
+
+/// Where the default naming puts the mp4 clip of the review
+fn recording_path(
+    review: &dyn ReviewProps,
+    alternative: Option<bool>,
+    file_name_charset: FileNameCharset,
+    camera_dirs: Option<CameraDirOrder>,
+) -> PathBuf {
+    DefaultNaming::new(&SyncSystemConfig {
+        file_name_charset,
+        camera_dirs,
+        ..SyncSystemConfig::default()
+    })
+    .recording_path(review, alternative, "mp4")
 }
 
 #[tokio::test]
@@ -77,22 +131,22 @@ async fn basic_upload_in_mocks() {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
     let review = TestReviewData {
         camera_name: "MyCamera".to_string(),
         start_time: 950.,
-        end_time: 1000.,
+        end_time: Some(1000.),
         id: "id-abcdefg".to_string(),
         type_field: payload::TypeField::New,
+        detections: Vec::new(),
     };
 
     let mut review_upload = ReviewUpload::new(
@@ -102,19 +156,218 @@ async fn basic_upload_in_mocks() {
         frigate_api_maker,
         file_sender_maker,
         path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+}
+
+/// Uploads the review to an in-memory destination with `skip_if_already_uploaded` set,
+/// and returns the number of times its clip was downloaded
+async fn upload_skipping_existing(
+    review: &TestReviewData,
+    file_sender: &Arc<dyn StoreDestination<Error = anyhow::Error>>,
+) -> usize {
+    let downloads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    let downloads_inner = downloads.clone();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| {
+            downloads_inner.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(Some(b"Hello world!".to_vec()))
+        });
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let file_sender = file_sender.clone();
+
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+
+    let sync_config = SyncSystemConfig {
+        skip_if_already_uploaded: true,
+        ..SyncSystemConfig::default()
+    };
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        Arc::new(FrigateApiConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(sync_config),
         TimeGetter::default(),
-        std::time::Duration::from_millis(500),
+        fixed_backoff(std::time::Duration::from_millis(500)),
     );
 
     review_upload.start().await.unwrap();
+    downloads.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+#[tokio::test]
+async fn skip_upload_of_ended_review_that_already_exists() {
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    let file_sender = make_inmemory_filesystem();
+
+    assert_eq!(upload_skipping_existing(&review, &file_sender).await, 1);
+    // The clip must not be retrieved again, since it's already uploaded
+    assert_eq!(upload_skipping_existing(&review, &file_sender).await, 0);
+}
+
+#[tokio::test]
+async fn partial_upload_of_review_is_not_skipped() {
+    let ongoing = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::Update,
+        detections: Vec::new(),
+    };
+    let ended = TestReviewData {
+        end_time: Some(1000.),
+        type_field: payload::TypeField::End,
+        ..ongoing.clone()
+    };
+
+    let file_sender = make_inmemory_filesystem();
+
+    // The clip of the ongoing review is at the path of the ended one, but it's partial
+    assert_eq!(upload_skipping_existing(&ongoing, &file_sender).await, 1);
+    assert!(
+        file_sender
+            .file_exists(&recording_path(
+                &ended,
+                Some(false),
+                FileNameCharset::Ascii,
+                None
+            ))
+            .await
+            .unwrap()
+    );
+    assert_eq!(upload_skipping_existing(&ended, &file_sender).await, 1);
+
+    // A review with the same id that ended later, e.g., when Frigate extended it, isn't complete either
+    let extended = TestReviewData {
+        end_time: Some(1100.),
+        ..ended.clone()
+    };
+    assert_eq!(upload_skipping_existing(&extended, &file_sender).await, 1);
+    assert_eq!(upload_skipping_existing(&extended, &file_sender).await, 0);
+
+    // A different review that starts at the same time
+    let other = TestReviewData {
+        id: "id-other".to_string(),
+        ..extended
+    };
+    assert_eq!(upload_skipping_existing(&other, &file_sender).await, 1);
+}
+
+#[tokio::test]
+async fn clips_of_ongoing_review_on_another_date_are_deleted_once_it_ends() {
+    // A minute before and after a local midnight
+    let midnight = chrono::NaiveDate::from_ymd_opt(2024, 6, 10)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .unwrap()
+        .timestamp();
+    #[allow(clippy::cast_precision_loss)]
+    let midnight = midnight as f64;
+    let ongoing = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: midnight - 60.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::Update,
+        detections: Vec::new(),
+    };
+    let ended = TestReviewData {
+        end_time: Some(midnight + 60.),
+        type_field: payload::TypeField::End,
+        ..ongoing.clone()
+    };
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        date_basis: DateBasis::End,
+        ..SyncSystemConfig::default()
+    });
+    let naming = DefaultNaming::new(&sync_config);
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = {
+        let file_sender = file_sender.clone();
+        Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()))
+    };
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![file_sender.path_descriptor().clone()]),
+    };
+
+    // Both versions of the ongoing review are uploaded to the date it started, and the ended one to the date it ended
+    let mut alternative_upload = false;
+    for review in [&ongoing, &ongoing, &ended] {
+        let mut review_upload = ReviewUpload::new(
+            Arc::new(review.clone()),
+            alternative_upload,
+            Arc::new(FrigateApiConfig::default()),
+            frigate_api_maker.clone(),
+            file_sender_maker.clone(),
+            path_descriptors.clone(),
+            sync_config.clone(),
+            TimeGetter::default(),
+            fixed_backoff(std::time::Duration::from_millis(500)),
+        );
+        review_upload.start().await.unwrap();
+        alternative_upload = !alternative_upload;
+    }
+
+    for alternative in [false, true] {
+        assert!(
+            !file_sender
+                .file_exists(&naming.recording_path(&ongoing, Some(alternative), "mp4"))
+                .await
+                .unwrap()
+        );
+    }
+    assert!(
+        file_sender
+            .file_exists(&naming.recording_path(&ended, Some(false), "mp4"))
+            .await
+            .unwrap()
+    );
 }
 
 #[tokio::test]
 async fn basic_upload_in_virtual_filesystem() {
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     // Prepare the file sender mock
@@ -124,9 +377,10 @@ async fn basic_upload_in_virtual_filesystem() {
     let review_new = TestReviewData {
         camera_name: "MyCamera".to_string(),
         start_time: 950.,
-        end_time: 1000.,
+        end_time: Some(1000.),
         id: "id-abcdefg".to_string(),
         type_field: payload::TypeField::New,
+        detections: Vec::new(),
     };
 
     {
@@ -146,8 +400,8 @@ async fn basic_upload_in_virtual_filesystem() {
             Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -158,8 +412,9 @@ async fn basic_upload_in_virtual_filesystem() {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             TimeGetter::default(),
-            std::time::Duration::from_millis(500),
+            fixed_backoff(std::time::Duration::from_millis(500)),
         );
 
         review_upload.start().await.unwrap();
@@ -225,8 +480,8 @@ async fn basic_upload_in_virtual_filesystem() {
             Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -237,8 +492,9 @@ async fn basic_upload_in_virtual_filesystem() {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             TimeGetter::default(),
-            std::time::Duration::from_millis(500),
+            fixed_backoff(std::time::Duration::from_millis(500)),
         );
 
         review_upload.start().await.unwrap();
@@ -287,3 +543,1463 @@ async fn basic_upload_in_virtual_filesystem() {
         b"Hello world2!"
     );
 }
+
+#[tokio::test]
+async fn single_version_upload_in_virtual_filesystem() {
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        keep_alternative_versions: false,
+        ..SyncSystemConfig::default()
+    });
+
+    let file_sender = make_inmemory_filesystem();
+
+    let review_new = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::New,
+        detections: Vec::new(),
+    };
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    // The alternative upload flag flips between uploads, like it does in the task, but must have no effect
+    for (alternative_upload, clip) in [(false, b"Hello world!"), (true, b"Hello world2")] {
+        let mut frigate_api_mock = make_frigate_client_mock();
+        frigate_api_mock
+            .expect_recording_clip()
+            .returning(move |_, _, _| Ok(Some(clip.to_vec())));
+
+        let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+        let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+        let file_sender_inner = file_sender.clone();
+        let file_sender_maker =
+            Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+        let mut review_upload = ReviewUpload::new(
+            Arc::new(review_new.clone()),
+            alternative_upload,
+            frigate_config.clone(),
+            frigate_api_maker,
+            file_sender_maker,
+            path_descriptors.clone(),
+            sync_config.clone(),
+            TimeGetter::default(),
+            fixed_backoff(std::time::Duration::from_millis(500)),
+        );
+
+        review_upload.start().await.unwrap();
+
+        let dirs = file_sender.ls(Path::new(".")).await.unwrap();
+        assert_eq!(dirs.len(), 1);
+
+        let uploaded_files = file_sender
+            .ls(&Path::new(".").join(&dirs[0]))
+            .await
+            .unwrap();
+
+        // The same single file is overwritten
+        assert_eq!(uploaded_files.len(), 1);
+        assert_eq!(
+            uploaded_files[0],
+            recording_path(&review_new, None, FileNameCharset::Ascii, None)
+                .file_name()
+                .unwrap()
+        );
+        let file_name = uploaded_files[0].to_str().unwrap();
+        assert!(file_name.contains(&review_new.camera_name));
+        assert!(!file_name.ends_with("-0.mp4"));
+        assert!(!file_name.ends_with("-1.mp4"));
+
+        assert_eq!(
+            file_sender
+                .get_to_memory(&Path::new(".").join(&dirs[0]).join(&uploaded_files[0]))
+                .await
+                .unwrap(),
+            clip
+        );
+    }
+}
+
+struct FixedTimeGetter(Time);
+
+impl TimeGetterFn for FixedTimeGetter {
+    fn get_time(&self) -> Time {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn clip_start_clamped_to_recording_retention() {
+    let now = Time::from_secs_since_epoch(1_000_000);
+    let retention = std::time::Duration::from_secs(24 * 60 * 60);
+    let earliest_ts = now
+        .saturating_duration_sub(retention)
+        .as_unix_timestamp_f64();
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    // The review starts before the retention, and ends within it
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: earliest_ts - 1000.,
+        end_time: Some(earliest_ts + 1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    let clip = b"Hello world!";
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(move |_, start_ts, _| *start_ts < earliest_ts)
+        .returning(|_, _, _| Ok(None))
+        .once();
+    frigate_api_mock
+        .expect_recording_retention()
+        .returning(move |_| Ok(Some(retention)))
+        .once();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(move |_, start_ts, end_ts| {
+            (*start_ts - earliest_ts).abs() < 1e-6 && (*end_ts - (earliest_ts + 1000.)).abs() < 1e-6
+        })
+        .returning(move |_, _, _| Ok(Some(clip.to_vec())))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::new(Arc::new(FixedTimeGetter(now))),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+
+    let dirs = file_sender.ls(Path::new(".")).await.unwrap();
+    assert_eq!(dirs.len(), 1);
+    let uploaded_files = file_sender
+        .ls(&Path::new(".").join(&dirs[0]))
+        .await
+        .unwrap();
+    assert_eq!(uploaded_files.len(), 1);
+    assert_eq!(
+        file_sender
+            .get_to_memory(&Path::new(".").join(&dirs[0]).join(&uploaded_files[0]))
+            .await
+            .unwrap(),
+        clip
+    );
+}
+
+#[tokio::test]
+async fn clip_entirely_outside_recording_retention() {
+    let now = Time::from_secs_since_epoch(1_000_000);
+    let retention = std::time::Duration::from_secs(24 * 60 * 60);
+    let earliest_ts = now
+        .saturating_duration_sub(retention)
+        .as_unix_timestamp_f64();
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: earliest_ts - 1000.,
+        end_time: Some(earliest_ts - 500.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(None))
+        .once();
+    frigate_api_mock
+        .expect_recording_retention()
+        .returning(move |_| Ok(Some(retention)))
+        .once();
+
+    // Nothing is uploaded
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(make_store_mock());
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::new(Arc::new(FixedTimeGetter(now))),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    assert_eq!(
+        review_upload.start().await.unwrap_err(),
+        ReviewUploadError::OutsideRetention("id-abcdefg".to_string())
+    );
+}
+
+#[tokio::test]
+async fn ongoing_review_clip_ends_before_now_by_safety_margin() {
+    let now = Time::from_secs_since_epoch(1_000_000);
+    let margin = std::time::Duration::from_secs(5);
+    let latest_end_ts = now.saturating_duration_sub(margin).as_unix_timestamp_f64();
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        clip_end_safety_margin: margin,
+        ..Default::default()
+    });
+
+    // The review hasn't ended yet
+    let start_ts = now.as_unix_timestamp_f64() - 60.;
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: start_ts,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::New,
+        detections: Vec::new(),
+    };
+
+    let clip = b"Hello world!";
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(move |_, start, end| *start < *end && *end <= latest_end_ts)
+        .returning(move |_, _, _| Ok(Some(clip.to_vec())))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::new(Arc::new(FixedTimeGetter(now))),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+
+    let dirs = file_sender.ls(Path::new(".")).await.unwrap();
+    assert_eq!(dirs.len(), 1);
+}
+
+#[tokio::test]
+async fn ongoing_review_started_within_safety_margin() {
+    let now = Time::from_secs_since_epoch(1_000_000);
+    let margin = std::time::Duration::from_secs(5);
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        clip_end_safety_margin: margin,
+        ..Default::default()
+    });
+
+    // Started just now, so there's nothing that's safely recorded yet
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: now.as_unix_timestamp_f64() - 1.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::New,
+        detections: Vec::new(),
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock.expect_recording_clip().never();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::new(Arc::new(FixedTimeGetter(now))),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    let result = review_upload.start().await;
+    assert!(matches!(
+        result,
+        Err(ReviewUploadError::NothingRecordedYet(_))
+    ));
+}
+
+#[tokio::test]
+async fn event_snapshots_uploaded_next_to_clip() {
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        keep_alternative_versions: false,
+        upload_event_snapshots: true,
+        ..SyncSystemConfig::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: vec!["event-1".to_string(), "event-2".to_string()],
+    };
+
+    let clip = b"Hello world!";
+    let snapshot = b"Snapshot of event-1";
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| Ok(Some(clip.to_vec())))
+        .once();
+    frigate_api_mock
+        .expect_event_snapshot()
+        .withf(|event_id| event_id == "event-1")
+        .returning(move |_| Ok(Some(snapshot.to_vec())))
+        .once();
+    // An event without a snapshot is skipped
+    frigate_api_mock
+        .expect_event_snapshot()
+        .withf(|event_id| event_id == "event-2")
+        .returning(|_| Ok(None))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        sync_config,
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+
+    let dirs = file_sender.ls(Path::new(".")).await.unwrap();
+    assert_eq!(dirs.len(), 1);
+    let dir = Path::new(".").join(&dirs[0]);
+    let mut uploaded_files = file_sender.ls(&dir).await.unwrap();
+    uploaded_files.sort();
+    assert_eq!(uploaded_files.len(), 2);
+
+    let snapshot_name = uploaded_files[0].to_str().unwrap();
+    assert!(snapshot_name.starts_with("EventSnapshot-MyCamera-"));
+    assert!(snapshot_name.ends_with("-event-1.jpg"));
+    assert_eq!(
+        file_sender
+            .get_to_memory(&dir.join(&uploaded_files[0]))
+            .await
+            .unwrap(),
+        snapshot
+    );
+    assert_eq!(
+        uploaded_files[1],
+        recording_path(&review, None, FileNameCharset::Ascii, None)
+            .file_name()
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn recording_and_event_snapshots_uploaded_into_recordings_subdir() {
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        keep_alternative_versions: false,
+        upload_event_snapshots: true,
+        recordings_subdir: "media/recordings".into(),
+        snapshots_subdir: "media/snapshots".into(),
+        ..SyncSystemConfig::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: vec!["event-1".to_string()],
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+    frigate_api_mock
+        .expect_event_snapshot()
+        .returning(|_| Ok(Some(b"Snapshot of event-1".to_vec())))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review.clone()),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        sync_config,
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+
+    let date = Time::from_f64_secs_since_epoch(950.).as_local_time_in_dir_foramt();
+    let dir = Path::new("media/recordings").join(date);
+
+    let clip_path = review_upload.uploaded_path().unwrap();
+    assert_eq!(clip_path.parent().unwrap(), dir);
+    assert!(file_sender.file_exists(clip_path).await.unwrap());
+
+    // The event snapshots go with the clip, rather than to the snapshots subdirectory
+    let uploaded_files = file_sender.ls(&dir).await.unwrap();
+    assert_eq!(uploaded_files.len(), 2);
+    assert_eq!(
+        file_sender.ls(Path::new("media")).await.unwrap(),
+        vec![Path::new("recordings")]
+    );
+}
+
+#[tokio::test]
+async fn final_only_destination_receives_only_ended_review() {
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let regular_destination = Arc::new(PathDescriptor::local("/home/regular/"));
+    let final_only_destination = Arc::new(PathDescriptor::local("/home/archive/"));
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        keep_alternative_versions: false,
+        final_only_destinations: vec![final_only_destination.clone()],
+        ..SyncSystemConfig::default()
+    });
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![regular_destination.clone()]),
+    };
+
+    let regular_file_sender = make_inmemory_filesystem();
+    let final_only_file_sender = make_inmemory_filesystem();
+
+    let file_sender_maker = {
+        let regular_file_sender = regular_file_sender.clone();
+        let final_only_file_sender = final_only_file_sender.clone();
+        let final_only_destination = final_only_destination.clone();
+        Arc::new(move |pd: &Arc<PathDescriptor>| {
+            if *pd == final_only_destination {
+                Ok(final_only_file_sender.clone())
+            } else {
+                Ok(regular_file_sender.clone())
+            }
+        })
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    for (type_field, end_time) in [
+        (payload::TypeField::New, None),
+        (payload::TypeField::Update, None),
+        (payload::TypeField::End, Some(1000.)),
+    ] {
+        let review = TestReviewData {
+            camera_name: "MyCamera".to_string(),
+            start_time: 950.,
+            end_time,
+            id: "id-abcdefg".to_string(),
+            type_field,
+            detections: Vec::new(),
+        };
+
+        let mut review_upload = ReviewUpload::new(
+            Arc::new(review),
+            false,
+            frigate_config.clone(),
+            frigate_api_maker.clone(),
+            file_sender_maker.clone(),
+            path_descriptors.clone(),
+            sync_config.clone(),
+            TimeGetter::new(Arc::new(FixedTimeGetter(Time::from_secs_since_epoch(1100)))),
+            fixed_backoff(std::time::Duration::from_millis(500)),
+        );
+
+        review_upload.start().await.unwrap();
+
+        assert_eq!(
+            regular_file_sender.ls(Path::new(".")).await.unwrap().len(),
+            1
+        );
+
+        let final_only_dirs = final_only_file_sender.ls(Path::new(".")).await.unwrap();
+        if type_field == payload::TypeField::End {
+            assert_eq!(final_only_dirs.len(), 1);
+            let files = final_only_file_sender
+                .ls(&Path::new(".").join(&final_only_dirs[0]))
+                .await
+                .unwrap();
+            assert_eq!(files.len(), 1);
+        } else {
+            assert!(final_only_dirs.is_empty());
+        }
+    }
+}
+
+/// A store whose uploads fail the given number of times before succeeding
+fn flaky_store_mock(
+    descriptor: &Arc<PathDescriptor>,
+    failure_count: u32,
+    upload_count: usize,
+) -> Arc<dyn StoreDestination<Error = anyhow::Error>> {
+    let attempts = std::sync::atomic::AtomicU32::new(0);
+
+    let mut store_mock = make_store_mock();
+    store_mock.expect_init().returning(|| Ok(()));
+    store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    store_mock
+        .expect_path_descriptor()
+        .return_const(descriptor.clone());
+    store_mock
+        .expect_put_from_memory()
+        .returning(move |_, _| {
+            if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < failure_count {
+                Err(anyhow::anyhow!("Connection reset"))
+            } else {
+                Ok(())
+            }
+        })
+        .times(upload_count);
+    Arc::new(store_mock)
+}
+
+#[tokio::test]
+#[rstest::rstest]
+// Retried within the same upload
+#[case(1, 1)]
+// Retried by the next run of the upload, after all the attempts of the first one failed
+#[case(MAX_UPLOAD_ATTEMPTS, 2)]
+async fn only_failed_destination_is_retried(#[case] failure_count: u32, #[case] run_count: u32) {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let healthy_destination = Arc::new(PathDescriptor::local("/home/healthy/"));
+    let flaky_destination = Arc::new(PathDescriptor::local("/home/flaky/"));
+
+    // The healthy destination gets the clip exactly once, however many times the flaky one is retried
+    let healthy_store = flaky_store_mock(&healthy_destination, 0, 1);
+    let flaky_store = flaky_store_mock(
+        &flaky_destination,
+        failure_count,
+        failure_count as usize + 1,
+    );
+
+    let file_sender_maker = {
+        let flaky_destination = flaky_destination.clone();
+        Arc::new(move |pd: &Arc<PathDescriptor>| {
+            if *pd == flaky_destination {
+                Ok(flaky_store.clone())
+            } else {
+                Ok(healthy_store.clone())
+            }
+        })
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::New,
+        detections: Vec::new(),
+    };
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review),
+        false,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![healthy_destination, flaky_destination]),
+        },
+        Arc::new(SyncSystemConfig {
+            keep_alternative_versions: false,
+            ..SyncSystemConfig::default()
+        }),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(10)),
+    );
+
+    for _ in 1..run_count {
+        review_upload.start().await.unwrap_err();
+    }
+    review_upload.start().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_destination_is_retried_with_backoff() {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let flaky_destination = Arc::new(PathDescriptor::local("/home/flaky/"));
+    let flaky_store = flaky_store_mock(&flaky_destination, 3, 4);
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(flaky_store.clone()));
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::New,
+        detections: Vec::new(),
+    };
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review),
+        false,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![flaky_destination]),
+        },
+        Arc::new(SyncSystemConfig {
+            keep_alternative_versions: false,
+            file_op_retry: RecordingRetry {
+                max_attempts: Some(4),
+                ..RecordingRetry::default()
+            },
+            ..SyncSystemConfig::default()
+        }),
+        TimeGetter::default(),
+        Backoff::new(
+            std::time::Duration::from_secs(1),
+            std::time::Duration::from_secs(30),
+        ),
+    );
+
+    let started = tokio::time::Instant::now();
+    review_upload.start().await.unwrap();
+
+    // The waits double from 1 second, each shortened by a jitter of up to half of it: (1 + 2 + 4) / 2 to 1 + 2 + 4
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed >= std::time::Duration::from_millis(3500),
+        "{elapsed:?}"
+    );
+    assert!(elapsed <= std::time::Duration::from_secs(7), "{elapsed:?}");
+}
+
+type RecordedSpans = Arc<std::sync::Mutex<Vec<(String, BTreeMap<String, String>)>>>;
+
+/// Records the name and fields of every span created while it's the active subscriber
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: RecordedSpans,
+}
+
+impl<S: tracing::Subscriber> Layer<S> for SpanRecorder {
+    // Interest in a callsite is cached globally, so being interested in events would make
+    // tests running concurrently on other threads evaluate the arguments of their log lines
+    fn register_callsite(
+        &self,
+        metadata: &'static tracing::Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        if metadata.is_span() {
+            tracing::subscriber::Interest::always()
+        } else {
+            tracing::subscriber::Interest::never()
+        }
+    }
+
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        _id: &tracing::span::Id,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        self.spans
+            .lock()
+            .unwrap()
+            .push((attrs.metadata().name().to_string(), fields.0));
+    }
+}
+
+#[derive(Default)]
+struct SpanFields(BTreeMap<String, String>);
+
+impl tracing::field::Visit for SpanFields {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+#[tokio::test]
+async fn upload_pipeline_spans_carry_review_id_and_camera() {
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(recorder.clone()));
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    review_upload.start().await.unwrap();
+
+    let spans = recorder.spans.lock().unwrap();
+    for span_name in ["review_upload", "recording_clip", "remote_file_op"] {
+        let (_, fields) = spans
+            .iter()
+            .find(|(name, _)| name == span_name)
+            .unwrap_or_else(|| panic!("No span named `{span_name}` was created"));
+        assert_eq!(fields.get("review_id").unwrap(), "id-abcdefg");
+        assert_eq!(fields.get("camera").unwrap(), "MyCamera");
+    }
+}
+
+#[rstest::rstest]
+#[case("front/door", FileNameCharset::Ascii, "RecordingClip-front_door-")]
+#[case("..", FileNameCharset::Ascii, "RecordingClip-__-")]
+#[case("back yard", FileNameCharset::Ascii, "RecordingClip-back_yard-")]
+#[case("../jardín", FileNameCharset::Ascii, "RecordingClip-___jard_n-")]
+#[case("../jardín", FileNameCharset::Unicode, "RecordingClip-___jardín-")]
+fn recording_file_name_of_hostile_camera_name_stays_in_upload_dir(
+    #[case] camera_name: &str,
+    #[case] charset: FileNameCharset,
+    #[case] expected_prefix: &str,
+) {
+    let review = TestReviewData {
+        camera_name: camera_name.to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    let path = recording_path(&review, None, charset, None);
+
+    // Only the date directory and the file itself
+    let components = path.components().collect::<Vec<_>>();
+    assert_eq!(components.len(), 2);
+    assert!(
+        components
+            .iter()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+    );
+
+    let file_name = path.file_name().unwrap().to_str().unwrap();
+    assert!(file_name.starts_with(expected_prefix));
+    assert!(!file_name.contains(' '));
+    assert!(!file_name.contains(".."));
+}
+
+#[rstest::rstest]
+#[case(None, &["<date>"])]
+#[case(Some(CameraDirOrder::CameraFirst), &["front_door", "<date>"])]
+#[case(Some(CameraDirOrder::DateFirst), &["<date>", "front_door"])]
+fn recordings_grouped_by_camera(
+    #[case] camera_dirs: Option<CameraDirOrder>,
+    #[case] expected_dirs: &[&str],
+) {
+    let review = TestReviewData {
+        camera_name: "front/door".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+    let date = Time::from_f64_secs_since_epoch(950.).as_local_time_in_dir_foramt();
+
+    let path = recording_path(&review, None, FileNameCharset::Ascii, camera_dirs);
+
+    let dirs = path
+        .parent()
+        .unwrap()
+        .iter()
+        .map(|c| c.to_str().unwrap())
+        .collect::<Vec<_>>();
+    let expected_dirs = expected_dirs
+        .iter()
+        .map(|d| if *d == "<date>" { date.as_str() } else { d })
+        .collect::<Vec<_>>();
+    assert_eq!(dirs, expected_dirs);
+}
+
+/// An in-memory store that reports every chunk it receives in `put_from_stream` as soon as it arrives
+struct StreamingSink {
+    inner: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    chunks_received: tokio::sync::mpsc::UnboundedSender<bytes::Bytes>,
+}
+
+#[async_trait::async_trait]
+impl StoreDestination for StreamingSink {
+    type Error = anyhow::Error;
+
+    async fn init(&self) -> anyhow::Result<()> {
+        self.inner.init().await
+    }
+
+    async fn ls(&self, path: &Path) -> anyhow::Result<Vec<std::path::PathBuf>> {
+        self.inner.ls(path).await
+    }
+
+    async fn del_file(&self, path: &Path) -> anyhow::Result<()> {
+        self.inner.del_file(path).await
+    }
+
+    async fn mkdir_p(&self, path: &Path) -> anyhow::Result<()> {
+        self.inner.mkdir_p(path).await
+    }
+
+    async fn put(&self, from: &Path, to: &Path) -> anyhow::Result<()> {
+        self.inner.put(from, to).await
+    }
+
+    async fn put_from_memory(&self, _from: &[u8], _to: &Path) -> anyhow::Result<()> {
+        panic!("The clip must be streamed to the store")
+    }
+
+    async fn put_from_stream(
+        &self,
+        mut from: file_sender::traits::ByteStream,
+        to: &Path,
+    ) -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        while let Some(chunk) = futures::StreamExt::next(&mut from).await {
+            let chunk = chunk?;
+            data.extend_from_slice(&chunk);
+            self.chunks_received.send(chunk).unwrap();
+        }
+        self.inner.put_from_memory(&data, to).await
+    }
+
+    async fn get_to_memory(&self, from: &Path) -> anyhow::Result<Vec<u8>> {
+        self.inner.get_to_memory(from).await
+    }
+
+    async fn dir_exists(&self, path: &Path) -> anyhow::Result<bool> {
+        self.inner.dir_exists(path).await
+    }
+
+    async fn file_exists(&self, path: &Path) -> anyhow::Result<bool> {
+        self.inner.file_exists(path).await
+    }
+
+    fn path_descriptor(&self) -> &Arc<PathDescriptor> {
+        self.inner.path_descriptor()
+    }
+}
+
+#[tokio::test]
+async fn streamed_clip_is_uploaded_while_downloading() {
+    const HEAD: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00";
+    const REST: &[u8] = b"Hello world!";
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    // The download only goes on when the test sends the next chunk
+    let (source_sender, source_receiver) =
+        futures::channel::mpsc::unbounded::<anyhow::Result<bytes::Bytes>>();
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip_stream()
+        .return_once(move |_, _, _| Ok(Some(futures::StreamExt::boxed(source_receiver))));
+    frigate_api_mock.expect_recording_clip().never();
+
+    let (chunks_sender, mut chunks_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let inner_store = make_inmemory_filesystem();
+    let sink: Arc<dyn StoreDestination<Error = anyhow::Error>> = Arc::new(StreamingSink {
+        inner: inner_store.clone(),
+        chunks_received: chunks_sender,
+    });
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(sink.clone()));
+
+    let sync_config = SyncSystemConfig {
+        stream_uploads: true,
+        keep_alternative_versions: false,
+        ..SyncSystemConfig::default()
+    };
+
+    let review = Arc::new(review);
+    let mut review_upload = ReviewUpload::new(
+        review.clone(),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(sync_config),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    let download = async move {
+        // Every chunk reaches the store before the next one is downloaded
+        for chunk in [HEAD, REST] {
+            source_sender
+                .unbounded_send(Ok(bytes::Bytes::from_static(chunk)))
+                .unwrap();
+            assert_eq!(chunks_receiver.recv().await.unwrap(), chunk);
+        }
+    };
+
+    let (upload_result, ()) = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        tokio::join!(review_upload.start(), download)
+    })
+    .await
+    .expect("The upload must not wait for the whole clip to be downloaded");
+    upload_result.unwrap();
+
+    let path = recording_path(review.as_ref(), None, FileNameCharset::default(), None);
+    assert_eq!(
+        inner_store.get_to_memory(&path).await.unwrap(),
+        [HEAD, REST].concat()
+    );
+}
+
+#[tokio::test]
+async fn streamed_clip_that_is_too_large_is_unrecoverable() {
+    const HEAD: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00";
+
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        max_clip_bytes: Some(16),
+        ..Default::default()
+    });
+
+    let review = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+        detections: Vec::new(),
+    };
+
+    // The limit is only found to be exceeded after the head of the clip has been streamed
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip_stream()
+        .return_once(move |_, _, _| {
+            let chunks: Vec<anyhow::Result<bytes::Bytes>> = vec![
+                Ok(bytes::Bytes::from_static(HEAD)),
+                Err(FrigateApiError::TooLarge("The clip is too large".to_string()).into()),
+            ];
+            Ok(Some(futures::StreamExt::boxed(futures::stream::iter(
+                chunks,
+            ))))
+        });
+    frigate_api_mock.expect_recording_clip().never();
+
+    let store = make_inmemory_filesystem();
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![store.path_descriptor().clone()]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(store.clone()));
+
+    let sync_config = SyncSystemConfig {
+        stream_uploads: true,
+        keep_alternative_versions: false,
+        ..SyncSystemConfig::default()
+    };
+
+    let mut review_upload = ReviewUpload::new(
+        Arc::new(review),
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(sync_config),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    let error = review_upload.start().await.unwrap_err();
+    assert!(
+        matches!(error, ReviewUploadError::UnrecoverableApiError(_)),
+        "{error:?}"
+    );
+}
+
+fn export_test_setup(
+    frigate_api_mock: mocks::frigate_api::MockFrigateApi,
+    export_timeout: std::time::Duration,
+    type_field: payload::TypeField,
+) -> (
+    ReviewUpload<impl FrigateApiMaker, impl FileSenderMaker>,
+    Arc<dyn StoreDestination<Error = anyhow::Error>>,
+    std::path::PathBuf,
+) {
+    let frigate_config = Arc::new(FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    });
+
+    let review = Arc::new(TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field,
+        detections: Vec::new(),
+    });
+    let path = recording_path(review.as_ref(), None, FileNameCharset::default(), None);
+
+    let store = make_inmemory_filesystem();
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![store.path_descriptor().clone()]),
+    };
+
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+    let file_sender_maker = {
+        let store = store.clone();
+        Arc::new(move |_: &Arc<PathDescriptor>| Ok(store.clone()))
+    };
+
+    let sync_config = SyncSystemConfig {
+        export_recording: true,
+        export_timeout,
+        keep_alternative_versions: false,
+        ..SyncSystemConfig::default()
+    };
+
+    let review_upload = ReviewUpload::new(
+        review,
+        false,
+        frigate_config,
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(sync_config),
+        TimeGetter::default(),
+        fixed_backoff(std::time::Duration::from_millis(500)),
+    );
+
+    (review_upload, store, path)
+}
+
+fn make_export(in_progress: bool) -> Export {
+    Export {
+        id: "export-1".to_string(),
+        camera: "MyCamera".to_string(),
+        video_path: "/media/frigate/exports/export-1.mp4".to_string(),
+        in_progress,
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn exported_clip_is_downloaded_once_export_is_done() {
+    const CLIP: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00Hello world!";
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    let mut seq = mockall::Sequence::new();
+    frigate_api_mock
+        .expect_start_export()
+        .withf(|camera, _, _| camera == "MyCamera")
+        .once()
+        .returning(|_, _, _| Ok("export-1".to_string()))
+        .in_sequence(&mut seq);
+    frigate_api_mock
+        .expect_export()
+        .withf(|id| id == "export-1")
+        .times(2)
+        .returning(|_| Ok(make_export(true)))
+        .in_sequence(&mut seq);
+    frigate_api_mock
+        .expect_export()
+        .once()
+        .returning(|_| Ok(make_export(false)))
+        .in_sequence(&mut seq);
+    frigate_api_mock
+        .expect_export_clip()
+        .withf(|export| *export == make_export(false))
+        .once()
+        .returning(|_| Ok(Some(CLIP.to_vec())))
+        .in_sequence(&mut seq);
+    frigate_api_mock
+        .expect_delete_export()
+        .withf(|id| id == "export-1")
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    frigate_api_mock.expect_recording_clip().never();
+    frigate_api_mock.expect_recording_clip_stream().never();
+
+    let (mut review_upload, store, path) = export_test_setup(
+        frigate_api_mock,
+        std::time::Duration::from_secs(60),
+        payload::TypeField::End,
+    );
+
+    let started = tokio::time::Instant::now();
+    review_upload.start().await.unwrap();
+
+    // Polled after 1 and 1+2 seconds
+    assert_eq!(started.elapsed(), std::time::Duration::from_secs(3));
+    assert_eq!(store.get_to_memory(&path).await.unwrap(), CLIP);
+}
+
+#[tokio::test(start_paused = true)]
+async fn export_not_done_in_time_is_requested_again() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    let mut seq = mockall::Sequence::new();
+    for export_id in ["export-1", "export-2"] {
+        frigate_api_mock
+            .expect_start_export()
+            .once()
+            .returning(move |_, _, _| Ok(export_id.to_string()))
+            .in_sequence(&mut seq);
+        // With a timeout of 5 seconds, it's checked after 0, 1 and 3 seconds, but not after 7
+        frigate_api_mock
+            .expect_export()
+            .withf(move |id| id == export_id)
+            .times(3)
+            .returning(|_| Ok(make_export(true)))
+            .in_sequence(&mut seq);
+        frigate_api_mock
+            .expect_delete_export()
+            .withf(move |id| id == export_id)
+            .once()
+            .returning(|_| Ok(()))
+            .in_sequence(&mut seq);
+    }
+    frigate_api_mock.expect_export_clip().never();
+
+    let (mut review_upload, store, path) = export_test_setup(
+        frigate_api_mock,
+        std::time::Duration::from_secs(5),
+        payload::TypeField::End,
+    );
+
+    for _ in 0..2 {
+        let error = review_upload.start().await.unwrap_err();
+        assert_eq!(
+            error,
+            ReviewUploadError::ExportTimedOut("id-abcdefg".to_string())
+        );
+    }
+    assert!(!store.file_exists(&path).await.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn export_that_fails_to_download_is_deleted_and_requested_again() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    let mut seq = mockall::Sequence::new();
+    for export_id in ["export-1", "export-2"] {
+        frigate_api_mock
+            .expect_start_export()
+            .once()
+            .returning(move |_, _, _| Ok(export_id.to_string()))
+            .in_sequence(&mut seq);
+        frigate_api_mock
+            .expect_export()
+            .withf(move |id| id == export_id)
+            .once()
+            .returning(|_| Ok(make_export(false)))
+            .in_sequence(&mut seq);
+        frigate_api_mock
+            .expect_export_clip()
+            .once()
+            .returning(|_| Err(FrigateApiError::Network("Connection reset".to_string())))
+            .in_sequence(&mut seq);
+        frigate_api_mock
+            .expect_delete_export()
+            .withf(move |id| id == export_id)
+            .once()
+            .returning(|_| Ok(()))
+            .in_sequence(&mut seq);
+    }
+
+    let (mut review_upload, store, path) = export_test_setup(
+        frigate_api_mock,
+        std::time::Duration::from_secs(60),
+        payload::TypeField::End,
+    );
+
+    for _ in 0..2 {
+        let error = review_upload.start().await.unwrap_err();
+        assert!(
+            matches!(error, ReviewUploadError::ClipRetrievalError(_)),
+            "{error:?}"
+        );
+    }
+    assert!(!store.file_exists(&path).await.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn abandoned_export_is_deleted() {
+    let deleted = Arc::new(tokio::sync::Notify::new());
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_start_export()
+        .once()
+        .returning(|_, _, _| Ok("export-1".to_string()));
+    frigate_api_mock
+        .expect_export()
+        .once()
+        .returning(|_| Err(FrigateApiError::Network("Connection reset".to_string())));
+    frigate_api_mock.expect_export_clip().never();
+    frigate_api_mock
+        .expect_delete_export()
+        .withf(|id| id == "export-1")
+        .once()
+        .returning({
+            let deleted = deleted.clone();
+            move |_| {
+                deleted.notify_one();
+                Ok(())
+            }
+        });
+
+    let (mut review_upload, _store, _path) = export_test_setup(
+        frigate_api_mock,
+        std::time::Duration::from_secs(60),
+        payload::TypeField::End,
+    );
+
+    // E.g., it's replaced by the upload of a newer update of the review while the export is awaited
+    review_upload.start().await.unwrap_err();
+    drop(review_upload);
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), deleted.notified())
+        .await
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn clip_of_review_that_hasnt_ended_isnt_exported() {
+    const CLIP: &[u8] = b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00Hello world!";
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock.expect_start_export().never();
+    frigate_api_mock
+        .expect_recording_clip()
+        .once()
+        .returning(|_, _, _| Ok(Some(CLIP.to_vec())));
+
+    let (mut review_upload, store, path) = export_test_setup(
+        frigate_api_mock,
+        std::time::Duration::from_secs(60),
+        payload::TypeField::Update,
+    );
+
+    review_upload.start().await.unwrap();
+    assert_eq!(store.get_to_memory(&path).await.unwrap(), CLIP);
+}
@@ -0,0 +1,147 @@
+use crate::system::{
+    common::{
+        file_senders::{make_file_senders, split_file_senders_and_descriptors},
+        file_upload::UploadableFile,
+        naming::NamingStrategy,
+    },
+    traits::FileSenderMaker,
+};
+use file_sender::path_descriptor::PathDescriptor;
+use mqtt_handler::types::reviews::ReviewProps;
+use std::{path::PathBuf, sync::Arc};
+
+/// Takes the place of the clip's extension, so that the marker is named after the clip
+const UPLOAD_MARKER_EXTENSION: &str = "uploaded.json";
+
+/// Which upload of a review is complete. A clip at the review's path can be one of an ongoing review,
+/// or of an earlier end time, so only a marker of the same review and end time tells that it's complete.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct UploadMarkerContent {
+    review_id: String,
+    end_time: f64,
+    clip_path: PathBuf,
+}
+
+impl UploadMarkerContent {
+    fn matches(&self, review: &dyn ReviewProps) -> bool {
+        self.review_id == review.id()
+            && review
+                .end_time()
+                .is_some_and(|end_time| end_time.to_bits() == self.end_time.to_bits())
+    }
+}
+
+/// Uploaded next to the clip of a review that has ended once the clip is uploaded,
+/// so that uploading the review again, e.g., when its message is replayed, can be skipped
+pub struct UploadMarker {
+    review: Arc<dyn ReviewProps>,
+    content: Vec<u8>,
+    naming: Arc<dyn NamingStrategy>,
+}
+
+impl UploadMarker {
+    /// None if the review hasn't ended, since only the clip of its end is complete
+    pub fn new(
+        review: Arc<dyn ReviewProps>,
+        clip_path: PathBuf,
+        naming: Arc<dyn NamingStrategy>,
+    ) -> Option<Self> {
+        let content = UploadMarkerContent {
+            review_id: review.id().to_string(),
+            end_time: review.end_time()?,
+            clip_path,
+        };
+        let content = serde_json::to_vec(&content).expect("Serializing the marker cannot fail");
+
+        Some(Self {
+            review,
+            content,
+            naming,
+        })
+    }
+}
+
+impl UploadableFile for UploadMarker {
+    fn file_bytes(&self) -> &[u8] {
+        &self.content
+    }
+
+    fn full_upload_path(&self) -> PathBuf {
+        marker_path(self.naming.as_ref(), self.review.as_ref())
+    }
+
+    fn file_description(&self) -> String {
+        format!("Upload marker of review with id {}", self.review.id())
+    }
+
+    fn camera_name(&self) -> &str {
+        self.review.camera_name()
+    }
+
+    fn source_id(&self) -> Option<String> {
+        Some(self.review.id().to_string())
+    }
+}
+
+fn marker_path(naming: &dyn NamingStrategy, review: &dyn ReviewProps) -> PathBuf {
+    naming.recording_path(review, None, UPLOAD_MARKER_EXTENSION)
+}
+
+/// Returns the path of the clip of the review if every destination has a marker of the review with
+/// the same end time, and the clip it names. A destination that cannot be reached, or fails the check,
+/// counts as not having the upload.
+pub async fn find_completed_upload<S: FileSenderMaker>(
+    review: &dyn ReviewProps,
+    naming: &dyn NamingStrategy,
+    path_descriptors: &[Arc<PathDescriptor>],
+    file_sender_maker: &Arc<S>,
+) -> Option<PathBuf> {
+    let file_senders = make_file_senders(file_sender_maker, path_descriptors).await;
+    let (file_senders, unreachable_descriptors) = split_file_senders_and_descriptors(file_senders);
+
+    if !unreachable_descriptors.is_empty() || file_senders.is_empty() {
+        return None;
+    }
+
+    let path = marker_path(naming, review);
+    let mut clip_path = None;
+    for s in &file_senders {
+        let check = async {
+            if !s.file_exists(&path).await? {
+                return Ok(None);
+            }
+
+            let content = s.get_to_memory(&path).await?;
+            let content = match serde_json::from_slice::<UploadMarkerContent>(&content) {
+                Ok(content) if content.matches(review) => content,
+                Ok(_) => return Ok(None),
+                Err(e) => {
+                    tracing::warn!(
+                        "Ignoring malformed upload marker `{}` in `{}`. Error: {e}",
+                        path.display(),
+                        s.path_descriptor()
+                    );
+                    return Ok(None);
+                }
+            };
+
+            let exists = s.file_exists(&content.clip_path).await?;
+            anyhow::Ok(exists.then_some(content.clip_path))
+        };
+
+        match check.await {
+            Ok(Some(path)) => clip_path = Some(path),
+            Ok(None) => return None,
+            Err(e) => {
+                tracing::error!(
+                    "Error checking whether review with id `{}` is uploaded to `{}`. Error: {e}",
+                    review.id(),
+                    s.path_descriptor()
+                );
+                return None;
+            }
+        }
+    }
+
+    clip_path
+}
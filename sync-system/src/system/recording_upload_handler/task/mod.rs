@@ -2,22 +2,39 @@ mod file_upload;
 
 use crate::{
     config::PathDescriptors,
-    system::traits::{FileSenderMaker, FrigateApiMaker},
+    system::{
+        common::{dead_letter::record_dead_letter, retry::retry_delay},
+        config::SyncSystemConfig,
+        traits::{FileSenderMaker, FrigateApiMaker},
+    },
 };
-use file_upload::ReviewUpload;
-use frigate_api_caller::config::FrigateApiConfig;
+use file_upload::{ReviewUpload, ReviewUploadError, file_op_backoff};
+
+pub use file_upload::upload_review_montage;
+use frigate_api_caller::{config::FrigateApiConfig, discard_partial_download};
 use mqtt_handler::types::reviews::{self, ReviewProps};
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tokio::sync::oneshot;
 use utils::time_getter::TimeGetter;
 
 const DEFAULT_RETRY_PERIOD: std::time::Duration = std::time::Duration::from_secs(60);
+const DEFAULT_MAX_RETRY_PERIOD: std::time::Duration = std::time::Duration::from_secs(30 * 60);
 const DEFAULT_MAX_RETRY_ATTEMPTS: u32 = 60;
-const DEFAULT_UPLOAD_RETRY_SLEEP_ON_ERROR: std::time::Duration = std::time::Duration::from_secs(1);
 
 type ReviewsReceiver =
     tokio::sync::mpsc::UnboundedReceiver<(Arc<dyn ReviewProps>, Option<oneshot::Sender<()>>)>;
 
+/// A review to process, with the senders to confirm to once it's processed
+type ReviewToProcess = (Arc<dyn ReviewProps>, Vec<oneshot::Sender<()>>);
+
+/// An update of the review that waits for the debounce interval to pass before it's processed.
+/// Updates received meanwhile replace its review, and are confirmed once it's processed.
+struct DebouncedUpdate {
+    review: Arc<dyn ReviewProps>,
+    result_senders: Vec<oneshot::Sender<()>>,
+    run_at: tokio::time::Instant,
+}
+
 /// A struct that tracks the updates of a single review, and keeps uploading until
 /// the review type "end" has been reached, or a deadline is hit.
 /// On every update, the upload will trigger again.
@@ -43,6 +60,7 @@ pub struct SingleRecordingUploadTask<F, S> {
     file_sender_maker: Arc<S>,
 
     path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
 
     /// The upload that is currently running (or will be run to retry or done, so running it will lead to no-op).
     /// This can be replaced by a new object when an update is received.
@@ -53,8 +71,31 @@ pub struct SingleRecordingUploadTask<F, S> {
 
     retry_attempt: u32,
     max_retry_attempts: u32,
+    /// The error of the last run of the upload, if it failed rather than uploaded a clip of a review that hasn't
+    /// ended yet, so that only reviews that failed are recorded as dead letters, with the reason
+    last_upload_error: Option<String>,
 
+    /// The wait before the first retry, which doubles with every retry until it reaches `max_retry_duration`
     retry_duration: std::time::Duration,
+    max_retry_duration: std::time::Duration,
+
+    /// When the last review was processed, from which the next update is debounced
+    last_review_processed_at: tokio::time::Instant,
+    debounced_update: Option<DebouncedUpdate>,
+
+    /// When the last review was received, from which the review is idle if `review_idle_timeout` is set
+    last_review_received_at: tokio::time::Instant,
+    /// Set once the review has been idle, or the task reached its lifetime,
+    /// after which a successful upload concludes it like the end of the review
+    conclude_on_success: bool,
+    /// After this, the task is concluded with whatever clip it has, if `max_task_lifetime` is set
+    deadline: Option<tokio::time::Instant>,
+    /// Set once the end of the review is uploaded, if `post_end_grace` is set.
+    /// The task is concluded at this instant, unless a late update of the review arrives before it.
+    end_grace_until: Option<tokio::time::Instant>,
+    /// Set while the upload window is closed, to when it opens. The upload is deferred until then,
+    /// while updates of the review, and the other timers, are still handled.
+    upload_window_opens_at: Option<tokio::time::Instant>,
 
     time_getter: TimeGetter,
 }
@@ -75,10 +116,17 @@ where
         frigate_api_maker: Arc<F>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
         max_retry_attempts: Option<u32>,
         retry_period: Option<std::time::Duration>,
+        max_retry_period: Option<std::time::Duration>,
         time_getter: TimeGetter,
     ) -> Self {
+        let retry_duration = retry_period.unwrap_or(DEFAULT_RETRY_PERIOD);
+        let deadline = sync_config
+            .max_task_lifetime
+            .map(|lifetime| tokio::time::Instant::now() + lifetime);
+
         Self {
             current_review: start_review, // The current one is the start one
             first_review_resolved_sender: Some(first_review_resolved_sender),
@@ -90,6 +138,7 @@ where
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            sync_config,
 
             alternative_upload: false,
 
@@ -97,57 +146,120 @@ where
 
             retry_attempt: 0,
             max_retry_attempts: max_retry_attempts.unwrap_or(DEFAULT_MAX_RETRY_ATTEMPTS),
+            last_upload_error: None,
+
+            retry_duration,
+            // The max can't be lower than the base
+            max_retry_duration: max_retry_period
+                .unwrap_or(DEFAULT_MAX_RETRY_PERIOD)
+                .max(retry_duration),
+
+            last_review_processed_at: tokio::time::Instant::now(),
+            debounced_update: None,
 
-            retry_duration: retry_period.unwrap_or(DEFAULT_RETRY_PERIOD),
+            last_review_received_at: tokio::time::Instant::now(),
+            conclude_on_success: false,
+            deadline,
+            end_grace_until: None,
+            upload_window_opens_at: None,
 
             time_getter,
         }
     }
 
-    pub async fn start(mut self) -> String {
+    pub async fn start(mut self) -> RecordingTaskResult {
         let id = self.current_review.id().to_string();
 
         tracing::debug!("Launched recoding upload task for review with id: {id}");
 
         // We have the initial review, so we use it
-        let _ = self.on_received_review(self.current_review.clone()).await;
+        let mut final_result = self.on_received_review(self.current_review.clone()).await;
         self.first_review_resolved_sender
             .take()
             .expect("Since this is running once, it must exist")
             .send(())
             .expect("The channel must exist");
 
-        let mut final_result = UploadConclusion::NotDone;
-
         loop {
-            let retry_instant = tokio::time::Instant::now() + self.retry_duration;
+            if final_result == UploadConclusion::Unrecoverable {
+                tracing::error!(
+                    "Upload cancelled for review recording with id `{id}`, as it can never succeed."
+                );
+                if let Some(dead_letter_path) = &self.sync_config.dead_letter_path {
+                    let reason = self.last_upload_error.as_deref().unwrap_or("Unrecoverable");
+                    record_dead_letter(dead_letter_path, &id, reason).await;
+                }
+                break;
+            }
+            if self.is_concluded(final_result) {
+                break;
+            }
+
+            let retry_delay = retry_delay(
+                self.retry_duration,
+                self.max_retry_duration,
+                self.retry_attempt,
+                &mut randomness::make_pseudo_rng(),
+            );
+            let retry_instant = self.retry_instant(retry_delay);
+            let debounce_instant = self.debounced_update.as_ref().map(|u| u.run_at);
+            let idle_instant = self.idle_instant();
 
             tokio::select! {
                 Some((review, result_sender)) = self.reviews_receiver.recv() => {
-                    // After having received a new review, we reset the retries
-                    self.reset_retry_attempts();
+                    self.last_review_received_at = tokio::time::Instant::now();
 
-                    final_result = self.on_received_review(review).await;
+                    let Some((review, result_senders)) = self.debounce(review, result_sender) else {
+                        continue;
+                    };
 
-                    if let Some(sender) = result_sender {
-                        if sender.send(()).is_err() {
-                            tracing::error!("CRITICAL: Signal that confirms the result of uploading a recording is dead.
-                                This can indicate a race and bad programming. Should never happen.");
-                        }
+                    final_result = self.on_received_review_update(review, result_senders).await;
+                    if self.is_concluded(final_result) {
+                        break;
                     }
+                }
 
-                    match final_result {
-                        UploadConclusion::Done => break,
-                        UploadConclusion::NotDone => self.increment_retry_attempts(),
-                    };
+                () = sleep_until_if_some(debounce_instant) => {
+                    let update = self
+                        .debounced_update
+                        .take()
+                        .expect("The debounce timer only runs with an update");
+                    tracing::debug!("Processing the latest debounced update of review with id `{id}`");
+
+                    final_result = self.on_received_review_update(update.review, update.result_senders).await;
+                    if self.is_concluded(final_result) {
+                        break;
+                    }
+                }
+
+                () = sleep_until_if_some(idle_instant) => {
+                    final_result = self.conclude_when_idle().await;
+                    if self.is_concluded(final_result) {
+                        break;
+                    }
+                }
 
+                () = sleep_until_if_some(self.deadline) => {
+                    final_result = self.conclude_at_deadline().await;
+                    break;
                 }
 
-                () = tokio::time::sleep_until(retry_instant) => {
+                () = sleep_until_if_some(self.end_grace_until) => {
+                    tracing::debug!("No update of review with id `{id}` was received after its end. Concluding its upload.");
+                    break;
+                }
+
+                () = sleep_until_if_some(self.upload_window_opens_at) => {
+                    tracing::debug!("The upload window opened. Uploading recording of review with id `{id}`");
+                    final_result = self.run_upload().await;
+                    if self.is_concluded(final_result) {
+                        break;
+                    }
+                }
+
+                () = sleep_until_if_some(retry_instant) => {
                     if self.retry_attempt >= self.max_retry_attempts {
-                        tracing::error!(
-                            "Upload cancelled for review recording with id `{id}` after having retried {} times.", self.retry_attempt
-                        );
+                        self.give_up(&id).await;
                         break;
                     }
 
@@ -156,17 +268,29 @@ where
 
                     // Note that running upload again doesn't necessarily mean it will re-upload. If the file hasn't been uploaded,
                     // it will try again. But if it's successfully done, it will just be a No-Op.
-                    tracing::debug!("Re-running upload recording with id `{id}` after having waited: {}. If no review update has been received, this will be a no-op.", humantime::format_duration(self.retry_duration));
+                    tracing::debug!("Re-running upload recording with id `{id}` after having waited: {}. If no review update has been received, this will be a no-op.", humantime::format_duration(retry_delay));
                     final_result = self.run_upload().await;
 
-                    match final_result {
-                        UploadConclusion::Done => break,
-                        UploadConclusion::NotDone => (),
+                    if self.is_concluded(final_result) {
+                        break;
                     }
                 }
             }
         }
 
+        // Whatever the conclusion, the clips of the review aren't downloaded anymore
+        if self.frigate_api_config.resume_downloads {
+            discard_partial_download(
+                self.current_review.camera_name(),
+                self.current_review.start_time(),
+            )
+            .await;
+        }
+
+        self.finish(id, final_result)
+    }
+
+    fn finish(self, id: String, final_result: UploadConclusion) -> RecordingTaskResult {
         if let Some(sender) = self.end_review_resolved_sender {
             if sender.send(final_result).is_err() {
                 tracing::error!(
@@ -175,7 +299,170 @@ where
             }
         }
 
-        id
+        RecordingTaskResult {
+            id,
+            camera_name: self.current_review.camera_name().to_string(),
+            conclusion: final_result,
+            uploaded_path: self
+                .current_upload_process
+                .as_ref()
+                .and_then(|p| p.uploaded_path().map(ToOwned::to_owned)),
+        }
+    }
+
+    /// Whether the task is done with the result of its last upload. Once the end of the review is uploaded,
+    /// the task is only done after `post_end_grace`, if set, so that late updates of the review are still uploaded.
+    fn is_concluded(&mut self, result: UploadConclusion) -> bool {
+        if result != UploadConclusion::Done || self.end_grace_until.is_some() {
+            return false;
+        }
+        // The idle review and the deadline conclude the task without a grace
+        if self.conclude_on_success {
+            return true;
+        }
+        let Some(grace) = self.sync_config.post_end_grace else {
+            return true;
+        };
+
+        tracing::debug!(
+            "Review with id `{}` ended. Waiting {} for late updates before concluding its upload.",
+            self.current_review.id(),
+            humantime::format_duration(grace)
+        );
+        self.end_grace_until = Some(tokio::time::Instant::now() + grace);
+        false
+    }
+
+    /// Once the review has been idle, the upload is concluded as soon as its clip is uploaded,
+    /// without waiting for the end of the review
+    async fn conclude_when_idle(&mut self) -> UploadConclusion {
+        tracing::info!(
+            "No update of review with id `{}` was received for {}. Concluding its upload once its clip is uploaded, without its end.",
+            self.current_review.id(),
+            humantime::format_duration(self.sync_config.review_idle_timeout.unwrap_or_default())
+        );
+        self.conclude_on_success = true;
+
+        // A no-op if the latest clip is already uploaded
+        self.run_upload().await
+    }
+
+    /// Makes a last attempt at uploading the clip once the task reached its maximum lifetime,
+    /// after which the task ends whatever the result is
+    async fn conclude_at_deadline(&mut self) -> UploadConclusion {
+        let id = self.current_review.id().to_string();
+        tracing::warn!(
+            "Upload task of review with id `{id}` reached its maximum lifetime of {}. Concluding it with the clip it has.",
+            humantime::format_duration(self.sync_config.max_task_lifetime.unwrap_or_default())
+        );
+        self.conclude_on_success = true;
+
+        // A no-op if the latest clip is already uploaded
+        let result = self.run_upload().await;
+        if result == UploadConclusion::NotDone {
+            tracing::error!(
+                "Upload cancelled for review recording with id `{id}`, as its task reached its maximum lifetime."
+            );
+            if let Some(dead_letter_path) = &self.sync_config.dead_letter_path {
+                record_dead_letter(
+                    dead_letter_path,
+                    &id,
+                    "The task reached its maximum lifetime",
+                )
+                .await;
+            }
+        }
+        result
+    }
+
+    /// Returns the review to process now, with the senders to confirm it with once it's processed.
+    /// Updates that arrive within the debounce interval of the last processed review are held
+    /// instead, and only the latest of them is processed once the interval passes.
+    /// Other reviews, like the end of the review, are never held, and replace a held update.
+    fn debounce(
+        &mut self,
+        review: Arc<dyn ReviewProps>,
+        result_sender: Option<oneshot::Sender<()>>,
+    ) -> Option<ReviewToProcess> {
+        let mut result_senders = self
+            .debounced_update
+            .take()
+            .map(|u| u.result_senders)
+            .unwrap_or_default();
+        result_senders.extend(result_sender);
+
+        let run_at = self.last_review_processed_at + self.sync_config.review_update_debounce;
+        // A late update after the end of the review isn't held, as the task is waiting to conclude
+        if review.type_field() != reviews::payload::TypeField::Update
+            || run_at <= tokio::time::Instant::now()
+            || self.end_grace_until.is_some()
+        {
+            return Some((review, result_senders));
+        }
+
+        tracing::trace!(
+            "Holding update of review with id `{}`, as the review was processed within the debounce interval",
+            review.id()
+        );
+        self.debounced_update = Some(DebouncedUpdate {
+            review,
+            result_senders,
+            run_at,
+        });
+        None
+    }
+
+    /// Processes a review received after the first one, and confirms it to the senders
+    async fn on_received_review_update(
+        &mut self,
+        review: Arc<dyn ReviewProps>,
+        result_senders: Vec<oneshot::Sender<()>>,
+    ) -> UploadConclusion {
+        // After having received a new review, we reset the retries
+        self.reset_retry_attempts();
+
+        let result = self.on_received_review(review).await;
+
+        for sender in result_senders {
+            if sender.send(()).is_err() {
+                tracing::error!(
+                    "CRITICAL: Signal that confirms the result of uploading a recording is dead.
+                    This can indicate a race and bad programming. Should never happen."
+                );
+            }
+        }
+
+        // Waiting for the upload window isn't a failed attempt
+        if result == UploadConclusion::NotDone && self.upload_window_opens_at.is_none() {
+            self.increment_retry_attempts();
+        }
+
+        result
+    }
+
+    /// When the review becomes idle, if it can, and hasn't yet
+    fn idle_instant(&self) -> Option<tokio::time::Instant> {
+        if self.conclude_on_success || self.end_grace_until.is_some() {
+            return None;
+        }
+        self.sync_config
+            .review_idle_timeout
+            .map(|timeout| self.last_review_received_at + timeout)
+    }
+
+    /// When the upload is retried, unless it's waiting for late updates after the end of the review,
+    /// where there's nothing left to retry, or for the upload window to open
+    fn retry_instant(&self, retry_delay: std::time::Duration) -> Option<tokio::time::Instant> {
+        (self.end_grace_until.is_none() && self.upload_window_opens_at.is_none())
+            .then(|| tokio::time::Instant::now() + retry_delay)
+    }
+
+    /// How long until the configured upload window opens, if it's closed
+    fn time_until_upload_window(&self) -> Option<std::time::Duration> {
+        self.sync_config
+            .upload_window
+            .map(|window| window.time_until_open(self.time_getter.get_time()))
+            .filter(|wait| !wait.is_zero())
     }
 
     fn increment_retry_attempts(&mut self) {
@@ -188,6 +475,9 @@ where
 
     pub async fn on_received_review(&mut self, review: Arc<dyn ReviewProps>) -> UploadConclusion {
         self.current_review = review.clone();
+        self.last_review_processed_at = tokio::time::Instant::now();
+        self.conclude_on_success = false;
+        self.end_grace_until = None;
 
         let new_upload_process = ReviewUpload::new(
             review,
@@ -196,8 +486,9 @@ where
             self.frigate_api_maker.clone(),
             self.file_sender_maker.clone(),
             self.path_descriptors.clone(),
+            self.sync_config.clone(),
             self.time_getter.clone(),
-            DEFAULT_UPLOAD_RETRY_SLEEP_ON_ERROR,
+            file_op_backoff(&self.sync_config.file_op_retry),
         );
 
         // Previous upload attempts will be be cancelled if a new recording has arrived.
@@ -208,7 +499,43 @@ where
         self.run_upload().await
     }
 
+    /// Called once the retries are exhausted. Only a review whose upload failed is recorded as a dead letter.
+    async fn give_up(&self, id: &str) {
+        if let Some(error) = &self.last_upload_error {
+            tracing::error!(
+                "Upload cancelled for review recording with id `{id}` after having retried {} times.",
+                self.retry_attempt
+            );
+            if let Some(dead_letter_path) = &self.sync_config.dead_letter_path {
+                let reason = format!("Retried {} times. Last error: {error}", self.retry_attempt);
+                record_dead_letter(dead_letter_path, id, &reason).await;
+            }
+        } else {
+            tracing::warn!(
+                "Stopped waiting for the end of review with id `{id}` after {} retries. Its last clip is uploaded.",
+                self.retry_attempt
+            );
+        }
+    }
+
     async fn run_upload(&mut self) -> UploadConclusion {
+        // The upload runs once the window opens, with the latest review received by then
+        if let Some(wait) = self.time_until_upload_window() {
+            // The clip is downloaded now, so that it doesn't leave Frigate's recording retention while waiting
+            if let Some(conclusion) = self.download_before_upload_window().await {
+                return conclusion;
+            }
+            tracing::info!(
+                "Outside the upload window. Deferring the upload of review with id `{}` for {}",
+                self.current_review.id(),
+                humantime::format_duration(wait)
+            );
+            self.upload_window_opens_at = Some(tokio::time::Instant::now() + wait);
+            self.last_upload_error = None;
+            return UploadConclusion::NotDone;
+        }
+        self.upload_window_opens_at = None;
+
         let Some(current_upload_process) = self.current_upload_process.as_mut() else {
             // Once an upload has been initiated, this can never be None again
             tracing::error!("CRITICAL: INVARIANT BROKEN: Current upload process is empty");
@@ -216,24 +543,77 @@ where
         };
 
         let result = current_upload_process.start().await;
+        self.last_upload_error = result.as_ref().err().map(ToString::to_string);
 
         match result {
             Ok(()) => {
                 // When an upload is successful, the next upload will go to the alternative file name
                 self.alternative_upload = !self.alternative_upload;
 
-                if self.current_review.type_field() == reviews::payload::TypeField::End {
+                if self.conclude_on_success
+                    || self.current_review.type_field() == reviews::payload::TypeField::End
+                {
                     UploadConclusion::Done
                 } else {
                     UploadConclusion::NotDone
                 }
             }
+            Err(
+                e @ (ReviewUploadError::OutsideRetention(_)
+                | ReviewUploadError::UnrecoverableApiError(_)),
+            ) => {
+                tracing::error!("Recording upload finished with error: {}", e);
+                UploadConclusion::Unrecoverable
+            }
             Err(e) => {
                 tracing::error!("Recording upload finished with error: {}", e);
                 UploadConclusion::NotDone
             }
         }
     }
+
+    /// Returns the conclusion if the download failed in a way that retrying can't fix.
+    /// Other failures are retried once the upload window opens.
+    async fn download_before_upload_window(&mut self) -> Option<UploadConclusion> {
+        let current_upload_process = self.current_upload_process.as_mut()?;
+
+        match current_upload_process.download().await {
+            Ok(()) => None,
+            Err(
+                e @ (ReviewUploadError::OutsideRetention(_)
+                | ReviewUploadError::UnrecoverableApiError(_)),
+            ) => {
+                tracing::error!("Recording download finished with error: {}", e);
+                self.last_upload_error = Some(e.to_string());
+                Some(UploadConclusion::Unrecoverable)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Downloading the recording of review with id `{}` before the upload window failed. It's retried once the window opens. Error: {e}",
+                    self.current_review.id()
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Never finishes if there's no instant to sleep until
+async fn sleep_until_if_some(instant: Option<tokio::time::Instant>) {
+    match instant {
+        Some(instant) => tokio::time::sleep_until(instant).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// What a finished recording upload task returns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingTaskResult {
+    pub id: String,
+    pub camera_name: String,
+    pub conclusion: UploadConclusion,
+    /// Where the clip was last uploaded to, if it has been
+    pub uploaded_path: Option<PathBuf>,
 }
 
 /// The result of uploading a single recording file
@@ -242,6 +622,8 @@ where
 pub enum UploadConclusion {
     NotDone,
     Done,
+    /// The upload failed in a way that retrying can't fix
+    Unrecoverable,
 }
 
 #[cfg(test)]
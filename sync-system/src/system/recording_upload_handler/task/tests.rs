@@ -3,7 +3,7 @@ use crate::system::recording_upload_handler::task::file_upload::MAX_UPLOAD_ATTEM
 use file_sender::{
     make_inmemory_filesystem, path_descriptor::PathDescriptor, traits::StoreDestination,
 };
-use frigate_api_caller::traits::FrigateApi;
+use frigate_api_caller::{FrigateApiError, traits::FrigateApi};
 use mocks::{frigate_api::make_frigate_client_mock, store_dest::make_store_mock};
 use mqtt_handler::types::reviews::payload;
 use rstest::rstest;
@@ -48,6 +48,18 @@ impl ReviewProps for TestReviewData {
     fn type_field(&self) -> payload::TypeField {
         self.type_field
     }
+
+    fn detections(&self) -> &[String] {
+        &[]
+    }
+
+    fn objects(&self) -> &[String] {
+        &[]
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        None
+    }
 }
 
 #[tokio::test]
@@ -58,8 +70,7 @@ async fn recording_upload(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let expected_file_content = Arc::new(Mutex::new(gen_random_bytes(&mut rng, 100..1000)));
@@ -106,8 +117,8 @@ async fn recording_upload(random_seed: Seed) {
             Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -120,8 +131,10 @@ async fn recording_upload(random_seed: Seed) {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             Some(3),
             Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
             TimeGetter::default(),
         );
         let task_handle = tokio::task::spawn(task.start());
@@ -283,8 +296,7 @@ async fn recording_upload_mocked(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let expected_file_content = Arc::new(Mutex::new(gen_random_bytes(&mut rng, 100..1000)));
@@ -353,8 +365,8 @@ async fn recording_upload_mocked(random_seed: Seed) {
         let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -367,8 +379,10 @@ async fn recording_upload_mocked(random_seed: Seed) {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             Some(3),
             Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
             TimeGetter::default(),
         );
         let task_handle = tokio::task::spawn(task.start());
@@ -450,8 +464,7 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let expected_file_content = Arc::new(Mutex::new(gen_random_bytes(&mut rng, 100..1000)));
@@ -471,8 +484,8 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
     frigate_api_mock
         .expect_recording_clip()
         .returning(move |_, _, _| {
-            Err(anyhow::anyhow!(
-                "Artificial error when retrieving the video"
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
             ))
         })
         .once()
@@ -480,8 +493,8 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
     frigate_api_mock
         .expect_recording_clip()
         .returning(move |_, _, _| {
-            Err(anyhow::anyhow!(
-                "Artificial error when retrieving the video"
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
             ))
         })
         .once()
@@ -516,13 +529,6 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
         .once()
         .in_sequence(&mut sequence);
 
-    // This comes from emitting the error
-    file_store_mock
-        .expect_path_descriptor()
-        .return_const(Arc::new(PathDescriptor::Local("<Fake>".to_string().into())))
-        .once()
-        .in_sequence(&mut sequence);
-
     // Second upload attempt succeeds
     file_store_mock
         .expect_init()
@@ -592,8 +598,8 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
         let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -606,8 +612,10 @@ async fn recording_upload_mocked_failures_then_success(random_seed: Seed) {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             Some(3),
             Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
             TimeGetter::default(),
         );
         let task_handle = tokio::task::spawn(task.start());
@@ -628,8 +636,7 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     // Prepare the file sender mock
@@ -645,8 +652,8 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
     frigate_api_mock
         .expect_recording_clip()
         .returning(move |_, _, _| {
-            Err(anyhow::anyhow!(
-                "Artificial error when retrieving the video"
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
             ))
         })
         .once()
@@ -656,8 +663,8 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
         frigate_api_mock
             .expect_recording_clip()
             .returning(move |_, _, _| {
-                Err(anyhow::anyhow!(
-                    "Artificial error when retrieving the video"
+                Err(FrigateApiError::Network(
+                    "Artificial error when retrieving the video".to_string(),
                 ))
             })
             .once()
@@ -689,8 +696,8 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
         let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -703,8 +710,10 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             Some(number_of_download_attempts),
             Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
             TimeGetter::default(),
         );
         let task_handle = tokio::task::spawn(task.start());
@@ -717,6 +726,366 @@ async fn recording_upload_mocked_failures_return_not_done(random_seed: Seed) {
     }
 }
 
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn recording_upload_exhausted_retries_recorded_in_dead_letter(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let file_store_mock = make_store_mock();
+
+    let number_of_retry_attempts = rng.random_range(1..3);
+
+    // The API always fails to give the file, for the first attempt and all retries
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| {
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
+            ))
+        })
+        .times(number_of_retry_attempts as usize + 1);
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let review_end = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    // We only send one review here, no need for sender
+    let _review_sender = review_sender;
+
+    let dead_letter_dir = tempfile::tempdir().unwrap();
+    let dead_letter_path = dead_letter_dir.path().join("dead-letter.txt");
+    // An existing entry must be kept
+    std::fs::write(&dead_letter_path, "id-from-before\n").unwrap();
+
+    let sync_config = SyncSystemConfig {
+        dead_letter_path: Some(dead_letter_path.clone()),
+        ..SyncSystemConfig::default()
+    };
+
+    {
+        let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+        let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+        let path_descriptors = PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
+            ))]),
+        };
+
+        let task = SingleRecordingUploadTask::new(
+            Arc::new(review_end),
+            first_resolve_sender,
+            review_receiver,
+            Some(end_sender),
+            Arc::new(frigate_config),
+            frigate_api_maker,
+            file_sender_maker,
+            path_descriptors,
+            Arc::new(sync_config),
+            Some(number_of_retry_attempts),
+            Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
+            TimeGetter::default(),
+        );
+        let task_handle = tokio::task::spawn(task.start());
+
+        first_resolve_receiver.await.unwrap();
+
+        task_handle.await.unwrap();
+
+        assert_eq!(end_receiver.await.unwrap(), UploadConclusion::NotDone);
+    }
+
+    let dead_letters = std::fs::read_to_string(&dead_letter_path).unwrap();
+    let (previous, recorded) = dead_letters.split_once('\n').unwrap();
+    assert_eq!(previous, "id-from-before");
+    let (id, reason) = recorded.trim_end().split_once('\t').unwrap();
+    assert_eq!(id, "id-abcdefg");
+    assert!(reason.contains(&format!("Retried {number_of_retry_attempts} times")));
+    assert!(reason.contains("Artificial error when retrieving the video"));
+}
+
+#[tokio::test]
+async fn unrecoverable_upload_recorded_in_dead_letter() {
+    // Retrying is pointless once Frigate refuses the credentials
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Err(FrigateApiError::Unauthorized { status: 401 }))
+        .once();
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(make_store_mock());
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review_end = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-unrecoverable".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let dead_letter_dir = tempfile::tempdir().unwrap();
+    let dead_letter_path = dead_letter_dir.path().join("dead-letter.txt");
+    let sync_config = SyncSystemConfig {
+        dead_letter_path: Some(dead_letter_path.clone()),
+        ..SyncSystemConfig::default()
+    };
+
+    let (_review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_end),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(FrigateApiConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
+            ))]),
+        },
+        Arc::new(sync_config),
+        Some(3),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+    task_handle.await.unwrap();
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Unrecoverable);
+
+    let dead_letters = std::fs::read_to_string(&dead_letter_path).unwrap();
+    let (id, reason) = dead_letters.trim_end().split_once('\t').unwrap();
+    assert_eq!(id, "id-unrecoverable");
+    assert!(reason.starts_with("Unrecoverable"));
+    assert!(reason.contains("401"));
+}
+
+#[tokio::test]
+async fn failed_upload_recorded_in_dead_letter_with_its_reason() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Some video".to_vec())));
+
+    // Every upload attempt fails
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    file_store_mock
+        .expect_put_from_memory()
+        .returning(|_, _| Err(anyhow::anyhow!("Artificial error when uploading the video")));
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review_end = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-failed-upload".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let dead_letter_dir = tempfile::tempdir().unwrap();
+    let dead_letter_path = dead_letter_dir.path().join("dead-letter.txt");
+    let sync_config = SyncSystemConfig {
+        dead_letter_path: Some(dead_letter_path.clone()),
+        ..SyncSystemConfig::default()
+    };
+
+    let (_review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_end),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(FrigateApiConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
+            ))]),
+        },
+        Arc::new(sync_config),
+        Some(1),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+    task_handle.await.unwrap();
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::NotDone);
+
+    let dead_letters = std::fs::read_to_string(&dead_letter_path).unwrap();
+    let (id, reason) = dead_letters.trim_end().split_once('\t').unwrap();
+    assert_eq!(id, "id-failed-upload");
+    assert!(
+        reason.contains("Review recording upload failed"),
+        "{reason}"
+    );
+    assert!(reason.contains("id-failed-upload"), "{reason}");
+    assert!(!reason.contains("Deleting alternative upload file failed"));
+}
+
+#[tokio::test]
+async fn partial_download_is_discarded_once_the_task_concludes() {
+    // Unique, so that no partial download of another test is touched
+    let camera_name = "partial_download_discarded";
+    let partial_path = frigate_api_caller::partial_download_path(camera_name, 950.);
+    std::fs::create_dir_all(partial_path.parent().unwrap()).unwrap();
+    std::fs::write(&partial_path, b"Data of an interrupted download").unwrap();
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Err(FrigateApiError::Unauthorized { status: 401 }))
+        .once();
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(make_store_mock());
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review_end = TestReviewData {
+        camera_name: camera_name.to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-partial".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let (_review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_end),
+        first_resolve_sender,
+        review_receiver,
+        None,
+        Arc::new(FrigateApiConfig {
+            resume_downloads: true,
+            ..Default::default()
+        }),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
+            ))]),
+        },
+        Arc::new(SyncSystemConfig::default()),
+        Some(3),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+    let result = task_handle.await.unwrap();
+    assert_eq!(result.conclusion, UploadConclusion::Unrecoverable);
+    assert!(!partial_path.exists());
+}
+
+#[tokio::test]
+async fn uploaded_review_without_end_not_recorded_in_dead_letter() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())));
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    // Its end never arrives
+    let review_update = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::Update,
+    };
+    let (_review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let dead_letter_dir = tempfile::tempdir().unwrap();
+    let dead_letter_path = dead_letter_dir.path().join("dead-letter.txt");
+    let sync_config = SyncSystemConfig {
+        dead_letter_path: Some(dead_letter_path.clone()),
+        ..SyncSystemConfig::default()
+    };
+
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_update),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(FrigateApiConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/home/data/"))]),
+        },
+        Arc::new(sync_config),
+        Some(2),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+    task_handle.await.unwrap();
+
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::NotDone);
+    // It was uploaded, so there's nothing to retry
+    assert!(!dead_letter_path.exists());
+}
+
 #[tokio::test]
 #[rstest]
 #[trace]
@@ -727,8 +1096,7 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let expected_file_content = Arc::new(Mutex::new(gen_random_bytes(&mut rng, 100..1000)));
@@ -748,8 +1116,8 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
     frigate_api_mock
         .expect_recording_clip()
         .returning(move |_, _, _| {
-            Err(anyhow::anyhow!(
-                "Artificial error when retrieving the video"
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
             ))
         })
         .once()
@@ -784,13 +1152,6 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
         .once()
         .in_sequence(&mut sequence);
 
-    // This comes from emitting the error
-    file_store_mock
-        .expect_path_descriptor()
-        .return_const(Arc::new(PathDescriptor::Local("<Fake>".to_string().into())))
-        .once()
-        .in_sequence(&mut sequence);
-
     let number_of_download_attempts: u32 = rng.random_range(4..7);
 
     for i in 0..number_of_download_attempts * MAX_UPLOAD_ATTEMPTS - 1
@@ -811,11 +1172,6 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
             .returning(move |_, _| Err(anyhow::anyhow!("Fake attempt {i} failure")))
             .once()
             .in_sequence(&mut sequence);
-        file_store_mock
-            .expect_path_descriptor()
-            .return_const(Arc::new(PathDescriptor::Local("<Fake>".to_string().into())))
-            .once()
-            .in_sequence(&mut sequence);
     }
 
     let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
@@ -843,8 +1199,8 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
         let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
 
         let path_descriptors = PathDescriptors {
-            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-                "/home/data/".to_string().into(),
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
             ))]),
         };
 
@@ -857,8 +1213,10 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
             frigate_api_maker,
             file_sender_maker,
             path_descriptors,
+            Arc::new(SyncSystemConfig::default()),
             Some(number_of_download_attempts),
             Some(RETRY_PERIOD),
+            Some(RETRY_PERIOD),
             TimeGetter::default(),
         );
         let task_handle = tokio::task::spawn(task.start());
@@ -870,3 +1228,798 @@ async fn recording_upload_mocked_failures_in_download_then_upload_leads_to_not_d
         assert_eq!(end_receiver.await.unwrap(), UploadConclusion::NotDone);
     }
 }
+
+#[rstest]
+#[trace]
+fn retry_delay_grows_within_cap(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let base = std::time::Duration::from_millis(rng.random_range(100..2000));
+    let max = base * rng.random_range(10..100);
+
+    let delays = (0..40)
+        .map(|attempt| retry_delay(base, max, attempt, &mut rng))
+        .collect::<Vec<_>>();
+
+    for (attempt, delay) in delays.iter().enumerate() {
+        assert!(
+            *delay >= base / 2,
+            "Attempt {attempt}: {delay:?} < {base:?}/2"
+        );
+        assert!(*delay <= max, "Attempt {attempt}: {delay:?} > {max:?}");
+    }
+
+    // Until the cap is reached, every delay is at least as long as the one before.
+    // Each delay is in [d/2, d] for the exponential delay d, which doubles with every attempt.
+    let uncapped_count = delays
+        .iter()
+        .zip(0..)
+        .take_while(|(_, attempt)| base * 2u32.pow(*attempt) <= max)
+        .count();
+    assert!(uncapped_count > 1);
+    for pair in delays[..uncapped_count].windows(2) {
+        assert!(pair[0] <= pair[1], "Delays didn't grow: {pair:?}");
+    }
+
+    // Once capped, the delays stay in the upper half of the cap
+    for delay in &delays[uncapped_count..] {
+        assert!(*delay >= max / 2);
+    }
+}
+
+#[tokio::test]
+async fn recording_outside_retention_is_unrecoverable() {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    // Nothing is uploaded
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(make_store_mock());
+
+    // The review is long gone, and there's no point in retrying, so each call happens once
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(None))
+        .once();
+    frigate_api_mock
+        .expect_recording_retention()
+        .returning(|_| Ok(Some(std::time::Duration::from_secs(24 * 60 * 60))))
+        .once();
+
+    let review_end = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    // We only send one review here, no need for sender
+    let _review_sender = review_sender;
+
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_end),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    task_handle.await.unwrap();
+
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Unrecoverable);
+}
+
+#[tokio::test]
+async fn unauthorized_api_is_unrecoverable() {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    // Nothing is uploaded
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(make_store_mock());
+
+    // Credentials don't fix themselves, so the download isn't retried
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Err(FrigateApiError::Unauthorized { status: 401 }))
+        .once();
+
+    let review_end = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::End,
+    };
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    // We only send one review here, no need for sender
+    let _review_sender = review_sender;
+
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review_end),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    task_handle.await.unwrap();
+
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Unrecoverable);
+}
+
+#[tokio::test]
+#[rstest]
+#[case(std::time::Duration::ZERO, 12)]
+#[case(std::time::Duration::from_millis(300), 3)]
+async fn rapid_updates_are_debounced(
+    #[case] review_update_debounce: std::time::Duration,
+    #[case] expected_downloads: usize,
+) {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let downloads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let downloads_inner = downloads.clone();
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| {
+            downloads_inner.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(b"012345".to_vec()))
+        });
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = |type_field, end_time| TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time,
+        id: "id-abcdefg".to_string(),
+        type_field,
+    };
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review(payload::TypeField::New, None)),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            review_update_debounce,
+            ..SyncSystemConfig::default()
+        }),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    // Every update is confirmed once it's processed, or once the update that replaced it is
+    let mut confirmations = Vec::new();
+    for _ in 0..10 {
+        let (review_res_sender, review_res_receiver) = oneshot::channel();
+        review_sender
+            .send((
+                Arc::new(review(payload::TypeField::Update, None)),
+                Some(review_res_sender),
+            ))
+            .unwrap();
+        confirmations.push(review_res_receiver);
+    }
+    for confirmation in confirmations {
+        confirmation.await.unwrap();
+    }
+
+    // The end is processed right away
+    review_sender
+        .send((Arc::new(review(payload::TypeField::End, Some(1000.))), None))
+        .unwrap();
+
+    task_handle.await.unwrap();
+
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Done);
+    assert_eq!(
+        downloads.load(std::sync::atomic::Ordering::SeqCst),
+        expected_downloads
+    );
+}
+
+#[tokio::test]
+#[rstest]
+#[case(Some(std::time::Duration::from_millis(300)))]
+#[case(None)]
+async fn idle_review_is_concluded_without_end(
+    #[case] review_idle_timeout: Option<std::time::Duration>,
+) {
+    // Long enough for a review without an idle timeout to be retried a few times
+    const WAIT_FOR_CONCLUSION: std::time::Duration = std::time::Duration::from_secs(2);
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"012345".to_vec())));
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = |type_field| TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field,
+    };
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review(payload::TypeField::New)),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            review_idle_timeout,
+            ..SyncSystemConfig::default()
+        }),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    // Only updates arrive, and the end never does
+    for _ in 0..3 {
+        let (review_res_sender, review_res_receiver) = oneshot::channel();
+        review_sender
+            .send((
+                Arc::new(review(payload::TypeField::Update)),
+                Some(review_res_sender),
+            ))
+            .unwrap();
+        review_res_receiver.await.unwrap();
+    }
+    let last_update_at = tokio::time::Instant::now();
+
+    let result = tokio::time::timeout(WAIT_FOR_CONCLUSION, task_handle).await;
+
+    match review_idle_timeout {
+        Some(review_idle_timeout) => {
+            let result = result.unwrap().unwrap();
+            assert!(last_update_at.elapsed() >= review_idle_timeout);
+            assert_eq!(result.conclusion, UploadConclusion::Done);
+            assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Done);
+            assert!(
+                file_sender
+                    .file_exists(result.uploaded_path.as_ref().unwrap())
+                    .await
+                    .unwrap()
+            );
+        }
+        None => {
+            // Still waiting for the end of the review
+            assert!(result.is_err());
+        }
+    }
+}
+
+#[tokio::test]
+#[rstest]
+#[case(Some(std::time::Duration::from_millis(300)))]
+#[case(None)]
+async fn late_update_after_end_is_uploaded_within_grace(
+    #[case] post_end_grace: Option<std::time::Duration>,
+) {
+    const WAIT_FOR_CONCLUSION: std::time::Duration = std::time::Duration::from_secs(2);
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let downloads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let downloads_inner = downloads.clone();
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| {
+            downloads_inner.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(b"012345".to_vec()))
+        });
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = |type_field, end_time| TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time,
+        id: "id-abcdefg".to_string(),
+        type_field,
+    };
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review(payload::TypeField::New, None)),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            post_end_grace,
+            ..SyncSystemConfig::default()
+        }),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    let (end_res_sender, end_res_receiver) = oneshot::channel();
+    review_sender
+        .send((
+            Arc::new(review(payload::TypeField::End, Some(1000.))),
+            Some(end_res_sender),
+        ))
+        .unwrap();
+    end_res_receiver.await.unwrap();
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    let Some(post_end_grace) = post_end_grace else {
+        // Without a grace, the task is concluded with the end, and a late update has nowhere to go
+        let result = tokio::time::timeout(WAIT_FOR_CONCLUSION, task_handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.conclusion, UploadConclusion::Done);
+        assert!(
+            review_sender
+                .send((Arc::new(review(payload::TypeField::Update, None)), None))
+                .is_err()
+        );
+        return;
+    };
+
+    // The review is reopened within the grace, and its update is uploaded
+    let (update_res_sender, update_res_receiver) = oneshot::channel();
+    review_sender
+        .send((
+            Arc::new(review(payload::TypeField::Update, None)),
+            Some(update_res_sender),
+        ))
+        .unwrap();
+    update_res_receiver.await.unwrap();
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 3);
+    assert!(!task_handle.is_finished());
+
+    // Once it ends again, the task is concluded after the grace
+    let (end_res_sender, end_res_receiver) = oneshot::channel();
+    review_sender
+        .send((
+            Arc::new(review(payload::TypeField::End, Some(1010.))),
+            Some(end_res_sender),
+        ))
+        .unwrap();
+    end_res_receiver.await.unwrap();
+    let last_end_at = tokio::time::Instant::now();
+
+    let result = tokio::time::timeout(WAIT_FOR_CONCLUSION, task_handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(last_end_at.elapsed() >= post_end_grace);
+    assert_eq!(result.conclusion, UploadConclusion::Done);
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Done);
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn task_is_concluded_at_its_max_lifetime_despite_updates() {
+    const MAX_TASK_LIFETIME: std::time::Duration = std::time::Duration::from_millis(500);
+    const UPDATE_PERIOD: std::time::Duration = std::time::Duration::from_millis(20);
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"012345".to_vec())));
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = |type_field| TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field,
+    };
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (end_sender, end_receiver) = tokio::sync::oneshot::channel::<UploadConclusion>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let started_at = tokio::time::Instant::now();
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review(payload::TypeField::New)),
+        first_resolve_sender,
+        review_receiver,
+        Some(end_sender),
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            // Updates never stop, so the review never idles
+            review_idle_timeout: Some(std::time::Duration::from_secs(60)),
+            max_task_lifetime: Some(MAX_TASK_LIFETIME),
+            ..SyncSystemConfig::default()
+        }),
+        None,
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        TimeGetter::default(),
+    );
+    let mut task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    // Updates keep arriving until the task is done
+    let result = loop {
+        tokio::select! {
+            result = &mut task_handle => break result.unwrap(),
+            () = tokio::time::sleep(UPDATE_PERIOD) => {
+                assert!(
+                    started_at.elapsed() < MAX_TASK_LIFETIME * 4,
+                    "The task outlived its maximum lifetime"
+                );
+                // The task may have just stopped receiving
+                let _ = review_sender.send((Arc::new(review(payload::TypeField::Update)), None));
+            }
+        }
+    };
+
+    assert!(started_at.elapsed() >= MAX_TASK_LIFETIME);
+    assert_eq!(result.conclusion, UploadConclusion::Done);
+    assert_eq!(end_receiver.await.unwrap(), UploadConclusion::Done);
+    assert!(
+        file_sender
+            .file_exists(result.uploaded_path.as_ref().unwrap())
+            .await
+            .unwrap()
+    );
+}
+
+/// A clock that starts at a given time and follows the (possibly paused) tokio clock
+struct TokioClockTimeGetter {
+    start_time: Time,
+    started_at: tokio::time::Instant,
+}
+
+impl utils::time_getter::TimeGetterFn for TokioClockTimeGetter {
+    fn get_time(&self) -> Time {
+        self.start_time
+            .saturating_duration_add(self.started_at.elapsed())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn upload_deferred_until_upload_window_opens() {
+    // 2023-11-14 22:13:20 UTC, so the window opens in 2h46m40s
+    let start_time = Time::from_secs_since_epoch(1_700_000_000);
+    let expected_wait = std::time::Duration::from_secs(2 * 3600 + 46 * 60 + 40);
+    let window = crate::system::config::UploadWindow::new(1, 2)
+        .unwrap()
+        .with_timezone(chrono_tz::UTC);
+    assert_eq!(window.time_until_open(start_time), expected_wait);
+
+    let time_getter = TimeGetter::new(Arc::new(TokioClockTimeGetter {
+        start_time,
+        started_at: tokio::time::Instant::now(),
+    }));
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    // Every review is downloaded when it's received, so that its recording can't leave Frigate's retention
+    // while the window is closed. Only the latest one is uploaded, once the window opens.
+    let downloads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut frigate_api_mock = make_frigate_client_mock();
+    let mut seq = mockall::Sequence::new();
+    let downloads_inner = downloads.clone();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(|_, _, end| *end > 1000.)
+        .returning(move |_, _, _| {
+            downloads_inner.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(b"012".to_vec()))
+        })
+        .once()
+        .in_sequence(&mut seq);
+    let downloads_inner = downloads.clone();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(|_, _, end| (*end - 1000.).abs() < f64::EPSILON)
+        .returning(move |_, _, _| {
+            downloads_inner.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Some(b"012345".to_vec()))
+        })
+        .once()
+        .in_sequence(&mut seq);
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let review = |type_field, end_time| TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time,
+        id: "id-abcdefg".to_string(),
+        type_field,
+    };
+
+    let (review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let started_at = tokio::time::Instant::now();
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(review(payload::TypeField::New, None)),
+        first_resolve_sender,
+        review_receiver,
+        None,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            upload_window: Some(window),
+            ..SyncSystemConfig::default()
+        }),
+        Some(2),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        time_getter,
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    // The task still handles updates while the upload is deferred, without using up its retries
+    tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+    let (review_res_sender, review_res_receiver) = oneshot::channel();
+    review_sender
+        .send((
+            Arc::new(review(payload::TypeField::End, Some(1000.))),
+            Some(review_res_sender),
+        ))
+        .unwrap();
+    review_res_receiver.await.unwrap();
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(file_sender.ls(Path::new(".")).await.unwrap().is_empty());
+
+    let result = task_handle.await.unwrap();
+    assert!(started_at.elapsed() >= expected_wait);
+    assert_eq!(result.conclusion, UploadConclusion::Done);
+    assert_eq!(
+        file_sender
+            .get_to_memory(result.uploaded_path.as_ref().unwrap())
+            .await
+            .unwrap(),
+        b"012345"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn unrecoverable_download_before_upload_window_concludes_the_task() {
+    // 2023-11-14 22:13:20 UTC, hours before the window opens
+    let time_getter = TimeGetter::new(Arc::new(TokioClockTimeGetter {
+        start_time: Time::from_secs_since_epoch(1_700_000_000),
+        started_at: tokio::time::Instant::now(),
+    }));
+    let window = crate::system::config::UploadWindow::new(1, 2)
+        .unwrap()
+        .with_timezone(chrono_tz::UTC);
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Err(FrigateApiError::Unauthorized { status: 401 }))
+        .once();
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let (_review_sender, review_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (first_resolve_sender, first_resolve_receiver) = tokio::sync::oneshot::channel::<()>();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let started_at = tokio::time::Instant::now();
+    let task = SingleRecordingUploadTask::new(
+        Arc::new(TestReviewData {
+            camera_name: "MyCamera".to_string(),
+            start_time: 950.,
+            end_time: Some(1000.),
+            id: "id-abcdefg".to_string(),
+            type_field: payload::TypeField::End,
+        }),
+        first_resolve_sender,
+        review_receiver,
+        None,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            upload_window: Some(window),
+            ..SyncSystemConfig::default()
+        }),
+        Some(2),
+        Some(RETRY_PERIOD),
+        Some(RETRY_PERIOD),
+        time_getter,
+    );
+    let task_handle = tokio::task::spawn(task.start());
+
+    first_resolve_receiver.await.unwrap();
+
+    // Waiting for the window can't fix it, so the task doesn't
+    let result = task_handle.await.unwrap();
+    assert_eq!(result.conclusion, UploadConclusion::Unrecoverable);
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(3600));
+    assert!(file_sender.ls(Path::new(".")).await.unwrap().is_empty());
+}
@@ -1,13 +1,24 @@
 use super::RecordingsTaskHandler;
 use crate::{
-    config::PathDescriptors, system::recording_upload_handler::RecordingsUploadTaskHandlerCommand,
+    config::PathDescriptors,
+    stats::SharedUploadStats,
+    system::{
+        config::{RecordingRetry, SyncSystemConfig},
+        recording_upload_handler::RecordingsUploadTaskHandlerCommand,
+    },
 };
 use file_sender::{make_inmemory_filesystem, path_descriptor::PathDescriptor};
-use frigate_api_caller::{config::FrigateApiConfig, traits::FrigateApi};
+use frigate_api_caller::{FrigateApiError, config::FrigateApiConfig, traits::FrigateApi};
 use mocks::frigate_api::make_frigate_client_mock;
 use mqtt_handler::types::reviews::{ReviewProps, payload};
 use rstest::rstest;
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 use test_utils::random::{Seed, gen_random_bytes, make_seedable_rng, random_seed};
 use tokio::sync::oneshot;
 
@@ -40,6 +51,18 @@ impl ReviewProps for TestReviewData {
     fn type_field(&self) -> payload::TypeField {
         self.type_field
     }
+
+    fn detections(&self) -> &[String] {
+        &[]
+    }
+
+    fn objects(&self) -> &[String] {
+        &[]
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        None
+    }
 }
 
 async fn get_task_count(
@@ -77,13 +100,12 @@ async fn recordings_task_handler(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
@@ -107,12 +129,18 @@ async fn recordings_task_handler(random_seed: Seed) {
 
     let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
 
+    let upload_stats = SharedUploadStats::default();
+
     let task = RecordingsTaskHandler::new(
         cmd_receiver,
         Arc::new(frigate_config),
         frigate_api_maker,
         file_sender_maker,
         path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        None,
         None,
         None,
     );
@@ -212,6 +240,12 @@ async fn recordings_task_handler(random_seed: Seed) {
 
         // Now after the end event, the task should be evicted
         assert_eq!(get_task_count(&cmd_sender).await, 0);
+
+        // The review has been uploaded completely once, regardless of the updates
+        let camera_stats = upload_stats.get().camera("MyCamera");
+        assert_eq!(camera_stats.recordings_uploaded, 1);
+        assert!(camera_stats.last_recording_upload.is_some());
+        assert_eq!(camera_stats.snapshots_uploaded, 0);
     }
 
     // stop and shutdown
@@ -234,13 +268,12 @@ async fn recordings_task_handler_shutdown(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
@@ -271,6 +304,10 @@ async fn recordings_task_handler_shutdown(random_seed: Seed) {
         frigate_api_maker,
         file_sender_maker,
         path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
+        None,
         None,
         None,
     );
@@ -307,13 +344,12 @@ async fn recordings_task_handler_timeout_loses_task(random_seed: Seed) {
 
     let frigate_config = FrigateApiConfig {
         frigate_api_base_url: "http://someurl.com:5000/".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
+        ..Default::default()
     };
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
@@ -348,8 +384,12 @@ async fn recordings_task_handler_timeout_loses_task(random_seed: Seed) {
         frigate_api_maker,
         file_sender_maker,
         path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
         Some(max_retries),
         Some(retry_period),
+        Some(retry_period),
     );
 
     let task_handle = tokio::task::spawn(task.run());
@@ -396,3 +436,198 @@ async fn recordings_task_handler_timeout_loses_task(random_seed: Seed) {
         task_handle.await.unwrap();
     }
 }
+
+#[tokio::test]
+async fn duplicate_review_updates_are_downloaded_once() {
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let file_sender = make_inmemory_filesystem();
+
+    let download_count = Arc::new(AtomicUsize::new(0));
+    let download_count_inner = download_count.clone();
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |_, _, _| {
+            download_count_inner.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(b"Hello world!".to_vec()))
+        });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+
+    let task = RecordingsTaskHandler::new(
+        cmd_receiver,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
+        None,
+        None,
+        None,
+    );
+
+    let task_handle = tokio::task::spawn(task.run());
+
+    let review_update = TestReviewData {
+        camera_name: "MyCamera".to_string(),
+        start_time: 950.,
+        end_time: None,
+        id: "id-abcdefg".to_string(),
+        type_field: payload::TypeField::Update,
+    };
+    let review_end = TestReviewData {
+        end_time: Some(1000.),
+        type_field: payload::TypeField::End,
+        ..review_update.clone()
+    };
+
+    // The same update is delivered twice, then the review ends, which is a genuine update
+    for review in [review_update.clone(), review_update, review_end] {
+        let (confirm_sender, confirm_receiver) = oneshot::channel();
+        cmd_sender
+            .send(RecordingsUploadTaskHandlerCommand::Task(
+                Arc::new(review),
+                Some(confirm_sender),
+            ))
+            .unwrap();
+        confirm_receiver.await.unwrap();
+    }
+
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while get_task_count(&cmd_sender).await > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // Once for the update, and once for the end
+    assert_eq!(download_count.load(Ordering::SeqCst), 2);
+
+    cmd_sender
+        .send(RecordingsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+    task_handle.await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn camera_retry_override_is_honored() {
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/home/data/"))]),
+    };
+
+    let file_sender = make_inmemory_filesystem();
+
+    // Every download fails, so every attempt downloads again
+    let downloads = Arc::new(Mutex::new(BTreeMap::<String, u32>::new()));
+    let downloads_inner = downloads.clone();
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(move |camera_label, _, _| {
+            *downloads_inner
+                .lock()
+                .unwrap()
+                .entry(camera_label.to_string())
+                .or_default() += 1;
+            Err(FrigateApiError::Network(
+                "Artificial error when retrieving the video".to_string(),
+            ))
+        });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = Arc::new(move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()));
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()));
+
+    let sync_config = SyncSystemConfig {
+        recording_retry: RecordingRetry {
+            max_attempts: Some(1),
+            period: Some(std::time::Duration::from_secs(1)),
+            max_period: None,
+        },
+        camera_recording_retries: BTreeMap::from([(
+            "SlowCamera".to_string(),
+            RecordingRetry {
+                max_attempts: Some(4),
+                period: None,
+                max_period: None,
+            },
+        )]),
+        ..SyncSystemConfig::default()
+    };
+
+    // The handler's own retries are overridden by the config
+    let task = RecordingsTaskHandler::new(
+        cmd_receiver,
+        Arc::new(frigate_config),
+        frigate_api_maker,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(sync_config),
+        SharedUploadStats::default(),
+        None,
+        Some(10),
+        None,
+        None,
+    );
+
+    let task_handle = tokio::task::spawn(task.run());
+
+    for camera_name in ["MyCamera", "SlowCamera"] {
+        let review = TestReviewData {
+            camera_name: camera_name.to_string(),
+            start_time: 950.,
+            end_time: Some(1000.),
+            id: format!("id-{camera_name}"),
+            type_field: payload::TypeField::End,
+        };
+
+        let (confirm_sender, confirm_receiver) = oneshot::channel();
+        cmd_sender
+            .send(RecordingsUploadTaskHandlerCommand::Task(
+                Arc::new(review),
+                Some(confirm_sender),
+            ))
+            .unwrap();
+        confirm_receiver.await.unwrap();
+    }
+
+    // The tasks give up once their attempts run out
+    while get_task_count(&cmd_sender).await > 0 {
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
+    // The first attempt, and a download on every retry
+    assert_eq!(
+        *downloads.lock().unwrap(),
+        BTreeMap::from([("MyCamera".to_string(), 2), ("SlowCamera".to_string(), 5)])
+    );
+
+    cmd_sender
+        .send(RecordingsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+    task_handle.await.unwrap();
+}
@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use frigate_api_caller::json::{
+    event::Event, export::Export, frigate_config::RetainMode, review::Review,
+};
+use frigate_api_caller::{
+    FrigateApiError,
+    json::stats::StatsProps,
+    traits::{ClipStream, FrigateApi},
+};
+use futures::StreamExt;
+use std::collections::HashMap;
+
+const FIXTURE_EXPORT_ID: &str = "fixture";
+
+/// Frigate has been up long enough for any uptime gate to be open
+const FIXTURE_UPTIME: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+struct FixtureStats;
+
+impl StatsProps for FixtureStats {
+    fn uptime(&self) -> std::time::Duration {
+        FIXTURE_UPTIME
+    }
+}
+
+/// A Frigate API that returns the same fixture clip for any recording, without a live Frigate,
+/// e.g., to replay recorded mqtt messages. Frigate has no reviews, events or snapshots of its own,
+/// and keeps recordings forever.
+pub struct FixtureFrigateApi {
+    clip: Vec<u8>,
+}
+
+impl FixtureFrigateApi {
+    #[must_use]
+    pub fn new(clip: Vec<u8>) -> Self {
+        Self { clip }
+    }
+}
+
+#[async_trait]
+impl FrigateApi for FixtureFrigateApi {
+    async fn test_call(&self) -> Result<(), FrigateApiError> {
+        Ok(())
+    }
+
+    async fn review(&self, id: &str) -> Result<Review, FrigateApiError> {
+        Err(FrigateApiError::NotFound(format!("Review `{id}`")))
+    }
+
+    async fn review_raw(&self, id: &str) -> Result<serde_json::Value, FrigateApiError> {
+        Err(FrigateApiError::NotFound(format!("Review `{id}`")))
+    }
+
+    async fn events(
+        &self,
+        _after: f64,
+        _before: f64,
+        _cameras: &[String],
+    ) -> Result<Vec<Event>, FrigateApiError> {
+        Ok(Vec::new())
+    }
+
+    async fn stats(&self) -> Result<Box<dyn StatsProps>, FrigateApiError> {
+        Ok(Box::new(FixtureStats))
+    }
+
+    async fn recording_retention(
+        &self,
+        _camera_label: &str,
+    ) -> Result<Option<std::time::Duration>, FrigateApiError> {
+        Ok(None)
+    }
+
+    async fn recordings_retain_modes(
+        &self,
+    ) -> Result<HashMap<String, RetainMode>, FrigateApiError> {
+        Ok(HashMap::new())
+    }
+
+    async fn recording_clip(
+        &self,
+        _camera_label: &str,
+        _start_ts: f64,
+        _end_ts: f64,
+    ) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        Ok(Some(self.clip.clone()))
+    }
+
+    async fn recording_clip_stream(
+        &self,
+        _camera_label: &str,
+        _start_ts: f64,
+        _end_ts: f64,
+    ) -> Result<Option<ClipStream>, FrigateApiError> {
+        let clip = bytes::Bytes::from(self.clip.clone());
+        Ok(Some(futures::stream::once(async move { Ok(clip) }).boxed()))
+    }
+
+    async fn start_export(
+        &self,
+        _camera_label: &str,
+        _start_ts: f64,
+        _end_ts: f64,
+    ) -> Result<String, FrigateApiError> {
+        Ok(FIXTURE_EXPORT_ID.to_string())
+    }
+
+    async fn export(&self, export_id: &str) -> Result<Export, FrigateApiError> {
+        Ok(Export {
+            id: export_id.to_string(),
+            camera: String::new(),
+            video_path: format!("/media/frigate/exports/{export_id}.mp4"),
+            in_progress: false,
+        })
+    }
+
+    async fn export_clip(&self, _export: &Export) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        Ok(Some(self.clip.clone()))
+    }
+
+    async fn delete_export(&self, _export_id: &str) -> Result<(), FrigateApiError> {
+        Ok(())
+    }
+
+    async fn event_snapshot(&self, _event_id: &str) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        Ok(None)
+    }
+
+    async fn latest_snapshot(
+        &self,
+        _camera_label: &str,
+    ) -> Result<Option<Vec<u8>>, FrigateApiError> {
+        Ok(None)
+    }
+}
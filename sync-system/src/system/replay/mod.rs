@@ -0,0 +1,59 @@
+mod fixture_frigate_api;
+
+pub use fixture_frigate_api::FixtureFrigateApi;
+
+use super::{
+    SyncSystem,
+    config::SyncSystemConfig,
+    traits::{FileSenderMaker, FrigateApiMaker},
+};
+use crate::config::PathDescriptors;
+use frigate_api_caller::config::FrigateApiConfig;
+use mqtt_handler::types::CapturedPayloads;
+use std::sync::Arc;
+
+/// Runs the system on the given messages, as if they were received from MQTT in that order, without
+/// connecting to MQTT. Returns once all of them have been processed, and the uploads they started are done,
+/// up to the shutdown timeout.
+pub async fn run_replay<F, S>(
+    payloads: Vec<CapturedPayloads>,
+    upload_dests: PathDescriptors,
+    frigate_api_config: Arc<FrigateApiConfig>,
+    sync_config: Arc<SyncSystemConfig>,
+    frigate_api_maker: F,
+    file_sender_maker: S,
+) -> anyhow::Result<()>
+where
+    F: FrigateApiMaker,
+    S: FileSenderMaker,
+{
+    tracing::info!("Replaying {} recorded messages", payloads.len());
+
+    let (mqtt_data_sender, mqtt_data_receiver) = tokio::sync::mpsc::unbounded_channel();
+    for payload in payloads {
+        mqtt_data_sender
+            .send(payload)
+            .expect("The receiver is held right here");
+    }
+    // The system stops once it processed everything that was sent
+    drop(mqtt_data_sender);
+
+    SyncSystem::new(
+        upload_dests,
+        frigate_api_config,
+        sync_config,
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_stop_on_mqtt_data_end()
+    .start()
+    .await
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,85 @@
+use super::{FixtureFrigateApi, run_replay};
+use crate::{config::PathDescriptors, system::config::SyncSystemConfig};
+use file_sender::{make_inmemory_filesystem, path_descriptor::PathDescriptor};
+use frigate_api_caller::{config::FrigateApiConfig, traits::FrigateApi};
+use mqtt_handler::{config::MqttHandlerConfig, replay::read_replay_file};
+use std::{io::Write, path::Path, sync::Arc};
+
+const FIXTURE_CLIP: &[u8] = b"Fixture clip";
+
+fn review(id: &str, camera: &str) -> String {
+    let before = format!(
+        r#"{{"id": "{id}", "camera": "{camera}", "start_time": 1745534741.3, "end_time": null, "severity": "alert", "thumb_path": "/media/frigate/clips/review/thumb.webp", "data": {{"detections": [], "objects": ["person"], "sub_labels": [], "zones": [], "audio": []}}}}"#
+    );
+    let after = before.replace(r#""end_time": null"#, r#""end_time": 1745534761.5"#);
+    format!(
+        r#"{{"topic": "frigate/reviews", "payload": {{"type": "end", "before": {before}, "after": {after}}}}}"#
+    )
+}
+
+#[tokio::test]
+async fn replayed_messages_are_uploaded() {
+    let frigate_config = FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        ..Default::default()
+    };
+
+    // Recordings are only enabled for the front camera
+    let mut replay_file = tempfile::NamedTempFile::new().unwrap();
+    for line in [
+        r#"{"topic": "frigate/front/recordings/state", "payload": "ON"}"#.to_string(),
+        r#"{"topic": "frigate/back/recordings/state", "payload": "OFF"}"#.to_string(),
+        review("1745534741.3-front", "front"),
+        review("1745534741.3-back", "back"),
+    ] {
+        writeln!(replay_file, "{line}").unwrap();
+    }
+    let mqtt_config = MqttHandlerConfig {
+        mqtt_frigate_topic_prefix: "frigate".to_string(),
+        ..MqttHandlerConfig::default()
+    };
+    let payloads = read_replay_file(&mqtt_config, replay_file.path()).unwrap();
+
+    let frigate_api: Arc<dyn FrigateApi> = Arc::new(FixtureFrigateApi::new(FIXTURE_CLIP.to_vec()));
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone());
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    // Returns on its own once everything is replayed
+    tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        run_replay(
+            payloads,
+            path_descriptors,
+            Arc::new(frigate_config),
+            Arc::new(SyncSystemConfig::default()),
+            frigate_api_maker,
+            file_sender_maker,
+        ),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    let mut uploaded_files = Vec::new();
+    for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+        let dir = Path::new(".").join(dir);
+        for file in file_sender.ls(&dir).await.unwrap() {
+            uploaded_files.push(dir.join(file));
+        }
+    }
+    assert_eq!(uploaded_files.len(), 1, "Uploaded: {uploaded_files:?}");
+    assert!(uploaded_files[0].to_string_lossy().contains("front"));
+    assert_eq!(
+        file_sender.get_to_memory(&uploaded_files[0]).await.unwrap(),
+        FIXTURE_CLIP
+    );
+}
@@ -0,0 +1,92 @@
+use super::{
+    backfill::upload_reviews,
+    common::dead_letter::{finish_dead_letters_retry, record_dead_letter, take_dead_letters},
+    config::SyncSystemConfig,
+    review_from_api::ReviewFromApi,
+    traits::{FileSenderMaker, FrigateApiMaker},
+};
+use crate::{config::PathDescriptors, stats::UploadStats};
+use frigate_api_caller::{FrigateApiError, config::FrigateApiConfig};
+use mqtt_handler::types::reviews::ReviewProps;
+use std::sync::Arc;
+
+/// Uploads the recordings of the reviews recorded in the dead-letter file of the config, and returns once
+/// all of them have been processed. The reviews that fail again, or that cannot be retrieved from Frigate,
+/// are recorded in the dead-letter file again, while the ones that Frigate no longer has are dropped.
+pub async fn run_dead_letters_retry<F, S>(
+    upload_dests: PathDescriptors,
+    frigate_api_config: Arc<FrigateApiConfig>,
+    sync_config: Arc<SyncSystemConfig>,
+    frigate_api_maker: F,
+    file_sender_maker: S,
+) -> anyhow::Result<UploadStats>
+where
+    F: FrigateApiMaker,
+    S: FileSenderMaker,
+{
+    let dead_letter_path = sync_config
+        .dead_letter_path
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("No dead-letter file is set in the config"))?;
+
+    let api = frigate_api_maker(&frigate_api_config)?;
+    let ids = take_dead_letters(&dead_letter_path).await?;
+
+    tracing::info!("Retrying the uploads of {} dead letters", ids.len());
+
+    let mut reviews = Vec::<Arc<dyn ReviewProps>>::with_capacity(ids.len());
+    for id in ids {
+        match api.review(&id).await {
+            Ok(review) if review.end_time.is_some() => {
+                reviews.push(Arc::new(ReviewFromApi::from(review)));
+            }
+            Ok(_) => {
+                tracing::info!("Dead letter with id `{id}` is kept - The review hasn't ended yet.");
+                record_dead_letter(&dead_letter_path, &id, "The review hasn't ended yet").await;
+            }
+            Err(FrigateApiError::NotFound(_)) => {
+                tracing::warn!(
+                    "Dropping dead letter with id `{id}` - Frigate no longer has the review."
+                );
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Dead letter with id `{id}` is kept - Retrieving the review failed. Error: {e}"
+                );
+                record_dead_letter(
+                    &dead_letter_path,
+                    &id,
+                    &format!("Retrieving the review failed: {e}"),
+                )
+                .await;
+            }
+        }
+    }
+
+    // The reviews whose upload fails again are recorded by their tasks
+    let stats = upload_reviews(
+        reviews,
+        upload_dests,
+        frigate_api_config,
+        sync_config,
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await;
+
+    finish_dead_letters_retry(&dead_letter_path).await;
+
+    tracing::info!(
+        "Dead letters retry done. Uploaded recordings: {}",
+        stats
+            .cameras()
+            .values()
+            .map(|s| s.recordings_uploaded)
+            .sum::<u64>()
+    );
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,126 @@
+use super::run_dead_letters_retry;
+use crate::{config::PathDescriptors, system::config::SyncSystemConfig};
+use file_sender::{make_inmemory_filesystem, path_descriptor::PathDescriptor};
+use frigate_api_caller::{
+    FrigateApiError,
+    config::FrigateApiConfig,
+    json::review::{Data, Review},
+    traits::FrigateApi,
+};
+use mocks::frigate_api::make_frigate_client_mock;
+use std::{path::Path, sync::Arc};
+
+fn make_review(id: &str, end_time: Option<f64>) -> Review {
+    Review {
+        id: id.to_string(),
+        camera: "front".to_string(),
+        start_time: 950.,
+        end_time,
+        has_been_reviewed: false,
+        severity: "alert".to_string(),
+        thumb_path: String::new(),
+        data: Data {
+            detections: Vec::new(),
+            objects: vec!["person".to_string()],
+            sub_labels: Vec::new(),
+            zones: Vec::new(),
+            audio: Vec::new(),
+        },
+    }
+}
+
+#[tokio::test]
+async fn dead_letters_are_uploaded_or_kept() {
+    let dead_letter_dir = tempfile::tempdir().unwrap();
+    let dead_letter_path = dead_letter_dir.path().join("dead-letter.txt");
+    std::fs::write(
+        &dead_letter_path,
+        "id-ok\nid-missing\nid-api-error\nid-ongoing\nid-ok\n",
+    )
+    .unwrap();
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock.expect_review().returning(|id| match id {
+        "id-ok" => Ok(make_review(id, Some(1000.))),
+        "id-ongoing" => Ok(make_review(id, None)),
+        "id-missing" => Err(FrigateApiError::NotFound("Review not found".to_string())),
+        _ => Err(FrigateApiError::Network("Connection refused".to_string())),
+    });
+    // Only the review that has ended is downloaded, once
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone());
+
+    let stats = run_dead_letters_retry(
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+                "/home/data/".to_string(),
+            ))]),
+        },
+        Arc::new(FrigateApiConfig {
+            frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+            ..Default::default()
+        }),
+        Arc::new(SyncSystemConfig {
+            dead_letter_path: Some(dead_letter_path.clone()),
+            ..SyncSystemConfig::default()
+        }),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(stats.camera("front").recordings_uploaded, 1);
+    assert!(!file_sender.ls(Path::new(".")).await.unwrap().is_empty());
+
+    // The review that Frigate no longer has is dropped, and the ones that cannot be uploaded yet are kept
+    let dead_letters = std::fs::read_to_string(&dead_letter_path).unwrap();
+    let dead_letters = dead_letters
+        .lines()
+        .map(|line| line.split_once('\t').unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(dead_letters[0].0, "id-api-error");
+    assert!(
+        dead_letters[0]
+            .1
+            .starts_with("Retrieving the review failed")
+    );
+    assert_eq!(
+        dead_letters[1],
+        ("id-ongoing", "The review hasn't ended yet")
+    );
+    assert_eq!(dead_letters.len(), 2);
+    assert_eq!(
+        std::fs::read_dir(dead_letter_dir.path()).unwrap().count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn retrying_dead_letters_requires_a_dead_letter_file() {
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(make_frigate_client_mock());
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender.clone());
+
+    let result = run_dead_letters_retry(
+        PathDescriptors {
+            path_descriptors: Arc::new(Vec::new()),
+        },
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+    )
+    .await;
+
+    assert!(result.is_err());
+}
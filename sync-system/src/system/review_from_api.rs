@@ -0,0 +1,53 @@
+use frigate_api_caller::json::review::Review;
+use mqtt_handler::types::reviews::{ReviewProps, payload::TypeField};
+
+/// A review retrieved from the Frigate API, to be processed like the reviews that arrive from MQTT.
+#[derive(Debug, Clone)]
+pub struct ReviewFromApi {
+    review: Review,
+}
+
+impl From<Review> for ReviewFromApi {
+    fn from(review: Review) -> Self {
+        Self { review }
+    }
+}
+
+impl ReviewProps for ReviewFromApi {
+    fn camera_name(&self) -> &str {
+        &self.review.camera
+    }
+
+    fn id(&self) -> &str {
+        &self.review.id
+    }
+
+    fn start_time(&self) -> f64 {
+        self.review.start_time
+    }
+
+    fn end_time(&self) -> Option<f64> {
+        self.review.end_time
+    }
+
+    fn type_field(&self) -> TypeField {
+        // The API doesn't tell us about events, so we only know whether the review has ended
+        if self.review.end_time.is_some() {
+            TypeField::End
+        } else {
+            TypeField::Update
+        }
+    }
+
+    fn detections(&self) -> &[String] {
+        &self.review.data.detections
+    }
+
+    fn objects(&self) -> &[String] {
+        &self.review.data.objects
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        Some(!self.review.data.objects.is_empty() || !self.review.data.audio.is_empty())
+    }
+}
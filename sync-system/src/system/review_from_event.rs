@@ -0,0 +1,59 @@
+use frigate_api_caller::json::event::Event;
+use mqtt_handler::types::reviews::{ReviewProps, payload::TypeField};
+
+/// An event retrieved from the Frigate API, processed as a review of the single object it tracks.
+#[derive(Debug, Clone)]
+pub struct ReviewFromEvent {
+    event: Event,
+    detections: Vec<String>,
+    objects: Vec<String>,
+}
+
+impl From<Event> for ReviewFromEvent {
+    fn from(event: Event) -> Self {
+        Self {
+            detections: vec![event.id.clone()],
+            objects: vec![event.label.clone()],
+            event,
+        }
+    }
+}
+
+impl ReviewProps for ReviewFromEvent {
+    fn camera_name(&self) -> &str {
+        &self.event.camera
+    }
+
+    fn id(&self) -> &str {
+        &self.event.id
+    }
+
+    fn start_time(&self) -> f64 {
+        self.event.start_time
+    }
+
+    fn end_time(&self) -> Option<f64> {
+        self.event.end_time
+    }
+
+    fn type_field(&self) -> TypeField {
+        if self.event.end_time.is_some() {
+            TypeField::End
+        } else {
+            TypeField::Update
+        }
+    }
+
+    fn detections(&self) -> &[String] {
+        &self.detections
+    }
+
+    fn objects(&self) -> &[String] {
+        &self.objects
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        // An event is the tracking of an object
+        Some(true)
+    }
+}
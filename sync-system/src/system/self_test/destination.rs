@@ -0,0 +1,85 @@
+use super::{SELF_TEST_UPLOAD_DIR, SelfTestReport};
+use crate::system::traits::FileSenderMaker;
+use file_sender::path_descriptor::PathDescriptor;
+use std::{path::Path, str::FromStr, sync::Arc};
+use utils::time::get_time;
+
+/// Checks a single destination, given as it's written in the config, without the rest of the config:
+/// it's parsed, its store is made and initialized, and a file is written, read back, and deleted in it.
+/// Unlike the self-test of the Frigate pipeline, this writes to the destination itself.
+pub async fn run_destination_test<S: FileSenderMaker>(
+    descriptor: &str,
+    file_sender_maker: S,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    // A failed step is already in the report
+    let _ = run_steps(&mut report, descriptor, file_sender_maker).await;
+    report
+}
+
+async fn run_steps<S: FileSenderMaker>(
+    report: &mut SelfTestReport,
+    descriptor: &str,
+    file_sender_maker: S,
+) -> Option<()> {
+    let path_descriptor = report
+        .run_step("parse", async {
+            let path_descriptor = Arc::new(PathDescriptor::from_str(descriptor)?);
+            let description = format!("Parsed destination `{path_descriptor}`");
+            Ok((path_descriptor, description))
+        })
+        .await?;
+
+    let store = report
+        .run_step("connect", async {
+            let store = file_sender_maker(&path_descriptor)?;
+            store.init().await?;
+            store.health_check().await?;
+            Ok((store, "Initialized the destination".to_string()))
+        })
+        .await?;
+
+    let now = get_time();
+    let dir = Path::new(SELF_TEST_UPLOAD_DIR);
+    let path = dir.join(format!(
+        "destination-test-{}.txt",
+        now.as_local_time_in_file_name_format()
+    ));
+    let content = format!(
+        "Written by frigate-snap-sync to test the destination, at {}\n",
+        now.as_local_datetime().to_rfc3339()
+    );
+
+    report
+        .run_step("write", async {
+            store.mkdir_p(dir).await?;
+            store.put_from_memory(content.as_bytes(), &path).await?;
+            let description = format!("Wrote {} bytes to `{}`", content.len(), path.display());
+            Ok(((), description))
+        })
+        .await?;
+
+    let read = report
+        .run_step("read", async {
+            let read = store.get_to_memory(&path).await?;
+            if read != content.as_bytes() {
+                return Err(anyhow::anyhow!(
+                    "Read {} bytes that differ from the {} bytes written",
+                    read.len(),
+                    content.len()
+                ));
+            }
+            Ok(((), "Read back what was written".to_string()))
+        })
+        .await;
+
+    // The file is deleted even if it couldn't be read back
+    report
+        .run_step("delete", async {
+            store.del_file(&path).await?;
+            Ok(((), format!("Deleted `{}`", path.display())))
+        })
+        .await?;
+
+    read
+}
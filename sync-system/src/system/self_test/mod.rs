@@ -0,0 +1,180 @@
+mod destination;
+
+use super::traits::FrigateApiMaker;
+use file_sender::traits::StoreDestination;
+use frigate_api_caller::{config::FrigateApiConfig, json::event::Event, traits::FrigateApi};
+use std::{future::Future, path::Path, sync::Arc, time::Instant};
+use utils::{
+    file_name::{FileNameCharset, sanitize_file_name_part},
+    time::get_time,
+};
+
+pub use destination::run_destination_test;
+
+const SELF_TEST_UPLOAD_DIR: &str = "self-test";
+
+/// The outcome of one step of a self-test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub latency: std::time::Duration,
+    /// What the step did if it succeeded, or why it failed
+    pub result: Result<String, String>,
+}
+
+/// The steps of a self-test, in the order they ran. The test stops at the first step that fails.
+#[must_use]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.steps.iter().all(|s| s.result.is_ok())
+    }
+
+    async fn run_step<T>(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = anyhow::Result<(T, String)>>,
+    ) -> Option<T> {
+        let start = Instant::now();
+        let result = step.await;
+        let latency = start.elapsed();
+
+        let (value, result) = match result {
+            Ok((value, description)) => {
+                tracing::info!(
+                    "Self-test: step `{name}` succeeded in {} ms. {description}",
+                    latency.as_millis()
+                );
+                (Some(value), Ok(description))
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Self-test: step `{name}` failed after {} ms: {e:#}",
+                    latency.as_millis()
+                );
+                (None, Err(format!("{e:#}")))
+            }
+        };
+
+        self.steps.push(SelfTestStep {
+            name,
+            latency,
+            result,
+        });
+
+        value
+    }
+}
+
+/// Goes through the pipeline a review goes through, with the latest ended event that has a recording
+/// within the lookback period: it's found with the Frigate API, its clip is downloaded, and then
+/// uploaded to the given store, which is meant to be an in-memory one.
+pub async fn run_self_test<F>(
+    frigate_api_config: &FrigateApiConfig,
+    lookback: std::time::Duration,
+    frigate_api_maker: F,
+    store: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+) -> SelfTestReport
+where
+    F: FrigateApiMaker,
+{
+    let mut report = SelfTestReport::default();
+    // A failed step is already in the report
+    let _ = run_steps(
+        &mut report,
+        frigate_api_config,
+        lookback,
+        frigate_api_maker,
+        store,
+    )
+    .await;
+    report
+}
+
+async fn run_steps<F>(
+    report: &mut SelfTestReport,
+    frigate_api_config: &FrigateApiConfig,
+    lookback: std::time::Duration,
+    frigate_api_maker: F,
+    store: Arc<dyn StoreDestination<Error = anyhow::Error>>,
+) -> Option<()>
+where
+    F: FrigateApiMaker,
+{
+    let api: Arc<dyn FrigateApi> = report
+        .run_step("connect", async {
+            let api = frigate_api_maker(frigate_api_config)?;
+            api.test_call().await?;
+            Ok((
+                api,
+                format!("Connected to `{}`", frigate_api_config.api_root()),
+            ))
+        })
+        .await?;
+
+    let event = report
+        .run_step("find event", async {
+            let now = get_time().as_unix_timestamp_f64();
+            let events = api.events(now - lookback.as_secs_f64(), now, &[]).await?;
+            let event = latest_event_with_clip(events).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No ended event with a recording was found within the last {} hours",
+                    lookback.as_secs() / 3600
+                )
+            })?;
+            let description = format!(
+                "Found event with id `{}` from camera `{}`",
+                event.id, event.camera
+            );
+            Ok((event, description))
+        })
+        .await?;
+
+    let end_time = event.end_time?;
+
+    let clip = report
+        .run_step("download clip", async {
+            let clip = api
+                .recording_clip(&event.camera, event.start_time, end_time)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Frigate has no recording of the event"))?;
+            if !frigate_api_config.is_valid_clip(&clip) {
+                return Err(anyhow::anyhow!(
+                    "The downloaded clip is not a valid {:?} file",
+                    frigate_api_config.clip_container
+                ));
+            }
+            let description = format!("Downloaded a clip of {} bytes", clip.len());
+            Ok((clip, description))
+        })
+        .await?;
+
+    report
+        .run_step("upload", async {
+            let dir = Path::new(SELF_TEST_UPLOAD_DIR);
+            let path = dir.join(format!(
+                "{}.{}",
+                sanitize_file_name_part(&event.id, FileNameCharset::Ascii),
+                frigate_api_config.clip_container.extension_for(&clip)
+            ));
+            store.mkdir_p(dir).await?;
+            store.put_from_memory(&clip, &path).await?;
+            Ok(((), format!("Uploaded the clip to `{}`", path.display())))
+        })
+        .await
+}
+
+fn latest_event_with_clip(events: Vec<Event>) -> Option<Event> {
+    events
+        .into_iter()
+        .filter(|e| e.has_clip && e.end_time.is_some())
+        .max_by(|a, b| a.start_time.total_cmp(&b.start_time))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,161 @@
+use super::{run_destination_test, run_self_test};
+use file_sender::{make_inmemory_filesystem, make_store, path_descriptor::PathDescriptor};
+use frigate_api_caller::{
+    config::{ClipContainer, FrigateApiConfig},
+    json::event::Event,
+    traits::FrigateApi,
+};
+use mocks::frigate_api::make_frigate_client_mock;
+use std::{path::Path, sync::Arc};
+
+fn make_event(id: &str, start_time: f64, end_time: Option<f64>, has_clip: bool) -> Event {
+    Event {
+        id: id.to_string(),
+        camera: "front".to_string(),
+        label: "person".to_string(),
+        start_time,
+        end_time,
+        has_clip,
+        has_snapshot: true,
+    }
+}
+
+fn make_frigate_config() -> FrigateApiConfig {
+    FrigateApiConfig {
+        frigate_api_base_url: "http://someurl.com:5000/".to_string(),
+        clip_container: ClipContainer::Auto,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn self_test_uploads_latest_event_clip_to_memory() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_test_call()
+        .returning(|| Ok(()))
+        .once();
+    frigate_api_mock
+        .expect_events()
+        .withf(|after, before, cameras| (before - after - 3600.).abs() < 1e-3 && cameras.is_empty())
+        .returning(|_, _, _| {
+            Ok(vec![
+                make_event("older", 100., Some(150.), true),
+                make_event("latest", 200., Some(230.), true),
+                // Later, but unusable
+                make_event("no-clip", 300., Some(330.), false),
+                make_event("ongoing", 400., None, true),
+            ])
+        })
+        .once();
+    frigate_api_mock
+        .expect_recording_clip()
+        .withf(|camera, start, end| {
+            camera == "front"
+                && (start.to_bits(), end.to_bits()) == (200_f64.to_bits(), 230_f64.to_bits())
+        })
+        .returning(|_, _, _| Ok(Some(b"Hello world!".to_vec())))
+        .once();
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let store = make_inmemory_filesystem();
+
+    let report = run_self_test(
+        &make_frigate_config(),
+        std::time::Duration::from_secs(3600),
+        frigate_api_maker,
+        store.clone(),
+    )
+    .await;
+
+    assert!(report.is_success(), "{report:?}");
+    assert_eq!(
+        report.steps.iter().map(|s| s.name).collect::<Vec<_>>(),
+        ["connect", "find event", "download clip", "upload"]
+    );
+    assert_eq!(
+        store
+            .get_to_memory(Path::new("self-test/latest.mp4"))
+            .await
+            .unwrap(),
+        b"Hello world!"
+    );
+}
+
+#[tokio::test]
+async fn self_test_stops_at_first_failed_step() {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_test_call()
+        .returning(|| Ok(()))
+        .once();
+    frigate_api_mock
+        .expect_events()
+        .returning(|_, _, _| Ok(vec![make_event("ongoing", 400., None, true)]))
+        .once();
+    frigate_api_mock.expect_recording_clip().never();
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let store = make_inmemory_filesystem();
+
+    let report = run_self_test(
+        &make_frigate_config(),
+        std::time::Duration::from_secs(3600),
+        frigate_api_maker,
+        store.clone(),
+    )
+    .await;
+
+    assert!(!report.is_success());
+    assert_eq!(report.steps.len(), 2);
+    assert!(report.steps[0].result.is_ok());
+    assert!(report.steps[1].result.is_err());
+    assert!(!store.dir_exists(Path::new("self-test")).await.unwrap());
+}
+
+#[tokio::test]
+async fn destination_test_round_trips_a_file_in_memory() {
+    let store = make_inmemory_filesystem();
+    let store_inner = store.clone();
+
+    let report = run_destination_test("local:path=/home/data", move |_: &Arc<PathDescriptor>| {
+        Ok(store_inner.clone())
+    })
+    .await;
+
+    assert!(report.is_success(), "{report:?}");
+    assert_eq!(
+        report.steps.iter().map(|s| s.name).collect::<Vec<_>>(),
+        vec!["parse", "connect", "write", "read", "delete"]
+    );
+    // Nothing is left behind
+    assert!(store.ls(Path::new("self-test")).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn destination_test_round_trips_a_file_locally() {
+    let dir = tempfile::tempdir().unwrap();
+    let descriptor = format!("local:path={}", dir.path().display());
+
+    let report = run_destination_test(&descriptor, make_store).await;
+
+    assert!(report.is_success(), "{report:?}");
+    assert_eq!(
+        std::fs::read_dir(dir.path().join("self-test"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn destination_test_stops_at_invalid_descriptor() {
+    let report = run_destination_test("local:directory=/home/data", make_store).await;
+
+    assert!(!report.is_success());
+    assert_eq!(report.steps.len(), 1);
+    assert_eq!(report.steps[0].name, "parse");
+    assert!(report.steps[0].result.is_err());
+}
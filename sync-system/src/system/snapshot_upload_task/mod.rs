@@ -1,11 +1,18 @@
 mod task;
 
-use super::traits::FileSenderMaker;
-use crate::config::PathDescriptors;
+use super::{
+    common::{
+        naming::{NamingStrategy, naming_strategy},
+        upload_notifier::{UploadKind, UploadNotifier},
+    },
+    config::{RecordingRetry, SyncSystemConfig},
+    traits::FileSenderMaker,
+};
+use crate::{config::PathDescriptors, stats::SharedUploadStats};
 use futures::{StreamExt, stream::FuturesUnordered};
 use mqtt_handler::types::snapshot::Snapshot;
-use std::{fmt::Display, sync::Arc};
-use task::SnapshotUploadTask;
+use std::{collections::VecDeque, fmt::Display, sync::Arc};
+use task::{LatestSnapshots, SnapshotUploadTask};
 use tokio::{sync::oneshot, task::JoinHandle};
 use utils::struct_name;
 
@@ -17,8 +24,25 @@ pub struct SnapshotsTaskHandler<S> {
 
     file_sender_maker: Arc<S>,
     path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
+    /// Where snapshots are uploaded to
+    naming: Arc<dyn NamingStrategy>,
+    /// Every snapshot that is uploaded is counted here
+    upload_stats: SharedUploadStats,
+    /// If set, a confirmation is published for every snapshot that is uploaded
+    upload_notifier: Option<UploadNotifier>,
+
+    /// How the upload of every snapshot retries
+    retry: RecordingRetry,
+    /// Keeps the latest snapshot of every camera from being replaced by an older one
+    latest_snapshots: LatestSnapshots,
 
     running_tasks: FuturesUnordered<JoinHandle<()>>,
+    /// Snapshots waiting for a running task to finish, oldest first. Bounded by `max_snapshot_tasks`,
+    /// beyond which the oldest ones are dropped.
+    queued_snapshots: VecDeque<(Arc<Snapshot>, Option<oneshot::Sender<()>>)>,
+    /// The number of snapshots dropped from the queue since start
+    dropped_snapshots: u64,
 
     /// Stops the event loop
     stopped: bool,
@@ -28,7 +52,6 @@ pub enum SnapshotsUploadTaskHandlerCommand {
     /// Send a new Review to process its snapshot
     Task(Arc<Snapshot>, Option<oneshot::Sender<()>>),
     /// Get the number of outstanding upload tasks running
-    #[allow(dead_code)]
     GetTaskCount(oneshot::Sender<usize>),
     /// Stops the task handler by shutting down the event loop
     Stop,
@@ -38,17 +61,31 @@ impl<S> SnapshotsTaskHandler<S>
 where
     S: FileSenderMaker,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         command_receiver: tokio::sync::mpsc::UnboundedReceiver<SnapshotsUploadTaskHandlerCommand>,
         file_sender_maker: Arc<S>,
         path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
+        upload_stats: SharedUploadStats,
+        upload_notifier: Option<UploadNotifier>,
+        retry: RecordingRetry,
     ) -> Self {
         SnapshotsTaskHandler {
             command_receiver,
             file_sender_maker,
             path_descriptors,
+            naming: naming_strategy(&sync_config),
+            sync_config,
+            upload_stats,
+            upload_notifier,
+
+            retry,
+            latest_snapshots: LatestSnapshots::default(),
 
             running_tasks: FuturesUnordered::default(),
+            queued_snapshots: VecDeque::new(),
+            dropped_snapshots: 0,
 
             stopped: false,
         }
@@ -66,7 +103,7 @@ where
                             }
                         }
                         SnapshotsUploadTaskHandlerCommand::Task(snapshot, confirm_sender) => {
-                            self.launch_snapshot_upload_task(snapshot, confirm_sender);
+                            self.on_snapshot_received(snapshot, confirm_sender);
                         }
                         SnapshotsUploadTaskHandlerCommand::GetTaskCount(result_sender) => {
                             if result_sender.send(self.running_tasks.len()).is_err() {
@@ -77,18 +114,52 @@ where
                 }
 
                 Some(task_result) = self.running_tasks.next() => {
-                    Self::on_task_joined(task_result);
+                    self.on_task_joined(task_result);
                 }
             }
         }
 
-        // Wrap all remaining tasks
+        // Wrap all remaining tasks, including the queued ones
         while let Some(task_result) = self.running_tasks.next().await {
-            Self::on_task_joined(task_result);
+            self.on_task_joined(task_result);
+        }
+    }
+
+    fn on_snapshot_received(
+        &mut self,
+        snapshot: Arc<Snapshot>,
+        confirm_sender: Option<oneshot::Sender<()>>,
+    ) {
+        let max_tasks = self.sync_config.max_snapshot_tasks.get();
+        if self.running_tasks.len() < max_tasks {
+            self.launch_snapshot_upload_task(snapshot, confirm_sender);
+            return;
+        }
+
+        self.queued_snapshots.push_back((snapshot, confirm_sender));
+        if self.queued_snapshots.len() > max_tasks {
+            if let Some((dropped, confirm_sender)) = self.queued_snapshots.pop_front() {
+                self.dropped_snapshots += 1;
+                tracing::warn!(
+                    "Too many snapshots are waiting to be uploaded. Dropped the oldest one, of camera `{}`. Dropped snapshots so far: {}",
+                    dropped.camera_label,
+                    self.dropped_snapshots
+                );
+                self.upload_stats
+                    .record_snapshot_drop(dropped.camera_label.clone());
+
+                if let Some(sender) = confirm_sender {
+                    if sender.send(()).is_err() {
+                        tracing::error!(
+                            "CRITICAL: Oneshot confirmation sender for a dropped snapshot in {STRUCT_NAME} failed to send. This indicates a race condition."
+                        );
+                    }
+                }
+            }
         }
     }
 
-    fn on_task_joined<E: Display>(task_result: Result<(), E>) {
+    fn on_task_joined<E: Display>(&mut self, task_result: Result<(), E>) {
         match task_result {
             Ok(()) => {
                 tracing::info!("Snapshot task joined successfully");
@@ -103,6 +174,10 @@ where
                 panic!("Panic occurred: {e}")
             }
         }
+
+        if let Some((snapshot, confirm_sender)) = self.queued_snapshots.pop_front() {
+            self.launch_snapshot_upload_task(snapshot, confirm_sender);
+        }
     }
 
     fn launch_snapshot_upload_task(
@@ -111,11 +186,31 @@ where
         confirm_sender: Option<oneshot::Sender<()>>,
     ) {
         let path_descriptors = self.path_descriptors.clone();
-        let file_sender_maker = self.file_sender_maker.clone();
+        let upload_stats = self.upload_stats.clone();
+        let upload_notifier = self.upload_notifier.clone();
+        let camera_label = snapshot.camera_label.clone();
+        // Made here, in the order the snapshots were received, which decides which one is the latest
+        let task = SnapshotUploadTask::new(
+            snapshot,
+            self.naming.as_ref(),
+            self.file_sender_maker.clone(),
+            path_descriptors.clone(),
+            self.sync_config.clone(),
+            self.retry,
+            &self.latest_snapshots,
+        );
         let handle = tokio::task::spawn(async move {
-            let snapshot = snapshot;
-            let task = SnapshotUploadTask::new(snapshot, file_sender_maker, path_descriptors);
-            task.run().await;
+            if let Some(uploaded_path) = task.run().await {
+                if let Some(notifier) = &upload_notifier {
+                    notifier.notify(
+                        &camera_label,
+                        UploadKind::Snapshot,
+                        &uploaded_path,
+                        &path_descriptors.path_descriptors,
+                    );
+                }
+                upload_stats.record_snapshot_upload(camera_label);
+            }
 
             if let Some(sender) = confirm_sender {
                 if sender.send(()).is_err() {
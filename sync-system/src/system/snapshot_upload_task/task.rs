@@ -1,39 +1,76 @@
 use crate::{
     config::PathDescriptors,
     system::{
-        common::file_upload::{RemoteFileOp, UploadableFile, remote_file_op},
+        common::{
+            file_upload::{RemoteFileOp, UploadableFile, remote_file_op},
+            naming::NamingStrategy,
+            retry::Backoff,
+        },
+        config::{RecordingRetry, SyncSystemConfig},
         traits::FileSenderMaker,
     },
 };
 use mqtt_handler::types::snapshot::Snapshot;
-use std::{path::PathBuf, sync::Arc};
-use utils::time::Time;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 const MAX_ATTEMPT_COUNT: u32 = 128;
 const DEFAULT_UPLOAD_RETRY_SLEEP_ON_ERROR: std::time::Duration = std::time::Duration::from_secs(1);
+const DEFAULT_MAX_UPLOAD_RETRY_SLEEP_ON_ERROR: std::time::Duration =
+    std::time::Duration::from_secs(30);
 
 #[must_use]
 pub struct SnapshotUploadTask<S> {
     snapshot: Arc<dyn UploadableFile>,
+    /// Set if the latest snapshot of the camera is kept, which is overwritten by this one unless a newer one was written
+    latest_snapshot: Option<(Arc<dyn UploadableFile>, LatestSnapshotTurn)>,
     file_sender_maker: Arc<S>,
     file_senders_path_descriptors: PathDescriptors,
+    sync_config: Arc<SyncSystemConfig>,
+    max_retry_attempts: u32,
+    backoff: Backoff,
 }
 
 impl<S: FileSenderMaker> SnapshotUploadTask<S> {
     pub fn new(
-        snapshot: Arc<dyn UploadableFile>,
+        snapshot: Arc<Snapshot>,
+        naming: &dyn NamingStrategy,
         file_sender_maker: Arc<S>,
         file_senders_path_descriptors: PathDescriptors,
+        sync_config: Arc<SyncSystemConfig>,
+        retry: RecordingRetry,
+        latest_snapshots: &LatestSnapshots,
     ) -> Self {
+        let latest_snapshot = sync_config.maintain_latest_snapshot.then(|| {
+            let file = Arc::new(LatestSnapshotFile {
+                snapshot: snapshot.clone(),
+                path: naming.latest_snapshot_path(&snapshot.camera_label),
+            }) as Arc<dyn UploadableFile>;
+            (file, latest_snapshots.take_turn(&snapshot.camera_label))
+        });
+
         Self {
-            snapshot,
+            snapshot: Arc::new(SnapshotFile {
+                path: naming.snapshot_path(&snapshot),
+                snapshot,
+            }),
+            latest_snapshot,
             file_sender_maker,
             file_senders_path_descriptors,
+            sync_config,
+            max_retry_attempts: retry.max_attempts.unwrap_or(MAX_ATTEMPT_COUNT),
+            backoff: Backoff::new(
+                retry.period.unwrap_or(DEFAULT_UPLOAD_RETRY_SLEEP_ON_ERROR),
+                retry
+                    .max_period
+                    .unwrap_or(DEFAULT_MAX_UPLOAD_RETRY_SLEEP_ON_ERROR),
+            ),
         }
     }
 
-    pub async fn run(self) {
+    /// Returns the path the snapshot was uploaded to, if it was uploaded to all destinations
+    pub async fn run(self) -> Option<PathBuf> {
         let snapshot = self.snapshot;
+        let uploaded_path = snapshot.full_upload_path();
         let path_descriptors = self
             .file_senders_path_descriptors
             .path_descriptors
@@ -41,33 +78,141 @@ impl<S: FileSenderMaker> SnapshotUploadTask<S> {
             .clone();
         let file_sender_maker = self.file_sender_maker;
 
-        let _ = remote_file_op(
+        let result = remote_file_op(
             RemoteFileOp::Upload(snapshot.as_ref()),
-            path_descriptors,
-            file_sender_maker,
-            MAX_ATTEMPT_COUNT,
-            DEFAULT_UPLOAD_RETRY_SLEEP_ON_ERROR,
+            path_descriptors.clone(),
+            file_sender_maker.clone(),
+            self.max_retry_attempts,
+            self.backoff,
+            self.sync_config.audit_log_path.as_deref(),
         )
         .await
-        .inspect_err(|e| tracing::error!("Snapshot remote op file error: {e}"));
+        .inspect_err(|e| tracing::error!("Snapshot remote op file error: {e}"))
+        .ok()
+        .map(|()| uploaded_path);
+
+        // Failing to update the latest snapshot doesn't fail the upload, which is already done.
+        // A snapshot that failed to upload isn't made the latest one.
+        if let (Some((latest_snapshot, turn)), Some(_)) = (self.latest_snapshot, &result) {
+            // Held while writing, so that the latest snapshot of a camera is written by one task at a time
+            let mut last_written = turn.last_written.lock().await;
+            if *last_written > turn.sequence {
+                tracing::debug!(
+                    "A newer snapshot of camera `{}` was already written as its latest. Skipping this one.",
+                    latest_snapshot.camera_name()
+                );
+            } else if remote_file_op(
+                RemoteFileOp::Replace(latest_snapshot.as_ref()),
+                path_descriptors,
+                file_sender_maker,
+                self.max_retry_attempts,
+                self.backoff,
+                None,
+            )
+            .await
+            .inspect_err(|e| tracing::error!("Latest snapshot remote op file error: {e}"))
+            .is_ok()
+            {
+                *last_written = turn.sequence;
+            }
+        }
+
+        result
     }
 }
 
-impl UploadableFile for Snapshot {
+/// The order in which the snapshots of every camera were received, so that the latest snapshot of a camera,
+/// written by concurrent tasks, is never replaced by an older one
+#[derive(Debug, Clone, Default)]
+pub struct LatestSnapshots {
+    inner: Arc<std::sync::Mutex<LatestSnapshotsInner>>,
+}
+
+#[derive(Debug, Default)]
+struct LatestSnapshotsInner {
+    next_sequence: u64,
+    /// By camera label, the sequence number of the snapshot last written as its latest
+    last_written: BTreeMap<String, Arc<tokio::sync::Mutex<u64>>>,
+}
+
+impl LatestSnapshots {
+    /// Called in the order the snapshots were received
+    fn take_turn(&self, camera_label: &str) -> LatestSnapshotTurn {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        inner.next_sequence += 1;
+        LatestSnapshotTurn {
+            sequence: inner.next_sequence,
+            last_written: inner
+                .last_written
+                .entry(camera_label.to_string())
+                .or_default()
+                .clone(),
+        }
+    }
+}
+
+/// The place of a snapshot among the snapshots of its camera
+#[derive(Debug)]
+struct LatestSnapshotTurn {
+    sequence: u64,
+    last_written: Arc<tokio::sync::Mutex<u64>>,
+}
+
+/// A snapshot from MQTT, with the path it's uploaded to
+struct SnapshotFile {
+    snapshot: Arc<Snapshot>,
+    path: PathBuf,
+}
+
+impl UploadableFile for SnapshotFile {
     fn file_bytes(&self) -> &[u8] {
-        &self.image_bytes
+        &self.snapshot.image_bytes
     }
 
-    fn file_name(&self) -> PathBuf {
-        self.make_file_name()
+    fn full_upload_path(&self) -> PathBuf {
+        self.path.clone()
     }
 
-    fn upload_dir(&self) -> PathBuf {
-        let date = Time::local_time_in_dir_foramt();
-        PathBuf::from(date)
+    fn file_description(&self) -> String {
+        format!("Snapshot from camera {}", self.snapshot.camera_label)
+    }
+
+    fn camera_name(&self) -> &str {
+        &self.snapshot.camera_label
+    }
+
+    fn source_id(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The copy of a snapshot at the stable path of its camera's latest snapshot
+struct LatestSnapshotFile {
+    snapshot: Arc<Snapshot>,
+    path: PathBuf,
+}
+
+impl UploadableFile for LatestSnapshotFile {
+    fn file_bytes(&self) -> &[u8] {
+        &self.snapshot.image_bytes
+    }
+
+    fn full_upload_path(&self) -> PathBuf {
+        self.path.clone()
     }
 
     fn file_description(&self) -> String {
-        format!("Snapshot from camera {}", self.camera_label)
+        format!("Latest snapshot of camera {}", self.snapshot.camera_label)
+    }
+
+    fn camera_name(&self) -> &str {
+        &self.snapshot.camera_label
+    }
+
+    fn source_id(&self) -> Option<String> {
+        None
     }
 }
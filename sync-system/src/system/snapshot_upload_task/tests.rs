@@ -1,10 +1,18 @@
 use super::*;
+use crate::{
+    stats::SharedUploadStats,
+    system::config::{CameraDirOrder, RecordingRetry, SyncSystemConfig},
+};
 use file_sender::{
-    make_inmemory_filesystem, path_descriptor::PathDescriptor, traits::StoreDestination,
+    ListingVerifiedStore, StoreError, make_inmemory_filesystem, path_descriptor::PathDescriptor,
+    traits::StoreDestination,
 };
 use mocks::store_dest::make_store_mock;
 use rstest::rstest;
-use std::path::Path;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use test_utils::{
     asserts::assert_str_contains,
     random::{Seed, gen_random_bytes, make_seedable_rng, random_seed},
@@ -37,14 +45,24 @@ async fn upload_snapshot(random_seed: Seed) {
     let file_sender_inner = file_sender.clone();
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
     let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
 
-    let task_handler = SnapshotsTaskHandler::new(cmd_receiver, file_sender_maker, path_descriptors);
+    let upload_stats = SharedUploadStats::default();
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        RecordingRetry::default(),
+    );
 
     let task_handle = tokio::task::spawn(task_handler.run());
 
@@ -56,7 +74,7 @@ async fn upload_snapshot(random_seed: Seed) {
         let snapshot = Snapshot {
             image_bytes,
             camera_label: "CameraLabel".to_string(),
-            object_name: "Snapshot1".to_string(),
+            object_name: Some("Snapshot1".to_string()),
         };
 
         let (confirm_sender, confirm_receiver) = oneshot::channel();
@@ -72,6 +90,11 @@ async fn upload_snapshot(random_seed: Seed) {
 
         confirm_receiver.await.unwrap();
 
+        let camera_stats = upload_stats.get().camera(&snapshot.camera_label);
+        assert_eq!(camera_stats.snapshots_uploaded, 1);
+        assert!(camera_stats.last_snapshot_upload.is_some());
+        assert_eq!(camera_stats.recordings_uploaded, 0);
+
         // Wait for the task/upload to finish
         tokio::time::timeout(VERY_LONG_WAIT, async {
             loop {
@@ -95,7 +118,7 @@ async fn upload_snapshot(random_seed: Seed) {
         );
         assert_str_contains(
             file_sender.ls(dir_name).await.unwrap()[0].to_str().unwrap(),
-            &snapshot.object_name,
+            snapshot.object_name.as_ref().unwrap(),
         );
     }
 
@@ -141,14 +164,22 @@ async fn upload_snapshot_mocked(random_seed: Seed) {
         Arc::new(file_store_mock);
 
     let path_descriptors = PathDescriptors {
-        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::Local(
-            "/home/data/".to_string().into(),
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
         ))]),
     };
 
     let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
 
-    let task_handler = SnapshotsTaskHandler::new(cmd_receiver, file_sender_maker, path_descriptors);
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
+        RecordingRetry::default(),
+    );
 
     let task_handle = tokio::task::spawn(task_handler.run());
 
@@ -158,7 +189,7 @@ async fn upload_snapshot_mocked(random_seed: Seed) {
         let snapshot = Snapshot {
             image_bytes,
             camera_label: "CameraLabel".to_string(),
-            object_name: "Snapshot1".to_string(),
+            object_name: Some("Snapshot1".to_string()),
         };
 
         let (confirm_sender, confirm_receiver) = oneshot::channel();
@@ -197,6 +228,111 @@ async fn upload_snapshot_mocked(random_seed: Seed) {
     }
 }
 
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn upload_snapshot_mocked_publishes_confirmation(
+    random_seed: Seed,
+    #[values(false, true)] retain: bool,
+) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (publication_sender, mut publication_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    // Prepare the file sender
+    let mut file_store_mock = make_store_mock();
+    let mut seq = mockall::Sequence::new();
+
+    let uploaded_path = Arc::new(std::sync::Mutex::new(None));
+    let uploaded_path_inner = uploaded_path.clone();
+
+    file_store_mock
+        .expect_init()
+        .once()
+        .returning(|| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_mkdir_p()
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(move |_, path| {
+            *uploaded_path_inner.lock().unwrap() = Some(path.to_path_buf());
+            Ok(())
+        })
+        .in_sequence(&mut seq);
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let path_descriptor = Arc::new(PathDescriptor::local("/home/data/".to_string()));
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![path_descriptor.clone()]),
+    };
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        Some(UploadNotifier::new(
+            publication_sender,
+            "snapsync".to_string(),
+            retain,
+        )),
+        RecordingRetry::default(),
+    );
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: gen_random_bytes(&mut rng, 100..200),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot1".to_string()),
+    });
+
+    let (confirm_sender, confirm_receiver) = oneshot::channel();
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Task(
+            snapshot,
+            Some(confirm_sender),
+        ))
+        .unwrap();
+    confirm_receiver.await.unwrap();
+
+    // The confirmation is published before the task confirms it's done
+    let publication = publication_receiver.try_recv().unwrap();
+    assert_eq!(publication.topic, "snapsync/CameraLabel/uploaded");
+    assert_eq!(publication.retain, retain);
+
+    let uploaded_path = uploaded_path.lock().unwrap().clone().unwrap();
+    let payload: serde_json::Value = serde_json::from_slice(&publication.payload).unwrap();
+    assert_eq!(
+        payload,
+        serde_json::json!({
+            "camera": "CameraLabel",
+            "kind": "snapshot",
+            "path": uploaded_path.display().to_string(),
+            "destinations": [path_descriptor.to_string()],
+        })
+    );
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+    task_handle.await.unwrap();
+
+    // Only one confirmation per upload
+    assert!(publication_receiver.try_recv().is_err());
+}
+
 #[tokio::test]
 #[rstest]
 #[trace]
@@ -205,8 +341,8 @@ async fn upload_snapshot_mocked_error_mkdir(random_seed: Seed) {
 
     let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::Local(
-        "/home/data/".to_string().into(),
+    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::local(
+        "/home/data/".to_string(),
     ))]);
     let path_descriptors = PathDescriptors { path_descriptors };
 
@@ -224,11 +360,6 @@ async fn upload_snapshot_mocked_error_mkdir(random_seed: Seed) {
         .once()
         .returning(|_| Err(anyhow::anyhow!("Faked error in mkdir")))
         .in_sequence(&mut seq);
-    file_store_mock
-        .expect_path_descriptor()
-        .once()
-        .return_const(path_descriptors.path_descriptors[0].clone())
-        .in_sequence(&mut seq);
 
     // After this failure, it'll try again later
     file_store_mock
@@ -252,7 +383,15 @@ async fn upload_snapshot_mocked_error_mkdir(random_seed: Seed) {
 
     let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
 
-    let task_handler = SnapshotsTaskHandler::new(cmd_receiver, file_sender_maker, path_descriptors);
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
+        RecordingRetry::default(),
+    );
 
     let task_handle = tokio::task::spawn(task_handler.run());
 
@@ -262,7 +401,7 @@ async fn upload_snapshot_mocked_error_mkdir(random_seed: Seed) {
         let snapshot = Snapshot {
             image_bytes,
             camera_label: "CameraLabel".to_string(),
-            object_name: "Snapshot1".to_string(),
+            object_name: Some("Snapshot1".to_string()),
         };
 
         let (confirm_sender, confirm_receiver) = oneshot::channel();
@@ -300,3 +439,919 @@ async fn upload_snapshot_mocked_error_mkdir(random_seed: Seed) {
         task_handle.await.unwrap();
     }
 }
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn upload_snapshot_mocked_error_put_then_retry(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::local(
+        "/home/data/".to_string(),
+    ))]);
+    let path_descriptors = PathDescriptors { path_descriptors };
+
+    // Prepare the file sender
+    let mut file_store_mock = make_store_mock();
+    let mut seq = mockall::Sequence::new();
+
+    file_store_mock
+        .expect_init()
+        .once()
+        .returning(|| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_mkdir_p()
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(|_, _| Err(anyhow::anyhow!("Faked error in put")))
+        .in_sequence(&mut seq);
+
+    // The retry succeeds
+    file_store_mock
+        .expect_init()
+        .once()
+        .returning(|| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_mkdir_p()
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(|_, _| Ok(()))
+        .in_sequence(&mut seq);
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let upload_stats = SharedUploadStats::default();
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        RecordingRetry {
+            max_attempts: Some(2),
+            period: Some(std::time::Duration::from_millis(10)),
+            max_period: None,
+        },
+    );
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let image_bytes = gen_random_bytes(&mut rng, 100..200);
+
+    {
+        let snapshot = Arc::new(Snapshot {
+            image_bytes,
+            camera_label: "CameraLabel".to_string(),
+            object_name: Some("Snapshot1".to_string()),
+        });
+
+        let (confirm_sender, confirm_receiver) = oneshot::channel();
+
+        cmd_sender
+            .send(SnapshotsUploadTaskHandlerCommand::Task(
+                snapshot.clone(),
+                Some(confirm_sender),
+            ))
+            .unwrap();
+
+        tokio::time::timeout(VERY_LONG_WAIT, confirm_receiver)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The snapshot is only counted when the upload eventually succeeds
+        let camera_stats = upload_stats.get().camera(&snapshot.camera_label);
+        assert_eq!(camera_stats.snapshots_uploaded, 1);
+    }
+
+    // stop and shutdown
+    {
+        cmd_sender
+            .send(SnapshotsUploadTaskHandlerCommand::Stop)
+            .unwrap();
+
+        task_handle.await.unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn snapshot_retries_back_off() {
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    // The first 4 attempts fail
+    let failures = Arc::new(AtomicUsize::new(4));
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    file_store_mock
+        .expect_put_from_memory()
+        .times(5)
+        .returning(move |_, _| {
+            if failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |f| f.checked_sub(1))
+                .is_ok()
+            {
+                Err(anyhow::anyhow!("Faked error in put"))
+            } else {
+                Ok(())
+            }
+        });
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let second = std::time::Duration::from_secs(1);
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        SharedUploadStats::default(),
+        None,
+        RecordingRetry {
+            max_attempts: Some(10),
+            period: Some(second),
+            max_period: Some(8 * second),
+        },
+    );
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let start = tokio::time::Instant::now();
+    let (confirm_sender, confirm_receiver) = oneshot::channel();
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Task(
+            Arc::new(Snapshot {
+                image_bytes: vec![1, 2, 3],
+                camera_label: "CameraLabel".to_string(),
+                object_name: None,
+            }),
+            Some(confirm_sender),
+        ))
+        .unwrap();
+    confirm_receiver.await.unwrap();
+
+    // The waits double from 1 to 8 seconds, each with a jitter of up to half of it,
+    // which is longer than 4 waits of the period
+    let elapsed = start.elapsed();
+    assert!(elapsed >= second * 15 / 2, "{elapsed:?}");
+    assert!(elapsed <= second * 15, "{elapsed:?}");
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+    task_handle.await.unwrap();
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn upload_snapshot_mocked_not_listed_after_put_then_retry(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::local(
+        "/home/data/".to_string(),
+    ))]);
+    let path_descriptors = PathDescriptors { path_descriptors };
+
+    // Prepare the file sender
+    let mut file_store_mock = make_store_mock();
+    let mut seq = mockall::Sequence::new();
+
+    // The put succeeds, but the file isn't listed afterwards
+    file_store_mock
+        .expect_init()
+        .once()
+        .returning(|| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_mkdir_p()
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(|_, _| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_file_exists()
+        .once()
+        .returning(|_| Ok(false))
+        .in_sequence(&mut seq);
+
+    // The retry succeeds
+    file_store_mock
+        .expect_init()
+        .once()
+        .returning(|| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_mkdir_p()
+        .once()
+        .returning(|_| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(|_, _| Ok(()))
+        .in_sequence(&mut seq);
+    file_store_mock
+        .expect_file_exists()
+        .once()
+        .returning(|_| Ok(true))
+        .in_sequence(&mut seq);
+
+    let file_store: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(ListingVerifiedStore::new(Arc::new(file_store_mock)));
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store.clone()));
+
+    let upload_stats = SharedUploadStats::default();
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        RecordingRetry {
+            max_attempts: Some(2),
+            period: Some(std::time::Duration::from_millis(10)),
+            max_period: None,
+        },
+    );
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let image_bytes = gen_random_bytes(&mut rng, 100..200);
+
+    {
+        let snapshot = Arc::new(Snapshot {
+            image_bytes,
+            camera_label: "CameraLabel".to_string(),
+            object_name: Some("Snapshot1".to_string()),
+        });
+
+        let (confirm_sender, confirm_receiver) = oneshot::channel();
+
+        cmd_sender
+            .send(SnapshotsUploadTaskHandlerCommand::Task(
+                snapshot.clone(),
+                Some(confirm_sender),
+            ))
+            .unwrap();
+
+        tokio::time::timeout(VERY_LONG_WAIT, confirm_receiver)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let camera_stats = upload_stats.get().camera(&snapshot.camera_label);
+        assert_eq!(camera_stats.snapshots_uploaded, 1);
+    }
+
+    // stop and shutdown
+    {
+        cmd_sender
+            .send(SnapshotsUploadTaskHandlerCommand::Stop)
+            .unwrap();
+
+        task_handle.await.unwrap();
+    }
+}
+
+#[rstest]
+#[case(StoreError::OutOfSpace {
+    destination: "/home/data/".to_string(),
+    reason: "No space left on device (os error 28)".to_string(),
+})]
+#[case(StoreError::BelowMinFreeSpace {
+    destination: "/home/data/".to_string(),
+    free_bytes: 1000,
+    write_bytes: 150,
+    min_free_bytes: 1000,
+})]
+#[trace]
+#[tokio::test]
+async fn upload_snapshot_mocked_out_of_space_is_not_retried(
+    random_seed: Seed,
+    #[case] store_error: StoreError,
+) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::local(
+        "/home/data/".to_string(),
+    ))]);
+    let path_descriptors = PathDescriptors { path_descriptors };
+
+    // Every attempt would fail for lack of space, so only one may be made
+    let put_count = Arc::new(AtomicUsize::new(0));
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    file_store_mock.expect_put_from_memory().returning({
+        let put_count = put_count.clone();
+        move |_, _| {
+            put_count.fetch_add(1, Ordering::SeqCst);
+            Err(store_error.clone().into())
+        }
+    });
+    file_store_mock
+        .expect_path_descriptor()
+        .return_const(path_descriptors.path_descriptors[0].clone());
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let upload_stats = SharedUploadStats::default();
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        RecordingRetry {
+            max_attempts: Some(10),
+            period: Some(std::time::Duration::from_millis(10)),
+            max_period: None,
+        },
+    );
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: gen_random_bytes(&mut rng, 100..200),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot1".to_string()),
+    });
+
+    let (confirm_sender, confirm_receiver) = oneshot::channel();
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Task(
+            snapshot.clone(),
+            Some(confirm_sender),
+        ))
+        .unwrap();
+
+    tokio::time::timeout(VERY_LONG_WAIT, confirm_receiver)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(put_count.load(Ordering::SeqCst), 1);
+    let camera_stats = upload_stats.get().camera(&snapshot.camera_label);
+    assert_eq!(camera_stats.snapshots_uploaded, 0);
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+
+    task_handle.await.unwrap();
+}
+
+#[rstest]
+#[trace]
+#[tokio::test]
+async fn upload_snapshot_mocked_directory_deleted_before_put_is_made_again(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let path_descriptors = Arc::new(vec![Arc::new(PathDescriptor::local(
+        "/home/data/".to_string(),
+    ))]);
+    let path_descriptors = PathDescriptors { path_descriptors };
+
+    // The directory is gone by the first put, and is there again once it's made again
+    let mkdir_count = Arc::new(AtomicUsize::new(0));
+    let put_count = Arc::new(AtomicUsize::new(0));
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning({
+        let mkdir_count = mkdir_count.clone();
+        move |_| {
+            mkdir_count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    });
+    file_store_mock.expect_put_from_memory().returning({
+        let put_count = put_count.clone();
+        move |_, _| {
+            if put_count.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(StoreError::DirectoryNotFound {
+                    destination: "/home/data/".to_string(),
+                    reason: "No such file or directory (os error 2)".to_string(),
+                }
+                .into())
+            } else {
+                Ok(())
+            }
+        }
+    });
+    file_store_mock
+        .expect_path_descriptor()
+        .return_const(path_descriptors.path_descriptors[0].clone());
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let upload_stats = SharedUploadStats::default();
+
+    // A single attempt, so the upload only succeeds if it's retried within it
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig::default()),
+        upload_stats.clone(),
+        None,
+        RecordingRetry {
+            max_attempts: Some(1),
+            period: Some(std::time::Duration::from_millis(10)),
+            max_period: None,
+        },
+    );
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: gen_random_bytes(&mut rng, 100..200),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot1".to_string()),
+    });
+
+    let (confirm_sender, confirm_receiver) = oneshot::channel();
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Task(
+            snapshot.clone(),
+            Some(confirm_sender),
+        ))
+        .unwrap();
+
+    tokio::time::timeout(VERY_LONG_WAIT, confirm_receiver)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(mkdir_count.load(Ordering::SeqCst), 2);
+    assert_eq!(put_count.load(Ordering::SeqCst), 2);
+    let camera_stats = upload_stats.get().camera(&snapshot.camera_label);
+    assert_eq!(camera_stats.snapshots_uploaded, 1);
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+
+    task_handle.await.unwrap();
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn upload_snapshot_writes_audit_log(random_seed: Seed) {
+    use sha2::{Digest, Sha256};
+
+    let mut rng = make_seedable_rng(random_seed);
+
+    let path_descriptor = Arc::new(PathDescriptor::local("/home/data/".to_string()));
+
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    file_store_mock
+        .expect_put_from_memory()
+        .times(2)
+        .returning(|_, _| Ok(()));
+    file_store_mock
+        .expect_path_descriptor()
+        .return_const(path_descriptor.clone());
+
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![path_descriptor.clone()]),
+    };
+
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let audit_log_dir = tempfile::tempdir().unwrap();
+    let audit_log_path = audit_log_dir.path().join("audit.jsonl");
+    let sync_config = Arc::new(SyncSystemConfig {
+        audit_log_path: Some(audit_log_path.clone()),
+        ..SyncSystemConfig::default()
+    });
+
+    let image_bytes = gen_random_bytes(&mut rng, 100..200);
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: image_bytes.clone(),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot1".to_string()),
+    });
+
+    // Upload twice, to ensure lines are appended
+    for _ in 0..2 {
+        assert!(
+            SnapshotUploadTask::new(
+                snapshot.clone(),
+                naming_strategy(&sync_config).as_ref(),
+                file_sender_maker.clone(),
+                path_descriptors.clone(),
+                sync_config.clone(),
+                RecordingRetry::default(),
+                &LatestSnapshots::default(),
+            )
+            .run()
+            .await
+            .is_some()
+        );
+    }
+
+    let audit_log = std::fs::read_to_string(&audit_log_path).unwrap();
+    let lines = audit_log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+
+    for line in lines {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(record["timestamp"].is_string());
+        assert_eq!(record["camera"], "CameraLabel");
+        assert!(record["id"].is_null());
+        assert_str_contains(record["path"].as_str().unwrap(), "Snapshot1");
+        assert_eq!(record["destination"], path_descriptor.to_string());
+        assert_eq!(record["size"], image_bytes.len());
+        assert_eq!(
+            record["sha256"],
+            format!("{:x}", Sha256::digest(&image_bytes))
+        );
+        assert_eq!(record["outcome"], "success");
+        assert!(record["error"].is_null());
+    }
+}
+
+#[rstest]
+#[case(None, "")]
+#[case(Some(CameraDirOrder::CameraFirst), "CameraLabel")]
+#[tokio::test]
+async fn upload_snapshot_into_snapshots_subdir(
+    #[case] camera_dirs: Option<CameraDirOrder>,
+    #[case] camera_dir: &str,
+) {
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        camera_dirs,
+        recordings_subdir: "recordings".into(),
+        snapshots_subdir: "media/snapshots".into(),
+        ..SyncSystemConfig::default()
+    });
+
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: b"Hello world!".to_vec(),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot1".to_string()),
+    });
+
+    let uploaded_path = SnapshotUploadTask::new(
+        snapshot,
+        naming_strategy(&sync_config).as_ref(),
+        file_sender_maker,
+        path_descriptors,
+        sync_config,
+        RecordingRetry::default(),
+        &LatestSnapshots::default(),
+    )
+    .run()
+    .await
+    .unwrap();
+
+    assert!(uploaded_path.starts_with(Path::new("media/snapshots").join(camera_dir)));
+    assert!(file_sender.file_exists(&uploaded_path).await.unwrap());
+    assert_eq!(
+        file_sender.ls(Path::new(".")).await.unwrap(),
+        vec![Path::new("media")]
+    );
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn latest_snapshot_is_replaced_by_every_upload(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        snapshots_subdir: "snapshots".into(),
+        maintain_latest_snapshot: true,
+        ..SyncSystemConfig::default()
+    });
+
+    let latest_path = Path::new("snapshots/latest/CameraLabel.jpg");
+    let latest_snapshots = LatestSnapshots::default();
+
+    for i in 0..3 {
+        let image_bytes = gen_random_bytes(&mut rng, 100..200);
+        let snapshot = Arc::new(Snapshot {
+            image_bytes: image_bytes.clone(),
+            camera_label: "CameraLabel".to_string(),
+            object_name: Some(format!("Snapshot{i}")),
+        });
+
+        let uploaded_path = SnapshotUploadTask::new(
+            snapshot,
+            naming_strategy(&sync_config).as_ref(),
+            file_sender_maker.clone(),
+            path_descriptors.clone(),
+            sync_config.clone(),
+            RecordingRetry::default(),
+            &latest_snapshots,
+        )
+        .run()
+        .await
+        .unwrap();
+
+        // The timestamped snapshot is still uploaded, and the latest one is its copy
+        assert_eq!(
+            file_sender.get_to_memory(&uploaded_path).await.unwrap(),
+            image_bytes
+        );
+        assert_eq!(
+            file_sender.get_to_memory(latest_path).await.unwrap(),
+            image_bytes
+        );
+        assert_eq!(
+            file_sender
+                .ls(Path::new("snapshots/latest"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    // Only kept if enabled
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+    let snapshot = Arc::new(Snapshot {
+        image_bytes: gen_random_bytes(&mut rng, 100..200),
+        camera_label: "CameraLabel".to_string(),
+        object_name: Some("Snapshot".to_string()),
+    });
+    let sync_config = Arc::new(SyncSystemConfig {
+        snapshots_subdir: "snapshots".into(),
+        ..SyncSystemConfig::default()
+    });
+    SnapshotUploadTask::new(
+        snapshot,
+        naming_strategy(&sync_config).as_ref(),
+        file_sender_maker,
+        path_descriptors,
+        sync_config,
+        RecordingRetry::default(),
+        &LatestSnapshots::default(),
+    )
+    .run()
+    .await
+    .unwrap();
+    assert!(!file_sender.file_exists(latest_path).await.unwrap());
+}
+
+#[tokio::test]
+async fn older_snapshot_does_not_replace_newer_latest_snapshot() {
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        snapshots_subdir: "snapshots".into(),
+        maintain_latest_snapshot: true,
+        ..SyncSystemConfig::default()
+    });
+    let latest_snapshots = LatestSnapshots::default();
+
+    let make_task = |image_bytes: &[u8]| {
+        SnapshotUploadTask::new(
+            Arc::new(Snapshot {
+                image_bytes: image_bytes.to_vec(),
+                camera_label: "CameraLabel".to_string(),
+                object_name: None,
+            }),
+            naming_strategy(&sync_config).as_ref(),
+            file_sender_maker.clone(),
+            path_descriptors.clone(),
+            sync_config.clone(),
+            RecordingRetry::default(),
+            &latest_snapshots,
+        )
+    };
+    let older = make_task(b"older");
+    let newer = make_task(b"newer");
+
+    // The newer snapshot's upload finishes first
+    newer.run().await.unwrap();
+    older.run().await.unwrap();
+
+    assert_eq!(
+        file_sender
+            .get_to_memory(Path::new("snapshots/latest/CameraLabel.jpg"))
+            .await
+            .unwrap(),
+        b"newer"
+    );
+}
+
+#[tokio::test]
+async fn failed_snapshot_does_not_replace_latest_snapshot() {
+    let path_descriptor = Arc::new(PathDescriptor::local("/home/data/".to_string()));
+
+    // Only the snapshot itself is put, and never the latest snapshot
+    let mut file_store_mock = make_store_mock();
+    file_store_mock.expect_init().returning(|| Ok(()));
+    file_store_mock.expect_mkdir_p().returning(|_| Ok(()));
+    file_store_mock
+        .expect_put_from_memory()
+        .once()
+        .returning(|_, _| Err(anyhow::anyhow!("Connection lost")));
+    file_store_mock
+        .expect_path_descriptor()
+        .return_const(path_descriptor.clone());
+    let file_store_mock: Arc<dyn StoreDestination<Error = anyhow::Error>> =
+        Arc::new(file_store_mock);
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_store_mock.clone()));
+
+    let sync_config = Arc::new(SyncSystemConfig {
+        maintain_latest_snapshot: true,
+        ..SyncSystemConfig::default()
+    });
+
+    let uploaded_path = SnapshotUploadTask::new(
+        Arc::new(Snapshot {
+            image_bytes: b"Hello world!".to_vec(),
+            camera_label: "CameraLabel".to_string(),
+            object_name: None,
+        }),
+        naming_strategy(&sync_config).as_ref(),
+        file_sender_maker,
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![path_descriptor]),
+        },
+        sync_config,
+        RecordingRetry {
+            max_attempts: Some(1),
+            ..RecordingRetry::default()
+        },
+        &LatestSnapshots::default(),
+    )
+    .run()
+    .await;
+
+    assert!(uploaded_path.is_none());
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+#[case(1, 1)]
+#[case(1, 10)]
+#[case(3, 5)]
+#[case(3, 20)]
+async fn flooded_snapshots_are_bounded_and_oldest_dropped(
+    random_seed: Seed,
+    #[case] max_tasks: usize,
+    #[case] snapshot_count: usize,
+) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let (cmd_sender, cmd_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_inner = file_sender.clone();
+    let file_sender_maker = Arc::new(move |_: &Arc<PathDescriptor>| Ok(file_sender_inner.clone()));
+
+    let path_descriptors = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            "/home/data/".to_string(),
+        ))]),
+    };
+
+    let upload_stats = SharedUploadStats::default();
+
+    let task_handler = SnapshotsTaskHandler::new(
+        cmd_receiver,
+        file_sender_maker,
+        path_descriptors,
+        Arc::new(SyncSystemConfig {
+            max_snapshot_tasks: max_tasks.try_into().unwrap(),
+            ..SyncSystemConfig::default()
+        }),
+        upload_stats.clone(),
+        None,
+        RecordingRetry::default(),
+    );
+
+    // The whole flood is in the channel before the handler gets to run, so no task can finish in between
+    let confirm_receivers = (0..snapshot_count)
+        .map(|i| {
+            let snapshot = Arc::new(Snapshot {
+                image_bytes: gen_random_bytes(&mut rng, 100..200),
+                camera_label: "CameraLabel".to_string(),
+                object_name: Some(format!("Snapshot{i}")),
+            });
+            let (confirm_sender, confirm_receiver) = oneshot::channel();
+            cmd_sender
+                .send(SnapshotsUploadTaskHandlerCommand::Task(
+                    snapshot,
+                    Some(confirm_sender),
+                ))
+                .unwrap();
+            confirm_receiver
+        })
+        .collect::<Vec<_>>();
+
+    let task_handle = tokio::task::spawn(task_handler.run());
+
+    assert_eq!(
+        get_task_count(&cmd_sender).await,
+        snapshot_count.min(max_tasks)
+    );
+
+    // Dropped snapshots are confirmed too, so all of them are done here
+    for confirm_receiver in confirm_receivers {
+        tokio::time::timeout(VERY_LONG_WAIT, confirm_receiver)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let expected_dropped = snapshot_count.saturating_sub(2 * max_tasks);
+    let camera_stats = upload_stats.get().camera("CameraLabel");
+    assert_eq!(camera_stats.snapshots_dropped, expected_dropped as u64);
+    assert_eq!(
+        camera_stats.snapshots_uploaded,
+        (snapshot_count - expected_dropped) as u64
+    );
+
+    cmd_sender
+        .send(SnapshotsUploadTaskHandlerCommand::Stop)
+        .unwrap();
+    task_handle.await.unwrap();
+}
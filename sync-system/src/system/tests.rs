@@ -1,15 +1,38 @@
-use crate::{config::PathDescriptors, state::CamerasState, system::SyncSystem};
-use file_sender::{make_store, path_descriptor::PathDescriptor};
-use frigate_api_caller::{config::FrigateApiConfig, json::stats::StatsProps, traits::FrigateApi};
-use mocks::frigate_api::make_frigate_client_mock;
-use mqtt_handler::types::{
-    CapturedPayloads,
-    reviews::{ReviewProps, payload},
-    snapshot::Snapshot,
-    snapshots_state::SnapshotsState,
+use crate::{
+    config::{PathDescriptors, VideoSyncConfig},
+    state::{CAMERA_REPUBLISH_GRACE, CamerasState},
+    stats::UploadStats,
+    system::{
+        CameraStateChange, CameraStateKind, SyncSystem, SyncSystemCommand,
+        config::{GateOnStatsFailure, SnapshotMode, SyncSystemConfig},
+        run_mqtt_commands,
+    },
+};
+use file_sender::{make_inmemory_filesystem, make_store, path_descriptor::PathDescriptor};
+use frigate_api_caller::{
+    FrigateApiError,
+    config::{FrigateApiConfig, ProxyAuth},
+    json::{
+        frigate_config::RetainMode,
+        review::{Data, Review},
+        stats::StatsProps,
+    },
+    traits::FrigateApi,
+};
+use mocks::{frigate_api::make_frigate_client_mock, store_dest::make_store_mock};
+use mqtt_handler::{
+    command::MqttCommand,
+    config::MqttHandlerConfig,
+    types::{
+        CapturedPayloads,
+        reviews::{ReviewProps, payload},
+        snapshot::Snapshot,
+        snapshots_state::SnapshotsState,
+    },
 };
 use rstest::rstest;
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::{Arc, atomic::AtomicU64},
 };
@@ -19,6 +42,7 @@ use test_utils::{
     random::{Seed, gen_random_bytes, gen_random_string, make_seedable_rng, random_seed},
 };
 use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use utils::systemd::{ServiceNotifier, ServiceState};
 
 const VERY_LONG_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
 
@@ -38,6 +62,14 @@ async fn get_camera_state(sender: &UnboundedSender<oneshot::Sender<CamerasState>
     state_receiver.await.unwrap()
 }
 
+async fn get_upload_stats(sender: &UnboundedSender<SyncSystemCommand>) -> UploadStats {
+    let (stats_sender, stats_receiver) = oneshot::channel();
+    sender
+        .send(SyncSystemCommand::GetUploadStats(stats_sender))
+        .unwrap();
+    stats_receiver.await.unwrap()
+}
+
 #[derive(Debug, Clone)]
 struct TestReviewData {
     camera_name: String,
@@ -45,6 +77,7 @@ struct TestReviewData {
     end_time: Option<f64>,
     id: String,
     type_field: payload::TypeField,
+    activity: Option<bool>,
 }
 
 impl ReviewProps for TestReviewData {
@@ -67,6 +100,18 @@ impl ReviewProps for TestReviewData {
     fn type_field(&self) -> payload::TypeField {
         self.type_field
     }
+
+    fn detections(&self) -> &[String] {
+        &[]
+    }
+
+    fn objects(&self) -> &[String] {
+        &[]
+    }
+
+    fn has_activity(&self) -> Option<bool> {
+        self.activity
+    }
 }
 
 #[tokio::test]
@@ -81,20 +126,20 @@ async fn basic_syncsystem_uploads(
     let temp_dir1 = tempfile::TempDir::new().unwrap();
     let temp_dir2 = tempfile::TempDir::new().unwrap();
     let upload_dests = Arc::new(vec![
-        Arc::new(PathDescriptor::Local(temp_dir1.path().to_owned())),
-        Arc::new(PathDescriptor::Local(temp_dir2.path().to_owned())),
+        Arc::new(PathDescriptor::local(temp_dir1.path().to_owned())),
+        Arc::new(PathDescriptor::local(temp_dir2.path().to_owned())),
     ]);
     let upload_dests = PathDescriptors {
         path_descriptors: upload_dests,
     };
 
-    let frigate_api_config = FrigateApiConfig {
-        frigate_api_base_url: "http://example.com".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup: std::time::Duration::ZERO,
-    };
+    let frigate_api_config = FrigateApiConfig::default();
 
     let mut frigate_api_mock = make_frigate_client_mock();
+
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
     let frigate_returned_video_data_vec = b"012345".to_vec();
     {
         frigate_api_mock.expect_test_call().returning(move || {
@@ -102,7 +147,9 @@ async fn basic_syncsystem_uploads(
             if pass_initial_api_test {
                 Ok(())
             } else {
-                Err(anyhow::anyhow!("Fake api error for tests"))
+                Err(FrigateApiError::Network(
+                    "Fake api error for tests".to_string(),
+                ))
             }
         });
         frigate_api_mock.expect_stats().returning(|| {
@@ -129,10 +176,13 @@ async fn basic_syncsystem_uploads(
     let sync_sys = SyncSystem::new(
         upload_dests.clone(),
         Arc::new(frigate_api_config),
+        Arc::new(SyncSystemConfig::default()),
         frigate_api_maker,
         file_sender_maker,
         mqtt_data_receiver,
+        None,
         Some(camera_state_getter_receiver),
+        None,
         Some(stop_receiver),
     );
 
@@ -150,7 +200,7 @@ async fn basic_syncsystem_uploads(
         let snapshot = Snapshot {
             image_bytes: gen_random_bytes(&mut rng, 100..1000),
             camera_label: gen_random_string(&mut rng, 10..20),
-            object_name: gen_random_string(&mut rng, 10..20),
+            object_name: Some(gen_random_string(&mut rng, 10..20)),
         };
         let payload = CapturedPayloads::Snapshot(Arc::new(snapshot));
         mqtt_data_sender.send(payload).unwrap();
@@ -170,6 +220,7 @@ async fn basic_syncsystem_uploads(
             end_time: None,
             id: "id-abcdefg".to_string(),
             type_field: payload::TypeField::New,
+            activity: None,
         };
         let payload = CapturedPayloads::Reviews(Arc::new(review));
         mqtt_data_sender.send(payload).unwrap();
@@ -228,7 +279,7 @@ async fn basic_syncsystem_uploads(
         let snapshot = Snapshot {
             image_bytes: gen_random_bytes(&mut rng, 100..1000),
             camera_label: camera1_label.to_string(),
-            object_name: gen_random_string(&mut rng, 10..20),
+            object_name: Some(gen_random_string(&mut rng, 10..20)),
         };
         let payload = CapturedPayloads::Snapshot(Arc::new(snapshot));
         mqtt_data_sender.send(payload).unwrap();
@@ -312,6 +363,7 @@ async fn basic_syncsystem_uploads(
             end_time: None,
             id: "id-abcdefg".to_string(),
             type_field: payload::TypeField::End, // We use end because otherwise the upload task is considered unfinished
+            activity: None,
         };
         let payload = CapturedPayloads::Reviews(Arc::new(review));
         mqtt_data_sender.send(payload).unwrap();
@@ -373,8 +425,8 @@ async fn basic_syncsystem_uploads_with_delay_test(
     let temp_dir1 = tempfile::TempDir::new().unwrap();
     let temp_dir2 = tempfile::TempDir::new().unwrap();
     let upload_dests = Arc::new(vec![
-        Arc::new(PathDescriptor::Local(temp_dir1.path().to_owned())),
-        Arc::new(PathDescriptor::Local(temp_dir2.path().to_owned())),
+        Arc::new(PathDescriptor::local(temp_dir1.path().to_owned())),
+        Arc::new(PathDescriptor::local(temp_dir2.path().to_owned())),
     ]);
     let upload_dests = PathDescriptors {
         path_descriptors: upload_dests,
@@ -398,12 +450,15 @@ async fn basic_syncsystem_uploads_with_delay_test(
     let delay_after_startup = std::time::Duration::from_secs(rng.random_range(1..1000));
 
     let frigate_api_config = FrigateApiConfig {
-        frigate_api_base_url: "http://example.com".to_string(),
-        frigate_api_proxy: None,
-        delay_after_startup,
+        delay_after_startup: Some(delay_after_startup),
+        ..Default::default()
     };
 
     let mut frigate_api_mock = make_frigate_client_mock();
+
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
     let frigate_returned_video_data_vec = b"012345".to_vec();
     {
         frigate_api_mock.expect_test_call().returning(move || {
@@ -411,7 +466,9 @@ async fn basic_syncsystem_uploads_with_delay_test(
             if pass_initial_api_test {
                 Ok(())
             } else {
-                Err(anyhow::anyhow!("Fake api error for tests"))
+                Err(FrigateApiError::Network(
+                    "Fake api error for tests".to_string(),
+                ))
             }
         });
         let frigate_uptime_f_inner = frigate_uptime.clone();
@@ -439,10 +496,13 @@ async fn basic_syncsystem_uploads_with_delay_test(
     let sync_sys = SyncSystem::new(
         upload_dests.clone(),
         Arc::new(frigate_api_config),
+        Arc::new(SyncSystemConfig::default()),
         frigate_api_maker,
         file_sender_maker,
         mqtt_data_receiver,
+        None,
         Some(camera_state_getter_receiver),
+        None,
         Some(stop_receiver),
     );
 
@@ -548,6 +608,7 @@ async fn basic_syncsystem_uploads_with_delay_test(
             end_time: None,
             id: "id-abcdefg".to_string(),
             type_field: payload::TypeField::New,
+            activity: None,
         };
         let payload = CapturedPayloads::Reviews(Arc::new(review));
         mqtt_data_sender.send(payload).unwrap();
@@ -567,6 +628,7 @@ async fn basic_syncsystem_uploads_with_delay_test(
             end_time: None,
             id: "id-abcdefg".to_string(),
             type_field: payload::TypeField::New,
+            activity: None,
         };
         let payload = CapturedPayloads::Reviews(Arc::new(review));
         mqtt_data_sender.send(payload).unwrap();
@@ -588,7 +650,7 @@ async fn basic_syncsystem_uploads_with_delay_test(
         let snapshot = Snapshot {
             image_bytes: gen_random_bytes(&mut rng, 100..1000),
             camera_label: camera1_label.to_string(),
-            object_name: gen_random_string(&mut rng, 10..20),
+            object_name: Some(gen_random_string(&mut rng, 10..20)),
         };
         let payload = CapturedPayloads::Snapshot(Arc::new(snapshot));
         mqtt_data_sender.send(payload).unwrap();
@@ -630,6 +692,7 @@ async fn basic_syncsystem_uploads_with_delay_test(
             end_time: None,
             id: "id-abcdefg".to_string(),
             type_field: payload::TypeField::End, // We use end because otherwise the upload task is considered unfinished
+            activity: None,
         };
         let payload = CapturedPayloads::Reviews(Arc::new(review));
         mqtt_data_sender.send(payload).unwrap();
@@ -678,3 +741,1528 @@ async fn basic_syncsystem_uploads_with_delay_test(
             .unwrap();
     }
 }
+
+#[tokio::test]
+async fn shutdown_abandons_stuck_tasks_after_timeout() {
+    let timeout = std::time::Duration::from_millis(200);
+
+    let mut join_handles = vec![
+        ("finishing".to_string(), tokio::task::spawn(async {})),
+        (
+            "stuck".to_string(),
+            tokio::task::spawn(futures::future::pending::<()>()),
+        ),
+    ];
+
+    let start = tokio::time::Instant::now();
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        super::join_tasks_with_timeout(&mut join_handles, timeout),
+    )
+    .await
+    .expect("Shutdown must not wait for the stuck task beyond the timeout");
+
+    assert!(start.elapsed() >= timeout);
+
+    // Give the runtime a chance to process the abort
+    tokio::task::yield_now().await;
+    assert!(join_handles.iter().all(|(_, h)| h.is_finished()));
+}
+
+fn make_api_review(id: &str, camera: &str) -> Review {
+    Review {
+        id: id.to_string(),
+        camera: camera.to_string(),
+        start_time: 950.,
+        end_time: Some(1000.),
+        has_been_reviewed: false,
+        severity: "alert".to_string(),
+        thumb_path: String::new(),
+        data: Data {
+            detections: Vec::new(),
+            objects: Vec::new(),
+            sub_labels: Vec::new(),
+            zones: Vec::new(),
+            audio: Vec::new(),
+        },
+    }
+}
+
+#[tokio::test]
+async fn sync_review_command() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            temp_dir.path().to_owned(),
+        ))]),
+    };
+
+    let frigate_api_config = FrigateApiConfig::default();
+
+    let disabled_camera = "disabled_camera";
+    let forced_camera = "forced_camera";
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_stats().returning(|| {
+        Ok(Box::new(TestStats {
+            uptime: std::time::Duration::from_secs(10000),
+        }))
+    });
+    frigate_api_mock
+        .expect_review()
+        .returning(move |id| match id {
+            "id-disabled" => Ok(make_api_review(id, disabled_camera)),
+            "id-forced" => Ok(make_api_review(id, forced_camera)),
+            _ => Err(FrigateApiError::NotFound(format!(
+                "Unknown review id in test: {id}"
+            ))),
+        });
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"012345".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    let (_mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        upload_dests.clone(),
+        Arc::new(frigate_api_config),
+        Arc::new(SyncSystemConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        Some(command_receiver),
+        Some(stop_receiver),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    // Recordings are disabled for all cameras, so without forcing, this is ignored.
+    // Commands are processed in order, so it's done by the time the next one is processed.
+    command_sender
+        .send(SyncSystemCommand::SyncReview {
+            id: "id-disabled".to_string(),
+            force: false,
+        })
+        .unwrap();
+
+    command_sender
+        .send(SyncSystemCommand::SyncReview {
+            id: "id-forced".to_string(),
+            force: true,
+        })
+        .unwrap();
+
+    // An upload is counted once its task is done, so the files are there by then
+    let upload_stats = tokio::time::timeout(VERY_LONG_WAIT, async {
+        loop {
+            let upload_stats = get_upload_stats(&command_sender).await;
+            if upload_stats.camera(forced_camera).recordings_uploaded > 0 {
+                break upload_stats;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(upload_stats.camera(forced_camera).recordings_uploaded, 1);
+    assert_eq!(upload_stats.camera(disabled_camera).recordings_uploaded, 0);
+
+    let file_sender = file_sender_maker(&upload_dests.path_descriptors[0]).unwrap();
+    let files = file_sender.ls(&PathBuf::from("1970-01-01")).await.unwrap();
+    assert!(!files.is_empty());
+    for file in &files {
+        assert_str_contains(&file.display().to_string(), forced_camera);
+    }
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn mqtt_commands_are_run_and_their_results_published() {
+    let camera = "front";
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_stats().returning(|| {
+        Ok(Box::new(TestStats {
+            uptime: std::time::Duration::from_secs(10000),
+        }))
+    });
+    frigate_api_mock
+        .expect_review()
+        .returning(move |id| Ok(make_api_review(id, camera)));
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"012345".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender.clone());
+
+    let (_mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (mqtt_command_sender, mqtt_command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (publication_sender, mut publication_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        PathDescriptors {
+            path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/data"))]),
+        },
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig::default()),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        Some(command_receiver),
+        Some(stop_receiver),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+    let commands_task = tokio::task::spawn(run_mqtt_commands(
+        mqtt_command_receiver,
+        command_sender,
+        publication_sender,
+        "snapsync".to_string(),
+    ));
+
+    // Recordings are disabled for the camera, since no state arrived from mqtt, so it has to be forced
+    mqtt_command_sender
+        .send(MqttCommand::SyncReview {
+            id: "id-1".to_string(),
+            force: true,
+        })
+        .unwrap();
+
+    let upload_stats = tokio::time::timeout(VERY_LONG_WAIT, async {
+        loop {
+            mqtt_command_sender
+                .send(MqttCommand::GetUploadStats)
+                .unwrap();
+            let publication = publication_receiver.recv().await.unwrap();
+            assert_eq!(publication.topic, "snapsync/upload_stats");
+
+            let upload_stats =
+                serde_json::from_slice::<serde_json::Value>(&publication.payload).unwrap();
+            if upload_stats[camera]["recordings_uploaded"] == 1 {
+                break upload_stats;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(upload_stats[camera]["last_recording_upload"].is_f64());
+    assert_eq!(upload_stats[camera]["snapshots_uploaded"], 0);
+
+    mqtt_command_sender
+        .send(MqttCommand::GetDegradedDestinations)
+        .unwrap();
+    let publication = publication_receiver.recv().await.unwrap();
+    assert_eq!(publication.topic, "snapsync/degraded_destinations");
+    assert_eq!(publication.payload, b"[]");
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    // Running commands stops once the sync system is gone
+    mqtt_command_sender
+        .send(MqttCommand::GetUploadStats)
+        .unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, commands_task)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn disabled_snapshots_sync_uploads_no_snapshots(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            temp_dir.path().to_owned(),
+        ))]),
+    };
+
+    let frigate_api_config = FrigateApiConfig::default();
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_stats().returning(|| {
+        Ok(Box::new(TestStats {
+            uptime: std::time::Duration::from_secs(10000),
+        }))
+    });
+    frigate_api_mock
+        .expect_recording_clip()
+        .returning(|_, _, _| Ok(Some(b"012345".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_config = SyncSystemConfig {
+        enable_snapshots_sync: false,
+        ..SyncSystemConfig::default()
+    };
+
+    let sync_sys = SyncSystem::new(
+        upload_dests.clone(),
+        Arc::new(frigate_api_config),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        Some(stop_receiver),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    let camera_label = gen_random_string(&mut rng, 10..20);
+
+    // Enable both snapshots and recordings for the camera in Frigate
+    mqtt_data_sender
+        .send(CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+            camera_label: camera_label.clone(),
+            state: true,
+        }))
+        .unwrap();
+    mqtt_data_sender
+        .send(CapturedPayloads::CameraRecordingsState(
+            mqtt_handler::types::recordings_state::RecordingsState {
+                camera_label: camera_label.clone(),
+                state: true,
+            },
+        ))
+        .unwrap();
+
+    mqtt_data_sender
+        .send(CapturedPayloads::Snapshot(Arc::new(Snapshot {
+            image_bytes: gen_random_bytes(&mut rng, 100..1000),
+            camera_label: camera_label.clone(),
+            object_name: Some(gen_random_string(&mut rng, 10..20)),
+        })))
+        .unwrap();
+
+    // Mqtt data is processed in order, so once the recording is uploaded, the snapshot was already handled
+    mqtt_data_sender
+        .send(CapturedPayloads::Reviews(Arc::new(TestReviewData {
+            camera_name: camera_label.clone(),
+            start_time: 950.,
+            end_time: Some(1000.),
+            id: "id-abcdefg".to_string(),
+            type_field: payload::TypeField::End,
+            activity: None,
+        })))
+        .unwrap();
+
+    let file_sender = file_sender_maker(&upload_dests.path_descriptors[0]).unwrap();
+    let expected_dir = PathBuf::from("1970-01-01");
+
+    tokio::time::timeout(VERY_LONG_WAIT, async {
+        loop {
+            let dirs = file_sender.ls(Path::new(".")).await.unwrap();
+            if dirs.contains(&expected_dir)
+                && !file_sender.ls(&expected_dir).await.unwrap().is_empty()
+            {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // Stopping waits for all the running uploads to finish
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+        for file in file_sender.ls(&dir).await.unwrap() {
+            assert_str_starts_with(&file.display().to_string(), "RecordingClip-");
+        }
+    }
+}
+
+/// A Frigate API that's reachable, and has nothing to upload
+fn make_idle_frigate_api_maker()
+-> impl Fn(&FrigateApiConfig) -> anyhow::Result<Arc<dyn FrigateApi>> + Send + Sync + 'static {
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_stats().returning(|| {
+        Ok(Box::new(TestStats {
+            uptime: std::time::Duration::from_secs(10000),
+        }))
+    });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone())
+}
+
+async fn get_degraded_destinations(
+    sender: &UnboundedSender<SyncSystemCommand>,
+) -> Vec<Arc<PathDescriptor>> {
+    let (result_sender, result_receiver) = oneshot::channel();
+    sender
+        .send(SyncSystemCommand::GetDegradedDestinations(result_sender))
+        .unwrap();
+    result_receiver.await.unwrap()
+}
+
+#[tokio::test]
+async fn unreachable_destination_fails_startup_when_all_are_required() {
+    let descriptor = Arc::new(PathDescriptor::local("/unreachable"));
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![descriptor.clone()]),
+    };
+
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| {
+        let mut store_mock = make_store_mock();
+        store_mock
+            .expect_init()
+            .returning(|| Err(anyhow::anyhow!("Faked unreachable destination")));
+        Ok(Arc::new(store_mock)
+            as Arc<
+                dyn file_sender::traits::StoreDestination<Error = anyhow::Error>,
+            >)
+    };
+
+    let (_mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+
+    let sync_config = SyncSystemConfig {
+        require_all_destinations: true,
+        ..SyncSystemConfig::default()
+    };
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(sync_config),
+        make_idle_frigate_api_maker(),
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        None,
+    );
+
+    let error = tokio::time::timeout(VERY_LONG_WAIT, sync_sys.start())
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_str_contains(&error.to_string(), &descriptor.to_string());
+    assert_str_contains(&error.to_string(), "Faked unreachable destination");
+}
+
+#[tokio::test]
+async fn unreachable_destination_is_degraded_and_retried() {
+    let descriptor = Arc::new(PathDescriptor::local("/unreachable"));
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![descriptor.clone()]),
+    };
+
+    let reachable = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let reachable_inner = reachable.clone();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| {
+        let reachable = reachable_inner.clone();
+        let mut store_mock = make_store_mock();
+        store_mock.expect_init().returning(|| Ok(()));
+        store_mock.expect_health_check().returning(move || {
+            if reachable.load(std::sync::atomic::Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Faked unreachable destination"))
+            }
+        });
+        Ok(Arc::new(store_mock)
+            as Arc<
+                dyn file_sender::traits::StoreDestination<Error = anyhow::Error>,
+            >)
+    };
+
+    let (_mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_config = SyncSystemConfig {
+        require_all_destinations: false,
+        destination_retry_period: std::time::Duration::from_millis(10),
+        ..SyncSystemConfig::default()
+    };
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(sync_config),
+        make_idle_frigate_api_maker(),
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        Some(command_receiver),
+        Some(stop_receiver),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    // The system keeps running, with the destination marked degraded while it's unreachable
+    assert_eq!(
+        get_degraded_destinations(&command_sender).await,
+        vec![descriptor.clone()]
+    );
+
+    reachable.store(true, std::sync::atomic::Ordering::SeqCst);
+
+    tokio::time::timeout(VERY_LONG_WAIT, async {
+        while !get_degraded_destinations(&command_sender).await.is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn cameras_republished_after_frigate_is_back_online_are_not_pruned() {
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/data"))]),
+    };
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender.clone());
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (camera_state_getter_sender, camera_state_getter_receiver) =
+        tokio::sync::mpsc::unbounded_channel();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig::default()),
+        make_idle_frigate_api_maker(),
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        Some(camera_state_getter_receiver),
+        None,
+        Some(stop_receiver),
+    );
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    let availability = |online| {
+        CapturedPayloads::FrigateAvailability(
+            mqtt_handler::types::availability::FrigateAvailability { online },
+        )
+    };
+    // With the clock paused, this only returns once the system has processed everything it was sent
+    let settle = || tokio::time::sleep(std::time::Duration::from_millis(10));
+
+    mqtt_data_sender.send(recordings_enabled("front")).unwrap();
+    mqtt_data_sender
+        .send(recordings_enabled("removed"))
+        .unwrap();
+    // Frigate restarts, and says it's online before republishing its cameras
+    mqtt_data_sender.send(availability(false)).unwrap();
+    mqtt_data_sender.send(availability(true)).unwrap();
+    settle().await;
+    mqtt_data_sender.send(recordings_enabled("front")).unwrap();
+    settle().await;
+
+    let camera_state = get_camera_state(&camera_state_getter_sender).await;
+    assert!(camera_state.camera_recordings_state("front"));
+    assert!(camera_state.camera_recordings_state("removed"));
+
+    tokio::time::sleep(CAMERA_REPUBLISH_GRACE).await;
+    let camera_state = get_camera_state(&camera_state_getter_sender).await;
+    assert!(camera_state.camera_recordings_state("front"));
+    assert!(!camera_state.camera_recordings_state("removed"));
+
+    stop_sender.send(()).unwrap();
+    task_handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[rstest]
+#[trace]
+async fn syncsystem_runs_with_builder_config(random_seed: Seed) {
+    let mut rng = make_seedable_rng(random_seed);
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config = VideoSyncConfig::builder("localhost", "http://example.com")
+        .mqtt_port(1884)
+        .mqtt_credentials("user", "password")
+        .mqtt_client_id("embedded-snap-sync")
+        .frigate_api_proxy("http://proxy:3128")
+        .frigate_api_proxy_auth(ProxyAuth {
+            username: "proxy-user".to_string(),
+            password: "proxy-password".to_string(),
+        })
+        .upload_destination(PathDescriptor::local(temp_dir.path().to_owned()))
+        .require_all_destinations(true)
+        .gate_on_stats_failure(GateOnStatsFailure::Block)
+        .shutdown_timeout(std::time::Duration::from_secs(30))
+        .enable_recordings_sync(false)
+        .force_enable_camera("front_*")
+        .build()
+        .unwrap();
+
+    let mqtt_config = MqttHandlerConfig::from(&config);
+    assert_eq!(mqtt_config.mqtt_port, 1884);
+    assert_eq!(mqtt_config.mqtt_client_id, "embedded-snap-sync");
+    assert_eq!(mqtt_config.mqtt_password.as_deref(), Some("password"));
+
+    let frigate_api_config = FrigateApiConfig::from(&config);
+    assert_eq!(
+        frigate_api_config.frigate_api_proxy.as_deref(),
+        Some("http://proxy:3128")
+    );
+
+    let sync_config = SyncSystemConfig::from(&config);
+    assert!(sync_config.require_all_destinations);
+    assert!(!sync_config.enable_recordings_sync);
+
+    let upload_dests = config.upload_destinations().clone();
+    let file_sender_maker = move |pd: &Arc<PathDescriptor>| make_store(pd);
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        upload_dests.clone(),
+        Arc::new(frigate_api_config),
+        Arc::new(sync_config),
+        make_idle_frigate_api_maker(),
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        Some(stop_receiver),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    // Snapshots of the camera are uploaded without being enabled in Frigate, as it's forced in the config
+    mqtt_data_sender
+        .send(CapturedPayloads::Snapshot(Arc::new(Snapshot {
+            image_bytes: gen_random_bytes(&mut rng, 100..1000),
+            camera_label: "front_door".to_string(),
+            object_name: Some(gen_random_string(&mut rng, 10..20)),
+        })))
+        .unwrap();
+
+    let file_sender = file_sender_maker(&upload_dests.path_descriptors[0]).unwrap();
+
+    tokio::time::timeout(VERY_LONG_WAIT, async {
+        loop {
+            let mut uploaded = false;
+            for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+                uploaded |= !file_sender.ls(&dir).await.unwrap().is_empty();
+            }
+            if uploaded {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn camera_state_changes_are_notified_only_on_changes() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            temp_dir.path().to_owned(),
+        ))]),
+    };
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig::default()),
+        make_idle_frigate_api_maker(),
+        move |pd: &Arc<PathDescriptor>| make_store(pd),
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        Some(stop_receiver),
+    );
+    let mut changes = sync_sys.subscribe_camera_state_changes();
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    let recordings = |camera: &str, state| {
+        CapturedPayloads::CameraRecordingsState(
+            mqtt_handler::types::recordings_state::RecordingsState {
+                camera_label: camera.to_string(),
+                state,
+            },
+        )
+    };
+    let snapshots = |camera: &str, state| {
+        CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+            camera_label: camera.to_string(),
+            state,
+        })
+    };
+
+    for payload in [
+        recordings("front", true),
+        recordings("front", true),
+        snapshots("front", false),
+        snapshots("front", false),
+        recordings("front", false),
+        recordings("front", false),
+        // Mqtt data is processed in order, so once this is notified, all the above were handled
+        snapshots("back", true),
+    ] {
+        mqtt_data_sender.send(payload).unwrap();
+    }
+
+    let change = |camera: &str, kind, old_state, new_state| CameraStateChange {
+        camera_name: camera.to_string(),
+        kind,
+        old_state,
+        new_state,
+    };
+    let expected = [
+        change("front", CameraStateKind::Recordings, None, true),
+        change("front", CameraStateKind::Snapshots, None, false),
+        change("front", CameraStateKind::Recordings, Some(true), false),
+        change("back", CameraStateKind::Snapshots, None, true),
+    ];
+    for expected in expected {
+        let received = tokio::time::timeout(VERY_LONG_WAIT, changes.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, expected);
+    }
+    assert!(changes.try_recv().is_err());
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+/// Sends every notification it receives to the test
+struct ChannelServiceNotifier(UnboundedSender<ServiceState>);
+
+impl ServiceNotifier for ChannelServiceNotifier {
+    fn notify(&self, state: ServiceState) {
+        self.0.send(state).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn service_watchdog_is_notified_from_event_loop() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local(
+            temp_dir.path().to_owned(),
+        ))]),
+    };
+
+    let (_mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (notification_sender, mut notification_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig::default()),
+        make_idle_frigate_api_maker(),
+        move |pd: &Arc<PathDescriptor>| make_store(pd),
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        Some(stop_receiver),
+    )
+    .with_service_notifier(
+        Arc::new(ChannelServiceNotifier(notification_sender)),
+        Some(std::time::Duration::from_millis(10)),
+    );
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    // The watchdog keeps being notified while the event loop runs
+    for _ in 0..3 {
+        let notification = tokio::time::timeout(VERY_LONG_WAIT, notification_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification, ServiceState::Watchdog);
+    }
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let mut remaining = Vec::new();
+    while let Ok(notification) = notification_receiver.try_recv() {
+        remaining.push(notification);
+    }
+    assert_eq!(remaining.last(), Some(&ServiceState::Stopping));
+    assert!(
+        remaining[..remaining.len() - 1]
+            .iter()
+            .all(|n| *n == ServiceState::Watchdog)
+    );
+}
+
+/// Records the message of every heartbeat logged while it's the active subscriber, with when it was logged
+#[derive(Clone, Default)]
+struct HeartbeatRecorder {
+    heartbeats: Arc<std::sync::Mutex<Vec<(tokio::time::Instant, String)>>>,
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for HeartbeatRecorder {
+    // Interest is cached globally, so only the log lines of the system itself are evaluated for other tests too
+    fn register_callsite(
+        &self,
+        metadata: &'static tracing::Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        if metadata.is_event()
+            && metadata.module_path() == Some(module_path!().trim_end_matches("::tests"))
+        {
+            tracing::subscriber::Interest::always()
+        } else {
+            tracing::subscriber::Interest::never()
+        }
+    }
+
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut message = EventMessage::default();
+        event.record(&mut message);
+        if message.0.contains("heartbeat") {
+            self.heartbeats
+                .lock()
+                .unwrap()
+                .push((tokio::time::Instant::now(), message.0));
+        }
+    }
+}
+
+#[derive(Default)]
+struct EventMessage(String);
+
+impl tracing::field::Visit for EventMessage {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn heartbeat_is_logged_at_the_configured_interval() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+    let recorder = HeartbeatRecorder::default();
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::Registry::default().with(recorder.clone()),
+    );
+
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/home/data/"))]),
+    };
+    let file_sender = file_sender::make_inmemory_filesystem();
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (mqtt_status_sender, mqtt_status_receiver) = tokio::sync::watch::channel(true);
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig {
+            heartbeat_interval: Some(INTERVAL),
+            enable_recordings_sync: false,
+            ..SyncSystemConfig::default()
+        }),
+        make_idle_frigate_api_maker(),
+        move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()),
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        Some(stop_receiver),
+    )
+    .with_mqtt_connection_status(mqtt_status_receiver);
+
+    let start = tokio::time::Instant::now();
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    tokio::time::sleep(INTERVAL * 2 + INTERVAL / 2).await;
+    mqtt_status_sender.send_replace(false);
+    mqtt_data_sender
+        .send(CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+            camera_label: "front".to_string(),
+            state: true,
+        }))
+        .unwrap();
+    tokio::time::sleep(INTERVAL).await;
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let heartbeats = recorder.heartbeats.lock().unwrap().clone();
+    let times = heartbeats
+        .iter()
+        .map(|(time, _)| *time - start)
+        .collect::<Vec<_>>();
+    assert_eq!(times, vec![INTERVAL, INTERVAL * 2, INTERVAL * 3]);
+
+    let messages = heartbeats
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect::<Vec<_>>();
+    for message in &messages {
+        assert_str_contains(message, "Running recording tasks: disabled");
+        assert_str_contains(message, "Running snapshot tasks: 0");
+    }
+    assert_str_contains(&messages[1], "MQTT: connected");
+    assert_str_contains(&messages[1], "Last event: none yet");
+    assert_str_contains(&messages[2], "MQTT: disconnected");
+    assert_str_contains(&messages[2], "Last event: 30s ago");
+}
+
+#[tokio::test]
+#[rstest]
+#[case(true)]
+#[case(false)]
+async fn latest_snapshots_are_fetched_on_mqtt_reconnect(#[case] fetch_on_reconnect: bool) {
+    // Gives the event loop the time to see a change before the next one
+    async fn settle() {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/home/data/"))]),
+    };
+    let file_sender = file_sender::make_inmemory_filesystem();
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    // Only the camera with snapshots enabled is fetched, and only on reconnecting, not on connecting
+    frigate_api_mock
+        .expect_latest_snapshot()
+        .withf(|camera| camera == "front")
+        .times(usize::from(fetch_on_reconnect))
+        .returning(|_| Ok(Some(b"latest frame".to_vec())));
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+
+    let (mqtt_data_sender, mqtt_data_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<CapturedPayloads>();
+    let (command_sender, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (stop_sender, stop_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let (mqtt_status_sender, mqtt_status_receiver) = tokio::sync::watch::channel(false);
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(SyncSystemConfig {
+            fetch_latest_snapshot_on_reconnect: fetch_on_reconnect,
+            enable_recordings_sync: false,
+            ..SyncSystemConfig::default()
+        }),
+        move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone()),
+        move |_: &Arc<PathDescriptor>| Ok(file_sender.clone()),
+        mqtt_data_receiver,
+        None,
+        None,
+        Some(command_receiver),
+        Some(stop_receiver),
+    )
+    .with_mqtt_connection_status(mqtt_status_receiver);
+
+    let task_handle = tokio::task::spawn(async move { sync_sys.start().await });
+
+    mqtt_status_sender.send_replace(true);
+    for (camera, state) in [("front", true), ("back", false)] {
+        mqtt_data_sender
+            .send(CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+                camera_label: camera.to_string(),
+                state,
+            }))
+            .unwrap();
+    }
+    settle().await;
+    mqtt_status_sender.send_replace(false);
+    settle().await;
+    mqtt_status_sender.send_replace(true);
+    settle().await;
+
+    if fetch_on_reconnect {
+        tokio::time::timeout(VERY_LONG_WAIT, async {
+            while get_upload_stats(&command_sender)
+                .await
+                .camera("front")
+                .snapshots_uploaded
+                == 0
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+    let upload_stats = get_upload_stats(&command_sender).await;
+    assert_eq!(
+        upload_stats.camera("front").snapshots_uploaded,
+        u64::from(fetch_on_reconnect)
+    );
+    assert_eq!(upload_stats.camera("back").snapshots_uploaded, 0);
+
+    stop_sender.send(()).unwrap();
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+/// What a sync system did while processing mqtt data
+struct FlowOutcome {
+    /// The start times of the clips requested from Frigate
+    requested_clips: Vec<f64>,
+    uploaded_files: Vec<PathBuf>,
+}
+
+/// Runs a sync system on the given mqtt data, with a Frigate that returns a clip for any review,
+/// until all the data is processed and the uploads it started are finished
+async fn run_on_mqtt_data(
+    sync_config: SyncSystemConfig,
+    retain_modes: HashMap<String, RetainMode>,
+    mqtt_data: Vec<CapturedPayloads>,
+) -> FlowOutcome {
+    let requested_clips = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(move || Ok(retain_modes.clone()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_stats().returning(|| {
+        Ok(Box::new(TestStats {
+            uptime: std::time::Duration::from_secs(10000),
+        }))
+    });
+    frigate_api_mock.expect_recording_clip().returning({
+        let requested_clips = requested_clips.clone();
+        move |_, start_ts, _| {
+            requested_clips.lock().unwrap().push(start_ts);
+            Ok(Some(b"012345".to_vec()))
+        }
+    });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = {
+        let file_sender = file_sender.clone();
+        move |_: &Arc<PathDescriptor>| Ok(file_sender.clone())
+    };
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/data"))]),
+    };
+
+    let (mqtt_data_sender, mqtt_data_receiver) = tokio::sync::mpsc::unbounded_channel();
+    for data in mqtt_data {
+        mqtt_data_sender.send(data).unwrap();
+    }
+    drop(mqtt_data_sender);
+
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_stop_on_mqtt_data_end();
+    tokio::time::timeout(VERY_LONG_WAIT, sync_sys.start())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut uploaded_files = Vec::new();
+    for dir in file_sender.ls(Path::new(".")).await.unwrap() {
+        for file in file_sender.ls(&dir).await.unwrap() {
+            uploaded_files.push(dir.join(file));
+        }
+    }
+
+    let requested_clips = requested_clips.lock().unwrap().clone();
+    FlowOutcome {
+        requested_clips,
+        uploaded_files,
+    }
+}
+
+fn recordings_enabled(camera_label: &str) -> CapturedPayloads {
+    CapturedPayloads::CameraRecordingsState(
+        mqtt_handler::types::recordings_state::RecordingsState {
+            camera_label: camera_label.to_string(),
+            state: true,
+        },
+    )
+}
+
+fn review_payload(
+    camera_name: &str,
+    id: &str,
+    start_time: f64,
+    end_time: Option<f64>,
+) -> CapturedPayloads {
+    review_payload_with_activity(camera_name, id, start_time, end_time, None)
+}
+
+fn review_payload_with_activity(
+    camera_name: &str,
+    id: &str,
+    start_time: f64,
+    end_time: Option<f64>,
+    activity: Option<bool>,
+) -> CapturedPayloads {
+    CapturedPayloads::Reviews(Arc::new(TestReviewData {
+        camera_name: camera_name.to_string(),
+        start_time,
+        end_time,
+        id: id.to_string(),
+        type_field: if end_time.is_some() {
+            payload::TypeField::End
+        } else {
+            payload::TypeField::Update
+        },
+        activity,
+    }))
+}
+
+#[tokio::test]
+async fn reviews_shorter_than_minimum_duration_are_not_uploaded() {
+    let now = utils::time::get_time().as_unix_timestamp_f64();
+    let sync_config = SyncSystemConfig {
+        min_review_duration: std::time::Duration::from_secs(30),
+        ..SyncSystemConfig::default()
+    };
+
+    let outcome = run_on_mqtt_data(
+        sync_config,
+        HashMap::new(),
+        vec![
+            recordings_enabled("front"),
+            review_payload("front", "short", 950., Some(960.)),
+            // Not long enough so far, so it's deferred
+            review_payload("front", "ongoing", now, None),
+            review_payload("front", "long", 900., Some(1000.)),
+        ],
+    )
+    .await;
+
+    assert!(!outcome.uploaded_files.is_empty());
+    assert!(
+        outcome
+            .requested_clips
+            .iter()
+            .all(|start| (*start - 900.).abs() < f64::EPSILON),
+        "Requested clips: {:?}",
+        outcome.requested_clips
+    );
+}
+
+#[tokio::test]
+async fn ongoing_review_is_uploaded_once_longer_than_minimum_duration() {
+    let requested_clips = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_recording_clip().returning({
+        let requested_clips = requested_clips.clone();
+        move |_, start_ts, _| {
+            requested_clips.lock().unwrap().push(start_ts);
+            Ok(Some(b"012345".to_vec()))
+        }
+    });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender.clone());
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/data"))]),
+    };
+
+    let min_review_duration = std::time::Duration::from_secs(1);
+    let sync_config = SyncSystemConfig {
+        min_review_duration,
+        // The review never ends, so its upload task is abandoned on shutdown
+        shutdown_timeout: std::time::Duration::from_secs(1),
+        ..SyncSystemConfig::default()
+    };
+
+    let (mqtt_data_sender, mqtt_data_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_stop_on_mqtt_data_end();
+    let task_handle = tokio::task::spawn(sync_sys.start());
+
+    let now = utils::time::get_time().as_unix_timestamp_f64();
+    mqtt_data_sender.send(recordings_enabled("front")).unwrap();
+    mqtt_data_sender
+        .send(review_payload("front", "ongoing", now, None))
+        .unwrap();
+    // Ends before it's long enough, so it's never uploaded
+    mqtt_data_sender
+        .send(review_payload("front", "blip", now - 0.5, None))
+        .unwrap();
+    mqtt_data_sender
+        .send(review_payload("front", "blip", now - 0.5, Some(now - 0.3)))
+        .unwrap();
+
+    tokio::time::sleep(min_review_duration / 2).await;
+    mqtt_data_sender
+        .send(review_payload("front", "ongoing", now, None))
+        .unwrap();
+    tokio::time::sleep(min_review_duration / 4).await;
+    assert!(requested_clips.lock().unwrap().is_empty());
+
+    // Crosses the minimum duration between its updates, while it's still ongoing
+    tokio::time::sleep(min_review_duration).await;
+    mqtt_data_sender
+        .send(review_payload("front", "ongoing", now, None))
+        .unwrap();
+    drop(mqtt_data_sender);
+
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let requested_clips = requested_clips.lock().unwrap().clone();
+    assert!(!requested_clips.is_empty());
+    assert!(
+        requested_clips
+            .iter()
+            .all(|start| (*start - now).abs() < f64::EPSILON),
+        "Requested clips: {requested_clips:?}"
+    );
+}
+
+#[tokio::test]
+async fn deferred_review_is_not_uploaded_after_recordings_are_disabled() {
+    let min_review_duration = std::time::Duration::from_secs(1);
+    let sync_config = SyncSystemConfig {
+        min_review_duration,
+        shutdown_timeout: std::time::Duration::from_secs(1),
+        ..SyncSystemConfig::default()
+    };
+    let now = utils::time::get_time().as_unix_timestamp_f64();
+
+    let requested_clips = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut frigate_api_mock = make_frigate_client_mock();
+    frigate_api_mock
+        .expect_recordings_retain_modes()
+        .returning(|| Ok(HashMap::new()));
+    frigate_api_mock.expect_test_call().returning(|| Ok(()));
+    frigate_api_mock.expect_recording_clip().returning({
+        let requested_clips = requested_clips.clone();
+        move |_, start_ts, _| {
+            requested_clips.lock().unwrap().push(start_ts);
+            Ok(Some(b"012345".to_vec()))
+        }
+    });
+    let frigate_api_mock: Arc<dyn FrigateApi> = Arc::new(frigate_api_mock);
+    let frigate_api_maker = move |_: &FrigateApiConfig| Ok(frigate_api_mock.clone());
+
+    let file_sender = make_inmemory_filesystem();
+    let file_sender_maker = move |_: &Arc<PathDescriptor>| Ok(file_sender.clone());
+    let upload_dests = PathDescriptors {
+        path_descriptors: Arc::new(vec![Arc::new(PathDescriptor::local("/data"))]),
+    };
+
+    let (mqtt_data_sender, mqtt_data_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let sync_sys = SyncSystem::new(
+        upload_dests,
+        Arc::new(FrigateApiConfig::default()),
+        Arc::new(sync_config),
+        frigate_api_maker,
+        file_sender_maker,
+        mqtt_data_receiver,
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_stop_on_mqtt_data_end();
+    let task_handle = tokio::task::spawn(sync_sys.start());
+
+    mqtt_data_sender.send(recordings_enabled("front")).unwrap();
+    // Not long enough yet, so it's deferred
+    mqtt_data_sender
+        .send(review_payload("front", "ongoing", now, None))
+        .unwrap();
+    mqtt_data_sender
+        .send(CapturedPayloads::CameraRecordingsState(
+            mqtt_handler::types::recordings_state::RecordingsState {
+                camera_label: "front".to_string(),
+                state: false,
+            },
+        ))
+        .unwrap();
+
+    // The deferred review becomes due while recordings are disabled
+    tokio::time::sleep(min_review_duration * 2).await;
+    drop(mqtt_data_sender);
+
+    tokio::time::timeout(VERY_LONG_WAIT, task_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    assert!(
+        requested_clips.lock().unwrap().is_empty(),
+        "Requested clips: {:?}",
+        requested_clips.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn reviews_without_activity_are_not_uploaded_when_only_motion_is_retained() {
+    let outcome = run_on_mqtt_data(
+        SyncSystemConfig::default(),
+        HashMap::from([("front".to_string(), RetainMode::Motion)]),
+        vec![
+            recordings_enabled("front"),
+            review_payload_with_activity("front", "nothing", 100., Some(110.), Some(false)),
+            // E.g., audio only
+            review_payload_with_activity("front", "activity", 200., Some(210.), Some(true)),
+            // E.g., its data was malformed
+            review_payload_with_activity("front", "unknown", 300., Some(310.), None),
+        ],
+    )
+    .await;
+
+    let mut requested_clips = outcome.requested_clips;
+    requested_clips.sort_by(f64::total_cmp);
+    requested_clips.dedup();
+    assert_eq!(requested_clips, vec![200., 300.]);
+}
+
+#[tokio::test]
+async fn snapshots_are_matched_to_reviews_that_pass_the_filters_on_any_message() {
+    let sync_config = SyncSystemConfig {
+        snapshot_mode: SnapshotMode::OnReview,
+        min_review_duration: std::time::Duration::from_secs(30),
+        ..SyncSystemConfig::default()
+    };
+
+    let snapshot = |camera_label: &str| {
+        CapturedPayloads::Snapshot(Arc::new(Snapshot {
+            image_bytes: b"snapshot".to_vec(),
+            camera_label: camera_label.to_string(),
+            object_name: None,
+        }))
+    };
+    let snapshots_enabled = |camera_label: &str| {
+        CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+            camera_label: camera_label.to_string(),
+            state: true,
+        })
+    };
+
+    // Snapshots are buffered at the time they're received, so the reviews start now
+    let now = utils::time::get_time().as_unix_timestamp_f64();
+    let outcome = run_on_mqtt_data(
+        sync_config,
+        HashMap::new(),
+        vec![
+            recordings_enabled("front"),
+            recordings_enabled("back"),
+            snapshots_enabled("front"),
+            snapshots_enabled("back"),
+            snapshot("front"),
+            snapshot("front"),
+            snapshot("back"),
+            // Its `new` message was missed, and it's deferred until it's known to be long enough, once it ends
+            review_payload("front", "long", now, None),
+            review_payload("front", "long", now, Some(now + 100.)),
+            // Filtered out, so its snapshot isn't uploaded
+            review_payload("back", "short", now, Some(now + 10.)),
+        ],
+    )
+    .await;
+
+    let snapshot_files = outcome
+        .uploaded_files
+        .iter()
+        .filter_map(|path| path.file_name()?.to_str())
+        .filter(|name| name.starts_with("Snapshot-"))
+        .collect::<Vec<_>>();
+    assert_eq!(snapshot_files.len(), 1, "Uploaded: {snapshot_files:?}");
+    assert_str_starts_with(snapshot_files[0], "Snapshot-front-");
+}
+
+#[tokio::test]
+async fn one_snapshot_is_uploaded_per_review_with_many_messages() {
+    let sync_config = SyncSystemConfig {
+        snapshot_mode: SnapshotMode::OnReview,
+        ..SyncSystemConfig::default()
+    };
+
+    // Each has its own object name, so that snapshots uploaded within the same second have different file names
+    let snapshot = |object_name: &str| {
+        CapturedPayloads::Snapshot(Arc::new(Snapshot {
+            image_bytes: b"snapshot".to_vec(),
+            camera_label: "front".to_string(),
+            object_name: Some(object_name.to_string()),
+        }))
+    };
+
+    let now = utils::time::get_time().as_unix_timestamp_f64();
+    let outcome = run_on_mqtt_data(
+        sync_config,
+        HashMap::new(),
+        vec![
+            recordings_enabled("front"),
+            CapturedPayloads::CameraSnapshotsState(SnapshotsState {
+                camera_label: "front".to_string(),
+                state: true,
+            }),
+            snapshot("person"),
+            snapshot("car"),
+            snapshot("dog"),
+            review_payload("front", "review", now, None),
+            review_payload("front", "review", now, None),
+            review_payload("front", "review", now, Some(now + 10.)),
+        ],
+    )
+    .await;
+
+    let snapshot_files = outcome
+        .uploaded_files
+        .iter()
+        .filter_map(|path| path.file_name()?.to_str())
+        .filter(|name| name.starts_with("Snapshot-"))
+        .collect::<Vec<_>>();
+    assert_eq!(snapshot_files.len(), 1, "Uploaded: {snapshot_files:?}");
+}
@@ -0,0 +1,114 @@
+use crate::system::config::GateOnStatsFailure;
+use frigate_api_caller::traits::FrigateApi;
+use std::sync::Arc;
+
+/// Checks whether Frigate has been up for at least `delay`, which is when uploads can happen.
+/// If Frigate's uptime can't be retrieved, `on_stats_failure` decides whether uploads happen anyway.
+pub async fn has_upload_delay_passed(
+    frigate_api: anyhow::Result<Arc<dyn FrigateApi>>,
+    delay: std::time::Duration,
+    on_stats_failure: GateOnStatsFailure,
+) -> bool {
+    let uptime = match frigate_api {
+        Ok(frigate_api) => frigate_api
+            .stats()
+            .await
+            .map(|stats| stats.uptime())
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e.context("Creating the Frigate API caller failed")),
+    };
+
+    let uptime = match uptime {
+        Ok(uptime) => uptime,
+        Err(e) => {
+            let passed = on_stats_failure == GateOnStatsFailure::Allow;
+            tracing::warn!(
+                "Failed to retrieve the uptime of Frigate to check whether the delay after its startup has passed. Uploads are {} until it can be retrieved. Error: {e}",
+                if passed { "allowed" } else { "blocked" }
+            );
+            return passed;
+        }
+    };
+
+    if uptime >= delay {
+        true
+    } else {
+        tracing::info!(
+            "Delay after uptime has not passed yet. Upload will not happen. Frigate uptime: {} seconds. vs required delay: {} seconds",
+            uptime.as_secs(),
+            delay.as_secs()
+        );
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frigate_api_caller::{FrigateApiError, json::stats::StatsProps};
+    use mocks::frigate_api::make_frigate_client_mock;
+    use rstest::rstest;
+
+    const DELAY: std::time::Duration = std::time::Duration::from_secs(120);
+
+    struct TestStats {
+        uptime: std::time::Duration,
+    }
+
+    impl StatsProps for TestStats {
+        fn uptime(&self) -> std::time::Duration {
+            self.uptime
+        }
+    }
+
+    fn frigate_api_with_uptime(uptime: Result<u64, FrigateApiError>) -> Arc<dyn FrigateApi> {
+        let mut frigate_api_mock = make_frigate_client_mock();
+        frigate_api_mock.expect_stats().return_once(move || {
+            uptime.map(|uptime| {
+                Box::new(TestStats {
+                    uptime: std::time::Duration::from_secs(uptime),
+                }) as Box<dyn StatsProps>
+            })
+        });
+        Arc::new(frigate_api_mock)
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case(GateOnStatsFailure::Allow, true)]
+    #[case(GateOnStatsFailure::Block, false)]
+    async fn failing_stats(#[case] on_stats_failure: GateOnStatsFailure, #[case] expected: bool) {
+        let frigate_api = Ok(frigate_api_with_uptime(Err(FrigateApiError::Network(
+            "Connection refused".into(),
+        ))));
+        assert_eq!(
+            has_upload_delay_passed(frigate_api, DELAY, on_stats_failure).await,
+            expected
+        );
+
+        let frigate_api = Err(anyhow::anyhow!("Invalid proxy"));
+        assert_eq!(
+            has_upload_delay_passed(frigate_api, DELAY, on_stats_failure).await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case(0, false)]
+    #[case(119, false)]
+    #[case(120, true)]
+    #[case(10000, true)]
+    async fn uptime_against_delay(
+        #[case] uptime: u64,
+        #[case] expected: bool,
+        #[values(GateOnStatsFailure::Allow, GateOnStatsFailure::Block)]
+        on_stats_failure: GateOnStatsFailure,
+    ) {
+        let frigate_api = Ok(frigate_api_with_uptime(Ok(uptime)));
+        assert_eq!(
+            has_upload_delay_passed(frigate_api, DELAY, on_stats_failure).await,
+            expected
+        );
+    }
+}
@@ -0,0 +1,50 @@
+/// Which characters of names coming from outside (e.g., camera and object labels) are kept
+/// when they're embedded in file names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileNameCharset {
+    /// ASCII letters, digits, `-` and `_`
+    #[default]
+    Ascii,
+    /// Like `Ascii`, but letters and digits of any language are kept too
+    Unicode,
+}
+
+impl FileNameCharset {
+    fn is_allowed(self, c: char) -> bool {
+        match self {
+            FileNameCharset::Ascii => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+            FileNameCharset::Unicode => c.is_alphanumeric() || c == '-' || c == '_',
+        }
+    }
+}
+
+/// Makes a name safe to be a part of a file name, by replacing every character that isn't allowed with `_`.
+/// Path separators and dots are never allowed, so the result can't escape the directory it's put in.
+#[must_use]
+pub fn sanitize_file_name_part(name: &str, charset: FileNameCharset) -> String {
+    name.chars()
+        .map(|c| if charset.is_allowed(c) { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("front_door", FileNameCharset::Ascii, "front_door")]
+    #[case("Camera-2", FileNameCharset::Ascii, "Camera-2")]
+    #[case("a/b", FileNameCharset::Ascii, "a_b")]
+    #[case("..", FileNameCharset::Ascii, "__")]
+    #[case("../../etc/passwd", FileNameCharset::Ascii, "______etc_passwd")]
+    #[case("a\\b", FileNameCharset::Ascii, "a_b")]
+    #[case("back yard", FileNameCharset::Ascii, "back_yard")]
+    #[case("jardín", FileNameCharset::Ascii, "jard_n")]
+    #[case("jardín", FileNameCharset::Unicode, "jardín")]
+    #[case("車庫/..", FileNameCharset::Unicode, "車庫___")]
+    #[case("back yard", FileNameCharset::Unicode, "back_yard")]
+    fn sanitize(#[case] name: &str, #[case] charset: FileNameCharset, #[case] expected: &str) {
+        assert_eq!(sanitize_file_name_part(name, charset), expected);
+    }
+}
@@ -0,0 +1,60 @@
+use randomness::Rng;
+use std::path::Path;
+
+/// A new id that starts with `prefix`, followed by random hex digits, so that instances with the same prefix
+/// (e.g., the same host name) still get different ids
+#[must_use]
+pub fn new_instance_id(prefix: &str) -> String {
+    format!(
+        "{prefix}-{:016x}",
+        randomness::make_true_rng().random::<u64>()
+    )
+}
+
+/// Reads the id of this instance from `path`. The first time, a new one is made with `new_instance_id` and written
+/// there, so that it stays the same across restarts, and every instance with its own file has its own id.
+pub fn load_or_create_instance_id(path: &Path, prefix: &str) -> std::io::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(id) if !id.trim().is_empty() => return Ok(id.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let id = new_instance_id(prefix);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Written under a temporary name first, so that a crash doesn't leave a partial id behind
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, &id)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_id_is_created_once_and_kept() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("state").join("instance-id");
+
+        let id = load_or_create_instance_id(&path, "host").unwrap();
+        assert!(id.starts_with("host-"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), id);
+
+        // Restarts read the same id
+        assert_eq!(load_or_create_instance_id(&path, "host").unwrap(), id);
+    }
+
+    #[test]
+    fn instances_with_their_own_files_get_different_ids() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let first = load_or_create_instance_id(&temp_dir.path().join("first"), "host").unwrap();
+        let second = load_or_create_instance_id(&temp_dir.path().join("second"), "host").unwrap();
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,109 @@
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum InstanceLockError {
+    #[error(
+        "Lock file `{}` is held by another running instance. Stop it first, or give each instance its own config.",
+        path.display()
+    )]
+    HeldByAnother { path: PathBuf },
+    #[error("Opening lock file `{}` failed: {error}", path.display())]
+    Open {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("Locking lock file `{}` failed: {error}", path.display())]
+    Lock { path: PathBuf, error: Errno },
+}
+
+/// An advisory lock on a local file that only one process holds at a time, so that a second instance
+/// of the program with the same lock file refuses to start. The lock is released when this is dropped,
+/// or by the OS if the process dies, so a crash doesn't leave it held.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: Flock<File>,
+}
+
+impl InstanceLock {
+    /// Creates the file if it doesn't exist, and locks it without waiting. The id of this process is written
+    /// to it, to tell which process holds it.
+    pub fn acquire(path: &Path) -> Result<Self, InstanceLockError> {
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|error| InstanceLockError::Open {
+                path: path.to_owned(),
+                error,
+            })?;
+
+        let mut file =
+            Flock::lock(file, FlockArg::LockExclusiveNonblock).map_err(|(_, error)| {
+                if error == Errno::EWOULDBLOCK {
+                    InstanceLockError::HeldByAnother {
+                        path: path.to_owned(),
+                    }
+                } else {
+                    InstanceLockError::Lock {
+                        path: path.to_owned(),
+                        error,
+                    }
+                }
+            })?;
+
+        // Only informative, so failing to write it doesn't matter
+        let pid_written = file
+            .set_len(0)
+            .and_then(|()| writeln!(file, "{}", std::process::id()));
+        if let Err(e) = pid_written {
+            tracing::warn!(
+                "Writing the process id to lock file `{}` failed: {e}",
+                path.display()
+            );
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_acquisition_fails_while_first_holds_lock() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("snap-sync.lock");
+
+        let first = InstanceLock::acquire(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        let err = InstanceLock::acquire(&path).unwrap_err();
+        assert!(matches!(err, InstanceLockError::HeldByAnother { path: p } if p == path));
+
+        // Released once the first is dropped
+        drop(first);
+        InstanceLock::acquire(&path).unwrap();
+    }
+
+    #[test]
+    fn lock_file_in_missing_directory_fails_to_open() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("missing").join("snap-sync.lock");
+
+        let err = InstanceLock::acquire(&path).unwrap_err();
+        assert!(matches!(err, InstanceLockError::Open { .. }));
+    }
+}
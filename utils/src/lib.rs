@@ -1,5 +1,10 @@
 pub mod eq;
+pub mod file_name;
+pub mod instance_id;
+pub mod instance_lock;
+pub mod mdns;
 pub mod podman;
 pub mod struct_name;
+pub mod systemd;
 pub mod time;
 pub mod time_getter;
@@ -0,0 +1,237 @@
+use mdns_sd::{HostnameResolutionEvent, ServiceDaemon};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Hosts given with this prefix, e.g., `mdns:frigate.local`, are resolved with mDNS
+pub const MDNS_PREFIX: &str = "mdns:";
+
+const MDNS_RESOLUTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a resolved address is used before the hostname is resolved again
+const MDNS_ADDRESS_TTL: Duration = Duration::from_secs(60);
+
+/// Resolving may block for a while, e.g., until a query times out, so it's done outside the async runtime
+pub trait HostResolver: Send + Sync {
+    fn resolve(&self, hostname: &str) -> anyhow::Result<IpAddr>;
+}
+
+/// Resolves hostnames by querying the devices on the local network with mDNS.
+/// The daemon that sends the queries is started once and shared by all of them, and the resolved addresses
+/// are reused for `MDNS_ADDRESS_TTL`.
+#[derive(Default)]
+pub struct MdnsResolver {
+    daemon: Mutex<Option<ServiceDaemon>>,
+    addresses: Mutex<AddressCache>,
+}
+
+impl MdnsResolver {
+    fn daemon(&self) -> anyhow::Result<ServiceDaemon> {
+        let mut daemon = self.daemon.lock().expect("Poisoned mutex");
+        match daemon.as_ref() {
+            Some(daemon) => Ok(daemon.clone()),
+            None => Ok(daemon.insert(ServiceDaemon::new()?).clone()),
+        }
+    }
+
+    fn query(&self, hostname: &str) -> anyhow::Result<IpAddr> {
+        // mDNS works with fully qualified names
+        let hostname = if hostname.ends_with('.') {
+            hostname.to_string()
+        } else {
+            format!("{hostname}.")
+        };
+
+        let daemon = self.daemon()?;
+        let receiver = daemon.resolve_hostname(
+            &hostname,
+            Some(MDNS_RESOLUTION_TIMEOUT.as_millis().try_into()?),
+        )?;
+
+        let result = loop {
+            match receiver.recv() {
+                Ok(HostnameResolutionEvent::AddressesFound(_, addresses)) => {
+                    // IPv4 is preferred, as it's what most brokers and Frigate listen on
+                    let address = addresses
+                        .iter()
+                        .find(|a| a.is_ipv4())
+                        .or_else(|| addresses.iter().next())
+                        .copied();
+                    if let Some(address) = address {
+                        break Ok(address);
+                    }
+                }
+                Ok(HostnameResolutionEvent::SearchTimeout(_)) | Err(_) => {
+                    break Err(anyhow::anyhow!(
+                        "No device answered the mDNS query for `{hostname}` within {} seconds",
+                        MDNS_RESOLUTION_TIMEOUT.as_secs()
+                    ));
+                }
+                Ok(_) => (),
+            }
+        };
+
+        // The query is over either way
+        let _ = daemon.stop_resolve_hostname(&hostname);
+
+        result
+    }
+}
+
+impl HostResolver for MdnsResolver {
+    fn resolve(&self, hostname: &str) -> anyhow::Result<IpAddr> {
+        if let Some(address) = self
+            .addresses
+            .lock()
+            .expect("Poisoned mutex")
+            .get(hostname, Instant::now())
+        {
+            return Ok(address);
+        }
+
+        let address = self.query(hostname)?;
+        self.addresses
+            .lock()
+            .expect("Poisoned mutex")
+            .insert(hostname, address, Instant::now());
+        Ok(address)
+    }
+}
+
+impl Drop for MdnsResolver {
+    fn drop(&mut self) {
+        if let Some(daemon) = self.daemon.get_mut().ok().and_then(Option::take) {
+            let _ = daemon.shutdown();
+        }
+    }
+}
+
+/// The addresses that hostnames were resolved to, until they expire
+#[derive(Default)]
+struct AddressCache {
+    addresses: HashMap<String, (IpAddr, Instant)>,
+}
+
+impl AddressCache {
+    fn get(&self, hostname: &str, now: Instant) -> Option<IpAddr> {
+        self.addresses
+            .get(hostname)
+            .filter(|(_, resolved_at)| now.duration_since(*resolved_at) < MDNS_ADDRESS_TTL)
+            .map(|(address, _)| *address)
+    }
+
+    fn insert(&mut self, hostname: &str, address: IpAddr, now: Instant) {
+        self.addresses.insert(hostname.to_string(), (address, now));
+    }
+}
+
+/// Returns the host as is, unless it's given as `mdns:<hostname>`, in which case the address it resolves to is returned
+pub fn resolve_host(host: &str, resolver: &dyn HostResolver) -> anyhow::Result<String> {
+    let Some(hostname) = host.strip_prefix(MDNS_PREFIX) else {
+        return Ok(host.to_string());
+    };
+
+    let address = resolver.resolve(hostname)?;
+    tracing::info!("Resolved `{hostname}` with mDNS to {address}");
+    Ok(address.to_string())
+}
+
+/// If the URL is given as `mdns:<url>`, e.g., `mdns:http://frigate.local:5000`, returns the URL without the prefix
+/// and its hostname to resolve with mDNS. Otherwise, returns None.
+pub fn strip_mdns_url(url: &str) -> anyhow::Result<Option<(&str, &str)>> {
+    let Some(url) = url.strip_prefix(MDNS_PREFIX) else {
+        return Ok(None);
+    };
+
+    let (_scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| anyhow::anyhow!("URL `{url}` to resolve with mDNS has no scheme"))?;
+    let host_end = rest.find([':', '/']).unwrap_or(rest.len());
+    let hostname = &rest[..host_end];
+    if hostname.is_empty() {
+        return Err(anyhow::anyhow!(
+            "URL `{url}` to resolve with mDNS has no host"
+        ));
+    }
+
+    Ok(Some((url, hostname)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    struct FixedResolver(IpAddr);
+
+    impl HostResolver for FixedResolver {
+        fn resolve(&self, hostname: &str) -> anyhow::Result<IpAddr> {
+            assert_eq!(hostname, "frigate.local");
+            Ok(self.0)
+        }
+    }
+
+    #[rstest]
+    #[case("mdns:frigate.local", "192.168.1.20")]
+    #[case("192.168.1.5", "192.168.1.5")]
+    #[case("broker.example.com", "broker.example.com")]
+    fn resolved_host(#[case] host: &str, #[case] expected: &str) {
+        let resolver = FixedResolver("192.168.1.20".parse().unwrap());
+        assert_eq!(resolve_host(host, &resolver).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "mdns:http://frigate.local:5000",
+        Some(("http://frigate.local:5000", "frigate.local"))
+    )]
+    #[case(
+        "mdns:https://frigate.local/frigate",
+        Some(("https://frigate.local/frigate", "frigate.local"))
+    )]
+    #[case("mdns:http://frigate.local", Some(("http://frigate.local", "frigate.local")))]
+    #[case("http://127.0.0.1:5000", None)]
+    fn stripped_mdns_url(#[case] url: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(strip_mdns_url(url).unwrap(), expected);
+    }
+
+    #[test]
+    fn mdns_url_without_scheme_or_host_is_an_error() {
+        assert!(strip_mdns_url("mdns:frigate.local:5000").is_err());
+        assert!(strip_mdns_url("mdns:http://:5000").is_err());
+    }
+
+    #[test]
+    fn cached_address_expires() {
+        let mut cache = AddressCache::default();
+        let now = Instant::now();
+        let address: IpAddr = "192.168.1.20".parse().unwrap();
+
+        assert_eq!(cache.get("frigate.local", now), None);
+
+        cache.insert("frigate.local", address, now);
+        assert_eq!(cache.get("frigate.local", now), Some(address));
+        assert_eq!(cache.get("other.local", now), None);
+        assert_eq!(
+            cache.get("frigate.local", now + MDNS_ADDRESS_TTL / 2),
+            Some(address)
+        );
+        assert_eq!(cache.get("frigate.local", now + MDNS_ADDRESS_TTL), None);
+    }
+
+    #[test]
+    fn failed_resolution_is_an_error() {
+        struct FailingResolver;
+
+        impl HostResolver for FailingResolver {
+            fn resolve(&self, _hostname: &str) -> anyhow::Result<IpAddr> {
+                Err(anyhow::anyhow!("Nobody answered"))
+            }
+        }
+
+        assert!(resolve_host("mdns:frigate.local", &FailingResolver).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::os::unix::net::UnixDatagram;
+
+/// Set by systemd for services with `Type=notify`, to the socket that notifications are sent to
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+/// Set by systemd for services with `WatchdogSec`, to the watchdog timeout in microseconds
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+/// If set, the watchdog is only meant for the process with this id
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// Startup is done, and the service is working
+    Ready,
+    /// The service is still alive, which has to be repeated within the watchdog timeout
+    Watchdog,
+    /// The service is shutting down
+    Stopping,
+}
+
+impl ServiceState {
+    fn as_notification(self) -> &'static str {
+        match self {
+            ServiceState::Ready => "READY=1",
+            ServiceState::Watchdog => "WATCHDOG=1",
+            ServiceState::Stopping => "STOPPING=1",
+        }
+    }
+}
+
+/// Tells whatever supervises the service, e.g., systemd, about its state
+pub trait ServiceNotifier: Send + Sync {
+    fn notify(&self, state: ServiceState);
+}
+
+/// Notifies systemd like `sd_notify`, over the socket it gives to the service
+pub struct SystemdNotifier {
+    socket: String,
+}
+
+impl SystemdNotifier {
+    /// None if the service wasn't started by systemd with notifications enabled
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let socket = std::env::var(NOTIFY_SOCKET_ENV).ok()?;
+        if socket.is_empty() {
+            return None;
+        }
+        Some(Self { socket })
+    }
+
+    /// How often the watchdog has to be notified, which is half of its timeout as systemd recommends.
+    /// None if the watchdog isn't enabled for this process.
+    #[must_use]
+    pub fn watchdog_interval() -> Option<std::time::Duration> {
+        if let Ok(pid) = std::env::var(WATCHDOG_PID_ENV) {
+            if pid.parse::<u32>().ok()? != std::process::id() {
+                return None;
+            }
+        }
+
+        let timeout_usec = std::env::var(WATCHDOG_USEC_ENV).ok()?.parse::<u64>().ok()?;
+        if timeout_usec == 0 {
+            return None;
+        }
+        Some(std::time::Duration::from_micros(timeout_usec) / 2)
+    }
+
+    fn send(&self, message: &str) -> std::io::Result<()> {
+        let datagram = UnixDatagram::unbound()?;
+
+        // Sockets starting with `@` are in the abstract namespace, which only exists on Linux
+        if let Some(name) = self.socket.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                datagram.send_to_addr(message.as_bytes(), &address)?;
+                return Ok(());
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("Abstract notification socket `@{name}` is only supported on Linux"),
+                ));
+            }
+        }
+
+        datagram.send_to(message.as_bytes(), &self.socket)?;
+        Ok(())
+    }
+}
+
+impl ServiceNotifier for SystemdNotifier {
+    fn notify(&self, state: ServiceState) {
+        let message = state.as_notification();
+        match self.send(message) {
+            Ok(()) => tracing::trace!("Notified systemd: {message}"),
+            Err(e) => tracing::warn!("Notifying systemd of `{message}` failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_are_sent_to_the_socket() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket_path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+
+        let notifier = SystemdNotifier {
+            socket: socket_path.to_str().unwrap().to_string(),
+        };
+
+        let mut buffer = [0; 64];
+        for (state, expected) in [
+            (ServiceState::Ready, "READY=1"),
+            (ServiceState::Watchdog, "WATCHDOG=1"),
+            (ServiceState::Stopping, "STOPPING=1"),
+        ] {
+            notifier.notify(state);
+            let size = receiver.recv(&mut buffer).unwrap();
+            assert_eq!(&buffer[..size], expected.as_bytes());
+        }
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     time::{Duration, SystemTime},
 };
 
-use chrono::TimeZone;
+use chrono::{TimeZone, Timelike};
 
 pub fn duration_to_int(d: &Duration) -> Result<u64, std::num::TryFromIntError> {
     let r = d.as_millis().try_into()?;
@@ -40,16 +40,36 @@ pub fn set(now: Duration) -> Result<(), std::num::TryFromIntError> {
     Ok(())
 }
 
-/// Either gets the current time or panics
+/// The latest time read from the system clock, as a Duration since `SystemTime::UNIX_EPOCH` in millis
+static LAST_SYSTEM_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Gets the current time. If the system clock is before `SystemTime::UNIX_EPOCH`, e.g., when a device
+/// without a real-time clock hasn't synced it yet, the latest time read from it before is returned instead.
 #[must_use]
 pub fn get_time() -> Time {
     match get_mocked_time() {
         Some(mocked_time) => mocked_time,
-        None => Time::from_duration_since_epoch(
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .expect("Time went backwards"),
-        ),
+        None => time_from_system_time(SystemTime::now()),
+    }
+}
+
+fn time_from_system_time(now: SystemTime) -> Time {
+    match now.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since_epoch) => {
+            if let Ok(value) = duration_to_int(&since_epoch) {
+                LAST_SYSTEM_TIME.fetch_max(value, Ordering::SeqCst);
+            }
+            Time::from_duration_since_epoch(since_epoch)
+        }
+        Err(e) => {
+            let last = duration_from_int(LAST_SYSTEM_TIME.load(Ordering::SeqCst));
+            tracing::warn!(
+                "The system clock is {} seconds before the unix epoch. Using the latest time read from it instead: {} seconds since the epoch",
+                e.duration().as_secs_f64(),
+                last.as_secs()
+            );
+            Time::from_duration_since_epoch(last)
+        }
     }
 }
 
@@ -128,20 +148,45 @@ impl Time {
     }
 
     #[must_use]
-    pub fn as_local_time_in_dir_foramt(&self) -> String {
+    pub fn as_local_datetime(&self) -> chrono::DateTime<chrono::Local> {
         // Convert Duration to seconds and nanoseconds
         #[allow(clippy::cast_possible_wrap)]
         let seconds = self.time.as_secs() as i64;
         let nanoseconds = self.time.subsec_nanos();
 
         // Create DateTime from timestamp in local timezone
-        let datetime = chrono::Local
+        chrono::Local
             .timestamp_opt(seconds, nanoseconds)
             .earliest()
-            .expect("Must be valid, since it's from valid time");
+            .expect("Must be valid, since it's from valid time")
+    }
 
+    #[must_use]
+    pub fn as_local_time_in_dir_foramt(&self) -> String {
         // Format the date as YYYY-MM-DD
-        datetime.format("%Y-%m-%d").to_string()
+        self.as_local_datetime().format("%Y-%m-%d").to_string()
+    }
+
+    /// The local date and time, to be used in file names
+    #[must_use]
+    pub fn as_local_time_in_file_name_format(&self) -> String {
+        self.as_local_datetime()
+            .format("%Y-%m-%d_%H-%M-%S%z")
+            .to_string()
+    }
+
+    /// Seconds passed since midnight in local time
+    #[must_use]
+    pub fn as_local_seconds_of_day(&self) -> u32 {
+        self.as_local_datetime().num_seconds_from_midnight()
+    }
+
+    /// Seconds passed since midnight in the given timezone
+    #[must_use]
+    pub fn as_seconds_of_day_in<Z: TimeZone>(&self, timezone: &Z) -> u32 {
+        self.as_local_datetime()
+            .with_timezone(timezone)
+            .num_seconds_from_midnight()
     }
 
     #[must_use]
@@ -225,6 +270,21 @@ mod tests {
         assert_ne!(get_time().as_secs_since_epoch(), 1337);
     }
 
+    #[test]
+    #[serial_test::serial]
+    fn system_clock_before_epoch_falls_back_to_latest_time() {
+        let observed = time_from_system_time(SystemTime::now());
+
+        // Any later reads of the clock by other tests can only make it later
+        let fallback = time_from_system_time(SystemTime::UNIX_EPOCH - Duration::from_secs(5));
+        assert!(fallback.as_secs_since_epoch() >= observed.as_secs_since_epoch());
+        assert!(fallback.saturating_sub(observed) < Duration::from_secs(60));
+
+        // The clock going back to after the epoch is used as it is
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(1337);
+        assert_eq!(time_from_system_time(later).as_secs_since_epoch(), 1337);
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_mocked() {